@@ -0,0 +1,392 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::marker::{self, Marker};
+use crate::packs::sha256_hex;
+
+pub const ACKS_FILE: &str = "watcher-knight-acks.toml";
+
+/// A human's sign-off on one specific failure of a watcher.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ack {
+    pub marker: String,
+    /// Commit the acknowledged failure was reviewed at.
+    pub commit: String,
+    /// Failure fingerprint (see [`failure_fingerprint`]).
+    pub fingerprint: String,
+    pub by: String,
+    pub date: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Acks {
+    #[serde(default, rename = "ack")]
+    pub acks: Vec<Ack>,
+}
+
+impl Acks {
+    pub fn find(&self, marker: &str, fingerprint: &str) -> Option<&Ack> {
+        self.acks
+            .iter()
+            .find(|a| a.marker == marker && a.fingerprint == fingerprint)
+    }
+
+    /// Add an ack, replacing any previous ack of the same failure.
+    pub fn upsert(&mut self, ack: Ack) {
+        self.acks
+            .retain(|a| !(a.marker == ack.marker && a.fingerprint == ack.fingerprint));
+        self.acks.push(ack);
+        self.acks.sort_by(|a, b| a.marker.cmp(&b.marker));
+    }
+}
+
+pub fn load_acks(root: &Path) -> Result<Acks, String> {
+    match fs::read_to_string(root.join(ACKS_FILE)) {
+        Ok(data) => toml::from_str(&data).map_err(|e| format!("invalid {ACKS_FILE}: {e}")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Acks::default()),
+        Err(e) => Err(format!("cannot read {ACKS_FILE}: {e}")),
+    }
+}
+
+pub fn save_acks(root: &Path, acks: &Acks) -> Result<(), String> {
+    let data = toml::to_string(acks).unwrap();
+    fs::write(root.join(ACKS_FILE), data).map_err(|e| format!("cannot write {ACKS_FILE}: {e}"))
+}
+
+/// Fingerprint of a failure: the marker definition plus the contents of every
+/// watched file in `files` (see [`Marker::watched_files`]), as returned by
+/// `read` (`None` for missing files). An unscoped marker watches the whole
+/// tree, so its fingerprint covers `tree`, a digest of the tree it failed on
+/// (see [`tree_at_commit`] and [`worktree_tree`]). Read files at a commit
+/// with a [`CommitReader`].
+///
+/// The same failure on unchanged code always has the same fingerprint; editing
+/// the marker or any watched file produces a new one.
+pub fn failure_fingerprint(
+    marker: &Marker,
+    files: &[String],
+    tree: Option<&str>,
+    read: impl Fn(&str) -> Option<String>,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(marker::fingerprint(marker).as_bytes());
+    if marker.files.is_empty() {
+        hasher.update([0]);
+        match tree {
+            Some(tree) => hasher.update(tree.as_bytes()),
+            None => hasher.update([0xff]),
+        }
+    }
+    let mut files = files.to_vec();
+    files.sort();
+    for file in &files {
+        hasher.update([0]);
+        hasher.update(file.as_bytes());
+        hasher.update([0]);
+        match read(file) {
+            Some(contents) => hasher.update(contents.as_bytes()),
+            None => hasher.update([0xff]),
+        }
+    }
+    hasher
+        .finalize()
+        .iter()
+        .take(8)
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Read a file from the working tree under `root`.
+pub fn read_worktree(root: &Path, file: &str) -> Option<String> {
+    fs::read_to_string(root.join(file)).ok()
+}
+
+/// Reads files as they were at one commit through a single
+/// `git cat-file --batch`, instead of a `git show` per file.
+pub struct CommitReader {
+    commit: String,
+    batch: RefCell<Option<Batch>>,
+}
+
+struct Batch {
+    child: process::Child,
+    stdin: process::ChildStdin,
+    stdout: BufReader<process::ChildStdout>,
+}
+
+impl CommitReader {
+    /// A reader of `commit` under `root`, which may be below the
+    /// repository's top level. Reads find nothing if git cannot be started.
+    pub fn new(root: &Path, commit: &str) -> Self {
+        let batch = process::Command::new("git")
+            .args(["cat-file", "--batch"])
+            .current_dir(root)
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::null())
+            .spawn()
+            .ok()
+            .map(|mut child| Batch {
+                stdin: child.stdin.take().unwrap(),
+                stdout: BufReader::new(child.stdout.take().unwrap()),
+                child,
+            });
+        CommitReader {
+            commit: commit.to_string(),
+            batch: RefCell::new(batch),
+        }
+    }
+
+    /// The contents of `file`, relative to the root, or `None` if it did not
+    /// exist. A broken batch is dropped and reads find nothing from then on.
+    pub fn read(&self, file: &str) -> Option<String> {
+        // The batch protocol is line based.
+        if file.contains('\n') {
+            return None;
+        }
+        let mut batch = self.batch.borrow_mut();
+        match batch.as_mut()?.read(&self.commit, file) {
+            Ok(contents) => contents,
+            Err(_) => {
+                *batch = None;
+                None
+            }
+        }
+    }
+}
+
+impl Batch {
+    fn read(&mut self, commit: &str, file: &str) -> io::Result<Option<String>> {
+        writeln!(self.stdin, "{commit}:./{file}")?;
+        self.stdin.flush()?;
+        let mut header = String::new();
+        if self.stdout.read_line(&mut header)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        // `<oid> <type> <size>`, or `<object> missing` and the like.
+        let size = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            [oid, _, size] if oid.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                size.parse::<usize>()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            }
+            _ => return Ok(None),
+        };
+        // The contents are followed by a newline.
+        let mut contents = vec![0; size + 1];
+        self.stdout.read_exact(&mut contents)?;
+        contents.pop();
+        Ok(Some(String::from_utf8_lossy(&contents).to_string()))
+    }
+}
+
+impl Drop for Batch {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn git_line(root: &Path, args: &[&str]) -> Option<String> {
+    process::Command::new("git")
+        .args(args)
+        .current_dir(root)
+        .stderr(process::Stdio::null())
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+}
+
+/// What an ack of an unscoped marker is not about: the acks file, which is
+/// committed after the ack, and local state.
+const NOT_ACKED: &[&str] = &[ACKS_FILE, ".watcher_knight"];
+
+/// A digest of the files under `root` at `commit`, but for [`NOT_ACKED`].
+pub fn tree_at_commit(root: &Path, commit: &str) -> Option<String> {
+    let listing = git_line(root, &["ls-tree", "-r", "-z", commit])?;
+    let entries: Vec<&str> = listing
+        .split('\0')
+        .filter(|entry| {
+            entry.split_once('\t').is_some_and(|(_, path)| {
+                !NOT_ACKED
+                    .iter()
+                    .any(|n| path == *n || path.starts_with(&format!("{n}/")))
+            })
+        })
+        .collect();
+    Some(sha256_hex(entries.join("\0").as_bytes()))
+}
+
+/// [`tree_at_commit`] of the files under `root` as they are now: `HEAD`'s,
+/// or `None` when there are other changes (untracked files included), which
+/// no ack matches.
+pub fn worktree_tree(root: &Path) -> Option<String> {
+    let mut args = vec!["status", "--porcelain", "--", "."];
+    let excluded: Vec<String> = NOT_ACKED.iter().map(|n| format!(":(exclude){n}")).collect();
+    args.extend(excluded.iter().map(String::as_str));
+    let status = git_line(root, &args)?;
+    if !status.is_empty() {
+        return None;
+    }
+    tree_at_commit(root, "HEAD")
+}
+
+/// Every file path in the tree of `commit`.
+pub fn files_at_commit(root: &Path, commit: &str) -> Vec<String> {
+    process::Command::new("git")
+        .args(["ls-tree", "-r", "-z", "--name-only", commit])
+        .current_dir(root)
+        .stderr(process::Stdio::null())
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| {
+            String::from_utf8_lossy(&o.stdout)
+                .split('\0')
+                .filter(|f| !f.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn make_marker(files: Vec<&str>) -> Marker {
+        Marker {
+            name: "w".to_string(),
+            rel_path: "src/app.ts".to_string(),
+            line: 1,
+            instruction: "Check it".to_string(),
+            files: files.into_iter().map(String::from).collect(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn failure_fingerprint_same_contents_same_fingerprint() {
+        let m = make_marker(vec!["a.ts"]);
+        let fp1 = failure_fingerprint(&m, &m.files, None, |_| Some("content".to_string()));
+        let fp2 = failure_fingerprint(&m, &m.files, None, |_| Some("content".to_string()));
+        assert_eq!(fp1, fp2);
+    }
+
+    #[test]
+    fn failure_fingerprint_changes_with_contents() {
+        let m = make_marker(vec!["a.ts"]);
+        let fp1 = failure_fingerprint(&m, &m.files, None, |_| Some("old".to_string()));
+        let fp2 = failure_fingerprint(&m, &m.files, None, |_| Some("new".to_string()));
+        let fp3 = failure_fingerprint(&m, &m.files, None, |_| None);
+        assert_ne!(fp1, fp2);
+        assert_ne!(fp1, fp3);
+    }
+
+    #[test]
+    fn failure_fingerprint_changes_with_marker() {
+        let a = make_marker(vec!["a.ts"]);
+        let mut b = make_marker(vec!["a.ts"]);
+        b.instruction = "Check something else".to_string();
+        let read = |_: &str| Some("content".to_string());
+        assert_ne!(
+            failure_fingerprint(&a, &a.files, None, read),
+            failure_fingerprint(&b, &b.files, None, read)
+        );
+    }
+
+    #[test]
+    fn failure_fingerprint_of_unscoped_marker_covers_the_tree() {
+        let scoped = make_marker(vec!["a.ts"]);
+        let unscoped = make_marker(vec![]);
+        let read = |_: &str| Some("content".to_string());
+        let fp = |m: &Marker, tree| failure_fingerprint(m, &m.watched_files_in(&[]), tree, read);
+        assert_eq!(fp(&scoped, Some("t1")), fp(&scoped, Some("t2")));
+        assert_eq!(fp(&unscoped, Some("t1")), fp(&unscoped, Some("t1")));
+        assert_ne!(fp(&unscoped, Some("t1")), fp(&unscoped, Some("t2")));
+        assert_ne!(fp(&unscoped, Some("t1")), fp(&unscoped, None));
+    }
+
+    #[test]
+    fn reads_and_trees_are_relative_to_a_subdirectory_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let output = process::Command::new("git")
+                .args(["-c", "user.name=a", "-c", "user.email=a@example.com"])
+                .args(args)
+                .current_dir(dir.path())
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+        };
+        git(&["init", "-q"]);
+        fs::create_dir(dir.path().join("web")).unwrap();
+        fs::write(dir.path().join("web/a.ts"), "one").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-qm", "one"]);
+        let root = dir.path().join("web");
+        let reader = CommitReader::new(&root, "HEAD");
+        assert_eq!(reader.read("a.ts").as_deref(), Some("one"));
+        assert_eq!(reader.read("missing file.ts"), None);
+        assert_eq!(reader.read("a.ts").as_deref(), Some("one"));
+        assert_eq!(files_at_commit(&root, "HEAD"), ["a.ts"]);
+        let tree = tree_at_commit(&root, "HEAD");
+        assert!(tree.is_some());
+        assert_eq!(worktree_tree(&root), tree);
+        // Recording an ack changes nothing it is about.
+        fs::write(root.join(ACKS_FILE), "").unwrap();
+        fs::create_dir(root.join(".watcher_knight")).unwrap();
+        fs::write(root.join(".watcher_knight/cache.json"), "{}").unwrap();
+        assert_eq!(worktree_tree(&root), tree);
+        git(&["add", "."]);
+        git(&["commit", "-qm", "ack"]);
+        assert_eq!(tree_at_commit(&root, "HEAD"), tree);
+        fs::write(root.join("b.ts"), "new").unwrap();
+        assert_eq!(worktree_tree(&root), None);
+    }
+
+    #[test]
+    fn acks_find_and_upsert() {
+        let ack = |fp: &str, by: &str| Ack {
+            marker: "w".to_string(),
+            commit: "abc123".to_string(),
+            fingerprint: fp.to_string(),
+            by: by.to_string(),
+            date: "2025-01-01".to_string(),
+        };
+        let mut acks = Acks::default();
+        acks.upsert(ack("f1", "alice"));
+        acks.upsert(ack("f1", "bob"));
+        acks.upsert(ack("f2", "alice"));
+        assert_eq!(acks.acks.len(), 2);
+        assert_eq!(acks.find("w", "f1").unwrap().by, "bob");
+        assert!(acks.find("w", "f3").is_none());
+        assert!(acks.find("other", "f1").is_none());
+    }
+
+    #[test]
+    fn acks_toml_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut acks = Acks::default();
+        acks.upsert(Ack {
+            marker: "w".to_string(),
+            commit: "abc123".to_string(),
+            fingerprint: "f1".to_string(),
+            by: "alice".to_string(),
+            date: "2025-01-01".to_string(),
+        });
+        save_acks(dir.path(), &acks).unwrap();
+        let loaded = load_acks(dir.path()).unwrap();
+        assert_eq!(loaded.acks, acks.acks);
+    }
+}
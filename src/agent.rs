@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+
+use crate::claude;
+use crate::stream::{Reply, Telemetry};
+
+/// Sent when a reply is not a verdict, in the same session.
+const FOLLOW_UP: &str = "Reply with only the JSON object {\"is_valid\": true|false, \"reason\": \
+                         \"...\"} for the check above, with no other text.";
+
+/// Tools the agent may use, from a watcher's `tools` option (`Read,Grep`).
+#[derive(Debug, PartialEq)]
+pub struct ToolConfig {
+    pub allowed: Vec<String>,
+}
+
+impl ToolConfig {
+    pub fn parse(option: &str) -> Self {
+        ToolConfig {
+            allowed: option
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+/// The answer a watcher must give.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Verdict {
+    pub is_valid: bool,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// A conversation with the agent; every prompt after the first resumes it.
+pub struct Session<'a> {
+    /// Names the caller in error messages (e.g. `watcher my-check`).
+    what: &'a str,
+    model: &'a str,
+    tools: String,
+    id: Option<String>,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(what: &'a str, model: &'a str, tools: &ToolConfig) -> Self {
+        Session {
+            what,
+            model,
+            tools: tools.allowed.join(","),
+            id: None,
+        }
+    }
+
+    /// Send `prompt` through [`claude::converse`] and return the final
+    /// answer, resuming the session after the first prompt.
+    pub fn send(&mut self, prompt: &str) -> Result<Reply, String> {
+        let (reply, id) = claude::converse(
+            self.what,
+            prompt,
+            self.model,
+            &self.tools,
+            self.id.as_deref(),
+        )?;
+        if id.is_some() {
+            self.id = id;
+        }
+        Ok(reply)
+    }
+}
+
+/// Run a watcher prompt and return its verdict with the telemetry of the
+/// whole session. A reply that is not a verdict gets one follow-up in the
+/// same session asking for just the JSON.
+pub fn validate(
+    what: &str,
+    prompt: &str,
+    model: &str,
+    tools: &ToolConfig,
+) -> Result<(Verdict, Option<Telemetry>), String> {
+    let mut session = Session::new(what, model, tools);
+    let reply = session.send(prompt)?;
+    if let Ok(verdict) = serde_json::from_str(claude::json_reply(&reply.text)) {
+        return Ok((verdict, reply.telemetry));
+    }
+    let follow_up = session.send(FOLLOW_UP)?;
+    let verdict = serde_json::from_str(claude::json_reply(&follow_up.text))
+        .map_err(|e| format!("response is not a verdict ({e}): {}", follow_up.text))?;
+    let mut telemetry = reply.telemetry.unwrap_or_default();
+    if let Some(more) = &follow_up.telemetry {
+        telemetry += more;
+    }
+    Ok((verdict, Some(telemetry)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::Transcript;
+
+    #[test]
+    fn verdict_from_stream() {
+        let mut transcript = Transcript::default();
+        transcript.push(r#"{"type":"system","subtype":"init","session_id":"s1"}"#);
+        transcript.push(
+            r#"{"type":"result","is_error":false,"result":"{\"is_valid\":true}","session_id":"s1","num_turns":2}"#,
+        );
+        let reply = transcript.finish(true, "exit status: 0", None).unwrap();
+        let verdict: Verdict = serde_json::from_str(&reply.text).unwrap();
+        assert_eq!(
+            verdict,
+            Verdict {
+                is_valid: true,
+                reason: None
+            }
+        );
+        assert_eq!(reply.telemetry.unwrap().turns, 2);
+    }
+
+    #[test]
+    fn tool_config_from_option() {
+        assert_eq!(
+            ToolConfig::parse("Read, Grep,,Glob").allowed,
+            vec!["Read", "Grep", "Glob"]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::packs;
+use crate::remote::JobRequest;
+
+/// Why an exported watcher did not run.
+pub const EXPORTED: &str = "exported";
+
+/// Where `run` takes AI validations instead of the backend: their prompts
+/// are written out for another machine to answer, or its answers are read
+/// back in.
+pub enum Airgap {
+    /// `--export-prompts DIR`. `commit` is the checkout the prompts were
+    /// built from.
+    Export {
+        dir: PathBuf,
+        commit: Option<String>,
+    },
+    /// `--import-verdicts DIR`.
+    Import(PathBuf),
+}
+
+/// File stem of one validation: the watcher name, made safe for a file
+/// name, and a hash of its location and chunk so that repeated names and
+/// the chunks of one watcher stay apart.
+pub fn job_id(name: &str, location: &str, chunk: Option<&str>) -> String {
+    let safe: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let key = format!("{location}\0{}", chunk.unwrap_or(""));
+    format!("{safe}-{}", &packs::sha256_hex(key.as_bytes())[..8])
+}
+
+/// Write `request` to `<id>.prompt.json` in `dir`.
+pub fn export(dir: &Path, id: &str, request: &JobRequest) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {e}", dir.display()))?;
+    let path = dir.join(format!("{id}.prompt.json"));
+    let json = serde_json::to_string_pretty(request).unwrap();
+    fs::write(&path, json + "\n").map_err(|e| format!("cannot write {}: {e}", path.display()))
+}
+
+/// The answer to `request` from `<id>.verdict.json` in `dir`. The prompt
+/// exported as `<id>.prompt.json` must be the one `request` has now, or the
+/// answer is to a question that is no longer asked.
+pub fn import(dir: &Path, id: &str, request: &JobRequest) -> Result<String, String> {
+    let read = |file: String| {
+        let path = dir.join(&file);
+        fs::read_to_string(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => format!("no {file} in {}", dir.display()),
+            _ => format!("cannot read {}: {e}", path.display()),
+        })
+    };
+    let exported: JobRequest = serde_json::from_str(&read(format!("{id}.prompt.json"))?)
+        .map_err(|e| format!("invalid {id}.prompt.json: {e}"))?;
+    if exported.prompt != request.prompt {
+        return Err(format!(
+            "the prompt of {id} changed since it was exported (the code, the diff or the \
+             watcher moved on); export the prompts again"
+        ));
+    }
+    read(format!("{id}.verdict.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(prompt: &str) -> JobRequest {
+        JobRequest {
+            version: 1,
+            name: "api/contract".to_string(),
+            location: "src/api.ts:3".to_string(),
+            model: "sonnet".to_string(),
+            tools: "Read,Grep,Glob".to_string(),
+            prompt: prompt.to_string(),
+            commit: Some("abc123".to_string()),
+        }
+    }
+
+    #[test]
+    fn job_ids_are_safe_and_distinct() {
+        let id = job_id("api/contract", "src/api.ts:3", None);
+        assert!(id.starts_with("api_contract-"));
+        assert_eq!(id.len(), "api_contract-".len() + 8);
+        assert_eq!(id, job_id("api/contract", "src/api.ts:3", None));
+        assert_ne!(id, job_id("api/contract", "src/api.ts:9", None));
+        assert_ne!(id, job_id("api/contract", "src/api.ts:3", Some("src/")));
+    }
+
+    #[test]
+    fn import_reads_verdicts_for_unchanged_prompts() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("prompts");
+        export(&out, "w-1", &request("Check it")).unwrap();
+        let err = import(&out, "w-1", &request("Check it")).unwrap_err();
+        assert!(err.starts_with("no w-1.verdict.json in"), "err was: {err}");
+
+        fs::write(out.join("w-1.verdict.json"), "{\"is_valid\": true}").unwrap();
+        let verdict = import(&out, "w-1", &request("Check it")).unwrap();
+        assert_eq!(verdict, "{\"is_valid\": true}");
+        let err = import(&out, "w-1", &request("Check it again")).unwrap_err();
+        assert!(
+            err.contains("changed since it was exported"),
+            "err was: {err}"
+        );
+    }
+}
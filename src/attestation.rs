@@ -0,0 +1,322 @@
+use std::env;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::report::Provenance;
+
+/// Git notes ref holding one attestation per commit. Notes travel with
+/// `git push origin refs/notes/watcher-knight` and
+/// `git fetch origin refs/notes/watcher-knight:refs/notes/watcher-knight`.
+pub const NOTES_REF: &str = "refs/notes/watcher-knight";
+
+/// Version of the attestation format. Bump on breaking changes.
+pub const ATTESTATION_VERSION: u32 = 2;
+
+/// Environment variable holding the secret attestations are signed and
+/// verified with (HMAC-SHA256). The jobs that attest and the gate share it,
+/// so whoever can push the notes ref still cannot forge an attestation.
+pub const KEY_VAR: &str = "WK_ATTESTATION_KEY";
+
+/// What a note holds: the attestation's JSON and its signature.
+#[derive(Serialize, Deserialize)]
+struct Signed {
+    attestation: String,
+    hmac_sha256: String,
+}
+
+/// A record that every watcher passed at a commit.
+#[derive(Serialize)]
+pub struct Attestation<'a> {
+    pub version: u32,
+    pub status: &'static str,
+    pub attested_at: i64,
+    pub provenance: &'a Provenance,
+}
+
+/// The parts of a stored attestation the gate checks.
+#[derive(Debug, Deserialize)]
+struct Stored {
+    version: u32,
+    status: String,
+    attested_at: i64,
+    provenance: StoredProvenance,
+}
+
+#[derive(Debug, Deserialize)]
+struct StoredProvenance {
+    version: String,
+    commit: Option<String>,
+    config_sha256: Option<String>,
+    markers_sha256: String,
+}
+
+/// A stored attestation that holds for the commit being gated.
+#[derive(Debug, PartialEq)]
+pub struct Verified {
+    pub attested_at: i64,
+    /// The watcher-knight version that ran the watchers.
+    pub tool_version: String,
+}
+
+/// The signing key from [`KEY_VAR`], if set.
+pub fn key_from_env() -> Option<String> {
+    env::var(KEY_VAR).ok().filter(|k| !k.is_empty())
+}
+
+/// HMAC-SHA256 (RFC 2104) of `message` under `key`, hex-encoded.
+fn hmac_sha256(key: &[u8], message: &[u8]) -> String {
+    const BLOCK: usize = 64;
+    let mut block = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.map(|b| b ^ byte);
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Whether `a` and `b` are equal, in time that does not depend on where
+/// they differ.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// The note recording `attestation`, signed with `key`.
+pub fn sign(attestation: &Attestation, key: &str) -> String {
+    let json = serde_json::to_string(attestation).unwrap();
+    let signed = Signed {
+        hmac_sha256: hmac_sha256(key.as_bytes(), json.as_bytes()),
+        attestation: json,
+    };
+    serde_json::to_string_pretty(&signed).unwrap()
+}
+
+/// Check the attestation `note` against `commit` as it is now: it must be
+/// signed with `key`, and be a passing run of that commit with the same
+/// config and the same watchers.
+pub fn verify(
+    note: &str,
+    key: &str,
+    commit: &str,
+    config_sha256: Option<&str>,
+    markers_sha256: &str,
+) -> Result<Verified, String> {
+    let signed: Signed =
+        serde_json::from_str(note).map_err(|e| format!("unreadable attestation: {e}"))?;
+    let expected = hmac_sha256(key.as_bytes(), signed.attestation.as_bytes());
+    if !constant_time_eq(&signed.hmac_sha256, &expected) {
+        return Err(format!(
+            "the attestation's signature does not match {KEY_VAR}"
+        ));
+    }
+    let stored: Stored = serde_json::from_str(&signed.attestation)
+        .map_err(|e| format!("unreadable attestation: {e}"))?;
+    if stored.version != ATTESTATION_VERSION {
+        return Err(format!(
+            "attestation version {} (expected {ATTESTATION_VERSION})",
+            stored.version
+        ));
+    }
+    let p = &stored.provenance;
+    if p.commit.as_deref() != Some(commit) {
+        return Err("the attestation is for another commit".to_string());
+    }
+    if stored.status != "passed" {
+        return Err(format!("the attested run {}", stored.status));
+    }
+    if p.config_sha256.as_deref() != config_sha256 {
+        return Err("watcher-knight.toml differs from the attested run's".to_string());
+    }
+    if p.markers_sha256 != markers_sha256 {
+        return Err("the watchers differ from the attested run's".to_string());
+    }
+    Ok(Verified {
+        attested_at: stored.attested_at,
+        tool_version: p.version.clone(),
+    })
+}
+
+/// The attestation note on `commit`, `None` if it has none.
+pub fn read(root: &Path, commit: &str) -> Option<String> {
+    let output = process::Command::new("git")
+        .args(["notes", "--ref", NOTES_REF, "show", commit])
+        .current_dir(root)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Whether git knows who is the author and committer of the commits the
+/// notes ref is made of.
+fn has_identity(root: &Path) -> bool {
+    ["GIT_AUTHOR_IDENT", "GIT_COMMITTER_IDENT"]
+        .iter()
+        .all(|var| {
+            process::Command::new("git")
+                .args(["var", var])
+                .current_dir(root)
+                .stdout(process::Stdio::null())
+                .stderr(process::Stdio::null())
+                .status()
+                .is_ok_and(|s| s.success())
+        })
+}
+
+/// Attach `attestation`, signed with `key`, to its commit, replacing an
+/// older one. CI checkouts often have no git identity, so one is supplied if
+/// missing.
+pub fn record(
+    root: &Path,
+    commit: &str,
+    attestation: &Attestation,
+    key: &str,
+) -> Result<(), String> {
+    let mut git = process::Command::new("git");
+    if !has_identity(root) {
+        git.args(["-c", "user.name=watcher-knight"])
+            .args(["-c", "user.email=watcher-knight@localhost"]);
+    }
+    let mut child = git
+        .args(["notes", "--ref", NOTES_REF, "add", "-f", "-F", "-", commit])
+        .current_dir(root)
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run git: {e}"))?;
+    let note = sign(attestation, key);
+    child.stdin.take().unwrap().write_all(note.as_bytes()).ok();
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to wait on git: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git notes failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(commit: &str, status: &'static str) -> String {
+        let provenance = Provenance {
+            tool: "watcher-knight",
+            version: "1.2.0",
+            config_sha256: Some("cfg".to_string()),
+            markers_sha256: "wk".to_string(),
+            commit: Some(commit.to_string()),
+            ..Provenance::default()
+        };
+        sign(
+            &Attestation {
+                version: ATTESTATION_VERSION,
+                status,
+                attested_at: 100,
+                provenance: &provenance,
+            },
+            "k3y",
+        )
+    }
+
+    #[test]
+    fn hmac_sha256_matches_rfc_4231() {
+        assert_eq!(
+            hmac_sha256(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            ),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn verify_accepts_a_passing_run_of_the_same_watchers() {
+        assert_eq!(
+            verify(&note("abc", "passed"), "k3y", "abc", Some("cfg"), "wk"),
+            Ok(Verified {
+                attested_at: 100,
+                tool_version: "1.2.0".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn verify_rejects_stale_or_failed_attestations() {
+        let passed = note("abc", "passed");
+        for (note, commit, config, markers, why) in [
+            (passed.as_str(), "def", Some("cfg"), "wk", "another commit"),
+            (
+                passed.as_str(),
+                "abc",
+                None,
+                "wk",
+                "watcher-knight.toml differs",
+            ),
+            (
+                passed.as_str(),
+                "abc",
+                Some("cfg"),
+                "new",
+                "the watchers differ",
+            ),
+            ("{}", "abc", Some("cfg"), "wk", "unreadable"),
+        ] {
+            let err = verify(note, "k3y", commit, config, markers).unwrap_err();
+            assert!(err.contains(why), "err was: {err}");
+        }
+        let err = verify(&note("abc", "failed"), "k3y", "abc", Some("cfg"), "wk").unwrap_err();
+        assert_eq!(err, "the attested run failed");
+    }
+
+    #[test]
+    fn verify_rejects_forged_attestations() {
+        let err = verify(&note("abc", "passed"), "other", "abc", Some("cfg"), "wk").unwrap_err();
+        assert!(err.contains("signature"), "err was: {err}");
+        let mut forged: serde_json::Value = serde_json::from_str(&note("abc", "failed")).unwrap();
+        let attestation = forged["attestation"]
+            .as_str()
+            .unwrap()
+            .replace("failed", "passed");
+        forged["attestation"] = attestation.into();
+        let err = verify(&forged.to_string(), "k3y", "abc", Some("cfg"), "wk").unwrap_err();
+        assert!(err.contains("signature"), "err was: {err}");
+        // Unsigned notes, as version 1 wrote them.
+        let unsigned = serde_json::from_str::<serde_json::Value>(&note("abc", "passed")).unwrap()
+            ["attestation"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let err = verify(&unsigned, "k3y", "abc", Some("cfg"), "wk").unwrap_err();
+        assert!(err.contains("unreadable"), "err was: {err}");
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt::Write as _;
+
+use crate::claude::WatcherResult;
+use crate::clock;
+use crate::history::PastRun;
+use crate::marker::Marker;
+use crate::report;
+use crate::results_diff::{self, Change, Results, ResultsDiff};
+
+/// Length of the commit hashes printed for a range.
+const SHORT_SHA: usize = 12;
+
+/// How this audit's results differ from the previous audit's.
+pub struct Comparison {
+    /// The day the previous audit ran (`YYYY-MM-DD`).
+    pub since: String,
+    /// The previous and current commit, when both are known and differ.
+    pub range: Option<(String, String)>,
+    /// Both audits ran at the same known commit.
+    pub same_commit: bool,
+    /// Commits in `range`, when git could count them.
+    pub commits: Option<usize>,
+    pub diff: ResultsDiff,
+}
+
+/// `results` as the entries the history stores.
+pub fn results_of(results: &[WatcherResult], markers: &[Marker]) -> Results {
+    Results {
+        version: report::SCHEMA_VERSION,
+        results: results
+            .iter()
+            .map(|r| {
+                let entry = report::entry(r, markers);
+                results_diff::Entry {
+                    name: entry.name,
+                    location: entry.location,
+                    status: entry.status,
+                    reason: entry.reason,
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Compare `current`, run at `commit`, with the `previous` audit.
+pub fn compare(previous: &PastRun, current: &Results, commit: Option<&str>) -> Comparison {
+    let range = match (&previous.commit, commit) {
+        (Some(before), Some(after)) if before != after => Some((before.clone(), after.to_string())),
+        _ => None,
+    };
+    Comparison {
+        since: clock::date_from_unix_days(previous.started_at.div_euclid(86_400)),
+        same_commit: previous.commit.is_some() && previous.commit.as_deref() == commit,
+        range,
+        commits: None,
+        diff: results_diff::diff(&previous.results, current),
+    }
+}
+
+fn short(sha: &str) -> &str {
+    &sha[..sha.len().min(SHORT_SHA)]
+}
+
+/// The watchers that flipped between passing and failing, and the commits
+/// to bisect for a new failure.
+pub fn render_text(c: &Comparison) -> String {
+    let mut out = String::new();
+    let mut previous = format!("Previous audit: {}", c.since);
+    match &c.range {
+        Some((before, after)) => {
+            write!(previous, ", {}..{}", short(before), short(after)).unwrap();
+            if let Some(n) = c.commits {
+                let s = if n == 1 { "" } else { "s" };
+                write!(previous, " ({n} commit{s})").unwrap();
+            }
+        }
+        None if c.same_commit => previous.push_str(", same commit"),
+        None => {}
+    }
+    writeln!(out, "{previous}").unwrap();
+    if c.diff.regressions.is_empty() && c.diff.fixes.is_empty() {
+        writeln!(out, "No watcher changed state since then.").unwrap();
+        return out;
+    }
+    let sections: [(&str, &Vec<Change>); 2] = [
+        ("Now failing:", &c.diff.regressions),
+        ("Now passing:", &c.diff.fixes),
+    ];
+    for (title, changes) in sections {
+        if changes.is_empty() {
+            continue;
+        }
+        writeln!(out, "\n{title}").unwrap();
+        for change in changes {
+            writeln!(
+                out,
+                "  {} ({}): {} -> {}",
+                change.name,
+                change.location,
+                change.before.as_deref().unwrap_or_default(),
+                change.after.as_deref().unwrap_or_default()
+            )
+            .unwrap();
+            if let Some(reason) = &change.reason {
+                writeln!(out, "    {}", reason.trim()).unwrap();
+            }
+        }
+    }
+    if let Some((before, after)) = &c.range
+        && !c.diff.regressions.is_empty()
+    {
+        writeln!(
+            out,
+            "\nBisect with: git bisect start {} {}",
+            short(after),
+            short(before)
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(entries: &[(&str, &str)]) -> Results {
+        Results {
+            version: report::SCHEMA_VERSION,
+            results: entries
+                .iter()
+                .map(|(name, status)| results_diff::Entry {
+                    name: name.to_string(),
+                    location: format!("src/{name}.ts:1"),
+                    status: status.to_string(),
+                    reason: (*status == "failed").then(|| format!("{name} broke")),
+                })
+                .collect(),
+        }
+    }
+
+    fn previous(commit: Option<&str>) -> PastRun {
+        PastRun {
+            started_at: 86_400,
+            commit: commit.map(str::to_string),
+            results: results(&[("api", "passed"), ("db", "failed"), ("ui", "passed")]),
+        }
+    }
+
+    #[test]
+    fn compare_reports_flips_and_the_range_to_bisect() {
+        let current = results(&[("api", "failed"), ("db", "passed"), ("ui", "passed")]);
+        let mut c = compare(
+            &previous(Some("aaaaaaaaaaaaaaaa")),
+            &current,
+            Some("bbbbbbbbbbbbbbbb"),
+        );
+        c.commits = Some(3);
+        assert_eq!(
+            render_text(&c),
+            "Previous audit: 1970-01-02, aaaaaaaaaaaa..bbbbbbbbbbbb (3 commits)\n\
+             \n\
+             Now failing:\n  api (src/api.ts:1): passed -> failed\n    api broke\n\
+             \n\
+             Now passing:\n  db (src/db.ts:1): failed -> passed\n\
+             \n\
+             Bisect with: git bisect start bbbbbbbbbbbb aaaaaaaaaaaa\n"
+        );
+    }
+
+    #[test]
+    fn compare_without_flips_or_new_commits() {
+        let current = results(&[("api", "passed"), ("db", "failed")]);
+        let c = compare(&previous(Some("abc")), &current, Some("abc"));
+        assert_eq!(c.range, None);
+        assert_eq!(
+            render_text(&c),
+            "Previous audit: 1970-01-02, same commit\nNo watcher changed state since then.\n"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::history::LatestRun;
+
+const LABEL: &str = "invariants";
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum BadgeFormat {
+    /// A flat SVG badge.
+    Svg,
+    /// A shields.io endpoint (`https://img.shields.io/endpoint?url=...`).
+    Json,
+}
+
+/// Message and shields.io color name of a badge.
+#[derive(Debug, PartialEq)]
+pub struct Badge {
+    pub message: String,
+    pub color: &'static str,
+}
+
+/// shields.io endpoint schema.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Endpoint<'a> {
+    schema_version: u32,
+    label: &'a str,
+    message: &'a str,
+    color: &'a str,
+}
+
+/// Badge for the latest recorded run, or `unknown` without history.
+pub fn for_run(run: Option<&LatestRun>) -> Badge {
+    let Some(run) = run else {
+        return Badge {
+            message: "unknown".to_string(),
+            color: "lightgrey",
+        };
+    };
+    let passed = run.count("passed");
+    let failed = run.count("failed");
+    let suppressed = run.total - passed - failed - run.count("not_run") - run.count("inconclusive");
+    if !run.passed {
+        // A post-processor can fail a run without failing watchers.
+        let message = if failed > 0 {
+            format!("{failed} failing")
+        } else {
+            "failing".to_string()
+        };
+        return Badge {
+            message,
+            color: "red",
+        };
+    }
+    if passed == 0 && suppressed == 0 {
+        return Badge {
+            message: "not run".to_string(),
+            color: "lightgrey",
+        };
+    }
+    if suppressed > 0 {
+        return Badge {
+            message: format!("{passed} passing, {suppressed} suppressed"),
+            color: "yellow",
+        };
+    }
+    Badge {
+        message: format!("{passed} passing"),
+        color: "brightgreen",
+    }
+}
+
+pub fn render(badge: &Badge, format: BadgeFormat) -> String {
+    match format {
+        BadgeFormat::Svg => svg(badge),
+        BadgeFormat::Json => serde_json::to_string_pretty(&Endpoint {
+            schema_version: 1,
+            label: LABEL,
+            message: &badge.message,
+            color: badge.color,
+        })
+        .unwrap(),
+    }
+}
+
+fn hex(color: &str) -> &'static str {
+    match color {
+        "brightgreen" => "#4c1",
+        "yellow" => "#dfb317",
+        "red" => "#e05d44",
+        _ => "#9f9f9f",
+    }
+}
+
+/// Approximate width of `text` in 11px Verdana, plus padding.
+fn text_width(text: &str) -> usize {
+    let width: usize = text
+        .chars()
+        .map(|c| match c {
+            'i' | 'l' | 'j' | ' ' | ',' | '.' | '\'' => 4,
+            'm' | 'w' | 'M' | 'W' => 10,
+            _ => 7,
+        })
+        .sum();
+    width + 10
+}
+
+/// A flat, shields.io-style SVG badge.
+fn svg(badge: &Badge) -> String {
+    let label_w = text_width(LABEL);
+    let message_w = text_width(&badge.message);
+    let width = label_w + message_w;
+    let color = hex(badge.color);
+    let message = badge
+        .message
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    let label_x = label_w * 5;
+    let message_x = (label_w * 2 + message_w) * 5;
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{LABEL}: {message}">
+<title>{LABEL}: {message}</title>
+<linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient>
+<clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath>
+<g clip-path="url(#r)"><rect width="{label_w}" height="20" fill="#555"/><rect x="{label_w}" width="{message_w}" height="20" fill="{color}"/><rect width="{width}" height="20" fill="url(#s)"/></g>
+<g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="110" transform="scale(.1)">
+<text x="{label_x}" y="150" fill="#010101" fill-opacity=".3">{LABEL}</text><text x="{label_x}" y="140">{LABEL}</text>
+<text x="{message_x}" y="150" fill="#010101" fill-opacity=".3">{message}</text><text x="{message_x}" y="140">{message}</text>
+</g>
+</svg>
+"##
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(passed: bool, counts: &[(&str, usize)]) -> LatestRun {
+        LatestRun {
+            passed,
+            total: counts.iter().map(|(_, n)| n).sum(),
+            counts: counts.iter().map(|(s, n)| (s.to_string(), *n)).collect(),
+        }
+    }
+
+    #[test]
+    fn badge_reflects_latest_run() {
+        assert_eq!(for_run(None).message, "unknown");
+        let passing = for_run(Some(&run(true, &[("passed", 12), ("not_run", 1)])));
+        assert_eq!(passing.message, "12 passing");
+        assert_eq!(passing.color, "brightgreen");
+        let failing = for_run(Some(&run(false, &[("passed", 3), ("failed", 2)])));
+        assert_eq!(failing.message, "2 failing");
+        assert_eq!(failing.color, "red");
+        let waived = for_run(Some(&run(true, &[("passed", 3), ("waived", 1)])));
+        assert_eq!(waived.message, "3 passing, 1 suppressed");
+        assert_eq!(
+            for_run(Some(&run(true, &[("not_run", 4)]))).message,
+            "not run"
+        );
+    }
+
+    #[test]
+    fn render_json_matches_shields_endpoint_schema() {
+        let badge = for_run(Some(&run(true, &[("passed", 5)])));
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&badge, BadgeFormat::Json)).unwrap();
+        assert_eq!(json["schemaVersion"], 1);
+        assert_eq!(json["label"], "invariants");
+        assert_eq!(json["message"], "5 passing");
+        assert_eq!(json["color"], "brightgreen");
+    }
+
+    #[test]
+    fn render_svg_sizes_to_text() {
+        let badge = for_run(Some(&run(false, &[("failed", 1)])));
+        let svg = render(&badge, BadgeFormat::Svg);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.contains("aria-label=\"invariants: 1 failing\""));
+        assert!(svg.contains("fill=\"#e05d44\""));
+        let width = text_width("invariants") + text_width("1 failing");
+        assert!(svg.contains(&format!("width=\"{width}\" height=\"20\" role")));
+    }
+}
@@ -2,14 +2,21 @@ use std::collections::HashMap;
 use std::fs;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde::{Deserialize, Serialize};
 
 use crate::claude::WatcherResult;
-use crate::marker::Marker;
+use crate::marker::{self, Marker};
+use crate::prompt::{self, Strategy};
+use crate::remote;
+use crate::script;
 
 const CACHE_DIR: &str = ".watcher_knight";
 const CACHE_FILE: &str = ".watcher_knight/cache.json";
+const VERDICTS_FILE: &str = ".watcher_knight/verdicts.json";
+/// Most verdicts kept in the content-addressed store; the oldest go first.
+const MAX_VERDICTS: usize = 10_000;
 
 #[derive(Serialize, Deserialize)]
 pub struct CacheEntry {
@@ -17,6 +24,10 @@ pub struct CacheEntry {
     pub file_hashes: HashMap<String, u64>,
     pub is_valid: bool,
     pub reason: Option<String>,
+    /// [`marker::fingerprint`] of the marker the verdict was for; missing in
+    /// entries written before fingerprints were recorded.
+    #[serde(default)]
+    pub fingerprint: Option<String>,
 }
 
 pub type Cache = HashMap<String, CacheEntry>;
@@ -40,9 +51,9 @@ fn hash_string(s: &str) -> u64 {
     hasher.finish()
 }
 
-/// Hash a marker's instruction and options together so that changing either
-/// invalidates the cache.
-fn marker_content_hash(marker: &Marker) -> u64 {
+/// Hash a marker's instruction and options, and the markers it references by
+/// `@name`, so that changing any of them invalidates the cache.
+fn marker_content_hash(marker: &Marker, related: &[&Marker]) -> u64 {
     let mut hasher = DefaultHasher::new();
     marker.instruction.hash(&mut hasher);
     let mut opts: Vec<_> = marker.options.iter().collect();
@@ -51,6 +62,13 @@ fn marker_content_hash(marker: &Marker) -> u64 {
         k.hash(&mut hasher);
         v.hash(&mut hasher);
     }
+    // Only hashed when present so existing cache entries stay valid.
+    if !marker.context.is_empty() {
+        marker.context.hash(&mut hasher);
+    }
+    for other in related {
+        marker::fingerprint(other).hash(&mut hasher);
+    }
     hasher.finish()
 }
 
@@ -58,22 +76,114 @@ fn cache_key(marker: &Marker) -> String {
     format!("{}::{}", marker.name, marker.rel_path)
 }
 
+/// Content hashes of the watched files and of local context files, which are
+/// inlined into the prompt and so also decide the verdict.
 fn hash_watched_files(marker: &Marker, root: &Path) -> HashMap<String, u64> {
     let mut hashes = HashMap::new();
-    for file in &marker.files {
-        let path = root.join(file);
+    let context = marker.context_files().map(str::to_string);
+    for file in marker.watched_files(root).into_iter().chain(context) {
+        let path = root.join(&file);
         if let Ok(contents) = fs::read_to_string(&path) {
-            hashes.insert(file.clone(), hash_string(&contents));
+            hashes.insert(file, hash_string(&contents));
         }
     }
     hashes
 }
 
+/// A verdict stored under the [`content_key`] of what was validated.
+#[derive(Serialize, Deserialize)]
+pub struct Verdict {
+    pub is_valid: bool,
+    pub reason: Option<String>,
+    /// Unix seconds, for evicting the oldest verdicts.
+    pub stored_at: u64,
+}
+
+/// Verdicts by content key. Unlike [`Cache`], which holds the latest verdict
+/// of each marker, it keeps one per distinct input, so verdicts survive
+/// switching branches and rebasing.
+pub type Verdicts = HashMap<String, Verdict>;
+
+/// Key of everything a scoped marker's verdict depends on: its definition,
+/// the markers it references, the contents of its watched and context files
+/// and, in diff mode, the hunks of the files it watches. `None` for unscoped
+/// markers, which may look at anything.
+pub fn content_key(
+    marker: &Marker,
+    related: &[&Marker],
+    root: &Path,
+    diff: Option<&str>,
+) -> Option<String> {
+    if !is_cacheable(marker) {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    marker::fingerprint(marker).hash(&mut hasher);
+    marker_content_hash(marker, related).hash(&mut hasher);
+    let mut files: Vec<(String, u64)> = hash_watched_files(marker, root).into_iter().collect();
+    files.sort();
+    files.hash(&mut hasher);
+    if let Some(diff) = diff {
+        remote::slice_diff(diff, marker).hash(&mut hasher);
+    }
+    Some(format!("{:016x}", hasher.finish()))
+}
+
+pub fn load_verdicts(root: &Path) -> Verdicts {
+    match fs::read_to_string(root.join(VERDICTS_FILE)) {
+        Ok(data) => serde_json::from_str(&data).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Save `verdicts`, dropping the oldest beyond [`MAX_VERDICTS`].
+pub fn save_verdicts(root: &Path, verdicts: &mut Verdicts) {
+    if verdicts.len() > MAX_VERDICTS {
+        let mut ages: Vec<u64> = verdicts.values().map(|v| v.stored_at).collect();
+        ages.sort_unstable_by(|a, b| b.cmp(a));
+        let cutoff = ages[MAX_VERDICTS - 1];
+        verdicts.retain(|_, v| v.stored_at >= cutoff);
+    }
+    fs::create_dir_all(root.join(CACHE_DIR)).ok();
+    let data = serde_json::to_string(verdicts).unwrap();
+    fs::write(root.join(VERDICTS_FILE), data).ok();
+}
+
+/// Record `result` under `key`.
+pub fn store_verdict(verdicts: &mut Verdicts, key: String, result: &WatcherResult) {
+    let stored_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    verdicts.insert(
+        key,
+        Verdict {
+            is_valid: result.is_valid,
+            reason: result.reason.clone(),
+            stored_at,
+        },
+    );
+}
+
+/// Whether verdicts of `marker` may be reused. Unscoped watchers may look at
+/// anything, and assertions are cheap to evaluate and may read files outside
+/// their scope, so both always run again; so do watchers judged on a
+/// command's output, which depends on more than their files.
+pub fn is_cacheable(marker: &Marker) -> bool {
+    !marker.files.is_empty()
+        && script::assert_for(marker).is_none()
+        && prompt::strategy_for(marker) != Ok(Strategy::CommandOutput)
+}
+
 /// Check if a marker's cached result is still valid.
 /// Returns None if cache miss, Some(CacheEntry) if hit.
-pub fn check_cache<'a>(marker: &Marker, cache: &'a Cache, root: &Path) -> Option<&'a CacheEntry> {
-    // Unscoped watchers (no files) always re-run
-    if marker.files.is_empty() {
+pub fn check_cache<'a>(
+    marker: &Marker,
+    related: &[&Marker],
+    cache: &'a Cache,
+    root: &Path,
+) -> Option<&'a CacheEntry> {
+    if !is_cacheable(marker) {
         return None;
     }
 
@@ -81,7 +191,7 @@ pub fn check_cache<'a>(marker: &Marker, cache: &'a Cache, root: &Path) -> Option
     let entry = cache.get(&key)?;
 
     // Check marker instruction hash
-    if entry.marker_hash != marker_content_hash(marker) {
+    if entry.marker_hash != marker_content_hash(marker, related) || is_modified(marker, cache) {
         return None;
     }
 
@@ -94,14 +204,29 @@ pub fn check_cache<'a>(marker: &Marker, cache: &'a Cache, root: &Path) -> Option
     Some(entry)
 }
 
+/// Whether the marker was edited since its cached verdict: its name and
+/// file still have an entry, but recorded for a different fingerprint.
+pub fn is_modified(marker: &Marker, cache: &Cache) -> bool {
+    cache
+        .get(&cache_key(marker))
+        .and_then(|entry| entry.fingerprint.as_deref())
+        .is_some_and(|fp| fp != marker::fingerprint(marker))
+}
+
 /// Build a cache entry from a watcher result.
-pub fn build_entry(marker: &Marker, result: &WatcherResult, root: &Path) -> (String, CacheEntry) {
+pub fn build_entry(
+    marker: &Marker,
+    related: &[&Marker],
+    result: &WatcherResult,
+    root: &Path,
+) -> (String, CacheEntry) {
     let key = cache_key(marker);
     let entry = CacheEntry {
-        marker_hash: marker_content_hash(marker),
+        marker_hash: marker_content_hash(marker, related),
         file_hashes: hash_watched_files(marker, root),
         is_valid: result.is_valid,
         reason: result.reason.clone(),
+        fingerprint: Some(marker::fingerprint(marker)),
     };
     (key, entry)
 }
@@ -118,18 +243,15 @@ mod tests {
             line: 1,
             instruction: instruction.to_string(),
             files,
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
             options: HashMap::new(),
         }
     }
 
     fn make_result(is_valid: bool, reason: Option<&str>) -> WatcherResult {
-        WatcherResult {
-            name: "test".to_string(),
-            location: "f:1".to_string(),
-            is_valid,
-            reason: reason.map(|s| s.to_string()),
-            cached: false,
-        }
+        WatcherResult::new("test", "f:1", is_valid, reason.map(|s| s.to_string()))
     }
 
     // ── hash_string ───────────────────────────────────────────────────────
@@ -155,14 +277,14 @@ mod tests {
     #[test]
     fn marker_content_hash_deterministic() {
         let m = make_marker("w", "Check it", vec![]);
-        assert_eq!(marker_content_hash(&m), marker_content_hash(&m));
+        assert_eq!(marker_content_hash(&m, &[]), marker_content_hash(&m, &[]));
     }
 
     #[test]
     fn marker_content_hash_changes_on_instruction_change() {
         let m1 = make_marker("w", "Check A", vec![]);
         let m2 = make_marker("w", "Check B", vec![]);
-        assert_ne!(marker_content_hash(&m1), marker_content_hash(&m2));
+        assert_ne!(marker_content_hash(&m1, &[]), marker_content_hash(&m2, &[]));
     }
 
     #[test]
@@ -171,7 +293,7 @@ mod tests {
         let mut m2 = make_marker("w", "Check it", vec![]);
         m1.options.insert("model".to_string(), "haiku".to_string());
         m2.options.insert("model".to_string(), "opus".to_string());
-        assert_ne!(marker_content_hash(&m1), marker_content_hash(&m2));
+        assert_ne!(marker_content_hash(&m1, &[]), marker_content_hash(&m2, &[]));
     }
 
     #[test]
@@ -184,7 +306,7 @@ mod tests {
         m2.options.insert("b".to_string(), "2".to_string());
         m2.options.insert("a".to_string(), "1".to_string());
 
-        assert_eq!(marker_content_hash(&m1), marker_content_hash(&m2));
+        assert_eq!(marker_content_hash(&m1, &[]), marker_content_hash(&m2, &[]));
     }
 
     #[test]
@@ -192,7 +314,20 @@ mod tests {
         let m1 = make_marker("name1", "Check it", vec![]);
         let mut m2 = make_marker("name2", "Check it", vec![]);
         m2.rel_path = "other.ts".to_string();
-        assert_eq!(marker_content_hash(&m1), marker_content_hash(&m2));
+        assert_eq!(marker_content_hash(&m1, &[]), marker_content_hash(&m2, &[]));
+    }
+
+    #[test]
+    fn marker_content_hash_changes_with_referenced_markers() {
+        let m = make_marker("w", "Keep in step with @other", vec![]);
+        let other = make_marker("other", "Check A", vec![]);
+        let edited = make_marker("other", "Check B", vec![]);
+        let plain = marker_content_hash(&m, &[]);
+        assert_ne!(plain, marker_content_hash(&m, &[&other]));
+        assert_ne!(
+            marker_content_hash(&m, &[&other]),
+            marker_content_hash(&m, &[&edited])
+        );
     }
 
     // ── cache_key ─────────────────────────────────────────────────────────
@@ -225,7 +360,7 @@ mod tests {
     fn check_cache_miss_empty_cache() {
         let m = make_marker("w", "Check it", vec!["file.ts".to_string()]);
         let cache = Cache::new();
-        assert!(check_cache(&m, &cache, Path::new("/repo")).is_none());
+        assert!(check_cache(&m, &[], &cache, Path::new("/repo")).is_none());
     }
 
     #[test]
@@ -235,14 +370,15 @@ mod tests {
         cache.insert(
             "w::src/app.ts".to_string(),
             CacheEntry {
-                marker_hash: marker_content_hash(&m),
+                marker_hash: marker_content_hash(&m, &[]),
                 file_hashes: HashMap::new(),
                 is_valid: true,
                 reason: None,
+                fingerprint: None,
             },
         );
         // Unscoped markers always miss
-        assert!(check_cache(&m, &cache, Path::new("/repo")).is_none());
+        assert!(check_cache(&m, &[], &cache, Path::new("/repo")).is_none());
     }
 
     #[test]
@@ -252,7 +388,7 @@ mod tests {
         fs::write(&file_path, "content").unwrap();
 
         let m = make_marker("w", "Check it", vec!["file.ts".to_string()]);
-        let content_hash = marker_content_hash(&m);
+        let content_hash = marker_content_hash(&m, &[]);
         let file_hashes = hash_watched_files(&m, dir.path());
 
         let mut cache = Cache::new();
@@ -263,10 +399,11 @@ mod tests {
                 file_hashes,
                 is_valid: true,
                 reason: None,
+                fingerprint: None,
             },
         );
 
-        let entry = check_cache(&m, &cache, dir.path()).unwrap();
+        let entry = check_cache(&m, &[], &cache, dir.path()).unwrap();
         assert!(entry.is_valid);
     }
 
@@ -283,14 +420,15 @@ mod tests {
         cache.insert(
             cache_key(&m_old),
             CacheEntry {
-                marker_hash: marker_content_hash(&m_old),
+                marker_hash: marker_content_hash(&m_old, &[]),
                 file_hashes: hash_watched_files(&m_old, dir.path()),
                 is_valid: true,
                 reason: None,
+                fingerprint: None,
             },
         );
 
-        assert!(check_cache(&m_new, &cache, dir.path()).is_none());
+        assert!(check_cache(&m_new, &[], &cache, dir.path()).is_none());
     }
 
     #[test]
@@ -300,7 +438,7 @@ mod tests {
         fs::write(&file_path, "original").unwrap();
 
         let m = make_marker("w", "Check it", vec!["file.ts".to_string()]);
-        let content_hash = marker_content_hash(&m);
+        let content_hash = marker_content_hash(&m, &[]);
         let old_hashes = hash_watched_files(&m, dir.path());
 
         let mut cache = Cache::new();
@@ -311,12 +449,13 @@ mod tests {
                 file_hashes: old_hashes,
                 is_valid: true,
                 reason: None,
+                fingerprint: None,
             },
         );
 
         // Modify the file
         fs::write(&file_path, "modified").unwrap();
-        assert!(check_cache(&m, &cache, dir.path()).is_none());
+        assert!(check_cache(&m, &[], &cache, dir.path()).is_none());
     }
 
     #[test]
@@ -336,15 +475,44 @@ mod tests {
         cache.insert(
             cache_key(&m_old),
             CacheEntry {
-                marker_hash: marker_content_hash(&m_old),
+                marker_hash: marker_content_hash(&m_old, &[]),
                 file_hashes: hash_watched_files(&m_old, dir.path()),
                 is_valid: true,
                 reason: None,
+                fingerprint: None,
             },
         );
 
         // m_new has an extra file, so file_hashes won't match
-        assert!(check_cache(&m_new, &cache, dir.path()).is_none());
+        assert!(check_cache(&m_new, &[], &cache, dir.path()).is_none());
+    }
+
+    #[test]
+    fn check_cache_miss_marker_modified() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.ts"), "a").unwrap();
+        fs::write(dir.path().join("b.ts"), "b").unwrap();
+
+        let m_old = make_marker("w", "Check it", vec!["a.ts".to_string()]);
+        let (key, entry) = build_entry(&m_old, &[], &make_result(true, None), dir.path());
+        let mut cache = Cache::from([(key, entry)]);
+        assert!(!is_modified(&m_old, &cache));
+        assert!(check_cache(&m_old, &[], &cache, dir.path()).is_some());
+
+        // Only whitespace changed: same fingerprint, still a hit.
+        let reflowed = make_marker("w", "Check\n   it", vec!["a.ts".to_string()]);
+        assert!(!is_modified(&reflowed, &cache));
+
+        // Excluding a file outside the scope changes neither the content hash
+        // nor the watched files, but the fingerprint catches the edit.
+        let mut rescoped = m_old.clone();
+        rescoped.exclude = vec!["b.ts".to_string()];
+        assert!(is_modified(&rescoped, &cache));
+        assert!(check_cache(&rescoped, &[], &cache, dir.path()).is_none());
+
+        // Entries from before fingerprints were recorded are not flagged.
+        cache.get_mut("w::src/app.ts").unwrap().fingerprint = None;
+        assert!(!is_modified(&rescoped, &cache));
     }
 
     // ── build_entry ───────────────────────────────────────────────────────
@@ -353,7 +521,7 @@ mod tests {
     fn build_entry_valid_result() {
         let m = make_marker("w", "Check it", vec![]);
         let r = make_result(true, None);
-        let (key, entry) = build_entry(&m, &r, Path::new("/repo"));
+        let (key, entry) = build_entry(&m, &[], &r, Path::new("/repo"));
         assert_eq!(key, "w::src/app.ts");
         assert!(entry.is_valid);
         assert!(entry.reason.is_none());
@@ -363,7 +531,7 @@ mod tests {
     fn build_entry_failed_result() {
         let m = make_marker("w", "Check it", vec![]);
         let r = make_result(false, Some("broken"));
-        let (_, entry) = build_entry(&m, &r, Path::new("/repo"));
+        let (_, entry) = build_entry(&m, &[], &r, Path::new("/repo"));
         assert!(!entry.is_valid);
         assert_eq!(entry.reason.as_deref(), Some("broken"));
     }
@@ -375,10 +543,75 @@ mod tests {
 
         let m = make_marker("w", "Check it", vec!["file.ts".to_string()]);
         let r = make_result(true, None);
-        let (_, entry) = build_entry(&m, &r, dir.path());
+        let (_, entry) = build_entry(&m, &[], &r, dir.path());
         assert!(entry.file_hashes.contains_key("file.ts"));
     }
 
+    // ── content_key / verdicts ────────────────────────────────────────────
+
+    #[test]
+    fn content_key_follows_bytes_not_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file.ts");
+        let m = make_marker("w", "Check it", vec!["file.ts".to_string()]);
+        assert_eq!(
+            content_key(&make_marker("w", "Check it", vec![]), &[], dir.path(), None),
+            None
+        );
+
+        fs::write(&file, "main").unwrap();
+        let on_main = content_key(&m, &[], dir.path(), None).unwrap();
+        fs::write(&file, "feature").unwrap();
+        let on_feature = content_key(&m, &[], dir.path(), None).unwrap();
+        assert_ne!(on_main, on_feature);
+        // Back on main: the same bytes give the same key.
+        fs::write(&file, "main").unwrap();
+        assert_eq!(content_key(&m, &[], dir.path(), None).unwrap(), on_main);
+
+        // Only the hunks of watched files count in diff mode.
+        let diff = |other: &str| {
+            format!(
+                "diff --git a/file.ts b/file.ts\n+x\ndiff --git a/other.ts b/other.ts\n+{other}\n"
+            )
+        };
+        assert_eq!(
+            content_key(&m, &[], dir.path(), Some(&diff("a"))),
+            content_key(&m, &[], dir.path(), Some(&diff("b")))
+        );
+        assert_ne!(
+            content_key(&m, &[], dir.path(), Some(&diff("a"))),
+            Some(on_main)
+        );
+    }
+
+    #[test]
+    fn verdicts_round_trip_and_evict_oldest() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut verdicts = Verdicts::new();
+        store_verdict(
+            &mut verdicts,
+            "k".to_string(),
+            &make_result(false, Some("broken")),
+        );
+        save_verdicts(dir.path(), &mut verdicts);
+        let loaded = load_verdicts(dir.path());
+        assert_eq!(loaded["k"].reason.as_deref(), Some("broken"));
+
+        let mut verdicts: Verdicts = (0..=MAX_VERDICTS as u64)
+            .map(|i| {
+                let verdict = Verdict {
+                    is_valid: true,
+                    reason: None,
+                    stored_at: i,
+                };
+                (i.to_string(), verdict)
+            })
+            .collect();
+        save_verdicts(dir.path(), &mut verdicts);
+        assert_eq!(verdicts.len(), MAX_VERDICTS);
+        assert!(!verdicts.contains_key("0"));
+    }
+
     // ── load_cache / save_cache ───────────────────────────────────────────
     // These use the hardcoded CACHE_DIR/CACHE_FILE paths so we test
     // the serialization logic directly instead.
@@ -393,6 +626,7 @@ mod tests {
                 file_hashes: HashMap::from([("f.ts".to_string(), 67890)]),
                 is_valid: true,
                 reason: None,
+                fingerprint: None,
             },
         );
 
@@ -429,6 +663,7 @@ mod tests {
                 file_hashes: HashMap::new(),
                 is_valid: false,
                 reason: Some("something broke".to_string()),
+                fingerprint: None,
             },
         );
 
@@ -454,6 +689,22 @@ mod tests {
         assert!(hashes.contains_key("b.ts"));
     }
 
+    #[test]
+    fn hash_watched_files_includes_local_context() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.ts"), "aaa").unwrap();
+        fs::write(dir.path().join("README.md"), "docs").unwrap();
+
+        let mut m = make_marker("w", "Check", vec!["a.ts".to_string()]);
+        m.context = vec![
+            "README.md".to_string(),
+            "https://wiki.example.com/spec.md".to_string(),
+        ];
+        let hashes = hash_watched_files(&m, dir.path());
+        assert_eq!(hashes.len(), 2);
+        assert!(hashes.contains_key("README.md"));
+    }
+
     #[test]
     fn hash_watched_files_missing_file_skipped() {
         let dir = tempfile::tempdir().unwrap();
@@ -487,4 +738,16 @@ mod tests {
         let h2 = hash_watched_files(&m, dir.path());
         assert_eq!(h1, h2);
     }
+
+    #[test]
+    fn command_output_watchers_are_not_cacheable() {
+        let mut m = make_marker("w", "Check", vec!["f.ts".to_string()]);
+        assert!(is_cacheable(&m));
+        m.options
+            .insert("strategy".to_string(), "files-inline".to_string());
+        assert!(is_cacheable(&m));
+        m.options
+            .insert("strategy".to_string(), "command-output".to_string());
+        assert!(!is_cacheable(&m));
+    }
 }
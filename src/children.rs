@@ -0,0 +1,122 @@
+use std::io;
+use std::process;
+use std::sync::Mutex;
+
+/// Process ids of the children a run is waiting on (claude sessions,
+/// checkers, requests to workers), each leading its own process group.
+static RUNNING: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// A spawned child, tracked until this is dropped.
+pub struct Tracked(u32);
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let mut running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+        running.retain(|&pid| pid != self.0);
+    }
+}
+
+/// Spawn `cmd` in a process group of its own and track it, so [`kill_all`]
+/// also stops whatever it started (a claude session's tool calls). Keep the
+/// [`Tracked`] until the child has been waited on.
+///
+/// Its own group also keeps the child from the terminal's SIGINT, so if
+/// watcher-knight dies without running [`kill_all`] (SIGKILL, a panic that
+/// aborts) the child would outlive it. On Linux the kernel kills the child
+/// when the thread that spawned it exits; its own children are not reached.
+/// Elsewhere such children are orphaned.
+pub fn spawn(cmd: &mut process::Command) -> io::Result<(process::Child, Tracked)> {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(cmd, 0);
+    #[cfg(target_os = "linux")]
+    die_with_parent(cmd);
+    let mut running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+    let child = cmd.spawn()?;
+    let pid = child.id();
+    running.push(pid);
+    Ok((child, Tracked(pid)))
+}
+
+/// Kill every tracked child with its process group. Whoever waits on one
+/// sees it die from a signal. Returns how many there were.
+pub fn kill_all() -> usize {
+    let running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+    for &pid in running.iter() {
+        kill_group(pid);
+    }
+    running.len()
+}
+
+#[cfg(unix)]
+fn kill_group(pid: u32) {
+    // SAFETY: `kill` has no memory effects; the group was created by `spawn`.
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn die_with_parent(cmd: &mut process::Command) {
+    let parent = process::id();
+    // SAFETY: `prctl` and `getppid` are async-signal-safe.
+    unsafe {
+        std::os::unix::process::CommandExt::pre_exec(cmd, move || {
+            if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            // The parent died before the signal was armed.
+            if libc::getppid() as u32 != parent {
+                return Err(io::Error::other("watcher-knight exited"));
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(pid: u32) {
+    process::Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &pid.to_string()])
+        .output()
+        .ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn killing_a_tracked_child_stops_it() {
+        // `kill_all` would also stop the children of tests running alongside.
+        let (mut child, tracked) =
+            spawn(process::Command::new("sh").args(["-c", "sleep 30; sleep 30"])).unwrap();
+        assert!(RUNNING.lock().unwrap().contains(&child.id()));
+        kill_group(child.id());
+        let status = child.wait().unwrap();
+        assert!(!status.success());
+        drop(tracked);
+        assert!(!RUNNING.lock().unwrap().contains(&child.id()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn a_child_dies_with_the_thread_that_spawned_it() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::time::{Duration, Instant};
+
+        let mut child =
+            std::thread::spawn(|| spawn(process::Command::new("sleep").arg("30")).unwrap().0)
+                .join()
+                .unwrap();
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait().unwrap() {
+                break status;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10), "still running");
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+
+use crate::marker::Marker;
+
+/// Watched files above which a watcher is validated in chunks, unless
+/// `chunk_files` in the config says otherwise.
+pub const DEFAULT_CHUNK_FILES: usize = 200;
+
+/// Part of a large scope, validated on its own.
+#[derive(Debug, PartialEq)]
+pub struct Chunk {
+    /// The directories it covers, e.g. `src/api/, src/auth/`.
+    pub label: String,
+    pub files: Vec<String>,
+}
+
+/// The directory holding `file`, with a trailing `/` (`./` at the root).
+fn dir_of(file: &str) -> String {
+    match file.rsplit_once('/') {
+        Some((dir, _)) => format!("{dir}/"),
+        None => "./".to_string(),
+    }
+}
+
+/// Split `files` into chunks of at most `max` files. Files stay with the
+/// others of their directory, and neighbouring directories share a chunk
+/// while they fit; a directory with more than `max` files is cut in parts.
+pub fn split(files: &[String], max: usize) -> Vec<Chunk> {
+    let mut by_dir: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for file in files {
+        by_dir.entry(dir_of(file)).or_default().push(file.clone());
+    }
+
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut dirs: Vec<String> = Vec::new();
+    let mut pending: Vec<String> = Vec::new();
+    let flush = |chunks: &mut Vec<Chunk>, dirs: &mut Vec<String>, pending: &mut Vec<String>| {
+        if !pending.is_empty() {
+            chunks.push(Chunk {
+                label: dirs.join(", "),
+                files: std::mem::take(pending),
+            });
+            dirs.clear();
+        }
+    };
+    for (dir, mut dir_files) in by_dir {
+        dir_files.sort();
+        if dir_files.len() > max {
+            flush(&mut chunks, &mut dirs, &mut pending);
+            let parts = dir_files.len().div_ceil(max);
+            for (i, part) in dir_files.chunks(max).enumerate() {
+                chunks.push(Chunk {
+                    label: format!("{dir} (part {} of {parts})", i + 1),
+                    files: part.to_vec(),
+                });
+            }
+            continue;
+        }
+        if pending.len() + dir_files.len() > max {
+            flush(&mut chunks, &mut dirs, &mut pending);
+        }
+        dirs.push(dir);
+        pending.extend(dir_files);
+    }
+    flush(&mut chunks, &mut dirs, &mut pending);
+    chunks
+}
+
+/// The chunks to validate `marker` in, or `None` when its watched files fit
+/// in one pass (`max` of 0 never chunks).
+pub fn chunks_for(marker: &Marker, watched: &[String], max: usize) -> Option<Vec<Chunk>> {
+    if max == 0 || marker.files.is_empty() || watched.len() <= max {
+        return None;
+    }
+    Some(split(watched, max))
+}
+
+/// `marker` narrowed to the files of `chunk`.
+pub fn narrow(marker: &Marker, chunk: &Chunk) -> Marker {
+    Marker {
+        files: chunk.files.clone(),
+        ..marker.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn split_keeps_directories_together() {
+        let chunks = split(
+            &files(&[
+                "src/api/a.ts",
+                "src/api/b.ts",
+                "src/auth/c.ts",
+                "src/db/d.ts",
+                "src/db/e.ts",
+                "src/db/f.ts",
+                "main.ts",
+            ]),
+            3,
+        );
+        assert_eq!(
+            chunks,
+            [
+                Chunk {
+                    label: "./, src/api/".to_string(),
+                    files: files(&["main.ts", "src/api/a.ts", "src/api/b.ts"]),
+                },
+                Chunk {
+                    label: "src/auth/".to_string(),
+                    files: files(&["src/auth/c.ts"]),
+                },
+                Chunk {
+                    label: "src/db/".to_string(),
+                    files: files(&["src/db/d.ts", "src/db/e.ts", "src/db/f.ts"]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn split_packs_small_directories_and_cuts_large_ones() {
+        let chunks = split(&files(&["a/1", "b/1", "c/1", "c/2", "c/3"]), 2);
+        let labels: Vec<&str> = chunks.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, ["a/, b/", "c/ (part 1 of 2)", "c/ (part 2 of 2)"]);
+        assert_eq!(chunks[2].files, ["c/3"]);
+    }
+
+    #[test]
+    fn only_large_scoped_watchers_are_chunked() {
+        let mut marker = Marker {
+            name: "big".to_string(),
+            rel_path: "README.md".to_string(),
+            line: 1,
+            instruction: "Check it".to_string(),
+            files: vec!["src/".to_string()],
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: Default::default(),
+        };
+        let watched = files(&["src/a/1", "src/b/1", "src/b/2"]);
+        assert!(chunks_for(&marker, &watched, 3).is_none());
+        assert!(chunks_for(&marker, &watched, 0).is_none());
+        let chunks = chunks_for(&marker, &watched, 2).unwrap();
+        assert_eq!(narrow(&marker, &chunks[1]).files, ["src/b/1", "src/b/2"]);
+        marker.files.clear();
+        assert!(chunks_for(&marker, &watched, 2).is_none());
+    }
+}
@@ -0,0 +1,346 @@
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::http::Request;
+use crate::tickets::GITHUB_TOKEN_VAR;
+
+/// Environment variable holding a Bitbucket repository access token with
+/// the `pullrequest` scope. Bitbucket Pipelines provides none by itself.
+pub const BITBUCKET_TOKEN_VAR: &str = "BITBUCKET_TOKEN";
+/// The job's OAuth token in Azure Pipelines, which a step only sees when the
+/// pipeline maps it (`env: SYSTEM_ACCESSTOKEN: $(System.AccessToken)`).
+pub const AZURE_TOKEN_VAR: &str = "SYSTEM_ACCESSTOKEN";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Provider {
+    GitHub,
+    Bitbucket,
+    AzureDevOps,
+}
+
+impl Provider {
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::GitHub => "GitHub",
+            Provider::Bitbucket => "Bitbucket",
+            Provider::AzureDevOps => "Azure DevOps",
+        }
+    }
+}
+
+/// The pull request a CI job is building.
+#[derive(Debug, PartialEq)]
+pub struct PullRequest {
+    pub provider: Provider,
+    pub id: String,
+    /// Where comments are posted.
+    pub comments_url: String,
+    pub token: String,
+}
+
+/// The pull request being built, from the CI system's environment: GitHub
+/// Actions, Bitbucket Pipelines or Azure Pipelines. `None` outside a pull
+/// request build; an error if the build lacks what posting needs.
+pub fn detect(var: impl Fn(&str) -> Option<String>) -> Result<Option<PullRequest>, String> {
+    let var = |name: &str| var(name).filter(|v| !v.is_empty());
+    let need = |provider: Provider, name: &str| {
+        var(name).ok_or_else(|| {
+            format!(
+                "commenting on a {} pull request needs {name}",
+                provider.name()
+            )
+        })
+    };
+    if var("GITHUB_ACTIONS").as_deref() == Some("true") {
+        // `refs/pull/<number>/merge` on pull_request events.
+        let Some(number) = var("GITHUB_REF").and_then(|r| {
+            r.strip_prefix("refs/pull/")?
+                .split_once('/')
+                .map(|(n, _)| n.to_string())
+        }) else {
+            return Ok(None);
+        };
+        let provider = Provider::GitHub;
+        let api = var("GITHUB_API_URL").unwrap_or_else(|| "https://api.github.com".to_string());
+        let repo = need(provider, "GITHUB_REPOSITORY")?;
+        return Ok(Some(PullRequest {
+            provider,
+            comments_url: format!(
+                "{}/repos/{repo}/issues/{number}/comments",
+                api.trim_end_matches('/')
+            ),
+            id: number,
+            token: need(provider, GITHUB_TOKEN_VAR)?,
+        }));
+    }
+    if let Some(id) = var("BITBUCKET_PR_ID") {
+        let provider = Provider::Bitbucket;
+        let workspace = need(provider, "BITBUCKET_WORKSPACE")?;
+        let repo = need(provider, "BITBUCKET_REPO_SLUG")?;
+        return Ok(Some(PullRequest {
+            provider,
+            comments_url: format!(
+                "https://api.bitbucket.org/2.0/repositories/{workspace}/{repo}/pullrequests/{id}/comments"
+            ),
+            id,
+            token: need(provider, BITBUCKET_TOKEN_VAR)?,
+        }));
+    }
+    if let Some(id) = var("SYSTEM_PULLREQUEST_PULLREQUESTID") {
+        let provider = Provider::AzureDevOps;
+        // `https://dev.azure.com/<org>/`; the IDs avoid escaping names.
+        let collection = need(provider, "SYSTEM_COLLECTIONURI")?;
+        let project = need(provider, "SYSTEM_TEAMPROJECTID")?;
+        let repo = need(provider, "BUILD_REPOSITORY_ID")?;
+        return Ok(Some(PullRequest {
+            provider,
+            comments_url: format!(
+                "{}/{project}/_apis/git/repositories/{repo}/pullRequests/{id}/threads?api-version=7.1",
+                collection.trim_end_matches('/')
+            ),
+            id,
+            token: need(provider, AZURE_TOKEN_VAR)?,
+        }));
+    }
+    Ok(None)
+}
+
+/// The CI job running watcher-knight, recorded in report provenance.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Job {
+    pub system: &'static str,
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// The CI job from the CI system's environment: GitHub Actions, GitLab CI,
+/// Bitbucket Pipelines, Azure Pipelines, Jenkins or CircleCI.
+pub fn job(var: impl Fn(&str) -> Option<String>) -> Option<Job> {
+    let var = |name: &str| var(name).filter(|v| !v.is_empty());
+    let job =
+        |system, id: Option<String>, url: Option<String>| id.map(|id| Job { system, id, url });
+    if var("GITHUB_ACTIONS").as_deref() == Some("true") {
+        let id = var("GITHUB_RUN_ID");
+        let url = match (var("GITHUB_SERVER_URL"), var("GITHUB_REPOSITORY"), &id) {
+            (Some(server), Some(repo), Some(id)) => {
+                Some(format!("{server}/{repo}/actions/runs/{id}"))
+            }
+            _ => None,
+        };
+        return job("GitHub Actions", id, url);
+    }
+    if var("GITLAB_CI").is_some() {
+        return job("GitLab CI", var("CI_JOB_ID"), var("CI_JOB_URL"));
+    }
+    if let Some(build) = var("BITBUCKET_BUILD_NUMBER") {
+        let url = var("BITBUCKET_WORKSPACE")
+            .zip(var("BITBUCKET_REPO_SLUG"))
+            .map(|(ws, repo)| {
+                format!("https://bitbucket.org/{ws}/{repo}/pipelines/results/{build}")
+            });
+        return job("Bitbucket Pipelines", Some(build), url);
+    }
+    if var("TF_BUILD").is_some() {
+        let id = var("BUILD_BUILDID");
+        let url = match (
+            var("SYSTEM_COLLECTIONURI"),
+            var("SYSTEM_TEAMPROJECTID"),
+            &id,
+        ) {
+            (Some(collection), Some(project), Some(id)) => Some(format!(
+                "{}/{project}/_build/results?buildId={id}",
+                collection.trim_end_matches('/')
+            )),
+            _ => None,
+        };
+        return job("Azure Pipelines", id, url);
+    }
+    if var("JENKINS_URL").is_some() {
+        return job("Jenkins", var("BUILD_NUMBER"), var("BUILD_URL"));
+    }
+    if var("CIRCLECI").is_some() {
+        return job("CircleCI", var("CIRCLE_BUILD_NUM"), var("CIRCLE_BUILD_URL"));
+    }
+    None
+}
+
+impl PullRequest {
+    /// The request body posting `markdown` as a comment. Azure DevOps
+    /// comments live in threads, so each post opens an active thread.
+    fn comment_body(&self, markdown: &str) -> Value {
+        match self.provider {
+            Provider::GitHub => json!({ "body": markdown }),
+            Provider::Bitbucket => json!({ "content": { "raw": markdown } }),
+            Provider::AzureDevOps => json!({
+                "comments": [{ "parentCommentId": 0, "content": markdown, "commentType": 1 }],
+                "status": 1,
+            }),
+        }
+    }
+
+    /// Post `markdown` as a new comment on the pull request.
+    pub fn post_comment(&self, markdown: &str) -> Result<(), String> {
+        Request::post(&self.comments_url)
+            .bearer(&self.token)
+            .json(self.comment_body(markdown).to_string())
+            .send()?
+            .success(&format!("POST {}", self.comments_url))
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Write;
+
+    fn env(vars: &[(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        move |name| vars.get(name).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn detect_reads_each_ci_system() {
+        let github = detect(env(&[
+            ("GITHUB_ACTIONS", "true"),
+            ("GITHUB_REF", "refs/pull/12/merge"),
+            ("GITHUB_REPOSITORY", "org/repo"),
+            ("GITHUB_TOKEN", "ghp"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            github.comments_url,
+            "https://api.github.com/repos/org/repo/issues/12/comments"
+        );
+
+        let bitbucket = detect(env(&[
+            ("BITBUCKET_PR_ID", "5"),
+            ("BITBUCKET_WORKSPACE", "team"),
+            ("BITBUCKET_REPO_SLUG", "repo"),
+            ("BITBUCKET_TOKEN", "bbt"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(bitbucket.provider, Provider::Bitbucket);
+        assert_eq!(
+            bitbucket.comments_url,
+            "https://api.bitbucket.org/2.0/repositories/team/repo/pullrequests/5/comments"
+        );
+
+        let azure = detect(env(&[
+            ("SYSTEM_PULLREQUEST_PULLREQUESTID", "9"),
+            ("SYSTEM_COLLECTIONURI", "https://dev.azure.com/org/"),
+            ("SYSTEM_TEAMPROJECTID", "p-id"),
+            ("BUILD_REPOSITORY_ID", "r-id"),
+            ("SYSTEM_ACCESSTOKEN", "azt"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            azure.comments_url,
+            "https://dev.azure.com/org/p-id/_apis/git/repositories/r-id/pullRequests/9/threads?api-version=7.1"
+        );
+        assert_eq!(
+            azure.comment_body("ok")["comments"][0]["content"],
+            json!("ok")
+        );
+    }
+
+    #[test]
+    fn detect_outside_pull_requests_and_without_tokens() {
+        let push = env(&[
+            ("GITHUB_ACTIONS", "true"),
+            ("GITHUB_REF", "refs/heads/main"),
+        ]);
+        assert_eq!(detect(push), Ok(None));
+        assert_eq!(detect(env(&[])), Ok(None));
+        let err = detect(env(&[
+            ("BITBUCKET_PR_ID", "5"),
+            ("BITBUCKET_WORKSPACE", "team"),
+            ("BITBUCKET_REPO_SLUG", "repo"),
+        ]))
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "commenting on a Bitbucket pull request needs BITBUCKET_TOKEN"
+        );
+    }
+
+    #[test]
+    fn job_identifies_the_ci_run() {
+        assert_eq!(
+            job(env(&[
+                ("GITHUB_ACTIONS", "true"),
+                ("GITHUB_RUN_ID", "42"),
+                ("GITHUB_SERVER_URL", "https://github.com"),
+                ("GITHUB_REPOSITORY", "org/repo"),
+            ])),
+            Some(Job {
+                system: "GitHub Actions",
+                id: "42".to_string(),
+                url: Some("https://github.com/org/repo/actions/runs/42".to_string()),
+            })
+        );
+        let gitlab = job(env(&[("GITLAB_CI", "true"), ("CI_JOB_ID", "7")])).unwrap();
+        assert_eq!((gitlab.system, gitlab.id.as_str()), ("GitLab CI", "7"));
+        assert_eq!(gitlab.url, None);
+        let azure = job(env(&[
+            ("TF_BUILD", "True"),
+            ("BUILD_BUILDID", "9"),
+            ("SYSTEM_COLLECTIONURI", "https://dev.azure.com/org/"),
+            ("SYSTEM_TEAMPROJECTID", "p-id"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            azure.url.as_deref(),
+            Some("https://dev.azure.com/org/p-id/_build/results?buildId=9")
+        );
+        assert_eq!(job(env(&[])), None);
+    }
+
+    #[test]
+    fn post_comment_sends_the_providers_body() {
+        use std::io::{BufRead, BufReader, Read};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/comments", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let (mut line, mut length, mut authorized) = (String::new(), 0, false);
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = v.trim().parse().unwrap();
+                }
+                authorized |= line.trim() == "Authorization: Bearer bbt";
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 201 X\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{{}}"
+            )
+            .unwrap();
+            (authorized, String::from_utf8(body).unwrap())
+        });
+        let pr = PullRequest {
+            provider: Provider::Bitbucket,
+            id: "5".to_string(),
+            comments_url: url,
+            token: "bbt".to_string(),
+        };
+        pr.post_comment("**All passed**").unwrap();
+        let (authorized, body) = server.join().unwrap();
+        assert!(authorized);
+        assert_eq!(body, r#"{"content":{"raw":"**All passed**"}}"#);
+    }
+}
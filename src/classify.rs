@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use clap::ValueEnum;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::marker::{self, Marker};
+use crate::plugins;
+use crate::script;
+
+/// AI suggestions by marker fingerprint, so each watcher is only sent once.
+const CACHE_FILE: &str = ".watcher_knight/classify.json";
+
+/// Version of the `classify --format json` schema. Bump on breaking changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How `classify` prints its report.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ClassifyFormat {
+    Text,
+    Json,
+}
+
+/// How a watcher is, or could be, checked.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// Already checked without a model: an `assert` or a checker plugin.
+    Deterministic,
+    /// Checked by a model, but an `assert` expression says the same.
+    Convertible,
+    /// Needs a model.
+    Llm,
+}
+
+impl Kind {
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Deterministic => "deterministic",
+            Kind::Convertible => "convertible",
+            Kind::Llm => "llm",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Classification {
+    pub name: String,
+    pub location: String,
+    pub kind: Kind,
+    /// The `assert` expression a convertible watcher could use.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assert: Option<String>,
+    /// Where the expression came from: `heuristic` or `ai`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub version: u32,
+    pub total: usize,
+    pub deterministic: usize,
+    pub convertible: usize,
+    pub llm: usize,
+    /// Share of watchers that are, or could be, checked exactly.
+    pub exact_ratio: f64,
+    pub watchers: Vec<Classification>,
+}
+
+/// Cached AI suggestions: marker fingerprint → expression, or `None` when
+/// the AI found no exact equivalent.
+pub type Suggestions = HashMap<String, Option<String>>;
+
+pub fn load_suggestions(root: &Path) -> Suggestions {
+    fs::read_to_string(root.join(CACHE_FILE))
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_suggestions(root: &Path, suggestions: &Suggestions) {
+    let path = root.join(CACHE_FILE);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).ok();
+    }
+    fs::write(path, serde_json::to_string(suggestions).unwrap()).ok();
+}
+
+/// Builds an `assert` expression from the groups a pattern captured.
+type Build = fn(&[&str]) -> String;
+
+/// Instruction shapes with an exact equivalent. Names and paths must be in
+/// backticks, so prose is never mistaken for one.
+static PATTERNS: LazyLock<Vec<(Regex, Build)>> = LazyLock::new(|| {
+    let must = r"(?:must|should|has to|needs to)";
+    let pattern = |re: &str| Regex::new(&format!("(?i)^{re}$")).unwrap();
+    vec![
+        (
+            pattern(&format!(
+                r"(?:the )?(?:file )?`([^`]+)` {must} (?:still )?exist"
+            )),
+            |c: &[&str]| format!("exists('{}')", path(c[0])),
+        ),
+        (
+            pattern(&format!(r"(?:the )?(?:file )?`([^`]+)` {must} not exist")),
+            |c: &[&str]| format!("!exists('{}')", path(c[0])),
+        ),
+        (
+            pattern(&format!(
+                r"(?:the )?(?:file )?`([^`]+)` {must} (?:contain|mention|include) `([^`]+)`"
+            )),
+            |c: &[&str]| format!("contains('{}', '{}')", path(c[0]), literal(c[1])),
+        ),
+        (
+            pattern(&format!(
+                r"(?:there {must} be )?exactly (\d+) files? (?:match(?:es|ing)?|under) `([^`]+)`"
+            )),
+            |c: &[&str]| format!("count('{}') == {}", path(c[1]), c[0]),
+        ),
+        (
+            pattern(&format!(r"`([^`]+)` {must} match exactly (\d+) files?")),
+            |c: &[&str]| format!("count('{}') == {}", path(c[0]), c[1]),
+        ),
+        (
+            pattern(&format!(
+                r"`([A-Za-z_][A-Za-z0-9_]*)` {must} (?:still )?be (?:referenced|used)(?: somewhere| elsewhere)?"
+            )),
+            |c: &[&str]| format!("references('{}')", c[0]),
+        ),
+    ]
+});
+
+/// A path from an instruction, which names it from the repo root unless it
+/// starts with `./` or `../`.
+fn path(text: &str) -> String {
+    if text.starts_with("./") || text.starts_with("../") || text.starts_with('/') {
+        text.to_string()
+    } else {
+        format!("/{text}")
+    }
+}
+
+/// A regex matching `text` literally, as a string in an `assert`.
+fn literal(text: &str) -> String {
+    regex::escape(text).replace('\\', "\\\\")
+}
+
+/// The `assert` expression equivalent to `instruction`, if it is a single
+/// sentence of a shape [`PATTERNS`] knows.
+pub fn heuristic(instruction: &str) -> Option<String> {
+    let sentence = instruction.split_whitespace().collect::<Vec<_>>().join(" ");
+    let sentence = sentence.trim_end_matches('.');
+    if sentence.contains(['\'', '"']) {
+        return None;
+    }
+    PATTERNS.iter().find_map(|(re, build)| {
+        let caps = re.captures(sentence)?;
+        let groups: Vec<&str> = caps.iter().skip(1).flatten().map(|m| m.as_str()).collect();
+        Some(build(&groups))
+    })
+}
+
+/// Classify every marker: declared checks first, then the heuristic, then a
+/// cached AI suggestion.
+pub fn classify(markers: &[Marker], suggestions: &Suggestions) -> Vec<Classification> {
+    markers
+        .iter()
+        .map(|m| {
+            let (kind, assert, source) =
+                if script::assert_for(m).is_some() || plugins::checker_for(m).is_some() {
+                    (Kind::Deterministic, None, None)
+                } else if let Some(expr) = heuristic(&m.instruction) {
+                    (Kind::Convertible, Some(expr), Some("heuristic"))
+                } else if let Some(Some(expr)) = suggestions.get(&marker::fingerprint(m)) {
+                    (Kind::Convertible, Some(expr.clone()), Some("ai"))
+                } else {
+                    (Kind::Llm, None, None)
+                };
+            Classification {
+                name: m.name.clone(),
+                location: format!("{}:{}", m.rel_path, m.line),
+                kind,
+                assert,
+                source,
+            }
+        })
+        .collect()
+}
+
+/// Markers the heuristic cannot convert and the AI has not seen yet.
+pub fn unasked<'a>(markers: &'a [Marker], suggestions: &Suggestions) -> Vec<&'a Marker> {
+    classify(markers, suggestions)
+        .iter()
+        .zip(markers)
+        .filter(|(c, m)| c.kind == Kind::Llm && !suggestions.contains_key(&marker::fingerprint(m)))
+        .map(|(_, m)| m)
+        .collect()
+}
+
+/// Keep an AI suggestion only if it is a usable option value that evaluates
+/// to a boolean for `marker` today.
+pub fn validate(expr: &str, marker: &Marker, root: &Path) -> Result<(), String> {
+    if expr.contains('"') {
+        return Err(format!("`{expr}` contains `\"`"));
+    }
+    script::eval_assert(expr, marker, root).map(|_| ())
+}
+
+pub fn report(watchers: Vec<Classification>) -> Report {
+    let count = |kind| watchers.iter().filter(|c| c.kind == kind).count();
+    let (deterministic, convertible, llm) = (
+        count(Kind::Deterministic),
+        count(Kind::Convertible),
+        count(Kind::Llm),
+    );
+    let total = watchers.len();
+    let exact_ratio = if total == 0 {
+        0.0
+    } else {
+        (deterministic + convertible) as f64 / total as f64
+    };
+    Report {
+        version: SCHEMA_VERSION,
+        total,
+        deterministic,
+        convertible,
+        llm,
+        exact_ratio,
+        watchers,
+    }
+}
+
+pub fn render(report: &Report, format: ClassifyFormat) -> String {
+    match format {
+        ClassifyFormat::Json => serde_json::to_string_pretty(report).unwrap() + "\n",
+        ClassifyFormat::Text => render_text(report),
+    }
+}
+
+fn render_text(report: &Report) -> String {
+    let mut out = String::new();
+    for c in &report.watchers {
+        writeln!(out, "{:<13} {} ({})", c.kind.label(), c.name, c.location).unwrap();
+        if let Some(expr) = &c.assert {
+            let source = c.source.unwrap_or("heuristic");
+            writeln!(
+                out,
+                "              options={{assert=\"{expr}\"}} ({source})"
+            )
+            .unwrap();
+        }
+    }
+    writeln!(
+        out,
+        "\n{} of {} watchers ({:.0}%) are exact-checkable: {} deterministic, {} convertible; {} need a model",
+        report.deterministic + report.convertible,
+        report.total,
+        report.exact_ratio * 100.0,
+        report.deterministic,
+        report.convertible,
+        report.llm
+    )
+    .unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_marker(name: &str, instruction: &str) -> Marker {
+        Marker {
+            name: name.to_string(),
+            rel_path: "src/app.ts".to_string(),
+            line: 1,
+            instruction: instruction.to_string(),
+            files: Vec::new(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: Default::default(),
+        }
+    }
+
+    #[test]
+    fn heuristic_converts_known_shapes() {
+        let cases = [
+            ("`docs/api.md` must exist.", "exists('/docs/api.md')"),
+            (
+                "The file `./legacy.ts` should not exist",
+                "!exists('./legacy.ts')",
+            ),
+            (
+                "`CHANGELOG.md` must mention `v1.2`.",
+                "contains('/CHANGELOG.md', 'v1\\\\.2')",
+            ),
+            (
+                "There must be exactly 12 files matching `migrations/*.sql`.",
+                "count('/migrations/*.sql') == 12",
+            ),
+            (
+                "`parseUser` must still be referenced elsewhere.",
+                "references('parseUser')",
+            ),
+        ];
+        for (instruction, expected) in cases {
+            assert_eq!(
+                heuristic(instruction).as_deref(),
+                Some(expected),
+                "{instruction}"
+            );
+        }
+    }
+
+    #[test]
+    fn heuristic_leaves_prose_alone() {
+        assert_eq!(heuristic("Every handler must validate its input."), None);
+        assert_eq!(
+            heuristic("`docs/api.md` must exist and be up to date."),
+            None
+        );
+        assert_eq!(heuristic("`a.md` must contain `it's`."), None);
+    }
+
+    #[test]
+    fn heuristic_expressions_evaluate() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("docs")).unwrap();
+        fs::write(dir.path().join("docs/api.md"), "v1.2\n").unwrap();
+        let marker = make_marker("a", "");
+        let expr = heuristic("`docs/api.md` must mention `v1.2`").unwrap();
+        assert!(script::eval_assert(&expr, &marker, dir.path()).unwrap());
+        let expr = heuristic("`docs/api.md` must mention `v1x2`").unwrap();
+        assert!(!script::eval_assert(&expr, &marker, dir.path()).unwrap());
+    }
+
+    #[test]
+    fn classify_and_report_ratio() {
+        let mut declared = make_marker("declared", "Checked by a script.");
+        declared
+            .options
+            .insert("checker".to_string(), "schema".to_string());
+        let heuristic = make_marker("docs", "`docs/api.md` must exist.");
+        let suggested = make_marker("suggested", "Every migration is numbered.");
+        let llm = make_marker("llm", "Handlers validate their input.");
+        let markers = [declared, heuristic, suggested, llm];
+        let mut suggestions = Suggestions::new();
+        suggestions.insert(
+            marker::fingerprint(&markers[2]),
+            Some("count('/migrations/*.sql') > 0".to_string()),
+        );
+
+        let classified = classify(&markers, &suggestions);
+        let kinds: Vec<Kind> = classified.iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                Kind::Deterministic,
+                Kind::Convertible,
+                Kind::Convertible,
+                Kind::Llm
+            ]
+        );
+        assert_eq!(classified[2].source, Some("ai"));
+        assert_eq!(unasked(&markers, &suggestions).len(), 1);
+
+        let report = report(classified);
+        assert_eq!(report.exact_ratio, 0.75);
+        let text = render(&report, ClassifyFormat::Text);
+        assert!(
+            text.contains("3 of 4 watchers (75%) are exact-checkable"),
+            "{text}"
+        );
+        assert!(text.contains("options={assert=\"exists('/docs/api.md')\"} (heuristic)"));
+    }
+
+    #[test]
+    fn validate_rejects_unusable_suggestions() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = make_marker("a", "");
+        assert!(validate("exists('a')", &marker, dir.path()).is_ok());
+        assert!(validate("exists(\"a\")", &marker, dir.path()).is_err());
+        assert!(validate("count('a')", &marker, dir.path()).is_err());
+    }
+}
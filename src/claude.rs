@@ -1,79 +1,836 @@
-use std::io::Write;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process;
-use std::sync::mpsc;
+use std::sync::{Mutex, mpsc};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::marker::Marker;
-use crate::prompt;
+use serde::Deserialize;
 
+#[cfg(feature = "agent-sdk")]
+use crate::agent;
+use crate::airgap::{self, Airgap};
+use crate::children;
+use crate::chunk;
+use crate::clock;
+use crate::cluster;
+use crate::config;
+use crate::confine::{self, Scope};
+use crate::context::{ContextDoc, ContextLoader};
+use crate::deps;
+use crate::history;
+use crate::hooks::Commands;
+use crate::lockfiles;
+use crate::marker::{self, Marker};
+use crate::platform;
+use crate::plugins::{self, Checkers};
+use crate::privacy;
+use crate::prompt::{self, Evidence, Strategy};
+use crate::quota::{self, BackendLimits, Usage};
+use crate::remote::{self, Pool};
+use crate::report;
+use crate::resume::{self, Resumed};
+use crate::script;
+use crate::shutdown;
+use crate::stream::{self, Reply, Telemetry, Transcript};
+use crate::symbols;
+use crate::throttle::{self, Throttle};
+
+#[derive(Clone)]
 pub struct WatcherResult {
     pub name: String,
     pub location: String,
     pub is_valid: bool,
     pub reason: Option<String>,
     pub cached: bool,
+    /// Set when the marker was edited since its cached verdict, so it was
+    /// validated again.
+    pub modified: bool,
+    /// Set when a failure is deliberately not enforced (e.g. waived).
+    pub suppression: Option<Suppression>,
+    /// Set when the watcher was not run at all, with the reason (e.g. `offline`).
+    pub skipped: Option<String>,
+    /// Set on a skipped watcher that would have run but for the run's
+    /// deadline or token budget, so its invariant is neither known to hold
+    /// nor to be broken.
+    pub inconclusive: bool,
+    /// How long a fresh validation took; `None` for cached and skipped results.
+    pub duration_ms: Option<u64>,
+    /// How often a rate-limited backend call was retried.
+    pub retries: u32,
+    /// Set when no verdict was obtained because the backend, a worker or a
+    /// checker failed; the failure is not an invariant violation.
+    pub errored: bool,
+    /// Tool calls, turns and tokens of the claude session that validated the
+    /// watcher; `None` for other backends and cached results.
+    pub telemetry: Option<Telemetry>,
+}
+
+/// Why a failing watcher does not fail the run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suppression {
+    /// Status shown in place of `FAILED`, e.g. `WAIVED`.
+    pub label: &'static str,
+    pub note: String,
+}
+
+impl WatcherResult {
+    pub fn new(name: &str, location: &str, is_valid: bool, reason: Option<String>) -> Self {
+        WatcherResult {
+            name: name.to_string(),
+            location: location.to_string(),
+            is_valid,
+            reason,
+            cached: false,
+            modified: false,
+            suppression: None,
+            skipped: None,
+            inconclusive: false,
+            duration_ms: None,
+            retries: 0,
+            errored: false,
+            telemetry: None,
+        }
+    }
+
+    /// A watcher for which no verdict could be obtained, with the reason.
+    pub fn errored(name: &str, location: &str, reason: String) -> Self {
+        WatcherResult {
+            errored: true,
+            ..WatcherResult::new(name, location, false, Some(reason))
+        }
+    }
+
+    /// A watcher that was not run, e.g. because no backend is available.
+    pub fn skipped(name: &str, location: &str, why: &str) -> Self {
+        WatcherResult {
+            skipped: Some(why.to_string()),
+            ..WatcherResult::new(name, location, false, None)
+        }
+    }
+
+    /// A watcher the run ran out of time or tokens for, with the reason.
+    pub fn inconclusive(name: &str, location: &str, why: &str) -> Self {
+        WatcherResult {
+            inconclusive: true,
+            ..WatcherResult::skipped(name, location, why)
+        }
+    }
+
+    /// Whether this result fails the run.
+    pub fn is_failure(&self) -> bool {
+        !self.is_valid && self.suppression.is_none() && self.skipped.is_none()
+    }
+
+    /// Colored status for progress lines.
+    pub fn status(&self) -> String {
+        if let Some(why) = &self.skipped {
+            if self.inconclusive {
+                return format!("\x1b[33mINCONCLUSIVE ({why})\x1b[0m");
+            }
+            return format!("\x1b[90mNOT RUN ({why})\x1b[0m");
+        }
+        match &self.suppression {
+            _ if self.is_valid => "\x1b[32mOK\x1b[0m".to_string(),
+            Some(s) => format!("\x1b[33m{}\x1b[0m", s.label),
+            None => "\x1b[31mFAILED\x1b[0m".to_string(),
+        }
+    }
+}
+
+/// Decides whether a failing result should be suppressed.
+pub type Suppressor<'a> = &'a dyn Fn(&WatcherResult) -> Option<Suppression>;
+
+/// Everything watchers share besides their marker.
+pub struct RunContext<'a> {
+    /// Scan root; checkers run here and read watched files relative to it.
+    pub root: &'a Path,
+    pub diff: Option<&'a str>,
+    pub model: &'a str,
+    pub checkers: &'a Checkers,
+    /// Skip AI watchers. Checker plugins and assertions are deterministic and
+    /// still run.
+    pub offline: bool,
+    /// Dispatch AI watchers to remote workers instead of the local `claude`.
+    pub pool: Option<&'a Pool>,
+    /// Loads the reference documents listed in `context={...}`.
+    pub context: &'a ContextLoader<'a>,
+    /// Every loaded marker, for resolving `@name` references.
+    pub markers: &'a [Marker],
+    /// `[backends]` limits from the config.
+    pub backends: &'a HashMap<String, BackendLimits>,
+    /// Keep agents to their watcher's files (`confine_tools`).
+    pub confine: bool,
+    /// Watched files above which a watcher is validated in chunks; 0 never
+    /// chunks (`chunk_files`).
+    pub chunk_files: usize,
+    /// `[commands]` for `strategy=command-output` watchers.
+    pub commands: &'a Commands,
+    /// Export AI prompts, or import their verdicts, instead of calling the
+    /// backend.
+    pub airgap: Option<&'a Airgap>,
+    /// `audit --full`: diff-only watchers are checked against the tree with
+    /// tools instead of being skipped for want of a diff.
+    pub audit: bool,
+    /// Verdicts of the interrupted run that `run --resume` continues; their
+    /// watchers are not validated again.
+    pub resumed: Option<&'a Resumed>,
+    /// When the run must stop (`run --deadline`): watchers not started by
+    /// then are inconclusive, and so are those still running, which are
+    /// killed.
+    pub deadline: Option<Instant>,
+    /// Validating an untrusted pull request (`run --pr`): checkers and
+    /// `command-output` watchers are not run, and agents only get
+    /// [`confine::READ_ONLY_TOOLS`].
+    pub untrusted: bool,
+}
+
+impl RunContext<'_> {
+    fn limits(&self, backend: &str) -> BackendLimits {
+        self.backends.get(backend).cloned().unwrap_or_default()
+    }
+
+    /// The markers `marker` references by `@name`.
+    pub fn related(&self, marker: &Marker) -> Vec<&Marker> {
+        marker::referenced(marker, self.markers)
+    }
+}
+
+enum Job {
+    Claude {
+        prompt: String,
+        tools: String,
+        scope: Option<Scope>,
+    },
+    Remote(remote::JobRequest),
+    Checker {
+        exe: PathBuf,
+        request: String,
+    },
+    Assert(String),
+    /// An AI verdict imported with `--import-verdicts`.
+    Imported(String),
+    /// A large scope validated in parts, each labelled with its directories.
+    Chunked(Vec<(String, Job)>),
+    Skip(&'static str),
+    Fail(String),
+}
+
+/// Why a watcher that would run a command is skipped under `run --pr`.
+const UNTRUSTED: &str = "untrusted pull request";
+
+/// `tools` (`Read,Bash(ls:*)`) without those not in
+/// [`confine::READ_ONLY_TOOLS`], for an untrusted pull request.
+fn read_only(tools: &str) -> String {
+    tools
+        .split(',')
+        .map(str::trim)
+        .filter(|tool| confine::READ_ONLY_TOOLS.contains(&confine::tool_name(tool)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn plan_job(marker: &Marker, ctx: &RunContext) -> Job {
+    if let Some(expr) = script::assert_for(marker) {
+        return Job::Assert(expr.to_string());
+    }
+    if let Some(checker) = plugins::checker_for(marker) {
+        if ctx.untrusted {
+            return Job::Skip(UNTRUSTED);
+        }
+        return match ctx.checkers.get(checker) {
+            Some(exe) => Job::Checker {
+                exe: exe.clone(),
+                request: plugins::build_request(marker, ctx.diff, ctx.root),
+            },
+            None => Job::Fail(format!(
+                "unknown checker `{checker}`: no {}{checker} on PATH and no `{checker}` entry \
+                 under [checkers] in {}",
+                plugins::PLUGIN_PREFIX,
+                config::CONFIG_FILE
+            )),
+        };
+    }
+    if ctx.offline {
+        return Job::Skip("offline");
+    }
+    let strategy = match prompt::strategy_for(marker) {
+        Ok(strategy) => strategy,
+        Err(reason) => return Job::Fail(reason),
+    };
+    let strategy = if strategy == Strategy::DiffOnly && ctx.audit {
+        Strategy::AgenticTools
+    } else {
+        strategy
+    };
+    if strategy == Strategy::DiffOnly && ctx.diff.is_none() {
+        return Job::Skip("diff-only outside --diff");
+    }
+    let tools = marker
+        .options
+        .get("tools")
+        .cloned()
+        .unwrap_or_else(|| "Read,Grep,Glob".to_string());
+    let tools = if ctx.untrusted {
+        read_only(&tools)
+    } else {
+        tools
+    };
+    let docs = match ctx.context.load(marker) {
+        Ok(docs) => docs,
+        Err(reason) => return Job::Fail(reason),
+    };
+    let related = ctx.related(marker);
+    // Only strategies that look at the watched files are chunked.
+    let chunked = matches!(strategy, Strategy::AgenticTools | Strategy::FilesInline);
+    let watched = if chunked && ctx.chunk_files > 0 && !marker.files.is_empty() {
+        marker.watched_files(ctx.root)
+    } else {
+        Vec::new()
+    };
+    if let Some(chunks) = chunk::chunks_for(marker, &watched, ctx.chunk_files) {
+        let n = chunks.len();
+        let jobs = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let part = chunk::narrow(marker, c);
+                let section = prompt::build_chunk_section(i + 1, n, &c.files);
+                let job = plan_ai_job(&part, ctx, strategy, &tools, &docs, &related, &section);
+                (c.label.clone(), job)
+            })
+            .collect();
+        return Job::Chunked(jobs);
+    }
+    plan_ai_job(marker, ctx, strategy, &tools, &docs, &related, "")
+}
+
+/// The claude or remote job validating `marker`. `chunk_section` is set for
+/// one chunk of a large scope, which only sees the diff of its own files.
+fn plan_ai_job(
+    marker: &Marker,
+    ctx: &RunContext,
+    strategy: Strategy,
+    tools: &str,
+    docs: &[ContextDoc],
+    related: &[&Marker],
+    chunk_section: &str,
+) -> Job {
+    let inlined;
+    let output;
+    let dependencies;
+    let evidence = match strategy {
+        Strategy::AgenticTools => Evidence::Tools,
+        Strategy::DiffOnly => Evidence::DiffOnly,
+        Strategy::FilesInline => {
+            inlined = ctx.context.inline_files(marker);
+            Evidence::Files(&inlined)
+        }
+        Strategy::CommandOutput => {
+            if ctx.untrusted {
+                return Job::Skip(UNTRUSTED);
+            }
+            let Some(name) = marker.options.get("command") else {
+                return Job::Fail(
+                    "`strategy=command-output` needs a `command` option naming a [commands] \
+                     entry"
+                        .to_string(),
+                );
+            };
+            output = match ctx.commands.output(name, ctx.root) {
+                Ok(text) => ctx.context.redact(&text, name),
+                Err(reason) => return Job::Fail(reason),
+            };
+            Evidence::Command {
+                command: ctx.commands.get(name).unwrap_or(name),
+                output: &output,
+            }
+        }
+        Strategy::Dependencies => {
+            dependencies = deps::evidence(marker, ctx.diff, ctx.root);
+            Evidence::Dependencies {
+                list: &dependencies,
+                changes: ctx.diff.is_some(),
+            }
+        }
+    };
+    // Tool-less strategies get everything in the prompt.
+    let tools = if strategy == Strategy::AgenticTools {
+        tools
+    } else {
+        ""
+    };
+    let scope = if ctx.confine && strategy == Strategy::AgenticTools {
+        confine::scope_for(marker, ctx.root)
+    } else {
+        None
+    };
+    // Nothing would keep its agent from reading the whole checkout.
+    if ctx.untrusted && strategy == Strategy::AgenticTools && scope.is_none() {
+        return Job::Fail(format!(
+            "an unscoped watcher cannot be confined on an {UNTRUSTED}; list the files it watches"
+        ));
+    }
+    let mut scope_section = scope
+        .as_ref()
+        .map(|s| prompt::build_scope_section(s.entries()))
+        .unwrap_or_default();
+    // The dependency list stands in for the diff.
+    let diff = ctx.diff.filter(|_| strategy != Strategy::Dependencies);
+    // Workers only need the part of the diff the marker watches. Their
+    // checkout lives elsewhere, so only the prompt states the scope.
+    let sliced = diff.map(|d| lockfiles::condense(&remote::slice_diff(d, marker), marker));
+    if let Some(diff) = &sliced {
+        scope_section.insert_str(
+            0,
+            &prompt::build_symbols_section(&symbols::changed_symbols(diff, ctx.root)),
+        );
+    }
+    scope_section.insert_str(0, chunk_section);
+    if let Some(pool) = ctx.pool {
+        let prompt =
+            prompt::build_watcher_prompt(marker, &evidence, sliced.as_deref(), docs, related)
+                + scope_section.as_str();
+        // Workers pass it on as is.
+        let prompt = privacy::scrub(&prompt);
+        if let Err(reason) = prompt::check_size(&prompt, ctx.model) {
+            return Job::Fail(reason);
+        }
+        return Job::Remote(remote::JobRequest {
+            version: remote::JOB_VERSION,
+            name: marker.name.clone(),
+            location: format!("{}:{}", marker.rel_path, marker.line),
+            model: ctx.model.to_string(),
+            tools: tools.to_string(),
+            prompt,
+            commit: pool.commit().map(str::to_string),
+        });
+    }
+    let whole;
+    let diff = if chunk_section.is_empty() {
+        whole = diff.map(|d| lockfiles::condense(d, marker));
+        whole.as_deref()
+    } else {
+        sliced.as_deref()
+    };
+    let prompt = prompt::build_watcher_prompt(marker, &evidence, diff, docs, related)
+        + scope_section.as_str();
+    if let Err(reason) = prompt::check_size(&prompt, ctx.model) {
+        return Job::Fail(reason);
+    }
+    let tools = match &scope {
+        Some(scope) => match scope.allowed_tools(tools) {
+            Ok(tools) => tools,
+            Err(reason) => return Job::Fail(reason),
+        },
+        None => tools.to_string(),
+    };
+    Job::Claude {
+        prompt,
+        tools,
+        scope,
+    }
+}
+
+/// `job` sent through `airgap`: its claude and remote jobs are exported
+/// instead of run, or answered with the verdicts imported for them.
+fn airgap_job(marker: &Marker, job: Job, airgap: &Airgap, ctx: &RunContext) -> Job {
+    let location = format!("{}:{}", marker.rel_path, marker.line);
+    let through = |job: Job, chunk: Option<&str>| {
+        let (prompt, tools, commit) = match job {
+            // The file leaves the machine like a prompt would.
+            Job::Claude { prompt, tools, .. } => (privacy::scrub(&prompt), tools, None),
+            Job::Remote(request) => (request.prompt, request.tools, request.commit),
+            job => return job,
+        };
+        let id = airgap::job_id(&marker.name, &location, chunk);
+        let mut request = remote::JobRequest {
+            version: remote::JOB_VERSION,
+            name: marker.name.clone(),
+            location: location.clone(),
+            model: ctx.model.to_string(),
+            tools,
+            prompt,
+            commit,
+        };
+        match airgap {
+            Airgap::Export { dir, commit } => {
+                request.commit = request.commit.or_else(|| commit.clone());
+                match airgap::export(dir, &id, &request) {
+                    Ok(()) => Job::Skip(airgap::EXPORTED),
+                    Err(reason) => Job::Fail(reason),
+                }
+            }
+            Airgap::Import(dir) => match airgap::import(dir, &id, &request) {
+                Ok(text) => Job::Imported(text),
+                Err(reason) => Job::Fail(reason),
+            },
+        }
+    };
+    match job {
+        Job::Chunked(chunks) => {
+            let chunks: Vec<(String, Job)> = chunks
+                .into_iter()
+                .map(|(label, job)| {
+                    let job = through(job, Some(&label));
+                    (label, job)
+                })
+                .collect();
+            match airgap {
+                // The watcher is exported once every chunk is.
+                Airgap::Export { .. } => chunks
+                    .into_iter()
+                    .map(|(_, job)| job)
+                    .find(|job| matches!(job, Job::Fail(_)))
+                    .unwrap_or(Job::Skip(airgap::EXPORTED)),
+                Airgap::Import(_) => Job::Chunked(chunks),
+            }
+        }
+        job => through(job, None),
+    }
+}
+
+/// Why a watcher is not run once its backend's token budget is spent.
+const BUDGET_SPENT: &str = "daily token budget spent";
+
+/// Why a watcher is not run, or its run was cut short, once the run's
+/// `--deadline` has passed.
+pub const DEADLINE_REACHED: &str = "deadline reached";
+
+/// How often calls still running past the deadline are killed.
+const KILL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The result of a backend call: its verdict, how long it took and how often
+/// it was retried.
+fn to_result(
+    name: &str,
+    location: &str,
+    (verdict, elapsed, retries): (Result<Reply, String>, Duration, u32),
+) -> WatcherResult {
+    let mut result = match verdict {
+        Ok(reply) => WatcherResult {
+            telemetry: reply.telemetry.clone(),
+            ..reply_result(name, location, reply)
+        },
+        Err(reason) => WatcherResult::errored(name, location, reason),
+    };
+    result.duration_ms = Some(elapsed.as_millis() as u64);
+    result.retries = retries;
+    result
+}
+
+/// One result for a watcher validated in chunks: valid when every chunk is.
+/// The reasons of failing chunks are kept, each under its directories, and
+/// an errored chunk errors the watcher.
+fn merge_chunks(name: &str, location: &str, parts: Vec<(String, WatcherResult)>) -> WatcherResult {
+    let mut reasons = Vec::new();
+    let mut errored = false;
+    let mut duration_ms = 0;
+    let mut retries = 0;
+    let mut telemetry: Option<Telemetry> = None;
+    for (label, part) in &parts {
+        if !part.is_valid {
+            let reason = part.reason.as_deref().unwrap_or("unknown reason");
+            reasons.push(format!("[{label}] {reason}"));
+            errored |= part.errored;
+        }
+        duration_ms += part.duration_ms.unwrap_or(0);
+        retries += part.retries;
+        if let Some(t) = &part.telemetry {
+            *telemetry.get_or_insert_with(Telemetry::default) += t;
+        }
+    }
+    let reason = (!reasons.is_empty()).then(|| reasons.join("\n"));
+    let mut result = match reason {
+        Some(reason) if errored => WatcherResult::errored(name, location, reason),
+        reason => WatcherResult::new(name, location, reason.is_none(), reason),
+    };
+    result.duration_ms = Some(duration_ms);
+    result.retries = retries;
+    result.telemetry = telemetry;
+    result
+}
+
+/// One backend's throttle and token budget for a batch of watchers.
+struct Backend<'a> {
+    name: &'static str,
+    throttle: Throttle,
+    budget: Option<u64>,
+    usage: &'a Usage,
 }
 
+impl<'a> Backend<'a> {
+    fn new(name: &'static str, ctx: &RunContext, usage: &'a Usage) -> Self {
+        let limits = ctx.limits(name);
+        Backend {
+            name,
+            throttle: Throttle::new(&limits, ctx.deadline),
+            budget: limits.daily_token_budget,
+            usage,
+        }
+    }
+
+    /// Send `prompt` with `call`, or `None` if it would overrun the daily
+    /// token budget. A session that answers counts with the input and output
+    /// tokens claude reports (the files an agent read included), or the
+    /// prompt and response estimated when there are none. Every failed
+    /// attempt, retried or not, counts its prompt.
+    fn call(
+        &self,
+        prompt: &str,
+        call: impl Fn() -> Result<Reply, String>,
+    ) -> Option<(Result<Reply, String>, Duration, u32)> {
+        let tokens = quota::estimate_tokens(prompt);
+        if !self.usage.reserve(self.name, self.budget, tokens) {
+            return None;
+        }
+        let (reply, elapsed, retries) = self.throttle.call(call);
+        let failed = tokens * u64::from(retries);
+        let spent = match &reply {
+            Ok(Reply {
+                telemetry: Some(t), ..
+            }) => failed + t.tokens(),
+            Ok(reply) => failed + tokens + quota::estimate_tokens(&reply.text),
+            Err(_) => failed + tokens,
+        };
+        self.usage.settle(self.name, tokens, spent);
+        Some((reply, elapsed, retries))
+    }
+}
+
+/// Run the watchers of `markers` on a fixed set of threads, each planning
+/// its watcher's job when it takes it up. AI calls go through a [`Throttle`]
+/// per backend, so as many run at once as the backend keeps up with and its
+/// `[backends]` limits allow.
 pub fn run_watchers(
     markers: &[Marker],
-    diff: Option<&str>,
-    model: &str,
+    ctx: &RunContext,
     total: usize,
     completed_offset: usize,
+    suppress: Suppressor,
 ) -> Vec<WatcherResult> {
+    let queue = Mutex::new(markers.iter().enumerate());
+    let usage = Usage::load(ctx.root, &clock::today().to_string());
+    let claude = Backend::new("claude", ctx, &usage);
+    let remote = Backend::new("remote", ctx, &usage);
+    let (model, root, pool, deadline) = (ctx.model, ctx.root, ctx.pool, ctx.deadline);
     let (tx, rx) = mpsc::channel();
 
-    for marker in markers {
-        let tx = tx.clone();
-        let name = marker.name.clone();
-        let location = format!("{}:{}", marker.rel_path, marker.line);
-        let prompt_text = prompt::build_watcher_prompt(marker, diff);
-        let model = model.to_string();
-        let tools = marker
-            .options
-            .get("tools")
-            .cloned()
-            .unwrap_or_else(|| "Read,Grep,Glob".to_string());
-
-        thread::spawn(move || {
-            let result = run_single_watcher(&name, &location, &prompt_text, &model, &tools);
-            tx.send(result).ok();
-        });
-    }
-    drop(tx);
-
     let mut results: Vec<WatcherResult> = Vec::new();
     let mut completed = completed_offset;
 
-    for result in rx {
-        completed += 1;
-        let status = if result.is_valid {
-            "\x1b[32mOK\x1b[0m"
-        } else {
-            "\x1b[31mFAILED\x1b[0m"
-        };
-        eprintln!("[{completed}/{total}] {}... {status}", result.name);
-        results.push(result);
-    }
+    thread::scope(|s| {
+        for _ in 0..markers.len().min(throttle::MAX_LIMIT) {
+            let tx = tx.clone();
+            let (queue, claude, remote) = (&queue, &claude, &remote);
+            s.spawn(move || {
+                while let Some((i, marker)) = queue.lock().unwrap().next() {
+                    let name = &marker.name;
+                    let location = format!("{}:{}", marker.rel_path, marker.line);
+                    let started = Instant::now();
+                    if let Some(result) = ctx.resumed.and_then(|r| r.get(name, &location)) {
+                        tx.send(result).ok();
+                        continue;
+                    }
+                    // Planning can take a while (context URLs, `[commands]`).
+                    if deadline.is_some_and(|d| started >= d) {
+                        let result = WatcherResult::inconclusive(name, &location, DEADLINE_REACHED);
+                        tx.send(result).ok();
+                        continue;
+                    }
+                    let job = plan_job(marker, ctx);
+                    let job = match ctx.airgap {
+                        Some(airgap) => airgap_job(marker, job, airgap, ctx),
+                        None => job,
+                    };
+                    // Runs a claude, remote, checker or failed job.
+                    let call = |job: Job| match job {
+                        Job::Claude {
+                            prompt,
+                            tools,
+                            scope,
+                        } => claude
+                            .call(&prompt, || ask_claude(name, &prompt, model, &tools))
+                            .map(|(reply, elapsed, retries)| {
+                                let reply = match &scope {
+                                    Some(scope) => reply.and_then(|r| scope.check(r)),
+                                    None => reply,
+                                };
+                                (reply, elapsed, retries)
+                            }),
+                        Job::Remote(request) => remote.call(&request.prompt, || {
+                            pool.unwrap().dispatch(&request, i).map(Reply::plain)
+                        }),
+                        Job::Checker { exe, request } => Some((
+                            plugins::run_checker(&exe, &request, root).map(Reply::plain),
+                            started.elapsed(),
+                            0,
+                        )),
+                        Job::Imported(text) => Some((Ok(Reply::plain(text)), started.elapsed(), 0)),
+                        Job::Fail(reason) => Some((Err(reason), started.elapsed(), 0)),
+                        Job::Assert(_) | Job::Chunked(_) | Job::Skip(_) => {
+                            unreachable!("handled by the worker")
+                        }
+                    };
+                    let result = match job {
+                        Job::Assert(expr) => {
+                            let mut result = match script::eval_assert(&expr, marker, root) {
+                                Ok(holds) => {
+                                    let verdict = if holds { "holds" } else { "does not hold" };
+                                    let reason = format!("`{expr}` {verdict}");
+                                    WatcherResult::new(name, &location, holds, Some(reason))
+                                }
+                                Err(reason) => WatcherResult::errored(name, &location, reason),
+                            };
+                            result.duration_ms = Some(started.elapsed().as_millis() as u64);
+                            result
+                        }
+                        Job::Skip(why) => WatcherResult::skipped(name, &location, why),
+                        Job::Chunked(chunks) => {
+                            let mut parts = Vec::new();
+                            for (label, job) in chunks {
+                                let Some(called) = call(job) else {
+                                    parts.clear();
+                                    break;
+                                };
+                                parts.push((label, to_result(name, &location, called)));
+                            }
+                            if parts.is_empty() {
+                                WatcherResult::inconclusive(name, &location, BUDGET_SPENT)
+                            } else {
+                                merge_chunks(name, &location, parts)
+                            }
+                        }
+                        job => match call(job) {
+                            Some(called) => to_result(name, &location, called),
+                            None => WatcherResult::inconclusive(name, &location, BUDGET_SPENT),
+                        },
+                    };
+                    // Calls still running at the deadline are killed.
+                    let result = if result.errored && deadline.is_some_and(|d| Instant::now() >= d)
+                    {
+                        WatcherResult::inconclusive(name, &location, DEADLINE_REACHED)
+                    } else {
+                        result
+                    };
+                    tx.send(result).ok();
+                }
+            });
+        }
+        drop(tx);
 
+        loop {
+            // Past the deadline, whatever is still running is killed, again
+            // every `KILL_INTERVAL` for calls that started just before it.
+            let received = match deadline {
+                Some(d) => rx.recv_timeout(
+                    d.checked_duration_since(Instant::now())
+                        .unwrap_or(KILL_INTERVAL),
+                ),
+                None => rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+            };
+            let mut result = match received {
+                Ok(result) => result,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    children::kill_all();
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            completed += 1;
+            if !result.is_valid && result.skipped.is_none() {
+                result.suppression = suppress(&result);
+            }
+            shutdown::record(&result);
+            resume::record(&result);
+            let telemetry = match &result.telemetry {
+                Some(t) => format!(" \x1b[90m({})\x1b[0m", t.summary()),
+                None => String::new(),
+            };
+            eprintln!(
+                "[{completed}/{total}] {}... {}{telemetry}",
+                result.name,
+                result.status()
+            );
+            results.push(result);
+        }
+    });
+
+    if let Err(e) = usage.save() {
+        eprintln!("\x1b[33m[WARNING] {e}\x1b[0m");
+    }
     results
 }
 
-pub fn print_results(results: &[WatcherResult]) {
-    let failures: Vec<_> = results.iter().filter(|r| !r.is_valid).collect();
+/// Print failures, suppressed failures and the summary line. Returns whether
+/// the run passed.
+pub fn print_results(
+    results: &[WatcherResult],
+    markers: &[Marker],
+    diff: Option<&str>,
+    slowest: Option<usize>,
+) -> bool {
+    let failures: Vec<_> = results.iter().filter(|r| r.is_failure()).collect();
     if !failures.is_empty() {
         println!();
         println!("\x1b[31m==== FAILURES ====");
-        for f in &failures {
+        // In diff mode, failures caused by the same hunks are shown together.
+        let clusters = match diff {
+            Some(diff) => cluster::cluster(&failures, markers, diff),
+            None => failures
+                .iter()
+                .map(|f| cluster::Cluster {
+                    failures: vec![f],
+                    shared: String::new(),
+                })
+                .collect(),
+        };
+        for c in &clusters {
             println!();
-            let cached_tag = if f.cached {
-                " \x1b[90m(cached)\x1b[31m"
-            } else {
-                ""
-            };
-            println!("---- {} ({}){} ----", f.name, f.location, cached_tag);
+            if let [f] = c.failures[..] {
+                println!("---- {} ({}){} ----", f.name, f.location, failure_tag(f));
+                println!();
+                print_failure(f, markers);
+                continue;
+            }
+            let names: Vec<&str> = c.failures.iter().map(|f| f.name.as_str()).collect();
+            println!(
+                "---- {} failures from one change: {} ----",
+                c.failures.len(),
+                names.join(", ")
+            );
             println!();
-            println!("{}\n", f.reason.as_deref().unwrap_or("unknown reason"));
+            println!("\x1b[90m{}\x1b[31m", c.shared.trim_end());
+            for f in &c.failures {
+                println!();
+                println!("{} ({}){}", f.name, f.location, failure_tag(f));
+                print_failure(f, markers);
+            }
+        }
+        print!("\x1b[0m");
+    }
+
+    let suppressed: Vec<_> = results.iter().filter(|r| r.suppression.is_some()).collect();
+    let mut labels: Vec<&str> = suppressed
+        .iter()
+        .filter_map(|r| r.suppression.as_ref().map(|s| s.label))
+        .collect();
+    labels.sort();
+    labels.dedup();
+    for label in &labels {
+        println!();
+        println!("\x1b[33m==== {label} ====");
+        for r in suppressed
+            .iter()
+            .filter(|r| r.suppression.as_ref().is_some_and(|s| s.label == *label))
+        {
+            let note = &r.suppression.as_ref().unwrap().note;
+            println!();
+            println!("---- {} ({}) ----", r.name, r.location);
+            println!();
+            println!("{}", r.reason.as_deref().unwrap_or("unknown reason"));
+            println!("\x1b[90m{note}\x1b[33m\n");
         }
         print!("\x1b[0m");
     }
@@ -81,187 +838,434 @@ pub fn print_results(results: &[WatcherResult]) {
     let passed = results.iter().filter(|r| r.is_valid).count();
     let failed = failures.len();
     let cached = results.iter().filter(|r| r.cached).count();
-    let cached_suffix = if cached > 0 {
-        format!(" ({cached} cached)")
-    } else {
-        String::new()
-    };
+    let inconclusive = results.iter().filter(|r| r.inconclusive).count();
+    let not_run = results.iter().filter(|r| r.skipped.is_some()).count() - inconclusive;
+    let modified = results.iter().filter(|r| r.modified).count();
+    let mut suffix = String::new();
+    for label in &labels {
+        let count = suppressed
+            .iter()
+            .filter(|r| r.suppression.as_ref().is_some_and(|s| s.label == *label))
+            .count();
+        suffix.push_str(&format!("; {count} {}", label.to_lowercase()));
+    }
+    if inconclusive > 0 {
+        suffix.push_str(&format!("; {inconclusive} inconclusive"));
+    }
+    if not_run > 0 {
+        suffix.push_str(&format!("; {not_run} not run"));
+    }
+    if modified > 0 {
+        suffix.push_str(&format!("; {modified} re-validated (marker modified)"));
+    }
+    if cached > 0 {
+        suffix.push_str(&format!(" ({cached} cached)"));
+    }
+    if let Some(usage) = render_usage(results, slowest) {
+        println!();
+        match slowest {
+            Some(n) => println!("\x1b[36m==== SLOWEST {n} ====\x1b[0m"),
+            None => println!("\x1b[36m==== RESOURCE USAGE ====\x1b[0m"),
+        }
+        println!();
+        print!("{usage}");
+    }
+
     println!();
     if failed == 0 {
-        println!(
-            "watcher-knight result: \x1b[32mOK\x1b[0m. {passed} passed; 0 failed{cached_suffix}"
-        );
+        println!("watcher-knight result: \x1b[32mOK\x1b[0m. {passed} passed; 0 failed{suffix}");
     } else {
         println!(
-            "watcher-knight result: \x1b[31mFAILED\x1b[0m. {passed} passed; {failed} failed{cached_suffix}"
+            "watcher-knight result: \x1b[31mFAILED\x1b[0m. {passed} passed; {failed} failed{suffix}"
         );
-        process::exit(1);
     }
+    failed == 0
 }
 
-fn run_single_watcher(
-    name: &str,
-    location: &str,
+/// ` (cached)` or ` (marker modified)` after a failure's heading.
+fn failure_tag(f: &WatcherResult) -> &'static str {
+    if f.cached {
+        " \x1b[90m(cached)\x1b[31m"
+    } else if f.modified {
+        " \x1b[90m(marker modified)\x1b[31m"
+    } else {
+        ""
+    }
+}
+
+/// A failure's reason, then why its invariant exists.
+fn print_failure(f: &WatcherResult, markers: &[Marker]) {
+    println!("{}\n", f.reason.as_deref().unwrap_or("unknown reason"));
+    if let Some(meta) = report::marker_for(f, markers).map(|m| &m.metadata) {
+        if let Some(rationale) = &meta.rationale {
+            println!("\x1b[90mWhy: {rationale}\x1b[31m");
+        }
+        if let Some(link) = &meta.link {
+            println!("\x1b[90mSee: {link}\x1b[31m");
+        }
+        if meta.rationale.is_some() || meta.link.is_some() {
+            println!();
+        }
+    }
+}
+
+/// Duration, retries and tokens of the fresh validations, slowest first (only
+/// the `slowest` first, if given), and a line of totals over all of them.
+/// `None` when nothing was validated fresh.
+fn render_usage(results: &[WatcherResult], slowest: Option<usize>) -> Option<String> {
+    let mut fresh: Vec<&WatcherResult> =
+        results.iter().filter(|r| r.duration_ms.is_some()).collect();
+    if fresh.is_empty() {
+        return None;
+    }
+    fresh.sort_by(|a, b| {
+        b.duration_ms
+            .cmp(&a.duration_ms)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    let seconds = |ms: u64| format!("{:.1}s", ms as f64 / 1000.0);
+    let cells: Vec<[String; 5]> = fresh
+        .iter()
+        .take(slowest.unwrap_or(fresh.len()))
+        .map(|r| {
+            [
+                r.name.clone(),
+                r.location.clone(),
+                seconds(r.duration_ms.unwrap_or(0)),
+                r.retries.to_string(),
+                r.telemetry
+                    .as_ref()
+                    .map_or("-".to_string(), |t| stream::format_tokens(t.tokens())),
+            ]
+        })
+        .collect();
+    let mut out = if cells.is_empty() {
+        String::new()
+    } else {
+        history::render_columns(
+            ["WATCHER", "LOCATION", "DURATION", "RETRIES", "TOKENS"],
+            &cells,
+        )
+    };
+    let total_ms: u64 = fresh.iter().filter_map(|r| r.duration_ms).sum();
+    let retries: u32 = fresh.iter().map(|r| r.retries).sum();
+    let tokens: u64 = fresh
+        .iter()
+        .filter_map(|r| r.telemetry.as_ref())
+        .map(Telemetry::tokens)
+        .sum();
+    out.push_str(&format!(
+        "total: {} validated in {} of watcher time; {retries} retries; {} tokens\n",
+        fresh.len(),
+        seconds(total_ms),
+        stream::format_tokens(tokens)
+    ));
+    Some(out)
+}
+
+/// Validate a watcher prompt with the local claude CLI and return the
+/// verdict text.
+#[cfg(not(feature = "agent-sdk"))]
+fn ask_claude(name: &str, prompt: &str, model: &str, tools: &str) -> Result<Reply, String> {
+    invoke(&format!("watcher {name}"), prompt, model, tools)
+}
+
+/// Through the agent interface the verdict arrives typed, and is handed on
+/// as is.
+#[cfg(feature = "agent-sdk")]
+fn ask_claude(name: &str, prompt: &str, model: &str, tools: &str) -> Result<Reply, String> {
+    let what = format!("watcher {name}");
+    let (verdict, telemetry) =
+        agent::validate(&what, prompt, model, &agent::ToolConfig::parse(tools))?;
+    Ok(Reply {
+        text: verdict.reason.clone().unwrap_or_default(),
+        telemetry,
+        verdict: Some(verdict),
+    })
+}
+
+/// Spawn `claude -p` with `--output-format stream-json`, feed it `prompt` on
+/// stdin and return its final answer with the session's telemetry. A claude
+/// that prints plain text instead of events is answered with that text.
+///
+/// `what` names the caller in error messages (e.g. `watcher my-check`).
+/// A claude that cannot be run, exits before reading the prompt, exits
+/// non-zero or ends with an error result is an `Err` with a human-readable
+/// reason, ending with the last line claude printed.
+pub fn invoke(what: &str, prompt: &str, model: &str, tools: &str) -> Result<Reply, String> {
+    converse(what, prompt, model, tools, None).map(|(reply, _)| reply)
+}
+
+/// [`invoke`], resuming session `resume` if given. Also returns the id of
+/// the session, for the next prompt of the conversation.
+pub fn converse(
+    what: &str,
     prompt: &str,
     model: &str,
     tools: &str,
-) -> WatcherResult {
-    let mut child = process::Command::new("claude")
-        .args([
-            "-p",
-            "--model",
-            model,
-            "--permission-mode",
-            "dontAsk",
-            "--allowedTools",
-            tools,
-        ])
-        .env_remove("CLAUDECODE")
-        .stdin(process::Stdio::piped())
-        .stdout(process::Stdio::piped())
-        .stderr(process::Stdio::null())
-        .spawn()
-        .unwrap_or_else(|e| {
-            eprintln!("Error: failed to launch claude for watcher {name}: {e}");
-            process::exit(1);
-        });
+    resume: Option<&str>,
+) -> Result<(Reply, Option<String>), String> {
+    let prompt = privacy::scrub(prompt);
+    let mut cmd = process::Command::new(platform::program("claude"));
+    cmd.args([
+        "-p",
+        "--output-format",
+        "stream-json",
+        "--verbose",
+        "--model",
+        model,
+        "--permission-mode",
+        "dontAsk",
+        "--allowedTools",
+        tools,
+    ]);
+    if let Some(id) = resume {
+        cmd.args(["--resume", id]);
+    }
+    let (mut child, _running) = children::spawn(
+        cmd.env_remove("CLAUDECODE")
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped()),
+    )
+    .map_err(|e| format!("failed to launch claude for {what}: {e}"))?;
 
-    child
-        .stdin
-        .take()
-        .unwrap()
-        .write_all(prompt.as_bytes())
-        .unwrap_or_else(|e| {
-            eprintln!("Error: failed to write prompt for watcher {name}: {e}");
-            process::exit(1);
-        });
+    // A claude that exits before reading its whole prompt closes the pipe;
+    // its stderr says why.
+    let written = child.stdin.take().unwrap().write_all(prompt.as_bytes());
+    if let Err(e) = &written
+        && e.kind() != io::ErrorKind::BrokenPipe
+    {
+        child.kill().ok();
+        child.wait().ok();
+        return Err(format!("failed to write prompt for {what}: {e}"));
+    }
 
-    let output = child.wait_with_output().unwrap_or_else(|e| {
-        eprintln!("Error: failed to wait on claude for watcher {name}: {e}");
-        process::exit(1);
-    });
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to wait on claude for {what}: {e}"))?;
+    if written.is_err() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "claude exited ({}) before reading the prompt for {what}: {}",
+            output.status,
+            stderr.trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut transcript = Transcript::default();
+    for line in stdout.lines() {
+        transcript.push(line);
+    }
+    // The last line of output usually says why it failed (e.g. a rate limit).
+    let detail = [&output.stderr, &output.stdout]
+        .into_iter()
+        .find_map(|out| {
+            String::from_utf8_lossy(out)
+                .lines()
+                .rev()
+                .find(|l| !l.trim().is_empty())
+                .map(|l| l.trim().to_string())
+        });
+    let session_id = transcript.session_id.clone();
+    let reply = transcript.finish(
+        output.status.success(),
+        &output.status.to_string(),
+        detail.as_deref(),
+    )?;
+    Ok((reply, session_id))
+}
 
-    let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+/// The result a reply's verdict gives.
+#[cfg(not(feature = "agent-sdk"))]
+fn reply_result(name: &str, location: &str, reply: Reply) -> WatcherResult {
+    parse_response(name, location, &reply.text)
+}
 
-    if !output.status.success() {
-        return WatcherResult {
-            name: name.to_string(),
-            location: location.to_string(),
-            is_valid: false,
-            reason: Some(format!("process exited with {}", output.status)),
-            cached: false,
-        };
+/// The result a reply's verdict gives: the typed verdict of the agent
+/// interface, or else the one in its text (remote workers, checkers).
+#[cfg(feature = "agent-sdk")]
+fn reply_result(name: &str, location: &str, reply: Reply) -> WatcherResult {
+    match reply.verdict {
+        Some(verdict) => verdict_result(name, location, verdict.is_valid, verdict.reason),
+        None => parse_response(name, location, &reply.text),
     }
+}
 
-    parse_response(name, location, &text)
+/// A verdict as a result: a failure always has a reason, a pass none.
+fn verdict_result(
+    name: &str,
+    location: &str,
+    is_valid: bool,
+    reason: Option<String>,
+) -> WatcherResult {
+    let reason = if is_valid {
+        None
+    } else {
+        reason.or_else(|| Some("marked invalid with no reason".to_string()))
+    };
+    WatcherResult::new(name, location, is_valid, reason)
 }
 
 fn parse_response(name: &str, location: &str, text: &str) -> WatcherResult {
-    // Try to find a JSON object in the response
-    let json_str = extract_json(text).unwrap_or(text);
-
-    match serde_json::from_str::<serde_json::Value>(json_str) {
+    match serde_json::from_str::<serde_json::Value>(json_reply(text)) {
         Ok(val) => {
             let is_valid = val
                 .get("is_valid")
                 .and_then(|v| v.as_bool())
                 .unwrap_or(false);
-            let reason = if !is_valid {
-                val.get("reason")
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.to_string())
-                    .or_else(|| Some("marked invalid with no reason".to_string()))
-            } else {
-                None
-            };
-            WatcherResult {
-                name: name.to_string(),
-                location: location.to_string(),
-                is_valid,
-                reason,
-                cached: false,
-            }
+            let reason = val
+                .get("reason")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            verdict_result(name, location, is_valid, reason)
         }
-        Err(_) => WatcherResult {
-            name: name.to_string(),
-            location: location.to_string(),
-            is_valid: false,
-            reason: Some(text.to_string()),
-            cached: false,
-        },
+        Err(_) => WatcherResult::errored(name, location, text.to_string()),
     }
 }
 
-/// Find the first `{ ... }` substring that looks like JSON.
-fn extract_json(text: &str) -> Option<&str> {
-    let start = text.find('{')?;
-    let mut depth = 0;
-    for (i, ch) in text[start..].char_indices() {
-        match ch {
-            '{' => depth += 1,
-            '}' => {
-                depth -= 1;
-                if depth == 0 {
-                    return Some(&text[start..start + i + 1]);
-                }
-            }
-            _ => {}
-        }
-    }
-    None
+/// A candidate marker proposed by `watcher-knight suggest`.
+#[derive(Debug, Deserialize)]
+pub struct Suggestion {
+    pub name: String,
+    /// File (relative to the scan root) the marker should be placed in.
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Files to watch, relative to the target file's directory.
+    #[serde(default)]
+    pub files: Vec<String>,
+    pub instruction: String,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[derive(Deserialize)]
+struct SuggestResponse {
+    suggestions: Vec<Suggestion>,
+}
 
-    // ── extract_json ──────────────────────────────────────────────────────
+/// Parse the `{"suggestions": [...]}` object out of a suggest response.
+pub fn parse_suggestions(text: &str) -> Result<Vec<Suggestion>, String> {
+    let json_str = json_reply(text);
+    serde_json::from_str::<SuggestResponse>(json_str)
+        .map(|r| r.suggestions)
+        .map_err(|e| format!("could not parse suggestions ({e}): {text}"))
+}
 
-    #[test]
-    fn extract_json_simple_object() {
-        let input = r#"{"is_valid": true}"#;
-        assert_eq!(extract_json(input), Some(input));
-    }
+/// The critique of one marker by `watcher-knight review-markers`.
+#[derive(Debug, Deserialize)]
+pub struct MarkerReview {
+    pub name: String,
+    #[serde(default)]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub ambiguity: Option<String>,
+    #[serde(default)]
+    pub testability: Option<String>,
+    /// Names of other markers that check the same thing.
+    #[serde(default)]
+    pub overlaps: Vec<String>,
+    /// Suggested replacement for the instruction.
+    pub rewrite: String,
+}
 
-    #[test]
-    fn extract_json_with_surrounding_text() {
-        let input = r#"Here is the result: {"is_valid": false, "reason": "x"} done"#;
-        assert_eq!(
-            extract_json(input),
-            Some(r#"{"is_valid": false, "reason": "x"}"#)
-        );
-    }
+#[derive(Deserialize)]
+struct ReviewResponse {
+    reviews: Vec<MarkerReview>,
+}
 
-    #[test]
-    fn extract_json_nested_braces() {
-        let input = r#"{"a": {"b": 1}}"#;
-        assert_eq!(extract_json(input), Some(input));
-    }
+/// Parse the `{"reviews": [...]}` object out of a review response.
+pub fn parse_reviews(text: &str) -> Result<Vec<MarkerReview>, String> {
+    let json_str = json_reply(text);
+    serde_json::from_str::<ReviewResponse>(json_str)
+        .map(|r| r.reviews)
+        .map_err(|e| format!("could not parse reviews ({e}): {text}"))
+}
 
-    #[test]
-    fn extract_json_no_braces() {
-        assert_eq!(extract_json("no json here"), None);
-    }
+/// The `classify` answer for one marker: an equivalent `assert`, if any.
+#[derive(Debug, Deserialize)]
+pub struct AssertSuggestion {
+    pub name: String,
+    #[serde(default)]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub assert: Option<String>,
+}
 
-    #[test]
-    fn extract_json_unclosed_brace() {
-        assert_eq!(extract_json("{ unclosed"), None);
+#[derive(Deserialize)]
+struct ClassifyResponse {
+    classifications: Vec<AssertSuggestion>,
+}
+
+/// Parse the `{"classifications": [...]}` object out of a classify response.
+pub fn parse_classifications(text: &str) -> Result<Vec<AssertSuggestion>, String> {
+    let json_str = json_reply(text);
+    serde_json::from_str::<ClassifyResponse>(json_str)
+        .map(|r| r.classifications)
+        .map_err(|e| format!("could not parse classifications ({e}): {text}"))
+}
+
+#[derive(Deserialize)]
+struct MarkerChangeResponse {
+    weakens: bool,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Parse the answer to a marker change prompt: what the edit gives up, or
+/// `None` if it does not weaken the watcher.
+pub fn parse_marker_change(text: &str) -> Result<Option<String>, String> {
+    let json_str = json_reply(text);
+    let response = serde_json::from_str::<MarkerChangeResponse>(json_str)
+        .map_err(|e| format!("could not parse the marker change review ({e}): {text}"))?;
+    Ok(response.weakens.then(|| {
+        response
+            .reason
+            .unwrap_or_else(|| "weakened with no reason given".to_string())
+    }))
+}
+
+/// The JSON document a reply is: the whole text, trimmed, or the body of
+/// the one fenced code block it consists of. Prompts ask for nothing else;
+/// JSON picked out of prose is not taken.
+pub fn json_reply(text: &str) -> &str {
+    let text = text.trim();
+    let fenced = text
+        .strip_prefix("```")
+        .and_then(|t| t.strip_suffix("```"))
+        .and_then(|t| t.split_once('\n'))
+        .filter(|(lang, _)| matches!(lang.trim(), "" | "json"));
+    match fenced {
+        Some((_, body)) => body.trim(),
+        None => text,
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ── json_reply ────────────────────────────────────────────────────────
 
     #[test]
-    fn extract_json_empty_object() {
-        assert_eq!(extract_json("{}"), Some("{}"));
+    fn json_reply_trims_the_document() {
+        assert_eq!(
+            json_reply("  {\"is_valid\": true}\n"),
+            r#"{"is_valid": true}"#
+        );
+        assert_eq!(json_reply(""), "");
     }
 
     #[test]
-    fn extract_json_multiple_objects_returns_first() {
-        let input = r#"{"a":1} {"b":2}"#;
-        assert_eq!(extract_json(input), Some(r#"{"a":1}"#));
+    fn json_reply_unwraps_one_fenced_block() {
+        assert_eq!(json_reply("```json\n{\"a\": 1}\n```"), r#"{"a": 1}"#);
+        assert_eq!(json_reply("```\n{}\n```\n"), "{}");
+        assert_eq!(json_reply("```rust\n{}\n```"), "```rust\n{}\n```");
     }
 
     #[test]
-    fn extract_json_empty_input() {
-        assert_eq!(extract_json(""), None);
+    fn json_reply_does_not_pick_json_out_of_prose() {
+        let text = r#"Here is the result: {"is_valid": false} done"#;
+        assert_eq!(json_reply(text), text);
     }
 
     // ── parse_response ────────────────────────────────────────────────────
@@ -311,14 +1315,12 @@ mod tests {
     }
 
     #[test]
-    fn parse_response_json_embedded_in_prose() {
-        let r = parse_response(
-            "test",
-            "f:1",
-            r#"Here is my answer: {"is_valid": true} Hope that helps!"#,
-        );
-        assert!(r.is_valid);
-        assert!(r.reason.is_none());
+    fn parse_response_json_embedded_in_prose_is_not_a_verdict() {
+        let text = r#"Here is my answer: {"is_valid": true} Hope that helps!"#;
+        let r = parse_response("test", "f:1", text);
+        assert!(!r.is_valid);
+        assert!(r.errored);
+        assert_eq!(r.reason.as_deref(), Some(text));
     }
 
     #[test]
@@ -346,4 +1348,287 @@ mod tests {
         assert!(r.is_valid);
         assert!(r.reason.is_none());
     }
+
+    #[cfg(feature = "agent-sdk")]
+    #[test]
+    fn typed_verdicts_are_not_parsed_from_text() {
+        let reply = |is_valid| Reply {
+            text: "not a verdict".to_string(),
+            telemetry: None,
+            verdict: Some(agent::Verdict {
+                is_valid,
+                reason: None,
+            }),
+        };
+        let r = reply_result("test", "f:1", reply(false));
+        assert!(!r.is_valid);
+        assert!(!r.errored);
+        assert_eq!(r.reason.as_deref(), Some("marked invalid with no reason"));
+        assert!(reply_result("test", "f:1", reply(true)).is_valid);
+        let r = reply_result(
+            "test",
+            "f:1",
+            Reply::plain(r#"{"is_valid": true}"#.to_string()),
+        );
+        assert!(r.is_valid);
+    }
+
+    // ── read_only ─────────────────────────────────────────────────────────
+
+    #[test]
+    fn read_only_keeps_read_grep_and_glob() {
+        assert_eq!(
+            read_only("Read, Bash(ls:*),Grep,Write,Read(src/**),Glob,WebFetch"),
+            "Read,Grep,Read(src/**),Glob"
+        );
+        assert_eq!(read_only("Bash"), "");
+    }
+
+    // ── merge_chunks ──────────────────────────────────────────────────────
+
+    #[test]
+    fn merge_chunks_fails_with_the_failing_chunks() {
+        let part = |is_valid, reason: Option<&str>| {
+            let mut r = WatcherResult::new("big", "a:1", is_valid, reason.map(String::from));
+            r.duration_ms = Some(100);
+            r.retries = 1;
+            r
+        };
+        let merged = merge_chunks(
+            "big",
+            "a:1",
+            vec![
+                ("src/api/".to_string(), part(false, Some("no docs"))),
+                ("src/auth/".to_string(), part(true, None)),
+                ("src/db/ (part 1 of 2)".to_string(), part(false, None)),
+            ],
+        );
+        assert!(!merged.is_valid && !merged.errored);
+        assert_eq!(
+            merged.reason.as_deref(),
+            Some("[src/api/] no docs\n[src/db/ (part 1 of 2)] unknown reason")
+        );
+        assert_eq!(merged.duration_ms, Some(300));
+        assert_eq!(merged.retries, 3);
+
+        let passed = merge_chunks("big", "a:1", vec![("a/".to_string(), part(true, None))]);
+        assert!(passed.is_valid && passed.reason.is_none());
+
+        let errored = WatcherResult::errored("big", "a:1", "timeout".to_string());
+        let merged = merge_chunks(
+            "big",
+            "a:1",
+            vec![
+                ("a/".to_string(), part(true, None)),
+                ("b/".to_string(), errored),
+            ],
+        );
+        assert!(merged.errored);
+        assert_eq!(merged.reason.as_deref(), Some("[b/] timeout"));
+    }
+
+    // ── WatcherResult ─────────────────────────────────────────────────────
+
+    #[test]
+    fn suppressed_failure_is_not_a_failure() {
+        let mut r = parse_response("test", "f:1", r#"{"is_valid": false, "reason": "x"}"#);
+        assert!(r.is_failure());
+        assert!(r.status().contains("FAILED"));
+        r.suppression = Some(Suppression {
+            label: "WAIVED",
+            note: "until 2025-03-01".to_string(),
+        });
+        assert!(!r.is_failure());
+        assert!(r.status().contains("WAIVED"));
+    }
+
+    #[test]
+    fn backend_call_stays_within_token_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let usage = Usage::load(dir.path(), "2025-03-01");
+        let backend = Backend {
+            name: "claude",
+            throttle: Throttle::new(&BackendLimits::default(), None),
+            budget: Some(10),
+            usage: &usage,
+        };
+        let prompt = "x".repeat(24);
+        let (reply, _, _) = backend
+            .call(&prompt, || Ok(Reply::plain("{}".to_string())))
+            .unwrap();
+        assert_eq!(reply.unwrap().text, "{}");
+        // 8 + 1 tokens spent, so another 8 would overrun the budget of 10.
+        assert!(backend.call(&prompt, || panic!("over budget")).is_none());
+    }
+
+    #[test]
+    fn backend_call_charges_reported_tokens_and_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let usage = Usage::load(dir.path(), "2025-03-01");
+        let backend = Backend {
+            name: "claude",
+            throttle: Throttle::new(&BackendLimits::default(), None),
+            budget: Some(1_000),
+            usage: &usage,
+        };
+        let prompt = "x".repeat(24);
+        let reply = || {
+            Ok(Reply {
+                text: "{}".to_string(),
+                telemetry: Some(Telemetry {
+                    input_tokens: 900,
+                    output_tokens: 50,
+                    ..Telemetry::default()
+                }),
+                #[cfg(feature = "agent-sdk")]
+                verdict: None,
+            })
+        };
+        assert!(backend.call(&prompt, reply).is_some());
+        let (reply, _, _) = backend.call(&prompt, || Err("boom".to_string())).unwrap();
+        assert!(reply.is_err());
+        // 950 reported and 8 for the failed prompt: 42 left.
+        assert!(!usage.reserve("claude", Some(1_000), 43));
+        assert!(usage.reserve("claude", Some(1_000), 42));
+    }
+
+    #[test]
+    fn usage_lists_slowest_fresh_results_with_totals() {
+        let mut slow = WatcherResult::new("slow", "a.ts:1", true, None);
+        slow.duration_ms = Some(12_300);
+        slow.retries = 2;
+        slow.telemetry = Some(Telemetry {
+            input_tokens: 11_000,
+            output_tokens: 1_300,
+            ..Telemetry::default()
+        });
+        let mut fast = WatcherResult::new("fast", "b.ts:1", false, None);
+        fast.duration_ms = Some(800);
+        let mut cached = WatcherResult::new("cached", "c.ts:1", true, None);
+        cached.cached = true;
+        let results = [fast, cached, slow];
+
+        let usage = render_usage(&results, None).unwrap();
+        let lines: Vec<&str> = usage.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "WATCHER  LOCATION  DURATION  RETRIES  TOKENS",
+                "slow     a.ts:1    12.3s     2        12.3k",
+                "fast     b.ts:1    0.8s      0        -",
+                "total: 2 validated in 13.1s of watcher time; 2 retries; 12.3k tokens",
+            ]
+        );
+        let slowest = render_usage(&results, Some(1)).unwrap();
+        assert_eq!(slowest.lines().count(), 3);
+        assert!(!slowest.contains("fast "));
+        assert!(render_usage(&results[1..2], None).is_none());
+    }
+
+    #[test]
+    fn skipped_result_is_neither_pass_nor_failure() {
+        let r = WatcherResult::skipped("test", "f:1", "offline");
+        assert!(!r.is_valid);
+        assert!(!r.is_failure());
+        assert!(r.status().contains("NOT RUN (offline)"));
+    }
+
+    #[test]
+    fn inconclusive_result_is_skipped_with_its_reason() {
+        let r = WatcherResult::inconclusive("test", "f:1", DEADLINE_REACHED);
+        assert!(!r.is_failure());
+        assert_eq!(r.skipped.as_deref(), Some(DEADLINE_REACHED));
+        assert!(r.status().contains("INCONCLUSIVE (deadline reached)"));
+    }
+
+    #[test]
+    fn passing_result_status_ok() {
+        let r = parse_response("test", "f:1", r#"{"is_valid": true}"#);
+        assert!(!r.is_failure());
+        assert!(r.status().contains("OK"));
+    }
+
+    // ── parse_suggestions ─────────────────────────────────────────────────
+
+    #[test]
+    fn parse_suggestions_full() {
+        let text = r#"```json
+            {"suggestions": [{"name": "api-align", "target": "src/api.ts",
+            "files": ["./api.ts", "../server/routes.py"], "instruction": "Keep routes aligned."}]}
+            ```"#;
+        let s = parse_suggestions(text).unwrap();
+        assert_eq!(s.len(), 1);
+        assert_eq!(s[0].name, "api-align");
+        assert_eq!(s[0].target.as_deref(), Some("src/api.ts"));
+        assert_eq!(s[0].files, vec!["./api.ts", "../server/routes.py"]);
+        assert_eq!(s[0].instruction, "Keep routes aligned.");
+    }
+
+    #[test]
+    fn parse_suggestions_optional_fields_default() {
+        let s =
+            parse_suggestions(r#"{"suggestions": [{"name": "a", "instruction": "b"}]}"#).unwrap();
+        assert!(s[0].target.is_none());
+        assert!(s[0].files.is_empty());
+    }
+
+    #[test]
+    fn parse_suggestions_empty_list() {
+        assert!(
+            parse_suggestions(r#"{"suggestions": []}"#)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn parse_suggestions_malformed() {
+        let err = parse_suggestions("no idea").unwrap_err();
+        assert!(err.contains("no idea"));
+    }
+
+    // ── parse_reviews ─────────────────────────────────────────────────────
+
+    #[test]
+    fn parse_reviews_full_and_defaults() {
+        let text = r#"{"reviews": [
+            {"name": "api-align", "location": "src/api.ts:3", "ambiguity": "\"in sync\" is vague",
+             "overlaps": ["routes-match"], "rewrite": "Every route in api.ts exists in routes.py."},
+            {"name": "no-todos", "rewrite": "No TODO comments in src/."}]}"#;
+        let reviews = parse_reviews(text).unwrap();
+        assert_eq!(reviews.len(), 2);
+        assert_eq!(reviews[0].location.as_deref(), Some("src/api.ts:3"));
+        assert_eq!(reviews[0].overlaps, vec!["routes-match"]);
+        assert!(reviews[0].testability.is_none());
+        assert!(reviews[1].ambiguity.is_none() && reviews[1].overlaps.is_empty());
+        assert!(parse_reviews("nothing").is_err());
+    }
+
+    // ── parse_classifications ─────────────────────────────────────────────
+
+    #[test]
+    fn parse_classifications_with_and_without_assert() {
+        let text = r#"{"classifications": [
+            {"name": "docs", "location": "src/app.ts:3", "assert": "exists('/docs/api.md')"},
+            {"name": "handlers", "assert": null}]}"#;
+        let found = parse_classifications(text).unwrap();
+        assert_eq!(found[0].assert.as_deref(), Some("exists('/docs/api.md')"));
+        assert!(found[1].assert.is_none() && found[1].location.is_none());
+        assert!(parse_classifications("nothing").is_err());
+    }
+
+    #[test]
+    fn parse_marker_change_reads_the_reason() {
+        assert_eq!(parse_marker_change(r#"{"weakens": false}"#), Ok(None));
+        assert_eq!(
+            parse_marker_change(r#"{"weakens": true, "reason": "drops src/api/"}"#),
+            Ok(Some("drops src/api/".to_string()))
+        );
+        assert!(parse_marker_change(r#"Verdict: {"weakens": false}"#).is_err());
+        assert_eq!(
+            parse_marker_change(r#"{"weakens": true}"#),
+            Ok(Some("weakened with no reason given".to_string()))
+        );
+        assert!(parse_marker_change("maybe").is_err());
+    }
 }
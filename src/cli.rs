@@ -1,16 +1,81 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ffi::OsString;
 use std::fs;
+use std::io::{self, IsTerminal};
 use std::path::{Path, PathBuf};
 use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap_complete::ArgValueCandidates;
 use walkdir::WalkDir;
 
+use crate::acks;
+use crate::airgap::{self, Airgap};
+use crate::attestation;
+use crate::audit;
+use crate::badge::{self, BadgeFormat};
 use crate::cache;
+use crate::chunk;
+use crate::ci;
+use crate::classify::{self, ClassifyFormat};
 use crate::claude;
+use crate::clock::{self, Date};
+use crate::completions;
+use crate::config;
+use crate::context::ContextLoader;
+use crate::coverage;
+use crate::doctor;
+use crate::exit_code;
+use crate::formatter;
+use crate::history::{self, HistoryFormat};
+use crate::hooks;
+use crate::index::Index;
+use crate::inventory::{self, ListFormat};
+use crate::jj;
+use crate::lint;
+use crate::lockfiles;
+use crate::manpage;
 use crate::marker;
+use crate::marker_changes;
+use crate::noise::{self, NoiseFilter};
+use crate::owners;
+use crate::packs;
+use crate::platform;
+use crate::plugins;
+use crate::policy;
+use crate::pr;
+use crate::privacy;
+use crate::profile::{self, Phase};
+use crate::prompt;
+use crate::protected;
+use crate::prune;
+use crate::ratchet;
+use crate::redact::{self, Redactor};
+use crate::remote;
+use crate::remote_cache::{self, RemoteCache, RemoteEntry};
+use crate::rename;
+use crate::report::{self, FailOn, OnInconclusive, ReportSpec, RunFormat};
+use crate::results_diff::{self, DiffFormat};
+use crate::resume;
+use crate::rpc;
+use crate::script;
+use crate::selfupdate;
+use crate::shard::{self, Shard};
+use crate::shutdown;
+use crate::snapshot::{self, Snapshots};
+use crate::tickets::{self, TicketConfig, Tracker};
+use crate::upload;
+use crate::vcs::{self, Vcs};
+use crate::virtual_markers;
+use crate::waivers;
 
 #[derive(Parser)]
-#[command(name = "watcher-knight")]
+#[command(
+    name = "watcher-knight",
+    about = "Validate code invariants declared in comments with Claude agents"
+)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Command,
@@ -19,7 +84,284 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Command {
     /// Scan the repository for watcher-knight markers and validate them
-    Run {
+    Run(Box<RunArgs>),
+
+    /// Validate watchers against the current tree on a schedule, e.g. a
+    /// nightly cron job
+    Audit(Box<AuditArgs>),
+
+    /// Final go/no-go check before a release: accept the commit's
+    /// attestation, or validate the commit again
+    Gate(GateArgs),
+
+    /// Validate every shareable watcher at the current commit and upload the
+    /// verdicts to the shared cache, e.g. on main after each merge
+    Prime(Box<PrimeArgs>),
+
+    /// List the watcher markers found in the repository
+    List {
+        /// Directory to scan for markers (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "text")]
+        format: ListFormat,
+
+        /// Include complete marker definitions (instruction, files, options, fingerprint)
+        #[arg(long)]
+        full: bool,
+
+        /// Also list invariants from a policy YAML file (may be repeated)
+        #[arg(long = "policy", value_name = "FILE")]
+        policies: Vec<PathBuf>,
+    },
+
+    /// Print the repository tree annotated with the markers whose `files`
+    /// scope covers each directory and file
+    Map {
+        /// Directory to map (default: the whole repository)
+        #[arg()]
+        path: Option<PathBuf>,
+
+        /// Only expand directories this many levels deep
+        #[arg(long, value_name = "N")]
+        depth: Option<usize>,
+
+        /// Also count invariants from a policy YAML file (may be repeated)
+        #[arg(long = "policy", value_name = "FILE")]
+        policies: Vec<PathBuf>,
+    },
+
+    /// Summarize past run results, e.g. watchers that failed repeatedly
+    Query(QueryArgs),
+
+    /// Show run totals, or per-watcher pass rates and failure streaks
+    Stats(StatsArgs),
+
+    /// Render a status badge for the latest recorded run
+    Badge {
+        /// Directory whose history to read (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+
+        /// svg, or json for a shields.io endpoint
+        #[arg(long, value_enum, default_value = "svg")]
+        format: BadgeFormat,
+
+        /// Write the badge to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Compare two results JSON files, e.g. a PR run against the main-branch
+    /// baseline, and list regressions, fixes and added or removed watchers
+    DiffResults {
+        /// Baseline results file
+        old: PathBuf,
+
+        /// Results file to compare against the baseline
+        new: PathBuf,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "text")]
+        format: DiffFormat,
+    },
+
+    /// Store a copy of the tree so later runs can diff against it without
+    /// version control
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotCommand,
+    },
+
+    /// Waive a failing watcher until a date, with a justification
+    Waive {
+        /// Name of the watcher to waive
+        #[arg(add = ArgValueCandidates::new(completions::marker_names))]
+        marker: String,
+
+        /// Directory containing the waivers file (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+
+        /// Last day the waiver applies (YYYY-MM-DD); enforcement resumes afterwards
+        #[arg(long)]
+        until: String,
+
+        /// Why the failure is accepted (e.g. a ticket reference)
+        #[arg(long)]
+        reason: String,
+    },
+
+    /// Acknowledge the current failure of a watcher so it stops failing runs
+    /// until the watcher or its watched files change
+    Ack {
+        /// Name of the watcher whose failure was reviewed
+        #[arg(add = ArgValueCandidates::new(completions::marker_names))]
+        marker: String,
+
+        /// Directory containing the acks file (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+
+        /// Commit at which the failure was reviewed
+        #[arg(long, default_value = "HEAD")]
+        commit: String,
+    },
+
+    /// Rename a watcher and everything that refers to it (waivers, acks, run history)
+    Rename {
+        /// Current watcher name
+        old: String,
+
+        /// New watcher name
+        new: String,
+
+        /// Directory to scan for markers (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+    },
+
+    /// Check markers and waivers for problems without running any watchers
+    Lint {
+        /// Directory to scan for markers (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+
+        /// Also lint invariants from a policy YAML file (may be repeated)
+        #[arg(long = "policy", value_name = "FILE")]
+        policies: Vec<PathBuf>,
+    },
+
+    /// Rewrite markers in place to the canonical layout
+    Fmt {
+        /// Directory to scan for markers (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+
+        /// Only list files that are not formatted, and exit 1 if there are any
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Find dead watchers (files gone, file deleted upstream, in no suite) and
+    /// delete them or rewrite their file lists
+    Prune {
+        /// Directory to scan for markers (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+
+        /// Branch whose deletions retire watchers (default: origin/main or
+        /// origin/master)
+        #[arg(long, value_name = "REF")]
+        base: Option<String>,
+
+        /// Only list dead watchers, and exit 1 if there are any
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Print shell code that enables tab completion (including marker and suite names)
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: completions::Shell,
+    },
+
+    /// Check the environment (git, claude CLI, auth, config, markers) and suggest fixes
+    Doctor {
+        /// Directory to check (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+    },
+
+    /// Print the manual page (roff) covering all subcommands and the marker syntax
+    Man,
+
+    /// Serve the remote job API, running AI watchers for `run --worker` clients
+    Worker {
+        /// Checkout to validate in (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8787")]
+        listen: String,
+
+        /// Maximum number of watchers to run at once
+        #[arg(long, default_value_t = 4)]
+        max_jobs: usize,
+
+        /// Tools jobs may give the agent; jobs asking for others are refused
+        #[arg(long, value_delimiter = ',', default_value = "Read,Grep,Glob")]
+        allow_tools: Vec<String>,
+
+        /// Models jobs may ask for; jobs asking for others are refused
+        #[arg(long, value_delimiter = ',', default_value = "haiku,sonnet,opus")]
+        allow_models: Vec<String>,
+
+        /// Listen on a non-loopback address although the job API is plain
+        /// HTTP (only on a trusted network; otherwise stay on loopback behind
+        /// a TLS proxy or tunnel)
+        #[arg(long)]
+        insecure: bool,
+    },
+
+    /// Serve JSON-RPC 2.0 over stdio for editor integrations
+    Rpc {
+        /// Directory to scan for markers (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+
+        /// AI model to use [haiku, sonnet, opus]
+        #[arg(long, default_value = "sonnet")]
+        model: String,
+
+        /// Also serve invariants from a policy YAML file (may be repeated)
+        #[arg(long = "policy", value_name = "FILE")]
+        policies: Vec<PathBuf>,
+    },
+
+    /// Report which watchers are, or could be, checked exactly without a
+    /// model, and suggest an `assert` expression for the convertible ones
+    Classify {
+        /// Directory to scan for markers (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+
+        /// Ask the AI about watchers the built-in rules cannot convert
+        /// (answers are cached per watcher definition)
+        #[arg(long)]
+        ai: bool,
+
+        /// AI model to use with --ai [haiku, sonnet, opus]
+        #[arg(long, default_value = "sonnet")]
+        model: String,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "text")]
+        format: ClassifyFormat,
+
+        /// Also classify invariants from a policy YAML file (may be repeated)
+        #[arg(long = "policy", value_name = "FILE")]
+        policies: Vec<PathBuf>,
+    },
+
+    /// Replace this binary with the latest release, after verifying its checksum and signature
+    SelfUpdate {
+        /// Only report whether a newer release is available
+        #[arg(long)]
+        check: bool,
+
+        /// Install although this build cannot verify the release signature
+        /// (no embedded signing key), trusting the checksum alone
+        #[arg(long)]
+        insecure: bool,
+    },
+
+    /// Ask the AI to critique every marker for ambiguity, testability and overlap
+    ReviewMarkers {
         /// Directory to scan for markers (default: git repo root, or cwd)
         #[arg()]
         root: Option<PathBuf>,
@@ -28,128 +370,3094 @@ pub enum Command {
         #[arg(long, default_value = "sonnet")]
         model: String,
 
-        /// Use git diff mode. Optional ref to diff against (default: auto-detect origin/main or origin/master)
-        #[arg(long, num_args = 0..=1, default_missing_value = "")]
-        diff: Option<String>,
+        /// Also review invariants from a policy YAML file (may be repeated)
+        #[arg(long = "policy", value_name = "FILE")]
+        policies: Vec<PathBuf>,
+    },
+
+    /// Ask the AI to propose watcher markers for changed code or a single file
+    Suggest {
+        /// Directory to run in (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+
+        /// Propose markers for this file instead of the git diff
+        #[arg(long)]
+        file: Option<PathBuf>,
+
+        /// Ref to diff against (default: auto-detect origin/main or origin/master)
+        #[arg(long, conflicts_with = "file")]
+        diff: Option<String>,
+
+        /// AI model to use [haiku, sonnet, opus]
+        #[arg(long, default_value = "sonnet")]
+        model: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SnapshotCommand {
+    /// Snapshot the files of the tree and print the snapshot id
+    Create {
+        /// Directory to snapshot (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+    },
+
+    /// List stored snapshots, newest first
+    List {
+        /// Directory whose snapshots to list (default: git repo root, or cwd)
+        #[arg()]
+        root: Option<PathBuf>,
+    },
+}
+
+#[derive(Args, Clone)]
+pub struct RunArgs {
+    /// Directory to scan for markers (default: git repo root, or cwd)
+    #[arg()]
+    pub root: Option<PathBuf>,
+
+    /// AI model to use [haiku, sonnet, opus]
+    #[arg(long, default_value = "sonnet")]
+    pub model: String,
+
+    /// Use git diff mode. Optional ref to diff against (default: auto-detect origin/main or origin/master)
+    #[arg(long, num_args = 0..=1, default_missing_value = "")]
+    pub diff: Option<String>,
+
+    /// Diff mode against the point where this branch forked from REF, the
+    /// parent branch of a stacked PR (implies --diff)
+    #[arg(long, value_name = "REF", conflicts_with = "diff")]
+    pub stack_base: Option<String>,
+
+    /// Diff mode against a snapshot from `snapshot create`, for directories
+    /// without version control
+    #[arg(long, value_name = "ID", conflicts_with_all = ["diff", "stack_base"])]
+    pub against_snapshot: Option<String>,
+
+    /// Skip cache, force all watchers to run fresh
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Read verdicts from the shared remote cache but never upload any (also
+    /// set by WK_CACHE_READONLY=1)
+    #[arg(long, conflicts_with = "no_cache")]
+    pub cache_readonly: bool,
+
+    /// Fail before running when a marker is never closed with `/>` or its
+    /// file list has entries matching no files, instead of warning
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Only fail on violations introduced since the last recorded run:
+    /// watchers that were already failing are reported as PRE-EXISTING
+    #[arg(long)]
+    pub only_new: bool,
+
+    /// Which failures fail the run, by the `severity` option of their watcher
+    #[arg(long, value_enum, default_value = "any")]
+    pub fail_on: FailOn,
+
+    /// Only fail when some severity has more failures than the baseline in
+    /// watcher-knight-ratchet.toml, which full runs record on first use and
+    /// lower as failures are fixed
+    #[arg(long)]
+    pub ratchet: bool,
+
+    /// Do not call the AI backend: report cached verdicts, lint markers and mark
+    /// every other watcher as not run
+    #[arg(long, conflicts_with = "no_cache")]
+    pub offline: bool,
+
+    /// Policy YAML file with organization-wide invariants to apply in addition to
+    /// in-repo markers (may be repeated)
+    #[arg(long = "policy", value_name = "FILE")]
+    pub policies: Vec<PathBuf>,
+
+    /// Output format for results
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: RunFormat,
+
+    /// Also write a report file: json=FILE, html=FILE or markdown=FILE (may be
+    /// repeated)
+    #[arg(long = "report", value_name = "KIND=FILE")]
+    pub reports: Vec<ReportSpec>,
+
+    /// Report the time spent walking, reading, parsing, diffing, filtering and
+    /// validating, and the 10 slowest files to scan
+    #[arg(long)]
+    pub profile: bool,
+
+    /// When watchers fail, ask the model for a short, prioritized summary of
+    /// the failures, printed after the results and added to reports
+    #[arg(long, conflicts_with_all = ["offline", "export_prompts", "import_verdicts"])]
+    pub summarize: bool,
+
+    /// Only list the N slowest watchers under resource usage (the totals
+    /// still cover every fresh validation)
+    #[arg(long, value_name = "N")]
+    pub slowest: Option<usize>,
+
+    /// Only run the watcher with this name (may be repeated)
+    #[arg(long, value_name = "NAME", add = ArgValueCandidates::new(completions::marker_names))]
+    pub only: Vec<String>,
+
+    /// Only run watchers in this suite, set with `options={suite="..."}` (may be repeated)
+    #[arg(long, value_name = "NAME", add = ArgValueCandidates::new(completions::suite_names))]
+    pub suite: Vec<String>,
+
+    /// Only run the K-th of N shards of the selected watchers, split by the
+    /// expected cost they declare, to spread a run over N CI jobs
+    #[arg(long, value_name = "K/N")]
+    pub shard: Option<Shard>,
+
+    /// Dispatch AI watchers to this remote worker instead of running claude locally
+    /// (may be repeated; overrides `workers` in watcher-knight.toml)
+    #[arg(long = "worker", value_name = "URL")]
+    pub workers: Vec<String>,
+
+    /// Write the prompt of every AI validation to DIR instead of calling the
+    /// backend, to be answered on another machine
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["offline", "import_verdicts"])]
+    pub export_prompts: Option<PathBuf>,
+
+    /// Take AI verdicts from DIR, where the prompts of --export-prompts were
+    /// answered, instead of calling the backend
+    #[arg(long, value_name = "DIR", conflicts_with = "offline")]
+    pub import_verdicts: Option<PathBuf>,
+
+    /// In diff mode, pass even when the change removes a watcher or edits
+    /// one in a way that weakens it
+    #[arg(long)]
+    pub allow_marker_changes: bool,
+
+    /// Validate a GitHub pull request by URL in a temporary clone, in diff
+    /// mode against its base branch (read-only; uses GITHUB_TOKEN if set)
+    #[arg(long, value_name = "URL", conflicts_with_all = ["against_snapshot", "comment"])]
+    pub pr: Option<String>,
+
+    /// Post the markdown report as a comment on the pull request being
+    /// built (GitHub Actions, Bitbucket Pipelines, Azure Pipelines)
+    #[arg(long)]
+    pub comment: bool,
+
+    /// When every watcher passes, record an attestation of the commit in
+    /// git notes (refs/notes/watcher-knight) for `gate`
+    #[arg(long, conflicts_with_all = ["diff", "stack_base", "against_snapshot", "only", "suite", "shard", "offline", "export_prompts", "import_verdicts"])]
+    pub attest: bool,
+
+    /// Stop starting watchers once the run has taken this long (e.g. 45s,
+    /// 10m, 1h); those left are reported as INCONCLUSIVE
+    #[arg(long, value_name = "DURATION", value_parser = parse_deadline)]
+    pub deadline: Option<Duration>,
+
+    /// Whether watchers left INCONCLUSIVE by --deadline or a spent token
+    /// budget fail the run, warn, or pass
+    #[arg(long, value_enum, default_value = "warn")]
+    pub inconclusive: OnInconclusive,
+
+    /// Continue an interrupted run (a crash, or a killed or retried CI job):
+    /// reuse the verdicts it saved in .watcher_knight/run-state.jsonl and only
+    /// validate the watchers it never finished
+    #[arg(long)]
+    pub resume: bool,
+
+    /// Set by `audit --full`.
+    #[arg(skip)]
+    pub audit: bool,
+
+    /// Set by `audit --compare-last`.
+    #[arg(skip)]
+    pub compare_last: bool,
+
+    /// Set by `prime`.
+    #[arg(skip)]
+    pub prime: bool,
+}
+
+impl RunArgs {
+    /// The `run` command line these arguments parse back from. The fields
+    /// other commands set have no flag and are left out.
+    pub fn to_args(&self) -> Vec<OsString> {
+        let RunArgs {
+            root,
+            model,
+            diff,
+            stack_base,
+            against_snapshot,
+            no_cache,
+            cache_readonly,
+            strict,
+            only_new,
+            fail_on,
+            ratchet,
+            offline,
+            policies,
+            format,
+            reports,
+            profile,
+            summarize,
+            slowest,
+            only,
+            suite,
+            shard,
+            workers,
+            export_prompts,
+            import_verdicts,
+            allow_marker_changes,
+            pr,
+            comment,
+            attest,
+            deadline,
+            inconclusive,
+            resume,
+            audit: _,
+            compare_last: _,
+            prime: _,
+        } = self;
+        let mut args: Vec<OsString> = vec!["run".into(), "--model".into(), model.into()];
+        let mut flag = |name: &str, value: Option<OsString>| {
+            args.push(format!("--{name}").into());
+            args.extend(value);
+        };
+        if let Some(diff) = diff {
+            // `--diff` alone auto-detects the ref.
+            flag(&format!("diff={diff}"), None);
+        }
+        for (name, value) in [
+            ("stack-base", stack_base),
+            ("against-snapshot", against_snapshot),
+            ("pr", pr),
+        ] {
+            if let Some(value) = value {
+                flag(name, Some(value.into()));
+            }
+        }
+        for (name, set) in [
+            ("no-cache", no_cache),
+            ("cache-readonly", cache_readonly),
+            ("strict", strict),
+            ("only-new", only_new),
+            ("ratchet", ratchet),
+            ("offline", offline),
+            ("profile", profile),
+            ("summarize", summarize),
+            ("allow-marker-changes", allow_marker_changes),
+            ("comment", comment),
+            ("attest", attest),
+            ("resume", resume),
+        ] {
+            if *set {
+                flag(name, None);
+            }
+        }
+        flag("fail-on", Some(value_name(fail_on).into()));
+        flag("format", Some(value_name(format).into()));
+        flag("inconclusive", Some(value_name(inconclusive).into()));
+        for policy in policies {
+            flag("policy", Some(policy.into()));
+        }
+        for report in reports {
+            let mut spec = OsString::from(format!("{}=", value_name(&report.kind)));
+            spec.push(&report.path);
+            flag("report", Some(spec));
+        }
+        for (name, values) in [("only", only), ("suite", suite), ("worker", workers)] {
+            for value in values {
+                flag(name, Some(value.into()));
+            }
+        }
+        if let Some(slowest) = slowest {
+            flag("slowest", Some(slowest.to_string().into()));
+        }
+        if let Some(shard) = shard {
+            flag(
+                "shard",
+                Some(format!("{}/{}", shard.index, shard.count).into()),
+            );
+        }
+        for (name, dir) in [
+            ("export-prompts", export_prompts),
+            ("import-verdicts", import_verdicts),
+        ] {
+            if let Some(dir) = dir {
+                flag(name, Some(dir.into()));
+            }
+        }
+        if let Some(deadline) = deadline {
+            flag("deadline", Some(format!("{}s", deadline.as_secs()).into()));
+        }
+        // After the flags, so that a root starting with `-` is not one.
+        if let Some(root) = root {
+            args.push("--".into());
+            args.push(root.into());
+        }
+        args
+    }
+}
+
+/// The command-line spelling of a `ValueEnum` value.
+fn value_name(value: &impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
+
+#[derive(Args)]
+pub struct AuditArgs {
+    /// Directory to scan for markers (default: git repo root, or cwd)
+    #[arg()]
+    pub root: Option<PathBuf>,
+
+    /// Validate every watcher against the whole current tree, without a diff
+    /// or the cache, to catch drift that diff-scoped checks missed
+    #[arg(long, required = true)]
+    pub full: bool,
+
+    /// Show the watchers that flipped between passing and failing since the
+    /// previous audit, and the commits in between to bisect
+    #[arg(long, conflicts_with = "shard")]
+    pub compare_last: bool,
+
+    /// AI model to use [haiku, sonnet, opus]
+    #[arg(long, default_value = "sonnet")]
+    pub model: String,
+
+    /// Fail before running when a marker is never closed with `/>` or its
+    /// file list has entries matching no files, instead of warning
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Which failures fail the audit, by the `severity` option of their watcher
+    #[arg(long, value_enum, default_value = "any")]
+    pub fail_on: FailOn,
+
+    /// Policy YAML file with organization-wide invariants to apply in addition to
+    /// in-repo markers (may be repeated)
+    #[arg(long = "policy", value_name = "FILE")]
+    pub policies: Vec<PathBuf>,
+
+    /// Output format for results
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: RunFormat,
+
+    /// Also write a report file: json=FILE, html=FILE or markdown=FILE (may be
+    /// repeated)
+    #[arg(long = "report", value_name = "KIND=FILE")]
+    pub reports: Vec<ReportSpec>,
+
+    /// When watchers fail, ask the model for a short, prioritized summary of
+    /// the failures, printed after the results and added to reports
+    #[arg(long)]
+    pub summarize: bool,
+
+    /// Only run the watcher with this name (may be repeated)
+    #[arg(long, value_name = "NAME", add = ArgValueCandidates::new(completions::marker_names))]
+    pub only: Vec<String>,
+
+    /// Only run watchers in this suite, set with `options={suite="..."}` (may be repeated)
+    #[arg(long, value_name = "NAME", add = ArgValueCandidates::new(completions::suite_names))]
+    pub suite: Vec<String>,
+
+    /// Only run the K-th of N shards of the selected watchers, split by the
+    /// expected cost they declare, to spread a run over N CI jobs
+    #[arg(long, value_name = "K/N")]
+    pub shard: Option<Shard>,
+
+    /// Dispatch AI watchers to this remote worker instead of running claude locally
+    /// (may be repeated; overrides `workers` in watcher-knight.toml)
+    #[arg(long = "worker", value_name = "URL")]
+    pub workers: Vec<String>,
+
+    /// When every watcher passes, record an attestation of the commit in
+    /// git notes (refs/notes/watcher-knight) for `gate`
+    #[arg(long, conflicts_with_all = ["only", "suite", "shard"])]
+    pub attest: bool,
+
+    /// Stop starting watchers once the audit has taken this long (e.g. 45s,
+    /// 10m, 1h); those left are reported as INCONCLUSIVE
+    #[arg(long, value_name = "DURATION", value_parser = parse_deadline)]
+    pub deadline: Option<Duration>,
+
+    /// Whether watchers left INCONCLUSIVE by --deadline or a spent token
+    /// budget fail the audit, warn, or pass
+    #[arg(long, value_enum, default_value = "warn")]
+    pub inconclusive: OnInconclusive,
+
+    /// Continue an interrupted audit: reuse the verdicts it saved and only
+    /// validate the watchers it never finished
+    #[arg(long)]
+    pub resume: bool,
+}
+
+impl AuditArgs {
+    /// The `run` an audit is: no diff and no cache.
+    pub fn run_args(self) -> RunArgs {
+        RunArgs {
+            root: self.root,
+            model: self.model,
+            diff: None,
+            stack_base: None,
+            against_snapshot: None,
+            no_cache: true,
+            cache_readonly: false,
+            strict: self.strict,
+            only_new: false,
+            fail_on: self.fail_on,
+            ratchet: false,
+            offline: false,
+            policies: self.policies,
+            format: self.format,
+            reports: self.reports,
+            profile: false,
+            summarize: self.summarize,
+            slowest: None,
+            only: self.only,
+            suite: self.suite,
+            shard: self.shard,
+            workers: self.workers,
+            export_prompts: None,
+            import_verdicts: None,
+            allow_marker_changes: false,
+            pr: None,
+            comment: false,
+            attest: self.attest,
+            deadline: self.deadline,
+            inconclusive: self.inconclusive,
+            resume: self.resume,
+            audit: true,
+            compare_last: self.compare_last,
+            prime: false,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct PrimeArgs {
+    /// Directory to scan for markers (default: git repo root, or cwd)
+    #[arg()]
+    pub root: Option<PathBuf>,
+
+    /// AI model to use [haiku, sonnet, opus]
+    #[arg(long, default_value = "sonnet")]
+    pub model: String,
+
+    /// Policy YAML file with organization-wide invariants to apply in addition to
+    /// in-repo markers (may be repeated)
+    #[arg(long = "policy", value_name = "FILE")]
+    pub policies: Vec<PathBuf>,
+
+    /// Only prime watchers in this suite, set with `options={suite="..."}` (may be repeated)
+    #[arg(long, value_name = "NAME", add = ArgValueCandidates::new(completions::suite_names))]
+    pub suite: Vec<String>,
+
+    /// Only prime the K-th of N shards of the watchers, split by the expected
+    /// cost they declare
+    #[arg(long, value_name = "K/N")]
+    pub shard: Option<Shard>,
+
+    /// Dispatch AI watchers to this remote worker instead of running claude locally
+    /// (may be repeated; overrides `workers` in watcher-knight.toml)
+    #[arg(long = "worker", value_name = "URL")]
+    pub workers: Vec<String>,
+}
+
+impl PrimeArgs {
+    /// The `run` priming is: cache mode over the watchers the shared cache
+    /// can hold, uploading every fresh verdict.
+    pub fn run_args(self) -> RunArgs {
+        RunArgs {
+            root: self.root,
+            model: self.model,
+            diff: None,
+            stack_base: None,
+            against_snapshot: None,
+            no_cache: false,
+            cache_readonly: false,
+            strict: false,
+            only_new: false,
+            fail_on: FailOn::Any,
+            ratchet: false,
+            offline: false,
+            policies: self.policies,
+            format: RunFormat::Text,
+            reports: Vec::new(),
+            profile: false,
+            summarize: false,
+            slowest: None,
+            only: Vec::new(),
+            suite: self.suite,
+            shard: self.shard,
+            workers: self.workers,
+            export_prompts: None,
+            import_verdicts: None,
+            allow_marker_changes: false,
+            pr: None,
+            comment: false,
+            attest: false,
+            deadline: None,
+            inconclusive: OnInconclusive::Warn,
+            resume: false,
+            audit: false,
+            compare_last: false,
+            prime: true,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct QueryArgs {
+    /// Directory whose history to read (default: git repo root, or cwd)
+    #[arg()]
+    pub root: Option<PathBuf>,
+
+    /// Only count results with this status: passed, failed, waived,
+    /// acknowledged, pre-existing or not_run (may be repeated)
+    #[arg(long)]
+    pub status: Vec<String>,
+
+    /// Only count results from runs in the last AGE (e.g. 30d, 12h, 2w)
+    #[arg(long, value_name = "AGE")]
+    pub since: Option<String>,
+
+    /// Only report this watcher (may be repeated)
+    #[arg(long = "marker", value_name = "NAME", add = ArgValueCandidates::new(completions::marker_names))]
+    pub markers: Vec<String>,
+
+    /// Only report watchers with at least this many matching results
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub min_count: usize,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: HistoryFormat,
+}
+
+#[derive(clap::Args)]
+pub struct StatsArgs {
+    /// Directory whose history to read (default: git repo root, or cwd)
+    #[arg()]
+    pub root: Option<PathBuf>,
+
+    /// Show pass rate, mean latency and failure streaks per watcher,
+    /// flagging chronically red ones
+    #[arg(long)]
+    pub trends: bool,
+
+    /// List watchers whose author has not committed within --since
+    /// (default 90d) and that set no `owner`, so they need reassigning
+    #[arg(long, conflicts_with = "trends")]
+    pub owners: bool,
+
+    /// Only use runs from the last AGE (e.g. 30d, 12h, 2w); with --owners,
+    /// how recent an author's last commit must be
+    #[arg(long, value_name = "AGE")]
+    pub since: Option<String>,
+
+    /// Only report this watcher (may be repeated)
+    #[arg(long = "marker", value_name = "NAME", requires = "trends", add = ArgValueCandidates::new(completions::marker_names))]
+    pub markers: Vec<String>,
+
+    /// Only report watchers validated in at least this many runs
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "trends")]
+    pub min_runs: usize,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: HistoryFormat,
+}
+
+/// What a run validates and with what, settled before any watcher runs.
+struct RunPlan {
+    started_at: i64,
+    started: Instant,
+    root: PathBuf,
+    config: config::Config,
+    vcs: Box<dyn Vcs>,
+    /// The ref diff mode runs against; `None` in cache mode.
+    diff_ref: Option<String>,
+    mode: &'static str,
+    /// Run metadata for hooks; post_run also gets the outcome.
+    hook_env: Vec<(&'static str, String)>,
+    redactor: Redactor,
+    noise: NoiseFilter,
+    checkers: plugins::Checkers,
+    /// The selected watchers.
+    markers: Vec<marker::Marker>,
+    /// Every watcher: `@name` references resolve against all of them.
+    all_markers: Vec<marker::Marker>,
+    /// Exit code of a run without violations.
+    clean_exit: i32,
+    suppressions: Suppressions,
+    airgap: Option<Airgap>,
+    pool: Option<remote::Pool>,
+    provenance: report::Provenance,
+}
+
+pub fn run(args: &RunArgs) {
+    let mut plan = plan_run(args);
+    let results = execute_run(&mut plan, args);
+    report_run(&plan, args, results);
+}
+
+/// Load the config and the watchers, select those to run and set up the
+/// backend, running the `pre_run` hook. Exits when there is nothing to run.
+fn plan_run(args: &RunArgs) -> RunPlan {
+    let started_at = clock::now();
+    let started = Instant::now();
+    let root = resolve_root(args.root.as_deref());
+
+    let mut config = load_config(&root);
+    if pr::untrusted() {
+        config.lock_down();
+    }
+    let vcs: Box<dyn Vcs> = if args.against_snapshot.is_some() {
+        Box::new(Snapshots::new(&root))
+    } else {
+        vcs::for_root(&root)
+    };
+    let diff_ref = match (
+        &args.against_snapshot,
+        &args.stack_base,
+        args.diff.as_deref(),
+    ) {
+        (Some(id), _, _) => Some(id.clone()),
+        (None, Some(parent), _) => Some(vcs.fork_point(parent)),
+        (None, None, Some("")) => Some(resolve_diff_ref(&*vcs)),
+        (None, None, diff) => diff.map(str::to_string),
+    };
+    let mode = match (args.audit, &diff_ref) {
+        (true, _) => "audit",
+        (false, Some(_)) => "diff",
+        (false, None) if args.prime => "prime",
+        (false, None) => "cache",
+    };
+    if args.prime {
+        let env = |v: &str| std::env::var(v).ok();
+        if remote_cache::effective_config(config.remote_cache.as_ref(), env).is_none() {
+            eprintln!(
+                "Error: `prime` fills the shared cache; configure [remote_cache] in {} or set {}",
+                config::CONFIG_FILE,
+                remote_cache::URL_VAR
+            );
+            process::exit(exit_code::CONFIG_ERROR);
+        }
+        if remote_cache::readonly_from_env() {
+            eprintln!("Error: `prime` cannot fill a read-only shared cache (WK_CACHE_READONLY)");
+            process::exit(exit_code::CONFIG_ERROR);
+        }
+    }
+
+    let mut hook_env = vec![
+        ("WK_ROOT", root.display().to_string()),
+        ("WK_MODE", mode.to_string()),
+        ("WK_MODEL", args.model.clone()),
+        (
+            "WK_OFFLINE",
+            if args.offline { "1" } else { "0" }.to_string(),
+        ),
+    ];
+    if let Some(diff_ref) = &diff_ref {
+        hook_env.push(("WK_DIFF_REF", diff_ref.clone()));
+    }
+    if let Some(command) = &config.pre_run
+        && let Err(e) = hooks::run_hook("pre_run", command, &root, &hook_env)
+    {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    }
+
+    if args.profile {
+        profile::enable();
+    }
+    let redactor = build_redactor(&config);
+    let noise = build_noise_filter(&config);
+    let (mut markers, parse_errors) = load_markers_with_errors(&root, &config, &args.policies);
+    let checkers = plugins::discover(&config.checkers, &root, &markers);
+    for err in &parse_errors {
+        if args.strict && err.unclosed {
+            eprintln!("Error: {err}");
+        } else {
+            eprintln!("\x1b[33m[WARNING] {err}\x1b[0m");
+        }
+    }
+    if args.strict && parse_errors.iter().any(|e| e.unclosed) {
+        process::exit(exit_code::MALFORMED_MARKERS);
+    }
+    // Exit code of a run without violations. A never-closed marker is only
+    // a warning without `--strict`.
+    let clean_exit = if parse_errors.iter().all(|e| e.unclosed) {
+        0
+    } else {
+        exit_code::MALFORMED_MARKERS
+    };
+    if markers.is_empty() {
+        eprintln!("No watchers found.");
+        process::exit(clean_exit);
+    }
+    // `@name` references resolve against every watcher, not just those selected.
+    let all_markers = markers.clone();
+    select_markers(&mut markers, args, &config, clean_exit);
+
+    let missing = lint::lint_file_entries(&markers, &root);
+    for issue in &missing {
+        if args.strict {
+            eprintln!("Error: {issue}");
+        } else {
+            eprintln!("\x1b[33m[WARNING] {issue}\x1b[0m");
+        }
+    }
+    if args.strict && !missing.is_empty() {
+        process::exit(exit_code::MALFORMED_MARKERS);
+    }
+
+    let mut suppressions = Suppressions::load(&root, &markers).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::CONFIG_ERROR);
+    });
+    if args.only_new {
+        suppressions.pre_existing = pre_existing_failures(&root, &markers);
+    }
+    if args.offline {
+        let issues = lint::lint_waivers(
+            &suppressions.waivers,
+            &markers,
+            &config.protected_paths,
+            suppressions.today,
+        )
+        .into_iter()
+        .chain(lint::lint_checkers(&markers, &checkers));
+        for issue in issues {
+            eprintln!("\x1b[33m[WARNING] {issue}\x1b[0m");
+        }
+    }
+
+    let (airgap, pool) = plan_backend(args, &config, &root);
+    let provenance = report::Provenance {
+        tool: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        config_sha256: fs::read(root.join(config::CONFIG_FILE))
+            .ok()
+            .map(|data| packs::sha256_hex(&data)),
+        markers_sha256: report::markers_sha256(&all_markers),
+        backend: match (&airgap, &pool) {
+            _ if args.offline => "offline",
+            (Some(Airgap::Export { .. }), _) => "export",
+            (Some(Airgap::Import(_)), _) => "import",
+            (None, Some(_)) => "remote",
+            (None, None) => "claude",
+        },
+        models: markers
+            .iter()
+            .map(|m| m.options.get("model").unwrap_or(&args.model).clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect(),
+        commit: git_output(&root, &["rev-parse", "HEAD"]),
+        diff_base: diff_ref.as_ref().map(|r| {
+            git_output(
+                &root,
+                &[
+                    "rev-parse",
+                    "--verify",
+                    "--quiet",
+                    &format!("{r}^{{commit}}"),
+                ],
+            )
+            .unwrap_or_else(|| r.clone())
+        }),
+        host: platform::hostname(),
+        ci: ci::job(|v| std::env::var(v).ok()),
+    };
+    RunPlan {
+        started_at,
+        started,
+        root,
+        config,
+        vcs,
+        diff_ref,
+        mode,
+        hook_env,
+        redactor,
+        noise,
+        checkers,
+        markers,
+        all_markers,
+        clean_exit,
+        suppressions,
+        airgap,
+        pool,
+        provenance,
+    }
+}
+
+/// Where AI watchers go instead of the local claude: prompts exported or
+/// verdicts imported (`--export-prompts`, `--import-verdicts`), or remote
+/// workers.
+fn plan_backend(
+    args: &RunArgs,
+    config: &config::Config,
+    root: &Path,
+) -> (Option<Airgap>, Option<remote::Pool>) {
+    let workers = if args.workers.is_empty() {
+        config.workers.clone()
+    } else {
+        args.workers.clone()
+    };
+    let airgap = match (&args.export_prompts, &args.import_verdicts) {
+        (Some(dir), _) => Some(Airgap::Export {
+            dir: dir.clone(),
+            commit: git_output(root, &["rev-parse", "HEAD"]),
+        }),
+        (None, Some(dir)) => Some(Airgap::Import(dir.clone())),
+        (None, None) => None,
+    };
+    let pool = (!workers.is_empty() && !args.offline && airgap.is_none()).then(|| {
+        if git_output(root, &["status", "--porcelain", "--untracked-files=no"])
+            .is_some_and(|s| !s.is_empty())
+        {
+            eprintln!(
+                "\x1b[33m[WARNING] Remote workers only see committed changes; uncommitted \
+                 edits are not validated\x1b[0m"
+            );
+        }
+        if config.confine_tools {
+            eprintln!(
+                "\x1b[33m[WARNING] confine_tools is not enforced on remote workers; their agents \
+                 are only told the scope\x1b[0m"
+            );
+        }
+        let commit = git_output(root, &["rev-parse", "HEAD"]);
+        remote::Pool::from_env(workers, commit).unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            process::exit(exit_code::CONFIG_ERROR);
+        })
+    });
+    (airgap, pool)
+}
+
+/// Keep the watchers `--only`, `--suite`, `--shard` and `prime` select.
+/// Exits with `clean_exit` when none are left.
+fn select_markers(
+    markers: &mut Vec<marker::Marker>,
+    args: &RunArgs,
+    config: &config::Config,
+    clean_exit: i32,
+) {
+    if !args.only.is_empty() || !args.suite.is_empty() {
+        let filter_start = Instant::now();
+        markers.retain(|m| {
+            let in_suite = m.options.get("suite").is_some_and(|suites| {
+                suites
+                    .split(',')
+                    .any(|s| args.suite.contains(&s.trim().to_string()))
+            });
+            args.only.contains(&m.name) || in_suite
+        });
+        profile::record(Phase::Filter, filter_start.elapsed());
+        if markers.is_empty() {
+            eprintln!("No watchers matched --only/--suite.");
+            process::exit(clean_exit);
+        }
+    }
+    if let Some(shard) = args.shard {
+        let filter_start = Instant::now();
+        let shards = shard::assign(markers, shard.count, &config.suites).unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            process::exit(exit_code::MALFORMED_MARKERS);
+        });
+        let mut shards = shards.into_iter();
+        markers.retain(|_| shards.next() == Some(shard.index - 1));
+        profile::record(Phase::Filter, filter_start.elapsed());
+        if markers.is_empty() {
+            eprintln!("No watchers in shard {shard}.");
+            process::exit(clean_exit);
+        }
+    }
+    if args.prime {
+        // The rest never reach the shared cache.
+        markers.retain(cache::is_cacheable);
+        if markers.is_empty() {
+            eprintln!(
+                "No watchers can be shared: only scoped watchers that are not assertions or \
+                 command-output watchers are cached."
+            );
+            process::exit(clean_exit);
+        }
+    }
+}
+
+/// Validate the planned watchers, in diff or cache mode. `None` when diff
+/// mode finds nothing to validate.
+fn execute_run(
+    plan: &mut RunPlan,
+    args: &RunArgs,
+) -> Option<(Vec<claude::WatcherResult>, Option<String>)> {
+    let root = &plan.root;
+    let config = &plan.config;
+    let context = ContextLoader::new(root, &config.context_allowlist, &plan.redactor);
+    let commands = hooks::Commands::new(&config.commands);
+    let suppress = |r: &claude::WatcherResult| plan.suppressions.suppress(r);
+    // Verdicts are saved as they come in, for `--resume` after a crash or a
+    // killed CI job.
+    let state = resume::Header::new(root, &plan.provenance, &args.model);
+    let resumed = args
+        .resume
+        .then(|| match resume::load(root, &state) {
+            Ok(resumed) => {
+                eprintln!(
+                    "Resuming an interrupted run: {} watchers already validated",
+                    resumed.len()
+                );
+                Some(resumed)
+            }
+            Err(e) => {
+                eprintln!(
+                    "\x1b[33m[WARNING] Nothing to resume ({e}); validating every watcher\x1b[0m"
+                );
+                None
+            }
+        })
+        .flatten();
+    resume::start(root, &state, resumed.as_ref());
+    let ctx = claude::RunContext {
+        root,
+        diff: None,
+        model: &args.model,
+        checkers: &plan.checkers,
+        offline: args.offline,
+        pool: plan.pool.as_ref(),
+        context: &context,
+        markers: &plan.all_markers,
+        backends: &config.backends,
+        confine: config.confine_tools,
+        chunk_files: config.chunk_files.unwrap_or(chunk::DEFAULT_CHUNK_FILES),
+        commands: &commands,
+        airgap: plan.airgap.as_ref(),
+        audit: args.audit,
+        resumed: resumed.as_ref(),
+        deadline: args.deadline.map(|d| plan.started + d),
+        untrusted: pr::untrusted(),
+    };
+
+    // A CI timeout or cancel still leaves the reports of what was validated.
+    if !args.reports.is_empty() {
+        shutdown::watch(
+            &args.reports,
+            plan.mode,
+            &args.model,
+            current_branch(root),
+            plan.started_at,
+            &plan.provenance,
+        );
+    }
+
+    let results = match plan.diff_ref.as_deref() {
+        Some(diff_ref) => run_diff_mode(
+            &ctx,
+            &mut plan.markers,
+            &*plan.vcs,
+            diff_ref,
+            &DiffFilters {
+                redactor: &plan.redactor,
+                noise: &plan.noise,
+            },
+            args.no_cache,
+            &suppress,
+        )
+        .map(|(results, diff)| (results, Some(diff))),
+        None => {
+            let env = |v: &str| std::env::var(v).ok();
+            let shared =
+                remote_cache::effective_config(config.remote_cache.as_ref(), env).map(|c| {
+                    let readonly = args.cache_readonly || remote_cache::readonly_from_env();
+                    RemoteCache::new(&c, readonly, env).unwrap_or_else(|e| {
+                        eprintln!("Error: {e}");
+                        process::exit(exit_code::CONFIG_ERROR);
+                    })
+                });
+            let results = run_cache_mode(
+                &ctx,
+                &plan.markers,
+                args.no_cache,
+                args.prime,
+                shared.as_ref(),
+                &suppress,
+            );
+            Some((results, None))
+        }
+    };
+    resume::finish(root);
+    if let Some(profile) = profile::render() {
+        eprint!("\n\x1b[36m==== PROFILE ====\x1b[0m\n\n{profile}");
+    }
+    results
+}
+
+/// Print the results, decide whether the run passed, record and publish
+/// it, and exit with its status.
+fn report_run(
+    plan: &RunPlan,
+    args: &RunArgs,
+    results: Option<(Vec<claude::WatcherResult>, Option<String>)>,
+) -> ! {
+    let RunPlan {
+        root,
+        config,
+        vcs,
+        diff_ref,
+        redactor,
+        markers,
+        all_markers,
+        suppressions,
+        airgap,
+        provenance,
+        ..
+    } = plan;
+    let vcs = vcs.as_ref();
+    let clean_exit = plan.clean_exit;
+    // A shard sees only its own watchers' results, so it cannot tell whether
+    // a protected change is guarded; the checks that need no results run in
+    // the first shard only, so that they are not repeated.
+    let repo_checks = args.shard.is_none_or(|s| s.index == 1);
+    if args.shard.is_some() && diff_ref.is_some() && !config.protected_paths.is_empty() {
+        eprintln!(
+            "\x1b[33m[WARNING] protected_paths are not checked in sharded runs; run them \
+             unsharded to enforce them\x1b[0m"
+        );
+    }
+    let Some((results, diff)) = results else {
+        let none: &[claude::WatcherResult] = &[];
+        // `&` so that both checks report their errors.
+        if let Some(diff_ref) = &diff_ref
+            && repo_checks
+            && !(check_path_policies(
+                config,
+                vcs,
+                diff_ref,
+                all_markers,
+                args.shard.is_none().then_some(none),
+                suppressions,
+            ) & check_marker_changes(root, vcs, diff_ref, all_markers, args))
+        {
+            process::exit(exit_code::VIOLATIONS);
+        }
+        process::exit(clean_exit);
+    };
+    let passed = match args.format {
+        RunFormat::Text => claude::print_results(&results, markers, diff.as_deref(), args.slowest),
+        RunFormat::Compact => {
+            for line in report::compact_lines(&results, markers) {
+                println!("{line}");
+            }
+            !results.iter().any(|r| r.is_failure())
+        }
+    };
+    let narrative = if args.summarize {
+        summarize_failures(&results, markers, diff.as_deref(), &args.model, redactor)
+    } else {
+        None
+    };
+    let extras = report::Extras {
+        narrative: narrative.as_deref(),
+        provenance: Some(provenance),
+    };
+    if let Some(narrative) = &narrative
+        && matches!(args.format, RunFormat::Text)
+    {
+        println!();
+        println!("\x1b[36m==== SUMMARY ====\x1b[0m");
+        println!();
+        println!("{}", narrative.trim());
+    }
+    if let Some(Airgap::Export { dir, .. }) = &airgap {
+        let exported = results
+            .iter()
+            .filter(|r| r.skipped.as_deref() == Some(airgap::EXPORTED))
+            .count();
+        eprintln!(
+            "\nExported {exported} prompts to {}. Answer each <id>.prompt.json in \
+             <id>.verdict.json, then run again with --import-verdicts {0}",
+            dir.display()
+        );
+    }
+
+    let passed = decide_outcome(plan, args, &results, extras, passed, repo_checks);
+    publish_run(
+        plan,
+        args,
+        &results,
+        diff.as_deref(),
+        narrative.as_deref(),
+        extras,
+        passed,
+    );
+    if !passed {
+        // Failures that are all backend errors say nothing about the code.
+        let mut failures = results.iter().filter(|r| r.is_failure()).peekable();
+        let only_errors = failures.peek().is_some() && failures.all(|r| r.errored);
+        process::exit(match (only_errors, clean_exit) {
+            (false, _) => exit_code::VIOLATIONS,
+            (true, 0) => exit_code::BACKEND_ERROR,
+            (true, code) => code,
+        });
+    }
+    process::exit(clean_exit);
+}
+
+/// Whether the run passed: `passed` as the results have it, revised by
+/// `--fail-on`, `--ratchet`, the post-processor, the `--inconclusive` policy
+/// and, in diff mode, path policies and marker changes.
+fn decide_outcome(
+    plan: &RunPlan,
+    args: &RunArgs,
+    results: &[claude::WatcherResult],
+    extras: report::Extras,
+    mut passed: bool,
+    repo_checks: bool,
+) -> bool {
+    let RunPlan {
+        root,
+        config,
+        vcs,
+        diff_ref,
+        markers,
+        all_markers,
+        suppressions,
+        ..
+    } = plan;
+    let vcs = vcs.as_ref();
+    if args.fail_on != FailOn::Any {
+        let blocking = results.iter().any(|r| {
+            r.is_failure()
+                && markers
+                    .iter()
+                    .find(|m| {
+                        m.name == r.name && format!("{}:{}", m.rel_path, m.line) == r.location
+                    })
+                    .is_none_or(|m| args.fail_on.blocks(m.severity()))
+        });
+        if !passed && !blocking {
+            println!();
+            println!(
+                "watcher-knight result: \x1b[32mOK\x1b[0m (no failure severe enough for --fail-on)"
+            );
+            passed = true;
+        }
+    }
+
+    if args.ratchet {
+        let full_run = diff_ref.is_none()
+            && args.only.is_empty()
+            && args.suite.is_empty()
+            && args.shard.is_none()
+            && results.iter().all(|r| r.skipped.is_none());
+        let ratchet_passed = apply_ratchet(root, results, markers, full_run);
+        if ratchet_passed != passed {
+            let outcome = if ratchet_passed {
+                "\x1b[32mOK\x1b[0m"
+            } else {
+                "\x1b[31mFAILED\x1b[0m"
+            };
+            println!();
+            println!("watcher-knight result: {outcome} (decided by --ratchet)");
+        }
+        passed = ratchet_passed;
+    }
+
+    if let Some(command) = &config.post_processor {
+        let json = report::to_json(results, markers, extras);
+        let verdict = hooks::run_post_processor(command, root, &json).unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            process::exit(exit_code::BACKEND_ERROR);
+        });
+        if !verdict.annotations.is_empty() {
+            println!();
+            println!("\x1b[36m==== POST-PROCESSOR ====\x1b[0m");
+            println!();
+            println!("{}", verdict.annotations);
+        }
+        if verdict.passed != passed {
+            let outcome = if verdict.passed {
+                "\x1b[32mOK\x1b[0m"
+            } else {
+                "\x1b[31mFAILED\x1b[0m"
+            };
+            println!();
+            println!("watcher-knight result: {outcome} (decided by post-processor `{command}`)");
+        }
+        passed = verdict.passed;
+    }
+    // The --inconclusive policy and path policies are enforced whatever the
+    // post-processor decides.
+    if !apply_inconclusive_policy(results, args) {
+        if passed {
+            println!();
+            println!(
+                "watcher-knight result: \x1b[31mFAILED\x1b[0m (inconclusive watchers, --inconclusive fail)"
+            );
+        }
+        passed = false;
+    }
+    if let Some(diff_ref) = &diff_ref
+        && repo_checks
+        && !check_path_policies(
+            config,
+            vcs,
+            diff_ref,
+            all_markers,
+            args.shard.is_none().then_some(results),
+            suppressions,
+        )
+    {
+        if passed {
+            println!();
+            println!(
+                "watcher-knight result: \x1b[31mFAILED\x1b[0m (path policies, see errors above)"
+            );
+        }
+        passed = false;
+    }
+    if let Some(diff_ref) = &diff_ref
+        && repo_checks
+        && !check_marker_changes(root, vcs, diff_ref, all_markers, args)
+    {
+        if passed {
+            println!();
+            println!(
+                "watcher-knight result: \x1b[31mFAILED\x1b[0m (marker changes, see errors above)"
+            );
+        }
+        passed = false;
+    }
+    passed
+}
+
+/// Record the run in history and hand it on: `--compare-last`, `--attest`,
+/// tickets, the report upload, `--report` files, `--comment` and `post_run`.
+fn publish_run(
+    plan: &RunPlan,
+    args: &RunArgs,
+    results: &[claude::WatcherResult],
+    diff: Option<&str>,
+    narrative: Option<&str>,
+    extras: report::Extras,
+    passed: bool,
+) {
+    let RunPlan {
+        root,
+        config,
+        markers,
+        provenance,
+        ..
+    } = plan;
+    let (started_at, mode) = (plan.started_at, plan.mode);
+    let mut hook_env = plan.hook_env.clone();
+    let run_info = history::RunInfo {
+        mode,
+        model: &args.model,
+        commit: git_output(root, &["rev-parse", "HEAD"]),
+        branch: current_branch(root),
+        passed,
+    };
+    // Read before this audit is recorded, which would make it the latest.
+    let previous_audit = args
+        .compare_last
+        .then(|| history::open(root).and_then(|conn| history::latest_run_in(&conn, "audit")));
+    if let Err(e) = history::open(root)
+        .and_then(|mut conn| history::record(&mut conn, &run_info, results, markers, started_at))
+    {
+        eprintln!("\x1b[33m[WARNING] Run not recorded in history: {e}\x1b[0m");
+    }
+    match previous_audit {
+        None => {}
+        Some(Err(e)) => {
+            eprintln!("\x1b[33m[WARNING] Cannot compare with the last audit: {e}\x1b[0m")
+        }
+        Some(Ok(None)) => eprintln!(
+            "\nNo earlier audit to compare with; the next --compare-last compares with this one."
+        ),
+        Some(Ok(Some(previous))) => {
+            let current = audit::results_of(results, markers);
+            let mut comparison = audit::compare(&previous, &current, run_info.commit.as_deref());
+            if let Some((before, after)) = &comparison.range {
+                comparison.commits = git_output(
+                    root,
+                    &["rev-list", "--count", &format!("{before}..{after}")],
+                )
+                .and_then(|n| n.parse().ok());
+            }
+            let text = format!(
+                "\n\x1b[36m==== SINCE LAST AUDIT ====\x1b[0m\n\n{}",
+                audit::render_text(&comparison)
+            );
+            // Compact output is for problem matchers; keep it to findings.
+            match args.format {
+                RunFormat::Text => print!("{text}"),
+                RunFormat::Compact => eprint!("{text}"),
+            }
+        }
+    }
+    if args.attest {
+        attest(root, results, provenance, passed);
+    }
+    if let Some(tickets) = &config.tickets
+        && run_info.branch.as_deref() == Some(tickets.branch.as_str())
+    {
+        file_tickets(root, tickets, results, markers, run_info.commit.as_deref());
+    }
+    if let Some(url) = &config.report.upload_url {
+        let mut report = report::build_report(results, markers);
+        report.narrative = narrative.map(str::to_string);
+        report.provenance = Some(provenance.clone());
+        let repo = git_output(root, &["remote", "get-url", "origin"])
+            .map(|url| upload::strip_credentials(&url));
+        let token = std::env::var(upload::TOKEN_VAR)
+            .ok()
+            .filter(|t| !t.is_empty());
+        let upload = upload::Upload::new(repo, &run_info, started_at, report);
+        // Like tickets, an unreachable dashboard must not break the run.
+        if let Err(e) = upload::post(url, token.as_deref(), &upload) {
+            eprintln!("\x1b[33m[WARNING] Report not uploaded: {e}\x1b[0m");
+        }
+    }
+    for spec in &args.reports {
+        if let Err(e) =
+            report::write_report(spec, results, markers, &run_info, started_at, diff, extras)
+        {
+            eprintln!("Error: {e}");
+            process::exit(exit_code::BACKEND_ERROR);
+        }
+    }
+
+    if args.comment {
+        post_pr_comment(results, markers, narrative);
+    }
+
+    if let Some(command) = &config.post_run {
+        let summary = report::Summary::of(results);
+        let results_file = report::save_report(results, markers, extras).unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            process::exit(exit_code::BACKEND_ERROR);
+        });
+        hook_env.extend([
+            (
+                "WK_STATUS",
+                if passed { "passed" } else { "failed" }.to_string(),
+            ),
+            ("WK_TOTAL", summary.total.to_string()),
+            ("WK_PASSED", summary.passed.to_string()),
+            ("WK_FAILED", summary.failed.to_string()),
+            ("WK_RESULTS", results_file.display().to_string()),
+        ]);
+        if let Err(e) = hooks::run_hook("post_run", command, root, &hook_env) {
+            eprintln!("Error: {e}");
+            process::exit(exit_code::BACKEND_ERROR);
+        }
+    }
+}
+
+/// `run --ratchet`: whether no severity has more failures than the baseline.
+/// Only a full run (every watcher, nothing skipped) records a missing baseline
+/// or lowers it; partial runs just compare against it.
+fn apply_ratchet(
+    root: &Path,
+    results: &[claude::WatcherResult],
+    markers: &[marker::Marker],
+    full_run: bool,
+) -> bool {
+    let current = ratchet::count(results, markers);
+    let baseline = ratchet::load(root).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::CONFIG_ERROR);
+    });
+    let save = |baseline: &ratchet::Baseline| {
+        if let Err(e) = ratchet::save(root, baseline) {
+            eprintln!("\x1b[33m[WARNING] Ratchet baseline not saved: {e}\x1b[0m");
+        }
+    };
+    let Some(mut baseline) = baseline else {
+        if full_run {
+            save(&current);
+            println!("Ratchet baseline recorded in {}", ratchet::RATCHET_FILE);
+        } else {
+            eprintln!(
+                "\x1b[33m[WARNING] No ratchet baseline yet; a run without --diff, --only or \
+                 --suite records one\x1b[0m"
+            );
+        }
+        return true;
+    };
+    let (increased, decreased) = ratchet::compare(&baseline, &current);
+    for c in &increased {
+        println!(
+            "ratchet: {} failures rose from {} to {}",
+            c.severity, c.before, c.after
+        );
+    }
+    if increased.is_empty() && full_run && !decreased.is_empty() {
+        for c in &decreased {
+            println!(
+                "ratchet: {} failures fell from {} to {}; baseline lowered",
+                c.severity, c.before, c.after
+            );
+            baseline.failures.insert(c.severity.clone(), c.after);
+        }
+        save(&baseline);
+    }
+    increased.is_empty()
+}
+
+/// Waivers and acks in effect for a run.
+pub struct Suppressions {
+    pub waivers: waivers::Waivers,
+    pub acks: acks::Acks,
+    /// `name@location` → current failure fingerprint (only when there are acks).
+    pub fingerprints: HashMap<String, String>,
+    /// `name@location` → start of the last recorded run in which the watcher
+    /// already failed (`--only-new`).
+    pub pre_existing: HashMap<String, i64>,
+    pub today: Date,
+}
+
+impl Suppressions {
+    pub fn load(root: &Path, markers: &[marker::Marker]) -> Result<Self, String> {
+        let waivers = waivers::load_waivers(root)?;
+        let acks = acks::load_acks(root)?;
+        // Failure fingerprints are only needed when there is something to match.
+        let fingerprints = if acks.acks.is_empty() {
+            HashMap::new()
+        } else {
+            let tree = if markers.iter().any(|m| m.files.is_empty()) {
+                acks::worktree_tree(root)
+            } else {
+                None
+            };
+            markers
+                .iter()
+                .map(|m| {
+                    let location = format!("{}:{}", m.rel_path, m.line);
+                    let fp = acks::failure_fingerprint(
+                        m,
+                        &m.watched_files(root),
+                        tree.as_deref(),
+                        |f| acks::read_worktree(root, f),
+                    );
+                    (format!("{}@{location}", m.name), fp)
+                })
+                .collect()
+        };
+        Ok(Suppressions {
+            waivers,
+            acks,
+            fingerprints,
+            pre_existing: HashMap::new(),
+            today: clock::today(),
+        })
+    }
+
+    /// Why a failing result should not fail the run, if it should not.
+    pub fn suppress(&self, r: &claude::WatcherResult) -> Option<claude::Suppression> {
+        if let Some(w) = self.waivers.active_for(&r.name, self.today) {
+            return Some(claude::Suppression {
+                label: "WAIVED",
+                note: format!("waived until {}: {}", w.until, w.reason),
+            });
+        }
+        let key = format!("{}@{}", r.name, r.location);
+        if let Some(a) = self
+            .fingerprints
+            .get(&key)
+            .and_then(|fp| self.acks.find(&r.name, fp))
+        {
+            return Some(claude::Suppression {
+                label: "ACKNOWLEDGED",
+                note: format!("acknowledged by {} on {} at {}", a.by, a.date, a.commit),
+            });
+        }
+        self.pre_existing.get(&key).map(|at| claude::Suppression {
+            label: "PRE-EXISTING",
+            note: format!(
+                "already failing in the run of {}",
+                clock::date_from_unix_days(at.div_euclid(86_400))
+            ),
+        })
+    }
+}
+
+/// Watchers (`name@location`) whose marker definition already failed in its
+/// last recorded validation on this branch, and when. Without history
+/// every failure is new.
+fn pre_existing_failures(root: &Path, markers: &[marker::Marker]) -> HashMap<String, i64> {
+    if !history::exists(root) {
+        return HashMap::new();
+    }
+    let branch = current_branch(root);
+    let known = history::open(root)
+        .and_then(|conn| history::known_failures(&conn, branch.as_deref()))
+        .unwrap_or_else(|e| {
+            eprintln!("\x1b[33m[WARNING] --only-new ignored: {e}\x1b[0m");
+            HashMap::new()
+        });
+    markers
+        .iter()
+        .filter_map(|m| {
+            let at = *known.get(&marker::fingerprint(m))?;
+            Some((format!("{}@{}:{}", m.name, m.rel_path, m.line), at))
+        })
+        .collect()
+}
+
+pub fn query(args: &QueryArgs) {
+    let root = resolve_root(args.root.as_deref());
+    let filter = history::Filter {
+        statuses: args.status.clone(),
+        since: since_arg(args.since.as_deref()),
+        markers: args.markers.clone(),
+        min_count: args.min_count,
+        branch: None,
+    };
+    let rows = if history::exists(&root) {
+        history::open(&root).and_then(|conn| history::query(&conn, &filter))
+    } else {
+        Ok(Vec::new())
+    }
+    .unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    });
+    match args.format {
+        HistoryFormat::Json => println!("{}", history::render_json(&rows)),
+        HistoryFormat::Table if rows.is_empty() => eprintln!("No matching results in history."),
+        HistoryFormat::Table => print!("{}", history::render_table(&rows)),
+    }
+}
+
+pub fn stats(args: &StatsArgs) {
+    let root = resolve_root(args.root.as_deref());
+    if args.owners {
+        return stats_owners(&root, args);
+    }
+    if !history::exists(&root) {
+        eprintln!("No run history yet; it is recorded by `watcher-knight run`.");
+        return;
+    }
+    let conn = history::open(&root).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    });
+    let since = since_arg(args.since.as_deref());
+    if !args.trends {
+        let summary = history::summary(&conn, since).unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            process::exit(exit_code::BACKEND_ERROR);
+        });
+        match args.format {
+            HistoryFormat::Json => println!("{}", serde_json::to_string_pretty(&summary).unwrap()),
+            HistoryFormat::Table => {
+                println!("Runs:     {}", summary.runs);
+                if summary.runs > 0 {
+                    println!(
+                        "Passed:   {} ({:.0}%)",
+                        summary.passed,
+                        summary.passed as f64 * 100.0 / summary.runs as f64
+                    );
+                }
+                if let (Some(first), Some(last)) = (&summary.first_run, &summary.last_run) {
+                    println!("Period:   {first} .. {last}");
+                }
+            }
+        }
+        return;
+    }
+
+    let filter = history::Filter {
+        statuses: Vec::new(),
+        since,
+        markers: args.markers.clone(),
+        min_count: args.min_runs,
+        branch: None,
+    };
+    let trends = history::trends(&conn, &filter).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    });
+    match args.format {
+        HistoryFormat::Json => println!("{}", history::render_trends_json(&trends)),
+        HistoryFormat::Table if trends.is_empty() => eprintln!("No matching results in history."),
+        HistoryFormat::Table => {
+            print!(
+                "{}",
+                history::render_trends_table(&trends, io::stdout().is_terminal())
+            );
+            let chronic = trends.iter().filter(|t| t.chronic).count();
+            if chronic > 0 {
+                eprintln!(
+                    "\n{chronic} watcher(s) are chronically red; fix the code or delete the invariant."
+                );
+            }
+        }
+    }
+}
+
+/// `stats --owners`: read from git, not from the run history.
+fn stats_owners(root: &Path, args: &StatsArgs) {
+    if git_output(root, &["rev-parse", "--git-dir"]).is_none() {
+        eprintln!("Error: --owners needs a git repository");
+        process::exit(exit_code::CONFIG_ERROR);
+    }
+    let markers = load_markers(root, &load_config(root), &[]);
+    let since = since_arg(Some(
+        args.since.as_deref().unwrap_or(owners::DEFAULT_RECENT),
+    ));
+    let orphans = owners::find(
+        &markers,
+        |m| owners::blame(root, m),
+        &owners::last_commits(root),
+        since.unwrap(),
+    );
+    match args.format {
+        HistoryFormat::Json => println!("{}", owners::render_json(&orphans)),
+        HistoryFormat::Table if orphans.is_empty() => {
+            eprintln!("Every watcher's author has committed recently.")
+        }
+        HistoryFormat::Table => {
+            print!("{}", owners::render_table(&orphans));
+            eprintln!(
+                "\n{} watcher(s) have no recent author; reassign them with an `owner` option or delete them.",
+                orphans.len()
+            );
+        }
+    }
+}
+
+pub fn badge(format: BadgeFormat, output: Option<&Path>, root_arg: Option<&Path>) {
+    let root = resolve_root(root_arg);
+    let latest = if history::exists(&root) {
+        history::open(&root).and_then(|conn| history::latest_run(&conn))
+    } else {
+        Ok(None)
+    }
+    .unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    });
+    let rendered = badge::render(&badge::for_run(latest.as_ref()), format);
+    match output {
+        Some(path) => {
+            if let Err(e) = fs::write(path, rendered) {
+                eprintln!("Error: cannot write {}: {e}", path.display());
+                process::exit(exit_code::BACKEND_ERROR);
+            }
+        }
+        None => print!("{rendered}"),
+    }
+}
+
+pub fn diff_results(old: &Path, new: &Path, format: DiffFormat) {
+    let load = |path: &Path| {
+        results_diff::load(path).unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            process::exit(exit_code::CONFIG_ERROR);
+        })
+    };
+    let diff = results_diff::diff(&load(old), &load(new));
+    match format {
+        DiffFormat::Text => print!("{}", results_diff::render_text(&diff)),
+        DiffFormat::Json => println!("{}", results_diff::render_json(&diff)),
+    }
+    if !diff.regressions.is_empty() {
+        process::exit(1);
+    }
+}
+
+pub fn snapshot(action: &SnapshotCommand) {
+    match action {
+        SnapshotCommand::Create { root } => {
+            let root = resolve_root(root.as_deref());
+            match snapshot::create(&root) {
+                Ok(id) => println!("{id}"),
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    process::exit(exit_code::BACKEND_ERROR);
+                }
+            }
+        }
+        SnapshotCommand::List { root } => {
+            let root = resolve_root(root.as_deref());
+            let snapshots = snapshot::list(&root).unwrap_or_else(|e| {
+                eprintln!("Error: {e}");
+                process::exit(exit_code::BACKEND_ERROR);
+            });
+            if snapshots.is_empty() {
+                eprintln!("No snapshots yet; create one with `watcher-knight snapshot create`.");
+            }
+            print!("{}", snapshot::render_list(&snapshots));
+        }
+    }
+}
+
+/// Unix time `--since AGE` ago.
+fn since_arg(age: Option<&str>) -> Option<i64> {
+    age.map(|age| {
+        history::parse_age(age)
+            .map(|secs| clock::now() - secs)
+            .unwrap_or_else(|e| {
+                eprintln!("Error: {e}");
+                process::exit(exit_code::CONFIG_ERROR);
+            })
+    })
+}
+
+pub fn waive(marker_name: &str, until: &str, reason: &str, root_arg: Option<&Path>) {
+    let root = resolve_root(root_arg);
+
+    let until_date = clock::parse_date(until).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::CONFIG_ERROR);
+    });
+    if reason.trim().is_empty() {
+        eprintln!("Error: a waiver needs a --reason");
+        process::exit(exit_code::CONFIG_ERROR);
+    }
+    if until_date < clock::today() {
+        eprintln!("\x1b[33m[WARNING] {until} is in the past; the waiver is already expired\x1b[0m");
+    }
+    let (markers, _) = scan_markers(&root);
+    if !marker_name.starts_with(protected::WAIVER_PREFIX)
+        && !markers.iter().any(|m| m.name == marker_name)
+    {
+        eprintln!("\x1b[33m[WARNING] no watcher named `{marker_name}` was found\x1b[0m");
+    }
+
+    let mut waivers = waivers::load_waivers(&root).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::CONFIG_ERROR);
+    });
+    waivers.upsert(waivers::Waiver {
+        marker: marker_name.to_string(),
+        until: until.to_string(),
+        reason: reason.trim().to_string(),
+    });
+    if let Err(e) = waivers::save_waivers(&root, &waivers) {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    }
+    eprintln!(
+        "waived {marker_name} until {until} (recorded in {})",
+        waivers::WAIVERS_FILE
+    );
+}
+
+pub fn ack(marker_name: &str, commit: &str, root_arg: Option<&Path>) {
+    let root = resolve_root(root_arg);
+
+    let markers = load_markers(&root, &load_config(&root), &[]);
+    let matching: Vec<&marker::Marker> = markers.iter().filter(|m| m.name == marker_name).collect();
+    if matching.is_empty() {
+        eprintln!("Error: no watcher named `{marker_name}` was found");
+        process::exit(exit_code::CONFIG_ERROR);
+    }
+
+    let sha = git_output(
+        &root,
+        &["rev-parse", "--verify", &format!("{commit}^{{commit}}")],
+    )
+    .unwrap_or_else(|| {
+        eprintln!("Error: `{commit}` is not a commit in this repository");
+        process::exit(exit_code::CONFIG_ERROR);
+    });
+    let by = git_output(&root, &["config", "user.name"])
+        .or_else(|| std::env::var("USER").ok())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let mut acks = acks::load_acks(&root).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::CONFIG_ERROR);
+    });
+    let tree = acks::files_at_commit(&root, &sha);
+    let tree_id = acks::tree_at_commit(&root, &sha);
+    let reader = acks::CommitReader::new(&root, &sha);
+    for m in &matching {
+        let files = m.watched_files_in(&tree);
+        acks.upsert(acks::Ack {
+            marker: marker_name.to_string(),
+            commit: sha.clone(),
+            fingerprint: acks::failure_fingerprint(m, &files, tree_id.as_deref(), |f| {
+                reader.read(f)
+            }),
+            by: by.clone(),
+            date: clock::today().to_string(),
+        });
+    }
+    if let Err(e) = acks::save_acks(&root, &acks) {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    }
+    eprintln!(
+        "acknowledged {marker_name} at {} (recorded in {})",
+        &sha[..sha.len().min(12)],
+        acks::ACKS_FILE
+    );
+}
+
+pub fn rename(old: &str, new: &str, root_arg: Option<&Path>) {
+    let root = resolve_root(root_arg);
+    let fail = |msg: String| -> ! {
+        eprintln!("Error: {msg}");
+        process::exit(exit_code::CONFIG_ERROR);
+    };
+    if !rename::is_valid_name(new) {
+        fail(format!(
+            "`{new}` is not a valid watcher name (use letters, digits, `-` and `_`)"
+        ));
+    }
+
+    let markers = load_markers(&root, &load_config(&root), &[]);
+    let matching: Vec<&marker::Marker> = markers.iter().filter(|m| m.name == old).collect();
+    if matching.is_empty() {
+        fail(format!("no watcher named `{old}` was found"));
+    }
+    if let Some(m) = markers.iter().find(|m| m.name == new) {
+        fail(format!(
+            "a watcher named `{new}` already exists at {}:{}",
+            m.rel_path, m.line
+        ));
+    }
+
+    // Every change is computed before anything is written.
+    let mut files = Vec::new();
+    let sources: BTreeSet<&str> = matching.iter().map(|m| m.rel_path.as_str()).collect();
+    for rel_path in sources {
+        let path = root.join(rel_path);
+        let renamed = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| rename::rename_in_source(&contents, rel_path, old, new))
+            .unwrap_or_else(|| {
+                fail(format!(
+                    "`{old}` in {rel_path} is not a marker in a source file under {}; \
+                     rename it where it is defined",
+                    root.display()
+                ))
+            });
+        files.push((path, renamed));
+    }
+    let source_files = files.len();
+
+    let mut waivers = waivers::load_waivers(&root).unwrap_or_else(|e| fail(e));
+    let mut moved_waivers = 0;
+    for w in waivers.waivers.iter_mut().filter(|w| w.marker == old) {
+        w.marker = new.to_string();
+        moved_waivers += 1;
+    }
+    if moved_waivers > 0 {
+        let data = toml::to_string(&waivers).unwrap();
+        files.push((root.join(waivers::WAIVERS_FILE), data));
+    }
+
+    let mut acks = acks::load_acks(&root).unwrap_or_else(|e| fail(e));
+    let moved_acks = rename::rename_acks(&root, &mut acks, &matching, new);
+    if moved_acks > 0 {
+        files.push((root.join(acks::ACKS_FILE), toml::to_string(&acks).unwrap()));
+    }
+
+    rename::write_all(&files).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    });
+
+    let mut moved_results = 0;
+    if history::exists(&root) {
+        let fingerprints: Vec<(String, String)> = matching
+            .iter()
+            .map(|m| {
+                (
+                    marker::fingerprint(m),
+                    marker::fingerprint(&rename::renamed(m, new)),
+                )
+            })
+            .collect();
+        match history::open(&root)
+            .and_then(|mut conn| history::rename(&mut conn, old, new, &fingerprints))
+        {
+            Ok(n) => moved_results = n,
+            Err(e) => eprintln!("\x1b[33m[WARNING] run history still uses `{old}`: {e}\x1b[0m"),
+        }
+    }
+
+    eprintln!(
+        "renamed `{old}` to `{new}` in {source_files} files \
+         ({moved_waivers} waivers, {moved_acks} acks, {moved_results} history results)"
+    );
+}
+
+pub fn lint(policies: &[PathBuf], root_arg: Option<&Path>) {
+    let root = resolve_root(root_arg);
+
+    let config = load_config(&root);
+    let (markers, errors) = load_markers_with_errors(&root, &config, policies);
+    let checkers = plugins::discover(&config.checkers, &root, &markers);
+    // Bad dates are lint issues here rather than errors.
+    let waivers = waivers::read_waivers(&root).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::CONFIG_ERROR);
+    });
+
+    let mut issues = lint::lint_parse_errors(&errors);
+    issues.extend(lint::lint_tag_style(&markers, &root));
+    issues.extend(lint::lint_waivers(
+        &waivers,
+        &markers,
+        &config.protected_paths,
+        clock::today(),
+    ));
+    issues.extend(lint::lint_checkers(&markers, &checkers));
+    issues.extend(lint::lint_when_conditions(&markers));
+    issues.extend(lint::lint_assertions(&markers, &root));
+    issues.extend(lint::lint_strategies(&markers, &config.commands));
+    issues.extend(lint::lint_file_entries(&markers, &root));
+    issues.extend(lint::lint_instructions(&markers, config.lint.min_words));
+    issues.extend(lint::lint_unscoped(&markers, &repo_files(&root)));
+    issues.extend(lint::lint_duplicates(&markers));
+    issues.extend(lint::lint_references(&markers));
+    issues.extend(lint::lint_config(&config.lint));
+    let issues = lint::without_disabled(issues, &config.lint);
+
+    for issue in &issues {
+        println!("{issue}");
+    }
+    if issues.is_empty() {
+        eprintln!("lint: {} watchers, no issues", markers.len());
+    } else {
+        eprintln!("lint: {} issues", issues.len());
+        process::exit(exit_code::MALFORMED_MARKERS);
+    }
+}
+
+pub fn fmt(check: bool, root_arg: Option<&Path>) {
+    let root = resolve_root(root_arg);
+    let (markers, _) = scan_markers(&root);
+    let files: BTreeSet<&str> = markers.iter().map(|m| m.rel_path.as_str()).collect();
+
+    let mut changed = 0;
+    for file in &files {
+        let path = root.join(file);
+        let contents = fs::read_to_string(&path).unwrap_or_else(|e| {
+            eprintln!("Error: cannot read {file}: {e}");
+            process::exit(exit_code::BACKEND_ERROR);
+        });
+        let formatted = formatter::format_source(&contents, file);
+        if formatted == contents {
+            continue;
+        }
+        changed += 1;
+        if check {
+            println!("{file}");
+        } else {
+            fs::write(&path, formatted).unwrap_or_else(|e| {
+                eprintln!("Error: cannot write {file}: {e}");
+                process::exit(exit_code::BACKEND_ERROR);
+            });
+            eprintln!("formatted {file}");
+        }
+    }
+
+    if changed == 0 {
+        eprintln!("fmt: {} files with watchers, all formatted", files.len());
+    } else if check {
+        eprintln!("fmt: {changed} files need formatting; run `watcher-knight fmt`");
+        process::exit(1);
+    }
+}
+
+pub fn prune(base: Option<&str>, dry_run: bool, root_arg: Option<&Path>) {
+    let root = resolve_root(root_arg);
+    let markers = load_markers(&root, &load_config(&root), &[]);
+    let fail = |msg: String| -> ! {
+        eprintln!("Error: {msg}");
+        process::exit(exit_code::CONFIG_ERROR);
+    };
+
+    let base = base.map(str::to_string).or_else(|| {
+        ["origin/main", "origin/master"]
+            .into_iter()
+            .find(|b| git_output(&root, &["rev-parse", "--verify", b]).is_some())
+            .map(str::to_string)
+    });
+    let upstream = base.as_ref().and_then(|base| {
+        let fork = git_output(&root, &["merge-base", "HEAD", base])?;
+        Some(prune::Upstream {
+            base: base.clone(),
+            at_fork: acks::files_at_commit(&root, &fork).into_iter().collect(),
+            at_base: acks::files_at_commit(&root, base).into_iter().collect(),
+        })
+    });
+    if let Some(base) = &base
+        && upstream.is_none()
+        && git_output(&root, &["rev-parse", "--git-dir"]).is_some()
+    {
+        fail(format!("cannot find where HEAD forked from `{base}`"));
+    }
+
+    let findings = prune::find(&markers, &root, upstream.as_ref());
+    if findings.is_empty() {
+        eprintln!("prune: {} watchers, none dead", markers.len());
+        return;
+    }
+    if dry_run || !io::stdin().is_terminal() {
+        for f in &findings {
+            let m = f.marker;
+            println!(
+                "{}:{}: {}: {}",
+                m.rel_path,
+                m.line,
+                m.name,
+                f.reason.describe()
+            );
+        }
+        eprintln!(
+            "prune: {} dead watchers; run `watcher-knight prune` in a terminal to delete or \
+             rewrite them",
+            findings.len()
+        );
+        process::exit(1);
+    }
+
+    let actions = prune::ask(&findings, &mut io::stdin().lock(), &mut io::stdout())
+        .unwrap_or_else(|e| fail(format!("cannot read the answer: {e}")));
+    let mut by_file: BTreeMap<&str, Vec<(usize, &prune::Action)>> = BTreeMap::new();
+    for (i, action) in &actions {
+        let m = findings[*i].marker;
+        by_file
+            .entry(m.rel_path.as_str())
+            .or_default()
+            .push((m.line, action));
+    }
+    let mut files = Vec::new();
+    let mut applied = 0;
+    for (rel_path, actions) in by_file {
+        let path = root.join(rel_path);
+        let pruned = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| prune::apply(&contents, rel_path, &actions));
+        match pruned {
+            Ok(contents) => {
+                files.push((path, contents));
+                applied += actions.len();
+            }
+            // Watchers from the config or a policy file have no tag to edit.
+            Err(e) => eprintln!("\x1b[33m[WARNING] {rel_path} left as it is: {e}\x1b[0m"),
+        }
+    }
+    rename::write_all(&files).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    });
+    eprintln!(
+        "prune: {applied} watchers deleted or rewritten in {} files",
+        files.len()
+    );
+}
+
+pub fn list(format: ListFormat, full: bool, policies: &[PathBuf], root_arg: Option<&Path>) {
+    let root = resolve_root(root_arg);
+    let markers = load_markers(&root, &load_config(&root), policies);
+    let inventory = inventory::build_inventory(&markers, full);
+    print!("{}", inventory::render(&inventory, format));
+}
+
+pub fn map(path: Option<&Path>, depth: Option<usize>, policies: &[PathBuf]) {
+    let root = resolve_root(None);
+    let rel = match path {
+        None => String::new(),
+        Some(path) => {
+            let abs = resolve_root(Some(path));
+            let root = root.canonicalize().unwrap_or_else(|_| root.clone());
+            match abs.strip_prefix(&root) {
+                Ok(rel) => platform::slash_path(rel),
+                Err(_) => {
+                    eprintln!(
+                        "Error: `{}` is outside the repository at {}",
+                        path.display(),
+                        root.display()
+                    );
+                    process::exit(exit_code::CONFIG_ERROR);
+                }
+            }
+        }
+    };
+    let markers = load_markers(&root, &load_config(&root), policies);
+    let tree = coverage::build(&repo_files(&root), &markers);
+    let Some(node) = coverage::subtree(&tree, &rel) else {
+        eprintln!("No files under `{rel}`.");
+        return;
+    };
+    let label = if rel.is_empty() { "." } else { &rel };
+    print!("{}", coverage::render(node, label, depth));
+
+    let unscoped: Vec<&str> = markers
+        .iter()
+        .filter(|m| m.files.is_empty())
+        .map(|m| m.name.as_str())
+        .collect();
+    if !unscoped.is_empty() {
+        println!(
+            "\n{} watcher(s) without a files scope: {}",
+            unscoped.len(),
+            unscoped.join(", ")
+        );
+    }
+}
+
+/// Repo-relative paths of the files tracked by git, or of every file under
+/// `root` outside a git repository.
+pub fn repo_files(root: &Path) -> Vec<String> {
+    if let Some(files) = git_output(root, &["ls-files", "-z"]) {
+        return files
+            .split('\0')
+            .filter(|f| !f.is_empty())
+            .map(str::to_string)
+            .collect();
+    }
+    WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name();
+            !vcs::is_metadata_dir(name) && name != ".watcher_knight"
+        })
+        .flatten()
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.path().strip_prefix(root).ok().map(platform::slash_path))
+        .collect()
+}
+
+pub fn completions(shell: completions::Shell) {
+    if let Err(e) = completions::write_registration(shell, &mut std::io::stdout()) {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    }
+}
+
+pub fn man() {
+    if let Err(e) = manpage::render(&mut std::io::stdout()) {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    }
+}
+
+pub fn self_update(check: bool, insecure: bool) {
+    if let Err(e) = selfupdate::self_update(check, insecure) {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    }
+}
+
+/// What a worker lets its clients do (`worker --allow-tools`,
+/// `--allow-models`, `--insecure`).
+pub struct WorkerAllow {
+    pub tools: Vec<String>,
+    pub models: Vec<String>,
+    pub insecure: bool,
+}
+
+pub fn worker(listen: &str, max_jobs: usize, allow: WorkerAllow, root_arg: Option<&Path>) {
+    let root = resolve_root(root_arg);
+    let token = std::env::var(remote::TOKEN_VAR).unwrap_or_default();
+    if token.is_empty() {
+        eprintln!(
+            "Error: {} must be set to the token shared with clients",
+            remote::TOKEN_VAR
+        );
+        process::exit(exit_code::CONFIG_ERROR);
+    }
+    let claude = doctor::check_claude();
+    if claude.status == doctor::Status::Fail {
+        eprintln!("Error: {}", claude.detail);
+        process::exit(exit_code::BACKEND_ERROR);
+    }
+    // claude runs in the current directory.
+    if let Err(e) = std::env::set_current_dir(&root) {
+        eprintln!("Error: cannot enter {}: {e}", root.display());
+        process::exit(exit_code::BACKEND_ERROR);
+    }
+    let listener = std::net::TcpListener::bind(listen).unwrap_or_else(|e| {
+        eprintln!("Error: cannot listen on {listen}: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    });
+    // The job API is plain HTTP: the token and prompts full of source code
+    // would cross the network in the clear.
+    let loopback = listener.local_addr().is_ok_and(|a| a.ip().is_loopback());
+    if !loopback && !allow.insecure {
+        eprintln!(
+            "Error: refusing to serve plain HTTP on {listen}. Listen on a loopback address \
+             behind a TLS proxy or SSH tunnel, or pass --insecure on a trusted network"
+        );
+        process::exit(exit_code::CONFIG_ERROR);
+    }
+    let commit = git_output(&root, &["rev-parse", "HEAD"]);
+    eprintln!(
+        "watcher-knight worker listening on {listen} ({}, up to {max_jobs} jobs)",
+        commit.as_deref().unwrap_or("not a git checkout")
+    );
+    let state = remote::WorkerState {
+        token,
+        commit,
+        max_jobs,
+        tools: allow.tools,
+        models: allow.models,
+    };
+    let run: std::sync::Arc<remote::Runner> = std::sync::Arc::new(|job: &remote::JobRequest| {
+        claude::invoke(
+            &format!("watcher {}", job.name),
+            &job.prompt,
+            &job.model,
+            &job.tools,
+        )
+        .map(|reply| reply.text)
+    });
+    if let Err(e) = remote::serve(listener, state, run) {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    }
+}
+
+pub fn doctor(root_arg: Option<&Path>) {
+    let root = resolve_root(root_arg);
+    let (markers, errors) = scan_markers(&root);
+    let claude_dir = doctor::claude_config_dir();
+    let mut checks = vec![
+        doctor::check_git_repo(&root),
+        doctor::check_head_parent(&root),
+    ];
+    if jj::is_repo(&root) {
+        checks.push(doctor::check_jj(&root));
+    }
+    checks.extend([
+        doctor::check_claude(),
+        doctor::check_auth(|v| std::env::var(v).ok(), claude_dir.as_deref()),
+        doctor::check_curl(),
+        doctor::check_config(&root),
+        doctor::check_markers(&markers, &errors),
+    ]);
+    for check in &checks {
+        println!("{check}");
+    }
+    let failed = checks
+        .iter()
+        .filter(|c| c.status == doctor::Status::Fail)
+        .count();
+    if failed > 0 {
+        eprintln!("\ndoctor: {failed} checks failed");
+        process::exit(1);
+    }
+}
 
-        /// Skip cache, force all watchers to run fresh
-        #[arg(long)]
-        no_cache: bool,
-    },
+pub fn rpc(model: &str, policies: &[PathBuf], root_arg: Option<&Path>) {
+    let root = resolve_root(root_arg);
+    let config = load_config(&root);
+    let redactor = build_redactor(&config);
+    rpc::run(&root, config, model, policies, redactor);
 }
 
-pub fn run(model: &str, diff: Option<&str>, no_cache: bool, root_arg: Option<&Path>) {
+pub fn suggest(model: &str, file: Option<&Path>, diff: Option<&str>, root_arg: Option<&Path>) {
     let root = resolve_root(root_arg);
+    let redactor = build_redactor(&load_config(&root));
+
+    let (prompt_text, default_target) = match file {
+        Some(path) => {
+            let contents = fs::read_to_string(path).unwrap_or_else(|e| {
+                eprintln!("Error: cannot read `{}`: {e}", path.display());
+                process::exit(exit_code::CONFIG_ERROR);
+            });
+            let rel_path = path
+                .canonicalize()
+                .ok()
+                .and_then(|p| p.strip_prefix(&root).ok().map(Path::to_path_buf))
+                .unwrap_or_else(|| path.to_path_buf());
+            let rel_path = platform::slash_path(&rel_path);
+            let contents = redact_for_prompt(&redactor, &contents, &rel_path);
+            let source = prompt::SuggestSource::File {
+                path: &rel_path,
+                contents: &contents,
+            };
+            (prompt::build_suggest_prompt(&source), Some(rel_path))
+        }
+        None => {
+            let vcs = vcs::for_root(&root);
+            let diff_ref = match diff {
+                Some(r) => r.to_string(),
+                None => resolve_diff_ref(&*vcs),
+            };
+            let diff = repo_diff(&*vcs, &diff_ref);
+            if diff.trim().is_empty() {
+                eprintln!("No changes since {diff_ref}. Nothing to suggest.");
+                return;
+            }
+            let diff = redact_for_prompt(&redactor, &diff, "diff");
+            let source = prompt::SuggestSource::Diff(&diff);
+            (prompt::build_suggest_prompt(&source), None)
+        }
+    };
+
+    eprintln!("asking {model} for marker suggestions...\n");
+    let text = claude::invoke("suggest", &prompt_text, model, "Read,Grep,Glob")
+        .unwrap_or_else(|e| {
+            eprintln!("Error: suggest failed: {e}");
+            process::exit(exit_code::BACKEND_ERROR);
+        })
+        .text;
+    let suggestions = claude::parse_suggestions(&text).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    });
+
+    if suggestions.is_empty() {
+        eprintln!("No suggestions.");
+        return;
+    }
+
+    for s in &suggestions {
+        let target = s.target.as_deref().or(default_target.as_deref());
+        let comment_prefix = marker::comment_prefix_for_path(target.unwrap_or(""));
+        match target {
+            Some(t) => println!("---- {} ({t}) ----", s.name),
+            None => println!("---- {} ----", s.name),
+        }
+        println!();
+        println!(
+            "{}",
+            marker::format_marker(&s.name, &s.files, &s.instruction, comment_prefix)
+        );
+        println!();
+    }
+}
 
-    let mut markers = collect_markers(&root);
+pub fn review_markers(model: &str, policies: &[PathBuf], root_arg: Option<&Path>) {
+    let root = resolve_root(root_arg);
+    let markers = load_markers(&root, &load_config(&root), policies);
     if markers.is_empty() {
-        eprintln!("No watchers found.");
+        eprintln!("No watchers to review.");
         return;
     }
 
-    if let Some(diff_ref) = diff {
-        run_diff_mode(&root, &mut markers, diff_ref, model);
-    } else {
-        run_cache_mode(&root, &markers, model, no_cache);
+    eprintln!("asking {model} to review {} watchers...\n", markers.len());
+    let prompt_text = prompt::build_review_prompt(&markers);
+    let text = claude::invoke("review-markers", &prompt_text, model, "Read,Grep,Glob")
+        .unwrap_or_else(|e| {
+            eprintln!("Error: review-markers failed: {e}");
+            process::exit(exit_code::BACKEND_ERROR);
+        })
+        .text;
+    let reviews = claude::parse_reviews(&text).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    });
+
+    if reviews.is_empty() {
+        eprintln!("No issues found.");
+        return;
+    }
+
+    for r in &reviews {
+        let marker = markers.iter().find(|m| {
+            m.name == r.name
+                && r.location
+                    .as_ref()
+                    .is_none_or(|l| *l == format!("{}:{}", m.rel_path, m.line))
+        });
+        let location = marker
+            .map(|m| format!("{}:{}", m.rel_path, m.line))
+            .or_else(|| r.location.clone());
+        match location {
+            Some(l) => println!("---- {} ({l}) ----", r.name),
+            None => println!("---- {} ----", r.name),
+        }
+        println!();
+        if let Some(ambiguity) = &r.ambiguity {
+            println!("Ambiguity: {ambiguity}");
+        }
+        if let Some(testability) = &r.testability {
+            println!("Testability: {testability}");
+        }
+        if !r.overlaps.is_empty() {
+            println!("Overlaps: {}", r.overlaps.join(", "));
+        }
+        println!();
+        println!("Suggested instruction:");
+        for line in r.rewrite.lines() {
+            println!("    {line}");
+        }
+        println!();
+    }
+    eprintln!(
+        "{} of {} watchers could be sharper",
+        reviews.len(),
+        markers.len()
+    );
+}
+
+pub fn classify(
+    ai: bool,
+    model: &str,
+    format: ClassifyFormat,
+    policies: &[PathBuf],
+    root_arg: Option<&Path>,
+) {
+    let root = resolve_root(root_arg);
+    let markers = load_markers(&root, &load_config(&root), policies);
+    let mut suggestions = classify::load_suggestions(&root);
+
+    let unasked = classify::unasked(&markers, &suggestions);
+    if ai && !unasked.is_empty() {
+        eprintln!("asking {model} about {} watchers...\n", unasked.len());
+        let prompt_text = prompt::build_classify_prompt(&unasked);
+        let text = claude::invoke("classify", &prompt_text, model, "Read,Grep,Glob")
+            .unwrap_or_else(|e| {
+                eprintln!("Error: classify failed: {e}");
+                process::exit(exit_code::BACKEND_ERROR);
+            })
+            .text;
+        let answers = claude::parse_classifications(&text).unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            process::exit(exit_code::BACKEND_ERROR);
+        });
+        for m in unasked {
+            let location = format!("{}:{}", m.rel_path, m.line);
+            let answer = answers
+                .iter()
+                .find(|a| a.name == m.name && a.location.as_ref().is_none_or(|l| *l == location));
+            let expr = answer.and_then(|a| a.assert.clone()).filter(|expr| {
+                classify::validate(expr, m, &root)
+                    .inspect_err(|e| {
+                        eprintln!(
+                            "\x1b[33m[WARNING] {}: ignoring suggested assert: {e}\x1b[0m",
+                            m.name
+                        );
+                    })
+                    .is_ok()
+            });
+            suggestions.insert(marker::fingerprint(m), expr);
+        }
+        classify::save_suggestions(&root, &suggestions);
     }
+
+    let report = classify::report(classify::classify(&markers, &suggestions));
+    print!("{}", classify::render(&report, format));
+}
+
+#[derive(Args)]
+pub struct GateArgs {
+    /// Repository to check (default: git repo root, or cwd)
+    #[arg()]
+    pub root: Option<PathBuf>,
+
+    /// Commit to check, e.g. the SHA being deployed
+    #[arg(long = "ref", value_name = "REV", default_value = "HEAD")]
+    pub git_ref: String,
+
+    /// Fail unless the commit has a valid attestation, instead of validating
+    /// it again
+    #[arg(long)]
+    pub require_attestation: bool,
+
+    /// AI model to use when validating again [haiku, sonnet, opus]
+    #[arg(long, default_value = "sonnet")]
+    pub model: String,
+
+    /// Policy YAML file applied by the attested run (may be repeated)
+    #[arg(long = "policy", value_name = "FILE")]
+    pub policies: Vec<PathBuf>,
+}
+
+/// Parse `--deadline`: seconds, optionally suffixed `s`, `m` or `h`.
+fn parse_deadline(deadline: &str) -> Result<Duration, String> {
+    shard::parse_cost(deadline)
+        .map(Duration::from_secs)
+        .map_err(|_| format!("invalid duration `{deadline}` (expected e.g. 45s, 10m or 1h)"))
 }
 
 /// Determine the root directory to scan for markers.
 ///
 /// If an explicit path is given, canonicalize and use it directly.
 /// Otherwise fall back to the git repo root, then the current working directory.
-fn resolve_root(explicit: Option<&Path>) -> PathBuf {
+pub fn resolve_root(explicit: Option<&Path>) -> PathBuf {
     if let Some(path) = explicit {
         match path.canonicalize() {
             Ok(p) if p.is_dir() => return p,
             Ok(p) => {
                 eprintln!("Error: `{}` is not a directory", p.display(),);
-                process::exit(1);
+                process::exit(exit_code::CONFIG_ERROR);
             }
             Err(e) => {
                 eprintln!("Error: cannot resolve path `{}`: {e}", path.display());
-                process::exit(1);
+                process::exit(exit_code::CONFIG_ERROR);
             }
         }
     }
 
-    // Try git repo first, fall back to cwd.
-    if let Ok(repo) = git2::Repository::discover(".")
-        && let Some(workdir) = repo.workdir()
-    {
-        return workdir.to_path_buf();
+    // Try the enclosing checkout first, fall back to cwd.
+    if let Some(root) = vcs::discover(Path::new(".")) {
+        return root;
     }
     std::env::current_dir().unwrap_or_else(|e| {
         eprintln!("Error: cannot determine working directory: {e}");
-        process::exit(1);
+        process::exit(exit_code::CONFIG_ERROR);
+    })
+}
+
+/// Load the config at `root`, and turn on privacy mode if it asks for it.
+fn load_config(root: &Path) -> config::Config {
+    let config = config::load_config(root).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::CONFIG_ERROR);
+    });
+    if let Some(privacy) = &config.privacy
+        && let Err(e) = privacy::enable(privacy, root)
+    {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::CONFIG_ERROR);
+    }
+    config
+}
+
+/// File or update tickets for watchers that keep failing on the ticket
+/// branch. Problems are warnings: ticketing must never break a run.
+fn file_tickets(
+    root: &Path,
+    config: &TicketConfig,
+    results: &[claude::WatcherResult],
+    markers: &[marker::Marker],
+    commit: Option<&str>,
+) {
+    let warn = |e: String| eprintln!("\x1b[33m[WARNING] Tickets not filed: {e}\x1b[0m");
+    let filter = history::Filter {
+        branch: Some(config.branch.clone()),
+        ..history::Filter::default()
+    };
+    let trends = match history::open(root).and_then(|conn| history::trends(&conn, &filter)) {
+        Ok(trends) => trends,
+        Err(e) => return warn(e),
+    };
+    let entries: Vec<_> = results.iter().map(|r| report::entry(r, markers)).collect();
+    let failures = tickets::persistent_failures(&entries, &trends, config);
+    if failures.is_empty() {
+        return;
+    }
+    let tracker = match Tracker::new(config, |v| std::env::var(v).ok()) {
+        Ok(tracker) => tracker,
+        Err(e) => return warn(e),
+    };
+    for failure in &failures {
+        let name = &failure.entry.name;
+        match tracker.file(failure, commit) {
+            Ok(tickets::Action::Filed(id)) => {
+                println!("\x1b[36m[TICKET]\x1b[0m filed {id} for `{name}`")
+            }
+            Ok(tickets::Action::Updated(id)) => {
+                println!("\x1b[36m[TICKET]\x1b[0m updated {id} for `{name}`")
+            }
+            Ok(tickets::Action::Unchanged(_)) => {}
+            Err(e) => eprintln!("\x1b[33m[WARNING] Ticket for `{name}` not filed: {e}\x1b[0m"),
+        }
+    }
+}
+
+/// List the watchers left inconclusive by the deadline or token budget, as
+/// `--inconclusive` asks. Returns false when they fail the run.
+fn apply_inconclusive_policy(results: &[claude::WatcherResult], args: &RunArgs) -> bool {
+    let mut inconclusive = results.iter().filter(|r| r.inconclusive).peekable();
+    if args.inconclusive == OnInconclusive::Pass || inconclusive.peek().is_none() {
+        return true;
+    }
+    let color = match args.inconclusive {
+        OnInconclusive::Fail => "\x1b[31m",
+        _ => "\x1b[33m",
+    };
+    let mut text = format!("\n{color}==== INCONCLUSIVE ====\x1b[0m\n\n");
+    for r in inconclusive {
+        let why = r.skipped.as_deref().unwrap_or_default();
+        text.push_str(&format!("{} ({}): {why}\n", r.name, r.location));
+    }
+    // Compact output is for problem matchers; keep it to findings.
+    match args.format {
+        RunFormat::Text => print!("{text}"),
+        RunFormat::Compact => eprint!("{text}"),
+    }
+    args.inconclusive != OnInconclusive::Fail
+}
+
+/// `run --attest`: record that every watcher passed at `HEAD`. A run with
+/// uncommitted changes, or with watchers that were not run, attests
+/// nothing.
+fn attest(
+    root: &Path,
+    results: &[claude::WatcherResult],
+    provenance: &report::Provenance,
+    passed: bool,
+) {
+    let warn = |e: &str| eprintln!("\x1b[33m[WARNING] Not attested: {e}\x1b[0m");
+    if !passed {
+        return;
+    }
+    let Some(commit) = &provenance.commit else {
+        return warn("no commit is checked out");
+    };
+    // Empty output reads as `None`: a clean checkout.
+    if git_output(root, &["status", "--porcelain", "--untracked-files=no"]).is_some() {
+        return warn("the checkout has uncommitted changes");
+    }
+    if results.iter().any(|r| r.skipped.is_some() || r.errored) {
+        return warn("some watchers were not run");
+    }
+    let Some(key) = attestation::key_from_env() else {
+        return warn(&format!(
+            "{} is not set to sign it with",
+            attestation::KEY_VAR
+        ));
+    };
+    let attestation = attestation::Attestation {
+        version: attestation::ATTESTATION_VERSION,
+        status: "passed",
+        attested_at: clock::now(),
+        provenance,
+    };
+    match attestation::record(root, commit, &attestation, &key) {
+        Ok(()) => println!(
+            "\x1b[36m[ATTESTED]\x1b[0m {} in {}",
+            &commit[..commit.len().min(12)],
+            attestation::NOTES_REF
+        ),
+        Err(e) => warn(&e),
+    }
+}
+
+/// `run --comment`: post the markdown report on the pull request the CI job
+/// builds. Failing to post only warns, like filing tickets.
+fn post_pr_comment(
+    results: &[claude::WatcherResult],
+    markers: &[marker::Marker],
+    narrative: Option<&str>,
+) {
+    let warn = |e: String| eprintln!("\x1b[33m[WARNING] PR comment not posted: {e}\x1b[0m");
+    let pr = match ci::detect(|v| std::env::var(v).ok()) {
+        Ok(Some(pr)) => pr,
+        Ok(None) => {
+            return warn(
+                "no pull request build of GitHub Actions, Bitbucket Pipelines or Azure Pipelines detected"
+                    .to_string(),
+            );
+        }
+        Err(e) => return warn(e),
+    };
+    match pr.post_comment(&report::to_markdown(results, markers, narrative)) {
+        Ok(()) => println!(
+            "\x1b[36m[COMMENT]\x1b[0m posted on {} pull request #{}",
+            pr.provider.name(),
+            pr.id
+        ),
+        Err(e) => warn(e),
+    }
+}
+
+/// Redact secrets from text that is about to be inlined into a prompt,
+/// reporting what was removed.
+/// The `--summarize` narrative of a run's failures, or `None` when nothing
+/// failed. A failed summary only warns: it never changes the run's outcome.
+fn summarize_failures(
+    results: &[claude::WatcherResult],
+    markers: &[marker::Marker],
+    diff: Option<&str>,
+    model: &str,
+    redactor: &Redactor,
+) -> Option<String> {
+    let failures: Vec<_> = results
+        .iter()
+        .filter(|r| r.is_failure())
+        .map(|r| (r, report::marker_for(r, markers)))
+        .collect();
+    if failures.is_empty() {
+        return None;
+    }
+    let diff = diff.map(|d| lockfiles::summarize(&redact_for_prompt(redactor, d, "diff")));
+    let prompt_text = prompt::build_summary_prompt(&failures, diff.as_deref());
+    eprintln!("asking {model} to summarize {} failures...", failures.len());
+    match claude::invoke("summary", &prompt_text, model, "Read,Grep,Glob") {
+        Ok(reply) if !reply.text.trim().is_empty() => Some(reply.text.trim().to_string()),
+        Ok(_) => None,
+        Err(e) => {
+            eprintln!("\x1b[33m[WARNING] No summary: {e}\x1b[0m");
+            None
+        }
+    }
+}
+
+pub fn redact_for_prompt(redactor: &Redactor, text: &str, what: &str) -> String {
+    let (redacted, redactions) = redactor.redact(text);
+    if !redactions.is_empty() {
+        eprintln!(
+            "\x1b[33m[REDACTED] {} in {what}\x1b[0m",
+            redact::summarize(&redactions)
+        );
+    }
+    redacted
+}
+
+fn build_redactor(config: &config::Config) -> Redactor {
+    Redactor::new(&config.redact_patterns).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::CONFIG_ERROR);
+    })
+}
+
+fn build_noise_filter(config: &config::Config) -> NoiseFilter {
+    NoiseFilter::new(&config.ignore_hunks_matching).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::CONFIG_ERROR);
     })
 }
 
-fn collect_markers(root: &Path) -> Vec<marker::Marker> {
+/// What is done to a diff before watchers are picked by it and prompted
+/// with it: secrets are redacted, then noise is dropped.
+struct DiffFilters<'a> {
+    redactor: &'a Redactor,
+    noise: &'a NoiseFilter,
+}
+
+/// Collect in-repo markers plus any invariants from policy files and the
+/// policy packs configured in `watcher-knight.toml`, printing parse warnings.
+fn load_markers(root: &Path, config: &config::Config, policies: &[PathBuf]) -> Vec<marker::Marker> {
+    let (markers, errors) = load_markers_with_errors(root, config, policies);
+    for err in &errors {
+        eprintln!("\x1b[33m[WARNING] {err}\x1b[0m");
+    }
+    markers
+}
+
+pub fn load_markers_with_errors(
+    root: &Path,
+    config: &config::Config,
+    policies: &[PathBuf],
+) -> (Vec<marker::Marker>, Vec<marker::ParseError>) {
+    let (mut markers, errors) = scan_markers(root);
+    if !config.markers.is_empty() {
+        match virtual_markers::instantiate(&config.markers, &repo_files(root), root) {
+            Ok(declared) => markers.extend(declared),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                process::exit(exit_code::CONFIG_ERROR);
+            }
+        }
+    }
+    match packs::load_packs(&config.policy_packs, root) {
+        Ok(pack_markers) => markers.extend(pack_markers),
+        Err(e) => {
+            eprintln!("Error: {e}");
+            process::exit(exit_code::CONFIG_ERROR);
+        }
+    }
+    for path in policies {
+        match policy::load_policy(path, root) {
+            Ok(policy_markers) => markers.extend(policy_markers),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                process::exit(exit_code::CONFIG_ERROR);
+            }
+        }
+    }
+    (markers, errors)
+}
+
+/// Walk `root` and parse every marker in it.
+pub fn scan_markers(root: &Path) -> (Vec<marker::Marker>, Vec<marker::ParseError>) {
+    // The config's tag keywords decide what opens a tag. Commands that read
+    // the config report it being invalid themselves.
+    if let Ok(config) = config::load_config(root) {
+        marker::register_tag_keywords(&config.tag_keywords);
+    }
     let mut markers = Vec::new();
     let mut all_errors = Vec::new();
+    let mut index = Index::load(root);
+    // Walking is what the loop takes beyond scanning the files.
+    let walk_start = Instant::now();
+    let mut scanning = Duration::ZERO;
     for entry in WalkDir::new(root).into_iter().filter_entry(|e| {
         let name = e.file_name();
-        name != ".git" && name != ".watcher_knight"
+        !vcs::is_metadata_dir(name) && name != ".watcher_knight"
     }) {
         let entry = match entry {
             Ok(e) if e.file_type().is_file() => e,
             _ => continue,
         };
-        let contents = match fs::read_to_string(entry.path()) {
-            Ok(c) => c,
-            Err(_) => continue,
+        let rel_path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let rel_path = platform::slash_path(rel_path);
+        let start = Instant::now();
+        let parsed = index.parse(entry.path(), &rel_path, root);
+        profile::record_file(&rel_path, start.elapsed());
+        scanning += start.elapsed();
+        // Unreadable and binary (non-UTF-8) files cannot hold markers.
+        let Ok((file_markers, file_errors)) = parsed else {
+            continue;
         };
-        let rel_path = entry
-            .path()
-            .strip_prefix(root)
-            .unwrap_or(entry.path())
-            .to_string_lossy()
-            .to_string();
-        let (file_markers, file_errors) = marker::parse_markers(&contents, &rel_path, root);
         markers.extend(file_markers);
         all_errors.extend(file_errors);
     }
-    for err in &all_errors {
-        eprintln!("\x1b[33m[WARNING] {err}\x1b[0m");
+    profile::record(Phase::Walk, walk_start.elapsed().saturating_sub(scanning));
+    index.save(root);
+    (markers, all_errors)
+}
+
+/// Report changes since `diff_ref` that break the path policies: changes
+/// under `protected_paths` that no passing or waived watcher covers, and
+/// files added under `require_marker_in` without a marker. Returns whether
+/// there were none. Without `results` (a shard, which sees only its own
+/// watchers' results) protected paths are not checked.
+fn check_path_policies(
+    config: &config::Config,
+    vcs: &dyn Vcs,
+    diff_ref: &str,
+    markers: &[marker::Marker],
+    results: Option<&[claude::WatcherResult]>,
+    suppressions: &Suppressions,
+) -> bool {
+    let mut issues = Vec::new();
+    if !config.protected_paths.is_empty()
+        && let Some(results) = results
+    {
+        let unguarded = protected::unguarded(
+            &config.protected_paths,
+            &repo_changed_files(vcs, diff_ref),
+            markers,
+            results,
+            &suppressions.waivers,
+            suppressions.today,
+        );
+        issues.extend(unguarded.iter().map(ToString::to_string));
     }
-    markers
+    if !config.require_marker_in.is_empty() {
+        let added = protected::added_files(&repo_diff(vcs, diff_ref));
+        let unmarked = protected::unmarked(&config.require_marker_in, &added, markers);
+        issues.extend(unmarked.iter().map(ToString::to_string));
+    }
+    for issue in &issues {
+        eprintln!("Error: {issue}");
+    }
+    issues.is_empty()
 }
 
-fn run_diff_mode(root: &Path, markers: &mut Vec<marker::Marker>, diff_ref: &str, model: &str) {
-    let diff_ref = if diff_ref.is_empty() {
-        resolve_diff_ref(root)
-    } else {
-        diff_ref.to_string()
-    };
+/// The meta-check of watchers the diff edited or removed: a removal, or an
+/// edit the model judges to weaken the watcher, fails the run unless
+/// `--allow-marker-changes` is passed or the watcher's owner approved it.
+fn check_marker_changes(
+    root: &Path,
+    vcs: &dyn Vcs,
+    diff_ref: &str,
+    markers: &[marker::Marker],
+    args: &RunArgs,
+) -> bool {
+    let before =
+        marker_changes::markers_at_base(vcs, diff_ref, &repo_changed_files(vcs, diff_ref), root);
+    let changes = marker_changes::find(&before, markers);
+    if changes.is_empty() {
+        return true;
+    }
+    let approvers = marker_changes::parse_approvers(
+        &std::env::var(marker_changes::APPROVERS_ENV).unwrap_or_default(),
+    );
+    let can_ask = !args.offline && args.export_prompts.is_none() && args.import_verdicts.is_none();
+    let mut ok = true;
+    eprintln!();
+    for change in &changes {
+        let m = &change.before;
+        let weakened = match &change.after {
+            None => Some("it was removed".to_string()),
+            Some(_) if !can_ask => {
+                Some("it was edited, and this run cannot ask whether that weakens it".to_string())
+            }
+            Some(after) => {
+                let prompt_text = prompt::build_marker_change_prompt(m, after);
+                eprintln!(
+                    "asking {} whether the edit to {} weakens it...",
+                    args.model, m.name
+                );
+                match claude::invoke("marker change", &prompt_text, &args.model, "")
+                    .and_then(|reply| claude::parse_marker_change(&reply.text))
+                {
+                    Ok(None) => None,
+                    Ok(Some(reason)) => Some(format!("the edit weakens it: {reason}")),
+                    Err(e) => Some(format!(
+                        "it was edited, and checking whether that weakens it failed: {e}"
+                    )),
+                }
+            }
+        };
+        let watcher = format!("watcher `{}` ({}:{})", m.name, m.rel_path, m.line);
+        let Some(why) = weakened else {
+            eprintln!("{watcher} was edited without weakening it");
+            continue;
+        };
+        if args.allow_marker_changes {
+            eprintln!(
+                "\x1b[33m[WARNING] {watcher}: {why}; allowed by --allow-marker-changes\x1b[0m"
+            );
+        } else if let Some(owner) = marker_changes::approved_by(change, &approvers) {
+            eprintln!("\x1b[33m[WARNING] {watcher}: {why}; approved by its owner {owner}\x1b[0m");
+        } else {
+            eprintln!(
+                "Error: {watcher}: {why}. Pass --allow-marker-changes, or have its `owner` \
+                 approve the change ({})",
+                marker_changes::APPROVERS_ENV
+            );
+            ok = false;
+        }
+    }
+    ok
+}
 
-    let diff = git_diff(root, &diff_ref);
+fn run_diff_mode(
+    ctx: &claude::RunContext,
+    markers: &mut Vec<marker::Marker>,
+    vcs: &dyn Vcs,
+    diff_ref: &str,
+    filters: &DiffFilters,
+    no_cache: bool,
+    suppress: claude::Suppressor,
+) -> Option<(Vec<claude::WatcherResult>, String)> {
+    let diff = profile::time(Phase::Diff, || repo_diff(vcs, diff_ref));
     if diff.trim().is_empty() {
         eprintln!("No changes since {diff_ref}. Nothing to validate.");
-        return;
+        return None;
     }
+    let diff = redact_for_prompt(filters.redactor, &diff, "diff");
+    let noise::Filtered { diff, quiet_files } = filters.noise.filter(&diff);
 
-    let changed_files = git_changed_files(root, &diff_ref);
-    markers.retain(|m| m.files.is_empty() || m.files.iter().any(|f| changed_files.contains(f)));
+    let mut changed_files = profile::time(Phase::Diff, || repo_changed_files(vcs, diff_ref));
+    if !quiet_files.is_empty() {
+        changed_files.retain(|f| !quiet_files.contains(f));
+        eprintln!(
+            "Ignoring {} files whose changes all match ignore_hunks_matching",
+            quiet_files.len()
+        );
+    }
+    let filter_start = Instant::now();
+    markers.retain(|m| m.files.is_empty() || changed_files.iter().any(|f| m.watches(f)));
+    let diff_info = script::DiffInfo {
+        files: changed_files,
+    };
+    markers.retain(|m| {
+        let Some(condition) = script::when_for(m) else {
+            return true;
+        };
+        // A broken condition must not silently disable the watcher.
+        script::eval_when(condition, &diff_info).unwrap_or_else(|e| {
+            eprintln!(
+                "\x1b[33m[WARNING] {}: {e}; running it anyway\x1b[0m",
+                m.name
+            );
+            true
+        })
+    });
+    profile::record(Phase::Filter, filter_start.elapsed());
 
     if markers.is_empty() {
         eprintln!("No watchers matched the changed files.");
-        return;
+        return None;
     }
 
-    warn_unstaged_files(root);
+    warn_unstaged_files(vcs);
     let n = markers.len();
     eprintln!("running {n} watchers\n");
-    let results = claude::run_watchers(markers, Some(&diff), model, n, 0);
-    claude::print_results(&results);
+    shutdown::expect(markers);
+
+    // Verdicts for the same watched bytes and hunks, e.g. before a rebase.
+    let root = ctx.root;
+    let lookup_start = Instant::now();
+    let mut verdicts = cache::load_verdicts(root);
+    let mut results = Vec::new();
+    let mut to_run = Vec::new();
+    for marker in markers.iter() {
+        let hit = cache::content_key(marker, &ctx.related(marker), root, Some(&diff))
+            .filter(|_| !no_cache)
+            .and_then(|key| verdicts.get(&key));
+        let Some(verdict) = hit else {
+            to_run.push(marker.clone());
+            continue;
+        };
+        let location = format!("{}:{}", marker.rel_path, marker.line);
+        let mut result = claude::WatcherResult::new(
+            &marker.name,
+            &location,
+            verdict.is_valid,
+            verdict.reason.clone(),
+        );
+        report_cached(&mut result, results.len() + 1, n, "cached", suppress);
+        results.push(result);
+    }
+    profile::record(Phase::Filter, lookup_start.elapsed());
+
+    let ctx = claude::RunContext {
+        diff: Some(&diff),
+        ..*ctx
+    };
+    let fresh = profile::time(Phase::Validate, || {
+        claude::run_watchers(&to_run, &ctx, n, results.len(), suppress)
+    });
+    for result in fresh.iter().filter(|r| r.skipped.is_none() && !r.errored) {
+        let marker = to_run.iter().find(|m| {
+            m.name == result.name && format!("{}:{}", m.rel_path, m.line) == result.location
+        });
+        if let Some(key) =
+            marker.and_then(|m| cache::content_key(m, &ctx.related(m), root, Some(&diff)))
+        {
+            cache::store_verdict(&mut verdicts, key, result);
+        }
+    }
+    cache::save_verdicts(root, &mut verdicts);
+    results.extend(fresh);
+    Some((results, diff))
 }
 
-fn run_cache_mode(root: &Path, markers: &[marker::Marker], model: &str, no_cache: bool) {
+/// Mark `result` as a cached verdict and print its progress line.
+fn report_cached(
+    result: &mut claude::WatcherResult,
+    completed: usize,
+    total: usize,
+    tag: &str,
+    suppress: claude::Suppressor,
+) {
+    result.cached = true;
+    if !result.is_valid {
+        result.suppression = suppress(result);
+    }
+    shutdown::record(result);
+    eprintln!(
+        "[{completed}/{total}] {}... {} \x1b[90m({tag})\x1b[0m",
+        result.name,
+        result.status()
+    );
+}
+
+/// Validate `markers` that no cache level has a verdict for. `prime` skips
+/// the local levels, so that verdicts only this machine has are validated
+/// again and uploaded.
+fn run_cache_mode(
+    ctx: &claude::RunContext,
+    markers: &[marker::Marker],
+    no_cache: bool,
+    prime: bool,
+    shared: Option<&RemoteCache>,
+    suppress: claude::Suppressor,
+) -> Vec<claude::WatcherResult> {
+    let root = ctx.root;
+    let lookup_start = Instant::now();
     let mut cache = if no_cache {
         cache::Cache::new()
     } else {
         cache::load_cache()
     };
+    // Loaded even with --no-cache so fresh verdicts are added to it.
+    let mut verdicts = cache::load_verdicts(root);
 
     let n = markers.len();
     let mut to_run_indices: Vec<usize> = Vec::new();
@@ -157,84 +3465,194 @@ fn run_cache_mode(root: &Path, markers: &[marker::Marker], model: &str, no_cache
     let mut completed = 0;
 
     eprintln!("running {n} watchers\n");
+    shutdown::expect(markers);
 
+    // Edited markers always get a fresh verdict, never a shared one.
+    let modified: Vec<bool> = markers
+        .iter()
+        .map(|m| !no_cache && cache::is_modified(m, &cache))
+        .collect();
     for (i, marker) in markers.iter().enumerate() {
-        if no_cache {
+        if modified[i] {
+            eprintln!(
+                "{} ({}:{}): marker modified, validating again",
+                marker.name, marker.rel_path, marker.line
+            );
+        }
+        if no_cache || prime {
             to_run_indices.push(i);
-        } else if let Some(entry) = cache::check_cache(marker, &cache, root) {
+        } else if let Some(entry) = cache::check_cache(marker, &ctx.related(marker), &cache, root) {
             completed += 1;
-            let status = if entry.is_valid {
-                "\x1b[32mOK\x1b[0m"
-            } else {
-                "\x1b[31mFAILED\x1b[0m"
-            };
-            eprintln!(
-                "[{completed}/{n}] {}... {status} \x1b[90m(cached)\x1b[0m",
-                marker.name
+            let location = format!("{}:{}", marker.rel_path, marker.line);
+            let mut result = claude::WatcherResult::new(
+                &marker.name,
+                &location,
+                entry.is_valid,
+                entry.reason.clone(),
             );
-            cached_results.push(claude::WatcherResult {
-                name: marker.name.clone(),
-                location: format!("{}:{}", marker.rel_path, marker.line),
-                is_valid: entry.is_valid,
-                reason: entry.reason.clone(),
-                cached: true,
-            });
+            report_cached(&mut result, completed, n, "cached", suppress);
+            cached_results.push(result);
+        } else if let Some(verdict) = cache::content_key(marker, &ctx.related(marker), root, None)
+            .filter(|_| !modified[i])
+            .and_then(|key| verdicts.get(&key))
+        {
+            // Validated before with the same bytes, e.g. on another branch.
+            completed += 1;
+            let location = format!("{}:{}", marker.rel_path, marker.line);
+            let mut result = claude::WatcherResult::new(
+                &marker.name,
+                &location,
+                verdict.is_valid,
+                verdict.reason.clone(),
+            );
+            report_cached(&mut result, completed, n, "cached", suppress);
+            let (key, entry) = cache::build_entry(marker, &ctx.related(marker), &result, root);
+            cache.insert(key, entry);
+            cached_results.push(result);
         } else {
             to_run_indices.push(i);
         }
     }
 
+    // Verdicts a teammate or another CI job already computed for the same inputs.
+    let shared = shared.filter(|_| !no_cache);
+    if let Some(shared) = shared {
+        let modified = &modified;
+        let hits: Vec<Option<RemoteEntry>> = thread::scope(|scope| {
+            let lookups: Vec<_> = to_run_indices
+                .iter()
+                .map(|&i| {
+                    scope.spawn(move || {
+                        remote_cache::key_for(&markers[i], &ctx.related(&markers[i]), root)
+                            .filter(|_| !modified[i])
+                            .and_then(|key| shared.get(&key))
+                    })
+                })
+                .collect();
+            lookups.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let mut misses = Vec::new();
+        for (i, hit) in to_run_indices.into_iter().zip(hits) {
+            let Some(entry) = hit else {
+                misses.push(i);
+                continue;
+            };
+            completed += 1;
+            let marker = &markers[i];
+            let location = format!("{}:{}", marker.rel_path, marker.line);
+            let mut result =
+                claude::WatcherResult::new(&marker.name, &location, entry.is_valid, entry.reason);
+            report_cached(&mut result, completed, n, "shared cache", suppress);
+            let (key, entry) = cache::build_entry(marker, &ctx.related(marker), &result, root);
+            cache.insert(key, entry);
+            cached_results.push(result);
+        }
+        to_run_indices = misses;
+    }
+    profile::record(Phase::Filter, lookup_start.elapsed());
+
     let to_run: Vec<marker::Marker> = to_run_indices.iter().map(|&i| markers[i].clone()).collect();
 
-    let fresh_results = if to_run.is_empty() && cached_results.is_empty() {
+    let mut fresh_results = if to_run.is_empty() && cached_results.is_empty() {
         Vec::new()
     } else {
-        claude::run_watchers(&to_run, None, model, n, completed)
+        profile::time(Phase::Validate, || {
+            claude::run_watchers(&to_run, ctx, n, completed, suppress)
+        })
     };
+    for result in &mut fresh_results {
+        result.modified = markers.iter().zip(&modified).any(|(m, &edited)| {
+            edited
+                && m.name == result.name
+                && format!("{}:{}", m.rel_path, m.line) == result.location
+        });
+    }
 
-    // Update cache with fresh results
-    for (marker, result) in to_run.iter().zip(fresh_results.iter()) {
-        let (key, entry) = cache::build_entry(marker, result, root);
-        cache.insert(key, entry);
+    // Update cache with fresh results. Results arrive in completion order, so
+    // match each one back to its marker.
+    let mut uploads = Vec::new();
+    for result in fresh_results
+        .iter()
+        .filter(|r| r.skipped.is_none() && !r.errored)
+    {
+        let marker = to_run.iter().find(|m| {
+            m.name == result.name && format!("{}:{}", m.rel_path, m.line) == result.location
+        });
+        if let Some(marker) = marker {
+            let related = ctx.related(marker);
+            let (key, entry) = cache::build_entry(marker, &related, result, root);
+            cache.insert(key, entry);
+            if let Some(key) = cache::content_key(marker, &related, root, None) {
+                cache::store_verdict(&mut verdicts, key, result);
+            }
+            if let Some(key) = remote_cache::key_for(marker, &related, root) {
+                uploads.push((
+                    key,
+                    RemoteEntry::new(result.is_valid, result.reason.clone()),
+                ));
+            }
+        }
     }
     cache::save_cache(&cache);
+    cache::save_verdicts(root, &mut verdicts);
 
-    let mut all_results = cached_results;
-    all_results.extend(fresh_results);
-    claude::print_results(&all_results);
-}
-
-fn resolve_diff_ref(root: &Path) -> String {
-    for candidate in ["origin/main", "origin/master"] {
-        let output = process::Command::new("git")
-            .args(["rev-parse", "--verify", candidate])
-            .current_dir(root)
-            .stdout(process::Stdio::null())
-            .stderr(process::Stdio::null())
-            .status();
-        if let Ok(status) = output
-            && status.success()
-        {
-            return candidate.to_string();
+    if let Some(shared) = shared.filter(|s| !s.readonly) {
+        let failed = thread::scope(|scope| {
+            let puts: Vec<_> = uploads
+                .iter()
+                .map(|(key, entry)| scope.spawn(move || shared.put(key, entry)))
+                .collect();
+            puts.into_iter()
+                .filter_map(|h| h.join().unwrap().err())
+                .collect::<Vec<_>>()
+        });
+        if let Some(first) = failed.first() {
+            eprintln!(
+                "\x1b[33m[WARNING] {} verdicts not uploaded to the shared cache: {first}\x1b[0m",
+                failed.len()
+            );
         }
     }
-    eprintln!(
-        "Error: could not find origin/main or origin/master. Pass a ref explicitly: --diff <ref>"
-    );
-    process::exit(1);
+
+    let mut all_results = cached_results;
+    all_results.extend(fresh_results);
+    all_results
 }
 
-fn warn_unstaged_files(root: &Path) {
+/// Run a git command in `root` and return its trimmed stdout, or `None` if it failed.
+pub fn git_output(root: &Path, args: &[&str]) -> Option<String> {
     let output = process::Command::new("git")
-        .args(["ls-files", "--others", "--exclude-standard"])
+        .args(args)
         .current_dir(root)
-        .output();
-    let output = match output {
-        Ok(o) if o.status.success() => o,
-        _ => return,
-    };
-    let lines: Vec<String> = String::from_utf8_lossy(&output.stdout)
-        .lines()
+        .stderr(process::Stdio::null())
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !text.is_empty()).then_some(text)
+}
+
+/// The checked-out branch. CI checkouts are often detached, so `WK_BRANCH`
+/// takes precedence.
+fn current_branch(root: &Path) -> Option<String> {
+    std::env::var("WK_BRANCH")
+        .ok()
+        .filter(|b| !b.is_empty())
+        .or_else(|| git_output(root, &["rev-parse", "--abbrev-ref", "HEAD"]))
+        .filter(|b| b != "HEAD")
+}
+
+/// The default diff base of the checkout's VCS (see `Vcs::default_base`).
+pub fn resolve_diff_ref(vcs: &dyn Vcs) -> String {
+    vcs.default_base().unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::CONFIG_ERROR);
+    })
+}
+
+fn warn_unstaged_files(vcs: &dyn Vcs) {
+    let lines: Vec<String> = vcs
+        .untracked_files()
+        .iter()
         .filter(|l| !l.is_empty())
         .map(|l| format!("  - {}", l.trim()))
         .collect();
@@ -247,43 +3665,18 @@ fn warn_unstaged_files(root: &Path) {
     );
 }
 
-fn git_changed_files(root: &Path, commit: &str) -> Vec<String> {
-    let output = process::Command::new("git")
-        .args(["diff", commit, "--name-only"])
-        .current_dir(root)
-        .output()
-        .unwrap_or_else(|e| {
-            eprintln!("Error: failed to run `git diff {commit} --name-only`: {e}");
-            process::exit(1);
-        });
-    if !output.status.success() {
-        eprintln!(
-            "Error: `git diff {commit} --name-only` failed: {}",
-            String::from_utf8_lossy(&output.stderr).trim()
-        );
-        process::exit(1);
-    }
-    String::from_utf8_lossy(&output.stdout)
-        .lines()
-        .map(|l| l.to_string())
-        .collect()
+fn repo_changed_files(vcs: &dyn Vcs, commit: &str) -> Vec<String> {
+    vcs.changed_files(commit).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    })
 }
 
-fn git_diff(root: &Path, commit: &str) -> String {
-    let output = process::Command::new("git")
-        .args(["diff", commit])
-        .current_dir(root)
-        .output()
-        .unwrap_or_else(|e| {
-            eprintln!("Error: failed to run `git diff {commit}`: {e}");
-            process::exit(1);
-        });
-    if !output.status.success() {
-        eprintln!(
-            "Error: `git diff {commit}` failed: {}",
-            String::from_utf8_lossy(&output.stderr).trim()
-        );
-        process::exit(1);
-    }
-    String::from_utf8_lossy(&output.stdout).to_string()
+/// The diff of the working copy against `commit` (a revision of the
+/// checkout's VCS).
+pub fn repo_diff(vcs: &dyn Vcs, commit: &str) -> String {
+    vcs.diff(commit).map(platform::lf).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    })
 }
@@ -0,0 +1,119 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the Unix epoch.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// A calendar date. Dates order chronologically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    year: i64,
+    month: u32,
+    day: u32,
+}
+
+impl Date {
+    /// The date `days` days after 1970-01-01.
+    pub fn from_unix_days(days: i64) -> Date {
+        // Howard Hinnant's civil_from_days algorithm.
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        Date {
+            year: yoe + era * 400 + i64::from(month <= 2),
+            month: month as u32,
+            day: day as u32,
+        }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Parse a real `YYYY-MM-DD` calendar date.
+pub fn parse_date(date: &str) -> Result<Date, String> {
+    let err = || format!("invalid date `{date}`: expected YYYY-MM-DD");
+    let parts: Vec<&str> = date.split('-').collect();
+    if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 {
+        return Err(err());
+    }
+    let nums: Vec<u32> = parts
+        .iter()
+        .map(|p| p.parse::<u32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| err())?;
+    let (year, month, day) = (nums[0], nums[1], nums[2]);
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return Err(err()),
+    };
+    if day == 0 || day > days_in_month {
+        return Err(err());
+    }
+    Ok(Date {
+        year: i64::from(year),
+        month,
+        day,
+    })
+}
+
+/// Today's UTC date.
+pub fn today() -> Date {
+    Date::from_unix_days(now().div_euclid(86_400))
+}
+
+/// Convert days since 1970-01-01 to a `YYYY-MM-DD` civil date.
+pub fn date_from_unix_days(days: i64) -> String {
+    Date::from_unix_days(days).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_date_accepts_real_dates() {
+        assert_eq!(parse_date("2025-03-01").unwrap().to_string(), "2025-03-01");
+        assert!(parse_date("2024-02-29").is_ok());
+    }
+
+    #[test]
+    fn parse_date_rejects_bad_dates() {
+        assert!(parse_date("2025-02-29").is_err());
+        assert!(parse_date("2025-13-01").is_err());
+        assert!(parse_date("2025-3-1").is_err());
+        assert!(parse_date("next week").is_err());
+    }
+
+    #[test]
+    fn dates_order_chronologically() {
+        let date = |d| parse_date(d).unwrap();
+        assert!(date("2025-02-28") < date("2025-03-01"));
+        assert!(date("2024-12-31") < date("2025-01-01"));
+        assert_eq!(Date::from_unix_days(20_148), date("2025-03-01"));
+    }
+
+    #[test]
+    fn date_from_unix_days_known_values() {
+        assert_eq!(date_from_unix_days(0), "1970-01-01");
+        assert_eq!(date_from_unix_days(11_016), "2000-02-29");
+        assert_eq!(date_from_unix_days(20_148), "2025-03-01");
+    }
+}
@@ -0,0 +1,226 @@
+use crate::claude::WatcherResult;
+use crate::marker::Marker;
+use crate::report;
+
+/// One `@@` hunk of a unified diff, with the path of its file.
+#[derive(Debug, PartialEq)]
+pub struct Hunk {
+    pub path: String,
+    /// The `@@` header line and the hunk's lines.
+    pub text: String,
+}
+
+/// Failures with a common root cause: watchers whose relevant hunks
+/// overlap, directly or through another member.
+pub struct Cluster<'a> {
+    pub failures: Vec<&'a WatcherResult>,
+    /// The hunks relevant to at least two members, as diff text under a
+    /// `path` line per file. Empty for a lone failure.
+    pub shared: String,
+}
+
+/// Split a unified diff into its hunks.
+pub fn hunks(diff: &str) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let mut path: Option<String> = None;
+    let mut in_hunk = false;
+    for line in diff.split_inclusive('\n') {
+        if let Some(header) = line.strip_prefix("diff --git ") {
+            path = header
+                .trim_end()
+                .rsplit_once(" b/")
+                .map(|(_, p)| p.to_string());
+            in_hunk = false;
+        } else if line.starts_with("@@") {
+            if let Some(path) = &path {
+                hunks.push(Hunk {
+                    path: path.clone(),
+                    text: line.to_string(),
+                });
+                in_hunk = true;
+            }
+        } else if in_hunk {
+            hunks.last_mut().unwrap().text.push_str(line);
+        }
+    }
+    hunks
+}
+
+/// Whether a change to `path` bears on `marker`: a watched file, or the
+/// marker's own file when it watches nothing in particular.
+fn relevant(marker: &Marker, path: &str) -> bool {
+    if marker.files.is_empty() {
+        path == marker.rel_path
+    } else {
+        marker.watches(path)
+    }
+}
+
+/// Group `failures` by the hunks of `diff` relevant to them, in the order
+/// of each group's first failure. Failures without a marker or without a
+/// relevant hunk stand alone.
+pub fn cluster<'a>(
+    failures: &[&'a WatcherResult],
+    markers: &[Marker],
+    diff: &str,
+) -> Vec<Cluster<'a>> {
+    let hunks = hunks(diff);
+    let relevant_hunks: Vec<Vec<usize>> = failures
+        .iter()
+        .map(|f| match report::marker_for(f, markers) {
+            Some(m) => (0..hunks.len())
+                .filter(|&i| relevant(m, &hunks[i].path))
+                .collect(),
+            None => Vec::new(),
+        })
+        .collect();
+
+    // Union-find over failures, joined by every hunk they share.
+    let mut parent: Vec<usize> = (0..failures.len()).collect();
+    fn find(parent: &mut [usize], i: usize) -> usize {
+        let mut root = i;
+        while parent[root] != root {
+            root = parent[root];
+        }
+        parent[i] = root;
+        root
+    }
+    let mut owner: Vec<Option<usize>> = vec![None; hunks.len()];
+    for (f, indices) in relevant_hunks.iter().enumerate() {
+        for &h in indices {
+            match owner[h] {
+                Some(other) => {
+                    let (a, b) = (find(&mut parent, f), find(&mut parent, other));
+                    parent[a.max(b)] = a.min(b);
+                }
+                None => owner[h] = Some(f),
+            }
+        }
+    }
+
+    let mut clusters: Vec<(usize, Vec<usize>)> = Vec::new();
+    for f in 0..failures.len() {
+        let root = find(&mut parent, f);
+        match clusters.iter_mut().find(|(r, _)| *r == root) {
+            Some((_, members)) => members.push(f),
+            None => clusters.push((root, vec![f])),
+        }
+    }
+
+    clusters
+        .into_iter()
+        .map(|(_, members)| {
+            let mut shared = String::new();
+            if members.len() > 1 {
+                let mut last_path: Option<&str> = None;
+                for (h, hunk) in hunks.iter().enumerate() {
+                    let users = members
+                        .iter()
+                        .filter(|&&f| relevant_hunks[f].contains(&h))
+                        .count();
+                    if users < 2 {
+                        continue;
+                    }
+                    if last_path != Some(hunk.path.as_str()) {
+                        shared.push_str(&hunk.path);
+                        shared.push('\n');
+                        last_path = Some(&hunk.path);
+                    }
+                    shared.push_str(&hunk.text);
+                }
+            }
+            Cluster {
+                failures: members.iter().map(|&f| failures[f]).collect(),
+                shared,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "\
+diff --git a/db/schema.sql b/db/schema.sql
+index 1..2 100644
+--- a/db/schema.sql
++++ b/db/schema.sql
+@@ -1,2 +1,2 @@
+-  email TEXT
++  email_address TEXT
+diff --git a/src/ui.ts b/src/ui.ts
+--- a/src/ui.ts
++++ b/src/ui.ts
+@@ -4 +4 @@
+-old
++new
+";
+
+    fn marker(name: &str, files: &[&str]) -> Marker {
+        Marker {
+            name: name.to_string(),
+            rel_path: format!("src/{name}.ts"),
+            line: 1,
+            instruction: "Check it".to_string(),
+            files: files.iter().map(|f| f.to_string()).collect(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: Default::default(),
+        }
+    }
+
+    fn failure(name: &str) -> WatcherResult {
+        WatcherResult::new(name, &format!("src/{name}.ts:1"), false, None)
+    }
+
+    #[test]
+    fn hunks_keep_their_path() {
+        let hunks = hunks(DIFF);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].path, "db/schema.sql");
+        assert_eq!(
+            hunks[0].text,
+            "@@ -1,2 +1,2 @@\n-  email TEXT\n+  email_address TEXT\n"
+        );
+        assert_eq!(hunks[1].path, "src/ui.ts");
+    }
+
+    #[test]
+    fn failures_sharing_a_hunk_are_clustered() {
+        let markers = [
+            marker("api", &["db/schema.sql"]),
+            marker("ui", &["src/ui.ts"]),
+            marker("export", &["db/"]),
+            marker("lonely", &[]),
+        ];
+        let results = [
+            failure("api"),
+            failure("ui"),
+            failure("export"),
+            failure("lonely"),
+            failure("gone"),
+        ];
+        let failures: Vec<&WatcherResult> = results.iter().collect();
+        let clusters = cluster(&failures, &markers, DIFF);
+        let names: Vec<Vec<&str>> = clusters
+            .iter()
+            .map(|c| c.failures.iter().map(|f| f.name.as_str()).collect())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                vec!["api", "export"],
+                vec!["ui"],
+                vec!["lonely"],
+                vec!["gone"]
+            ]
+        );
+        assert_eq!(
+            clusters[0].shared,
+            "db/schema.sql\n@@ -1,2 +1,2 @@\n-  email TEXT\n+  email_address TEXT\n"
+        );
+        assert!(clusters[1].shared.is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::path::Path;
+
+use clap::ValueEnum;
+use clap_complete::CompletionCandidate;
+use clap_complete::env::Shells;
+
+use crate::cli;
+use crate::marker::Marker;
+use crate::vcs;
+
+/// Environment variable that switches the binary into completion mode.
+pub const COMPLETE_VAR: &str = "COMPLETE";
+const BIN: &str = "watcher-knight";
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+    Elvish,
+}
+
+impl Shell {
+    fn name(self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Powershell => "powershell",
+            Shell::Elvish => "elvish",
+        }
+    }
+}
+
+/// Write the shell code that registers completions for `shell`. The script
+/// calls back into `watcher-knight` on every completion, so marker and suite
+/// names are always current.
+pub fn write_registration(shell: Shell, out: &mut dyn io::Write) -> io::Result<()> {
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(shell.name())
+        .expect("every Shell has a built-in completer");
+    completer.write_registration(COMPLETE_VAR, BIN, BIN, BIN, out)
+}
+
+fn scan_for_completion() -> Vec<Marker> {
+    // Completion must never print or exit; an unreadable tree just completes nothing.
+    match vcs::discover(Path::new(".")) {
+        Some(root) => cli::scan_markers(&root).0,
+        None => Vec::new(),
+    }
+}
+
+fn candidates(values: BTreeSet<String>) -> Vec<CompletionCandidate> {
+    values.into_iter().map(CompletionCandidate::new).collect()
+}
+
+/// Marker names in the current repository.
+pub fn marker_names() -> Vec<CompletionCandidate> {
+    candidates(scan_for_completion().into_iter().map(|m| m.name).collect())
+}
+
+/// Suite names (the `suite` marker option) in the current repository.
+pub fn suite_names() -> Vec<CompletionCandidate> {
+    candidates(suites(&scan_for_completion()))
+}
+
+pub fn suites(markers: &[Marker]) -> BTreeSet<String> {
+    markers
+        .iter()
+        .filter_map(|m| m.options.get("suite"))
+        .flat_map(|s| s.split(',').map(|s| s.trim().to_string()))
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn write_registration_every_shell() {
+        for shell in Shell::value_variants() {
+            let mut out = Vec::new();
+            write_registration(*shell, &mut out).unwrap();
+            let script = String::from_utf8(out).unwrap();
+            assert!(script.contains(BIN), "script was: {script}");
+        }
+    }
+
+    #[test]
+    fn suites_split_and_dedup() {
+        let marker = |suite: Option<&str>| Marker {
+            name: "w".to_string(),
+            rel_path: "a.ts".to_string(),
+            line: 1,
+            instruction: "Check it".to_string(),
+            files: vec![],
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: suite
+                .map(|s| HashMap::from([("suite".to_string(), s.to_string())]))
+                .unwrap_or_default(),
+        };
+        let found = suites(&[marker(Some("api, db")), marker(Some("db")), marker(None)]);
+        assert_eq!(found.into_iter().collect::<Vec<_>>(), vec!["api", "db"]);
+    }
+}
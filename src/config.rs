@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::lint::LintConfig;
+use crate::marker;
+use crate::noise::NoiseFilter;
+use crate::privacy::PrivacyConfig;
+use crate::quota::{self, BackendLimits};
+use crate::remote_cache::RemoteCacheConfig;
+use crate::shard::{self, SuiteConfig};
+use crate::tickets::TicketConfig;
+use crate::upload::ReportConfig;
+use crate::virtual_markers::VirtualMarker;
+
+pub const CONFIG_FILE: &str = "watcher-knight.toml";
+
+/// Repository-level configuration read from `watcher-knight.toml` at the scan root.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Remote policy packs (`<git url>#<ref>` or an `https://` URL to a policy YAML).
+    pub policy_packs: Vec<String>,
+    /// Extra regexes whose matches are redacted from diffs and file contents
+    /// before they are sent to the model (on top of the built-in secret rules).
+    pub redact_patterns: Vec<String>,
+    /// Regexes of changed lines to drop from diffs, e.g. log statements or
+    /// license headers. A file whose changes all match no longer counts as
+    /// changed, and prompts never show the dropped lines.
+    pub ignore_hunks_matching: Vec<String>,
+    /// Checker plugins by name, mapped to an executable path (relative to the
+    /// scan root). Takes precedence over `wk-check-<name>` found on PATH.
+    pub checkers: HashMap<String, String>,
+    /// Shell command run in the scan root before markers are scanned.
+    pub pre_run: Option<String>,
+    /// Shell command run in the scan root after the results are printed.
+    pub post_run: Option<String>,
+    /// Shell command that receives the results JSON on stdin after a run; its
+    /// exit code decides whether the run passes.
+    pub post_processor: Option<String>,
+    /// Base URLs of remote workers that run AI watchers (see `watcher-knight worker`).
+    pub workers: Vec<String>,
+    /// URL prefixes that `context={...}` documents may be fetched from.
+    /// Nothing is fetched unless it is listed here.
+    pub context_allowlist: Vec<String>,
+    /// Team-shared verdict cache (`[remote_cache]`).
+    pub remote_cache: Option<RemoteCacheConfig>,
+    /// Ticket filing for persistent failures (`[tickets]`).
+    pub tickets: Option<TicketConfig>,
+    /// Upload of every run's report to a central service (`[report]`).
+    pub report: ReportConfig,
+    /// Options of `watcher-knight lint` (`[lint]`).
+    pub lint: LintConfig,
+    /// Globs of repo-relative paths that a diff may only touch when a passing
+    /// (or waived) watcher covers the changed file.
+    pub protected_paths: Vec<String>,
+    /// Globs of repo-relative paths where a file added by the diff must hold
+    /// a marker.
+    pub require_marker_in: Vec<String>,
+    /// Watchers declared here instead of in comments (`[[markers]]`).
+    pub markers: Vec<VirtualMarker>,
+    /// Concurrency and quota limits per backend (`[backends.claude]`,
+    /// `[backends.remote]`).
+    pub backends: HashMap<String, BackendLimits>,
+    /// Tag keywords accepted besides `wk`, e.g. `guard` for `<guard: name ... />`.
+    pub tag_keywords: Vec<String>,
+    /// Keep the agents of scoped watchers to the watcher's own files: `Read`
+    /// is limited to them, and a verdict reached after reading or searching
+    /// anything else is discarded.
+    pub confine_tools: bool,
+    /// Watched files above which a scoped watcher is validated in
+    /// per-directory chunks (default 200; 0 never chunks).
+    pub chunk_files: Option<usize>,
+    /// Commands by name whose output `strategy=command-output` watchers are
+    /// judged on, run through the shell in the scan root.
+    pub commands: HashMap<String, String>,
+    /// Scrubbing of paths, login names and internal identifiers from
+    /// prompts (`[privacy]`).
+    pub privacy: Option<PrivacyConfig>,
+    /// Per-suite settings by suite name (`[suites.<name>]`), such as the
+    /// expected cost `run --shard` balances by.
+    pub suites: HashMap<String, SuiteConfig>,
+}
+
+impl Config {
+    /// Drop everything that runs a command or sends results elsewhere, for
+    /// validating an untrusted pull request (`run --pr`): hooks, the
+    /// post-processor, checkers, `[commands]`, workers, report uploads and
+    /// tickets. Agents are kept to their watcher's files (`confine_tools`).
+    pub fn lock_down(&mut self) {
+        self.confine_tools = true;
+        self.pre_run = None;
+        self.post_run = None;
+        self.post_processor = None;
+        self.checkers.clear();
+        self.commands.clear();
+        self.workers.clear();
+        self.report = ReportConfig::default();
+        self.tickets = None;
+    }
+}
+
+/// Load the config from `root`, returning the default config if there is none.
+pub fn load_config(root: &Path) -> Result<Config, String> {
+    let path = root.join(CONFIG_FILE);
+    match fs::read_to_string(&path) {
+        Ok(data) => parse_config(&data),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(format!("cannot read {CONFIG_FILE}: {e}")),
+    }
+}
+
+fn parse_config(data: &str) -> Result<Config, String> {
+    let config: Config = toml::from_str(data).map_err(|e| format!("invalid {CONFIG_FILE}: {e}"))?;
+    quota::validate(&config.backends).map_err(|e| format!("invalid {CONFIG_FILE}: {e}"))?;
+    shard::validate(&config.suites).map_err(|e| format!("invalid {CONFIG_FILE}: {e}"))?;
+    NoiseFilter::new(&config.ignore_hunks_matching)
+        .map_err(|e| format!("invalid {CONFIG_FILE}: {e}"))?;
+    for keyword in &config.tag_keywords {
+        marker::check_tag_keyword(keyword).map_err(|e| format!("invalid {CONFIG_FILE}: {e}"))?;
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config_empty() {
+        let config = parse_config("").unwrap();
+        assert!(config.policy_packs.is_empty());
+    }
+
+    #[test]
+    fn lock_down_drops_what_runs_commands() {
+        let mut config = parse_config(
+            "pre_run = \"make\"\npost_processor = \"jq\"\nworkers = [\"http://w\"]\n\
+             protected_paths = [\"billing/**\"]\n[checkers]\nschema = \"check.sh\"\n\
+             [commands]\ntests = \"cargo test\"\n[report]\nupload_url = \"https://d\"\n",
+        )
+        .unwrap();
+        config.lock_down();
+        assert_eq!(config.pre_run, None);
+        assert_eq!(config.post_processor, None);
+        assert!(config.workers.is_empty() && config.checkers.is_empty());
+        assert!(config.commands.is_empty());
+        assert_eq!(config.report.upload_url, None);
+        assert_eq!(config.protected_paths, ["billing/**"]);
+        assert!(config.confine_tools);
+    }
+
+    #[test]
+    fn parse_config_markers() {
+        let config = parse_config(
+            "[[markers]]\nname = \"pinned\"\napplies_to = \"*/Dockerfile\"\ninstruction = \"Pin it.\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.markers.len(), 1);
+        assert_eq!(config.markers[0].applies_to, "*/Dockerfile");
+        assert!(parse_config("[[markers]]\nname = \"a\"\napplies = \"*\"\n").is_err());
+    }
+
+    #[test]
+    fn parse_config_backends() {
+        let config = parse_config(
+            "[backends.claude]\nmax_inflight = 4\nrequests_per_minute = 50\n\
+             daily_token_budget = 2000000\n",
+        )
+        .unwrap();
+        let claude = &config.backends["claude"];
+        assert_eq!(claude.max_inflight, Some(4));
+        assert_eq!(claude.requests_per_minute, Some(50));
+        assert_eq!(claude.daily_token_budget, Some(2_000_000));
+        assert!(parse_config("[backends.api]\nmax_inflight = 1\n").is_err());
+    }
+
+    #[test]
+    fn parse_config_ignore_hunks_matching() {
+        let config = parse_config("ignore_hunks_matching = [\"^\\\\s*logger\\\\.\"]").unwrap();
+        assert_eq!(config.ignore_hunks_matching, vec![r"^\s*logger\."]);
+        assert!(parse_config("ignore_hunks_matching = [\"(\"]").is_err());
+    }
+
+    #[test]
+    fn parse_config_suites() {
+        let config = parse_config("[suites.db]\ncost = \"2m\"\n").unwrap();
+        assert_eq!(config.suites["db"].cost.as_deref(), Some("2m"));
+        assert!(parse_config("[suites.db]\ncost = \"later\"\n").is_err());
+    }
+
+    #[test]
+    fn parse_config_policy_packs() {
+        let config =
+            parse_config("policy_packs = [\"git@github.com:org/wk-policies.git#v3\"]").unwrap();
+        assert_eq!(
+            config.policy_packs,
+            vec!["git@github.com:org/wk-policies.git#v3"]
+        );
+    }
+
+    #[test]
+    fn parse_config_redact_patterns() {
+        let config = parse_config("redact_patterns = [\"corp-[0-9]{6}\"]").unwrap();
+        assert_eq!(config.redact_patterns, vec!["corp-[0-9]{6}"]);
+    }
+
+    #[test]
+    fn parse_config_checkers() {
+        let config = parse_config("[checkers]\nschema = \"scripts/check-schema.sh\"").unwrap();
+        assert_eq!(config.checkers["schema"], "scripts/check-schema.sh");
+    }
+
+    #[test]
+    fn parse_config_hooks() {
+        let config = parse_config("pre_run = \"./fetch.sh\"\npost_run = \"./upload.sh\"").unwrap();
+        assert_eq!(config.pre_run.as_deref(), Some("./fetch.sh"));
+        assert_eq!(config.post_run.as_deref(), Some("./upload.sh"));
+    }
+
+    #[test]
+    fn parse_config_confine_tools() {
+        assert!(!parse_config("").unwrap().confine_tools);
+        assert!(parse_config("confine_tools = true").unwrap().confine_tools);
+    }
+
+    #[test]
+    fn parse_config_chunk_files() {
+        assert_eq!(parse_config("").unwrap().chunk_files, None);
+        assert_eq!(
+            parse_config("chunk_files = 0").unwrap().chunk_files,
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn parse_config_workers() {
+        let config = parse_config("workers = [\"https://wk-1.internal:8787\"]").unwrap();
+        assert_eq!(config.workers, vec!["https://wk-1.internal:8787"]);
+    }
+
+    #[test]
+    fn parse_config_context_allowlist() {
+        let config = parse_config("context_allowlist = [\"https://wiki.corp/\"]").unwrap();
+        assert_eq!(config.context_allowlist, vec!["https://wiki.corp/"]);
+    }
+
+    #[test]
+    fn parse_config_remote_cache() {
+        let config =
+            parse_config("[remote_cache]\nurl = \"s3://wk-cache/team\"\nregion = \"eu-west-1\"")
+                .unwrap();
+        let remote = config.remote_cache.unwrap();
+        assert_eq!(remote.url, "s3://wk-cache/team");
+        assert_eq!(remote.region.as_deref(), Some("eu-west-1"));
+    }
+
+    #[test]
+    fn parse_config_tickets() {
+        let config =
+            parse_config("[tickets]\ntracker = \"github\"\nrepo = \"org/service\"").unwrap();
+        let tickets = config.tickets.unwrap();
+        assert_eq!(tickets.repo.as_deref(), Some("org/service"));
+        assert_eq!(tickets.branch, "main");
+        assert_eq!(tickets.after, 3);
+    }
+
+    #[test]
+    fn parse_config_unknown_key_rejected() {
+        let err = parse_config("policy_pack = []").unwrap_err();
+        assert!(
+            err.contains("invalid watcher-knight.toml"),
+            "err was: {err}"
+        );
+    }
+
+    #[test]
+    fn parse_config_tag_keywords() {
+        let config = parse_config("tag_keywords = [\"guard\", \"inv\"]").unwrap();
+        assert_eq!(config.tag_keywords, vec!["guard", "inv"]);
+        let err = parse_config("tag_keywords = [\"my guard\"]").unwrap_err();
+        assert!(err.contains("invalid tag keyword `my guard`"), "{err}");
+    }
+
+    #[test]
+    fn parse_lint_section() {
+        let config = parse_config("[lint]\ndisable = [\"unscoped-watcher\"]").unwrap();
+        assert_eq!(config.lint.disable, vec!["unscoped-watcher"]);
+        assert_eq!(config.lint.min_words, 5);
+    }
+
+    #[test]
+    fn load_config_missing_file_is_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = load_config(dir.path()).unwrap();
+        assert!(config.policy_packs.is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::marker::{self, Marker};
+use crate::platform;
+use crate::stream::Reply;
+
+/// The tools whose reach can be confined and checked: an agent that may
+/// only use these reads files and changes nothing.
+pub const READ_ONLY_TOOLS: &[&str] = &["Read", "Grep", "Glob"];
+
+/// The name of `tool`, without its rule (`Bash` for `Bash(ls:*)`).
+pub fn tool_name(tool: &str) -> &str {
+    tool.split('(').next().unwrap_or_default().trim()
+}
+
+/// What a scoped watcher's agent may read with `confine_tools`: the marker's
+/// own file, its file entries and its local context files.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    root: PathBuf,
+    entries: Vec<String>,
+    exclude: Vec<String>,
+}
+
+/// The scope of `marker`, or `None` for an unscoped marker, which may read
+/// anything.
+pub fn scope_for(marker: &Marker, root: &Path) -> Option<Scope> {
+    if marker.files.is_empty() {
+        return None;
+    }
+    let mut entries = vec![marker.rel_path.clone()];
+    entries.extend(marker.files.iter().cloned());
+    entries.extend(marker.context_files().map(str::to_string));
+    entries.dedup();
+    Some(Scope {
+        root: root.to_path_buf(),
+        entries,
+        exclude: marker.exclude.clone(),
+    })
+}
+
+impl Scope {
+    /// Repo-relative files and directories (ending in `/`) in scope.
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// `tools` with `Read` narrowed to one `Read(//<absolute path>)` rule per
+    /// entry. claude has no path rules for the search tools, so `Grep` and
+    /// `Glob` are only checked afterwards (see [`Scope::check`]). Any other
+    /// tool, e.g. `Bash` or `Write`, could reach beyond the scope unseen, so
+    /// it is an error.
+    pub fn allowed_tools(&self, tools: &str) -> Result<String, String> {
+        let root = platform::slash_path(&self.root);
+        let root = root.trim_end_matches('/');
+        let mut allowed = Vec::new();
+        for tool in tools.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            if !READ_ONLY_TOOLS.contains(&tool_name(tool)) {
+                return Err(format!(
+                    "tool `{tool}` cannot be confined to the watcher's files; confine_tools \
+                     allows only {}",
+                    READ_ONLY_TOOLS.join(", ")
+                ));
+            }
+            if tool != "Read" {
+                allowed.push(tool.to_string());
+                continue;
+            }
+            for entry in &self.entries {
+                let path = match entry.strip_suffix('/') {
+                    Some(dir) => format!("{dir}/**"),
+                    None => entry.clone(),
+                };
+                allowed.push(format!("Read(/{root}/{path})"));
+            }
+        }
+        Ok(allowed.join(","))
+    }
+
+    /// Whether the agent may read `path` (a file or a directory it
+    /// searched), as passed to the tool: absolute, or relative to `cwd`.
+    fn covers(&self, path: &str, cwd: &Path) -> bool {
+        let path = marker::normalize_path(&cwd.join(path));
+        let Ok(rel) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let rel = platform::slash_path(rel);
+        let dir = format!("{rel}/");
+        self.entries
+            .iter()
+            .any(|entry| marker::entry_covers(entry, &rel) || *entry == dir)
+            && !marker::is_excluded(&rel, &self.exclude)
+    }
+
+    /// Paths the agent read outside the scope, in the order it read them.
+    pub fn outside(&self, paths: &[String], cwd: &Path) -> Vec<String> {
+        let mut outside: Vec<String> = Vec::new();
+        for path in paths {
+            if !self.covers(path, cwd) && !outside.contains(path) {
+                outside.push(path.clone());
+            }
+        }
+        outside
+    }
+
+    /// `reply`, unless its agent read outside the scope: such a verdict is
+    /// discarded, since it may rest on files the watcher must not see. So is
+    /// a reply without telemetry, which cannot show what the agent read.
+    pub fn check(&self, reply: Reply) -> Result<Reply, String> {
+        let Some(telemetry) = &reply.telemetry else {
+            return Err(
+                "claude reported no tool calls, so confinement to the watcher's files cannot \
+                 be checked; the verdict is discarded"
+                    .to_string(),
+            );
+        };
+        let cwd = env::current_dir().unwrap_or_else(|_| self.root.clone());
+        let outside = self.outside(&telemetry.paths, &cwd);
+        if outside.is_empty() {
+            return Ok(reply);
+        }
+        Err(format!(
+            "the agent read outside the watcher's files ({}); the verdict is discarded",
+            outside.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_marker(files: &[&str]) -> Marker {
+        Marker {
+            name: "billing".to_string(),
+            rel_path: "billing/README.md".to_string(),
+            line: 1,
+            instruction: "Check it".to_string(),
+            files: files.iter().map(|f| f.to_string()).collect(),
+            exclude: vec!["billing/keys".to_string()],
+            context: vec!["docs/billing.md".to_string()],
+            metadata: Default::default(),
+            options: Default::default(),
+        }
+    }
+
+    fn scoped(files: &[&str]) -> Scope {
+        scope_for(&make_marker(files), Path::new("/repo")).unwrap()
+    }
+
+    #[test]
+    fn unscoped_markers_are_not_confined() {
+        assert!(scope_for(&make_marker(&[]), Path::new("/repo")).is_none());
+    }
+
+    #[test]
+    fn outside_lists_paths_beyond_the_scope() {
+        let scope = scoped(&["billing/", "api/charge.ts"]);
+        let cwd = Path::new("/repo");
+        let paths: Vec<String> = [
+            "/repo/billing/invoice.ts",
+            "billing",
+            "/repo/api/charge.ts",
+            "docs/billing.md",
+            "/repo/billing/README.md",
+            "/repo/secrets/.env",
+            ".",
+            "/repo/billing/keys/prod.pem",
+            "../other/file",
+            "/repo/secrets/.env",
+        ]
+        .iter()
+        .map(|p| p.to_string())
+        .collect();
+        assert_eq!(
+            scope.outside(&paths, cwd),
+            [
+                "/repo/secrets/.env",
+                ".",
+                "/repo/billing/keys/prod.pem",
+                "../other/file"
+            ]
+        );
+    }
+
+    #[test]
+    fn allowed_tools_narrow_read() {
+        let scope = scoped(&["billing/"]);
+        assert_eq!(
+            scope.allowed_tools("Read, Grep").unwrap(),
+            "Read(//repo/billing/README.md),Read(//repo/billing/**),\
+             Read(//repo/docs/billing.md),Grep"
+        );
+        for tool in ["Bash", "Bash(ls:*)", "Write", "WebFetch"] {
+            let err = scope.allowed_tools(&format!("Read,{tool}")).unwrap_err();
+            assert!(err.contains(&format!("`{tool}`")), "err was: {err}");
+        }
+    }
+
+    #[test]
+    fn check_discards_replies_without_telemetry() {
+        let scope = scoped(&["billing/"]);
+        let err = scope
+            .check(Reply::plain("{\"is_valid\": true}".to_string()))
+            .unwrap_err();
+        assert!(err.contains("cannot be checked"), "err was: {err}");
+    }
+}
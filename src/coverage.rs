@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+
+use crate::marker::Marker;
+
+/// A directory or file in the coverage map.
+#[derive(Debug, Default)]
+pub struct Node {
+    /// Subdirectories and files by name (empty for files).
+    pub children: BTreeMap<String, Node>,
+    pub is_file: bool,
+    /// Markers whose `files` scope covers this file, or any file below this
+    /// directory.
+    pub markers: BTreeSet<String>,
+    /// Files at or below this node, and how many of them are covered.
+    pub files: usize,
+    pub covered: usize,
+}
+
+/// Build the coverage tree of `files` (repo-relative paths) from the `files`
+/// scopes of `markers`. Unscoped markers cover nothing in particular and are
+/// left out.
+pub fn build(files: &[String], markers: &[Marker]) -> Node {
+    let mut root = Node::default();
+    for file in files {
+        let covering: BTreeSet<String> = markers
+            .iter()
+            .filter(|m| m.watches(file))
+            .map(|m| m.name.clone())
+            .collect();
+        let covered = usize::from(!covering.is_empty());
+
+        let mut node = &mut root;
+        for part in file.split('/') {
+            node.files += 1;
+            node.covered += covered;
+            node.markers.extend(covering.iter().cloned());
+            node = node.children.entry(part.to_string()).or_default();
+        }
+        node.is_file = true;
+        node.files = 1;
+        node.covered = covered;
+        node.markers = covering;
+    }
+    root
+}
+
+fn annotation(node: &Node) -> String {
+    let names: Vec<&str> = node.markers.iter().map(String::as_str).collect();
+    if node.is_file {
+        return names.join(", ");
+    }
+    let mut out = format!("{}/{} files covered", node.covered, node.files);
+    if !names.is_empty() {
+        write!(out, ": {}", names.join(", ")).unwrap();
+    }
+    out
+}
+
+/// Render `node` as a tree named `label`, directories first. Directories
+/// deeper than `max_depth` are collapsed into their summary line.
+pub fn render(node: &Node, label: &str, max_depth: Option<usize>) -> String {
+    let mut out = String::new();
+    writeln!(out, "{label}  {}", annotation(node)).unwrap();
+    render_children(&mut out, node, "", 1, max_depth);
+    out
+}
+
+fn render_children(
+    out: &mut String,
+    node: &Node,
+    prefix: &str,
+    depth: usize,
+    max_depth: Option<usize>,
+) {
+    if max_depth.is_some_and(|max| depth > max) {
+        return;
+    }
+    let mut children: Vec<(&String, &Node)> = node.children.iter().collect();
+    children.sort_by_key(|(name, child)| (child.is_file, name.as_str()));
+    for (i, (name, child)) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let slash = if child.is_file { "" } else { "/" };
+        let note = annotation(child);
+        if note.is_empty() {
+            writeln!(out, "{prefix}{branch}{name}{slash}").unwrap();
+        } else {
+            writeln!(out, "{prefix}{branch}{name}{slash}  {note}").unwrap();
+        }
+        render_children(
+            out,
+            child,
+            &format!("{prefix}{indent}"),
+            depth + 1,
+            max_depth,
+        );
+    }
+}
+
+/// The node at `path` (repo-relative, `""` for the root).
+pub fn subtree<'a>(root: &'a Node, path: &str) -> Option<&'a Node> {
+    path.split('/')
+        .filter(|p| !p.is_empty())
+        .try_fold(root, |node, part| node.children.get(part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn marker(name: &str, files: &[&str]) -> Marker {
+        Marker {
+            name: name.to_string(),
+            rel_path: "README.md".to_string(),
+            line: 1,
+            instruction: "Check".to_string(),
+            files: files.iter().map(|f| f.to_string()).collect(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: HashMap::new(),
+        }
+    }
+
+    fn files() -> Vec<String> {
+        ["src/api/a.ts", "src/api/b.ts", "src/util.ts", "README.md"]
+            .iter()
+            .map(|f| f.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn build_counts_coverage_per_directory() {
+        let markers = [
+            marker("api", &["src/api/a.ts", "src/api/b.ts"]),
+            marker("readme", &["README.md"]),
+            marker("unscoped", &[]),
+        ];
+        let root = build(&files(), &markers);
+        assert_eq!((root.files, root.covered), (4, 3));
+        let src = subtree(&root, "src").unwrap();
+        assert_eq!((src.files, src.covered), (3, 2));
+        assert_eq!(src.markers, BTreeSet::from(["api".to_string()]));
+        assert!(subtree(&root, "src/util.ts").unwrap().markers.is_empty());
+        assert!(!root.markers.contains("unscoped"));
+    }
+
+    #[test]
+    fn render_tree_dirs_first() {
+        let root = build(&files(), &[marker("api", &["src/api/"])]);
+        let expected = "\
+.  2/4 files covered: api
+├── src/  2/3 files covered: api
+│   ├── api/  2/2 files covered: api
+│   │   ├── a.ts  api
+│   │   └── b.ts  api
+│   └── util.ts
+└── README.md
+";
+        assert_eq!(render(&root, ".", None), expected);
+    }
+
+    #[test]
+    fn render_limits_depth() {
+        let root = build(&files(), &[]);
+        let rendered = render(&root, ".", Some(1));
+        assert_eq!(rendered.lines().count(), 3);
+        assert!(rendered.contains("src/  0/3 files covered"));
+    }
+}
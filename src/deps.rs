@@ -0,0 +1,283 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::cli;
+use crate::marker::Marker;
+
+/// A package name line: `name = "serde"` (Cargo, poetry, uv),
+/// `"node_modules/left-pad": {` (npm) or `left-pad@^1.0.0:` (yarn).
+static NAME_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"^\s*(?:name = "([^"]+)"|"(?:[^"]*node_modules/)?([^"]*)": \{|"?((?:@[^@\s"/]+/)?[^@\s"]+)@[^:]*:$)"#,
+    )
+    .unwrap()
+});
+
+/// A version line: `version = "1.2.0"`,
+/// `"version": "1.2.0"` or `version "1.2.0"`.
+static VERSION_LINE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^\s*"?version"?\s*[:=]?\s*"v?(\d[^"]*)""#).unwrap());
+
+/// Lockfiles whose packages this module reads. Others (e.g. `go.sum`) are
+/// only summarized.
+const PARSED: &[&str] = &[
+    "Cargo.lock",
+    "poetry.lock",
+    "uv.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+];
+
+/// A dependency whose locked versions changed in a lockfile.
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub lockfile: String,
+    pub name: String,
+    /// Versions only the base locked.
+    pub before: Vec<String>,
+    /// Versions only the working tree locks.
+    pub after: Vec<String>,
+}
+
+impl Change {
+    pub fn kind(&self) -> &'static str {
+        match (self.before.is_empty(), self.after.is_empty()) {
+            (true, _) => "added",
+            (_, true) => "removed",
+            _ => "updated",
+        }
+    }
+}
+
+/// Whether `path` is a lockfile this module reads.
+pub fn is_lockfile(path: &str) -> bool {
+    let name = Path::new(path).file_name().and_then(|n| n.to_str());
+    name.is_some_and(|n| PARSED.contains(&n))
+}
+
+/// Locked `(name, version)` pairs of lines of a lockfile, each tagged with
+/// the side of a diff it is on: `' '` both, `'-'` the base, `'+'` the tree.
+/// A version belongs to the last name seen on its side.
+fn versions<'a>(lines: impl Iterator<Item = (u8, &'a str)>) -> Vec<(u8, String, String)> {
+    let (mut old_name, mut new_name) = (None::<String>, None::<String>);
+    let mut out = Vec::new();
+    for (side, text) in lines {
+        if let Some(caps) = NAME_LINE.captures(text) {
+            let name = caps
+                .iter()
+                .skip(1)
+                .flatten()
+                .next()
+                .map(|m| m.as_str().to_string());
+            if side != b'+' {
+                old_name.clone_from(&name);
+            }
+            if side != b'-' {
+                new_name = name;
+            }
+        } else if let Some(caps) = VERSION_LINE.captures(text) {
+            let name = if side == b'+' { &new_name } else { &old_name };
+            // The root package of an npm lockfile has an empty name.
+            if let Some(name) = name.as_ref().filter(|n| !n.is_empty()) {
+                out.push((side, name.clone(), caps[1].to_string()));
+            }
+        }
+    }
+    out
+}
+
+/// Every `(name, version)` the lockfile `text` locks.
+pub fn locked(text: &str) -> Vec<(String, String)> {
+    versions(text.lines().map(|l| (b' ', l)))
+        .into_iter()
+        .map(|(_, name, version)| (name, version))
+        .collect()
+}
+
+/// The dependency changes of the lockfiles in a unified diff that `keep`
+/// accepts, by lockfile then name.
+pub fn changes(diff: &str, keep: impl Fn(&str) -> bool) -> Vec<Change> {
+    // Lockfile -> name -> (base versions, tree versions) on changed lines.
+    let mut seen: BTreeMap<String, BTreeMap<String, [BTreeSet<String>; 2]>> = BTreeMap::new();
+    let mut section: Option<(String, Vec<(u8, &str)>)> = None;
+    let mut flush = |section: Option<(String, Vec<(u8, &str)>)>| {
+        let Some((path, lines)) = section else {
+            return;
+        };
+        let by_name = seen.entry(path).or_default();
+        for (side, name, version) in versions(lines.into_iter()) {
+            let sides = by_name.entry(name).or_default();
+            match side {
+                b'-' => sides[0].insert(version),
+                b'+' => sides[1].insert(version),
+                _ => false,
+            };
+        }
+    };
+    for line in diff.lines() {
+        if let Some(header) = line.strip_prefix("diff --git ") {
+            flush(section.take());
+            section = header
+                .rsplit_once(" b/")
+                .map(|(_, p)| p)
+                .filter(|p| is_lockfile(p) && keep(p))
+                .map(|p| (p.to_string(), Vec::new()));
+            continue;
+        }
+        let Some((_, lines)) = section.as_mut() else {
+            continue;
+        };
+        if line.starts_with("+++") || line.starts_with("---") || line.starts_with("@@") {
+            continue;
+        }
+        if let Some(&side @ (b' ' | b'+' | b'-')) = line.as_bytes().first() {
+            lines.push((side, &line[1..]));
+        }
+    }
+    flush(section);
+    let mut out = Vec::new();
+    for (lockfile, by_name) in seen {
+        for (name, [before, after]) in by_name {
+            // A package moved within the file changes nothing.
+            let change = Change {
+                lockfile: lockfile.clone(),
+                name,
+                before: before.difference(&after).cloned().collect(),
+                after: after.difference(&before).cloned().collect(),
+            };
+            if !change.before.is_empty() || !change.after.is_empty() {
+                out.push(change);
+            }
+        }
+    }
+    out
+}
+
+/// The dependency section of a `strategy="dependencies"` prompt. In diff
+/// mode, the changes to the lockfiles `marker` watches (any lockfile when
+/// it watches no files); otherwise every dependency they lock now.
+pub fn evidence(marker: &Marker, diff: Option<&str>, root: &Path) -> String {
+    let watched = |path: &str| marker.files.is_empty() || marker.watches(path);
+    let mut out = String::new();
+    let Some(diff) = diff else {
+        let files = cli::repo_files(root);
+        for path in files.iter().filter(|f| is_lockfile(f) && watched(f)) {
+            let text = fs::read_to_string(root.join(path)).unwrap_or_default();
+            let mut locked = locked(&text);
+            locked.sort();
+            locked.dedup();
+            writeln!(out, "{path}: {} locked dependencies", locked.len()).unwrap();
+            for (name, version) in locked {
+                writeln!(out, "- {name} {version}").unwrap();
+            }
+        }
+        if out.is_empty() {
+            out.push_str("No lockfile is watched.\n");
+        }
+        return out;
+    };
+    let changes = changes(diff, watched);
+    if changes.is_empty() {
+        out.push_str("No locked dependency changed.\n");
+    }
+    let mut lockfile = "";
+    for change in &changes {
+        if change.lockfile != lockfile {
+            lockfile = &change.lockfile;
+            writeln!(out, "{lockfile}:").unwrap();
+        }
+        let versions = match change.kind() {
+            "added" => change.after.join(", "),
+            "removed" => change.before.join(", "),
+            _ => format!(
+                "{} -> {}",
+                change.before.join(", "),
+                change.after.join(", ")
+            ),
+        };
+        writeln!(out, "- {} {} {versions}", change.kind(), change.name).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "diff --git a/Cargo.lock b/Cargo.lock\n\
+        --- a/Cargo.lock\n\
+        +++ b/Cargo.lock\n\
+        @@ -10,7 +10,12 @@\n \
+        [[package]]\n \
+        name = \"serde\"\n\
+        -version = \"1.0.1\"\n\
+        +version = \"1.0.2\"\n\
+        -checksum = \"aa\"\n\
+        +checksum = \"bb\"\n\
+        @@ -40,4 +45,4 @@\n\
+        -name = \"atty\"\n\
+        -version = \"0.2.14\"\n\
+        +name = \"tokio\"\n\
+        +version = \"2.0.0\"\n\
+        diff --git a/web/package-lock.json b/web/package-lock.json\n\
+        --- a/web/package-lock.json\n\
+        +++ b/web/package-lock.json\n\
+        @@ -1,6 +1,6 @@\n  \
+        \"node_modules/left-pad\": {\n\
+        -      \"version\": \"1.3.0\",\n\
+        +      \"version\": \"1.3.1\",\n\
+        diff --git a/src/main.rs b/src/main.rs\n\
+        +version = \"9\"\n";
+
+    #[test]
+    fn changes_pair_versions_with_their_package() {
+        let all = changes(DIFF, |_| true);
+        let summary: Vec<(&str, &str, &str)> = all
+            .iter()
+            .map(|c| (c.lockfile.as_str(), c.name.as_str(), c.kind()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("Cargo.lock", "atty", "removed"),
+                ("Cargo.lock", "serde", "updated"),
+                ("Cargo.lock", "tokio", "added"),
+                ("web/package-lock.json", "left-pad", "updated"),
+            ]
+        );
+        assert_eq!(all[1].before, ["1.0.1"]);
+        assert_eq!(all[1].after, ["1.0.2"]);
+        assert_eq!(changes(DIFF, |p| p == "Cargo.lock").len(), 3);
+    }
+
+    #[test]
+    fn locked_reads_whole_lockfiles() {
+        let cargo = "version = 4\n\n[[package]]\nname = \"tokio\"\nversion = \"1.38.0\"\n\
+                     source = \"registry\"\n\n[[package]]\nname = \"serde\"\nversion = \"1.0.2\"\n";
+        assert_eq!(
+            locked(cargo),
+            [
+                ("tokio".to_string(), "1.38.0".to_string()),
+                ("serde".to_string(), "1.0.2".to_string())
+            ]
+        );
+        let yarn = "\"@babel/core@^7.0.0\", \"@babel/core@^7.1.0\":\n  version \"7.24.0\"\n\
+                    left-pad@^1.3.0:\n  version \"1.3.0\"\n";
+        assert_eq!(
+            locked(yarn),
+            [
+                ("@babel/core".to_string(), "7.24.0".to_string()),
+                ("left-pad".to_string(), "1.3.0".to_string())
+            ]
+        );
+        let npm = "{\n  \"name\": \"web\",\n  \"packages\": {\n    \"\": {\n      \"version\": \"0.1.0\"\n    },\n\
+                   \x20   \"node_modules/a/node_modules/b\": {\n      \"version\": \"2.0.0\"\n    }\n  }\n}\n";
+        assert_eq!(locked(npm), [("b".to_string(), "2.0.0".to_string())]);
+    }
+}
@@ -0,0 +1,305 @@
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::config;
+use crate::marker::{Marker, ParseError};
+use crate::platform;
+use crate::redact::Redactor;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// The outcome of one environment check.
+#[derive(Debug, PartialEq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    /// How to fix a warning or failure.
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn problem(
+        name: &'static str,
+        status: Status,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Check {
+            name,
+            status,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tag = match self.status {
+            Status::Pass => "\x1b[32m[PASS]\x1b[0m",
+            Status::Warn => "\x1b[33m[WARN]\x1b[0m",
+            Status::Fail => "\x1b[31m[FAIL]\x1b[0m",
+        };
+        write!(f, "{tag} {}: {}", self.name, self.detail)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n       \x1b[90mhint: {hint}\x1b[0m")?;
+        }
+        Ok(())
+    }
+}
+
+/// Run a command in `root`, returning trimmed stdout on success.
+fn command_output(program: &str, args: &[&str], root: &Path) -> Result<String, String> {
+    let output = process::Command::new(platform::program(program))
+        .args(args)
+        .current_dir(root)
+        .env_remove("CLAUDECODE")
+        .stdin(process::Stdio::null())
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+pub fn check_git_repo(root: &Path) -> Check {
+    match command_output("git", &["rev-parse", "--show-toplevel"], root) {
+        Ok(top) => Check::pass("git repository", top),
+        Err(e) => Check::problem(
+            "git repository",
+            Status::Fail,
+            format!("not inside a git repository ({e})"),
+            "run watcher-knight from a git checkout, or pass its path as the root",
+        ),
+    }
+}
+
+pub fn check_head_parent(root: &Path) -> Check {
+    match command_output("git", &["rev-parse", "--verify", "--quiet", "HEAD^"], root) {
+        Ok(sha) => Check::pass("HEAD^ available", sha),
+        Err(_) => Check::problem(
+            "HEAD^ available",
+            Status::Warn,
+            "HEAD has no parent commit (shallow clone or first commit)",
+            "in CI fetch more history (e.g. `git fetch --deepen=1` or `fetch-depth: 0`) so \
+             --diff and ack can read earlier commits",
+        ),
+    }
+}
+
+/// Only checked in jj repositories, where diff mode runs `jj diff`.
+pub fn check_jj(root: &Path) -> Check {
+    match command_output("jj", &["--version"], root) {
+        Ok(version) => Check::pass("jj CLI", version),
+        Err(e) => Check::problem(
+            "jj CLI",
+            Status::Fail,
+            format!("this is a jj repository but `jj` cannot be run ({e})"),
+            "install Jujutsu (https://jj-vcs.github.io/jj/) so --diff can read the working-copy commit",
+        ),
+    }
+}
+
+pub fn check_claude() -> Check {
+    match command_output("claude", &["--version"], Path::new(".")) {
+        Ok(version) => Check::pass("claude CLI", version),
+        Err(e) => Check::problem(
+            "claude CLI",
+            Status::Fail,
+            format!("`claude --version` failed: {e}"),
+            "install Claude Code (`npm install -g @anthropic-ai/claude-code`) and make sure \
+             `claude` is on PATH",
+        ),
+    }
+}
+
+/// curl sends every HTTP request (context URLs, packs, workers, the shared
+/// cache, uploads, tickets, `run --pr`, `self-update`). Validation itself
+/// works without it.
+pub fn check_curl() -> Check {
+    match command_output("curl", &["--version"], Path::new(".")) {
+        // `curl 8.5.0 (x86_64-pc-linux-gnu) libcurl/8.5.0 ...`
+        Ok(version) => Check::pass("curl", version.split(" (").next().unwrap_or_default()),
+        Err(e) => Check::problem(
+            "curl",
+            Status::Warn,
+            format!("`curl --version` failed: {e}"),
+            "install curl and make sure it is on PATH; features that fetch or send anything \
+             over HTTP need it",
+        ),
+    }
+}
+
+/// Environment variables that authenticate `claude` without an interactive login.
+const AUTH_ENV_VARS: &[&str] = &[
+    "ANTHROPIC_API_KEY",
+    "ANTHROPIC_AUTH_TOKEN",
+    "CLAUDE_CODE_OAUTH_TOKEN",
+    "CLAUDE_CODE_USE_BEDROCK",
+    "CLAUDE_CODE_USE_VERTEX",
+];
+
+/// Look for credentials without making an API call: an auth environment
+/// variable or a stored login under `claude_dir`.
+pub fn check_auth(var: impl Fn(&str) -> Option<String>, claude_dir: Option<&Path>) -> Check {
+    if let Some(name) = AUTH_ENV_VARS
+        .iter()
+        .find(|v| var(v).is_some_and(|val| !val.is_empty()))
+    {
+        return Check::pass("API auth", format!("{name} is set"));
+    }
+    if let Some(dir) = claude_dir
+        && dir.join(".credentials.json").is_file()
+    {
+        return Check::pass("API auth", format!("stored login in {}", dir.display()));
+    }
+    Check::problem(
+        "API auth",
+        Status::Warn,
+        "no API key or stored login found (the macOS keychain is not checked)",
+        "set ANTHROPIC_API_KEY, or run `claude` once and log in",
+    )
+}
+
+/// Default location of the `claude` CLI's stored login.
+pub fn claude_config_dir() -> Option<PathBuf> {
+    env::var_os("CLAUDE_CONFIG_DIR")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".claude")))
+}
+
+pub fn check_config(root: &Path) -> Check {
+    let name = "config file";
+    if !root.join(config::CONFIG_FILE).exists() {
+        return Check::pass(name, format!("no {} (defaults apply)", config::CONFIG_FILE));
+    }
+    let config = match config::load_config(root) {
+        Ok(config) => config,
+        Err(e) => {
+            return Check::problem(
+                name,
+                Status::Fail,
+                e,
+                "fix the reported key or value; see the Configuration section of the README",
+            );
+        }
+    };
+    if let Err(e) = Redactor::new(&config.redact_patterns) {
+        return Check::problem(
+            name,
+            Status::Fail,
+            e,
+            "redact_patterns must be valid regular expressions",
+        );
+    }
+    Check::pass(name, format!("{} is valid", config::CONFIG_FILE))
+}
+
+pub fn check_markers(markers: &[Marker], errors: &[ParseError]) -> Check {
+    let name = "marker parsing";
+    if errors.is_empty() {
+        return Check::pass(name, format!("{} markers, no parse errors", markers.len()));
+    }
+    let first = &errors[0];
+    Check::problem(
+        name,
+        Status::Fail,
+        format!(
+            "{} markers, {} parse errors (first: {}:{}: {})",
+            markers.len(),
+            errors.len(),
+            first.file,
+            first.line,
+            first.message
+        ),
+        "run `watcher-knight lint` for the full list",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn check_auth_env_var() {
+        let check = check_auth(
+            |v| (v == "ANTHROPIC_API_KEY").then(|| "sk-x".to_string()),
+            None,
+        );
+        assert_eq!(check.status, Status::Pass);
+        assert!(check.detail.contains("ANTHROPIC_API_KEY"));
+    }
+
+    #[test]
+    fn check_auth_stored_login() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".credentials.json"), "{}").unwrap();
+        let check = check_auth(|_| None, Some(dir.path()));
+        assert_eq!(check.status, Status::Pass);
+    }
+
+    #[test]
+    fn check_auth_missing_warns_with_hint() {
+        let dir = tempfile::tempdir().unwrap();
+        let check = check_auth(|_| Some(String::new()), Some(dir.path()));
+        assert_eq!(check.status, Status::Warn);
+        assert!(check.hint.is_some());
+    }
+
+    #[test]
+    fn check_config_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(config::CONFIG_FILE), "bogus = 1").unwrap();
+        assert_eq!(check_config(dir.path()).status, Status::Fail);
+        fs::write(
+            dir.path().join(config::CONFIG_FILE),
+            "redact_patterns = [\"(\"]",
+        )
+        .unwrap();
+        assert_eq!(check_config(dir.path()).status, Status::Fail);
+        fs::remove_file(dir.path().join(config::CONFIG_FILE)).unwrap();
+        assert_eq!(check_config(dir.path()).status, Status::Pass);
+    }
+
+    #[test]
+    fn check_markers_reports_first_error() {
+        let errors = vec![ParseError {
+            file: "a.ts".to_string(),
+            line: 3,
+            message: "unclosed tag".to_string(),
+            unclosed: true,
+        }];
+        let check = check_markers(&[], &errors);
+        assert_eq!(check.status, Status::Fail);
+        assert!(
+            check.detail.contains("a.ts:3: unclosed tag"),
+            "{}",
+            check.detail
+        );
+    }
+
+    #[test]
+    fn check_git_repo_outside_repo_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(check_git_repo(dir.path()).status, Status::Fail);
+    }
+}
@@ -0,0 +1,15 @@
+// Exit codes of `watcher-knight`, documented in the README so wrapper scripts
+// can branch on them. 0 means every watcher passed; when several of these
+// apply to a run, the lowest wins.
+
+/// Invariant violations: failing watchers, or a policy (protected paths,
+/// required markers, a post-processor) failed the run.
+pub const VIOLATIONS: i32 = 1;
+/// Malformed markers, or (with `--strict`) file list entries matching no files.
+pub const MALFORMED_MARKERS: i32 = 2;
+/// Infrastructure errors: the AI backend, a worker, a checker, a hook or the
+/// VCS failed.
+pub const BACKEND_ERROR: i32 = 3;
+/// Configuration errors: invalid arguments, `watcher-knight.toml`, policy,
+/// waiver or ack files.
+pub const CONFIG_ERROR: i32 = 4;
@@ -0,0 +1,264 @@
+use crate::marker::{self, TagParts};
+
+/// Lines are wrapped to fit this many columns where possible.
+const WIDTH: usize = 100;
+
+/// Canonical order of metadata lines.
+const METADATA_KEYS: [&str; 3] = ["description", "rationale", "link"];
+
+/// Rewrite every marker in `contents` to the canonical layout and return the
+/// new contents. Tags that do not parse, or that are not in line comments
+/// (e.g. inside `/* ... */` or `<!-- ... -->`), are left as they are.
+///
+/// The canonical layout is `<wk: name [files]` on the opening line, then one
+/// `options={...}` line with sorted keys, one `context={...}` line, the
+/// metadata lines and the instruction wrapped to [`WIDTH`] columns, closed by
+/// ` />`. A marker with only a short instruction stays on a single line.
+pub fn format_source(contents: &str, rel_path: &str) -> String {
+    let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+    let (tags, _) = marker::extract_raw_tags(contents.lines(), rel_path);
+    let mut out = String::new();
+    let mut next = 0;
+    for tag in tags {
+        let (start, end) = (tag.line - 1, tag.end_line - 1);
+        // A second tag on a line was kept as it is with the first one.
+        if start < next {
+            continue;
+        }
+        let Some(formatted) = format_tag(&tag.content, lines[start], lines[end], start == end)
+        else {
+            continue;
+        };
+        out.push_str(&lines[next..start].concat());
+        out.push_str(&formatted);
+        next = end + 1;
+    }
+    out.push_str(&lines[next..].concat());
+    out
+}
+
+/// The lines of one tag laid out again, with the line ending of `last`, or
+/// `None` to leave the tag alone.
+fn format_tag(content: &str, first: &str, last: &str, single_line: bool) -> Option<String> {
+    let parts = marker::split_tag(content).ok()?;
+    if parts.instruction.is_empty() {
+        return None;
+    }
+    let (col, prefix) = marker::find_tag_in_line(first)?;
+    marker::detect_comment_prefix(&first[..col])?;
+    // Doc comments keep their variant (`///`, `#:`) on every line.
+    let comment_prefix = marker::comment_lead(&first[..col])?;
+
+    let lead = first[..col].trim_end();
+    let indent = &first[..first.len() - first.trim_start().len()];
+    let continuation = format!("{indent}{comment_prefix} ");
+    let eol = &last[last.trim_end_matches(['\r', '\n']).len()..];
+    // Whatever follows `/>` on the closing line (another comment, code).
+    let from = if single_line { col } else { 0 };
+    let close = from + last[from..].find("/>")?;
+    let trailer = last[close + 2..].trim_end_matches(['\r', '\n']);
+
+    let mut head = format!(
+        "{lead} {}: {}",
+        marker::canonical_prefix(prefix),
+        parts.name
+    );
+    if !parts.files.is_empty() {
+        head.push_str(&format!(" [{}]", parts.files.join(", ")));
+    }
+
+    let paragraphs = paragraphs(&parts.instruction);
+    if has_no_fields(&parts) && paragraphs.len() == 1 {
+        let line = format!("{head} {} />{trailer}", paragraphs[0]);
+        if line.chars().count() <= WIDTH {
+            return Some(format!("{line}{eol}"));
+        }
+    }
+
+    let mut body = Vec::new();
+    if !parts.options.is_empty() {
+        let mut options = parts.options.clone();
+        options.sort_by_key(|(k, _)| *k);
+        // A later options line overrides an earlier one, as when parsing.
+        options.reverse();
+        options.dedup_by_key(|(k, _)| *k);
+        options.reverse();
+        let pairs: Vec<String> = options
+            .iter()
+            .map(|(k, v)| format!("{k}=\"{v}\""))
+            .collect();
+        body.push(format!("options={{{}}}", pairs.join(", ")));
+    }
+    if !parts.context.is_empty() {
+        body.push(format!("context={{{}}}", parts.context.join(", ")));
+    }
+    for key in METADATA_KEYS {
+        if let Some((_, value)) = parts.metadata.iter().rev().find(|(k, _)| *k == key) {
+            body.push(format!("{key}=\"{value}\""));
+        }
+    }
+    let room = WIDTH.saturating_sub(continuation.chars().count());
+    for paragraph in &paragraphs {
+        body.extend(wrap(paragraph, room));
+    }
+
+    let mut out = format!("{}{eol}", head.trim_end());
+    let last_index = body.len() - 1;
+    for (i, line) in body.iter().enumerate() {
+        out.push_str(&continuation);
+        out.push_str(line);
+        if i == last_index {
+            out.push_str(" />");
+            out.push_str(trailer);
+        }
+        out.push_str(eol);
+    }
+    Some(out)
+}
+
+fn has_no_fields(parts: &TagParts) -> bool {
+    parts.options.is_empty() && parts.context.is_empty() && parts.metadata.is_empty()
+}
+
+/// Join instruction lines into paragraphs. A line starting a list item
+/// (`- `, `* `, `1. `) starts a new one, so lists keep one item per line.
+fn paragraphs(lines: &[&str]) -> Vec<String> {
+    let mut paragraphs: Vec<String> = Vec::new();
+    for line in lines {
+        let words = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        match paragraphs.last_mut() {
+            Some(last) if !is_list_item(line) => {
+                last.push(' ');
+                last.push_str(&words);
+            }
+            _ => paragraphs.push(words),
+        }
+    }
+    paragraphs
+}
+
+fn is_list_item(line: &str) -> bool {
+    if line.starts_with("- ") || line.starts_with("* ") {
+        return true;
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    digits > 0 && line[digits..].starts_with(". ")
+}
+
+/// Greedy word wrap to `width` columns; longer words get a line of their own.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn normalizes_tag_spacing_on_one_line() {
+        let src = "let a = 1;\n//   <wk:name [./a.ts ,./b.ts]   Check   it.   />\nlet b = 2;\n";
+        assert_eq!(
+            format_source(src, "test.ts"),
+            "let a = 1;\n// <wk: name [./a.ts, ./b.ts] Check it. />\nlet b = 2;\n"
+        );
+    }
+
+    #[test]
+    fn orders_fields_and_wraps_instruction() {
+        let src = "    # <wk: w [./a.py]\n\
+                   \x20   # link=\"https://adr/7\"\n\
+                   \x20   # Every handler in this module must validate\n\
+                   \x20   # its payload with the shared schema helpers before touching the database.\n\
+                   \x20   # options={model=\"haiku\"}\n\
+                   \x20   # options={tools=\"Read\",  model=\"opus\"}\n\
+                   \x20   # rationale=\"SQL injection\" />\n";
+        let expected = "    # <wk: w [./a.py]\n\
+                        \x20   # options={model=\"opus\", tools=\"Read\"}\n\
+                        \x20   # rationale=\"SQL injection\"\n\
+                        \x20   # link=\"https://adr/7\"\n\
+                        \x20   # Every handler in this module must validate its payload with the \
+                        shared schema helpers before\n\
+                        \x20   # touching the database. />\n";
+        let formatted = format_source(src, "test.ts");
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source(&formatted, "test.ts"), formatted);
+
+        let (before, _) = marker::parse_markers(src, "a.py", Path::new("/repo"));
+        let (after, _) = marker::parse_markers(&formatted, "a.py", Path::new("/repo"));
+        assert_eq!(
+            marker::fingerprint(&before[0]),
+            marker::fingerprint(&after[0])
+        );
+        assert_eq!(before[0].metadata, after[0].metadata);
+    }
+
+    #[test]
+    fn formats_trailing_markers_and_keeps_a_second_tag() {
+        assert_eq!(
+            format_source("let x = 1; //<wk:w   Check x. />\n", "test.ts"),
+            "let x = 1; // <wk: w Check x. />\n"
+        );
+        let src = "f(); //<wk:a  First. /> <wk:b Second. />\n";
+        assert_eq!(
+            format_source(src, "test.ts"),
+            "f(); // <wk: a First. /> <wk:b Second. />\n"
+        );
+    }
+
+    #[test]
+    fn rewrites_legacy_prefix_and_case() {
+        assert_eq!(
+            format_source("# <watcher-knight: w Check it. />\n", "test.ts"),
+            "# <wk: w Check it. />\n"
+        );
+        assert_eq!(
+            format_source("//\t<WK :w Check it. />\n", "test.ts"),
+            "// <wk: w Check it. />\n"
+        );
+    }
+
+    #[test]
+    fn keeps_doc_comment_variant_on_continuation_lines() {
+        let src = "/// <wk: w\n/// options={model=\"haiku\"}\n/// Check it. />\n";
+        assert_eq!(format_source(src, "test.ts"), src);
+    }
+
+    #[test]
+    fn keeps_list_items_and_line_endings() {
+        let src = "// <wk: w\r\n// Check that:\r\n// - a\r\n// - b />\r\n";
+        assert_eq!(
+            format_source(src, "test.ts"),
+            "// <wk: w\r\n// Check that:\r\n// - a\r\n// - b />\r\n"
+        );
+        let src = "// <wk: w Check\n// that it holds. />\n";
+        assert_eq!(
+            format_source(src, "test.ts"),
+            "// <wk: w Check that it holds. />\n"
+        );
+    }
+
+    #[test]
+    fn leaves_unparsed_and_block_comment_tags_alone() {
+        let src = "<br/> // <wk: Check it. />\n/* <wk: w  x /> */";
+        assert_eq!(format_source(src, "test.ts"), src);
+        assert_eq!(
+            format_source("<!-- <wk: w   Check it. /> -->\n", "test.ts"),
+            "<!-- <wk: w Check it. /> -->\n"
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+use crate::cli::{self, GateArgs};
+use crate::{attestation, clock, config, exit_code, packs, report};
+
+/// Length of the commit hashes printed in the verdict.
+const SHORT_SHA: usize = 12;
+
+/// A detached worktree of the gated commit, removed when dropped. The gate
+/// reads the commit from it rather than the current checkout, which may be
+/// on another commit or have local changes.
+struct Worktree {
+    repo: PathBuf,
+    dir: PathBuf,
+}
+
+impl Worktree {
+    fn add(repo: &Path, commit: &str) -> Result<Self, String> {
+        let dir = env::temp_dir().join(format!("watcher-knight-gate-{}", process::id()));
+        let output = process::Command::new("git")
+            .args(["worktree", "add", "--detach", "--quiet"])
+            .arg(&dir)
+            .arg(commit)
+            .current_dir(repo)
+            .output()
+            .map_err(|e| format!("failed to run git: {e}"))?;
+        if !output.status.success() {
+            return Err(format!(
+                "git worktree add failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(Worktree {
+            repo: repo.to_path_buf(),
+            dir,
+        })
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        process::Command::new("git")
+            .args(["worktree", "remove", "--force"])
+            .arg(&self.dir)
+            .current_dir(&self.repo)
+            .output()
+            .ok();
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+fn short(sha: &str) -> &str {
+    &sha[..sha.len().min(SHORT_SHA)]
+}
+
+/// Check the attestation of `commit` against its config and watchers as
+/// checked out in `dir`.
+fn check(dir: &Path, commit: &str, policies: &[PathBuf]) -> Result<attestation::Verified, String> {
+    let key = attestation::key_from_env()
+        .ok_or_else(|| format!("{} is not set to verify it with", attestation::KEY_VAR))?;
+    let note = attestation::read(dir, commit).ok_or("no attestation")?;
+    let config = config::load_config(dir)?;
+    let (markers, _) = cli::load_markers_with_errors(dir, &config, policies);
+    let config_sha256 = fs::read(dir.join(config::CONFIG_FILE))
+        .ok()
+        .map(|data| packs::sha256_hex(&data));
+    attestation::verify(
+        &note,
+        &key,
+        commit,
+        config_sha256.as_deref(),
+        &report::markers_sha256(&markers),
+    )
+}
+
+/// `gate`: the last check on a commit's invariants before it is deployed.
+/// A commit whose attestation still holds passes at once; otherwise the gate
+/// fails with `--require-attestation`, or runs a full attested audit of the
+/// commit and exits with its code.
+pub fn run(args: &GateArgs) -> ! {
+    let root = cli::resolve_root(args.root.as_deref());
+    let Some(commit) = cli::git_output(
+        &root,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{}^{{commit}}", args.git_ref),
+        ],
+    ) else {
+        eprintln!("Error: `{}` is not a commit", args.git_ref);
+        process::exit(exit_code::CONFIG_ERROR);
+    };
+    let worktree = Worktree::add(&root, &commit).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(exit_code::BACKEND_ERROR);
+    });
+    let why = match check(&worktree.dir, &commit, &args.policies) {
+        Ok(verified) => {
+            drop(worktree);
+            println!(
+                "\x1b[32mGO\x1b[0m {} was attested on {} by watcher-knight {}",
+                short(&commit),
+                clock::date_from_unix_days(verified.attested_at.div_euclid(86_400)),
+                verified.tool_version
+            );
+            process::exit(0);
+        }
+        Err(why) => why,
+    };
+    if args.require_attestation {
+        drop(worktree);
+        println!("\x1b[31mNO-GO\x1b[0m {}: {why}", short(&commit));
+        process::exit(exit_code::VIOLATIONS);
+    }
+    eprintln!(
+        "No valid attestation of {} ({why}); validating it again",
+        short(&commit)
+    );
+    let status = env::current_exe()
+        .map_err(|e| format!("cannot find own binary: {e}"))
+        .and_then(|exe| {
+            process::Command::new(exe)
+                .arg("audit")
+                .arg(&worktree.dir)
+                .args(["--full", "--attest", "--model", &args.model])
+                .args(
+                    args.policies
+                        .iter()
+                        .flat_map(|p| [Path::new("--policy"), p]),
+                )
+                .status()
+                .map_err(|e| format!("failed to run the audit: {e}"))
+        });
+    // `process::exit` skips destructors.
+    drop(worktree);
+    let code = match status {
+        Ok(status) => status.code().unwrap_or(exit_code::BACKEND_ERROR),
+        Err(e) => {
+            eprintln!("Error: {e}");
+            exit_code::BACKEND_ERROR
+        }
+    };
+    if code == 0 {
+        println!("\x1b[32mGO\x1b[0m {} passed validation", short(&commit));
+    } else {
+        println!("\x1b[31mNO-GO\x1b[0m {} failed validation", short(&commit));
+    }
+    process::exit(code);
+}
@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::vcs::Vcs;
+
+/// Revsets tried in order for a bare `--diff`: the last public (pushed)
+/// ancestor of the working directory, where local draft work begins, then
+/// the common ancestor with the `default` branch.
+const DEFAULT_BASES: [&str; 2] = ["max(::. and public())", "ancestor(., default)"];
+
+/// Mercurial checkouts, through the `hg` CLI (`hg` feature only).
+pub struct Hg {
+    root: PathBuf,
+}
+
+impl Hg {
+    pub fn new(root: &Path) -> Self {
+        Hg {
+            root: root.to_path_buf(),
+        }
+    }
+
+    fn hg(&self, args: &[&str]) -> Result<String, String> {
+        // HGPLAIN ignores user configuration that changes output, such as
+        // aliases, colors or diff options.
+        let output = process::Command::new("hg")
+            .args(args)
+            .env("HGPLAIN", "1")
+            .current_dir(&self.root)
+            .output()
+            .map_err(|e| format!("failed to run `hg {}`: {e}", args.join(" ")))?;
+        if !output.status.success() {
+            return Err(format!(
+                "`hg {}` failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl Vcs for Hg {
+    fn default_base(&self) -> Result<String, String> {
+        DEFAULT_BASES
+            .into_iter()
+            .find_map(|revset| {
+                self.hg(&["log", "-r", revset, "-T", "{node}"])
+                    .ok()
+                    .filter(|node| !node.is_empty())
+            })
+            .ok_or_else(|| {
+                "could not find a public ancestor or the default branch. Pass a revision \
+                 explicitly: --diff <rev>"
+                    .to_string()
+            })
+    }
+
+    fn fork_point(&self, parent: &str) -> String {
+        format!("ancestor(., {parent})")
+    }
+
+    fn diff(&self, base: &str) -> Result<String, String> {
+        self.hg(&["diff", "--git", "-r", base])
+    }
+
+    fn changed_files(&self, base: &str) -> Result<Vec<String>, String> {
+        let names = self.hg(&[
+            "status",
+            "--rev",
+            base,
+            "--modified",
+            "--added",
+            "--removed",
+            "--no-status",
+        ])?;
+        Ok(names.lines().map(str::to_string).collect())
+    }
+
+    fn untracked_files(&self) -> Vec<String> {
+        self.hg(&["status", "--unknown", "--no-status"])
+            .map(|out| out.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    fn file_at(&self, base: &str, path: &str) -> Option<String> {
+        self.hg(&["cat", "-r", base, &format!("path:{path}")]).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fork_point_is_common_ancestor_revset() {
+        let hg = Hg::new(Path::new("."));
+        assert_eq!(hg.fork_point("feature-a"), "ancestor(., feature-a)");
+    }
+}
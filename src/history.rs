@@ -0,0 +1,1006 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use clap::ValueEnum;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+
+use crate::claude::WatcherResult;
+use crate::clock;
+use crate::marker::{self, Marker};
+use crate::report;
+use crate::results_diff;
+
+const HISTORY_DIR: &str = ".watcher_knight";
+const HISTORY_FILE: &str = ".watcher_knight/history.db";
+
+/// Version of the database schema, stored in `PRAGMA user_version`.
+const SCHEMA_VERSION: i64 = 3;
+
+/// Version of the `query` and `stats --trends` JSON output. Bump on breaking
+/// changes.
+pub const QUERY_SCHEMA_VERSION: u32 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started_at INTEGER NOT NULL,
+    mode TEXT NOT NULL,
+    model TEXT NOT NULL,
+    git_commit TEXT,
+    status TEXT NOT NULL,
+    branch TEXT
+);
+CREATE TABLE IF NOT EXISTS results (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    name TEXT NOT NULL,
+    location TEXT NOT NULL,
+    status TEXT NOT NULL,
+    reason TEXT,
+    cached INTEGER NOT NULL,
+    duration_ms INTEGER,
+    fingerprint TEXT
+);
+CREATE INDEX IF NOT EXISTS results_name ON results(name);
+";
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HistoryFormat {
+    Table,
+    Json,
+}
+
+/// Metadata of one `run`, stored next to its results.
+pub struct RunInfo<'a> {
+    /// `cache`, `diff` or `audit`.
+    pub mode: &'a str,
+    pub model: &'a str,
+    pub commit: Option<String>,
+    /// Branch checked out during the run (`None` on a detached HEAD).
+    pub branch: Option<String>,
+    pub passed: bool,
+}
+
+pub fn exists(root: &Path) -> bool {
+    root.join(HISTORY_FILE).is_file()
+}
+
+/// Open (creating if needed) the history database under `root`.
+pub fn open(root: &Path) -> Result<Connection, String> {
+    fs::create_dir_all(root.join(HISTORY_DIR))
+        .map_err(|e| format!("cannot create {HISTORY_DIR}: {e}"))?;
+    let conn = Connection::open(root.join(HISTORY_FILE))
+        .map_err(|e| format!("cannot open {HISTORY_FILE}: {e}"))?;
+    init(&conn)?;
+    Ok(conn)
+}
+
+fn init(conn: &Connection) -> Result<(), String> {
+    let version: i64 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| format!("cannot read {HISTORY_FILE}: {e}"))?;
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "{HISTORY_FILE} was written by a newer watcher-knight (schema {version})"
+        ));
+    }
+    // Schema 2 records the branch of each run, schema 3 the marker
+    // fingerprint of each result.
+    let migrations = [
+        (2, "ALTER TABLE runs ADD COLUMN branch TEXT"),
+        (3, "ALTER TABLE results ADD COLUMN fingerprint TEXT"),
+    ];
+    for (to, sql) in migrations {
+        if version > 0 && version < to {
+            conn.execute_batch(sql)
+                .map_err(|e| format!("cannot migrate {HISTORY_FILE}: {e}"))?;
+        }
+    }
+    conn.execute_batch(SCHEMA)
+        .and_then(|()| conn.pragma_update(None, "user_version", SCHEMA_VERSION))
+        .map_err(|e| format!("cannot initialize {HISTORY_FILE}: {e}"))
+}
+
+/// Append a run and its results. Returns the run id.
+pub fn record(
+    conn: &mut Connection,
+    run: &RunInfo,
+    results: &[WatcherResult],
+    markers: &[Marker],
+    started_at: i64,
+) -> Result<i64, String> {
+    let err = |e: rusqlite::Error| format!("cannot write {HISTORY_FILE}: {e}");
+    let tx = conn.transaction().map_err(err)?;
+    tx.execute(
+        "INSERT INTO runs (started_at, mode, model, git_commit, status, branch) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            started_at,
+            run.mode,
+            run.model,
+            run.commit,
+            if run.passed { "passed" } else { "failed" },
+            run.branch,
+        ],
+    )
+    .map_err(err)?;
+    let run_id = tx.last_insert_rowid();
+    {
+        let mut insert = tx
+            .prepare(
+                "INSERT INTO results \
+                 (run_id, name, location, status, reason, cached, duration_ms, fingerprint) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )
+            .map_err(err)?;
+        for r in results {
+            let entry = report::entry(r, markers);
+            insert
+                .execute(params![
+                    run_id,
+                    entry.name,
+                    entry.location,
+                    entry.status,
+                    entry.reason,
+                    entry.cached,
+                    r.duration_ms.map(|d| d as i64),
+                    report::marker_for(r, markers).map(marker::fingerprint),
+                ])
+                .map_err(err)?;
+        }
+    }
+    tx.commit().map_err(err)?;
+    Ok(run_id)
+}
+
+/// Move the results of watcher `old` to `new`. `fingerprints` maps each old
+/// marker fingerprint to the renamed marker's, so `--only-new` still knows
+/// the renamed watcher's failures. Returns how many results moved.
+pub fn rename(
+    conn: &mut Connection,
+    old: &str,
+    new: &str,
+    fingerprints: &[(String, String)],
+) -> Result<usize, String> {
+    let err = |e: rusqlite::Error| format!("cannot write {HISTORY_FILE}: {e}");
+    let tx = conn.transaction().map_err(err)?;
+    for (before, after) in fingerprints {
+        tx.execute(
+            "UPDATE results SET fingerprint = ?1 WHERE name = ?2 AND fingerprint = ?3",
+            params![after, old, before],
+        )
+        .map_err(err)?;
+    }
+    let moved = tx
+        .execute(
+            "UPDATE results SET name = ?1 WHERE name = ?2",
+            params![new, old],
+        )
+        .map_err(err)?;
+    tx.commit().map_err(err)?;
+    Ok(moved)
+}
+
+/// Marker fingerprints whose most recent validated result was a failure
+/// (suppressed failures included), mapped to when that run started. Only
+/// runs on `branch` count, unless it has none recorded.
+pub fn known_failures(
+    conn: &Connection,
+    branch: Option<&str>,
+) -> Result<HashMap<String, i64>, String> {
+    let err = |e: rusqlite::Error| format!("cannot query {HISTORY_FILE}: {e}");
+    let on_branch: bool = conn
+        .query_row(
+            "SELECT EXISTS (SELECT 1 FROM runs WHERE branch = ?1)",
+            [branch],
+            |row| row.get(0),
+        )
+        .map_err(err)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT r.fingerprint, r.status, runs.started_at \
+             FROM results r JOIN runs ON runs.id = r.run_id \
+             WHERE r.fingerprint IS NOT NULL AND r.status NOT IN ('not_run', 'inconclusive') \
+             AND (?1 IS NULL OR runs.branch = ?1) \
+             ORDER BY runs.started_at, runs.id",
+        )
+        .map_err(err)?;
+    let rows = stmt
+        .query_map([branch.filter(|_| on_branch)], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })
+        .map_err(err)?;
+    let mut latest = HashMap::new();
+    for row in rows {
+        let (fingerprint, status, started_at) = row.map_err(err)?;
+        latest.insert(fingerprint, (status, started_at));
+    }
+    Ok(latest
+        .into_iter()
+        .filter(|(_, (status, _))| status != "passed")
+        .map(|(fingerprint, (_, started_at))| (fingerprint, started_at))
+        .collect())
+}
+
+/// Parse an age such as `30d`, `12h` or `2w` into seconds.
+pub fn parse_age(age: &str) -> Result<i64, String> {
+    let err = || format!("invalid age `{age}`: expected a number followed by h, d or w (e.g. 30d)");
+    let (number, unit) = age.split_at(age.len().saturating_sub(1));
+    let n: i64 = number.parse().map_err(|_| err())?;
+    let unit_secs = match unit {
+        "h" => 3_600,
+        "d" => 86_400,
+        "w" => 7 * 86_400,
+        _ => return Err(err()),
+    };
+    Ok(n * unit_secs)
+}
+
+/// Which results `query` counts.
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Result statuses to count (`failed`, `passed`, `waived`, ...); empty
+    /// counts every result.
+    pub statuses: Vec<String>,
+    /// Only results from runs started at or after this unix time.
+    pub since: Option<i64>,
+    /// Only these markers; empty means all.
+    pub markers: Vec<String>,
+    /// Only markers with at least this many matching results.
+    pub min_count: usize,
+    /// Only runs on this branch (`stats --trends` and ticket filing).
+    pub branch: Option<String>,
+}
+
+/// One marker's matching results.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct QueryRow {
+    pub name: String,
+    /// Location in the most recent matching run.
+    pub location: String,
+    /// Results matching the filter.
+    pub matches: usize,
+    /// All results for the marker in the time window.
+    pub runs: usize,
+    /// Date (UTC) of the most recent match.
+    pub last_seen: String,
+    pub last_reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct QueryOutput<'a> {
+    version: u32,
+    markers: &'a [QueryRow],
+}
+
+pub fn query(conn: &Connection, filter: &Filter) -> Result<Vec<QueryRow>, String> {
+    let err = |e: rusqlite::Error| format!("cannot query {HISTORY_FILE}: {e}");
+    let mut stmt = conn
+        .prepare(
+            "SELECT r.name, r.location, r.status, r.reason, runs.started_at \
+             FROM results r JOIN runs ON runs.id = r.run_id \
+             WHERE runs.started_at >= ?1 \
+             ORDER BY runs.started_at, runs.id",
+        )
+        .map_err(err)?;
+    let rows = stmt
+        .query_map([filter.since.unwrap_or(i64::MIN)], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, i64>(4)?,
+            ))
+        })
+        .map_err(err)?;
+
+    let mut by_marker: BTreeMap<String, QueryRow> = BTreeMap::new();
+    for row in rows {
+        let (name, location, status, reason, started_at) = row.map_err(err)?;
+        if !filter.markers.is_empty() && !filter.markers.contains(&name) {
+            continue;
+        }
+        let entry = by_marker.entry(name.clone()).or_insert_with(|| QueryRow {
+            name,
+            location: String::new(),
+            matches: 0,
+            runs: 0,
+            last_seen: String::new(),
+            last_reason: None,
+        });
+        entry.runs += 1;
+        if filter.statuses.is_empty() || filter.statuses.contains(&status) {
+            entry.matches += 1;
+            entry.location = location;
+            entry.last_seen = clock::date_from_unix_days(started_at.div_euclid(86_400));
+            entry.last_reason = reason;
+        }
+    }
+
+    let mut rows: Vec<QueryRow> = by_marker
+        .into_values()
+        .filter(|r| r.matches > 0 && r.matches >= filter.min_count)
+        .collect();
+    rows.sort_by(|a, b| b.matches.cmp(&a.matches).then_with(|| a.name.cmp(&b.name)));
+    Ok(rows)
+}
+
+pub fn render_json(rows: &[QueryRow]) -> String {
+    serde_json::to_string_pretty(&QueryOutput {
+        version: QUERY_SCHEMA_VERSION,
+        markers: rows,
+    })
+    .unwrap()
+}
+
+pub fn render_table(rows: &[QueryRow]) -> String {
+    let cells: Vec<[String; 5]> = rows
+        .iter()
+        .map(|r| {
+            [
+                r.name.clone(),
+                r.matches.to_string(),
+                r.runs.to_string(),
+                r.last_seen.clone(),
+                r.location.clone(),
+            ]
+        })
+        .collect();
+    render_columns(
+        ["MARKER", "MATCHES", "RUNS", "LAST SEEN", "LOCATION"],
+        &cells,
+    )
+}
+
+/// Totals over the runs in a time window.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RunSummary {
+    pub runs: usize,
+    pub passed: usize,
+    /// Date (UTC) of the first and most recent run.
+    pub first_run: Option<String>,
+    pub last_run: Option<String>,
+}
+
+pub fn summary(conn: &Connection, since: Option<i64>) -> Result<RunSummary, String> {
+    conn.query_row(
+        "SELECT COUNT(*), COUNT(CASE WHEN status = 'passed' THEN 1 END), \
+         MIN(started_at), MAX(started_at) FROM runs WHERE started_at >= ?1",
+        [since.unwrap_or(i64::MIN)],
+        |row| {
+            let date =
+                |at: Option<i64>| at.map(|at| clock::date_from_unix_days(at.div_euclid(86_400)));
+            Ok(RunSummary {
+                runs: row.get::<_, i64>(0)? as usize,
+                passed: row.get::<_, i64>(1)? as usize,
+                first_run: date(row.get(2)?),
+                last_run: date(row.get(3)?),
+            })
+        },
+    )
+    .map_err(|e| format!("cannot query {HISTORY_FILE}: {e}"))
+}
+
+/// Result counts of the most recent run.
+#[derive(Debug, PartialEq)]
+pub struct LatestRun {
+    pub passed: bool,
+    pub total: usize,
+    /// Results by status (`passed`, `failed`, `waived`, ...).
+    pub counts: BTreeMap<String, usize>,
+}
+
+impl LatestRun {
+    pub fn count(&self, status: &str) -> usize {
+        self.counts.get(status).copied().unwrap_or(0)
+    }
+}
+
+pub fn latest_run(conn: &Connection) -> Result<Option<LatestRun>, String> {
+    let err = |e: rusqlite::Error| format!("cannot query {HISTORY_FILE}: {e}");
+    let run = conn
+        .query_row(
+            "SELECT id, status FROM runs ORDER BY started_at DESC, id DESC LIMIT 1",
+            [],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+        )
+        .optional()
+        .map_err(err)?;
+    let Some((run_id, status)) = run else {
+        return Ok(None);
+    };
+    let mut stmt = conn
+        .prepare("SELECT status, COUNT(*) FROM results WHERE run_id = ?1 GROUP BY status")
+        .map_err(err)?;
+    let counts = stmt
+        .query_map([run_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+        })
+        .map_err(err)?
+        .collect::<Result<BTreeMap<_, _>, _>>()
+        .map_err(err)?;
+    Ok(Some(LatestRun {
+        passed: status == "passed",
+        total: counts.values().sum(),
+        counts,
+    }))
+}
+
+/// The results of an earlier run, for comparing with this one.
+#[derive(Debug, PartialEq)]
+pub struct PastRun {
+    pub started_at: i64,
+    pub commit: Option<String>,
+    pub results: results_diff::Results,
+}
+
+/// The most recent run in `mode` (e.g. `audit`).
+pub fn latest_run_in(conn: &Connection, mode: &str) -> Result<Option<PastRun>, String> {
+    let err = |e: rusqlite::Error| format!("cannot query {HISTORY_FILE}: {e}");
+    let run = conn
+        .query_row(
+            "SELECT id, started_at, git_commit FROM runs WHERE mode = ?1 \
+             ORDER BY started_at DESC, id DESC LIMIT 1",
+            [mode],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(err)?;
+    let Some((run_id, started_at, commit)) = run else {
+        return Ok(None);
+    };
+    let mut stmt = conn
+        .prepare(
+            "SELECT name, location, status, reason FROM results WHERE run_id = ?1 ORDER BY rowid",
+        )
+        .map_err(err)?;
+    let results = stmt
+        .query_map([run_id], |row| {
+            Ok(results_diff::Entry {
+                name: row.get(0)?,
+                location: row.get(1)?,
+                status: row.get(2)?,
+                reason: row.get(3)?,
+            })
+        })
+        .map_err(err)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(err)?;
+    Ok(Some(PastRun {
+        started_at,
+        commit,
+        results: results_diff::Results {
+            version: report::SCHEMA_VERSION,
+            results,
+        },
+    }))
+}
+
+/// A watcher needs attention once it has failed this many runs in a row...
+const CHRONIC_STREAK: usize = 5;
+/// ...or passes less than this share of at least `CHRONIC_STREAK` runs.
+const CHRONIC_PASS_RATE: f64 = 0.5;
+
+/// Pass rate, latency and failure streaks of one watcher.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Trend {
+    pub name: String,
+    /// Location in the most recent run.
+    pub location: String,
+    /// Runs in which the watcher was validated (`not_run` and `inconclusive`
+    /// results excluded).
+    pub runs: usize,
+    pub passed: usize,
+    pub pass_rate: f64,
+    /// Mean time of fresh (uncached) validations.
+    pub mean_latency_ms: Option<u64>,
+    /// Consecutive failing runs up to the most recent one.
+    pub current_streak: usize,
+    pub longest_streak: usize,
+    /// Red for long enough to be worth fixing or deleting.
+    pub chronic: bool,
+}
+
+#[derive(Serialize)]
+struct TrendsOutput<'a> {
+    version: u32,
+    markers: &'a [Trend],
+}
+
+#[derive(Default)]
+struct TrendAcc {
+    location: String,
+    runs: usize,
+    passed: usize,
+    latency_total: u64,
+    latency_count: u64,
+    current_streak: usize,
+    longest_streak: usize,
+}
+
+/// Per-watcher trends, chronically red watchers first, then by pass rate.
+/// Waived and acknowledged results count as failures: the invariant is
+/// still broken, only its enforcement is relaxed.
+pub fn trends(conn: &Connection, filter: &Filter) -> Result<Vec<Trend>, String> {
+    let err = |e: rusqlite::Error| format!("cannot query {HISTORY_FILE}: {e}");
+    let mut stmt = conn
+        .prepare(
+            "SELECT r.name, r.location, r.status, r.duration_ms \
+             FROM results r JOIN runs ON runs.id = r.run_id \
+             WHERE runs.started_at >= ?1 AND r.status NOT IN ('not_run', 'inconclusive') \
+             AND (?2 IS NULL OR runs.branch = ?2) \
+             ORDER BY runs.started_at, runs.id",
+        )
+        .map_err(err)?;
+    let rows = stmt
+        .query_map(
+            params![filter.since.unwrap_or(i64::MIN), filter.branch],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                ))
+            },
+        )
+        .map_err(err)?;
+
+    let mut by_marker: BTreeMap<String, TrendAcc> = BTreeMap::new();
+    for row in rows {
+        let (name, location, status, duration_ms) = row.map_err(err)?;
+        if !filter.markers.is_empty() && !filter.markers.contains(&name) {
+            continue;
+        }
+        let acc = by_marker.entry(name).or_default();
+        acc.location = location;
+        acc.runs += 1;
+        if status == "passed" {
+            acc.passed += 1;
+            acc.current_streak = 0;
+        } else {
+            acc.current_streak += 1;
+            acc.longest_streak = acc.longest_streak.max(acc.current_streak);
+        }
+        if let Some(ms) = duration_ms {
+            acc.latency_total += ms as u64;
+            acc.latency_count += 1;
+        }
+    }
+
+    let mut trends: Vec<Trend> = by_marker
+        .into_iter()
+        .filter(|(_, acc)| acc.runs >= filter.min_count)
+        .map(|(name, acc)| {
+            let pass_rate = acc.passed as f64 / acc.runs as f64;
+            Trend {
+                name,
+                location: acc.location,
+                runs: acc.runs,
+                passed: acc.passed,
+                pass_rate,
+                mean_latency_ms: (acc.latency_count > 0)
+                    .then(|| acc.latency_total / acc.latency_count),
+                current_streak: acc.current_streak,
+                longest_streak: acc.longest_streak,
+                chronic: acc.current_streak >= CHRONIC_STREAK
+                    || (acc.runs >= CHRONIC_STREAK && pass_rate < CHRONIC_PASS_RATE),
+            }
+        })
+        .collect();
+    trends.sort_by(|a, b| {
+        b.chronic
+            .cmp(&a.chronic)
+            .then_with(|| a.pass_rate.total_cmp(&b.pass_rate))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(trends)
+}
+
+pub fn render_trends_json(trends: &[Trend]) -> String {
+    serde_json::to_string_pretty(&TrendsOutput {
+        version: QUERY_SCHEMA_VERSION,
+        markers: trends,
+    })
+    .unwrap()
+}
+
+/// Trends table; chronically red watchers are flagged and shown in red.
+pub fn render_trends_table(trends: &[Trend], color: bool) -> String {
+    let cells: Vec<[String; 7]> = trends
+        .iter()
+        .map(|t| {
+            [
+                t.name.clone(),
+                format!("{:.0}%", t.pass_rate * 100.0),
+                t.runs.to_string(),
+                t.mean_latency_ms
+                    .map(|ms| format!("{:.1}s", ms as f64 / 1000.0))
+                    .unwrap_or_else(|| "-".to_string()),
+                t.current_streak.to_string(),
+                t.longest_streak.to_string(),
+                if t.chronic { "chronic" } else { "" }.to_string(),
+            ]
+        })
+        .collect();
+    let table = render_columns(
+        [
+            "MARKER",
+            "PASS RATE",
+            "RUNS",
+            "MEAN LATENCY",
+            "STREAK",
+            "LONGEST",
+            "",
+        ],
+        &cells,
+    );
+    if !color {
+        return table;
+    }
+    let mut lines = table.lines();
+    let mut out = String::new();
+    writeln!(out, "{}", lines.next().unwrap_or_default()).unwrap();
+    for (line, t) in lines.zip(trends) {
+        if t.chronic {
+            writeln!(out, "\x1b[31m{line}\x1b[0m").unwrap();
+        } else {
+            writeln!(out, "{line}").unwrap();
+        }
+    }
+    out
+}
+
+/// Left-aligned columns separated by two spaces.
+pub fn render_columns<const N: usize>(header: [&str; N], cells: &[[String; N]]) -> String {
+    let mut widths = header.map(str::len);
+    for row in cells {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.len());
+        }
+    }
+    let mut out = String::new();
+    let mut line = |cols: Vec<&str>| {
+        let padded: Vec<String> = cols
+            .iter()
+            .zip(widths)
+            .map(|(c, w)| format!("{c:<w$}"))
+            .collect();
+        writeln!(out, "{}", padded.join("  ").trim_end()).unwrap();
+    };
+    line(header.to_vec());
+    for row in cells {
+        line(row.iter().map(String::as_str).collect());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86_400;
+
+    fn result(name: &str, is_valid: bool) -> WatcherResult {
+        let reason = (!is_valid).then(|| format!("{name} broke"));
+        WatcherResult::new(name, "src/a.ts:1", is_valid, reason)
+    }
+
+    fn db_with_runs(runs: &[(i64, &[(&str, bool)])]) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init(&conn).unwrap();
+        for (at, results) in runs {
+            let results: Vec<_> = results.iter().map(|(n, ok)| result(n, *ok)).collect();
+            let info = RunInfo {
+                mode: "cache",
+                model: "sonnet",
+                commit: None,
+                branch: Some("main".to_string()),
+                passed: results.iter().all(|r| r.is_valid),
+            };
+            record(&mut conn, &info, &results, &[], *at).unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn parse_age_units() {
+        assert_eq!(parse_age("30d").unwrap(), 30 * DAY);
+        assert_eq!(parse_age("12h").unwrap(), 12 * 3_600);
+        assert_eq!(parse_age("2w").unwrap(), 14 * DAY);
+        assert!(parse_age("30").is_err());
+        assert!(parse_age("d").is_err());
+    }
+
+    #[test]
+    fn query_counts_failures_in_window() {
+        let conn = db_with_runs(&[
+            (DAY, &[("a", false), ("b", true)]),
+            (40 * DAY, &[("a", false), ("b", false)]),
+            (41 * DAY, &[("a", false), ("b", true)]),
+            (42 * DAY, &[("a", false), ("b", true)]),
+        ]);
+        let filter = Filter {
+            statuses: vec!["failed".to_string()],
+            since: Some(42 * DAY - 30 * DAY),
+            markers: Vec::new(),
+            min_count: 3,
+            branch: None,
+        };
+        let rows = query(&conn, &filter).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].name, "a");
+        assert_eq!(rows[0].matches, 3);
+        assert_eq!(rows[0].runs, 3);
+        assert_eq!(rows[0].last_seen, "1970-02-12");
+        assert_eq!(rows[0].last_reason.as_deref(), Some("a broke"));
+    }
+
+    #[test]
+    fn query_filters_by_marker() {
+        let conn = db_with_runs(&[(DAY, &[("a", true), ("b", true)])]);
+        let filter = Filter {
+            markers: vec!["b".to_string()],
+            ..Filter::default()
+        };
+        let rows = query(&conn, &filter).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].name, "b");
+    }
+
+    #[test]
+    fn init_rejects_newer_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        assert!(init(&conn).unwrap_err().contains("newer"));
+    }
+
+    #[test]
+    fn init_migrates_from_schema_1() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE runs (id INTEGER PRIMARY KEY, started_at INTEGER NOT NULL, \
+             mode TEXT NOT NULL, model TEXT NOT NULL, git_commit TEXT, status TEXT NOT NULL); \
+             CREATE TABLE results (run_id INTEGER NOT NULL, name TEXT NOT NULL, \
+             location TEXT NOT NULL, status TEXT NOT NULL, reason TEXT, \
+             cached INTEGER NOT NULL, duration_ms INTEGER); \
+             INSERT INTO runs (started_at, mode, model, status) VALUES (1, 'cache', 'sonnet', 'passed'); \
+             PRAGMA user_version = 1;",
+        )
+        .unwrap();
+        init(&conn).unwrap();
+        let branch: Option<String> = conn
+            .query_row("SELECT branch FROM runs", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(branch, None);
+        let version: i64 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn known_failures_use_latest_result_per_marker() {
+        let marker = |name: &str| Marker {
+            name: name.to_string(),
+            rel_path: "src/a.ts".to_string(),
+            line: 1,
+            instruction: format!("Check {name}"),
+            files: Vec::new(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: Default::default(),
+        };
+        let markers = [marker("a"), marker("b")];
+        let mut conn = Connection::open_in_memory().unwrap();
+        init(&conn).unwrap();
+        let runs = [
+            (DAY, "main", [false, false]),
+            (2 * DAY, "main", [false, true]),
+            (3 * DAY, "feature", [true, false]),
+        ];
+        for (at, branch, [a, b]) in runs {
+            let info = RunInfo {
+                mode: "cache",
+                model: "sonnet",
+                commit: None,
+                branch: Some(branch.to_string()),
+                passed: a && b,
+            };
+            let results = [result("a", a), result("b", b)];
+            record(&mut conn, &info, &results, &markers, at).unwrap();
+        }
+        let fp = |i: usize| marker::fingerprint(&markers[i]);
+
+        let main = known_failures(&conn, Some("main")).unwrap();
+        assert_eq!(main, HashMap::from([(fp(0), 2 * DAY)]));
+        // A branch without runs falls back to every run.
+        let other = known_failures(&conn, Some("other")).unwrap();
+        assert_eq!(other, HashMap::from([(fp(1), 3 * DAY)]));
+    }
+
+    #[test]
+    fn rename_moves_results_and_fingerprints() {
+        let mut conn = db_with_runs(&[(DAY, &[("a", false), ("b", true)])]);
+        conn.execute("UPDATE results SET fingerprint = 'fa' WHERE name = 'a'", [])
+            .unwrap();
+        let fingerprints = [("fa".to_string(), "fz".to_string())];
+        assert_eq!(rename(&mut conn, "a", "z", &fingerprints).unwrap(), 1);
+        let rows: Vec<(String, Option<String>)> = conn
+            .prepare("SELECT name, fingerprint FROM results ORDER BY name")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("b".to_string(), None),
+                ("z".to_string(), Some("fz".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn trends_filter_by_branch() {
+        let mut conn = db_with_runs(&[(DAY, &[("a", false)]), (2 * DAY, &[("a", false)])]);
+        let info = RunInfo {
+            mode: "cache",
+            model: "sonnet",
+            commit: None,
+            branch: Some("feature".to_string()),
+            passed: true,
+        };
+        record(&mut conn, &info, &[result("a", true)], &[], 3 * DAY).unwrap();
+        let main = Filter {
+            branch: Some("main".to_string()),
+            ..Filter::default()
+        };
+        assert_eq!(trends(&conn, &main).unwrap()[0].current_streak, 2);
+        assert_eq!(
+            trends(&conn, &Filter::default()).unwrap()[0].current_streak,
+            0
+        );
+    }
+
+    #[test]
+    fn summary_counts_runs_in_window() {
+        let conn = db_with_runs(&[
+            (DAY, &[("a", false)]),
+            (2 * DAY, &[("a", true)]),
+            (3 * DAY, &[("a", true)]),
+        ]);
+        let summary = summary(&conn, Some(2 * DAY)).unwrap();
+        assert_eq!(summary.runs, 2);
+        assert_eq!(summary.passed, 2);
+        assert_eq!(summary.first_run.as_deref(), Some("1970-01-03"));
+        assert_eq!(summary.last_run.as_deref(), Some("1970-01-04"));
+    }
+
+    #[test]
+    fn latest_run_counts_statuses() {
+        let conn = db_with_runs(&[
+            (DAY, &[("a", true), ("b", true)]),
+            (2 * DAY, &[("a", false), ("b", true), ("c", true)]),
+        ]);
+        let latest = latest_run(&conn).unwrap().unwrap();
+        assert!(!latest.passed);
+        assert_eq!(latest.total, 3);
+        assert_eq!(latest.count("passed"), 2);
+        assert_eq!(latest.count("failed"), 1);
+        assert_eq!(latest.count("waived"), 0);
+
+        let empty = Connection::open_in_memory().unwrap();
+        init(&empty).unwrap();
+        assert_eq!(latest_run(&empty).unwrap(), None);
+    }
+
+    #[test]
+    fn latest_run_in_picks_the_mode() {
+        let mut conn = db_with_runs(&[(3 * DAY, &[("a", true)])]);
+        assert_eq!(latest_run_in(&conn, "audit").unwrap(), None);
+        let info = RunInfo {
+            mode: "audit",
+            model: "sonnet",
+            commit: Some("abc123".to_string()),
+            branch: None,
+            passed: false,
+        };
+        let results = [result("a", false), result("b", true)];
+        record(&mut conn, &info, &results, &[], DAY).unwrap();
+        let past = latest_run_in(&conn, "audit").unwrap().unwrap();
+        assert_eq!(past.started_at, DAY);
+        assert_eq!(past.commit.as_deref(), Some("abc123"));
+        let statuses: Vec<(&str, &str)> = past
+            .results
+            .results
+            .iter()
+            .map(|e| (e.name.as_str(), e.status.as_str()))
+            .collect();
+        assert_eq!(statuses, [("a", "failed"), ("b", "passed")]);
+        assert_eq!(past.results.results[0].reason.as_deref(), Some("a broke"));
+    }
+
+    #[test]
+    fn trends_compute_pass_rate_and_streaks() {
+        let conn = db_with_runs(&[
+            (DAY, &[("a", true), ("b", false)]),
+            (2 * DAY, &[("a", false), ("b", false)]),
+            (3 * DAY, &[("a", true), ("b", true)]),
+            (4 * DAY, &[("a", true), ("b", false)]),
+        ]);
+        let trends = trends(&conn, &Filter::default()).unwrap();
+        let names: Vec<&str> = trends.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["b", "a"]);
+        assert_eq!(trends[0].runs, 4);
+        assert_eq!(trends[0].passed, 1);
+        assert_eq!(trends[0].current_streak, 1);
+        assert_eq!(trends[0].longest_streak, 2);
+        assert_eq!(trends[1].pass_rate, 0.75);
+        assert_eq!(trends[1].current_streak, 0);
+    }
+
+    #[test]
+    fn trends_flag_chronic_failures() {
+        let runs: Vec<(i64, &[(&str, bool)])> = (1..=CHRONIC_STREAK as i64)
+            .map(|d| (d * DAY, &[("red", false), ("green", true)][..]))
+            .collect();
+        let conn = db_with_runs(&runs);
+        let trends = trends(&conn, &Filter::default()).unwrap();
+        assert_eq!(trends[0].name, "red");
+        assert!(trends[0].chronic);
+        assert!(!trends[1].chronic);
+    }
+
+    #[test]
+    fn trends_average_latency_of_fresh_results() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init(&conn).unwrap();
+        let info = RunInfo {
+            mode: "cache",
+            model: "sonnet",
+            commit: None,
+            branch: None,
+            passed: true,
+        };
+        for ms in [Some(1_000), Some(3_000), None] {
+            let mut r = result("a", true);
+            r.duration_ms = ms;
+            record(&mut conn, &info, &[r], &[], DAY).unwrap();
+        }
+        let trends = trends(&conn, &Filter::default()).unwrap();
+        assert_eq!(trends[0].mean_latency_ms, Some(2_000));
+    }
+
+    #[test]
+    fn render_table_aligns_columns() {
+        let rows = vec![QueryRow {
+            name: "api-align".to_string(),
+            location: "src/a.ts:1".to_string(),
+            matches: 3,
+            runs: 4,
+            last_seen: "2026-10-01".to_string(),
+            last_reason: None,
+        }];
+        let table = render_table(&rows);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "MARKER     MATCHES  RUNS  LAST SEEN   LOCATION");
+        assert_eq!(lines[1], "api-align  3        4     2026-10-01  src/a.ts:1");
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use std::sync::Mutex;
+
+use crate::config;
+use crate::context;
+
+/// Run a configured hook command through the shell in `root`, with `env` added
+/// to its environment. Its output goes straight to the terminal.
+fn shell(command: &str) -> process::Command {
+    if cfg!(windows) {
+        let mut cmd = process::Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd
+    } else {
+        let mut cmd = process::Command::new("sh");
+        cmd.args(["-c", command]);
+        cmd
+    }
+}
+
+pub fn run_hook(
+    which: &str,
+    command: &str,
+    root: &Path,
+    env: &[(&str, String)],
+) -> Result<(), String> {
+    let status = shell(command)
+        .current_dir(root)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        // Keep stdout for the run's own output.
+        .stdout(io::stderr())
+        .status()
+        .map_err(|e| format!("failed to run {which} hook `{command}`: {e}"))?;
+    if !status.success() {
+        return Err(format!("{which} hook `{command}` exited with {status}"));
+    }
+    Ok(())
+}
+
+/// What a post-processor decided about a run.
+#[derive(Debug, PartialEq)]
+pub struct Verdict {
+    /// Exit code 0 passes the run, anything else fails it.
+    pub passed: bool,
+    /// Annotations the post-processor printed on stdout.
+    pub annotations: String,
+}
+
+/// Feed the results JSON to a post-processor on stdin. Its exit code decides
+/// the final outcome of the run.
+pub fn run_post_processor(
+    command: &str,
+    root: &Path,
+    results_json: &str,
+) -> Result<Verdict, String> {
+    let mut child = shell(command)
+        .current_dir(root)
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run post-processor `{command}`: {e}"))?;
+    // A post-processor that ignores its input may close stdin early.
+    if let Err(e) = child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(results_json.as_bytes())
+        && e.kind() != io::ErrorKind::BrokenPipe
+    {
+        return Err(format!(
+            "failed to send results to post-processor `{command}`: {e}"
+        ));
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to wait on post-processor `{command}`: {e}"))?;
+    Ok(Verdict {
+        passed: output.status.success(),
+        annotations: String::from_utf8_lossy(&output.stdout).trim().to_string(),
+    })
+}
+
+/// The `[commands]` whose output `strategy=command-output` watchers are
+/// judged on. Each runs at most once per run, however many watchers quote it.
+pub struct Commands {
+    configured: HashMap<String, String>,
+    outputs: Mutex<HashMap<String, Result<String, String>>>,
+}
+
+impl Commands {
+    pub fn new(configured: &HashMap<String, String>) -> Self {
+        Commands {
+            configured: configured.clone(),
+            outputs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The command line configured as `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.configured.get(name).map(String::as_str)
+    }
+
+    /// The combined stdout and stderr of command `name` run in `root`, with
+    /// its exit status; a failing command is output like any other.
+    pub fn output(&self, name: &str, root: &Path) -> Result<String, String> {
+        let command = self.get(name).ok_or_else(|| {
+            format!(
+                "unknown command `{name}`: add it under [commands] in {}",
+                config::CONFIG_FILE
+            )
+        })?;
+        let mut outputs = self.outputs.lock().unwrap();
+        outputs
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                let output = shell(command)
+                    .current_dir(root)
+                    .stdin(process::Stdio::null())
+                    .output()
+                    .map_err(|e| format!("failed to run command `{command}`: {e}"))?;
+                let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+                text.push_str(&String::from_utf8_lossy(&output.stderr));
+                if !text.is_empty() && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                text.push_str(&format!("[{}]\n", output.status));
+                Ok(context::truncate(text))
+            })
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[cfg(unix)]
+    #[test]
+    fn run_hook_passes_env_and_runs_in_root() {
+        let dir = tempfile::tempdir().unwrap();
+        run_hook(
+            "pre_run",
+            "echo \"$WK_MODE\" > out.txt",
+            dir.path(),
+            &[("WK_MODE", "diff".to_string())],
+        )
+        .unwrap();
+        let out = fs::read_to_string(dir.path().join("out.txt")).unwrap();
+        assert_eq!(out.trim(), "diff");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_run_once_and_keep_failing_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let configured = HashMap::from([(
+            "tests".to_string(),
+            "echo run >> runs.txt; echo 2 failed; exit 1".to_string(),
+        )]);
+        let commands = Commands::new(&configured);
+        let first = commands.output("tests", dir.path()).unwrap();
+        assert_eq!(first, "2 failed\n[exit status: 1]\n");
+        assert_eq!(commands.output("tests", dir.path()).unwrap(), first);
+        let runs = fs::read_to_string(dir.path().join("runs.txt")).unwrap();
+        assert_eq!(runs, "run\n");
+        let err = commands.output("lint", dir.path()).unwrap_err();
+        assert!(err.contains("unknown command `lint`"), "err was: {err}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_hook_failure_is_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = run_hook("post_run", "exit 4", dir.path(), &[]).unwrap_err();
+        assert!(err.contains("post_run hook"), "err was: {err}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_post_processor_exit_code_decides() {
+        let dir = tempfile::tempdir().unwrap();
+        let veto = "grep -q '\"status\": \"waived\"' && { echo 'waived security failure'; exit 1; }; exit 0";
+        let verdict = run_post_processor(veto, dir.path(), r#"{"status": "waived"}"#).unwrap();
+        assert_eq!(
+            verdict,
+            Verdict {
+                passed: false,
+                annotations: "waived security failure".to_string(),
+            }
+        );
+        let verdict = run_post_processor(veto, dir.path(), r#"{"status": "passed"}"#).unwrap();
+        assert!(verdict.passed);
+        assert!(verdict.annotations.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_post_processor_may_ignore_its_input() {
+        let dir = tempfile::tempdir().unwrap();
+        // Bigger than a pipe buffer, so the write hits the closed pipe.
+        let results = "x".repeat(1 << 20);
+        let verdict = run_post_processor("echo ok", dir.path(), &results).unwrap();
+        assert!(verdict.passed);
+        assert_eq!(verdict.annotations, "ok");
+    }
+}
@@ -0,0 +1,420 @@
+use std::fmt::Write as _;
+
+use crate::claude::WatcherResult;
+use crate::clock;
+use crate::history::RunInfo;
+use crate::marker::Marker;
+use crate::remote;
+use crate::report::{self, ResultEntry};
+
+const STYLE: &str = r#"
+body { font: 14px/1.5 -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; margin: 0; color: #1f2328; background: #f6f8fa; }
+header { background: #24292f; color: #fff; padding: 16px 32px; }
+header h1 { margin: 0 0 4px; font-size: 20px; }
+header .meta { color: #afb8c1; font-size: 13px; }
+main { padding: 24px 32px; }
+.status { display: inline-block; padding: 2px 10px; border-radius: 12px; font-weight: 600; font-size: 12px; text-transform: uppercase; }
+.passed { background: #dafbe1; color: #1a7f37; }
+.failed { background: #ffebe9; color: #cf222e; }
+.suppressed { background: #fff8c5; color: #9a6700; }
+.not_run { background: #eaeef2; color: #57606a; }
+.inconclusive { background: #fff1e5; color: #bc4c00; }
+.cards { display: flex; gap: 12px; flex-wrap: wrap; margin-bottom: 24px; }
+.card { background: #fff; border: 1px solid #d0d7de; border-radius: 6px; padding: 12px 20px; min-width: 96px; }
+.card .n { font-size: 28px; font-weight: 600; background: none; }
+.card .label { color: #57606a; font-size: 12px; text-transform: uppercase; }
+.narrative { white-space: pre-wrap; background: #fff; border: 1px solid #d0d7de; border-radius: 6px; padding: 12px 16px; margin: 0 0 24px; }
+.filters { margin-bottom: 12px; display: flex; gap: 8px; }
+.filters input, .filters select { font: inherit; padding: 4px 8px; border: 1px solid #d0d7de; border-radius: 6px; }
+.filters input { flex: 1; max-width: 360px; }
+table { width: 100%; border-collapse: collapse; background: #fff; border: 1px solid #d0d7de; }
+th, td { text-align: left; padding: 8px 12px; border-bottom: 1px solid #d0d7de; vertical-align: top; }
+th { background: #f6f8fa; font-size: 12px; text-transform: uppercase; color: #57606a; }
+tr.result { cursor: pointer; }
+tr.result:hover { background: #f6f8fa; }
+tr.details td { background: #fbfbfc; }
+tr.details[hidden] { display: none; }
+.reason { white-space: pre-wrap; margin: 0 0 8px; }
+.note { color: #57606a; margin: 0 0 8px; }
+code, pre { font: 12px/1.45 ui-monospace, SFMono-Regular, Menlo, monospace; }
+pre.diff { background: #fff; border: 1px solid #d0d7de; border-radius: 6px; padding: 8px; overflow-x: auto; margin: 0; }
+pre.diff .add { color: #1a7f37; background: #e6ffec; display: block; }
+pre.diff .del { color: #cf222e; background: #ffebe9; display: block; }
+pre.diff .hunk { color: #8250df; display: block; }
+"#;
+
+const SCRIPT: &str = r#"
+const search = document.getElementById("search");
+const statusFilter = document.getElementById("status");
+function applyFilters() {
+  const text = search.value.toLowerCase();
+  const status = statusFilter.value;
+  for (const row of document.querySelectorAll("tr.result")) {
+    const visible = (!status || row.dataset.status === status)
+      && row.textContent.toLowerCase().includes(text);
+    row.hidden = !visible;
+    const details = row.nextElementSibling;
+    if (!visible) details.hidden = true;
+  }
+}
+search.addEventListener("input", applyFilters);
+statusFilter.addEventListener("change", applyFilters);
+for (const row of document.querySelectorAll("tr.result")) {
+  row.addEventListener("click", () => {
+    const details = row.nextElementSibling;
+    details.hidden = !details.hidden;
+  });
+}
+"#;
+
+/// A standalone HTML page (no external assets) for `--report html=FILE`:
+/// summary cards, a filterable results table, and per-watcher details with
+/// the diff hunks of its watched files. Failures start expanded.
+pub fn render(
+    results: &[WatcherResult],
+    markers: &[Marker],
+    run: &RunInfo,
+    started_at: i64,
+    diff: Option<&str>,
+    narrative: Option<&str>,
+) -> String {
+    let report = report::build_report(results, markers);
+    let status = if run.passed { "passed" } else { "failed" };
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<title>watcher-knight report</title>\n");
+    writeln!(out, "<style>{STYLE}</style>\n</head>\n<body>").unwrap();
+
+    writeln!(
+        out,
+        "<header><h1>watcher-knight report <span class=\"status {status}\">{status}</span></h1>"
+    )
+    .unwrap();
+    let mut meta = format!(
+        "{} &middot; {} mode &middot; model {}",
+        timestamp(started_at),
+        run.mode,
+        escape(run.model)
+    );
+    if let Some(commit) = &run.commit {
+        write!(meta, " &middot; commit <code>{}</code>", escape(commit)).unwrap();
+    }
+    writeln!(out, "<div class=\"meta\">{meta}</div></header>\n<main>").unwrap();
+
+    let s = &report.summary;
+    out.push_str("<div class=\"cards\">\n");
+    for (n, label, class) in [
+        (s.total, "Total", ""),
+        (s.passed, "Passed", "passed"),
+        (s.failed, "Failed", "failed"),
+        (s.suppressed, "Suppressed", "suppressed"),
+        (s.not_run, "Not run", "not_run"),
+        (s.inconclusive, "Inconclusive", "inconclusive"),
+        (s.cached, "Cached", ""),
+    ] {
+        writeln!(
+            out,
+            "<div class=\"card\"><div class=\"n {class}\">{n}</div><div class=\"label\">{label}</div></div>"
+        )
+        .unwrap();
+    }
+    out.push_str("</div>\n");
+    if let Some(narrative) = narrative {
+        writeln!(
+            out,
+            "<p class=\"narrative\">{}</p>",
+            escape(narrative.trim())
+        )
+        .unwrap();
+    }
+
+    out.push_str(
+        "<div class=\"filters\">\
+         <input id=\"search\" type=\"search\" placeholder=\"Filter by name, location or reason\">\
+         <select id=\"status\"><option value=\"\">All statuses</option>",
+    );
+    let mut statuses: Vec<&str> = report.results.iter().map(|e| e.status.as_str()).collect();
+    statuses.sort_unstable();
+    statuses.dedup();
+    for status in statuses {
+        let status = escape(status);
+        write!(out, "<option value=\"{status}\">{status}</option>").unwrap();
+    }
+    out.push_str("</select></div>\n");
+
+    out.push_str(
+        "<table>\n<thead><tr><th>Status</th><th>Watcher</th><th>Location</th><th>Reason</th></tr></thead>\n<tbody>\n",
+    );
+    for entry in &report.results {
+        let marker = markers.iter().find(|m| {
+            m.name == entry.name && format!("{}:{}", m.rel_path, m.line) == entry.location
+        });
+        write_entry(&mut out, entry, marker, diff);
+    }
+    out.push_str("</tbody>\n</table>\n</main>\n");
+    writeln!(out, "<script>{SCRIPT}</script>\n</body>\n</html>").unwrap();
+    out
+}
+
+fn write_entry(out: &mut String, entry: &ResultEntry, marker: Option<&Marker>, diff: Option<&str>) {
+    let class = status_class(&entry.status);
+    let status = escape(&entry.status);
+    let summary = entry
+        .reason
+        .as_deref()
+        .or(entry.note.as_deref())
+        .and_then(|r| r.lines().next())
+        .unwrap_or_default();
+    let cached = if entry.cached {
+        " <small>(cached)</small>"
+    } else {
+        ""
+    };
+    writeln!(
+        out,
+        "<tr class=\"result\" data-status=\"{status}\"><td><span class=\"status {class}\">{status}</span></td>\
+         <td>{}{cached}</td><td><code>{}</code></td><td>{}</td></tr>",
+        escape(&entry.name),
+        escape(&entry.location),
+        escape(summary)
+    )
+    .unwrap();
+
+    let hidden = if class == "failed" { "" } else { " hidden" };
+    write!(out, "<tr class=\"details\"{hidden}><td colspan=\"4\">").unwrap();
+    if let Some(reason) = &entry.reason {
+        write!(out, "<p class=\"reason\">{}</p>", escape(reason)).unwrap();
+    }
+    if let Some(note) = &entry.note {
+        write!(out, "<p class=\"note\">{}</p>", escape(note)).unwrap();
+    }
+    if let Some(marker) = marker {
+        write!(out, "<p class=\"note\">{}</p>", escape(&marker.instruction)).unwrap();
+    }
+    let meta = &entry.metadata;
+    if let Some(description) = &meta.description {
+        write!(out, "<p class=\"note\">{}</p>", escape(description)).unwrap();
+    }
+    if let Some(rationale) = &meta.rationale {
+        write!(out, "<p class=\"note\">Why: {}</p>", escape(rationale)).unwrap();
+    }
+    if let Some(link) = &meta.link {
+        // Only web links become anchors; anything else (e.g. `javascript:`)
+        // is shown as text.
+        if link.starts_with("https://") || link.starts_with("http://") {
+            write!(
+                out,
+                "<p class=\"note\">See: <a href=\"{0}\">{0}</a></p>",
+                escape(link)
+            )
+            .unwrap();
+        } else {
+            write!(out, "<p class=\"note\">See: {}</p>", escape(link)).unwrap();
+        }
+    }
+    if !entry.options.is_empty() {
+        let options: Vec<String> = entry
+            .options
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect();
+        write!(
+            out,
+            "<p class=\"note\">Options: <code>{}</code></p>",
+            escape(&options.join(" "))
+        )
+        .unwrap();
+    }
+    let hunks = match (diff, marker) {
+        (Some(diff), Some(marker)) => watched_hunks(diff, marker),
+        _ => String::new(),
+    };
+    if !hunks.is_empty() {
+        write!(out, "<pre class=\"diff\">{}</pre>", render_diff(&hunks)).unwrap();
+    }
+    out.push_str("</td></tr>\n");
+}
+
+/// Diff sections for the marker's watched files, or for the file holding
+/// the marker when it watches nothing in particular.
+fn watched_hunks(diff: &str, marker: &Marker) -> String {
+    if marker.files.is_empty() {
+        remote::filter_diff(diff, |path| path == marker.rel_path)
+    } else {
+        remote::slice_diff(diff, marker)
+    }
+}
+
+fn render_diff(diff: &str) -> String {
+    let mut out = String::new();
+    for line in diff.lines() {
+        let class = if line.starts_with("+++") || line.starts_with("---") {
+            None
+        } else if line.starts_with('+') {
+            Some("add")
+        } else if line.starts_with('-') {
+            Some("del")
+        } else if line.starts_with("@@") {
+            Some("hunk")
+        } else {
+            None
+        };
+        match class {
+            Some(class) => write!(out, "<span class=\"{class}\">{}</span>", escape(line)).unwrap(),
+            None => writeln!(out, "{}", escape(line)).unwrap(),
+        }
+    }
+    out
+}
+
+/// `YYYY-MM-DD HH:MM UTC` for a unix time.
+fn timestamp(unix: i64) -> String {
+    let secs = unix.rem_euclid(86_400);
+    format!(
+        "{} {:02}:{:02} UTC",
+        clock::date_from_unix_days(unix.div_euclid(86_400)),
+        secs / 3_600,
+        secs % 3_600 / 60
+    )
+}
+
+fn status_class(status: &str) -> &'static str {
+    match status {
+        "passed" => "passed",
+        "failed" => "failed",
+        "not_run" => "not_run",
+        "inconclusive" => "inconclusive",
+        _ => "suppressed",
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "diff --git a/src/a.ts b/src/a.ts
+--- a/src/a.ts
++++ b/src/a.ts
+@@ -1 +1 @@
+-old
++new
+diff --git a/src/b.ts b/src/b.ts
+--- a/src/b.ts
++++ b/src/b.ts
+@@ -1 +1 @@
+-b
++<b>
+";
+
+    fn run() -> RunInfo<'static> {
+        RunInfo {
+            mode: "diff",
+            model: "sonnet",
+            commit: Some("abc123".to_string()),
+            branch: Some("main".to_string()),
+            passed: false,
+        }
+    }
+
+    fn marker(name: &str, files: &[&str]) -> Marker {
+        Marker {
+            name: name.to_string(),
+            rel_path: "src/a.ts".to_string(),
+            line: 1,
+            instruction: "Keep <it> aligned".to_string(),
+            files: files.iter().map(|f| f.to_string()).collect(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: Default::default(),
+        }
+    }
+
+    #[test]
+    fn escape_html_special_characters() {
+        assert_eq!(
+            escape(r#"<a href="x">&'"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;"
+        );
+    }
+
+    #[test]
+    fn render_includes_summary_and_escaped_results() {
+        let results = vec![
+            WatcherResult::new("broken", "src/a.ts:1", false, Some("x < y".to_string())),
+            WatcherResult::new("fine", "src/a.ts:1", true, None),
+        ];
+        let html = render(&results, &[], &run(), 0, None, None);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<span class=\"status failed\">failed</span></h1>"));
+        assert!(html.contains("<div class=\"n failed\">1</div>"));
+        assert!(html.contains("x &lt; y"));
+        assert!(html.contains("1970-01-01 00:00 UTC &middot; diff mode"));
+        assert!(html.contains("commit <code>abc123</code>"));
+        assert!(!html.contains("<link") && !html.contains("src=\"http"));
+    }
+
+    #[test]
+    fn render_shows_watched_hunks_only() {
+        let results = vec![WatcherResult::new("b-check", "src/a.ts:1", false, None)];
+        let html = render(
+            &results,
+            &[marker("b-check", &["src/b.ts"])],
+            &run(),
+            0,
+            Some(DIFF),
+            Some("Fix <b> first."),
+        );
+        assert!(html.contains("<span class=\"add\">+&lt;b&gt;</span>"));
+        assert!(!html.contains("+new"));
+        assert!(html.contains("Keep &lt;it&gt; aligned"));
+        assert!(html.contains("<p class=\"narrative\">Fix &lt;b&gt; first.</p>"));
+    }
+
+    #[test]
+    fn unscoped_marker_shows_hunks_of_its_own_file() {
+        let hunks = watched_hunks(DIFF, &marker("a", &[]));
+        assert!(hunks.contains("+new"));
+        assert!(!hunks.contains("src/b.ts"));
+    }
+
+    #[test]
+    fn render_links_design_docs() {
+        let results = vec![WatcherResult::new("b-check", "src/a.ts:1", false, None)];
+        let mut m = marker("b-check", &[]);
+        m.metadata.link = Some("https://docs.example.com/adr/1".to_string());
+        let html = render(&results, &[m.clone()], &run(), 0, None, None);
+        assert!(html.contains(
+            "See: <a href=\"https://docs.example.com/adr/1\">https://docs.example.com/adr/1</a>"
+        ));
+        m.metadata.link = Some("javascript:alert(1)".to_string());
+        let html = render(&results, &[m], &run(), 0, None, None);
+        assert!(html.contains("See: javascript:alert(1)</p>"));
+    }
+
+    #[test]
+    fn only_failures_start_expanded() {
+        let results = vec![
+            WatcherResult::new("broken", "src/a.ts:1", false, None),
+            WatcherResult::new("fine", "src/a.ts:2", true, None),
+        ];
+        let html = render(&results, &[], &run(), 0, None, None);
+        assert_eq!(html.matches("<tr class=\"details\">").count(), 1);
+        assert_eq!(html.matches("<tr class=\"details\" hidden>").count(), 1);
+    }
+}
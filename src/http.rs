@@ -0,0 +1,317 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::children;
+
+/// Bodies larger than this are staged in a temporary file: curl caps the
+/// length of a config line.
+const INLINE_BODY: usize = 1 << 20;
+
+/// An HTTP request, sent with curl, which must be on `PATH`. The URL,
+/// headers, credentials and body all go into curl's config on stdin
+/// (`-K -`), escaped, so none of them appears in the process list or is read
+/// as a curl option. Only `http` and `https` URLs are fetched, and `file`
+/// URLs (mirrors, tests).
+pub struct Request {
+    method: String,
+    url: String,
+    config: String,
+    body: Option<String>,
+    timeout_secs: Option<u64>,
+    follow_redirects: bool,
+}
+
+/// What the server answered.
+pub struct Response {
+    pub status: u16,
+    /// Headers of the final response (after redirects and `100 Continue`).
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn new(method: &str, url: &str) -> Self {
+        Request {
+            method: method.to_string(),
+            url: url.to_string(),
+            config: String::new(),
+            body: None,
+            timeout_secs: None,
+            follow_redirects: false,
+        }
+    }
+
+    pub fn get(url: &str) -> Self {
+        Request::new("GET", url)
+    }
+
+    pub fn post(url: &str) -> Self {
+        Request::new("POST", url)
+    }
+
+    fn option(mut self, name: &str, value: &str) -> Self {
+        self.config
+            .push_str(&format!("{name} = \"{}\"\n", escape_config(value)));
+        self
+    }
+
+    /// A request header, e.g. `Accept: application/json`.
+    pub fn header(self, header: &str) -> Self {
+        self.option("header", header)
+    }
+
+    pub fn bearer(self, token: &str) -> Self {
+        self.header(&format!("Authorization: Bearer {token}"))
+    }
+
+    /// Basic authentication as `user:password`.
+    pub fn basic_auth(self, credentials: &str) -> Self {
+        self.option("user", credentials)
+    }
+
+    /// Sign the request with AWS Signature V4 (`aws:amz:<region>:<service>`)
+    /// using `access_key:secret_key`.
+    pub fn aws_sigv4(self, provider: &str, credentials: &str) -> Self {
+        self.option("aws-sigv4", provider).basic_auth(credentials)
+    }
+
+    /// A JSON body.
+    pub fn json(mut self, body: String) -> Self {
+        self.body = Some(body);
+        self.header("Content-Type: application/json")
+    }
+
+    pub fn timeout(mut self, secs: u64) -> Self {
+        self.timeout_secs = Some(secs);
+        self
+    }
+
+    /// Follow redirects. Only for fixed, trusted URLs: a redirect may lead
+    /// anywhere.
+    pub fn follow_redirects(mut self) -> Self {
+        self.follow_redirects = true;
+        self
+    }
+
+    /// The curl config lines set so far.
+    #[cfg(test)]
+    pub fn config(&self) -> &str {
+        &self.config
+    }
+
+    /// Send the request. Only a failure to reach the server is an `Err`;
+    /// any HTTP status is a [`Response`].
+    pub fn send(self) -> Result<Response, String> {
+        let mut config = self.config.clone();
+        config.push_str(&format!("url = \"{}\"\n", escape_config(&self.url)));
+        if self.method != "GET" {
+            config.push_str(&format!("request = \"{}\"\n", escape_config(&self.method)));
+        }
+        let staged = match &self.body {
+            Some(body) if body.len() > INLINE_BODY => {
+                let path = stage(body).map_err(|e| format!("cannot stage request body: {e}"))?;
+                config.push_str(&format!(
+                    "data-binary = \"@{}\"\n",
+                    escape_config(&path.display().to_string())
+                ));
+                Some(path)
+            }
+            Some(body) => {
+                config.push_str(&format!("data-binary = \"{}\"\n", escape_config(body)));
+                None
+            }
+            None => None,
+        };
+        // `file://` URLs (mirrors, tests) have neither headers nor a status.
+        let is_http = self.url.starts_with("http://") || self.url.starts_with("https://");
+        // No other scheme (`ftp`, `scp`, `smb`, ...), even through a redirect.
+        let proto = if is_http { "=https,http" } else { "=file" };
+        let mut cmd = process::Command::new("curl");
+        cmd.args(["-sS", "-K", "-", "-w", "\n%{http_code}"]);
+        cmd.args(["--proto", proto, "--proto-redir", "=https,http"]);
+        if is_http {
+            cmd.args(["-D", "-"]);
+        }
+        if let Some(secs) = self.timeout_secs {
+            cmd.args(["--max-time", &secs.to_string()]);
+        }
+        if self.follow_redirects {
+            cmd.args(["-L", "--max-redirs", "5"]);
+        }
+        cmd.stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped());
+        let output = children::spawn(&mut cmd).and_then(|(mut child, _running)| {
+            if let Err(e) = child.stdin.take().unwrap().write_all(config.as_bytes()) {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(e);
+            }
+            child.wait_with_output()
+        });
+        if let Some(path) = staged {
+            fs::remove_file(path).ok();
+        }
+        let output = output.map_err(|e| format!("failed to run curl: {e}"))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        let mut response = parse_output(&output.stdout)?;
+        if !is_http {
+            response.status = 200;
+        }
+        Ok(response)
+    }
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// The value of response header `name`, case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The response, or an error naming `what` failed, with the status and
+    /// the start of the body.
+    pub fn success(self, what: &str) -> Result<Response, String> {
+        if self.is_success() {
+            return Ok(self);
+        }
+        let text = self.text();
+        let mut detail = text.trim();
+        if let Some((end, _)) = detail.char_indices().nth(500) {
+            detail = &detail[..end];
+        }
+        Err(format!("{what} failed ({}): {detail}", self.status))
+    }
+}
+
+/// Write `body` to a fresh temporary file only this user can read.
+fn stage(body: &str) -> io::Result<std::path::PathBuf> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = env::temp_dir().join(format!(
+        "watcher-knight-body-{}-{}",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&path)?.write_all(body.as_bytes())?;
+    Ok(path)
+}
+
+/// Split curl's output (`-D -` headers, body, then `-w "\n%{http_code}"`).
+fn parse_output(stdout: &[u8]) -> Result<Response, String> {
+    let unexpected = || {
+        format!(
+            "unexpected curl output `{}`",
+            String::from_utf8_lossy(stdout)
+        )
+    };
+    let split = stdout
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .ok_or_else(unexpected)?;
+    let status = std::str::from_utf8(&stdout[split + 1..])
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .ok_or_else(unexpected)?;
+    let mut rest = &stdout[..split];
+    // Each redirect and interim response (`100 Continue`) has its own head.
+    let mut head: &[u8] = &[];
+    while rest.starts_with(b"HTTP/") {
+        match rest.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(end) => (head, rest) = (&rest[..end], &rest[end + 4..]),
+            None => (head, rest) = (rest, &[]),
+        }
+    }
+    let headers = String::from_utf8_lossy(head)
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect();
+    Ok(Response {
+        status,
+        headers,
+        body: rest.to_vec(),
+    })
+}
+
+/// Escape a value for a double-quoted curl config string.
+pub fn escape_config(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_config_quotes_json() {
+        assert_eq!(
+            escape_config("{\"reason\": \"a\nb\"}"),
+            "{\\\"reason\\\": \\\"a\\nb\\\"}"
+        );
+    }
+
+    #[test]
+    fn options_are_escaped_into_the_config() {
+        let request = Request::post("https://x.test/a")
+            .bearer("t\"0k\nheader = \"X-Evil: 1")
+            .json("{\"a\": 1}".to_string());
+        assert_eq!(
+            request.config,
+            "header = \"Authorization: Bearer t\\\"0k\\nheader = \\\"X-Evil: 1\"\n\
+             header = \"Content-Type: application/json\"\n"
+        );
+    }
+
+    #[test]
+    fn parse_output_keeps_the_final_head_and_body() {
+        let stdout = b"HTTP/1.1 100 Continue\r\n\r\n\
+                       HTTP/1.1 301 Moved\r\nLocation: /b\r\n\r\n\
+                       HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Type: text/plain\r\n\r\n\
+                       line one\nline two\n\n200";
+        let response = parse_output(stdout).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("etag"), Some("\"v1\""));
+        assert_eq!(response.header("location"), None);
+        assert_eq!(response.text(), "line one\nline two\n");
+        assert!(parse_output(b"garbage").is_err());
+    }
+
+    #[test]
+    fn send_refuses_other_schemes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("feed"), "ok").unwrap();
+        let url = format!("file://{}", dir.path().join("feed").display());
+        assert_eq!(Request::get(&url).send().unwrap().text(), "ok");
+        let Err(err) = Request::get("dict://127.0.0.1:1/x").send() else {
+            panic!("dict:// was fetched");
+        };
+        assert!(err.contains("dict"), "err was: {err}");
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::marker::{self, Marker, ParseError, RawTag};
+use crate::profile::{self, Phase};
+
+const INDEX_DIR: &str = ".watcher_knight";
+const INDEX_FILE: &str = ".watcher_knight/index";
+
+/// Version of the index format. Bump on changes to it or to tag extraction;
+/// an index of another version is discarded.
+const INDEX_VERSION: u32 = 1;
+
+/// The tags extracted from one file, and what identified its contents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// Modification time in nanoseconds since the epoch.
+    mtime: u128,
+    size: u64,
+    /// SHA-256 of the contents, checked when the mtime or size changed.
+    hash: String,
+    tags: Vec<RawTag>,
+    errors: Vec<ParseError>,
+}
+
+/// Tags per file from earlier scans, in `.watcher_knight/index`. A scan only
+/// reads files whose size, mtime and hash changed since.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Index {
+    version: u32,
+    /// Custom tag prefixes the tags were extracted with.
+    #[serde(default)]
+    prefixes: Vec<String>,
+    files: BTreeMap<String, Entry>,
+    /// Modification time of the index file in nanoseconds since the epoch.
+    #[serde(skip)]
+    written: u128,
+    /// Files visited by this scan; the others are dropped on save.
+    #[serde(skip)]
+    seen: BTreeMap<String, Entry>,
+}
+
+impl Index {
+    /// The index of `root`, or an empty one if it is missing, unreadable, of
+    /// another version or built for other `tag_keywords`.
+    pub fn load(root: &Path) -> Self {
+        let path = root.join(INDEX_FILE);
+        let Some(mut index) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<Index>(&data).ok())
+            .filter(|index| {
+                index.version == INDEX_VERSION && index.prefixes == marker::custom_prefixes()
+            })
+        else {
+            return Index::default();
+        };
+        index.written = fs::metadata(&path).map_or(0, |meta| mtime_nanos(&meta));
+        index
+    }
+
+    /// Save the entries of the files visited since loading. Failing to save
+    /// only costs the next scan time.
+    pub fn save(self, root: &Path) {
+        let index = Index {
+            version: INDEX_VERSION,
+            prefixes: marker::custom_prefixes()
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            files: self.seen,
+            written: 0,
+            seen: BTreeMap::new(),
+        };
+        fs::create_dir_all(root.join(INDEX_DIR)).ok();
+        fs::write(
+            root.join(INDEX_FILE),
+            serde_json::to_string(&index).unwrap(),
+        )
+        .ok();
+    }
+
+    /// Parse the markers of the file at `path`, reusing its indexed tags when
+    /// the file is unchanged.
+    pub fn parse(
+        &mut self,
+        path: &Path,
+        rel_path: &str,
+        repo_root: &Path,
+    ) -> io::Result<(Vec<Marker>, Vec<ParseError>)> {
+        let entry = self.entry(path, rel_path)?;
+        let parsed = profile::time(Phase::Parse, || {
+            marker::parse_tags(
+                entry.tags.clone(),
+                entry.errors.clone(),
+                rel_path,
+                repo_root,
+            )
+        });
+        self.seen.insert(rel_path.to_string(), entry);
+        Ok(parsed)
+    }
+
+    fn entry(&mut self, path: &Path, rel_path: &str) -> io::Result<Entry> {
+        let meta = profile::time(Phase::Read, || fs::metadata(path))?;
+        let mtime = mtime_nanos(&meta);
+        let size = meta.len();
+        let known = self.files.remove(rel_path);
+        // A file modified in the same clock tick as the index was written
+        // may have changed after it was indexed, so it is hashed again.
+        if let Some(entry) = &known
+            && entry.mtime == mtime
+            && entry.size == size
+            && mtime < self.written
+        {
+            return Ok(entry.clone());
+        }
+        let hash = profile::time(Phase::Read, || hash_file(path))?;
+        if let Some(entry) = known.filter(|e| e.hash == hash) {
+            return Ok(Entry {
+                mtime,
+                size,
+                ..entry
+            });
+        }
+        let (tags, errors) =
+            profile::time(Phase::Parse, || marker::extract_file_tags(path, rel_path))?;
+        Ok(Entry {
+            mtime,
+            size,
+            hash,
+            tags,
+            errors,
+        })
+    }
+}
+
+fn mtime_nanos(meta: &fs::Metadata) -> u128 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos())
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_reuses_tags_of_unchanged_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let path = root.join("a.ts");
+        fs::write(&path, "// <wk: first Check it. />\n").unwrap();
+
+        let mut index = Index::load(root);
+        let (markers, _) = index.parse(&path, "a.ts", root).unwrap();
+        assert_eq!(markers[0].name, "first");
+        index.save(root);
+
+        // Pretend the file was parsed with different contents: an unchanged
+        // mtime and size means the indexed tags are used without reading it.
+        let mut data: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(root.join(INDEX_FILE)).unwrap()).unwrap();
+        data["files"]["a.ts"]["tags"][0]["content"] = "<wk: cached Check it. ".into();
+        fs::write(root.join(INDEX_FILE), data.to_string()).unwrap();
+        let mut index = Index::load(root);
+        let (markers, _) = index.parse(&path, "a.ts", root).unwrap();
+        assert_eq!(markers[0].name, "cached");
+
+        // A changed file is parsed again.
+        fs::write(&path, "// <wk: second Check it again. />\n").unwrap();
+        let (markers, _) = index.parse(&path, "a.ts", root).unwrap();
+        assert_eq!(markers[0].name, "second");
+    }
+
+    #[test]
+    fn index_hashes_files_modified_as_it_was_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let path = root.join("a.ts");
+        fs::write(&path, "// <wk: first Check it. />\n").unwrap();
+        let mut index = Index::load(root);
+        index.parse(&path, "a.ts", root).unwrap();
+        index.save(root);
+
+        // The file may have changed after it was indexed in the same tick:
+        // its hash no longer matches, so the indexed tags are not used.
+        let mut data: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(root.join(INDEX_FILE)).unwrap()).unwrap();
+        data["files"]["a.ts"]["hash"] = "stale".into();
+        data["files"]["a.ts"]["tags"][0]["content"] = "<wk: cached Check it. ".into();
+        fs::write(root.join(INDEX_FILE), data.to_string()).unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        File::options()
+            .write(true)
+            .open(root.join(INDEX_FILE))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let mut index = Index::load(root);
+        let (markers, _) = index.parse(&path, "a.ts", root).unwrap();
+        assert_eq!(markers[0].name, "first");
+    }
+
+    #[test]
+    fn save_drops_files_not_seen() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for name in ["a.ts", "b.ts"] {
+            fs::write(root.join(name), "// <wk: w Check. />\n").unwrap();
+        }
+        let mut index = Index::load(root);
+        index.parse(&root.join("a.ts"), "a.ts", root).unwrap();
+        index.parse(&root.join("b.ts"), "b.ts", root).unwrap();
+        index.save(root);
+
+        let mut index = Index::load(root);
+        assert_eq!(index.files.len(), 2);
+        index.parse(&root.join("a.ts"), "a.ts", root).unwrap();
+        index.save(root);
+        let index = Index::load(root);
+        assert_eq!(index.files.keys().collect::<Vec<_>>(), vec!["a.ts"]);
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::marker::{self, Marker, Metadata};
+
+/// Version of the exported inventory schema. Bump on breaking changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ListFormat {
+    Text,
+    Json,
+    Yaml,
+}
+
+#[derive(Serialize)]
+pub struct Inventory {
+    pub version: u32,
+    pub markers: Vec<MarkerEntry>,
+}
+
+#[derive(Serialize)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+}
+
+/// One exported marker. Optional fields other than the metadata are only
+/// populated with `--full`.
+#[derive(Serialize)]
+pub struct MarkerEntry {
+    pub name: String,
+    pub location: Location,
+    /// Description, rationale and link, whenever the marker has them.
+    #[serde(flatten)]
+    pub metadata: Metadata,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instruction: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+pub fn build_inventory(markers: &[Marker], full: bool) -> Inventory {
+    let markers = markers
+        .iter()
+        .map(|m| MarkerEntry {
+            name: m.name.clone(),
+            location: Location {
+                file: m.rel_path.clone(),
+                line: m.line,
+            },
+            metadata: m.metadata.clone(),
+            instruction: full.then(|| m.instruction.clone()),
+            files: full.then(|| m.files.clone()),
+            options: full.then(|| m.options.clone().into_iter().collect()),
+            fingerprint: full.then(|| marker::fingerprint(m)),
+        })
+        .collect();
+    Inventory {
+        version: SCHEMA_VERSION,
+        markers,
+    }
+}
+
+pub fn render(inventory: &Inventory, format: ListFormat) -> String {
+    match format {
+        ListFormat::Json => serde_json::to_string_pretty(inventory).unwrap() + "\n",
+        ListFormat::Yaml => serde_yaml::to_string(inventory).unwrap(),
+        ListFormat::Text => render_text(inventory),
+    }
+}
+
+fn render_text(inventory: &Inventory) -> String {
+    let mut out = String::new();
+    for m in &inventory.markers {
+        writeln!(out, "{} ({}:{})", m.name, m.location.file, m.location.line).unwrap();
+        let meta = &m.metadata;
+        for (label, value) in [
+            ("description", &meta.description),
+            ("rationale", &meta.rationale),
+            ("link", &meta.link),
+        ] {
+            if let Some(value) = value {
+                writeln!(out, "    {label}: {value}").unwrap();
+            }
+        }
+        if let Some(instruction) = &m.instruction {
+            for line in instruction.lines() {
+                writeln!(out, "    {line}").unwrap();
+            }
+        }
+        if let Some(files) = m.files.as_ref().filter(|f| !f.is_empty()) {
+            writeln!(out, "    files: {}", files.join(", ")).unwrap();
+        }
+        if let Some(options) = m.options.as_ref().filter(|o| !o.is_empty()) {
+            let pairs: Vec<String> = options.iter().map(|(k, v)| format!("{k}={v}")).collect();
+            writeln!(out, "    options: {}", pairs.join(", ")).unwrap();
+        }
+        if let Some(fp) = &m.fingerprint {
+            writeln!(out, "    fingerprint: {fp}").unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn make_marker(name: &str) -> Marker {
+        Marker {
+            name: name.to_string(),
+            rel_path: "src/app.ts".to_string(),
+            line: 7,
+            instruction: "Keep it aligned.".to_string(),
+            files: vec!["src/api.ts".to_string()],
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: HashMap::from([("model".to_string(), "haiku".to_string())]),
+        }
+    }
+
+    #[test]
+    fn inventory_summary_omits_full_fields() {
+        let inv = build_inventory(&[make_marker("a")], false);
+        let json = render(&inv, ListFormat::Json);
+        let val: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(val["version"], SCHEMA_VERSION);
+        assert_eq!(val["markers"][0]["name"], "a");
+        assert_eq!(val["markers"][0]["location"]["line"], 7);
+        assert!(val["markers"][0].get("instruction").is_none());
+        assert!(val["markers"][0].get("fingerprint").is_none());
+    }
+
+    #[test]
+    fn inventory_full_includes_definition() {
+        let m = make_marker("a");
+        let inv = build_inventory(std::slice::from_ref(&m), true);
+        let json = render(&inv, ListFormat::Json);
+        let val: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let entry = &val["markers"][0];
+        assert_eq!(entry["instruction"], "Keep it aligned.");
+        assert_eq!(entry["files"][0], "src/api.ts");
+        assert_eq!(entry["options"]["model"], "haiku");
+        assert_eq!(entry["fingerprint"], marker::fingerprint(&m));
+    }
+
+    #[test]
+    fn inventory_yaml_output() {
+        let inv = build_inventory(&[make_marker("a")], true);
+        let yaml = render(&inv, ListFormat::Yaml);
+        assert!(yaml.contains("version: 1"));
+        assert!(yaml.contains("name: a"));
+        assert!(yaml.contains("file: src/app.ts"));
+    }
+
+    #[test]
+    fn inventory_text_output() {
+        let summary = render(
+            &build_inventory(&[make_marker("a")], false),
+            ListFormat::Text,
+        );
+        assert_eq!(summary, "a (src/app.ts:7)\n");
+        let full = render(
+            &build_inventory(&[make_marker("a")], true),
+            ListFormat::Text,
+        );
+        assert!(full.contains("    Keep it aligned.\n"));
+        assert!(full.contains("    files: src/api.ts\n"));
+        assert!(full.contains("    options: model=haiku\n"));
+        assert!(full.contains("    fingerprint: "));
+    }
+
+    #[test]
+    fn inventory_always_includes_metadata() {
+        let mut m = make_marker("a");
+        m.metadata.link = Some("https://docs.example.com/adr/1".to_string());
+        let inv = build_inventory(&[m], false);
+        let val: serde_json::Value = serde_json::from_str(&render(&inv, ListFormat::Json)).unwrap();
+        assert_eq!(val["markers"][0]["link"], "https://docs.example.com/adr/1");
+        assert!(val["markers"][0].get("rationale").is_none());
+        assert_eq!(
+            render(&inv, ListFormat::Text),
+            "a (src/app.ts:7)\n    link: https://docs.example.com/adr/1\n"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+
+use crate::cli;
+use crate::vcs::{self, Vcs};
+
+/// Default diff base in a jj repository: where the working-copy commit `@`
+/// forked from the trunk, so the diff holds the whole change being worked on
+/// but nothing that landed on the trunk since.
+pub const DEFAULT_BASE: &str = "heads(::@ & ::trunk())";
+
+/// Whether `root` is a Jujutsu repository (colocated with git or not).
+pub fn is_repo(root: &Path) -> bool {
+    root.join(".jj").is_dir()
+}
+
+/// The revision to diff from. A git ref such as `origin/main` is resolved to
+/// its commit id (which jj accepts) so `--diff` takes the same refs as in a
+/// plain git checkout; anything else is passed on as a jj revset.
+pub fn base_revision(root: &Path, base: &str) -> String {
+    cli::git_output(
+        root,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{base}^{{commit}}"),
+        ],
+    )
+    .unwrap_or_else(|| base.to_string())
+}
+
+pub struct Jj {
+    root: PathBuf,
+}
+
+impl Jj {
+    pub fn new(root: &Path) -> Self {
+        Jj {
+            root: root.to_path_buf(),
+        }
+    }
+
+    fn jj(&self, args: &[&str]) -> Result<String, String> {
+        vcs::command(
+            "jj",
+            &self.root,
+            &[args, &["--color", "never", "--no-pager"]].concat(),
+        )
+    }
+}
+
+impl Vcs for Jj {
+    fn default_base(&self) -> Result<String, String> {
+        Ok(DEFAULT_BASE.to_string())
+    }
+
+    fn fork_point(&self, parent: &str) -> String {
+        format!("heads(::@ & ::({}))", base_revision(&self.root, parent))
+    }
+
+    /// The changes in the working-copy commit and its ancestors since
+    /// `base`. jj snapshots the working copy into `@` before running, so new
+    /// files are included without being added.
+    fn diff(&self, base: &str) -> Result<String, String> {
+        self.jj(&[
+            "diff",
+            "--git",
+            "--from",
+            &base_revision(&self.root, base),
+            "--to",
+            "@",
+        ])
+    }
+
+    fn changed_files(&self, base: &str) -> Result<Vec<String>, String> {
+        let names = self.jj(&[
+            "diff",
+            "--name-only",
+            "--from",
+            &base_revision(&self.root, base),
+            "--to",
+            "@",
+        ])?;
+        Ok(names.lines().map(str::to_string).collect())
+    }
+
+    /// jj tracks new files automatically.
+    fn untracked_files(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn file_at(&self, base: &str, path: &str) -> Option<String> {
+        self.jj(&[
+            "file",
+            "show",
+            "-r",
+            &base_revision(&self.root, base),
+            &format!("root-file:{path:?}"),
+        ])
+        .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn is_repo_checks_for_jj_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!is_repo(dir.path()));
+        fs::create_dir(dir.path().join(".jj")).unwrap();
+        assert!(is_repo(dir.path()));
+    }
+
+    #[test]
+    fn base_revision_resolves_git_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            process::Command::new("git")
+                .args(["-c", "user.name=wk", "-c", "user.email=wk@example.com"])
+                .args(args)
+                .current_dir(dir.path())
+                .output()
+                .unwrap()
+        };
+        git(&["init", "--quiet", "-b", "main"]);
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "--quiet", "-m", "a"]);
+        let head = cli::git_output(dir.path(), &["rev-parse", "HEAD"]).unwrap();
+        assert_eq!(base_revision(dir.path(), "main"), head);
+        assert_eq!(base_revision(dir.path(), DEFAULT_BASE), DEFAULT_BASE);
+        assert_eq!(base_revision(dir.path(), "main@origin"), "main@origin");
+    }
+}
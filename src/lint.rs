@@ -0,0 +1,764 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::clock::{self, Date};
+use crate::config;
+use crate::marker::{self, Marker, ParseError};
+use crate::plugins::{self, Checkers};
+use crate::prompt::{self, Strategy};
+use crate::protected;
+use crate::script;
+use crate::waivers::{self, Waivers};
+
+/// Every rule `watcher-knight lint` reports, by ID.
+pub const RULES: &[&str] = &[
+    "parse-error",
+    "tag-style",
+    "expired-waiver",
+    "invalid-waiver",
+    "unknown-waiver",
+    "unknown-checker",
+    "invalid-when",
+    "invalid-assert",
+    "invalid-strategy",
+    "missing-file",
+    "short-instruction",
+    "vague-instruction",
+    "unscoped-watcher",
+    "duplicate-instruction",
+    "unknown-reference",
+    "unknown-rule",
+];
+
+/// `[lint]` in `watcher-knight.toml`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+    /// Rule IDs that are not reported.
+    pub disable: Vec<String>,
+    /// Instructions with fewer words are reported as `short-instruction`.
+    pub min_words: usize,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            disable: Vec::new(),
+            min_words: 5,
+        }
+    }
+}
+
+/// A problem found by `watcher-knight lint`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintIssue {
+    /// Stable rule identifier, e.g. `expired-waiver`.
+    pub rule: &'static str,
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: [{}] {}", self.location, self.rule, self.message)
+    }
+}
+
+pub fn lint_parse_errors(errors: &[ParseError]) -> Vec<LintIssue> {
+    errors
+        .iter()
+        .map(|e| LintIssue {
+            rule: "parse-error",
+            location: format!("{}:{}", e.file, e.line),
+            message: e.message.clone(),
+        })
+        .collect()
+}
+
+/// Flag tags opened in another case or spacing than `<wk: name` (or
+/// `<keyword: name` for a configured keyword), e.g. `<WK :name` or the legacy
+/// `<watcher-knight:`; they parse, but `fmt` normalizes them.
+pub fn lint_tag_style(markers: &[Marker], repo_root: &Path) -> Vec<LintIssue> {
+    let mut files: HashMap<&str, Option<String>> = HashMap::new();
+    markers
+        .iter()
+        .filter_map(|m| {
+            let contents = files
+                .entry(m.rel_path.as_str())
+                .or_insert_with(|| fs::read_to_string(repo_root.join(&m.rel_path)).ok());
+            // Policy and config-defined watchers have no tag of their own.
+            let line = contents.as_deref()?.lines().nth(m.line.checked_sub(1)?)?;
+            let written = marker::tag_opening(line).filter(|w| w.ends_with(&m.name))?;
+            let (_, prefix) = marker::find_tag_in_line(line)?;
+            let canonical = format!("{}: {}", marker::canonical_prefix(prefix), m.name);
+            (written != canonical).then(|| LintIssue {
+                rule: "tag-style",
+                location: format!("{}:{}", m.rel_path, m.line),
+                message: format!(
+                    "watcher `{}` opens with `{written}`; write `{canonical}` (`watcher-knight fmt` \
+                     rewrites it)",
+                    m.name
+                ),
+            })
+        })
+        .collect()
+}
+
+/// Flag expired waivers, waivers whose `until` is not a date and waivers for
+/// markers that no longer exist.
+pub fn lint_waivers(
+    waivers: &Waivers,
+    markers: &[Marker],
+    protected_paths: &[String],
+    today: Date,
+) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    for w in &waivers.waivers {
+        if let Err(e) = clock::parse_date(&w.until) {
+            issues.push(LintIssue {
+                rule: "invalid-waiver",
+                location: waivers::WAIVERS_FILE.to_string(),
+                message: format!(
+                    "waiver for `{}`: {e}; it never applies, and runs refuse the file",
+                    w.marker
+                ),
+            });
+        } else if w.is_expired(today) {
+            issues.push(LintIssue {
+                rule: "expired-waiver",
+                location: waivers::WAIVERS_FILE.to_string(),
+                message: format!(
+                    "waiver for `{}` expired on {}; the watcher is enforced again ({})",
+                    w.marker, w.until, w.reason
+                ),
+            });
+        }
+        if let Some(pattern) = w.marker.strip_prefix(protected::WAIVER_PREFIX) {
+            if !protected_paths.iter().any(|p| p == pattern) {
+                issues.push(LintIssue {
+                    rule: "unknown-waiver",
+                    location: waivers::WAIVERS_FILE.to_string(),
+                    message: format!(
+                        "waiver for `{}` matches no entry of protected_paths",
+                        w.marker
+                    ),
+                });
+            }
+        } else if !markers.iter().any(|m| m.name == w.marker) {
+            issues.push(LintIssue {
+                rule: "unknown-waiver",
+                location: waivers::WAIVERS_FILE.to_string(),
+                message: format!("waiver for `{}` matches no watcher", w.marker),
+            });
+        }
+    }
+    issues
+}
+
+/// Flag markers that ask for a checker plugin that cannot be found.
+pub fn lint_checkers(markers: &[Marker], checkers: &Checkers) -> Vec<LintIssue> {
+    markers
+        .iter()
+        .filter_map(|m| {
+            let checker = plugins::checker_for(m)?;
+            (!checkers.contains_key(checker)).then(|| LintIssue {
+                rule: "unknown-checker",
+                location: format!("{}:{}", m.rel_path, m.line),
+                message: format!(
+                    "watcher `{}` uses checker `{checker}` but no {}{checker} was found",
+                    m.name,
+                    plugins::PLUGIN_PREFIX
+                ),
+            })
+        })
+        .collect()
+}
+
+/// Flag `when` conditions that do not parse.
+pub fn lint_when_conditions(markers: &[Marker]) -> Vec<LintIssue> {
+    markers
+        .iter()
+        .filter_map(|m| {
+            let err = script::check_when(script::when_for(m)?).err()?;
+            Some(LintIssue {
+                rule: "invalid-when",
+                location: format!("{}:{}", m.rel_path, m.line),
+                message: err,
+            })
+        })
+        .collect()
+}
+
+/// Flag `assert` expressions that cannot be evaluated: syntax errors,
+/// unknown functions, bad regexes or globs, or a result that is not a
+/// boolean. Expressions that evaluate to `false` are left to `run`.
+pub fn lint_assertions(markers: &[Marker], repo_root: &Path) -> Vec<LintIssue> {
+    markers
+        .iter()
+        .filter_map(|m| {
+            let err = script::eval_assert(script::assert_for(m)?, m, repo_root).err()?;
+            Some(LintIssue {
+                rule: "invalid-assert",
+                location: format!("{}:{}", m.rel_path, m.line),
+                message: err,
+            })
+        })
+        .collect()
+}
+
+/// Flag unknown `strategy` values, and `command-output` watchers whose
+/// `command` is missing or not under `[commands]`.
+pub fn lint_strategies(markers: &[Marker], commands: &HashMap<String, String>) -> Vec<LintIssue> {
+    markers
+        .iter()
+        .filter_map(|m| {
+            let message = match prompt::strategy_for(m) {
+                Err(e) => e,
+                Ok(Strategy::CommandOutput) => match m.options.get("command") {
+                    None => format!(
+                        "watcher `{}` uses strategy `command-output` without a `command` option",
+                        m.name
+                    ),
+                    Some(name) if !commands.contains_key(name) => format!(
+                        "watcher `{}` uses command `{name}`, which is not under [commands] in {}",
+                        m.name,
+                        config::CONFIG_FILE
+                    ),
+                    Some(_) => return None,
+                },
+                Ok(_) => return None,
+            };
+            Some(LintIssue {
+                rule: "invalid-strategy",
+                location: format!("{}:{}", m.rel_path, m.line),
+                message,
+            })
+        })
+        .collect()
+}
+
+/// Flag file entries that match no existing path (typos, moved files).
+pub fn lint_file_entries(markers: &[Marker], repo_root: &Path) -> Vec<LintIssue> {
+    markers
+        .iter()
+        .flat_map(|m| {
+            marker::unresolved_files(m, repo_root)
+                .into_iter()
+                .map(move |entry| LintIssue {
+                    rule: "missing-file",
+                    location: format!("{}:{}", m.rel_path, m.line),
+                    message: format!(
+                        "watcher `{}` lists `{entry}`, which matches no files",
+                        m.name
+                    ),
+                })
+        })
+        .collect()
+}
+
+/// Flag disabled rule IDs that do not exist, so a typo does not go unnoticed.
+pub fn lint_config(config: &LintConfig) -> Vec<LintIssue> {
+    config
+        .disable
+        .iter()
+        .filter(|rule| !RULES.contains(&rule.as_str()))
+        .map(|rule| LintIssue {
+            rule: "unknown-rule",
+            location: config::CONFIG_FILE.to_string(),
+            message: format!("[lint] disables `{rule}`, which is not a lint rule"),
+        })
+        .collect()
+}
+
+/// Drop the issues of rules disabled in `[lint]`.
+pub fn without_disabled(issues: Vec<LintIssue>, config: &LintConfig) -> Vec<LintIssue> {
+    issues
+        .into_iter()
+        .filter(|issue| !config.disable.iter().any(|rule| rule == issue.rule))
+        .collect()
+}
+
+/// Lowercased words of an instruction, punctuation dropped.
+fn words(instruction: &str) -> Vec<String> {
+    instruction
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Flag instructions too short to say what to check, and open-ended ones
+/// ("etc", "and so on") that leave the model to guess the rest of the list.
+pub fn lint_instructions(markers: &[Marker], min_words: usize) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    for m in markers {
+        let location = format!("{}:{}", m.rel_path, m.line);
+        let words = words(&m.instruction);
+        if words.len() < min_words {
+            issues.push(LintIssue {
+                rule: "short-instruction",
+                location: location.clone(),
+                message: format!(
+                    "watcher `{}` has a {}-word instruction; say what must hold and where \
+                     (at least {min_words} words)",
+                    m.name,
+                    words.len()
+                ),
+            });
+        }
+        let open_ended =
+            words.iter().any(|w| w == "etc") || words.windows(3).any(|w| w == ["and", "so", "on"]);
+        if open_ended {
+            issues.push(LintIssue {
+                rule: "vague-instruction",
+                location,
+                message: format!(
+                    "watcher `{}` ends a list with \"etc\" or \"and so on\"; list every case \
+                     to check",
+                    m.name
+                ),
+            });
+        }
+    }
+    issues
+}
+
+/// The language of a source file, by extension.
+fn language(path: &str) -> Option<&'static str> {
+    let ext = Path::new(path)
+        .extension()?
+        .to_string_lossy()
+        .to_lowercase();
+    Some(match ext.as_str() {
+        "rs" => "Rust",
+        "py" => "Python",
+        "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" => "JavaScript/TypeScript",
+        "go" => "Go",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "rb" => "Ruby",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "cxx" | "hpp" => "C++",
+        "cs" => "C#",
+        "swift" => "Swift",
+        "php" => "PHP",
+        "scala" => "Scala",
+        "ex" | "exs" => "Elixir",
+        _ => return None,
+    })
+}
+
+/// Flag watchers without a file list in a repo with several languages: they
+/// run on every change, in whichever language, and are never cached.
+pub fn lint_unscoped(markers: &[Marker], repo_files: &[String]) -> Vec<LintIssue> {
+    let languages: BTreeSet<&str> = repo_files.iter().filter_map(|f| language(f)).collect();
+    if languages.len() < 2 {
+        return Vec::new();
+    }
+    let languages = languages.into_iter().collect::<Vec<_>>().join(", ");
+    markers
+        .iter()
+        .filter(|m| m.files.is_empty())
+        .map(|m| LintIssue {
+            rule: "unscoped-watcher",
+            location: format!("{}:{}", m.rel_path, m.line),
+            message: format!(
+                "watcher `{}` has no file list, so it runs on every change to this \
+                 multi-language repo ({languages})",
+                m.name
+            ),
+        })
+        .collect()
+}
+
+/// Flag watchers whose instruction repeats an earlier watcher's (ignoring
+/// case, whitespace and punctuation): usually a copy-paste left unedited.
+pub fn lint_duplicates(markers: &[Marker]) -> Vec<LintIssue> {
+    let mut first: HashMap<Vec<String>, &Marker> = HashMap::new();
+    let mut issues = Vec::new();
+    for m in markers {
+        let key = words(&m.instruction);
+        if key.is_empty() {
+            continue;
+        }
+        match first.get(&key) {
+            Some(original) => issues.push(LintIssue {
+                rule: "duplicate-instruction",
+                location: format!("{}:{}", m.rel_path, m.line),
+                message: format!(
+                    "watcher `{}` has the same instruction as `{}` ({}:{})",
+                    m.name, original.name, original.rel_path, original.line
+                ),
+            }),
+            None => {
+                first.insert(key, m);
+            }
+        }
+    }
+    issues
+}
+
+/// Flag `@name` references in instructions that match no watcher.
+pub fn lint_references(markers: &[Marker]) -> Vec<LintIssue> {
+    let names: BTreeSet<&str> = markers.iter().map(|m| m.name.as_str()).collect();
+    let mut issues = Vec::new();
+    for m in markers {
+        for name in marker::reference_names(&m.instruction) {
+            if !names.contains(name) {
+                issues.push(LintIssue {
+                    rule: "unknown-reference",
+                    location: format!("{}:{}", m.rel_path, m.line),
+                    message: format!(
+                        "watcher `{}` references `@{name}`, which is not a watcher",
+                        m.name
+                    ),
+                });
+            }
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::waivers::Waiver;
+    use std::collections::HashMap;
+
+    fn make_marker(name: &str) -> Marker {
+        Marker {
+            name: name.to_string(),
+            rel_path: "src/app.ts".to_string(),
+            line: 1,
+            instruction: "Check it".to_string(),
+            files: vec![],
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: HashMap::new(),
+        }
+    }
+
+    fn date(date: &str) -> Date {
+        clock::parse_date(date).unwrap()
+    }
+
+    fn waivers(entries: &[(&str, &str)]) -> Waivers {
+        Waivers {
+            waivers: entries
+                .iter()
+                .map(|(m, until)| Waiver {
+                    marker: m.to_string(),
+                    until: until.to_string(),
+                    reason: "JIRA-1".to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn tag_style_flags_case_spacing_and_legacy_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/app.ts"),
+            "// <wk: fine Check it. />\n// <WK :shouty Check it. />\n\
+             # <watcher-knight: old Check it. />\n",
+        )
+        .unwrap();
+        let mut markers: Vec<Marker> = ["fine", "shouty", "old"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Marker {
+                line: i + 1,
+                ..make_marker(name)
+            })
+            .collect();
+        // A config-defined watcher located at another watcher's tag.
+        markers.push(make_marker("virtual"));
+        let issues = lint_tag_style(&markers, dir.path());
+        let messages: Vec<&str> = issues.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "watcher `shouty` opens with `<WK :shouty`; write `<wk: shouty` (`watcher-knight \
+                 fmt` rewrites it)",
+                "watcher `old` opens with `<watcher-knight: old`; write `<wk: old` \
+                 (`watcher-knight fmt` rewrites it)",
+            ]
+        );
+        assert_eq!(issues[0].location, "src/app.ts:2");
+    }
+
+    #[test]
+    fn lint_waivers_clean() {
+        let issues = lint_waivers(
+            &waivers(&[("a", "2025-03-01")]),
+            &[make_marker("a")],
+            &[],
+            date("2025-01-01"),
+        );
+        assert!(issues.is_empty(), "issues: {issues:?}");
+    }
+
+    #[test]
+    fn lint_waivers_expired() {
+        let issues = lint_waivers(
+            &waivers(&[("a", "2025-03-01")]),
+            &[make_marker("a")],
+            &[],
+            date("2025-03-02"),
+        );
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].rule, "expired-waiver");
+        assert!(issues[0].message.contains("2025-03-01"));
+    }
+
+    #[test]
+    fn lint_waivers_bad_date() {
+        let issues = lint_waivers(
+            &waivers(&[("a", "2025-3-1")]),
+            &[make_marker("a")],
+            &[],
+            date("2025-01-01"),
+        );
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].rule, "invalid-waiver");
+        assert!(issues[0].message.contains("`2025-3-1`"));
+    }
+
+    #[test]
+    fn lint_waivers_unknown_marker() {
+        let issues = lint_waivers(
+            &waivers(&[("gone", "2099-01-01")]),
+            &[],
+            &[],
+            date("2025-01-01"),
+        );
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].rule, "unknown-waiver");
+    }
+
+    #[test]
+    fn lint_waivers_checks_protected_paths() {
+        let entries = waivers(&[("protected:billing/**", "2099-01-01")]);
+        let protected = vec!["billing/**".to_string()];
+        assert!(lint_waivers(&entries, &[], &protected, date("2025-01-01")).is_empty());
+        let issues = lint_waivers(&entries, &[], &[], date("2025-01-01"));
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.contains("protected_paths"));
+    }
+
+    #[test]
+    fn lint_parse_errors_maps_location() {
+        let issues = lint_parse_errors(&[ParseError {
+            file: "a.ts".to_string(),
+            line: 3,
+            message: "unclosed watcher tag".to_string(),
+            unclosed: true,
+        }]);
+        assert_eq!(
+            issues[0].to_string(),
+            "a.ts:3: [parse-error] unclosed watcher tag"
+        );
+    }
+
+    // ── lint_checkers ─────────────────────────────────────────────────────
+
+    #[test]
+    fn lint_checkers_flags_missing_checker() {
+        let mut known = make_marker("known");
+        known
+            .options
+            .insert("checker".to_string(), "schema".to_string());
+        let mut missing = make_marker("missing");
+        missing
+            .options
+            .insert("checker".to_string(), "nope".to_string());
+        let checkers = Checkers::from([("schema".to_string(), "/bin/true".into())]);
+
+        let issues = lint_checkers(&[known, missing, make_marker("ai")], &checkers);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].rule, "unknown-checker");
+        assert!(issues[0].message.contains("`missing`"));
+    }
+
+    // ── lint_when_conditions ──────────────────────────────────────────────
+
+    #[test]
+    fn lint_file_entries_flags_missing_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.ts"), "").unwrap();
+        let mut marker = make_marker("a");
+        marker.files = vec!["a.ts".to_string(), "b.ts".to_string()];
+        marker.context = vec!["docs/spec.md".to_string()];
+        let issues = lint_file_entries(&[marker], dir.path());
+        assert_eq!(issues.len(), 2);
+        assert!(issues[1].message.contains("`docs/spec.md`"));
+        assert_eq!(issues[0].rule, "missing-file");
+        assert_eq!(
+            issues[0].to_string(),
+            "src/app.ts:1: [missing-file] watcher `a` lists `b.ts`, which matches no files"
+        );
+    }
+
+    #[test]
+    fn lint_when_conditions_flags_syntax_errors() {
+        let mut good = make_marker("good");
+        good.options
+            .insert("when".to_string(), "diff.touches(\"src/**\")".to_string());
+        let mut bad = make_marker("bad");
+        bad.options
+            .insert("when".to_string(), "diff.touches(".to_string());
+
+        let issues = lint_when_conditions(&[good, bad, make_marker("plain")]);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].rule, "invalid-when");
+    }
+
+    #[test]
+    fn lint_assertions_flags_errors_but_not_false_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = |name: &str, expr: &str| {
+            let mut m = make_marker(name);
+            m.options.insert("assert".to_string(), expr.to_string());
+            m
+        };
+        let markers = [
+            marker("false", "exists('/missing.md')"),
+            marker("syntax", "exists("),
+            marker("regex", "contains('/a.ts', '(')"),
+            marker("unknown", "exsts('/a.ts')"),
+            marker("number", "count('/*.ts')"),
+            make_marker("plain"),
+        ];
+        let issues = lint_assertions(&markers, dir.path());
+        assert_eq!(issues.len(), 4);
+        assert!(issues.iter().all(|i| i.rule == "invalid-assert"));
+    }
+
+    #[test]
+    fn lint_strategies_flags_unknown_strategies_and_commands() {
+        let marker = |name: &str, options: &[(&str, &str)]| {
+            let mut m = make_marker(name);
+            for (k, v) in options {
+                m.options.insert(k.to_string(), v.to_string());
+            }
+            m
+        };
+        let markers = [
+            marker("typo", &[("strategy", "diff-onyl")]),
+            marker("no-command", &[("strategy", "command-output")]),
+            marker(
+                "unknown",
+                &[("strategy", "command-output"), ("command", "lint")],
+            ),
+            marker(
+                "good",
+                &[("strategy", "command-output"), ("command", "tests")],
+            ),
+            marker("inline", &[("strategy", "files-inline")]),
+            make_marker("plain"),
+        ];
+        let commands = HashMap::from([("tests".to_string(), "cargo test".to_string())]);
+        let issues = lint_strategies(&markers, &commands);
+        assert_eq!(issues.len(), 3);
+        assert!(issues.iter().all(|i| i.rule == "invalid-strategy"));
+        assert!(issues[0].message.contains("unknown strategy `diff-onyl`"));
+        assert!(issues[2].message.contains("command `lint`"));
+    }
+
+    // ── quality rules ─────────────────────────────────────────────────────
+
+    #[test]
+    fn lint_instructions_flags_short_and_vague() {
+        let short = make_marker("short");
+        let mut vague = make_marker("vague");
+        vague.instruction = "Every handler validates its input with zod, yup, etc.".to_string();
+        let mut so_on = make_marker("so-on");
+        so_on.instruction =
+            "Routes, models and so on must be registered in the index file.".to_string();
+        let mut fine = make_marker("fine");
+        fine.instruction = "Every etcd client sets a request timeout.".to_string();
+
+        let issues = lint_instructions(&[short, vague, so_on, fine], 5);
+        let rules: Vec<_> = issues.iter().map(|i| (i.rule, i.message.clone())).collect();
+        assert_eq!(issues.len(), 3, "issues: {rules:?}");
+        assert_eq!(issues[0].rule, "short-instruction");
+        assert!(issues[0].message.contains("2-word"));
+        assert_eq!(issues[1].rule, "vague-instruction");
+        assert!(issues[1].message.contains("`vague`"));
+        assert!(issues[2].message.contains("`so-on`"));
+    }
+
+    #[test]
+    fn lint_unscoped_only_in_multi_language_repos() {
+        let mut scoped = make_marker("scoped");
+        scoped.files = vec!["src/app.ts".to_string()];
+        let markers = [scoped, make_marker("global")];
+
+        let single = ["src/app.ts".to_string(), "README.md".to_string()];
+        assert!(lint_unscoped(&markers, &single).is_empty());
+
+        let multi = [single[0].clone(), "api/server.py".to_string()];
+        let issues = lint_unscoped(&markers, &multi);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].rule, "unscoped-watcher");
+        assert!(issues[0].message.contains("`global`"));
+        assert!(
+            issues[0]
+                .message
+                .contains("(JavaScript/TypeScript, Python)")
+        );
+    }
+
+    #[test]
+    fn lint_duplicates_names_the_original() {
+        let a = make_marker("a");
+        let mut b = make_marker("b");
+        b.line = 9;
+        b.instruction = "check   IT!".to_string();
+        let issues = lint_duplicates(&[a, b, make_marker("c")]);
+        assert_eq!(issues.len(), 2);
+        assert_eq!(
+            issues[0].to_string(),
+            "src/app.ts:9: [duplicate-instruction] watcher `b` has the same instruction as \
+             `a` (src/app.ts:1)"
+        );
+    }
+
+    #[test]
+    fn lint_references_flags_unknown_names() {
+        let mut a = make_marker("a");
+        a.instruction = "Keep in step with @b and @missing; ask ops@example.com.".to_string();
+        let issues = lint_references(&[a, make_marker("b")]);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].to_string(),
+            "src/app.ts:1: [unknown-reference] watcher `a` references `@missing`, which is not \
+             a watcher"
+        );
+    }
+
+    #[test]
+    fn disabled_rules_are_dropped_and_unknown_ones_reported() {
+        let config = LintConfig {
+            disable: vec![
+                "short-instruction".to_string(),
+                "short-instructions".to_string(),
+            ],
+            ..LintConfig::default()
+        };
+        let issues = lint_instructions(&[make_marker("a")], config.min_words);
+        assert_eq!(issues.len(), 1);
+        assert!(without_disabled(issues, &config).is_empty());
+        let unknown = lint_config(&config);
+        assert_eq!(unknown.len(), 1);
+        assert!(unknown[0].message.contains("`short-instructions`"));
+    }
+}
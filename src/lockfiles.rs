@@ -0,0 +1,204 @@
+use std::path::Path;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::marker::Marker;
+
+/// Files a package manager rewrites on every dependency update.
+const LOCKFILES: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "poetry.lock",
+    "Pipfile.lock",
+    "uv.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "go.sum",
+];
+
+/// A changed line pinning a version: `version = "1.2.0"` (Cargo, poetry, uv),
+/// `"version": "1.2.0"` (npm, composer), `version "1.2.0"` (yarn) or
+/// `version: 1.2.0` (pnpm).
+static VERSION_LINE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^\s*"?version"?\s*[:=]?\s*"?v?\d"#).unwrap());
+
+/// Whether changes to `path` are dependency churn: a lockfile, or code
+/// vendored under a `vendor/` directory.
+pub fn is_dependency_churn(path: &str) -> bool {
+    let name = Path::new(path).file_name().and_then(|n| n.to_str());
+    name.is_some_and(|n| LOCKFILES.contains(&n))
+        || path.starts_with("vendor/")
+        || path.contains("/vendor/")
+}
+
+/// Whether `marker` asks to see dependency churn in full
+/// (`options={lockfiles="full"}`), e.g. an invariant about pinned versions.
+pub fn wants_full_text(marker: &Marker) -> bool {
+    marker.options.get("lockfiles").is_some_and(|v| v == "full")
+}
+
+/// The diff `marker`'s prompt shows: the sections of lockfiles and vendored
+/// files are each replaced by a one-line summary, unless the marker wants
+/// the full text.
+pub fn condense(diff: &str, marker: &Marker) -> String {
+    if wants_full_text(marker) {
+        return diff.to_string();
+    }
+    summarize(diff)
+}
+
+/// Replace the hunks of every dependency churn section of `diff` with a
+/// summary of what changed.
+pub fn summarize(diff: &str) -> String {
+    let mut out = String::new();
+    // Added and removed version lines, then other added and removed lines,
+    // of the churn section being read.
+    let mut churn: Option<[usize; 4]> = None;
+    for line in diff.split_inclusive('\n') {
+        if let Some(header) = line.strip_prefix("diff --git ") {
+            if let Some(counts) = churn.take() {
+                out.push_str(&summary_line(counts));
+            }
+            out.push_str(line);
+            let path = header.trim_end().rsplit_once(" b/").map(|(_, p)| p);
+            if path.is_some_and(is_dependency_churn) {
+                churn = Some([0; 4]);
+            }
+            continue;
+        }
+        let Some(counts) = churn.as_mut() else {
+            out.push_str(line);
+            continue;
+        };
+        if line.starts_with("+++") || line.starts_with("---") {
+            continue;
+        }
+        let version = |text: &str| VERSION_LINE.is_match(text);
+        match line.as_bytes().first() {
+            Some(b'+') if version(&line[1..]) => counts[0] += 1,
+            Some(b'-') if version(&line[1..]) => counts[1] += 1,
+            Some(b'+') => counts[2] += 1,
+            Some(b'-') => counts[3] += 1,
+            _ => {}
+        }
+    }
+    if let Some(counts) = churn {
+        out.push_str(&summary_line(counts));
+    }
+    out
+}
+
+/// E.g. `# Dependency churn, summarized: 3 dependency version bumps, 1
+/// dependency added`.
+fn summary_line([added_versions, removed_versions, added, removed]: [usize; 4]) -> String {
+    let bumps = added_versions.min(removed_versions);
+    let dependencies = |n: usize| if n == 1 { "dependency" } else { "dependencies" };
+    let lines = |n: usize| if n == 1 { "line" } else { "lines" };
+    let mut parts = Vec::new();
+    if bumps > 0 {
+        let s = if bumps == 1 { "" } else { "s" };
+        parts.push(format!("{bumps} dependency version bump{s}"));
+    }
+    if added_versions > bumps {
+        let n = added_versions - bumps;
+        parts.push(format!("{n} {} added", dependencies(n)));
+    }
+    if removed_versions > bumps {
+        let n = removed_versions - bumps;
+        parts.push(format!("{n} {} removed", dependencies(n)));
+    }
+    if parts.is_empty() {
+        parts.push(format!("{added} {} added, {removed} removed", lines(added)));
+    }
+    format!(
+        "# Dependency churn, summarized: {} (full text left out)\n",
+        parts.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "diff --git a/src/main.rs b/src/main.rs\n\
+        --- a/src/main.rs\n\
+        +++ b/src/main.rs\n\
+        @@ -1 +1 @@\n\
+        -fn main() {}\n\
+        +fn main() { run() }\n\
+        diff --git a/Cargo.lock b/Cargo.lock\n\
+        --- a/Cargo.lock\n\
+        +++ b/Cargo.lock\n\
+        @@ -10,7 +10,12 @@\n \
+        name = \"serde\"\n\
+        -version = \"1.0.1\"\n\
+        +version = \"1.0.2\"\n\
+        -checksum = \"aa\"\n\
+        +checksum = \"bb\"\n\
+        +[[package]]\n\
+        +name = \"regex\"\n\
+        +version = \"1.10.0\"\n\
+        diff --git a/vendor/lib/a.go b/vendor/lib/a.go\n\
+        --- a/vendor/lib/a.go\n\
+        +++ b/vendor/lib/a.go\n\
+        @@ -1,2 +1,3 @@\n\
+        -package a\n\
+        +package a // v2\n\
+        +func A() {}\n";
+
+    #[test]
+    fn summarize_replaces_churn_hunks_with_counts() {
+        assert_eq!(
+            summarize(DIFF),
+            "diff --git a/src/main.rs b/src/main.rs\n\
+             --- a/src/main.rs\n\
+             +++ b/src/main.rs\n\
+             @@ -1 +1 @@\n\
+             -fn main() {}\n\
+             +fn main() { run() }\n\
+             diff --git a/Cargo.lock b/Cargo.lock\n\
+             # Dependency churn, summarized: 1 dependency version bump, 1 dependency added \
+             (full text left out)\n\
+             diff --git a/vendor/lib/a.go b/vendor/lib/a.go\n\
+             # Dependency churn, summarized: 2 lines added, 1 removed (full text left out)\n"
+        );
+        assert_eq!(
+            summary_line([5, 3, 0, 0]),
+            "# Dependency churn, summarized: 3 dependency version bumps, 2 dependencies added \
+             (full text left out)\n"
+        );
+        assert_eq!(
+            summary_line([0, 0, 1, 0]),
+            "# Dependency churn, summarized: 1 line added, 0 removed (full text left out)\n"
+        );
+    }
+
+    #[test]
+    fn churn_paths_and_full_text_option() {
+        assert!(is_dependency_churn("web/package-lock.json"));
+        assert!(is_dependency_churn("third_party/vendor/x.c"));
+        assert!(!is_dependency_churn("src/vendors.rs"));
+        assert!(!is_dependency_churn("Cargo.toml"));
+
+        let mut marker = Marker {
+            name: "pins".to_string(),
+            rel_path: "Cargo.toml".to_string(),
+            line: 1,
+            instruction: String::new(),
+            files: vec![],
+            exclude: vec![],
+            context: vec![],
+            metadata: Default::default(),
+            options: Default::default(),
+        };
+        assert_ne!(condense(DIFF, &marker), DIFF);
+        marker
+            .options
+            .insert("lockfiles".to_string(), "full".to_string());
+        assert_eq!(condense(DIFF, &marker), DIFF);
+    }
+}
@@ -1,19 +1,191 @@
-use clap::Parser;
+use std::process;
 
+use clap::{CommandFactory, Parser};
+
+mod acks;
+#[cfg(feature = "agent-sdk")]
+mod agent;
+mod airgap;
+mod attestation;
+mod audit;
+mod badge;
 mod cache;
+mod children;
+mod chunk;
+mod ci;
+mod classify;
 mod claude;
 mod cli;
+mod clock;
+mod cluster;
+mod completions;
+mod config;
+mod confine;
+mod context;
+mod coverage;
+mod deps;
+mod doctor;
+mod exit_code;
+mod formatter;
+mod gate;
+#[cfg(feature = "hg")]
+mod hg;
+mod history;
+mod hooks;
+mod html_report;
+mod http;
+mod index;
+mod inventory;
+mod jj;
+mod lint;
+mod lockfiles;
+mod manpage;
 mod marker;
+mod marker_changes;
+mod noise;
+mod owners;
+mod packs;
+mod platform;
+mod plugins;
+mod policy;
+mod pr;
+mod privacy;
+mod profile;
 mod prompt;
+mod protected;
+mod prune;
+mod quota;
+mod ratchet;
+mod redact;
+mod remote;
+mod remote_cache;
+mod rename;
+mod report;
+mod results_diff;
+mod resume;
+mod rpc;
+mod script;
+mod selfupdate;
+mod shard;
+mod shutdown;
+mod snapshot;
+mod stack;
+mod stream;
+mod structured;
+mod symbols;
+mod throttle;
+mod tickets;
+mod upload;
+mod vcs;
+mod virtual_markers;
+mod waivers;
+#[cfg(feature = "wasm")]
+mod wasm;
 
 fn main() {
-    let cli = cli::Cli::parse();
+    clap_complete::CompleteEnv::with_factory(cli::Cli::command)
+        .var(completions::COMPLETE_VAR)
+        .complete();
+    // clap would exit with 2, which means malformed markers here.
+    let cli = cli::Cli::try_parse().unwrap_or_else(|e| {
+        e.print().ok();
+        process::exit(if e.use_stderr() {
+            exit_code::CONFIG_ERROR
+        } else {
+            0
+        });
+    });
+    shutdown::handle_signals();
     match cli.command {
-        cli::Command::Run {
+        cli::Command::Run(args) => match &args.pr {
+            Some(url) => pr::run(url, &args),
+            None => cli::run(&args),
+        },
+        cli::Command::Audit(args) => cli::run(&args.run_args()),
+        cli::Command::Gate(args) => gate::run(&args),
+        cli::Command::Prime(args) => cli::run(&args.run_args()),
+        cli::Command::List {
+            root,
+            format,
+            full,
+            policies,
+        } => cli::list(format, full, &policies, root.as_deref()),
+        cli::Command::Map {
+            path,
+            depth,
+            policies,
+        } => cli::map(path.as_deref(), depth, &policies),
+        cli::Command::Query(args) => cli::query(&args),
+        cli::Command::Stats(args) => cli::stats(&args),
+        cli::Command::Badge {
+            root,
+            format,
+            output,
+        } => cli::badge(format, output.as_deref(), root.as_deref()),
+        cli::Command::DiffResults { old, new, format } => cli::diff_results(&old, &new, format),
+        cli::Command::Snapshot { action } => cli::snapshot(&action),
+        cli::Command::Waive {
+            marker,
+            root,
+            until,
+            reason,
+        } => cli::waive(&marker, &until, &reason, root.as_deref()),
+        cli::Command::Ack {
+            marker,
+            root,
+            commit,
+        } => cli::ack(&marker, &commit, root.as_deref()),
+        cli::Command::Rename { old, new, root } => cli::rename(&old, &new, root.as_deref()),
+        cli::Command::Lint { root, policies } => cli::lint(&policies, root.as_deref()),
+        cli::Command::Fmt { root, check } => cli::fmt(check, root.as_deref()),
+        cli::Command::Prune {
+            root,
+            base,
+            dry_run,
+        } => cli::prune(base.as_deref(), dry_run, root.as_deref()),
+        cli::Command::Completions { shell } => cli::completions(shell),
+        cli::Command::Doctor { root } => cli::doctor(root.as_deref()),
+        cli::Command::Man => cli::man(),
+        cli::Command::Worker {
+            root,
+            listen,
+            max_jobs,
+            allow_tools,
+            allow_models,
+            insecure,
+        } => cli::worker(
+            &listen,
+            max_jobs,
+            cli::WorkerAllow {
+                tools: allow_tools,
+                models: allow_models,
+                insecure,
+            },
+            root.as_deref(),
+        ),
+        cli::Command::Rpc {
             root,
             model,
+            policies,
+        } => cli::rpc(&model, &policies, root.as_deref()),
+        cli::Command::ReviewMarkers {
+            root,
+            model,
+            policies,
+        } => cli::review_markers(&model, &policies, root.as_deref()),
+        cli::Command::Classify {
+            root,
+            ai,
+            model,
+            format,
+            policies,
+        } => cli::classify(ai, &model, format, &policies, root.as_deref()),
+        cli::Command::SelfUpdate { check, insecure } => cli::self_update(check, insecure),
+        cli::Command::Suggest {
+            root,
+            file,
             diff,
-            no_cache,
-        } => cli::run(&model, diff.as_deref(), no_cache, root.as_deref()),
+            model,
+        } => cli::suggest(&model, file.as_deref(), diff.as_deref(), root.as_deref()),
     }
 }
@@ -0,0 +1,190 @@
+use std::io;
+
+use clap::CommandFactory;
+use clap_mangen::Man;
+use roff::{Roff, bold, italic, roman};
+
+use crate::cli::Cli;
+
+/// Per-marker options, as documented in the OPTIONS table of the README.
+const MARKER_OPTIONS: &[(&str, &str)] = &[
+    ("model", "AI model for this watcher, overriding --model."),
+    (
+        "tools",
+        "Comma-separated Claude tools the agent may use (default Read,Grep,Glob).",
+    ),
+    (
+        "when",
+        "rhai condition deciding whether the watcher runs in --diff mode, \
+         e.g. diff.touches('src/db/**').",
+    ),
+    (
+        "suite",
+        "Comma-separated suites the watcher belongs to, for run --suite.",
+    ),
+    (
+        "cost",
+        "Expected validation time (45s, 2m, 1h), balanced by run --shard.",
+    ),
+    (
+        "severity",
+        "Severity reported by run --format compact (default error).",
+    ),
+    (
+        "checker",
+        "Validate with the checker plugin wk-check-<name> instead of Claude.",
+    ),
+    (
+        "assert",
+        "Check the watcher by evaluating exists(path), contains(path, regex), \
+         count(glob), references(symbol) and locked(name) instead of calling Claude.",
+    ),
+    (
+        "owner",
+        "Assignee of tickets filed for persistent failures ([tickets]).",
+    ),
+    (
+        "strategy",
+        "How the agent sees the code: diff-only, files-inline, \
+         agentic-tools (default), command-output or dependencies.",
+    ),
+    (
+        "command",
+        "The [commands] entry whose output a command-output watcher judges.",
+    ),
+    (
+        "lockfiles",
+        "full to show lockfile and vendor/ changes in full instead of \
+         summarized as dependency version bumps.",
+    ),
+];
+
+/// Render the complete manual page: the top-level command, every subcommand
+/// with its flags, and the marker syntax.
+pub fn render(out: &mut dyn io::Write) -> io::Result<()> {
+    let mut cmd = Cli::command().disable_help_subcommand(true);
+    cmd.build();
+    let man = Man::new(cmd.clone()).source(format!("watcher-knight {}", env!("CARGO_PKG_VERSION")));
+    man.render_title(out)?;
+    man.render_name_section(out)?;
+    man.render_synopsis_section(out)?;
+    man.render_description_section(out)?;
+    man.render_subcommands_section(out)?;
+
+    for sub in cmd.get_subcommands().filter(|s| !s.is_hide_set()) {
+        let name = sub.get_bin_name().unwrap_or_else(|| sub.get_name());
+        Roff::new()
+            .control("SH", [name.to_uppercase().as_str()])
+            .to_writer(out)?;
+        // Each subcommand becomes a section; its own sections become subsections.
+        let sub_man = Man::new(sub.clone());
+        let mut body = Vec::new();
+        sub_man.render_synopsis_section(&mut body)?;
+        sub_man.render_description_section(&mut body)?;
+        sub_man.render_options_section(&mut body)?;
+        out.write_all(demote_sections(&String::from_utf8_lossy(&body)).as_bytes())?;
+    }
+
+    marker_syntax().to_writer(out)
+}
+
+/// Turn `.SH` headings into `.SS` so they nest under the current section.
+fn demote_sections(roff: &str) -> String {
+    roff.lines()
+        .map(|line| match line.strip_prefix(".SH ") {
+            Some(heading) => format!(".SS {heading}\n"),
+            None => format!("{line}\n"),
+        })
+        .collect()
+}
+
+fn marker_syntax() -> Roff {
+    let mut roff = Roff::new();
+    roff.control("SH", ["MARKER SYNTAX"])
+        .text([
+            roman("Watchers are declared in source comments. A marker starts with "),
+            bold("<wk:"),
+            roman(" and ends with "),
+            bold("/>"),
+            roman(":"),
+        ])
+        .control("PP", [])
+        .control("nf", [])
+        .text([roman("// <wk: name [./file.ts, ./src/*.py]")])
+        .text([roman("// options={model=\"haiku\", tools=\"Read,Grep\"}")])
+        .text([roman(
+            "// context={./README.md, https://internal.wiki/api-policy.md}",
+        )])
+        .text([roman("// Instruction for the agent />")])
+        .control("fi", [])
+        .control("PP", [])
+        .text([
+            roman("Comment prefixes "),
+            bold("//"),
+            roman(", "),
+            bold("#"),
+            roman(", "),
+            bold("--"),
+            roman(", "),
+            bold("%"),
+            roman(" and "),
+            bold(";"),
+            roman(" are stripped from continuation lines. "),
+            italic("name"),
+            roman(
+                " identifies the watcher. The optional file list is relative to the \
+                 marker's directory and may contain glob patterns; a watcher is re-run \
+                 when one of its files changes, and in --diff mode only when one of them \
+                 appears in the diff. A watcher without files is never cached.",
+            ),
+        ])
+        .control("PP", [])
+        .text([roman(
+            "The optional context={...} line lists reference documents included in the \
+             prompt without affecting when the watcher runs: local files, and URLs fetched \
+             from prefixes in context_allowlist. The optional \
+             options={...} line sets per-watcher options as key=\"value\" pairs:",
+        )]);
+    for (key, description) in MARKER_OPTIONS {
+        roff.control("TP", [])
+            .text([bold(*key)])
+            .text([roman(*description)]);
+    }
+    roff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rendered() -> String {
+        let mut out = Vec::new();
+        render(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn render_covers_every_subcommand_and_flag() {
+        let page = rendered();
+        for sub in Cli::command().get_subcommands() {
+            let heading = format!(".SH \"WATCHER-KNIGHT {}\"", sub.get_name().to_uppercase());
+            assert!(page.contains(&heading), "missing {heading}");
+        }
+        assert!(page.contains("\\-\\-no\\-cache"));
+        assert!(page.contains("\\-\\-policy"));
+    }
+
+    #[test]
+    fn render_documents_marker_syntax() {
+        let page = rendered();
+        assert!(page.contains(".SH \"MARKER SYNTAX\""));
+        for (key, _) in MARKER_OPTIONS {
+            assert!(page.contains(&format!("\\fB{key}\\fR")), "missing {key}");
+        }
+    }
+
+    #[test]
+    fn demote_sections_nests_headings() {
+        assert_eq!(demote_sections(".SH OPTIONS\n.TP\n"), ".SS OPTIONS\n.TP\n");
+    }
+}
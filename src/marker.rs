@@ -1,20 +1,34 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 use nom::IResult;
 use nom::Parser;
-use nom::bytes::complete::{tag, take_while, take_while1};
+use nom::bytes::complete::{tag, tag_no_case, take_while, take_while1};
 use nom::character::complete::{char, space0};
 use nom::multi::separated_list0;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::context;
+use crate::platform;
+use crate::structured;
+use crate::vcs;
 
 // ── Types ──────────────────────────────────────────────────────────────────────
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ParseError {
     pub file: String,
     pub line: usize,
     pub message: String,
+    /// The tag was never closed with `/>`, so none of it was used.
+    #[serde(default)]
+    pub unclosed: bool,
 }
 
 impl fmt::Display for ParseError {
@@ -29,151 +43,441 @@ pub struct Marker {
     pub rel_path: String,
     pub line: usize,
     pub instruction: String,
+    /// Watched files: repo-relative paths, plus directory entries ending in
+    /// `/` that watch every file below them at run time.
     pub files: Vec<String>,
+    /// Exclusion patterns (`!` entries), repo-relative.
+    pub exclude: Vec<String>,
+    /// Reference documents (`context={...}`) whose contents are included in
+    /// the prompt: URLs, and repo-relative paths of local files. They do not
+    /// affect which changes are relevant.
+    pub context: Vec<String>,
+    pub metadata: Metadata,
     pub options: HashMap<String, String>,
 }
 
+/// Documentation attached to a marker (`description="..."`, `rationale="..."`,
+/// `link="..."` body lines). Shown by `list` and next to failures; it does not
+/// change what is checked.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Metadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Why the invariant exists.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rationale: Option<String>,
+    /// Design doc, ADR or ticket explaining the invariant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+}
+
+impl Metadata {
+    fn field(&mut self, key: &str) -> Option<&mut Option<String>> {
+        match key {
+            "description" => Some(&mut self.description),
+            "rationale" => Some(&mut self.rationale),
+            "link" => Some(&mut self.link),
+            _ => None,
+        }
+    }
+}
+
+impl Marker {
+    /// The `severity` option, `error` when unset.
+    pub fn severity(&self) -> &str {
+        self.options.get("severity").map_or("error", String::as_str)
+    }
+
+    /// Whether a change to `path` is relevant to this marker's file scope.
+    pub fn watches(&self, path: &str) -> bool {
+        self.files.iter().any(|entry| entry_covers(entry, path))
+            && !is_excluded(path, &self.exclude)
+    }
+
+    /// Files currently watched under `root`: file entries as listed, and every
+    /// file now below a directory entry.
+    pub fn watched_files(&self, root: &Path) -> Vec<String> {
+        self.expand(|dir| files_below(root, dir))
+    }
+
+    /// Like [`Marker::watched_files`], but listing directories from `tree`
+    /// (repo-relative paths, e.g. the files at a commit).
+    pub fn watched_files_in(&self, tree: &[String]) -> Vec<String> {
+        self.expand(|dir| {
+            tree.iter()
+                .filter(|f| entry_covers(&format!("{dir}/"), f))
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Local (non-URL) context files, repo-relative.
+    pub fn context_files(&self) -> impl Iterator<Item = &str> {
+        self.context
+            .iter()
+            .map(String::as_str)
+            .filter(|entry| !context::is_url(entry))
+    }
+
+    fn expand(&self, list_dir: impl Fn(&str) -> Vec<String>) -> Vec<String> {
+        let mut files = Vec::new();
+        for entry in &self.files {
+            match entry.strip_suffix('/') {
+                Some(dir) => files.extend(
+                    list_dir(dir)
+                        .into_iter()
+                        .filter(|f| !is_excluded(f, &self.exclude)),
+                ),
+                None => files.push(entry.clone()),
+            }
+        }
+        files.sort();
+        files.dedup();
+        files
+    }
+}
+
 // ── Constants ──────────────────────────────────────────────────────────────────
 
 const COMMENT_PREFIXES: &[&str] = &["//", "#", "--", "%", ";"];
 
-const TAG_PREFIXES: &[&str] = &["<wk"];
+/// Characters that extend a comment prefix into a doc-comment variant, e.g.
+/// `///`, `//!`, `///!`, `#:` or `---`.
+const DOC_MARKERS: &[char] = &['/', '!', ':', '-', '%', ';'];
+
+/// Tag prefixes, current first. `<watcher-knight` is the legacy spelling of
+/// `<wk`; both open the same tag syntax, and `fmt` rewrites the legacy one.
+const TAG_PREFIXES: &[&str] = &["<wk", "<watcher-knight"];
+
+/// Prefixes of the `tag_keywords` set in config (e.g. `<guard`), accepted on
+/// top of [`TAG_PREFIXES`].
+static CUSTOM_PREFIXES: OnceLock<Vec<&'static str>> = OnceLock::new();
+
+/// Prefix of file entries resolved from the repo root instead of the marker's
+/// directory (as is a leading `/`).
+const REPO_PREFIX: &str = "repo:";
+
+// ── Tag Keywords ───────────────────────────────────────────────────────────────
+
+/// Check a `tag_keywords` entry: a keyword opens tags as `<keyword: name`.
+pub fn check_tag_keyword(keyword: &str) -> Result<(), String> {
+    if keyword.is_empty()
+        || !keyword
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "invalid tag keyword `{keyword}` (keywords may contain ASCII letters, digits, \
+             hyphens, and underscores)"
+        ));
+    }
+    Ok(())
+}
+
+/// Accept tags opened with `<keyword` for each of `keywords` (the config's
+/// `tag_keywords`, already checked) for the rest of the process. Only the
+/// first call has an effect.
+pub fn register_tag_keywords(keywords: &[String]) {
+    let prefixes = keywords
+        .iter()
+        .map(|k| format!("<{}", k.to_ascii_lowercase()))
+        .filter(|p| !TAG_PREFIXES.contains(&p.as_str()))
+        .map(|p| &*Box::leak(p.into_boxed_str()))
+        .collect();
+    CUSTOM_PREFIXES.set(prefixes).ok();
+}
+
+/// The registered custom tag prefixes.
+pub fn custom_prefixes() -> &'static [&'static str] {
+    CUSTOM_PREFIXES.get().map_or(&[], Vec::as_slice)
+}
+
+fn tag_prefixes() -> impl Iterator<Item = &'static str> {
+    TAG_PREFIXES.iter().chain(custom_prefixes()).copied()
+}
+
+/// The prefix `fmt` writes and `lint` expects for a tag opened with `prefix`:
+/// the prefix itself, except that the legacy `<watcher-knight` becomes `<wk`.
+pub fn canonical_prefix(prefix: &str) -> &str {
+    if prefix == TAG_PREFIXES[1] {
+        TAG_PREFIXES[0]
+    } else {
+        prefix
+    }
+}
 
 // ── Phase 1: Tag Extraction ────────────────────────────────────────────────────
 
-struct RawTag {
+/// A tag as found in a file, before its content is parsed. Tags depend only
+/// on the file's contents, so the marker index stores them per file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawTag {
     /// Everything from the tag prefix up to (but not including) `/>`,
     /// with comment prefixes stripped from continuation lines.
-    content: String,
+    pub content: String,
     /// 1-based line number of the opening tag.
-    line: usize,
+    pub line: usize,
+    /// 1-based line number of the line holding `/>`.
+    pub end_line: usize,
 }
 
-/// Find `<wk` in a line. Returns `(byte_offset, prefix_str)`.
+/// Find the first tag prefix (`<wk`, `<watcher-knight` or a configured
+/// keyword, in any case) in a line. Returns `(byte_offset, prefix_str)`.
 /// Only matches when the prefix is followed by `:` or whitespace (to avoid false
-/// positives like `<wking>`).
-fn find_tag_in_line(line: &str) -> Option<(usize, &'static str)> {
-    for &prefix in TAG_PREFIXES {
-        if let Some(pos) = line.find(prefix) {
-            let after = &line[pos + prefix.len()..];
-            let next = after.chars().next();
-            if next.is_none() || next == Some(':') || next.unwrap().is_whitespace() {
-                return Some((pos, prefix));
-            }
-        }
-    }
-    None
+/// positives like `<wking>`); the search goes on past such words.
+pub fn find_tag_in_line(line: &str) -> Option<(usize, &'static str)> {
+    // ASCII lowercasing keeps byte offsets.
+    let lower = line.to_ascii_lowercase();
+    tag_prefixes()
+        .filter_map(|prefix| {
+            lower
+                .match_indices(prefix)
+                .map(|(pos, _)| pos)
+                .find(|&pos| {
+                    let next = line[pos + prefix.len()..].chars().next();
+                    next.is_none_or(|c| c == ':' || c.is_whitespace())
+                })
+                .map(|pos| (pos, prefix))
+        })
+        // The earliest tag; of prefixes found at the same place, the longest.
+        .min_by_key(|&(pos, prefix)| (pos, std::cmp::Reverse(prefix.len())))
 }
 
-/// Detect which comment prefix appears in the text before the tag.
-fn detect_comment_prefix(before_tag: &str) -> Option<&'static str> {
-    let trimmed = before_tag.trim();
+/// Detect which line comment prefix appears in the text before the tag.
+pub fn detect_comment_prefix(before_tag: &str) -> Option<&'static str> {
+    let lead = comment_lead(before_tag)?;
     COMMENT_PREFIXES
         .iter()
-        .find(|&&prefix| trimmed == prefix || trimmed.ends_with(prefix))
+        .find(|&&prefix| lead.starts_with(prefix))
         .copied()
 }
 
-/// Strip a comment prefix from a continuation line. Returns `None` if a comment
-/// prefix was expected but not found (i.e. the comment block ended).
+/// The comment token right before a tag as written, which the continuation
+/// lines of a multi-line tag repeat: a line comment prefix, possibly extended
+/// into a doc-comment variant (`///`, `//!`, `#:`), or `*` in a JSDoc block
+/// (`/** <wk:` or ` * <wk:`).
+pub fn comment_lead(before_tag: &str) -> Option<&str> {
+    let trimmed = before_tag.trim();
+    if trimmed == "*" || trimmed.ends_with("/**") {
+        return Some("*");
+    }
+    let token = trimmed.rsplit(char::is_whitespace).next()?;
+    COMMENT_PREFIXES.iter().find_map(|prefix| {
+        let lead = &token[token.find(prefix)?..];
+        lead[prefix.len()..]
+            .chars()
+            .all(|c| DOC_MARKERS.contains(&c))
+            .then_some(lead)
+    })
+}
+
+/// The opening of the tag in `line` as written, up to the end of the watcher
+/// name (e.g. `<WK :name`), to compare with the canonical `<wk: name`.
+pub fn tag_opening(line: &str) -> Option<&str> {
+    let (col, prefix) = find_tag_in_line(line)?;
+    let rest = line[col + prefix.len()..].trim_start();
+    let rest = rest.strip_prefix(':')?.trim_start();
+    let (rest, _) = nom_name(rest).ok()?;
+    Some(&line[col..line.len() - rest.len()])
+}
+
+/// Strip the comment lead of the opening line (see [`comment_lead`]), or at
+/// least its base prefix, from a continuation line. Returns `None` if a
+/// comment prefix was expected but not found (i.e. the comment block ended).
+/// The `*` of JSDoc lines is optional.
 fn strip_continuation<'a>(line: &'a str, comment_prefix: Option<&str>) -> Option<&'a str> {
     let trimmed = line.trim_start();
     match comment_prefix {
-        Some(cp) => trimmed.strip_prefix(cp),
+        Some("*") => Some(trimmed.strip_prefix('*').unwrap_or(trimmed)),
+        Some(lead) => trimmed
+            .strip_prefix(lead)
+            .or_else(|| trimmed.strip_prefix(detect_comment_prefix(lead)?)),
         None => Some(trimmed),
     }
 }
 
-/// Walk through the file contents, find every `<wk .../>`
-/// span, and return the raw tag content with comment prefixes stripped.
-fn extract_raw_tags(contents: &str, file: &str) -> (Vec<RawTag>, Vec<ParseError>) {
-    let lines: Vec<&str> = contents.lines().collect();
+/// Whether the tag at byte `col` of `line` is in a comment rather than in
+/// code, such as a string literal. It is when a comment of the file's language
+/// (see [`comment_prefix_for_path`]; `/*` too where that is `//`) starts before
+/// it outside string literals, or when nothing but a comment lead of any
+/// language (or nothing at all) comes before it.
+fn in_comment(line: &str, col: usize, file: &str) -> bool {
+    let prefix = comment_prefix_for_path(file);
+    let before = &line[..col];
+    let mut quote = None;
+    let mut chars = before.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) if c == '\\' => {
+                chars.next();
+            }
+            Some(_) => {}
+            None if before[i..].starts_with(prefix)
+                || (prefix == "//" && before[i..].starts_with("/*")) =>
+            {
+                return true;
+            }
+            // A quote only opens a string when the line closes it, so an
+            // apostrophe or a Rust lifetime does not.
+            None if matches!(c, '"' | '\'' | '`') && line[i + 1..].contains(c) => {
+                quote = Some(c);
+            }
+            None => {}
+        }
+    }
+    // Otherwise the lead must be all there is: `# <wk:` in any file, or
+    // `<!-- <wk:`, but not `x = 1; <wk:`.
+    let before = before.trim();
+    quote.is_none()
+        && (before.is_empty()
+            || (!before.contains(char::is_whitespace) && comment_lead(before).is_some()))
+}
+
+/// Walk through the lines of a file, find every `<wk .../>` span, and
+/// return the raw tag content with comment prefixes stripped. Lines are
+/// consumed one at a time, so the whole file never has to be in memory.
+pub fn extract_raw_tags<S: AsRef<str>>(
+    lines: impl IntoIterator<Item = S>,
+    file: &str,
+) -> (Vec<RawTag>, Vec<ParseError>) {
+    let mut lines = lines.into_iter().enumerate().peekable();
     let mut tags = Vec::new();
     let mut errors = Vec::new();
-    let mut i = 0;
 
-    while i < lines.len() {
-        let (col, _tag_prefix) = match find_tag_in_line(lines[i]) {
-            Some(r) => r,
-            None => {
-                i += 1;
+    while let Some((i, line)) = lines.next() {
+        let line = line.as_ref();
+        let start_line = i + 1; // 1-based
+
+        // Tags on this line, one after another: `code // <wk: a ... /> <wk: b ... />`.
+        let mut from = 0;
+        while let Some((offset, tag_prefix)) = find_tag_in_line(&line[from..]) {
+            let col = from + offset;
+            if !in_comment(line, col, file) {
+                from = col + tag_prefix.len();
                 continue;
             }
-        };
 
-        let start_line = i + 1; // 1-based
+            // Content from `<wk` onward on this line.
+            let after_tag_start = &line[col..];
+
+            // Step 2: Find the corresponding `/>`.
+            if let Some(close_pos) = after_tag_start.find("/>") {
+                // Single-line tag.
+                tags.push(RawTag {
+                    content: after_tag_start[..close_pos].to_string(),
+                    line: start_line,
+                    end_line: start_line,
+                });
+                from = col + close_pos + 2;
+                continue;
+            }
 
-        // Determine the comment prefix used on the opening line.
-        let before_tag = &lines[i][..col];
-        let comment_prefix = detect_comment_prefix(before_tag);
-
-        // Content from `<wk` onward on this line.
-        let after_tag_start = &lines[i][col..];
-
-        // Step 2: Find the corresponding `/>`.
-        if let Some(close_pos) = after_tag_start.find("/>") {
-            // Single-line tag.
-            let content = &after_tag_start[..close_pos];
-            tags.push(RawTag {
-                content: content.to_string(),
-                line: start_line,
-            });
-            i += 1;
-            continue;
-        }
+            // Multi-line: collect continuation lines until `/>`, using the
+            // comment prefix of the opening line. A line that ends the
+            // comment block is left for the outer loop, since it may open the
+            // next tag.
+            let comment_prefix = comment_lead(&line[..col]);
+            let mut collected = after_tag_start.to_string();
+            let mut found_close = false;
+            let mut end_line = start_line;
+
+            while let Some((j, next)) = lines.peek() {
+                end_line = j + 1;
+                let Some(stripped) = strip_continuation(next.as_ref(), comment_prefix) else {
+                    break; // Comment block ended without `/>`.
+                };
+                if find_tag_in_line(next.as_ref()).is_some() {
+                    break; // The next tag opens before this one was closed.
+                }
 
-        // Multi-line: collect continuation lines until `/>`.
-        let mut collected = after_tag_start.to_string();
-        i += 1;
-        let mut found_close = false;
-
-        while i < lines.len() {
-            let stripped = match strip_continuation(lines[i], comment_prefix) {
-                Some(s) => s,
-                None => break, // Comment block ended without `/>`.
-            };
-
-            if let Some(close_pos) = stripped.find("/>") {
-                let before = stripped[..close_pos].trim_end();
-                if !before.is_empty() {
-                    collected.push('\n');
-                    collected.push_str(before);
+                if let Some(close_pos) = stripped.find("/>") {
+                    let before = stripped[..close_pos].trim_end();
+                    if !before.is_empty() {
+                        collected.push('\n');
+                        collected.push_str(before);
+                    }
+                    found_close = true;
+                    lines.next();
+                    break;
                 }
-                found_close = true;
-                i += 1;
-                break;
-            }
 
-            collected.push('\n');
-            collected.push_str(stripped.trim());
-            i += 1;
-        }
+                collected.push('\n');
+                collected.push_str(stripped.trim());
+                lines.next();
+            }
 
-        if !found_close {
-            errors.push(ParseError {
-                file: file.to_string(),
-                line: start_line,
-                message: format!(
-                    "unclosed watcher tag: `{}` opened but no matching `/>` was found",
-                    _tag_prefix,
-                ),
-            });
-        } else {
-            tags.push(RawTag {
-                content: collected,
-                line: start_line,
-            });
+            if !found_close {
+                errors.push(ParseError {
+                    file: file.to_string(),
+                    line: start_line,
+                    message: format!(
+                        "unclosed watcher tag: `{tag_prefix}` opened but no matching `/>` was \
+                         found; the watcher is not run",
+                    ),
+                    unclosed: true,
+                });
+            } else {
+                tags.push(RawTag {
+                    content: collected,
+                    line: start_line,
+                    end_line,
+                });
+            }
+            break;
         }
     }
 
     (tags, errors)
 }
 
+/// Whether `reader` contains a tag prefix, or one of `extra`, anywhere, read
+/// in fixed-size chunks. Most files have no markers and are ruled out
+/// without decoding or splitting them into lines.
+fn may_contain_tag(mut reader: impl Read, extra: &[&str]) -> io::Result<bool> {
+    let mut needles = extra.to_vec();
+    for prefix in tag_prefixes() {
+        needles.push(prefix);
+    }
+    let longest = needles.iter().map(|n| n.len()).max().unwrap_or(0);
+    let mut buf = vec![0; 64 * 1024];
+    // Bytes kept from the previous chunk so a prefix split across two reads
+    // is still found.
+    let mut kept = 0;
+    loop {
+        let n = match reader.read(&mut buf[kept..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let filled = &buf[..kept + n];
+        if needles.iter().any(|p| {
+            filled
+                .windows(p.len())
+                .any(|w| w.eq_ignore_ascii_case(p.as_bytes()))
+        }) {
+            return Ok(true);
+        }
+        kept = longest.saturating_sub(1).min(filled.len());
+        let end = filled.len();
+        buf.copy_within(end - kept..end, 0);
+    }
+}
+
 // ── Phase 2: nom Parsers ───────────────────────────────────────────────────────
 
-/// Match `<wk`.
+/// Match `<wk`, the legacy `<watcher-knight` or a configured keyword, in any
+/// case. The longest prefix wins, so `<wk` does not cut `<wk-guard` short.
 fn nom_tag_prefix(input: &str) -> IResult<&str, &str> {
-    tag("<wk")(input)
+    let mut prefixes: Vec<&str> = tag_prefixes().collect();
+    prefixes.sort_by_key(|p| std::cmp::Reverse(p.len()));
+    for prefix in prefixes {
+        if let Ok(matched) = tag_no_case::<_, _, nom::error::Error<&str>>(prefix).parse(input) {
+            return Ok(matched);
+        }
+    }
+    tag_no_case(TAG_PREFIXES[0]).parse(input)
 }
 
 /// Match `:` with optional surrounding whitespace.
@@ -236,21 +540,49 @@ fn nom_options(input: &str) -> IResult<&str, Vec<(&str, &str)>> {
     Ok((input, pairs))
 }
 
+/// Match a single context entry inside `{...}` (everything up to `,` or `}`).
+fn nom_context_entry(input: &str) -> IResult<&str, &str> {
+    let (input, _) = space0(input)?;
+    let (input, entry) = take_while1(|c: char| c != ',' && c != '}')(input)?;
+    Ok((input, entry.trim()))
+}
+
+/// Match `context={url, ...}`.
+fn nom_context(input: &str) -> IResult<&str, Vec<&str>> {
+    let (input, _) = tag("context")(input)?;
+    let (input, _) = space0(input)?;
+    let (input, _) = char('=')(input)?;
+    let (input, _) = space0(input)?;
+    let (input, _) = char('{')(input)?;
+    let (input, _) = space0(input)?;
+    let (input, entries) =
+        separated_list0((space0, char(','), space0), nom_context_entry).parse(input)?;
+    let (input, _) = space0(input)?;
+    let (input, _) = char('}')(input)?;
+    Ok((input, entries))
+}
+
 // ── Phase 2: Tag Parsing ───────────────────────────────────────────────────────
 
-/// Parse a raw tag content string into a `Marker`, or return a `ParseError`.
-fn parse_raw_tag(
-    content: &str,
-    file: &str,
-    line: usize,
-    marker_parent: &Path,
-    repo_root: &Path,
-) -> Result<Marker, ParseError> {
-    let err = |msg: String| ParseError {
-        file: file.to_string(),
-        line,
-        message: msg,
-    };
+/// The parts of a tag as written, before file entries and context paths are
+/// resolved. `watcher-knight fmt` lays tags out again from these.
+#[derive(Debug, Default, PartialEq)]
+pub struct TagParts<'a> {
+    pub name: &'a str,
+    pub files: Vec<&'a str>,
+    /// `options={...}` pairs, from every options line in order.
+    pub options: Vec<(&'a str, &'a str)>,
+    pub context: Vec<&'a str>,
+    /// `description="..."`, `rationale="..."` and `link="..."` lines.
+    pub metadata: Vec<(&'a str, &'a str)>,
+    /// Non-empty instruction lines, trimmed.
+    pub instruction: Vec<&'a str>,
+}
+
+/// Split raw tag content into its parts. Errors carry the line offset within
+/// the tag (0 for the opening line).
+pub fn split_tag(content: &str) -> Result<TagParts<'_>, (usize, String)> {
+    let err = |msg: &str| Err((0, msg.to_string()));
 
     // Split into first line and the rest.
     let (first_line, rest) = match content.find('\n') {
@@ -261,53 +593,47 @@ fn parse_raw_tag(
     // Parse tag prefix.
     let remaining = match nom_tag_prefix(first_line) {
         Ok((r, _)) => r,
-        Err(_) => return Err(err("expected `<wk` tag prefix".to_string())),
+        Err(_) => return err("expected `<wk` tag prefix"),
     };
 
     // Parse colon.
     let remaining = match nom_colon(remaining) {
         Ok((r, _)) => r,
-        Err(_) => {
-            return Err(err(
-                "expected `:` after tag prefix (e.g., `<wk: my-watcher ...`)".to_string(),
-            ));
-        }
+        Err(_) => return err("expected `:` after tag prefix (e.g., `<wk: my-watcher ...`)"),
     };
 
     // Parse name.
-    let (remaining, name) =
-        match nom_name(remaining) {
-            Ok((r, n)) => (r, n.to_string()),
-            Err(_) => return Err(err(
+    let (remaining, name) = match nom_name(remaining) {
+        Ok((r, n)) => (r, n),
+        Err(_) => {
+            return err(
                 "expected watcher name after `<wk:` (names may contain alphanumeric characters, \
-                 hyphens, and underscores)"
-                    .to_string(),
-            )),
-        };
+                 hyphens, and underscores)",
+            );
+        }
+    };
 
     // Parse optional inline file list.
     let remaining_trimmed = remaining.trim_start();
-    let (remaining, raw_files) = if remaining_trimmed.starts_with('[') {
+    let (remaining, files) = if remaining_trimmed.starts_with('[') {
         match nom_file_list(remaining) {
             Ok((r, files)) => (r, files),
-            Err(_) => {
-                return Err(err(
-                    "unclosed `[` in file list: expected matching `]`".to_string()
-                ));
-            }
+            Err(_) => return err("unclosed `[` in file list: expected matching `]`"),
         }
     } else {
         (remaining, Vec::new())
     };
 
-    // Collect all remaining text (rest of first line + continuation lines).
-    let mut instruction_parts: Vec<String> = Vec::new();
-    let mut options: HashMap<String, String> = HashMap::new();
+    let mut parts = TagParts {
+        name,
+        files,
+        ..TagParts::default()
+    };
 
     // Remainder of the first line after structured parts.
     let first_remainder = remaining.trim();
     if !first_remainder.is_empty() {
-        instruction_parts.push(first_remainder.to_string());
+        parts.instruction.push(first_remainder);
     }
 
     // Process body lines.
@@ -321,39 +647,111 @@ fn parse_raw_tag(
         if trimmed.starts_with("options") {
             match nom_options(trimmed) {
                 Ok((_, pairs)) => {
-                    for (k, v) in pairs {
-                        options.insert(k.to_string(), v.to_string());
-                    }
+                    parts.options.extend(pairs);
                     continue;
                 }
                 Err(_) => {
-                    return Err(ParseError {
-                        file: file.to_string(),
-                        line: line + 1 + offset,
-                        message: "malformed options: expected `options={key=\"value\", ...}`"
+                    return Err((
+                        1 + offset,
+                        "malformed options: expected `options={key=\"value\", ...}`".to_string(),
+                    ));
+                }
+            }
+        }
+
+        // Try description="...", rationale="..." and link="...".
+        if let Ok((rest, (key, value))) = nom_key_value(trimmed)
+            && rest.trim().is_empty()
+            && Metadata::default().field(key).is_some()
+        {
+            parts.metadata.push((key, value));
+            continue;
+        }
+
+        // Try context={...}. Prose that merely starts with the word is
+        // instruction text.
+        if trimmed
+            .strip_prefix("context")
+            .is_some_and(|r| r.trim_start().starts_with('='))
+        {
+            match nom_context(trimmed) {
+                Ok((rest, entries)) if rest.trim().is_empty() => {
+                    parts.context.extend(entries);
+                    continue;
+                }
+                _ => {
+                    return Err((
+                        1 + offset,
+                        "malformed context: expected `context={./file, https://..., ...}`"
                             .to_string(),
-                    });
+                    ));
                 }
             }
         }
 
-        instruction_parts.push(trimmed.to_string());
+        parts.instruction.push(trimmed);
+    }
+
+    Ok(parts)
+}
+
+/// Parse a raw tag content string into a `Marker`, or return a `ParseError`.
+fn parse_raw_tag(
+    content: &str,
+    file: &str,
+    line: usize,
+    marker_parent: &Path,
+    repo_root: &Path,
+) -> Result<Marker, ParseError> {
+    let err = |offset: usize, msg: String| ParseError {
+        file: file.to_string(),
+        line: line + offset,
+        message: msg,
+        unclosed: false,
+    };
+
+    let parts = split_tag(content).map_err(|(offset, msg)| err(offset, msg))?;
+    let name = parts.name.to_string();
+
+    let mut options: HashMap<String, String> = HashMap::new();
+    for (k, v) in parts.options {
+        options.insert(k.to_string(), v.to_string());
+    }
+    let mut metadata = Metadata::default();
+    for (key, value) in parts.metadata {
+        if let Some(field) = metadata.field(key) {
+            *field = Some(value.to_string());
+        }
     }
+    let context = resolve_context(&parts.context, marker_parent);
 
-    let instruction = instruction_parts.join("\n");
+    let instruction = parts.instruction.join("\n");
     if instruction.is_empty() {
-        return Err(err(format!("watcher `{name}` has no instruction text")));
+        return Err(err(0, format!("watcher `{name}` has no instruction text")));
+    }
+
+    let raw_files = parts.files;
+    if !raw_files.is_empty() && raw_files.iter().all(|f| f.trim().starts_with('!')) {
+        return Err(err(
+            0,
+            format!(
+                "watcher `{name}` file list only has exclusions; add the files to watch (e.g. `./**/*`)"
+            ),
+        ));
     }
 
     // Resolve file paths.
-    let files = resolve_raw_files(&raw_files, marker_parent, repo_root);
+    let scope = resolve_raw_files(&raw_files, marker_parent, repo_root);
 
     Ok(Marker {
         name,
         rel_path: file.to_string(),
         line,
         instruction,
-        files,
+        files: scope.files,
+        exclude: scope.exclude,
+        context,
+        metadata,
         options,
     })
 }
@@ -362,7 +760,7 @@ fn parse_raw_tag(
 
 /// Normalize a path by resolving `.` and `..` components without touching the
 /// filesystem.
-fn normalize_path(path: &Path) -> PathBuf {
+pub fn normalize_path(path: &Path) -> PathBuf {
     let mut components = Vec::new();
     for component in path.components() {
         match component {
@@ -376,18 +774,66 @@ fn normalize_path(path: &Path) -> PathBuf {
     components.iter().collect()
 }
 
-/// Resolve raw file entries relative to the marker's parent directory, expanding
-/// glob patterns against the repo root.
-fn resolve_raw_files(raw: &[&str], marker_parent: &Path, repo_root: &Path) -> Vec<String> {
-    let mut files = Vec::new();
+/// A resolved file list.
+#[derive(Debug, Default, PartialEq)]
+pub struct FileScope {
+    pub files: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+/// Path of a file entry relative to the repo root: entries starting with `/`
+/// or `repo:` are anchored at the repo root, others at the marker's directory.
+pub fn anchor_entry(entry: &str, marker_parent: &Path) -> PathBuf {
+    let entry = platform::slash_entry(entry);
+    let entry = entry.as_str();
+    match entry
+        .strip_prefix(REPO_PREFIX)
+        .or_else(|| entry.strip_prefix('/'))
+    {
+        Some(rooted) => normalize_path(Path::new(rooted.trim_start_matches('/'))),
+        None => normalize_path(&marker_parent.join(entry)),
+    }
+}
+
+/// Anchor local `context={...}` entries like file entries; URLs are kept as
+/// they are.
+pub fn resolve_context(entries: &[&str], marker_parent: &Path) -> Vec<String> {
+    entries
+        .iter()
+        .map(|&entry| {
+            if context::is_url(entry) {
+                entry.to_string()
+            } else {
+                platform::slash_path(&anchor_entry(entry, marker_parent))
+            }
+        })
+        .collect()
+}
+
+/// Resolve raw file entries relative to the marker's parent directory (or the
+/// repo root, see [`anchor_entry`]), expanding glob patterns against the repo root. Directories (`./handlers/`) are kept as
+/// directory entries and expanded at run time. Entries starting with `!`
+/// exclude the paths they match (or any path below a matched directory).
+pub fn resolve_raw_files(raw: &[&str], marker_parent: &Path, repo_root: &Path) -> FileScope {
+    let mut scope = FileScope::default();
     for &entry in raw {
         let entry = entry.trim();
+        if let Some(pattern) = entry.strip_prefix('!') {
+            let normalized = anchor_entry(pattern.trim(), marker_parent);
+            scope.exclude.push(platform::slash_path(&normalized));
+            continue;
+        }
         if entry.is_empty() {
             continue;
         }
-        let joined = marker_parent.join(entry);
-        let normalized = normalize_path(&joined);
-        let pattern_str = normalized.to_string_lossy().to_string();
+        let normalized = anchor_entry(entry, marker_parent);
+        let pattern_str = platform::slash_path(&normalized);
+        if entry.ends_with('/')
+            || (!pattern_str.is_empty() && repo_root.join(&pattern_str).is_dir())
+        {
+            scope.files.push(format!("{pattern_str}/"));
+            continue;
+        }
 
         let abs_pattern = repo_root.join(&pattern_str);
         let abs_str = abs_pattern.to_string_lossy().to_string();
@@ -396,20 +842,226 @@ fn resolve_raw_files(raw: &[&str], marker_parent: &Path, repo_root: &Path) -> Ve
                 let mut matched = false;
                 for abs_path in paths.flatten() {
                     if let Ok(rel) = abs_path.strip_prefix(repo_root) {
-                        files.push(rel.to_string_lossy().to_string());
+                        scope.files.push(platform::slash_path(rel));
                         matched = true;
                     }
                 }
                 if !matched {
-                    files.push(pattern_str);
+                    scope.files.push(pattern_str);
                 }
             }
             Err(_) => {
-                files.push(pattern_str);
+                scope.files.push(pattern_str);
             }
         }
     }
-    files
+    let exclude = &scope.exclude;
+    scope.files.retain(|f| !is_excluded(f, exclude));
+    scope
+}
+
+/// Whether a file entry covers `path`: the same path, or a path below a
+/// directory entry.
+pub fn entry_covers(entry: &str, path: &str) -> bool {
+    match entry.strip_suffix('/') {
+        Some("") => true,
+        Some(dir) => path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/')),
+        None => path == entry,
+    }
+}
+
+/// Whether `file` or one of its parent directories matches an exclusion.
+pub fn is_excluded(file: &str, exclude: &[String]) -> bool {
+    if exclude.is_empty() {
+        return false;
+    }
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..glob::MatchOptions::new()
+    };
+    let patterns: Vec<glob::Pattern> = exclude
+        .iter()
+        .filter_map(|p| glob::Pattern::new(p).ok())
+        .collect();
+    Path::new(file.trim_end_matches('/'))
+        .ancestors()
+        .filter(|p| !p.as_os_str().is_empty())
+        .any(|p| patterns.iter().any(|e| e.matches_path_with(p, options)))
+}
+
+/// Repo-relative paths of the files below `dir` (`""` for the whole root).
+fn files_below(root: &Path, dir: &str) -> Vec<String> {
+    WalkDir::new(root.join(dir))
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name();
+            !vcs::is_metadata_dir(name) && name != ".watcher_knight"
+        })
+        .flatten()
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.path().strip_prefix(root).ok().map(platform::slash_path))
+        .collect()
+}
+
+/// File and local context entries of `marker` that resolved to no existing
+/// path. Globs that match nothing are kept verbatim by [`resolve_raw_files`],
+/// so a typo would otherwise only surface when the watcher fails.
+pub fn unresolved_files<'a>(marker: &'a Marker, repo_root: &Path) -> Vec<&'a str> {
+    marker
+        .files
+        .iter()
+        .map(String::as_str)
+        .chain(marker.context_files())
+        .filter(|f| !repo_root.join(f).exists())
+        .collect()
+}
+
+// ── References ─────────────────────────────────────────────────────────────────
+
+/// Names mentioned as `@name` in an instruction, in order of first mention.
+/// An `@` inside a word (as in an email address) is not a reference.
+pub fn reference_names(instruction: &str) -> Vec<&str> {
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    let mut names: Vec<&str> = Vec::new();
+    for (i, _) in instruction.match_indices('@') {
+        if instruction[..i]
+            .chars()
+            .next_back()
+            .is_some_and(is_name_char)
+        {
+            continue;
+        }
+        let rest = &instruction[i + 1..];
+        let len = rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len());
+        // A sentence may end right after the name, as in "see @api-server-".
+        let name = rest[..len].trim_end_matches(['-', '_']);
+        if !name.is_empty() && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// The other markers `marker` refers to with `@name`. A name shared by
+/// several markers refers to all of them; unknown names are skipped.
+pub fn referenced<'a>(marker: &Marker, all: &'a [Marker]) -> Vec<&'a Marker> {
+    reference_names(&marker.instruction)
+        .into_iter()
+        .flat_map(|name| {
+            all.iter().filter(move |m| {
+                m.name == name && (m.rel_path != marker.rel_path || m.line != marker.line)
+            })
+        })
+        .collect()
+}
+
+// ── Fingerprinting ─────────────────────────────────────────────────────────────
+
+/// Stable fingerprint of a marker's definition: name, whitespace-normalized
+/// instruction, file scope, context and options.
+///
+/// Unlike the cache hashes this does not depend on the Rust release, so it is
+/// safe to persist and export.
+pub fn fingerprint(marker: &Marker) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(marker.name.as_bytes());
+    hasher.update([0]);
+    let instruction: Vec<&str> = marker.instruction.split_whitespace().collect();
+    hasher.update(instruction.join(" ").as_bytes());
+    hasher.update([0]);
+    let mut files = marker.files.clone();
+    files.sort();
+    for file in &files {
+        hasher.update(file.as_bytes());
+        hasher.update([0]);
+    }
+    // Prefixed so markers without exclusions keep their fingerprint.
+    for pattern in &marker.exclude {
+        hasher.update(b"!");
+        hasher.update(pattern.as_bytes());
+        hasher.update([0]);
+    }
+    for url in &marker.context {
+        hasher.update(b"@");
+        hasher.update(url.as_bytes());
+        hasher.update([0]);
+    }
+    let mut opts: Vec<_> = marker.options.iter().collect();
+    opts.sort();
+    for (k, v) in opts {
+        hasher.update(k.as_bytes());
+        hasher.update([b'=']);
+        hasher.update(v.as_bytes());
+        hasher.update([0]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .take(8)
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+// ── Rendering ──────────────────────────────────────────────────────────────────
+
+/// Pick the line-comment prefix conventionally used for a file, based on its
+/// extension (or name, for extensionless files like `Dockerfile`).
+pub fn comment_prefix_for_path(path: &str) -> &'static str {
+    let path = Path::new(path);
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "py" | "rb" | "sh" | "bash" | "zsh" | "yaml" | "yml" | "toml" | "pl" | "r" | "tf"
+        | "cmake" | "nix" | "ex" | "exs" => "#",
+        "sql" | "lua" | "hs" | "elm" | "ada" => "--",
+        "tex" | "erl" | "m" => "%",
+        "lisp" | "clj" | "el" | "scm" | "asm" | "ini" => ";",
+        "" if name == "Dockerfile" || name == "Makefile" => "#",
+        _ => "//",
+    }
+}
+
+/// Render a marker as ready-to-paste comment lines using `comment_prefix`.
+///
+/// Short single-line instructions are rendered on one line; anything else
+/// uses the multi-line form with the instruction on continuation lines.
+pub fn format_marker(
+    name: &str,
+    files: &[String],
+    instruction: &str,
+    comment_prefix: &str,
+) -> String {
+    let mut head = format!("{comment_prefix} <wk: {name}");
+    if !files.is_empty() {
+        head.push_str(&format!(" [{}]", files.join(", ")));
+    }
+
+    let lines: Vec<&str> = instruction
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    if lines.len() == 1 && head.len() + lines[0].len() + 4 <= 100 {
+        return format!("{head} {} />", lines[0]);
+    }
+
+    let mut out = head;
+    for (i, line) in lines.iter().enumerate() {
+        out.push('\n');
+        out.push_str(&format!("{comment_prefix} {line}"));
+        if i + 1 == lines.len() {
+            out.push_str(" />");
+        }
+    }
+    out
 }
 
 // ── Public API ─────────────────────────────────────────────────────────────────
@@ -417,13 +1069,70 @@ fn resolve_raw_files(raw: &[&str], marker_parent: &Path, repo_root: &Path) -> Ve
 /// Parse all watcher-knight markers from a file's contents.
 ///
 /// Returns `(markers, errors)` — valid markers are returned even when some tags
-/// fail to parse.
+/// fail to parse. Scans stream files with [`extract_file_tags`] instead.
+#[cfg(test)]
 pub fn parse_markers(
     contents: &str,
     rel_path: &str,
     repo_root: &Path,
 ) -> (Vec<Marker>, Vec<ParseError>) {
-    let (raw_tags, mut errors) = extract_raw_tags(contents, rel_path);
+    let (raw_tags, errors) = extract_raw_tags(contents.lines(), rel_path);
+    parse_tags(raw_tags, errors, rel_path, repo_root)
+}
+
+/// Extract the tags of the file at `path`, streaming it line by line. Files
+/// without a tag prefix are skipped after a cheap byte scan. JSON, YAML and
+/// TOML files that pass it (which also looks for `x-watcher-knight` and
+/// `#:wk`) are read whole, for the watchers they declare as data
+/// ([`structured`]). Fails on unreadable or non-UTF-8 files.
+pub fn extract_file_tags(
+    path: &Path,
+    rel_path: &str,
+) -> io::Result<(Vec<RawTag>, Vec<ParseError>)> {
+    let is_structured = structured::format_of(rel_path).is_some();
+    let extra: &[&str] = if is_structured {
+        &[structured::KEY, structured::TOML_TAG]
+    } else {
+        &[]
+    };
+    if !may_contain_tag(File::open(path)?, extra)? {
+        return Ok((Vec::new(), Vec::new()));
+    }
+    if is_structured && let Ok(contents) = fs::read_to_string(path) {
+        return Ok(extract_tags(&contents, rel_path));
+    }
+    let mut read_error = None;
+    let lines = BufReader::new(File::open(path)?)
+        .lines()
+        .map_while(|line| line.map_err(|e| read_error = Some(e)).ok());
+    let tags = extract_raw_tags(lines, rel_path);
+    match read_error {
+        Some(e) => Err(e),
+        None => Ok(tags),
+    }
+}
+
+/// Extract the tags of a file from its contents, including the watchers a
+/// JSON, YAML or TOML file declares as data.
+pub fn extract_tags(contents: &str, rel_path: &str) -> (Vec<RawTag>, Vec<ParseError>) {
+    let (mut tags, mut errors) = extract_raw_tags(contents.lines(), rel_path);
+    if let Some(format) = structured::format_of(rel_path) {
+        let (declared, declared_errors) = structured::extract_tags(contents, format, rel_path);
+        tags.extend(declared);
+        tags.sort_by_key(|t| t.line);
+        errors.extend(declared_errors);
+    }
+    (tags, errors)
+}
+
+/// Parse extracted tags into markers. File scopes are expanded against the
+/// current tree, so this runs on every scan even for indexed tags.
+pub fn parse_tags(
+    raw_tags: Vec<RawTag>,
+    mut errors: Vec<ParseError>,
+    rel_path: &str,
+    repo_root: &Path,
+) -> (Vec<Marker>, Vec<ParseError>) {
     let mut markers = Vec::new();
 
     let marker_parent = Path::new(rel_path).parent().unwrap_or(Path::new(""));
@@ -443,6 +1152,7 @@ pub fn parse_markers(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
     use std::path::Path;
 
     /// Helper: parse markers from a string using dummy paths.
@@ -474,6 +1184,22 @@ mod tests {
         assert_eq!(markers[0].files, vec!["a.ts", "b.py"]);
     }
 
+    #[test]
+    fn legacy_prefix_parses_the_same_syntax() {
+        let input = "# <watcher-knight: api-check [./a.ts]\n\
+                     # options={severity=\"warning\"}\n\
+                     # Ensure alignment.\n\
+                     # />";
+        let (markers, errors) = parse(input);
+        assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].name, "api-check");
+        assert_eq!(markers[0].files, vec!["a.ts"]);
+        assert_eq!(markers[0].severity(), "warning");
+        assert_eq!(markers[0].instruction, "Ensure alignment.");
+        assert!(may_contain_tag(input.as_bytes(), &[]).unwrap());
+    }
+
     #[test]
     fn multi_line_basic() {
         let input = "\
@@ -517,6 +1243,97 @@ mod tests {
         assert_eq!(markers[0].options.get("model").unwrap(), "haiku");
     }
 
+    #[test]
+    fn multi_line_with_context() {
+        let input = "\
+// <wk: api-policy [./*]
+// context = { https://wiki.example.com/api-policy.md, https://wiki.example.com/errors.md }
+// Context: endpoints follow the API policy. />";
+        let (markers, errors) = parse(input);
+        assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+        assert_eq!(
+            markers[0].context,
+            vec![
+                "https://wiki.example.com/api-policy.md",
+                "https://wiki.example.com/errors.md"
+            ]
+        );
+        assert_eq!(
+            markers[0].instruction,
+            "Context: endpoints follow the API policy."
+        );
+    }
+
+    #[test]
+    fn context_files_resolve_like_file_entries() {
+        let input = "\
+// <wk: schema-docs [./schema.graphql]
+// context={./README.md, /docs/api.md, https://wiki.example.com/a.md}
+// The README documents every query. />";
+        let (markers, errors) = parse_markers(input, "src/api/mod.rs", Path::new("/repo"));
+        assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+        let m = &markers[0];
+        assert_eq!(
+            m.context,
+            vec![
+                "src/api/README.md",
+                "docs/api.md",
+                "https://wiki.example.com/a.md"
+            ]
+        );
+        assert_eq!(
+            m.context_files().collect::<Vec<_>>(),
+            vec!["src/api/README.md", "docs/api.md"]
+        );
+        // Context files never make a change relevant.
+        assert!(!m.watches("src/api/README.md"));
+    }
+
+    #[test]
+    fn multi_line_with_metadata() {
+        let input = "\
+// <wk: api-versioned [./*]
+// description=\"Public routes are versioned\"
+// rationale=\"Mobile clients pin an API version for years\"
+// link=\"https://docs.example.com/adr/0012\"
+// Every route is mounted under /v1 or later. />";
+        let (markers, errors) = parse(input);
+        assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+        let meta = &markers[0].metadata;
+        assert_eq!(
+            meta.description.as_deref(),
+            Some("Public routes are versioned")
+        );
+        assert_eq!(
+            meta.rationale.as_deref(),
+            Some("Mobile clients pin an API version for years")
+        );
+        assert_eq!(
+            meta.link.as_deref(),
+            Some("https://docs.example.com/adr/0012")
+        );
+        assert_eq!(
+            markers[0].instruction,
+            "Every route is mounted under /v1 or later."
+        );
+        // Documentation does not change what is checked.
+        let (plain, _) =
+            parse("// <wk: api-versioned [./*]\n// Every route is mounted under /v1 or later. />");
+        assert_eq!(fingerprint(&markers[0]), fingerprint(&plain[0]));
+    }
+
+    #[test]
+    fn error_malformed_context() {
+        let input = "\
+// <wk: bad-context [./*]
+// context={https://wiki.example.com/a.md
+// Check it. />";
+        let (_, errors) = parse(input);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("malformed context"));
+        assert_eq!(errors[0].line, 2);
+    }
+
     #[test]
     fn hash_comment_style() {
         let input = "\
@@ -642,6 +1459,20 @@ some code here
         assert!(errors[0].message.contains("unclosed watcher tag"));
     }
 
+    #[test]
+    fn unclosed_tag_does_not_swallow_next_tag() {
+        let input = "\
+// <wk: forgot Never closed,
+// <wk: next Still parsed. />";
+        let (markers, errors) = parse(input);
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].name, "next");
+        assert_eq!(markers[0].instruction, "Still parsed.");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 1);
+        assert!(errors[0].unclosed);
+    }
+
     #[test]
     fn error_missing_colon() {
         let input = "// <wk no-colon-here />";
@@ -746,6 +1577,7 @@ not a comment
             file: "src/app.ts".to_string(),
             line: 42,
             message: "unclosed watcher tag".to_string(),
+            unclosed: true,
         };
         assert_eq!(err.to_string(), "src/app.ts:42: unclosed watcher tag");
     }
@@ -766,9 +1598,83 @@ not a comment
     }
 
     #[test]
-    fn find_tag_rejects_watcher_knight() {
-        // <watcher-knight is no longer a valid tag prefix
-        assert!(find_tag_in_line("# <watcher-knight: name").is_none());
+    fn tags_match_in_any_case_and_spacing() {
+        let (markers, errors) = parse(
+            "// <WK: upper Check it. />\n// <wk :spaced Check it. />\n//\t<Wk:\ttabbed Check it. />",
+        );
+        assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+        let names: Vec<&str> = markers.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["upper", "spaced", "tabbed"]);
+        assert!(may_contain_tag("x <WATCHER-KNIGHT: y".as_bytes(), &[]).unwrap());
+        assert_eq!(tag_opening("// <WK :upper Check />"), Some("<WK :upper"));
+        assert_eq!(tag_opening("// <wk: ok Check />"), Some("<wk: ok"));
+    }
+
+    #[test]
+    fn find_tag_accepts_legacy_prefix() {
+        let (pos, prefix) = find_tag_in_line("# <watcher-knight: name").unwrap();
+        assert_eq!(pos, 2);
+        assert_eq!(prefix, "<watcher-knight");
+        assert!(find_tag_in_line("# <watcher-knights: name").is_none());
+    }
+
+    #[test]
+    fn trailing_markers_after_code() {
+        let input = "\
+let x = 1; // <wk: single Check x. />
+let y = 2; // <wk: multi
+// Check y.
+// />
+f(); /* <wk: block Check f. /> */";
+        let (markers, errors) = parse(input);
+        assert!(errors.is_empty(), "{errors:?}");
+        let names: Vec<(&str, usize)> = markers.iter().map(|m| (m.name.as_str(), m.line)).collect();
+        assert_eq!(names, vec![("single", 1), ("multi", 2), ("block", 5)]);
+        assert_eq!(markers[1].instruction, "Check y.");
+    }
+
+    #[test]
+    fn several_markers_on_one_line() {
+        let (markers, errors) = parse("f(); // <wk: a First. /> <wk: b Second. />");
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(markers.len(), 2);
+        assert_eq!((markers[1].name.as_str(), markers[1].line), ("b", 1));
+        assert_eq!(markers[1].instruction, "Second.");
+    }
+
+    #[test]
+    fn tags_in_string_literals_are_ignored() {
+        let input = "\
+let s = \"<wk: fake Not a tag. />\";
+let t = \"// <wk: fake2 Not a tag. />\";
+fn f<'a>(x: &'a str) {} // <wk: real Lifetimes are not strings. />";
+        let (markers, errors) = parse(input);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].name, "real");
+
+        // The comment prefix depends on the language.
+        let py =
+            "x = \"#\"  # <wk: py Hash in a string first. />\ny = 1 ; <wk: no Not a comment. />";
+        let (markers, _) = parse_markers(py, "a.py", Path::new("/repo"));
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].name, "py");
+    }
+
+    #[test]
+    fn registered_keywords_open_tags() {
+        // The only test registering keywords: they apply to the whole process.
+        register_tag_keywords(&["Guard".to_string(), "wk-inv".to_string(), "wk".to_string()]);
+        assert_eq!(custom_prefixes(), ["<guard", "<wk-inv"]);
+
+        let (markers, errors) = parse("// <GUARD: a Check a. />\n# <wk-inv: b Check b. />");
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(markers[0].name, "a");
+        assert_eq!(markers[1].name, "b");
+        assert_eq!(canonical_prefix("<guard"), "<guard");
+        assert_eq!(canonical_prefix("<watcher-knight"), "<wk");
+        assert!(check_tag_keyword("inv_2").is_ok());
+        assert!(check_tag_keyword("").is_err());
     }
 
     #[test]
@@ -815,6 +1721,104 @@ not a comment
         assert_eq!(pairs[1], ("verbose", "true"));
     }
 
+    fn tree(files: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for file in files {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn negated_entries_exclude_matches() {
+        let dir = tree(&["src/a.rs", "src/generated/b.rs", "src/generated/deep/c.rs"]);
+        let mut scope = resolve_raw_files(
+            &["./**/*.rs", "!./generated/**"],
+            Path::new("src"),
+            dir.path(),
+        );
+        scope.files.sort();
+        assert_eq!(scope.files, vec!["src/a.rs"]);
+        assert_eq!(scope.exclude, vec!["src/generated/**"]);
+
+        // A plain directory exclusion covers everything below it.
+        let scope = resolve_raw_files(&["./**/*.rs", "!generated"], Path::new("src"), dir.path());
+        assert_eq!(scope.files, vec!["src/a.rs"]);
+    }
+
+    #[test]
+    fn directory_entries_expand_at_run_time() {
+        let dir = tree(&["src/handlers/a.ts", "src/handlers/gen/b.ts"]);
+        let (markers, _) = parse_markers(
+            "// <wk: check [./handlers/, !./handlers/gen/] Check it. />",
+            "src/x.ts",
+            dir.path(),
+        );
+        let m = &markers[0];
+        assert_eq!(m.files, vec!["src/handlers/"]);
+        assert_eq!(m.watched_files(dir.path()), vec!["src/handlers/a.ts"]);
+
+        // Files added after parsing are covered too.
+        std::fs::write(dir.path().join("src/handlers/new.ts"), "").unwrap();
+        assert_eq!(
+            m.watched_files(dir.path()),
+            vec!["src/handlers/a.ts", "src/handlers/new.ts"]
+        );
+        assert!(m.watches("src/handlers/later.ts"));
+        assert!(!m.watches("src/handlers/gen/b.ts"));
+        assert!(!m.watches("src/handlersx/a.ts"));
+
+        let at_commit = ["src/handlers/old.ts".to_string(), "src/x.ts".to_string()];
+        assert_eq!(m.watched_files_in(&at_commit), vec!["src/handlers/old.ts"]);
+    }
+
+    #[test]
+    fn root_anchored_entries_resolve_from_repo_root() {
+        let dir = tree(&["openapi.yaml", "docs/api.md", "src/deep/a.ts"]);
+        let scope = resolve_raw_files(
+            &["/openapi.yaml", "repo:docs/*.md", "./a.ts", "!/docs/old/**"],
+            Path::new("src/deep"),
+            dir.path(),
+        );
+        assert_eq!(
+            scope.files,
+            vec!["openapi.yaml", "docs/api.md", "src/deep/a.ts"]
+        );
+        assert_eq!(scope.exclude, vec!["docs/old/**"]);
+    }
+
+    #[test]
+    fn existing_directory_without_slash_is_a_directory_entry() {
+        let dir = tree(&["src/handlers/a.ts"]);
+        let scope = resolve_raw_files(&["./handlers"], Path::new("src"), dir.path());
+        assert_eq!(scope.files, vec!["src/handlers/"]);
+    }
+
+    #[test]
+    fn error_only_exclusions() {
+        let (markers, errors) = parse("// <wk: check [!./gen/**] Check it. />");
+        assert!(markers.is_empty());
+        assert!(errors[0].message.contains("only has exclusions"));
+    }
+
+    #[test]
+    fn unresolved_files_reports_missing_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/a.ts"), "").unwrap();
+        let (markers, _) = parse_markers(
+            "// <wk: check [./a.ts, ./*.tsx, ./b.ts] Check it. />",
+            "src/x.ts",
+            dir.path(),
+        );
+        assert_eq!(
+            unresolved_files(&markers[0], dir.path()),
+            vec!["src/*.tsx", "src/b.ts"]
+        );
+    }
+
     #[test]
     fn normalize_path_resolves_dots() {
         assert_eq!(
@@ -853,9 +1857,28 @@ not a comment
 
     #[test]
     fn find_tag_multiple_occurrences_returns_first() {
-        // TAG_PREFIXES is tried longest-first; within a prefix, find() returns first match
         let r = find_tag_in_line("<wk: a <wk: b").unwrap();
         assert_eq!(r.0, 0);
+        // The earliest tag wins whichever prefix opens it.
+        let r = find_tag_in_line("// <watcher-knight: a /> <wk: b />").unwrap();
+        assert_eq!(r, (3, "<watcher-knight"));
+    }
+
+    #[test]
+    fn find_tag_looks_past_rejected_words() {
+        let r = find_tag_in_line("// see <wking> then <wk: eps").unwrap();
+        assert_eq!(r, (20, "<wk"));
+    }
+
+    #[test]
+    fn tags_after_other_tags_and_words_are_all_found() {
+        let (markers, errors) = parse(
+            "// <watcher-knight: alpha Alpha holds. /> <wk: beta Beta holds. />\n\
+             // see <wking> then <wk: eps Eps holds. />\n",
+        );
+        assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+        let names: Vec<&str> = markers.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["alpha", "beta", "eps"]);
     }
 
     // ── Additional detect_comment_prefix tests ────────────────────────────
@@ -892,11 +1915,52 @@ not a comment
         assert_eq!(strip_continuation("//", Some("//")), Some(""));
     }
 
+    #[test]
+    fn comment_lead_keeps_doc_comment_variants() {
+        assert_eq!(comment_lead("/// "), Some("///"));
+        assert_eq!(comment_lead("  //! "), Some("//!"));
+        assert_eq!(comment_lead("#: "), Some("#:"));
+        assert_eq!(comment_lead("let x = 1; // "), Some("//"));
+        assert_eq!(comment_lead(" * "), Some("*"));
+        assert_eq!(comment_lead("/** "), Some("*"));
+        assert_eq!(comment_lead("/* "), None);
+        assert_eq!(detect_comment_prefix("//! "), Some("//"));
+        assert_eq!(detect_comment_prefix("#: "), Some("#"));
+        assert_eq!(strip_continuation("/// more", Some("///")), Some(" more"));
+        assert_eq!(strip_continuation("// more", Some("///")), Some(" more"));
+        assert_eq!(strip_continuation(" * more", Some("*")), Some(" more"));
+        assert_eq!(strip_continuation("more", Some("*")), Some("more"));
+    }
+
+    #[test]
+    fn multi_line_in_doc_comments() {
+        for (lead, continuation) in [
+            ("///", "///"),
+            ("//!", "//!"),
+            ("///!", "///!"),
+            ("#:", "#:"),
+            ("/**", " *"),
+            (" *", " *"),
+        ] {
+            let input = format!(
+                "{lead} <wk: doc-check [./a.ts]\n{continuation} /api handlers must\n\
+                 {continuation} validate input. />"
+            );
+            let (markers, errors) = parse(&input);
+            assert!(errors.is_empty(), "{lead}: unexpected errors: {errors:?}");
+            assert_eq!(markers.len(), 1, "{lead}");
+            assert_eq!(
+                markers[0].instruction, "/api handlers must\nvalidate input.",
+                "{lead}"
+            );
+        }
+    }
+
     // ── Additional extract_raw_tags tests ─────────────────────────────────
 
     #[test]
     fn extract_raw_tags_empty_file() {
-        let (tags, errors) = extract_raw_tags("", "test.ts");
+        let (tags, errors) = extract_raw_tags("".lines(), "test.ts");
         assert!(tags.is_empty());
         assert!(errors.is_empty());
     }
@@ -904,7 +1968,7 @@ not a comment
     #[test]
     fn extract_raw_tags_close_on_own_line() {
         let input = "// <wk: foo\n// Check it.\n// />";
-        let (tags, errors) = extract_raw_tags(input, "test.ts");
+        let (tags, errors) = extract_raw_tags(input.lines(), "test.ts");
         assert!(errors.is_empty());
         assert_eq!(tags.len(), 1);
     }
@@ -912,11 +1976,73 @@ not a comment
     #[test]
     fn extract_raw_tags_bare_tag_no_comment() {
         let input = "<wk: bare-tag Check something. />";
-        let (tags, errors) = extract_raw_tags(input, "test.ts");
+        let (tags, errors) = extract_raw_tags(input.lines(), "test.ts");
         assert!(errors.is_empty());
         assert_eq!(tags.len(), 1);
     }
 
+    #[test]
+    fn extract_raw_tags_rescans_line_that_ends_block() {
+        let input = "// <wk: open\n<wk: next Check. />";
+        let (tags, errors) = extract_raw_tags(input.lines(), "test.ts");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].line, 2);
+    }
+
+    // ── Streaming parse tests ─────────────────────────────────────────────
+
+    #[test]
+    fn may_contain_tag_finds_prefix_across_chunks() {
+        let mut data = vec![b'x'; 64 * 1024 - 1];
+        data.extend_from_slice(b"<wk: a Check. />");
+        assert!(may_contain_tag(data.as_slice(), &[]).unwrap());
+        assert!(!may_contain_tag(vec![b'x'; 200_000].as_slice(), &[]).unwrap());
+        assert!(!may_contain_tag(&b""[..], &[]).unwrap());
+        let mut data = vec![b' '; 64 * 1024 - 3];
+        data.extend_from_slice(b"\"x-watcher-knight\": {}");
+        assert!(!may_contain_tag(data.as_slice(), &[]).unwrap());
+        assert!(may_contain_tag(data.as_slice(), &[structured::KEY]).unwrap());
+    }
+
+    #[test]
+    fn extract_file_tags_scans_data_files_before_reading_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(
+            &path,
+            r#"{"x-watcher-knight": {"name": "a", "instruction": "Check it."}}"#,
+        )
+        .unwrap();
+        let (tags, errors) = extract_file_tags(&path, "config.json").unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(tags.len(), 1);
+        let path = dir.path().join("Cargo.toml");
+        fs::write(&path, "#:wk deps\n#: Keep them pinned. />\n").unwrap();
+        assert_eq!(extract_file_tags(&path, "Cargo.toml").unwrap().0.len(), 1);
+        fs::write(&path, "[package]\nname = \"x\"\n").unwrap();
+        assert!(extract_file_tags(&path, "Cargo.toml").unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn extract_file_tags_streams_large_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.log");
+        let mut contents = "noise\n".repeat(50_000);
+        contents.push_str("// <wk: late\n// Check it. />\n");
+        fs::write(&path, &contents).unwrap();
+        let (tags, errors) = extract_file_tags(&path, "big.log").unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(tags, extract_raw_tags(contents.lines(), "big.log").0);
+        assert_eq!(tags[0].line, 50_001);
+
+        fs::write(&path, b"\xff\xfe <wk: bad Check. />").unwrap();
+        assert!(extract_file_tags(&path, "big.log").is_err());
+        fs::write(&path, b"\xff\xfe no markers").unwrap();
+        let (tags, errors) = extract_file_tags(&path, "big.log").unwrap();
+        assert!(tags.is_empty() && errors.is_empty());
+    }
+
     // ── Additional parse_raw_tag / parse_markers tests ────────────────────
 
     #[test]
@@ -1044,4 +2170,107 @@ line 3
             "parse errors in examples/backend.py: {errors:?}"
         );
     }
+
+    // ── Rendering ──────────────────────────────────────────────────────────
+
+    #[test]
+    fn comment_prefix_for_common_extensions() {
+        assert_eq!(comment_prefix_for_path("src/app.ts"), "//");
+        assert_eq!(comment_prefix_for_path("backend.py"), "#");
+        assert_eq!(comment_prefix_for_path("db/001.sql"), "--");
+        assert_eq!(comment_prefix_for_path("paper.tex"), "%");
+        assert_eq!(comment_prefix_for_path("services/api/Dockerfile"), "#");
+        assert_eq!(comment_prefix_for_path("unknown.xyz"), "//");
+    }
+
+    #[test]
+    fn reference_names_skip_emails_and_repeats() {
+        assert_eq!(
+            reference_names(
+                "Must match @api-server. Ask ops@corp.com; see @api-server and @db_schema-."
+            ),
+            vec!["api-server", "db_schema"]
+        );
+        assert!(reference_names("no refs @ all").is_empty());
+    }
+
+    #[test]
+    fn referenced_resolves_names_to_other_markers() {
+        let (markers, _) = parse(
+            "// <wk: client Must agree with @server and @missing. />\n\
+             // <wk: server Must agree with @client. />\n\
+             // <wk: client Second client, see @client. />\n",
+        );
+        let names = |refs: Vec<&Marker>| -> Vec<(String, usize)> {
+            refs.iter().map(|m| (m.name.clone(), m.line)).collect()
+        };
+        assert_eq!(
+            names(referenced(&markers[0], &markers)),
+            vec![("server".to_string(), 2)]
+        );
+        assert_eq!(
+            names(referenced(&markers[1], &markers)),
+            vec![("client".to_string(), 1), ("client".to_string(), 3)]
+        );
+        assert_eq!(
+            names(referenced(&markers[2], &markers)),
+            vec![("client".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn format_marker_single_line() {
+        let out = format_marker("check", &["./a.ts".to_string()], "Keep it.", "//");
+        assert_eq!(out, "// <wk: check [./a.ts] Keep it. />");
+    }
+
+    #[test]
+    fn format_marker_multi_line() {
+        let out = format_marker("check", &[], "First line.\nSecond line.", "#");
+        assert_eq!(out, "# <wk: check\n# First line.\n# Second line. />");
+    }
+
+    #[test]
+    fn format_marker_roundtrips_through_parser() {
+        let files = vec!["./a.ts".to_string(), "./b.py".to_string()];
+        let out = format_marker("api-align", &files, "Line one.\nLine two.", "//");
+        let (markers, errors) = parse(&out);
+        assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+        assert_eq!(markers[0].name, "api-align");
+        assert_eq!(markers[0].files, vec!["a.ts", "b.py"]);
+        assert_eq!(markers[0].instruction, "Line one.\nLine two.");
+    }
+
+    // ── Fingerprinting ─────────────────────────────────────────────────────
+
+    #[test]
+    fn fingerprint_is_stable_hex() {
+        let (markers, _) = parse("// <wk: check [./a.ts] Keep it. />");
+        let fp = fingerprint(&markers[0]);
+        assert_eq!(fp.len(), 16);
+        assert!(fp.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(fp, fingerprint(&markers[0]));
+    }
+
+    #[test]
+    fn fingerprint_ignores_whitespace_and_location() {
+        let (a, _) = parse("// <wk: check Keep   it. />");
+        let (b, _) = parse("\n\n// <wk: check\n// Keep\n// it. />");
+        assert_eq!(fingerprint(&a[0]), fingerprint(&b[0]));
+    }
+
+    #[test]
+    fn fingerprint_changes_with_definition() {
+        let (base, _) = parse("// <wk: check [./a.ts] Keep it. />");
+        let (renamed, _) = parse("// <wk: other [./a.ts] Keep it. />");
+        let (rescoped, _) = parse("// <wk: check [./b.ts] Keep it. />");
+        let (reworded, _) = parse("// <wk: check [./a.ts] Keep that. />");
+        let (with_context, _) =
+            parse("// <wk: check [./a.ts]\n// context={https://wiki/a.md}\n// Keep it. />");
+        let fp = fingerprint(&base[0]);
+        assert_ne!(fp, fingerprint(&with_context[0]));
+        assert_ne!(fp, fingerprint(&renamed[0]));
+        assert_ne!(fp, fingerprint(&rescoped[0]));
+        assert_ne!(fp, fingerprint(&reworded[0]));
+    }
 }
@@ -0,0 +1,148 @@
+use std::path::Path;
+
+use crate::marker::{self, Marker};
+use crate::vcs::Vcs;
+
+/// Logins that approved the change, comma- or space-separated. CI sets it
+/// from the pull request's approving reviews.
+pub const APPROVERS_ENV: &str = "WK_APPROVED_BY";
+
+/// A watcher defined at the diff base that the change edited or removed.
+#[derive(Debug)]
+pub struct MarkerChange {
+    pub before: Marker,
+    /// `None` when the watcher was removed.
+    pub after: Option<Marker>,
+}
+
+/// The watchers of `changed_files` as they were at `base`.
+pub fn markers_at_base(
+    vcs: &dyn Vcs,
+    base: &str,
+    changed_files: &[String],
+    root: &Path,
+) -> Vec<Marker> {
+    let mut markers = Vec::new();
+    for file in changed_files {
+        let Some(contents) = vcs.file_at(base, file) else {
+            continue;
+        };
+        let (tags, errors) = marker::extract_tags(&contents, file);
+        markers.extend(marker::parse_tags(tags, errors, file, root).0);
+    }
+    markers
+}
+
+/// The watchers in `before` that are gone from `after` or whose definition
+/// differs there. Watchers are matched by name, preferring one in the same
+/// file, so moving a watcher is not a change; renaming one removes it.
+pub fn find(before: &[Marker], after: &[Marker]) -> Vec<MarkerChange> {
+    before
+        .iter()
+        .filter_map(|old| {
+            let same_name: Vec<&Marker> = after.iter().filter(|m| m.name == old.name).collect();
+            let new = same_name
+                .iter()
+                .find(|m| m.rel_path == old.rel_path)
+                .or(same_name.first());
+            match new {
+                Some(new) if marker::fingerprint(new) == marker::fingerprint(old) => None,
+                new => Some(MarkerChange {
+                    before: old.clone(),
+                    after: new.map(|m| (*m).clone()),
+                }),
+            }
+        })
+        .collect()
+}
+
+/// The logins in a `WK_APPROVED_BY` value, lowercased and without `@`.
+pub fn parse_approvers(value: &str) -> Vec<String> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .map(|login| login.trim_start_matches('@').to_lowercase())
+        .filter(|login| !login.is_empty())
+        .collect()
+}
+
+/// The owner of the watcher before the change, if they approved it.
+pub fn approved_by<'a>(change: &'a MarkerChange, approvers: &[String]) -> Option<&'a str> {
+    change
+        .before
+        .options
+        .get("owner")
+        .map(String::as_str)
+        .filter(|owner| approvers.contains(&owner.trim_start_matches('@').to_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker(name: &str, rel_path: &str, instruction: &str) -> Marker {
+        Marker {
+            name: name.to_string(),
+            rel_path: rel_path.to_string(),
+            line: 1,
+            instruction: instruction.to_string(),
+            files: Vec::new(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: Default::default(),
+        }
+    }
+
+    #[test]
+    fn find_reports_edits_and_removals_but_not_moves() {
+        let before = [
+            marker("kept", "a.ts", "Check it."),
+            marker("moved", "a.ts", "Check it."),
+            marker("edited", "a.ts", "Check everything."),
+            marker("gone", "a.ts", "Check it."),
+        ];
+        let mut moved = marker("moved", "b.ts", "Check it.");
+        moved.line = 40;
+        let after = [
+            marker("kept", "a.ts", "Check   it."),
+            moved,
+            marker("edited", "b.ts", "Check it."),
+            marker("edited", "a.ts", "Check some things."),
+        ];
+        let changes = find(&before, &after);
+        let found: Vec<(&str, Option<&str>)> = changes
+            .iter()
+            .map(|c| {
+                (
+                    c.before.name.as_str(),
+                    c.after.as_ref().map(|m| m.instruction.as_str()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [("edited", Some("Check some things.")), ("gone", None)]
+        );
+    }
+
+    #[test]
+    fn only_the_owner_approves() {
+        let mut owned = marker("auth", "a.ts", "Check it.");
+        owned
+            .options
+            .insert("owner".to_string(), "@Alice".to_string());
+        let change = MarkerChange {
+            before: owned,
+            after: None,
+        };
+        let approvers = parse_approvers("bob, alice  carol,");
+        assert_eq!(approvers, ["bob", "alice", "carol"]);
+        assert_eq!(approved_by(&change, &approvers), Some("@Alice"));
+        assert_eq!(approved_by(&change, &parse_approvers("bob")), None);
+        let unowned = MarkerChange {
+            before: marker("auth", "a.ts", "Check it."),
+            after: None,
+        };
+        assert_eq!(approved_by(&unowned, &approvers), None);
+    }
+}
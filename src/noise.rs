@@ -0,0 +1,231 @@
+use regex::Regex;
+
+/// `ignore_hunks_matching`: changed lines that say nothing to a watcher, such
+/// as log statements or license headers.
+pub struct NoiseFilter {
+    patterns: Vec<Regex>,
+}
+
+/// A diff with its noise dropped.
+#[derive(Debug, PartialEq)]
+pub struct Filtered {
+    pub diff: String,
+    /// Files whose every changed line was noise. They no longer count as
+    /// changed when watchers are picked.
+    pub quiet_files: Vec<String>,
+}
+
+impl NoiseFilter {
+    pub fn new(patterns: &[String]) -> Result<Self, String> {
+        let patterns = patterns
+            .iter()
+            .map(|p| Regex::new(p).map_err(|e| format!("invalid ignore_hunks_matching `{p}`: {e}")))
+            .collect::<Result<_, _>>()?;
+        Ok(NoiseFilter { patterns })
+    }
+
+    fn is_noise(&self, line: &str) -> bool {
+        let text = line.trim_end_matches(['\n', '\r']);
+        self.patterns.iter().any(|re| re.is_match(text))
+    }
+
+    /// Drop the added and removed lines of a unified diff whose text (after
+    /// the `+` or `-`) matches a pattern, then the hunks left with no changes,
+    /// then the files left with no hunks. Hunk headers are recounted, so the
+    /// diff stays well formed.
+    pub fn filter(&self, diff: &str) -> Filtered {
+        if self.patterns.is_empty() {
+            return Filtered {
+                diff: diff.to_string(),
+                quiet_files: Vec::new(),
+            };
+        }
+        let mut out = String::new();
+        let mut quiet_files = Vec::new();
+        for section in sections(diff) {
+            let path = section
+                .lines()
+                .next()
+                .and_then(|l| l.strip_prefix("diff --git "))
+                .and_then(|h| h.rsplit_once(" b/"))
+                .map(|(_, p)| p.to_string());
+            let (header, hunks) = split_hunks(section);
+            if hunks.is_empty() {
+                // Nothing line-based to filter: a rename, mode change or binary.
+                out.push_str(section);
+                continue;
+            }
+            let kept: Vec<String> = hunks.iter().filter_map(|h| self.filter_hunk(h)).collect();
+            if kept.is_empty() {
+                quiet_files.extend(path);
+                continue;
+            }
+            out.push_str(header);
+            for hunk in kept {
+                out.push_str(&hunk);
+            }
+        }
+        Filtered {
+            diff: out,
+            quiet_files,
+        }
+    }
+
+    /// A hunk without its noise, `None` if only noise changed in it.
+    fn filter_hunk(&self, hunk: &str) -> Option<String> {
+        let mut lines = hunk.split_inclusive('\n');
+        let header = lines.next()?;
+        let mut body = String::new();
+        let (mut dropped_old, mut dropped_new, mut changes) = (0, 0, 0);
+        let mut dropped_last = false;
+        for line in lines {
+            let noise = match line.as_bytes().first() {
+                Some(b'-') if self.is_noise(&line[1..]) => {
+                    dropped_old += 1;
+                    true
+                }
+                Some(b'+') if self.is_noise(&line[1..]) => {
+                    dropped_new += 1;
+                    true
+                }
+                Some(b'-' | b'+') => {
+                    changes += 1;
+                    false
+                }
+                // `\ No newline at end of file` belongs to the line before it.
+                Some(b'\\') => dropped_last,
+                _ => false,
+            };
+            dropped_last = noise;
+            if !noise {
+                body.push_str(line);
+            }
+        }
+        if changes == 0 {
+            return None;
+        }
+        Some(recount(header, dropped_old, dropped_new) + body.as_str())
+    }
+}
+
+/// The `diff --git` sections of a diff, each with its trailing newline.
+fn sections(diff: &str) -> Vec<&str> {
+    let mut starts: Vec<usize> = diff
+        .match_indices("diff --git ")
+        .map(|(i, _)| i)
+        .filter(|&i| i == 0 || diff.as_bytes()[i - 1] == b'\n')
+        .collect();
+    if starts.first() != Some(&0) {
+        starts.insert(0, 0);
+    }
+    starts
+        .iter()
+        .zip(starts.iter().skip(1).chain([&diff.len()]))
+        .map(|(&a, &b)| &diff[a..b])
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// A section's file header (up to its first `@@`) and its hunks.
+fn split_hunks(section: &str) -> (&str, Vec<&str>) {
+    let mut starts = Vec::new();
+    let mut offset = 0;
+    for line in section.split_inclusive('\n') {
+        if line.starts_with("@@") {
+            starts.push(offset);
+        }
+        offset += line.len();
+    }
+    let Some(&first) = starts.first() else {
+        return (section, Vec::new());
+    };
+    let hunks = starts
+        .iter()
+        .zip(starts.iter().skip(1).chain([&section.len()]))
+        .map(|(&a, &b)| &section[a..b])
+        .collect();
+    (&section[..first], hunks)
+}
+
+/// `@@ -a,b +c,d @@` with `dropped_old` fewer old lines and `dropped_new`
+/// fewer new ones. A header that does not parse is kept as it is.
+fn recount(header: &str, dropped_old: usize, dropped_new: usize) -> String {
+    let recounted = (|| {
+        let rest = header.strip_prefix("@@ -")?;
+        let (ranges, tail) = rest.split_once(" @@")?;
+        let (old, new) = ranges.split_once(" +")?;
+        let shrink = |range: &str, dropped: usize| -> Option<String> {
+            let (start, count) = range.split_once(',').unwrap_or((range, "1"));
+            let count: usize = count.parse().ok()?;
+            Some(format!("{start},{}", count.saturating_sub(dropped)))
+        };
+        Some(format!(
+            "@@ -{} +{} @@{tail}",
+            shrink(old, dropped_old)?,
+            shrink(new, dropped_new)?
+        ))
+    })();
+    recounted.unwrap_or_else(|| header.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(patterns: &[&str]) -> NoiseFilter {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        NoiseFilter::new(&patterns).unwrap()
+    }
+
+    const DIFF: &str = "diff --git a/src/a.ts b/src/a.ts\n\
+        --- a/src/a.ts\n\
+        +++ b/src/a.ts\n\
+        @@ -1,3 +1,4 @@\n \
+        fn a() {\n\
+        +  logger.info(\"a\");\n\
+        -  return 1;\n\
+        +  return 2;\n \
+        }\n\
+        @@ -10,2 +11,3 @@\n \
+        fn b() {\n\
+        +  logger.debug(\"b\");\n \
+        }\n\
+        diff --git a/src/b.ts b/src/b.ts\n\
+        --- a/src/b.ts\n\
+        +++ b/src/b.ts\n\
+        @@ -1,1 +1,1 @@\n\
+        -// Copyright 2024\n\
+        +// Copyright 2025\n";
+
+    #[test]
+    fn filter_drops_noise_lines_hunks_and_files() {
+        let filtered = filter(&[r"^\s*logger\.", r"^\s*//"]).filter(DIFF);
+        assert_eq!(
+            filtered.diff,
+            "diff --git a/src/a.ts b/src/a.ts\n\
+             --- a/src/a.ts\n\
+             +++ b/src/a.ts\n\
+             @@ -1,3 +1,3 @@\n \
+             fn a() {\n\
+             -  return 1;\n\
+             +  return 2;\n \
+             }\n"
+        );
+        assert_eq!(filtered.quiet_files, ["src/b.ts"]);
+    }
+
+    #[test]
+    fn filter_without_patterns_keeps_the_diff() {
+        let filtered = filter(&[]).filter(DIFF);
+        assert_eq!(filtered.diff, DIFF);
+        assert!(filtered.quiet_files.is_empty());
+        let rename = "diff --git a/x b/y\nsimilarity index 100%\nrename from x\nrename to y\n";
+        assert_eq!(filter(&["x"]).filter(rename).diff, rename);
+    }
+
+    #[test]
+    fn new_rejects_invalid_patterns() {
+        let err = NoiseFilter::new(&["(".to_string()]).err().unwrap();
+        assert!(err.starts_with("invalid ignore_hunks_matching `(`"));
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::cli;
+use crate::history::{self, QUERY_SCHEMA_VERSION};
+use crate::marker::Marker;
+
+/// How far back `stats --owners` looks for commits, unless `--since` says
+/// otherwise.
+pub const DEFAULT_RECENT: &str = "90d";
+
+/// Whoever committed a line, per `git blame`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Author {
+    pub name: String,
+    pub email: String,
+}
+
+/// The author of the line in `git blame --porcelain` output, or `None` if it
+/// is not committed yet.
+pub fn parse_blame(porcelain: &str) -> Option<Author> {
+    let mut lines = porcelain.lines();
+    let sha = lines.next()?.split(' ').next()?;
+    if sha.bytes().all(|b| b == b'0') {
+        return None;
+    }
+    let mut name = None;
+    let mut email = None;
+    for line in lines {
+        if let Some(n) = line.strip_prefix("author ") {
+            name = Some(n.to_string());
+        } else if let Some(m) = line.strip_prefix("author-mail ") {
+            email = Some(m.trim_matches(['<', '>']).to_lowercase());
+        }
+    }
+    Some(Author {
+        name: name?,
+        email: email?,
+    })
+}
+
+/// Who wrote the opening line of `marker`. Whitespace changes and moves
+/// within the file keep the original author.
+pub fn blame(root: &Path, marker: &Marker) -> Option<Author> {
+    let range = format!("{0},{0}", marker.line);
+    let out = cli::git_output(
+        root,
+        &[
+            "blame",
+            "--porcelain",
+            "-w",
+            "-M",
+            "-L",
+            &range,
+            "--",
+            &marker.rel_path,
+        ],
+    )?;
+    parse_blame(&out)
+}
+
+/// An author's latest commit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LastCommit {
+    pub time: i64,
+    /// `YYYY-MM-DD`.
+    pub date: String,
+}
+
+/// Each author's latest commit, by lowercased email, from
+/// `git log --format=%ae%x09%at%x09%as` (newest first).
+pub fn parse_log(log: &str) -> HashMap<String, LastCommit> {
+    let mut last: HashMap<String, LastCommit> = HashMap::new();
+    for line in log.lines() {
+        let mut parts = line.split('\t');
+        let (Some(email), Some(Ok(time)), Some(date)) =
+            (parts.next(), parts.next().map(str::parse), parts.next())
+        else {
+            continue;
+        };
+        last.entry(email.to_lowercase()).or_insert(LastCommit {
+            time,
+            date: date.to_string(),
+        });
+    }
+    last
+}
+
+/// Each author's latest commit reachable from `HEAD`.
+pub fn last_commits(root: &Path) -> HashMap<String, LastCommit> {
+    cli::git_output(root, &["log", "--format=%ae%x09%at%x09%as"])
+        .map(|log| parse_log(&log))
+        .unwrap_or_default()
+}
+
+/// A watcher whose author has not committed since the cutoff, so it likely
+/// has no one looking after it.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Orphan {
+    pub name: String,
+    pub location: String,
+    pub author: String,
+    pub email: String,
+    /// The author's latest commit, if any is reachable.
+    pub last_commit: Option<String>,
+}
+
+/// The watchers among `markers` whose author, per `blame`, has no commit at
+/// or after `since`. Watchers with an `owner` option already have one, and
+/// uncommitted ones are being written now.
+pub fn find(
+    markers: &[Marker],
+    blame: impl Fn(&Marker) -> Option<Author>,
+    last: &HashMap<String, LastCommit>,
+    since: i64,
+) -> Vec<Orphan> {
+    markers
+        .iter()
+        .filter(|m| !m.options.contains_key("owner"))
+        .filter_map(|m| {
+            let author = blame(m)?;
+            let latest = last.get(&author.email);
+            if latest.is_some_and(|c| c.time >= since) {
+                return None;
+            }
+            Some(Orphan {
+                name: m.name.clone(),
+                location: format!("{}:{}", m.rel_path, m.line),
+                author: author.name,
+                email: author.email,
+                last_commit: latest.map(|c| c.date.clone()),
+            })
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct OwnersOutput<'a> {
+    version: u32,
+    markers: &'a [Orphan],
+}
+
+pub fn render_json(orphans: &[Orphan]) -> String {
+    serde_json::to_string_pretty(&OwnersOutput {
+        version: QUERY_SCHEMA_VERSION,
+        markers: orphans,
+    })
+    .unwrap()
+}
+
+pub fn render_table(orphans: &[Orphan]) -> String {
+    let cells: Vec<[String; 4]> = orphans
+        .iter()
+        .map(|o| {
+            [
+                o.name.clone(),
+                o.location.clone(),
+                format!("{} <{}>", o.author, o.email),
+                o.last_commit.clone().unwrap_or_else(|| "-".to_string()),
+            ]
+        })
+        .collect();
+    history::render_columns(["MARKER", "LOCATION", "AUTHOR", "LAST COMMIT"], &cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker(name: &str, owner: Option<&str>) -> Marker {
+        let mut m = Marker {
+            name: name.to_string(),
+            rel_path: format!("src/{name}.ts"),
+            line: 3,
+            instruction: "Check it".to_string(),
+            files: Vec::new(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: Default::default(),
+        };
+        if let Some(owner) = owner {
+            m.options.insert("owner".to_string(), owner.to_string());
+        }
+        m
+    }
+
+    #[test]
+    fn parse_blame_reads_the_author() {
+        let porcelain = "\
+4f1c2a9e0b 3 3 1
+author Alice Smith
+author-mail <Alice@Example.com>
+author-time 1700000000
+\t// <wk: api
+";
+        assert_eq!(
+            parse_blame(porcelain),
+            Some(Author {
+                name: "Alice Smith".to_string(),
+                email: "alice@example.com".to_string(),
+            })
+        );
+        let uncommitted = "0000000000000000000000000000000000000000 3 3 1\n\
+                           author Not Committed Yet\nauthor-mail <not.committed.yet>\n";
+        assert_eq!(parse_blame(uncommitted), None);
+    }
+
+    #[test]
+    fn parse_log_keeps_each_authors_latest_commit() {
+        let log = "bob@x.com\t300\t2024-03-01\nAlice@x.com\t200\t2024-02-01\nbob@x.com\t100\t2024-01-01\n";
+        let last = parse_log(log);
+        assert_eq!(last.len(), 2);
+        assert_eq!(last["bob@x.com"].date, "2024-03-01");
+        assert_eq!(last["alice@x.com"].time, 200);
+    }
+
+    #[test]
+    fn find_flags_watchers_of_inactive_authors() {
+        let markers = [
+            marker("active", None),
+            marker("gone", None),
+            marker("assigned", Some("carol")),
+            marker("draft", None),
+            marker("unknown", None),
+        ];
+        let blame = |m: &Marker| {
+            let email = match m.name.as_str() {
+                "active" => "bob@x.com",
+                "gone" | "assigned" => "alice@x.com",
+                "unknown" => "dave@x.com",
+                _ => return None,
+            };
+            Some(Author {
+                name: email.split('@').next().unwrap().to_string(),
+                email: email.to_string(),
+            })
+        };
+        let last = parse_log("bob@x.com\t300\t2024-03-01\nalice@x.com\t100\t2024-01-01\n");
+        let orphans = find(&markers, blame, &last, 200);
+        assert_eq!(
+            orphans,
+            [
+                Orphan {
+                    name: "gone".to_string(),
+                    location: "src/gone.ts:3".to_string(),
+                    author: "alice".to_string(),
+                    email: "alice@x.com".to_string(),
+                    last_commit: Some("2024-01-01".to_string()),
+                },
+                Orphan {
+                    name: "unknown".to_string(),
+                    location: "src/unknown.ts:3".to_string(),
+                    author: "dave".to_string(),
+                    email: "dave@x.com".to_string(),
+                    last_commit: None,
+                },
+            ]
+        );
+        assert!(render_table(&orphans).contains("alice <alice@x.com>  2024-01-01"));
+    }
+}
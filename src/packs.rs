@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use sha2::{Digest, Sha256};
+
+use crate::http::Request;
+use crate::marker::Marker;
+use crate::policy;
+
+const PACKS_DIR: &str = ".watcher_knight/packs";
+const PACKS_LOCK: &str = ".watcher_knight/packs/lock.json";
+
+#[derive(Debug, PartialEq)]
+enum PackSource {
+    /// A git repository, optionally pinned to a branch, tag or commit after `#`.
+    Git {
+        url: String,
+        reference: Option<String>,
+    },
+    /// A single policy YAML file served over HTTP(S).
+    Url(String),
+}
+
+fn parse_source(source: &str) -> Result<PackSource, String> {
+    let (location, reference) = match source.split_once('#') {
+        Some((l, r)) if !r.is_empty() => (l, Some(r.to_string())),
+        Some((l, _)) => (l, None),
+        None => (source, None),
+    };
+    let is_git = location.ends_with(".git")
+        || location.starts_with("git@")
+        || location.starts_with("ssh://")
+        || location.starts_with("git://")
+        || location.starts_with("file://");
+    if is_git {
+        return Ok(PackSource::Git {
+            url: location.to_string(),
+            reference,
+        });
+    }
+    if location.starts_with("https://") || location.starts_with("http://") {
+        return Ok(PackSource::Url(source.to_string()));
+    }
+    Err(format!(
+        "unsupported policy pack `{source}`: expected a git URL (`<url>.git#<ref>`) or an \
+         http(s) URL to a policy YAML file"
+    ))
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Directory name used to cache a pack, derived from its source string.
+fn pack_key(source: &str) -> String {
+    sha256_hex(source.as_bytes())[..16].to_string()
+}
+
+type PackLock = BTreeMap<String, String>;
+
+fn load_lock(root: &Path) -> PackLock {
+    fs::read_to_string(root.join(PACKS_LOCK))
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+fn save_lock(root: &Path, lock: &PackLock) {
+    fs::create_dir_all(root.join(PACKS_DIR)).ok();
+    let data = serde_json::to_string_pretty(lock).unwrap();
+    fs::write(root.join(PACKS_LOCK), data).ok();
+}
+
+fn git(args: &[&str], cwd: Option<&Path>) -> Result<String, String> {
+    let mut cmd = process::Command::new("git");
+    cmd.args(args);
+    if let Some(dir) = cwd {
+        cmd.current_dir(dir);
+    }
+    let output = cmd
+        .output()
+        .map_err(|e| format!("failed to run `git {}`: {e}", args.join(" ")))?;
+    if !output.status.success() {
+        return Err(format!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Clone (once) and check out a git pack, returning the pinned commit.
+///
+/// When the lock already pins a commit for this source, that commit is checked
+/// out without touching the network.
+fn fetch_git(
+    url: &str,
+    reference: Option<&str>,
+    dest: &Path,
+    pin: Option<&str>,
+) -> Result<String, String> {
+    let dest_str = dest.to_string_lossy().to_string();
+    if !dest.join(".git").exists() {
+        fs::create_dir_all(dest.parent().unwrap_or(Path::new("."))).ok();
+        git(&["clone", "--quiet", url, &dest_str], None)?;
+    }
+    match (pin, reference) {
+        (Some(sha), _) => git(&["checkout", "--quiet", "--detach", sha], Some(dest))?,
+        (None, Some(r)) => git(&["checkout", "--quiet", "--detach", r], Some(dest))?,
+        (None, None) => String::new(),
+    };
+    git(&["rev-parse", "HEAD"], Some(dest))
+}
+
+/// Download a URL pack (once) and return the sha256 of its contents.
+///
+/// A cached file whose hash no longer matches the pin is rejected.
+fn fetch_url(url: &str, dest: &Path, pin: Option<&str>) -> Result<String, String> {
+    if !dest.exists() {
+        fs::create_dir_all(dest.parent().unwrap_or(Path::new("."))).ok();
+        let response = Request::get(url)
+            .follow_redirects()
+            .send()
+            .and_then(|r| r.success("GET"))
+            .map_err(|e| format!("failed to download policy pack `{url}`: {e}"))?;
+        fs::write(dest, &response.body)
+            .map_err(|e| format!("cannot cache pack `{}`: {e}", dest.display()))?;
+    }
+    let data =
+        fs::read(dest).map_err(|e| format!("cannot read cached pack `{}`: {e}", dest.display()))?;
+    let digest = sha256_hex(&data);
+    if let Some(expected) = pin
+        && expected != digest
+    {
+        return Err(format!(
+            "policy pack `{url}` does not match its pinned sha256 {expected}; delete {} to re-pin",
+            dest.display()
+        ));
+    }
+    Ok(digest)
+}
+
+/// Policy YAML files at the top level of a git pack, sorted by name.
+fn pack_policy_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.is_file()
+                && p.extension()
+                    .is_some_and(|ext| ext == "yaml" || ext == "yml")
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Fetch (or reuse the cached copy of) every configured policy pack and return
+/// their invariants as markers.
+///
+/// Packs are cached under `.watcher_knight/packs/` and pinned in
+/// `.watcher_knight/packs/lock.json`: git packs by commit, URL packs by content
+/// hash. Delete a pack's cache directory to re-fetch it.
+pub fn load_packs(sources: &[String], root: &Path) -> Result<Vec<Marker>, String> {
+    if sources.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut lock = load_lock(root);
+    let mut markers = Vec::new();
+
+    for source in sources {
+        let dest = root.join(PACKS_DIR).join(pack_key(source));
+        let pin = lock.get(source).map(String::as_str);
+        match parse_source(source)? {
+            PackSource::Git { url, reference } => {
+                let sha = fetch_git(&url, reference.as_deref(), &dest, pin)?;
+                for file in pack_policy_files(&dest) {
+                    let data = fs::read_to_string(&file)
+                        .map_err(|e| format!("cannot read `{}`: {e}", file.display()))?;
+                    let name = file.file_name().unwrap().to_string_lossy();
+                    let label = format!("{source}/{name}");
+                    markers.extend(policy::parse_policy(&data, &label, root)?);
+                }
+                lock.insert(source.clone(), sha);
+            }
+            PackSource::Url(url) => {
+                let file = dest.with_extension("yaml");
+                let digest = fetch_url(&url, &file, pin)?;
+                let data = fs::read_to_string(&file)
+                    .map_err(|e| format!("cannot read `{}`: {e}", file.display()))?;
+                markers.extend(policy::parse_policy(&data, source, root)?);
+                lock.insert(source.clone(), digest);
+            }
+        }
+    }
+
+    save_lock(root, &lock);
+    Ok(markers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_source_git_with_ref() {
+        assert_eq!(
+            parse_source("git@github.com:org/wk-policies.git#v3").unwrap(),
+            PackSource::Git {
+                url: "git@github.com:org/wk-policies.git".to_string(),
+                reference: Some("v3".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_source_git_without_ref() {
+        assert_eq!(
+            parse_source("https://github.com/org/wk-policies.git").unwrap(),
+            PackSource::Git {
+                url: "https://github.com/org/wk-policies.git".to_string(),
+                reference: None,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_source_url() {
+        assert_eq!(
+            parse_source("https://example.com/policies.yaml").unwrap(),
+            PackSource::Url("https://example.com/policies.yaml".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_source_unsupported() {
+        let err = parse_source("org/wk-policies").unwrap_err();
+        assert!(err.contains("unsupported policy pack"), "err was: {err}");
+    }
+
+    #[test]
+    fn pack_key_stable_and_distinct() {
+        assert_eq!(pack_key("a#v1"), pack_key("a#v1"));
+        assert_ne!(pack_key("a#v1"), pack_key("a#v2"));
+        assert_eq!(pack_key("a").len(), 16);
+    }
+
+    #[test]
+    fn load_packs_empty_is_noop() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load_packs(&[], dir.path()).unwrap().is_empty());
+        assert!(!dir.path().join(PACKS_DIR).exists());
+    }
+
+    #[test]
+    fn load_packs_git_pins_commit() {
+        let pack = tempfile::tempdir().unwrap();
+        let repo = pack.path().join("policies.git");
+        fs::create_dir_all(&repo).unwrap();
+        fs::write(
+            repo.join("org.yaml"),
+            "invariants:\n  - name: no-pii\n    instruction: Never log PII.\n",
+        )
+        .unwrap();
+        let git_in = |args: &[&str]| git(args, Some(&repo)).unwrap();
+        git_in(&["init", "--quiet"]);
+        git_in(&["add", "."]);
+        git_in(&[
+            "-c",
+            "user.name=wk",
+            "-c",
+            "user.email=wk@example.com",
+            "commit",
+            "--quiet",
+            "-m",
+            "init",
+        ]);
+        let head = git_in(&["rev-parse", "HEAD"]);
+
+        let root = tempfile::tempdir().unwrap();
+        let source = format!("file://{}", repo.display());
+        let markers = load_packs(std::slice::from_ref(&source), root.path()).unwrap();
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].name, "no-pii");
+        assert_eq!(markers[0].rel_path, format!("{source}/org.yaml"));
+        assert_eq!(load_lock(root.path()).get(&source), Some(&head));
+    }
+}
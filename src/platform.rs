@@ -0,0 +1,106 @@
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// Extensions tried, in order, when looking a program up on Windows. npm
+/// installs `claude` as a `claude.cmd` shim, which a bare name does not find.
+const WINDOWS_EXTENSIONS: &[&str] = &[".exe", ".cmd", ".bat"];
+
+/// `path` with `/` separators, the form repo-relative paths take in markers,
+/// caches, reports and prompts whatever the platform.
+pub fn slash_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if cfg!(windows) {
+        path.replace('\\', "/")
+    } else {
+        path.into_owned()
+    }
+}
+
+/// A file entry as written in a marker, with `\` separators (Windows authors)
+/// read as `/`. Elsewhere `\` is a legal file name character and escapes
+/// glob patterns, so it is kept.
+pub fn slash_entry(entry: &str) -> String {
+    if cfg!(windows) {
+        entry.replace('\\', "/")
+    } else {
+        entry.to_string()
+    }
+}
+
+/// `text` with CRLF line endings turned into LF, so diffs of a checkout with
+/// `core.autocrlf` hash and slice like any other.
+pub fn lf(text: String) -> String {
+    if text.contains("\r\n") {
+        text.replace("\r\n", "\n")
+    } else {
+        text
+    }
+}
+
+/// This machine's name: `COMPUTERNAME` on Windows, else what `hostname`
+/// prints.
+pub fn hostname() -> Option<String> {
+    if cfg!(windows) {
+        return env::var("COMPUTERNAME").ok().filter(|h| !h.is_empty());
+    }
+    let output = std::process::Command::new("hostname").output().ok()?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !name.is_empty()).then_some(name)
+}
+
+/// The program to spawn for `name`: on Windows, the first `.exe`, `.cmd` or
+/// `.bat` with that name on PATH; elsewhere (or if none is found) `name`
+/// itself, for the usual PATH lookup.
+pub fn program(name: &str) -> PathBuf {
+    if cfg!(windows)
+        && let Some(path) = env::var_os("PATH")
+        && let Some(found) = find_in_path(name, &path, WINDOWS_EXTENSIONS)
+    {
+        return found;
+    }
+    PathBuf::from(name)
+}
+
+/// The first `<dir>/<name><ext>` that is a file, trying every extension in
+/// each PATH directory before the next.
+fn find_in_path(name: &str, path: &OsStr, extensions: &[&str]) -> Option<PathBuf> {
+    env::split_paths(path).find_map(|dir| {
+        extensions
+            .iter()
+            .map(|ext| dir.join(format!("{name}{ext}")))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn finds_shims_in_path_order() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        fs::write(first.path().join("claude.cmd"), "").unwrap();
+        fs::write(second.path().join("claude.exe"), "").unwrap();
+        let path = env::join_paths([first.path(), second.path()]).unwrap();
+
+        let found = find_in_path("claude", &path, WINDOWS_EXTENSIONS).unwrap();
+        assert_eq!(found, first.path().join("claude.cmd"));
+        assert_eq!(find_in_path("git", &path, WINDOWS_EXTENSIONS), None);
+    }
+
+    #[test]
+    fn crlf_becomes_lf() {
+        let diff = "diff --git a/a.ts b/a.ts\r\n+++ b/a.ts\r\n+x\r\n".to_string();
+        assert_eq!(lf(diff), "diff --git a/a.ts b/a.ts\n+++ b/a.ts\n+x\n");
+        assert_eq!(lf("a\nb\n".to_string()), "a\nb\n");
+    }
+
+    #[test]
+    fn paths_use_forward_slashes() {
+        let path = Path::new("src").join("api").join("handler.ts");
+        assert_eq!(slash_path(&path), "src/api/handler.ts");
+    }
+}
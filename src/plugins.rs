@@ -0,0 +1,324 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use serde::Serialize;
+
+use crate::children;
+use crate::marker::Marker;
+
+/// Executables named `wk-check-<name>` on PATH are discovered as checker `<name>`.
+pub const PLUGIN_PREFIX: &str = "wk-check-";
+
+/// Version of the JSON request sent to checkers. Bump on breaking changes.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Checker name → executable.
+pub type Checkers = BTreeMap<String, PathBuf>;
+
+#[derive(Serialize)]
+struct RequestMarker<'a> {
+    name: &'a str,
+    file: &'a str,
+    line: usize,
+    instruction: &'a str,
+    files: &'a [String],
+    options: BTreeMap<&'a String, &'a String>,
+}
+
+/// The JSON document a checker receives on stdin.
+#[derive(Serialize)]
+struct CheckRequest<'a> {
+    version: u32,
+    marker: RequestMarker<'a>,
+    diff: Option<&'a str>,
+    /// Contents of the marker's watched files (missing files are omitted).
+    files: BTreeMap<&'a str, String>,
+}
+
+/// Resolve the checkers `markers` ask for: those declared in config (which
+/// win), else `wk-check-<name>` on PATH. Only the names asked for are looked
+/// up, so PATH directories are never listed. Relative config paths are
+/// resolved against the scan root.
+pub fn discover(declared: &HashMap<String, String>, root: &Path, markers: &[Marker]) -> Checkers {
+    let mut checkers = Checkers::new();
+    for name in markers.iter().filter_map(checker_for) {
+        if checkers.contains_key(name) {
+            continue;
+        }
+        let exe = match declared.get(name) {
+            Some(exe) => Some(root.join(exe)),
+            None => find_on_path(name),
+        };
+        if let Some(exe) = exe {
+            checkers.insert(name.to_string(), exe);
+        }
+    }
+    checkers
+}
+
+/// The first `wk-check-<name>` on PATH that can be run. Earlier PATH entries
+/// win, as they would for the shell.
+fn find_on_path(name: &str) -> Option<PathBuf> {
+    // A name is not a path: `checker="../x"` must not reach outside PATH.
+    if name.is_empty() || name.contains(['/', '\\']) {
+        return None;
+    }
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path).find_map(|dir| {
+        ["", ".exe", ".cmd", ".bat", ".wasm"]
+            .iter()
+            .map(|ext| dir.join(format!("{PLUGIN_PREFIX}{name}{ext}")))
+            .find(|exe| is_runnable(exe))
+    })
+}
+
+/// A regular file that is executable, or a WASM module (run in-process).
+fn is_runnable(exe: &Path) -> bool {
+    let Ok(meta) = fs::metadata(exe) else {
+        return false;
+    };
+    if !meta.is_file() {
+        return false;
+    }
+    if exe.extension().is_some_and(|ext| ext == "wasm") {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        meta.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    true
+}
+
+/// Name of the checker a marker asks for, if any.
+pub fn checker_for(marker: &Marker) -> Option<&str> {
+    marker.options.get("checker").map(String::as_str)
+}
+
+pub fn build_request(marker: &Marker, diff: Option<&str>, root: &Path) -> String {
+    let watched = marker.watched_files(root);
+    let files = watched
+        .iter()
+        .filter_map(|f| {
+            fs::read_to_string(root.join(f))
+                .ok()
+                .map(|c| (f.as_str(), c))
+        })
+        .collect();
+    let request = CheckRequest {
+        version: PROTOCOL_VERSION,
+        marker: RequestMarker {
+            name: &marker.name,
+            file: &marker.rel_path,
+            line: marker.line,
+            instruction: &marker.instruction,
+            files: &marker.files,
+            options: marker.options.iter().collect(),
+        },
+        diff,
+        files,
+    };
+    serde_json::to_string(&request).unwrap()
+}
+
+/// Run a checker executable with `request` on stdin, in `root`, and return its
+/// stdout. A non-zero exit is an error. `*.wasm` checkers run in the WASM sandbox.
+pub fn run_checker(exe: &Path, request: &str, root: &Path) -> Result<String, String> {
+    if exe.extension().is_some_and(|ext| ext == "wasm") {
+        return run_wasm_checker(exe, request);
+    }
+    let (mut child, _running) = children::spawn(
+        process::Command::new(exe)
+            .current_dir(root)
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped()),
+    )
+    .map_err(|e| format!("failed to launch checker `{}`: {e}", exe.display()))?;
+    // A checker may exit without reading its request; its exit status says
+    // what happened.
+    if let Err(e) = child.stdin.take().unwrap().write_all(request.as_bytes())
+        && e.kind() != io::ErrorKind::BrokenPipe
+    {
+        return Err(format!(
+            "failed to send the request to checker `{}`: {e}",
+            exe.display()
+        ));
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to wait on checker `{}`: {e}", exe.display()))?;
+    if !output.status.success() {
+        return Err(format!(
+            "checker `{}` exited with {}: {}",
+            exe.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(feature = "wasm")]
+fn run_wasm_checker(module: &Path, request: &str) -> Result<String, String> {
+    crate::wasm::run_module(module, request)
+}
+
+#[cfg(not(feature = "wasm"))]
+fn run_wasm_checker(module: &Path, _request: &str) -> Result<String, String> {
+    Err(format!(
+        "checker `{}` is a WASM module but watcher-knight was built without the `wasm` feature",
+        module.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_marker(options: &[(&str, &str)]) -> Marker {
+        Marker {
+            name: "w".to_string(),
+            rel_path: "src/app.ts".to_string(),
+            line: 3,
+            instruction: "Check it".to_string(),
+            files: vec!["a.ts".to_string(), "missing.ts".to_string()],
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: options
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn checker_for_reads_option() {
+        assert_eq!(
+            checker_for(&make_marker(&[("checker", "schema")])),
+            Some("schema")
+        );
+        assert_eq!(checker_for(&make_marker(&[])), None);
+    }
+
+    #[test]
+    fn build_request_shape() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.ts"), "export const a = 1;").unwrap();
+        let m = make_marker(&[("checker", "schema")]);
+        let req = build_request(&m, Some("+ line"), dir.path());
+        let val: serde_json::Value = serde_json::from_str(&req).unwrap();
+        assert_eq!(val["version"], PROTOCOL_VERSION);
+        assert_eq!(val["marker"]["name"], "w");
+        assert_eq!(val["marker"]["line"], 3);
+        assert_eq!(val["marker"]["options"]["checker"], "schema");
+        assert_eq!(val["diff"], "+ line");
+        assert_eq!(val["files"]["a.ts"], "export const a = 1;");
+        assert!(val["files"].get("missing.ts").is_none());
+    }
+
+    #[test]
+    fn discover_uses_declared_checkers() {
+        let root = Path::new("/repo");
+        let declared = HashMap::from([
+            ("schema".to_string(), "scripts/check.sh".to_string()),
+            ("unused".to_string(), "scripts/unused.sh".to_string()),
+        ]);
+        let markers = [make_marker(&[("checker", "schema")]), make_marker(&[])];
+        let checkers = discover(&declared, root, &markers);
+        assert_eq!(
+            checkers,
+            Checkers::from([(
+                "schema".to_string(),
+                PathBuf::from("/repo/scripts/check.sh")
+            )])
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn is_runnable_needs_the_exec_bit() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("wk-check-schema");
+        fs::write(&exe, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&exe, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(!is_runnable(&exe));
+        fs::set_permissions(&exe, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(is_runnable(&exe));
+
+        let module = dir.path().join("wk-check-schema.wasm");
+        fs::write(&module, "").unwrap();
+        assert!(is_runnable(&module));
+        assert!(!is_runnable(dir.path()));
+    }
+
+    #[test]
+    fn find_on_path_rejects_paths() {
+        assert_eq!(find_on_path("../../bin/sh"), None);
+        assert_eq!(find_on_path(""), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_checker_roundtrip() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("wk-check-echo");
+        fs::write(
+            &exe,
+            "#!/bin/sh\ncat > /dev/null\necho '{\"is_valid\": false, \"reason\": \"nope\"}'\n",
+        )
+        .unwrap();
+        fs::set_permissions(&exe, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let out = run_checker(&exe, "{}", dir.path()).unwrap();
+        assert_eq!(out, r#"{"is_valid": false, "reason": "nope"}"#);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_checker_nonzero_exit_is_error() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("wk-check-fail");
+        fs::write(&exe, "#!/bin/sh\necho boom >&2\nexit 3\n").unwrap();
+        fs::set_permissions(&exe, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let err = run_checker(&exe, "{}", dir.path()).unwrap_err();
+        assert!(err.contains("boom"), "err was: {err}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_checker_may_ignore_its_request() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("wk-check-deaf");
+        fs::write(&exe, "#!/bin/sh\necho '{\"is_valid\": true}'\n").unwrap();
+        fs::set_permissions(&exe, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // Bigger than a pipe buffer, so the write hits the closed pipe.
+        let request = "x".repeat(1 << 20);
+        let out = run_checker(&exe, &request, dir.path()).unwrap();
+        assert_eq!(out, r#"{"is_valid": true}"#);
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[test]
+    fn run_checker_wasm_without_feature_is_error() {
+        let err = run_checker(Path::new("schema.wasm"), "{}", Path::new(".")).unwrap_err();
+        assert!(err.contains("`wasm` feature"), "err was: {err}");
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::marker::{self, Marker, Metadata};
+
+/// An organization-wide invariant declared outside the repository.
+#[derive(Debug, Deserialize)]
+struct PolicyInvariant {
+    name: String,
+    instruction: String,
+    /// Files to watch, relative to the scan root. Glob patterns are supported.
+    #[serde(default)]
+    files: Vec<String>,
+    /// Reference document URLs included in the prompt.
+    #[serde(default)]
+    context: Vec<String>,
+    description: Option<String>,
+    rationale: Option<String>,
+    link: Option<String>,
+    #[serde(default)]
+    options: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PolicyFile {
+    #[serde(default)]
+    invariants: Vec<PolicyInvariant>,
+}
+
+/// Load a policy YAML file and turn each invariant into a `Marker`.
+///
+/// Policy markers report their location as `<policy path>:<n>`, where `n` is the
+/// 1-based position of the invariant in the file.
+pub fn load_policy(path: &Path, repo_root: &Path) -> Result<Vec<Marker>, String> {
+    let data = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read policy `{}`: {e}", path.display()))?;
+    parse_policy(&data, &path.to_string_lossy(), repo_root)
+}
+
+/// Parse policy YAML text; `source` labels the resulting markers' locations.
+pub fn parse_policy(data: &str, source: &str, repo_root: &Path) -> Result<Vec<Marker>, String> {
+    let policy: PolicyFile =
+        serde_yaml::from_str(data).map_err(|e| format!("invalid policy `{source}`: {e}"))?;
+
+    let mut markers = Vec::new();
+    for (i, inv) in policy.invariants.into_iter().enumerate() {
+        let index = i + 1;
+        let valid_name = !inv.name.is_empty()
+            && inv
+                .name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            return Err(format!(
+                "{source}: invariant #{index} has an invalid name `{}` (names may contain \
+                 alphanumeric characters, hyphens, and underscores)",
+                inv.name
+            ));
+        }
+        let instruction = inv.instruction.trim().to_string();
+        if instruction.is_empty() {
+            return Err(format!(
+                "{source}: invariant `{}` has no instruction text",
+                inv.name
+            ));
+        }
+
+        let raw: Vec<&str> = inv.files.iter().map(String::as_str).collect();
+        let scope = marker::resolve_raw_files(&raw, Path::new(""), repo_root);
+        markers.push(Marker {
+            name: inv.name,
+            rel_path: source.to_string(),
+            line: index,
+            instruction,
+            files: scope.files,
+            exclude: scope.exclude,
+            context: inv.context,
+            metadata: Metadata {
+                description: inv.description,
+                rationale: inv.rationale,
+                link: inv.link,
+            },
+            options: inv.options,
+        });
+    }
+    Ok(markers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(data: &str) -> Result<Vec<Marker>, String> {
+        parse_policy(data, "policies.yaml", Path::new("/repo"))
+    }
+
+    #[test]
+    fn parse_policy_basic() {
+        let markers = parse(
+            "\
+invariants:
+  - name: no-pii-logging
+    instruction: No service may log PII.
+    files: [services/api.py]
+    context: [https://wiki.example.com/logging.md]
+    link: https://wiki.example.com/adr/7
+    options:
+      model: haiku
+",
+        )
+        .unwrap();
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].name, "no-pii-logging");
+        assert_eq!(markers[0].instruction, "No service may log PII.");
+        assert_eq!(markers[0].files, vec!["services/api.py"]);
+        assert_eq!(
+            markers[0].context,
+            vec!["https://wiki.example.com/logging.md"]
+        );
+        assert_eq!(markers[0].options.get("model").unwrap(), "haiku");
+        assert_eq!(markers[0].rel_path, "policies.yaml");
+        assert_eq!(markers[0].line, 1);
+    }
+
+    #[test]
+    fn parse_policy_line_is_position() {
+        let markers = parse(
+            "\
+invariants:
+  - name: a
+    instruction: First.
+  - name: b
+    instruction: Second.
+",
+        )
+        .unwrap();
+        assert_eq!(markers[1].name, "b");
+        assert_eq!(markers[1].line, 2);
+        assert!(markers[1].files.is_empty());
+    }
+
+    #[test]
+    fn parse_policy_empty_document() {
+        assert!(parse("invariants: []").unwrap().is_empty());
+        assert!(parse("{}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_policy_invalid_name() {
+        let err = parse("invariants:\n  - name: bad name\n    instruction: x\n").unwrap_err();
+        assert!(err.contains("invalid name"), "err was: {err}");
+    }
+
+    #[test]
+    fn parse_policy_empty_instruction() {
+        let err = parse("invariants:\n  - name: a\n    instruction: '  '\n").unwrap_err();
+        assert!(err.contains("no instruction"), "err was: {err}");
+    }
+
+    #[test]
+    fn parse_policy_malformed_yaml() {
+        let err = parse("invariants: [").unwrap_err();
+        assert!(err.contains("invalid policy"), "err was: {err}");
+    }
+}
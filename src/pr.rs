@@ -0,0 +1,418 @@
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+use std::{env, fs, process};
+
+use serde::Deserialize;
+
+use crate::cli::RunArgs;
+use crate::http::Request;
+use crate::tickets::GITHUB_TOKEN_VAR;
+use crate::{acks, config, exit_code, ratchet, remote_cache, waivers};
+
+/// Overrides the API base, as GitHub Actions sets it on Enterprise Server.
+pub const API_URL_VAR: &str = "GITHUB_API_URL";
+
+/// Set for the run `run --pr` starts on the clone, which must not trust it.
+pub const UNTRUSTED_VAR: &str = "WK_UNTRUSTED_PR";
+
+/// Files that decide what a run executes or forgives. The pull request may
+/// change them, so the run uses the merge base's.
+const TRUSTED_FILES: &[&str] = &[
+    config::CONFIG_FILE,
+    waivers::WAIVERS_FILE,
+    acks::ACKS_FILE,
+    ratchet::RATCHET_FILE,
+];
+
+/// Local state (caches, history), which a pull request could seed.
+const STATE_DIR: &str = ".watcher_knight";
+
+/// Length of the commit hashes printed for the checkout.
+const SHORT_SHA: usize = 12;
+
+/// A pull request named by its web URL.
+#[derive(Debug, PartialEq)]
+pub struct PrUrl {
+    pub host: String,
+    pub owner: String,
+    pub repo: String,
+    pub number: u64,
+}
+
+/// Parse `https://<host>/<owner>/<repo>/pull/<number>`, ignoring anything
+/// after the number (`/files`, `#discussion…`).
+pub fn parse_url(url: &str) -> Result<PrUrl, String> {
+    let invalid =
+        || format!("`{url}` is not a pull request URL (https://github.com/OWNER/REPO/pull/N)");
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .ok_or_else(invalid)?;
+    let mut parts = rest.split(['/', '#', '?']);
+    let (Some(host), Some(owner), Some(repo), Some("pull"), Some(number)) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return Err(invalid());
+    };
+    if host.is_empty() || owner.is_empty() || repo.is_empty() {
+        return Err(invalid());
+    }
+    Ok(PrUrl {
+        host: host.to_string(),
+        owner: owner.to_string(),
+        repo: repo.trim_end_matches(".git").to_string(),
+        number: number.parse().map_err(|_| invalid())?,
+    })
+}
+
+impl PrUrl {
+    /// The REST endpoint describing this pull request. `api` overrides the
+    /// host's default, `https://api.github.com` or `https://<host>/api/v3`.
+    pub fn api_url(&self, api: Option<&str>) -> String {
+        let base = match api {
+            Some(api) => api.trim_end_matches('/').to_string(),
+            None if self.host == "github.com" => "https://api.github.com".to_string(),
+            None => format!("https://{}/api/v3", self.host),
+        };
+        format!(
+            "{base}/repos/{}/{}/pulls/{}",
+            self.owner, self.repo, self.number
+        )
+    }
+}
+
+/// The parts of the API's pull request object a checkout needs. The head
+/// comes from the base repository, since a fork may be gone.
+#[derive(Debug, Deserialize)]
+pub struct PullRequest {
+    pub title: String,
+    pub base: Base,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Base {
+    pub sha: String,
+    #[serde(rename = "ref")]
+    pub branch: String,
+    pub repo: Repo,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Repo {
+    pub clone_url: String,
+}
+
+/// Fetch the pull request from the API, authenticating with `token` if set.
+pub fn fetch(url: &str, token: Option<&str>) -> Result<PullRequest, String> {
+    let mut request = Request::get(url)
+        .header("Accept: application/vnd.github+json")
+        .follow_redirects();
+    if let Some(token) = token {
+        request = request.bearer(token);
+    }
+    let response = request
+        .send()
+        .map_err(|e| format!("failed to fetch `{url}`: {e}"))?
+        .success(&format!("fetching `{url}`"))?;
+    serde_json::from_slice(&response.body)
+        .map_err(|e| format!("unexpected pull request from `{url}`: {e}"))
+}
+
+/// A throwaway clone of the pull request's head, removed when dropped.
+pub struct Checkout {
+    pub dir: PathBuf,
+    pub head: String,
+    /// Where the head forked from the base branch: the ref diff mode runs
+    /// against.
+    pub merge_base: String,
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| format!("failed to run git: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Clone the base repository and check out the pull request's head, which
+/// the base repository serves as `pull/<number>/head` even for forks.
+pub fn checkout(pr: &PullRequest, number: u64) -> Result<Checkout, String> {
+    // A fresh directory with an unpredictable name, so that nobody else on
+    // the machine can plant files in it beforehand.
+    let dir = tempfile::Builder::new()
+        .prefix(&format!("watcher-knight-pr-{number}-"))
+        .tempdir()
+        .map_err(|e| format!("cannot create a directory for the clone: {e}"))?
+        .keep();
+    // Owned from here on, so a failed step still removes the directory.
+    let mut checkout = Checkout {
+        dir,
+        head: String::new(),
+        merge_base: String::new(),
+    };
+    let dir = checkout.dir.clone();
+    git(
+        &dir,
+        &[
+            "clone",
+            "--quiet",
+            "--no-checkout",
+            &pr.base.repo.clone_url,
+            ".",
+        ],
+    )?;
+    git(
+        &dir,
+        &["fetch", "--quiet", "origin", &format!("pull/{number}/head")],
+    )?;
+    git(&dir, &["checkout", "--quiet", "--detach", "FETCH_HEAD"])?;
+    checkout.head = git(&dir, &["rev-parse", "HEAD"])?;
+    checkout.merge_base = git(&dir, &["merge-base", "HEAD", &pr.base.sha])?;
+    Ok(checkout)
+}
+
+/// Put back the merge base's version of each of [`TRUSTED_FILES`] under
+/// `root`, the directory of the clone the run scans (removing those the
+/// merge base lacks), and remove any committed local state there.
+pub fn pin_trusted_files(checkout: &Checkout, root: &Path) -> Result<(), String> {
+    let dir = &checkout.dir;
+    for file in TRUSTED_FILES {
+        let path = root.join(file);
+        let path = path.to_string_lossy();
+        let at_base = format!("{}:./{path}", checkout.merge_base);
+        if git(dir, &["cat-file", "-e", &at_base]).is_ok() {
+            git(
+                dir,
+                &["checkout", "--quiet", &checkout.merge_base, "--", &path],
+            )?;
+            continue;
+        }
+        match fs::remove_file(dir.join(&*path)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("cannot remove `{path}`: {e}"));
+            }
+            _ => {}
+        }
+    }
+    match fs::remove_dir_all(dir.join(root).join(STATE_DIR)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("cannot remove `{STATE_DIR}`: {e}"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether this run validates an untrusted pull request for `run --pr`.
+pub fn untrusted() -> bool {
+    env::var(UNTRUSTED_VAR).is_ok_and(|v| v == "1")
+}
+
+/// The directory of the pull request's repository that `args` scan: its
+/// root, as a path relative to the top of the repository.
+fn scanned_dir(args: &RunArgs) -> Result<PathBuf, String> {
+    let root = args.root.clone().unwrap_or_else(|| PathBuf::from("."));
+    if root.is_absolute() || root.components().any(|c| c == Component::ParentDir) {
+        return Err(format!(
+            "with --pr, the root is a directory of the pull request's repository \
+             (such as `.` or `web`), not `{}`",
+            root.display()
+        ));
+    }
+    Ok(root)
+}
+
+/// The arguments of the run on the clone: `args` without `--pr`, scanning
+/// `root` in the clone, in diff mode against `merge_base` unless they name
+/// another ref.
+fn child_args(args: &RunArgs, root: PathBuf, merge_base: &str) -> Vec<OsString> {
+    let mut child = args.clone();
+    child.pr = None;
+    child.root = Some(root);
+    if child.stack_base.is_none() && child.diff.as_deref().is_none_or(str::is_empty) {
+        child.diff = Some(merge_base.to_string());
+    }
+    child.to_args()
+}
+
+fn short(sha: &str) -> &str {
+    &sha[..sha.len().min(SHORT_SHA)]
+}
+
+/// `run --pr`: validate a pull request in a temporary clone, leaving the
+/// current checkout alone. The run itself is this binary again with the
+/// other `args`, by default in diff mode against the merge base, so that the
+/// clone is removed whatever it exits with. The pull request is untrusted: the run uses the merge base's
+/// config, runs no command the config or the markers name, gives agents
+/// read-only tools and never writes to the shared cache.
+pub fn run(url: &str, args: &RunArgs) -> ! {
+    let (pr_url, root) = parse_url(url)
+        .and_then(|pr_url| Ok((pr_url, scanned_dir(args)?)))
+        .unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            process::exit(exit_code::CONFIG_ERROR);
+        });
+    let token = env::var(GITHUB_TOKEN_VAR).ok().filter(|t| !t.is_empty());
+    let api = env::var(API_URL_VAR).ok().filter(|a| !a.is_empty());
+    let pr = fetch(&pr_url.api_url(api.as_deref()), token.as_deref()).unwrap_or_else(|e| fail(e));
+    let checkout = checkout(&pr, pr_url.number).unwrap_or_else(|e| fail(e));
+    if let Err(e) = pin_trusted_files(&checkout, &root) {
+        drop(checkout);
+        fail(e);
+    }
+    eprintln!(
+        "Checking {}/{}#{} \"{}\" at {} against {} ({})",
+        pr_url.owner,
+        pr_url.repo,
+        pr_url.number,
+        pr.title,
+        short(&checkout.head),
+        pr.base.branch,
+        short(&checkout.merge_base)
+    );
+    let status = env::current_exe()
+        .map_err(|e| format!("cannot find own binary: {e}"))
+        .and_then(|exe| {
+            process::Command::new(exe)
+                .args(child_args(
+                    args,
+                    checkout.dir.join(&root),
+                    &checkout.merge_base,
+                ))
+                .env(UNTRUSTED_VAR, "1")
+                .env(remote_cache::READONLY_VAR, "1")
+                .status()
+                .map_err(|e| format!("failed to run the check: {e}"))
+        });
+    // `process::exit` skips destructors.
+    drop(checkout);
+    match status {
+        Ok(status) => process::exit(status.code().unwrap_or(exit_code::BACKEND_ERROR)),
+        Err(e) => fail(e),
+    }
+}
+
+fn fail(e: String) -> ! {
+    eprintln!("Error: {e}");
+    process::exit(exit_code::BACKEND_ERROR);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    #[test]
+    fn parse_url_reads_github_and_enterprise_urls() {
+        let pr = parse_url("https://github.com/org/repo/pull/123/files").unwrap();
+        assert_eq!(
+            pr,
+            PrUrl {
+                host: "github.com".to_string(),
+                owner: "org".to_string(),
+                repo: "repo".to_string(),
+                number: 123,
+            }
+        );
+        assert_eq!(
+            pr.api_url(None),
+            "https://api.github.com/repos/org/repo/pulls/123"
+        );
+        let pr = parse_url("https://git.corp.example/a/b/pull/7#discussion_r1").unwrap();
+        assert_eq!(
+            pr.api_url(None),
+            "https://git.corp.example/api/v3/repos/a/b/pulls/7"
+        );
+        assert_eq!(
+            pr.api_url(Some("http://localhost:8080/")),
+            "http://localhost:8080/repos/a/b/pulls/7"
+        );
+        for bad in [
+            "github.com/org/repo/pull/1",
+            "https://github.com/org/repo/issues/1",
+            "https://github.com/org/repo/pull/x",
+            "https://github.com/org/repo",
+        ] {
+            assert!(parse_url(bad).is_err(), "{bad} parsed");
+        }
+    }
+
+    fn run_args(args: &[&str]) -> RunArgs {
+        let cli = Cli::try_parse_from(["watcher-knight"].iter().chain(args)).unwrap();
+        let Command::Run(args) = cli.command else {
+            panic!("not a run");
+        };
+        *args
+    }
+
+    #[test]
+    fn child_args_scan_the_clone_against_the_merge_base() {
+        let args = run_args(&["run", "web", "--pr", "u", "--strict", "--only=a", "--diff"]);
+        let root = Path::new("/tmp/clone").join(scanned_dir(&args).unwrap());
+        let child = child_args(&args, root, "mb");
+        assert_eq!(
+            child,
+            [
+                "run",
+                "--model",
+                "sonnet",
+                "--diff=mb",
+                "--strict",
+                "--fail-on",
+                "any",
+                "--format",
+                "text",
+                "--inconclusive",
+                "warn",
+                "--only",
+                "a",
+                "--",
+                "/tmp/clone/web",
+            ]
+            .map(OsString::from)
+        );
+        // The child parses them back to the same run.
+        let reparsed = run_args(
+            &child
+                .iter()
+                .map(|a| a.to_str().unwrap())
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(reparsed.to_args(), child);
+
+        let args = run_args(&["run", "--pr", "u", "--diff", "origin/release"]);
+        let child = child_args(&args, PathBuf::from("/tmp/clone"), "mb");
+        assert!(child.contains(&OsString::from("--diff=origin/release")));
+    }
+
+    #[test]
+    fn scanned_dir_stays_in_the_clone() {
+        assert_eq!(
+            scanned_dir(&run_args(&["run", "--pr", "u"])).unwrap(),
+            PathBuf::from(".")
+        );
+        for root in ["/etc", "../other", "web/../../x"] {
+            assert!(scanned_dir(&run_args(&["run", root, "--pr", "u"])).is_err());
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::env;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+use crate::packs;
+
+/// `[privacy]` in `watcher-knight.toml`. The section turns scrubbing on.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacyConfig {
+    /// Regexes for internal identifiers, such as hostnames or account IDs,
+    /// replaced with `<id:...>`.
+    pub identifiers: Vec<String>,
+}
+
+/// Absolute paths into a machine's filesystem: under the usual top-level
+/// directories on Unix, or behind a drive letter on Windows. The first group
+/// is the character before the path, which is kept. Other rooted strings,
+/// like `/api/users` routes, are left alone.
+static ABSOLUTE_PATH: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(^|[\s"'`(=,\[])((?:/(?:home|Users|root|tmp|var|opt|srv|mnt|media|Volumes|private|usr|etc|nix|builds|runner|workspaces?|data)(?:/[^\s"'`),:;\]]+)+)|[A-Za-z]:\\[^\s"'`),;\]]+)"#,
+    )
+    .unwrap()
+});
+
+/// Replaces what identifies a machine, a person or an internal system with
+/// placeholders. A placeholder holds a hash of what it replaces, so the same
+/// value gets the same placeholder in every prompt and run.
+pub struct Scrubber {
+    root: String,
+    identifiers: Vec<Regex>,
+    users: Vec<Regex>,
+}
+
+fn placeholder(kind: &str, value: &str) -> String {
+    format!("<{kind}:{}>", &packs::sha256_hex(value.as_bytes())[..8])
+}
+
+impl Scrubber {
+    /// `root` becomes `<repo>`; `users` are login names, matched as whole
+    /// words in any case.
+    pub fn new(config: &PrivacyConfig, root: &Path, users: &[String]) -> Result<Self, String> {
+        let identifiers = config
+            .identifiers
+            .iter()
+            .map(|p| Regex::new(p).map_err(|e| format!("invalid privacy identifier `{p}`: {e}")))
+            .collect::<Result<_, _>>()?;
+        let users = users
+            .iter()
+            .map(|u| Regex::new(&format!(r"(?i)\b{}\b", regex::escape(u))).unwrap())
+            .collect();
+        Ok(Scrubber {
+            root: root.to_string_lossy().into_owned(),
+            identifiers,
+            users,
+        })
+    }
+
+    pub fn scrub(&self, text: &str) -> String {
+        let mut out = if self.root.len() > 1 {
+            text.replace(&self.root, "<repo>")
+        } else {
+            text.to_string()
+        };
+        for re in &self.identifiers {
+            out = re
+                .replace_all(&out, |c: &Captures| placeholder("id", &c[0]))
+                .into_owned();
+        }
+        out = ABSOLUTE_PATH
+            .replace_all(&out, |c: &Captures| {
+                format!("{}{}", &c[1], placeholder("path", &c[2]))
+            })
+            .into_owned();
+        for re in &self.users {
+            out = re
+                .replace_all(&out, |c: &Captures| {
+                    placeholder("user", &c[0].to_lowercase())
+                })
+                .into_owned();
+        }
+        out
+    }
+}
+
+/// The login name of whoever runs watcher-knight. `root` is left out: it is
+/// a common word, and its home directory is scrubbed as a path.
+fn current_users() -> Vec<String> {
+    let mut users: Vec<String> = Vec::new();
+    for var in ["USER", "USERNAME", "LOGNAME"] {
+        if let Ok(user) = env::var(var)
+            && user.len() > 1
+            && user != "root"
+            && !users.contains(&user)
+        {
+            users.push(user);
+        }
+    }
+    users
+}
+
+/// `None` unless `[privacy]` enabled it.
+static SCRUBBER: Mutex<Option<Scrubber>> = Mutex::new(None);
+
+/// Scrub every prompt sent from now on.
+pub fn enable(config: &PrivacyConfig, root: &Path) -> Result<(), String> {
+    *SCRUBBER.lock().unwrap() = Some(Scrubber::new(config, root, &current_users())?);
+    Ok(())
+}
+
+/// `text` scrubbed for a prompt, or unchanged when privacy mode is off.
+pub fn scrub(text: &str) -> String {
+    match SCRUBBER.lock().unwrap().as_ref() {
+        Some(scrubber) => scrubber.scrub(text),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrubber() -> Scrubber {
+        let config = PrivacyConfig {
+            identifiers: vec![r"[a-z0-9-]+\.corp\.example\.com".to_string()],
+        };
+        Scrubber::new(
+            &config,
+            Path::new("/home/alice/src/shop"),
+            &["alice".to_string()],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn scrub_replaces_paths_users_and_identifiers() {
+        let out = scrubber().scrub(
+            "Read /home/alice/src/shop/src/app.ts, then /var/lib/app/db.sqlite \
+             on build01.corp.example.com. Ask Alice.",
+        );
+        assert_eq!(
+            out,
+            format!(
+                "Read <repo>/src/app.ts, then {} on {}. Ask {}.",
+                placeholder("path", "/var/lib/app/db.sqlite"),
+                placeholder("id", "build01.corp.example.com"),
+                placeholder("user", "alice"),
+            )
+        );
+    }
+
+    #[test]
+    fn scrub_is_stable_and_keeps_routes() {
+        let s = scrubber();
+        let text = "fetch(\"/api/users\") from C:\\Users\\bob\\app and C:\\Users\\bob\\app";
+        let out = s.scrub(text);
+        assert!(out.starts_with("fetch(\"/api/users\") from <path:"));
+        assert_eq!(out, s.scrub(text));
+        let placeholders: Vec<&str> = out.matches("<path:").collect();
+        assert_eq!(placeholders.len(), 2);
+        assert!(!out.contains("bob"));
+    }
+
+    #[test]
+    fn invalid_identifier_is_an_error() {
+        let config = PrivacyConfig {
+            identifiers: vec!["(".to_string()],
+        };
+        let err = Scrubber::new(&config, Path::new("/repo"), &[])
+            .err()
+            .unwrap();
+        assert!(err.starts_with("invalid privacy identifier `(`"));
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Files listed under the slowest to scan.
+const SLOWEST_FILES: usize = 10;
+
+/// A stage of `run` timed by `--profile`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Phase {
+    /// Walking the tree for files.
+    Walk,
+    /// Checking files against the index and hashing changed ones.
+    Read,
+    /// Extracting and parsing tags from changed files.
+    Parse,
+    /// Computing the diff and the changed files (diff mode).
+    Diff,
+    /// Selecting the watchers to validate: `--only`/`--suite`, changed
+    /// files, `when` conditions and cache lookups.
+    Filter,
+    /// Validating the watchers that are left.
+    Validate,
+}
+
+impl Phase {
+    const ALL: [Phase; 6] = [
+        Phase::Walk,
+        Phase::Read,
+        Phase::Parse,
+        Phase::Diff,
+        Phase::Filter,
+        Phase::Validate,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Phase::Walk => "walk",
+            Phase::Read => "read",
+            Phase::Parse => "parse",
+            Phase::Diff => "diff",
+            Phase::Filter => "filter",
+            Phase::Validate => "validate",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Profile {
+    phases: Vec<(Phase, Duration)>,
+    /// Time to scan each file: its index check, read and parse.
+    files: Vec<(String, Duration)>,
+}
+
+impl Profile {
+    fn add(&mut self, phase: Phase, elapsed: Duration) {
+        match self.phases.iter_mut().find(|(p, _)| *p == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => self.phases.push((phase, elapsed)),
+        }
+    }
+
+    fn render(&self) -> String {
+        let ms = |d: Duration| format!("{:.1}ms", d.as_secs_f64() * 1000.0);
+        let mut out = String::new();
+        for phase in Phase::ALL {
+            let elapsed = self
+                .phases
+                .iter()
+                .find(|(p, _)| *p == phase)
+                .map_or(Duration::ZERO, |(_, d)| *d);
+            writeln!(out, "{:<10}{:>12}", phase.label(), ms(elapsed)).unwrap();
+        }
+        let mut files: Vec<&(String, Duration)> = self.files.iter().collect();
+        files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        if !files.is_empty() {
+            writeln!(
+                out,
+                "\nSlowest files to scan ({} scanned):",
+                self.files.len()
+            )
+            .unwrap();
+            for (path, elapsed) in files.into_iter().take(SLOWEST_FILES) {
+                writeln!(out, "{:>10}  {path}", ms(*elapsed)).unwrap();
+            }
+        }
+        out
+    }
+}
+
+/// `None` unless `--profile` enabled it.
+static PROFILE: Mutex<Option<Profile>> = Mutex::new(None);
+
+/// Start collecting timings.
+pub fn enable() {
+    *PROFILE.lock().unwrap() = Some(Profile::default());
+}
+
+/// Add `elapsed` to `phase`.
+pub fn record(phase: Phase, elapsed: Duration) {
+    if let Some(profile) = PROFILE.lock().unwrap().as_mut() {
+        profile.add(phase, elapsed);
+    }
+}
+
+/// Run `f`, adding its duration to `phase`.
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let value = f();
+    record(phase, start.elapsed());
+    value
+}
+
+/// Record the time to scan one file.
+pub fn record_file(rel_path: &str, elapsed: Duration) {
+    if let Some(profile) = PROFILE.lock().unwrap().as_mut() {
+        profile.files.push((rel_path.to_string(), elapsed));
+    }
+}
+
+/// The `==== PROFILE ====` body: time per phase, then the slowest files to
+/// scan. `None` when profiling is off.
+pub fn render() -> Option<String> {
+    PROFILE.lock().unwrap().as_ref().map(Profile::render)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_sums_phases_and_lists_slowest_files() {
+        let mut profile = Profile::default();
+        profile.add(Phase::Walk, Duration::from_millis(2));
+        profile.add(Phase::Walk, Duration::from_millis(3));
+        for i in 0..12 {
+            profile
+                .files
+                .push((format!("f{i:02}.rs"), Duration::from_millis(i)));
+        }
+        let out = profile.render();
+        assert!(out.starts_with("walk             5.0ms\nread             0.0ms\n"));
+        assert!(out.contains("\nSlowest files to scan (12 scanned):\n    11.0ms  f11.rs\n"));
+        assert!(out.ends_with("     2.0ms  f02.rs\n"));
+        assert!(!out.contains("f01.rs"));
+    }
+}
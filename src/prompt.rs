@@ -1,15 +1,118 @@
 use std::fmt::Write as _;
 
+use crate::claude::WatcherResult;
+use crate::context::ContextDoc;
 use crate::marker::Marker;
+use crate::quota;
 
-pub fn build_watcher_prompt(marker: &Marker, diff: Option<&str>) -> String {
+/// Changed symbols listed in a watcher prompt; the rest are counted.
+const MAX_SYMBOLS: usize = 50;
+
+/// Tokens of a model's context left free for the agent's tool results and
+/// its answer.
+const RESPONSE_RESERVE: usize = 16_000;
+
+/// Bytes of the diff shown to the summary agent; the rest is cut.
+const SUMMARY_DIFF_LIMIT: usize = 20_000;
+
+/// How a watcher's agent sees the code, chosen with the `strategy` option.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// Only the diff, without tools: cheap checks of what a change adds.
+    DiffOnly,
+    /// The watched files inlined in the prompt, without tools.
+    FilesInline,
+    /// Read/Grep/Glob over the checkout (the default).
+    AgenticTools,
+    /// The output of a `[commands]` entry, without tools.
+    CommandOutput,
+    /// The dependencies the watched lockfiles add, remove or update (all
+    /// they lock outside diff mode), without tools.
+    Dependencies,
+}
+
+/// The `strategy` of `marker`, `agentic-tools` when it sets none.
+pub fn strategy_for(marker: &Marker) -> Result<Strategy, String> {
+    match marker.options.get("strategy").map(String::as_str) {
+        None | Some("agentic-tools") => Ok(Strategy::AgenticTools),
+        Some("diff-only") => Ok(Strategy::DiffOnly),
+        Some("files-inline") => Ok(Strategy::FilesInline),
+        Some("command-output") => Ok(Strategy::CommandOutput),
+        Some("dependencies") => Ok(Strategy::Dependencies),
+        Some(other) => Err(format!(
+            "unknown strategy `{other}` (expected diff-only, files-inline, agentic-tools, \
+             command-output or dependencies)"
+        )),
+    }
+}
+
+/// What a tool-less strategy shows the agent instead of tools.
+pub enum Evidence<'a> {
+    Tools,
+    DiffOnly,
+    Files(&'a [ContextDoc]),
+    Command {
+        command: &'a str,
+        output: &'a str,
+    },
+    /// The dependency list of `deps::evidence`; `changes` tells whether it
+    /// lists changes or everything locked.
+    Dependencies {
+        list: &'a str,
+        changes: bool,
+    },
+}
+
+/// `evidence` decides how the agent is told to verify the invariant, and
+/// what is inlined for it. `related` are the markers the instruction refers
+/// to as `@name`; they are inlined so both halves of a contract are judged
+/// with the same context.
+pub fn build_watcher_prompt(
+    marker: &Marker,
+    evidence: &Evidence,
+    diff: Option<&str>,
+    context: &[ContextDoc],
+    related: &[&Marker],
+) -> String {
     let mut out = String::new();
 
-    let diff_instruction = if diff.is_some() {
-        "Use the diff to understand what changed, then ALWAYS use Read/Grep/Glob to \
-         verify the invariant against the actual codebase."
+    let diff_instruction = match (evidence, diff.is_some()) {
+        (Evidence::Tools, true) => {
+            "Use the diff to understand what changed, then ALWAYS use Read/Grep/Glob to \
+             verify the invariant against the actual codebase."
+        }
+        (Evidence::Tools, false) => {
+            "ALWAYS use Read/Grep/Glob to verify the invariant against the actual codebase."
+        }
+        (Evidence::DiffOnly, _) => {
+            "You have no tools: decide from the diff below alone. Judge only what the diff \
+             shows, and treat code it does not show as satisfying the invariant."
+        }
+        (Evidence::Files(_), _) => {
+            "You have no tools: the watched files are included below, decide from them \
+             (and the diff, if any) alone."
+        }
+        (Evidence::Command { .. }, _) => {
+            "You have no tools: the output of a command run in the repository is included \
+             below, decide from it (and the diff, if any) alone."
+        }
+        (Evidence::Dependencies { changes: true, .. }, _) => {
+            "You have no tools: the dependencies the change adds, removes or updates, as \
+             read from the lockfiles, are listed below. Decide from them alone, and treat \
+             dependencies that are not listed as satisfying the invariant."
+        }
+        (Evidence::Dependencies { changes: false, .. }, _) => {
+            "You have no tools: every dependency the lockfiles lock is listed below, decide \
+             from them alone."
+        }
+    };
+    // Only an agent with tools can look for what the instruction names.
+    let existence = if matches!(evidence, Evidence::Tools) {
+        " You must confirm that any files or code referenced by the invariant actually \
+         exist. If a file referenced by the invariant does not exist, the invariant is \
+         violated."
     } else {
-        "ALWAYS use Read/Grep/Glob to verify the invariant against the actual codebase."
+        ""
     };
 
     writeln!(
@@ -21,9 +124,7 @@ pub fn build_watcher_prompt(marker: &Marker, diff: Option<&str>) -> String {
          Instruction: {}\n\
          \n\
          Check whether the current state of the code satisfies this invariant.\n\
-         {diff_instruction} You must confirm that any \
-         files or code referenced by the invariant actually exist. If a file referenced \
-         by the invariant does not exist, the invariant is violated.\n\
+         {diff_instruction}{existence}\n\
          \n\
          Respond with ONLY a JSON object, no other text:\n\
          - {{\"is_valid\": true}} if the invariant holds\n\
@@ -37,6 +138,82 @@ pub fn build_watcher_prompt(marker: &Marker, diff: Option<&str>) -> String {
     )
     .unwrap();
 
+    if !context.is_empty() {
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "## Reference documents\n\nThe invariant refers to these documents; treat them \
+             as the specification the code must follow."
+        )
+        .unwrap();
+        for doc in context {
+            writeln!(out).unwrap();
+            writeln!(out, "### {}", doc.source).unwrap();
+            writeln!(out, "```").unwrap();
+            write!(out, "{}", doc.contents).unwrap();
+            if !doc.contents.ends_with('\n') {
+                writeln!(out).unwrap();
+            }
+            writeln!(out, "```").unwrap();
+        }
+    }
+
+    if !related.is_empty() {
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "## Related invariants\n\nThe invariant refers to these invariants with `@name`. \
+             They are validated separately; use them to understand what this invariant must \
+             stay consistent with."
+        )
+        .unwrap();
+        for m in related {
+            writeln!(out).unwrap();
+            writeln!(out, "### {} ({}:{})", m.name, m.rel_path, m.line).unwrap();
+            if !m.files.is_empty() {
+                writeln!(out, "Files: {}", m.files.join(", ")).unwrap();
+            }
+            writeln!(out, "Instruction: {}", m.instruction).unwrap();
+        }
+    }
+
+    match evidence {
+        Evidence::Tools | Evidence::DiffOnly => {}
+        Evidence::Files(files) => {
+            writeln!(out).unwrap();
+            writeln!(out, "## Watched files").unwrap();
+            for file in *files {
+                writeln!(out).unwrap();
+                writeln!(out, "### {}", file.source).unwrap();
+                writeln!(out, "```").unwrap();
+                write!(out, "{}", file.contents).unwrap();
+                if !file.contents.ends_with('\n') {
+                    writeln!(out).unwrap();
+                }
+                writeln!(out, "```").unwrap();
+            }
+        }
+        Evidence::Dependencies { list, changes } => {
+            writeln!(out).unwrap();
+            if *changes {
+                writeln!(out, "## Dependency changes").unwrap();
+            } else {
+                writeln!(out, "## Locked dependencies").unwrap();
+            }
+            write!(out, "{list}").unwrap();
+        }
+        Evidence::Command { command, output } => {
+            writeln!(out).unwrap();
+            writeln!(out, "## Output of `{command}`").unwrap();
+            writeln!(out, "```").unwrap();
+            write!(out, "{output}").unwrap();
+            if !output.ends_with('\n') {
+                writeln!(out).unwrap();
+            }
+            writeln!(out, "```").unwrap();
+        }
+    }
+
     if let Some(diff) = diff {
         writeln!(out).unwrap();
         writeln!(out, "## Diff (HEAD → working tree)").unwrap();
@@ -51,6 +228,331 @@ pub fn build_watcher_prompt(marker: &Marker, diff: Option<&str>) -> String {
     out
 }
 
+/// What `watcher-knight suggest` should propose invariants for.
+pub enum SuggestSource<'a> {
+    Diff(&'a str),
+    File { path: &'a str, contents: &'a str },
+}
+
+pub fn build_suggest_prompt(source: &SuggestSource) -> String {
+    let mut out = String::new();
+
+    let subject = match source {
+        SuggestSource::Diff(_) => "the code changed in the diff below",
+        SuggestSource::File { .. } => "the file below",
+    };
+
+    writeln!(
+        out,
+        "You are helping a developer declare code invariants for watcher-knight.\n\
+         \n\
+         A watcher-knight marker is a comment that names an invariant, optionally lists \
+         the files it watches, and states in plain language a property that must keep \
+         holding. Markers are best for cross-file concerns, architectural constraints and \
+         integration contracts that linters, type checkers and tests cannot catch.\n\
+         \n\
+         Propose up to 5 candidate markers for {subject}. Use Read/Grep/Glob to \
+         understand how the code relates to the rest of the codebase before proposing. \
+         Only propose invariants that are specific, checkable and likely to matter.\n\
+         \n\
+         Respond with ONLY a JSON object, no other text:\n\
+         {{\"suggestions\": [{{\"name\": \"kebab-case-name\", \
+         \"target\": \"path/of/file/to/place/the/marker/in\", \
+         \"files\": [\"./paths/relative/to/the/target/directory\"], \
+         \"instruction\": \"...\"}}]}}\n\
+         \n\
+         `target` is relative to the repository root. `files` may be empty and may \
+         use glob patterns."
+    )
+    .unwrap();
+
+    writeln!(out).unwrap();
+    match source {
+        SuggestSource::Diff(diff) => {
+            writeln!(out, "## Diff (HEAD → working tree)").unwrap();
+            writeln!(out, "```diff").unwrap();
+            write!(out, "{diff}").unwrap();
+            if !diff.ends_with('\n') {
+                writeln!(out).unwrap();
+            }
+        }
+        SuggestSource::File { path, contents } => {
+            writeln!(out, "## File: {path}").unwrap();
+            writeln!(out, "```").unwrap();
+            write!(out, "{contents}").unwrap();
+            if !contents.ends_with('\n') {
+                writeln!(out).unwrap();
+            }
+        }
+    }
+    writeln!(out, "```").unwrap();
+
+    out
+}
+
+/// Ask for a critique of every marker in the repository, so overlaps
+/// between them can be spotted.
+pub fn build_review_prompt(markers: &[Marker]) -> String {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "You are reviewing the code invariants a team declared for watcher-knight.\n\
+         \n\
+         Each marker below names an invariant, the files it watches and a plain-language \
+         instruction that an AI agent later checks against the codebase. Review every \
+         marker for:\n\
+         - ambiguity: wording two reviewers could read differently, undefined terms, \
+         open-ended lists\n\
+         - testability: whether an agent reading the code could decide pass or fail \
+         without guessing\n\
+         - overlap: other markers below that check the same thing\n\
+         \n\
+         Use Read/Grep/Glob to see the code a marker refers to where that helps. Only \
+         report markers that have a problem, and give each a concrete rewrite of its \
+         instruction.\n\
+         \n\
+         Respond with ONLY a JSON object, no other text:\n\
+         {{\"reviews\": [{{\"name\": \"marker-name\", \"location\": \"path:line\", \
+         \"ambiguity\": \"...\", \"testability\": \"...\", \"overlaps\": [\"other-name\"], \
+         \"rewrite\": \"...\"}}]}}\n\
+         \n\
+         Leave `ambiguity` or `testability` out when there is nothing to say, and \
+         `overlaps` empty when there is no overlap."
+    )
+    .unwrap();
+
+    for m in markers {
+        writeln!(out).unwrap();
+        writeln!(out, "## {} ({}:{})", m.name, m.rel_path, m.line).unwrap();
+        if !m.files.is_empty() {
+            writeln!(out, "Files: {}", m.files.join(", ")).unwrap();
+        }
+        writeln!(out, "Instruction: {}", m.instruction).unwrap();
+    }
+
+    out
+}
+
+/// Appended to a watcher prompt under `confine_tools`: the only paths the
+/// agent may read or search.
+pub fn build_scope_section(entries: &[String]) -> String {
+    let mut out = String::new();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "## Scope\n\nOnly read or search these files and directories (relative to the \
+         working directory's repository root). Always pass a `path` inside them to Grep and \
+         Glob. Reading or searching anything else discards your answer."
+    )
+    .unwrap();
+    for entry in entries {
+        writeln!(out, "- {entry}").unwrap();
+    }
+    out
+}
+
+/// Appended to a diff-mode watcher prompt: the functions and types the
+/// change touches, for instructions phrased in terms of them.
+pub fn build_symbols_section(symbols: &[String]) -> String {
+    if symbols.is_empty() {
+        return String::new();
+    }
+    let mut out = String::from(
+        "\n## Changed symbols\n\nThe diff changes code inside these functions and types:\n",
+    );
+    for symbol in symbols.iter().take(MAX_SYMBOLS) {
+        out.push_str(&format!("- {symbol}\n"));
+    }
+    if symbols.len() > MAX_SYMBOLS {
+        out.push_str(&format!("- ... and {} more\n", symbols.len() - MAX_SYMBOLS));
+    }
+    out
+}
+
+/// Prepended to the extra sections of a watcher prompt for one chunk of a
+/// large scope: the files this pass checks.
+pub fn build_chunk_section(index: usize, total: usize, files: &[String]) -> String {
+    let mut out = String::new();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "## Chunk {index} of {total}\n\nThis invariant watches too many files to check at \
+         once, so it is checked in parts. Check it against these files only; the other \
+         parts are checked separately. Report a violation only when it is in these files."
+    )
+    .unwrap();
+    for file in files {
+        writeln!(out, "- {file}").unwrap();
+    }
+    out
+}
+
+/// The context window, in tokens, of `model`: 1M for the `[1m]` variants,
+/// 200k otherwise.
+pub fn context_window(model: &str) -> usize {
+    if model.ends_with("[1m]") {
+        1_000_000
+    } else {
+        200_000
+    }
+}
+
+/// Refuse a watcher prompt that would not fit in `model`'s context with
+/// room for the agent to work, rather than have the backend cut it.
+pub fn check_size(prompt: &str, model: &str) -> Result<(), String> {
+    let tokens = quota::estimate_tokens(prompt);
+    let limit = (context_window(model) - RESPONSE_RESERVE) as u64;
+    if tokens <= limit {
+        return Ok(());
+    }
+    Err(format!(
+        "prompt too large: about {tokens} tokens, over the {limit} that fit in the context of \
+         model `{model}`. Narrow the watcher's file list, split it into several watchers or \
+         lower `chunk_files` so each prompt fits"
+    ))
+}
+
+/// Ask which markers an `assert` expression could check exactly.
+pub fn build_classify_prompt(markers: &[&Marker]) -> String {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "You are classifying the code invariants a team declared for watcher-knight.\n\
+         \n\
+         Each marker below is checked by an AI agent today. Some could instead be \
+         checked exactly by an `assert` expression, which is a boolean combination \
+         (`&&`, `||`, `!`, comparisons) of:\n\
+         - exists(path): the file or directory exists\n\
+         - contains(path, regex): the file exists and matches the regex\n\
+         - count(glob): the number of repo files matching the glob (an integer)\n\
+         - references(symbol): a file other than the marker's own mentions the symbol \
+         as a whole word\n\
+         \n\
+         Paths starting with `/` are relative to the repo root, others to the marker's \
+         file. Write strings with single quotes, double backslashes in regexes, and never \
+         use double quotes. Give an expression only when it checks exactly what the \
+         instruction asks, no more and no less; use Read/Grep/Glob to check paths. \
+         Otherwise give null.\n\
+         \n\
+         Respond with ONLY a JSON object, no other text:\n\
+         {{\"classifications\": [{{\"name\": \"marker-name\", \"location\": \"path:line\", \
+         \"assert\": \"exists('/docs/api.md')\" or null}}]}}"
+    )
+    .unwrap();
+
+    for m in markers {
+        writeln!(out).unwrap();
+        writeln!(out, "## {} ({}:{})", m.name, m.rel_path, m.line).unwrap();
+        if !m.files.is_empty() {
+            writeln!(out, "Files: {}", m.files.join(", ")).unwrap();
+        }
+        writeln!(out, "Instruction: {}", m.instruction).unwrap();
+    }
+
+    out
+}
+
+/// Ask for a short, prioritized account of a run's failures. Each failure
+/// comes with its marker, when it is still known.
+pub fn build_summary_prompt(
+    failures: &[(&WatcherResult, Option<&Marker>)],
+    diff: Option<&str>,
+) -> String {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "You are summarizing a watcher-knight run for the developer who has to act on it.\n\
+         \n\
+         Each failure below is a code invariant that an AI agent found violated, with the \
+         agent's reason. Write a short plain-text narrative, at most a few sentences and \
+         a numbered list, that:\n\
+         - groups failures that share a root cause, naming the cause (e.g. one renamed \
+         field breaking several watchers)\n\
+         - orders them by what to fix first: severity, then how many watchers a fix clears\n\
+         - names the files to change\n\
+         \n\
+         Use Read/Grep/Glob to confirm a shared cause where that helps. Do not restate \
+         every reason, and do not invent failures that are not listed. Respond with ONLY \
+         the narrative, no headings or preamble."
+    )
+    .unwrap();
+
+    for (result, marker) in failures {
+        writeln!(out).unwrap();
+        writeln!(out, "## {} ({})", result.name, result.location).unwrap();
+        if let Some(m) = marker {
+            writeln!(out, "Severity: {}", m.severity()).unwrap();
+            if !m.files.is_empty() {
+                writeln!(out, "Files: {}", m.files.join(", ")).unwrap();
+            }
+            writeln!(out, "Instruction: {}", m.instruction).unwrap();
+        }
+        let reason = result.reason.as_deref().unwrap_or("(no reason given)");
+        writeln!(out, "Reason: {reason}").unwrap();
+    }
+
+    if let Some(diff) = diff.filter(|d| !d.trim().is_empty()) {
+        let mut end = diff.len().min(SUMMARY_DIFF_LIMIT);
+        while !diff.is_char_boundary(end) {
+            end -= 1;
+        }
+        writeln!(out, "\n## Diff under review\n\n```diff\n{}", &diff[..end]).unwrap();
+        if end < diff.len() {
+            writeln!(out, "[... diff truncated]").unwrap();
+        }
+        writeln!(out, "```").unwrap();
+    }
+
+    out
+}
+
+/// Ask whether an edit to a watcher's definition weakens the invariant it
+/// guards.
+pub fn build_marker_change_prompt(before: &Marker, after: &Marker) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "You are reviewing a change to a code invariant, not the code it guards.\n\
+         \n\
+         A watcher-knight watcher states an invariant that an AI agent checks on every \
+         change. A pull request edited the definition below. Decide whether the edit \
+         weakens the guardrail: the instruction demands less, allows more, or becomes \
+         vaguer; fewer files are watched or more are excluded; reference documents are \
+         dropped; or options make it fail less often (a lower severity, a narrower \
+         `when`, a strategy that sees less). Rewording, fixing file paths after a move, \
+         and stricter or broader checks do not weaken it.\n\
+         \n\
+         Respond with ONLY a JSON object, no other text:\n\
+         - {{\"weakens\": false}} if the invariant is as strong as before or stronger\n\
+         - {{\"weakens\": true, \"reason\": \"...\"}} if it is weaker, saying what was given up"
+    )
+    .unwrap();
+    for (title, m) in [("Before", before), ("After", after)] {
+        writeln!(out, "\n## {title}\n").unwrap();
+        writeln!(out, "Name: {}", m.name).unwrap();
+        writeln!(out, "File: {} (line {})", m.rel_path, m.line).unwrap();
+        if !m.files.is_empty() {
+            writeln!(out, "Watched files: {}", m.files.join(", ")).unwrap();
+        }
+        if !m.exclude.is_empty() {
+            writeln!(out, "Excluded: {}", m.exclude.join(", ")).unwrap();
+        }
+        if !m.context.is_empty() {
+            writeln!(out, "Reference documents: {}", m.context.join(", ")).unwrap();
+        }
+        let mut options: Vec<_> = m.options.iter().collect();
+        options.sort();
+        for (key, value) in options {
+            writeln!(out, "Option {key}: {value}").unwrap();
+        }
+        writeln!(out, "Instruction: {}", m.instruction).unwrap();
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -63,6 +565,9 @@ mod tests {
             line: 42,
             instruction: instruction.to_string(),
             files: vec![],
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
             options: HashMap::new(),
         }
     }
@@ -70,7 +575,7 @@ mod tests {
     #[test]
     fn prompt_contains_marker_fields() {
         let m = make_marker("my-check", "Ensure alignment");
-        let out = build_watcher_prompt(&m, None);
+        let out = build_watcher_prompt(&m, &Evidence::Tools, None, &[], &[]);
         assert!(out.contains("my-check"));
         assert!(out.contains("src/app.ts"));
         assert!(out.contains("42"));
@@ -80,7 +585,7 @@ mod tests {
     #[test]
     fn prompt_no_diff_has_no_diff_section() {
         let m = make_marker("test", "Check it");
-        let out = build_watcher_prompt(&m, None);
+        let out = build_watcher_prompt(&m, &Evidence::Tools, None, &[], &[]);
         assert!(!out.contains("## Diff"));
         assert!(!out.contains("```diff"));
     }
@@ -88,7 +593,7 @@ mod tests {
     #[test]
     fn prompt_no_diff_instruction_text() {
         let m = make_marker("test", "Check it");
-        let out = build_watcher_prompt(&m, None);
+        let out = build_watcher_prompt(&m, &Evidence::Tools, None, &[], &[]);
         assert!(out.contains("ALWAYS use Read/Grep/Glob"));
         assert!(!out.contains("Use the diff to understand"));
     }
@@ -96,16 +601,81 @@ mod tests {
     #[test]
     fn prompt_with_diff_has_diff_section() {
         let m = make_marker("test", "Check it");
-        let out = build_watcher_prompt(&m, Some("+ added line\n"));
+        let out = build_watcher_prompt(&m, &Evidence::Tools, Some("+ added line\n"), &[], &[]);
         assert!(out.contains("## Diff"));
         assert!(out.contains("```diff"));
         assert!(out.contains("+ added line"));
     }
 
+    #[test]
+    fn strategy_defaults_to_agentic_tools() {
+        let mut m = make_marker("test", "Check it");
+        assert_eq!(strategy_for(&m), Ok(Strategy::AgenticTools));
+        m.options
+            .insert("strategy".to_string(), "diff-only".to_string());
+        assert_eq!(strategy_for(&m), Ok(Strategy::DiffOnly));
+        m.options
+            .insert("strategy".to_string(), "dependencies".to_string());
+        assert_eq!(strategy_for(&m), Ok(Strategy::Dependencies));
+        m.options
+            .insert("strategy".to_string(), "files".to_string());
+        assert!(
+            strategy_for(&m)
+                .unwrap_err()
+                .contains("unknown strategy `files`")
+        );
+    }
+
+    #[test]
+    fn tool_less_prompts_inline_their_evidence() {
+        let m = make_marker("test", "Check it");
+        let out = build_watcher_prompt(&m, &Evidence::DiffOnly, Some("+ x\n"), &[], &[]);
+        assert!(out.contains("decide from the diff below alone"));
+        assert!(!out.contains("Read/Grep/Glob"));
+        assert!(!out.contains("actually exist"));
+
+        let files = [ContextDoc {
+            source: "src/app.ts".to_string(),
+            contents: "export const x = 1;".to_string(),
+        }];
+        let out = build_watcher_prompt(&m, &Evidence::Files(&files), None, &[], &[]);
+        assert!(out.contains("## Watched files"));
+        assert!(out.contains("export const x = 1;"));
+
+        let evidence = Evidence::Command {
+            command: "cargo test",
+            output: "2 failed\n[exit status: 1]\n",
+        };
+        let out = build_watcher_prompt(&m, &evidence, None, &[], &[]);
+        assert!(out.contains("## Output of `cargo test`"));
+        assert!(out.contains("[exit status: 1]"));
+
+        let evidence = Evidence::Dependencies {
+            list: "Cargo.lock:\n- updated tokio 1.38.0 -> 2.0.0\n",
+            changes: true,
+        };
+        let out = build_watcher_prompt(&m, &evidence, None, &[], &[]);
+        assert!(out.contains("## Dependency changes\nCargo.lock:\n- updated tokio"));
+        assert!(out.contains("dependencies that are not listed as satisfying"));
+    }
+
+    #[test]
+    fn check_size_refuses_prompts_over_the_context() {
+        assert!(check_size("short prompt", "sonnet").is_ok());
+        let prompt = "x".repeat(600_000);
+        let err = check_size(&prompt, "sonnet").unwrap_err();
+        assert!(
+            err.starts_with("prompt too large: about 200000 tokens, over the 184000"),
+            "err was: {err}"
+        );
+        assert!(err.contains("file list"));
+        assert!(check_size(&prompt, "sonnet[1m]").is_ok());
+    }
+
     #[test]
     fn prompt_with_diff_instruction_text() {
         let m = make_marker("test", "Check it");
-        let out = build_watcher_prompt(&m, Some("diff"));
+        let out = build_watcher_prompt(&m, &Evidence::Tools, Some("diff"), &[], &[]);
         assert!(out.contains("Use the diff to understand what changed"));
         assert!(out.contains("ALWAYS use Read/Grep/Glob"));
     }
@@ -113,7 +683,7 @@ mod tests {
     #[test]
     fn prompt_contains_json_format() {
         let m = make_marker("test", "Check it");
-        let out = build_watcher_prompt(&m, None);
+        let out = build_watcher_prompt(&m, &Evidence::Tools, None, &[], &[]);
         assert!(out.contains("\"is_valid\""));
         assert!(out.contains("JSON"));
     }
@@ -121,7 +691,7 @@ mod tests {
     #[test]
     fn prompt_diff_without_trailing_newline_adds_one() {
         let m = make_marker("test", "Check it");
-        let out = build_watcher_prompt(&m, Some("no trailing newline"));
+        let out = build_watcher_prompt(&m, &Evidence::Tools, Some("no trailing newline"), &[], &[]);
         // Should have newline before closing fence
         assert!(out.contains("no trailing newline\n```"));
     }
@@ -129,7 +699,7 @@ mod tests {
     #[test]
     fn prompt_diff_with_trailing_newline_no_double() {
         let m = make_marker("test", "Check it");
-        let out = build_watcher_prompt(&m, Some("has newline\n"));
+        let out = build_watcher_prompt(&m, &Evidence::Tools, Some("has newline\n"), &[], &[]);
         assert!(out.contains("has newline\n```"));
         assert!(!out.contains("has newline\n\n```"));
     }
@@ -137,8 +707,143 @@ mod tests {
     #[test]
     fn prompt_diff_empty_string() {
         let m = make_marker("test", "Check it");
-        let out = build_watcher_prompt(&m, Some(""));
+        let out = build_watcher_prompt(&m, &Evidence::Tools, Some(""), &[], &[]);
         assert!(out.contains("## Diff"));
         assert!(out.contains("```diff"));
     }
+
+    #[test]
+    fn prompt_includes_reference_documents() {
+        let m = make_marker("test", "Check it");
+        let docs = [ContextDoc {
+            source: "https://wiki.example.com/api.md".to_string(),
+            contents: "Routes are versioned.".to_string(),
+        }];
+        let out = build_watcher_prompt(&m, &Evidence::Tools, None, &docs, &[]);
+        assert!(out.contains("## Reference documents"));
+        assert!(
+            out.contains("### https://wiki.example.com/api.md\n```\nRoutes are versioned.\n```")
+        );
+        assert!(
+            !build_watcher_prompt(&m, &Evidence::Tools, None, &[], &[])
+                .contains("## Reference documents")
+        );
+    }
+
+    #[test]
+    fn prompt_inlines_related_invariants() {
+        let m = make_marker("client", "Requests must match @server.");
+        let mut server = make_marker("server", "Every route validates its body.");
+        server.rel_path = "api/server.py".to_string();
+        server.line = 3;
+        server.files = vec!["api/routes.py".to_string()];
+        let out = build_watcher_prompt(&m, &Evidence::Tools, Some("diff"), &[], &[&server]);
+        assert!(out.contains(
+            "### server (api/server.py:3)\nFiles: api/routes.py\n\
+             Instruction: Every route validates its body.\n"
+        ));
+        assert!(out.find("## Related invariants").unwrap() < out.find("## Diff").unwrap());
+        assert!(
+            !build_watcher_prompt(&m, &Evidence::Tools, None, &[], &[])
+                .contains("## Related invariants")
+        );
+    }
+
+    // ── build_suggest_prompt ──────────────────────────────────────────────
+
+    #[test]
+    fn suggest_prompt_with_diff() {
+        let out = build_suggest_prompt(&SuggestSource::Diff("+ new line\n"));
+        assert!(out.contains("the code changed in the diff"));
+        assert!(out.contains("```diff\n+ new line\n```"));
+        assert!(out.contains("\"suggestions\""));
+    }
+
+    #[test]
+    fn suggest_prompt_with_file() {
+        let out = build_suggest_prompt(&SuggestSource::File {
+            path: "src/app.ts",
+            contents: "const x = 1;",
+        });
+        assert!(out.contains("## File: src/app.ts"));
+        assert!(out.contains("const x = 1;\n```"));
+        assert!(!out.contains("## Diff"));
+    }
+
+    // ── build_review_prompt ───────────────────────────────────────────────
+
+    #[test]
+    fn review_prompt_lists_every_marker() {
+        let mut scoped = make_marker("api-align", "Keep the clients in sync.");
+        scoped.files = vec!["src/api.ts".to_string(), "server/api.py".to_string()];
+        let global = make_marker("no-todos", "No TODO comments.");
+        let out = build_review_prompt(&[scoped, global]);
+        assert!(out.contains("\"reviews\""));
+        assert!(out.contains(
+            "## api-align (src/app.ts:42)\nFiles: src/api.ts, server/api.py\n\
+             Instruction: Keep the clients in sync.\n"
+        ));
+        assert!(out.contains("## no-todos (src/app.ts:42)\nInstruction: No TODO comments.\n"));
+    }
+
+    #[test]
+    fn symbols_section_caps_the_list() {
+        assert!(build_symbols_section(&[]).is_empty());
+        let symbols: Vec<String> = (0..MAX_SYMBOLS + 2)
+            .map(|i| format!("a.rs: f{i}"))
+            .collect();
+        let out = build_symbols_section(&symbols);
+        assert!(out.starts_with(
+            "\n## Changed symbols\n\nThe diff changes code inside these functions and types:\n\
+             - a.rs: f0\n"
+        ));
+        assert!(!out.contains("f50"));
+        assert!(out.ends_with("- ... and 2 more\n"));
+    }
+
+    // ── build_summary_prompt ──────────────────────────────────────────────
+
+    #[test]
+    fn summary_prompt_lists_failures_and_truncates_the_diff() {
+        let mut m = make_marker("email-field", "Clients use `users.email`.");
+        m.options
+            .insert("severity".to_string(), "warning".to_string());
+        let known = WatcherResult::new(
+            "email-field",
+            "src/app.ts:42",
+            false,
+            Some("renamed".into()),
+        );
+        let gone = WatcherResult::new("gone", "src/old.ts:1", false, None);
+        let diff = "+".repeat(SUMMARY_DIFF_LIMIT + 10);
+        let out = build_summary_prompt(&[(&known, Some(&m)), (&gone, None)], Some(&diff));
+        assert!(out.contains(
+            "## email-field (src/app.ts:42)\nSeverity: warning\n\
+             Instruction: Clients use `users.email`.\nReason: renamed\n"
+        ));
+        assert!(out.contains("## gone (src/old.ts:1)\nReason: (no reason given)\n"));
+        assert!(out.contains("[... diff truncated]"));
+        assert!(!out.contains(&"+".repeat(SUMMARY_DIFF_LIMIT + 1)));
+    }
+
+    #[test]
+    fn marker_change_prompt_shows_both_definitions() {
+        let before = make_marker("auth", "Every handler checks the session.");
+        let mut after = make_marker("auth", "Most handlers check the session.");
+        after.files = vec!["./src/api/".to_string()];
+        after
+            .options
+            .insert("severity".to_string(), "info".to_string());
+        let out = build_marker_change_prompt(&before, &after);
+        assert!(out.contains(
+            "## Before\n\nName: auth\nFile: src/app.ts (line 42)\n\
+             Instruction: Every handler checks the session.\n"
+        ));
+        assert!(out.contains(
+            "## After\n\nName: auth\nFile: src/app.ts (line 42)\n\
+             Watched files: ./src/api/\nOption severity: info\n\
+             Instruction: Most handlers check the session.\n"
+        ));
+        assert!(out.contains("{\"weakens\": false}"));
+    }
 }
@@ -0,0 +1,243 @@
+use std::fmt;
+
+use crate::claude::WatcherResult;
+use crate::clock::Date;
+use crate::marker::Marker;
+use crate::waivers::Waivers;
+
+/// Waivers for `<WAIVER_PREFIX><pattern>` let changes under a protected path
+/// through without a watcher.
+pub const WAIVER_PREFIX: &str = "protected:";
+
+/// A changed file under a protected path that no passing watcher covers.
+#[derive(Debug, PartialEq)]
+pub struct Unguarded {
+    pub path: String,
+    pub pattern: String,
+}
+
+impl fmt::Display for Unguarded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unguarded change to protected path {} (protected by `{}`): no passing watcher \
+             covers it; add one or waive `{WAIVER_PREFIX}{}`",
+            self.path, self.pattern, self.pattern
+        )
+    }
+}
+
+/// Whether `path` matches a `protected_paths` glob.
+fn matches(pattern: &str, path: &str) -> bool {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..glob::MatchOptions::new()
+    };
+    glob::Pattern::new(pattern.trim_start_matches('/')).is_ok_and(|p| p.matches_with(path, options))
+}
+
+/// Changed files under `patterns` not covered by a watcher that passed or
+/// whose failure is waived, unless the pattern itself is waived.
+pub fn unguarded(
+    patterns: &[String],
+    changed_files: &[String],
+    markers: &[Marker],
+    results: &[WatcherResult],
+    waivers: &Waivers,
+    today: Date,
+) -> Vec<Unguarded> {
+    let guards: Vec<&Marker> = results
+        .iter()
+        .filter(|r| {
+            r.skipped.is_none()
+                && (r.is_valid || r.suppression.as_ref().is_some_and(|s| s.label == "WAIVED"))
+        })
+        .filter_map(|r| {
+            markers
+                .iter()
+                .find(|m| m.name == r.name && format!("{}:{}", m.rel_path, m.line) == r.location)
+        })
+        .collect();
+    let mut unguarded = Vec::new();
+    for path in changed_files {
+        let Some(pattern) = patterns.iter().find(|p| matches(p, path)) else {
+            continue;
+        };
+        let waived = patterns.iter().any(|p| {
+            matches(p, path)
+                && waivers
+                    .active_for(&format!("{WAIVER_PREFIX}{p}"), today)
+                    .is_some()
+        });
+        if !waived && !guards.iter().any(|m| m.watches(path)) {
+            unguarded.push(Unguarded {
+                path: path.clone(),
+                pattern: pattern.clone(),
+            });
+        }
+    }
+    unguarded
+}
+
+/// A file added under a `require_marker_in` path without a marker in it.
+#[derive(Debug, PartialEq)]
+pub struct Unmarked {
+    pub path: String,
+    pub pattern: String,
+}
+
+impl fmt::Display for Unmarked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "new file {} has no watcher-knight marker (required in `{}`); declare its \
+             invariants with a `<wk: ... />` comment",
+            self.path, self.pattern
+        )
+    }
+}
+
+/// Files a unified diff adds (`--- /dev/null` in a file header).
+pub fn added_files(diff: &str) -> Vec<String> {
+    let mut added = Vec::new();
+    let mut in_header = false;
+    let mut from_nothing = false;
+    for line in diff.lines() {
+        if line.starts_with("diff --git ") {
+            in_header = true;
+            from_nothing = false;
+        } else if line.starts_with("@@") {
+            in_header = false;
+        } else if in_header && line == "--- /dev/null" {
+            from_nothing = true;
+        } else if in_header
+            && from_nothing
+            && let Some(path) = line.strip_prefix("+++ b/")
+        {
+            added.push(path.to_string());
+        }
+    }
+    added
+}
+
+/// Added files under `patterns` that hold no marker.
+pub fn unmarked(patterns: &[String], added_files: &[String], markers: &[Marker]) -> Vec<Unmarked> {
+    added_files
+        .iter()
+        .filter(|path| !markers.iter().any(|m| m.rel_path == **path))
+        .filter_map(|path| {
+            let pattern = patterns.iter().find(|p| matches(p, path))?;
+            Some(Unmarked {
+                path: path.clone(),
+                pattern: pattern.clone(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::claude::Suppression;
+    use crate::clock;
+    use crate::waivers::Waiver;
+    use std::collections::HashMap;
+
+    fn marker(name: &str, files: &[&str]) -> Marker {
+        Marker {
+            name: name.to_string(),
+            rel_path: "src/app.ts".to_string(),
+            line: 1,
+            instruction: "Check it".to_string(),
+            files: files.iter().map(|f| f.to_string()).collect(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn changes_need_a_passing_or_waived_watcher() {
+        let patterns = vec!["billing/**".to_string(), "crypto/*".to_string()];
+        let changed: Vec<String> = [
+            "billing/charge.rs",
+            "crypto/aes.rs",
+            "crypto/x/y.rs",
+            "ui.rs",
+        ]
+        .map(String::from)
+        .to_vec();
+        let markers = vec![
+            marker("charge", &["billing/charge.rs"]),
+            marker("aes", &["crypto/aes.rs"]),
+        ];
+        let mut results = vec![
+            WatcherResult::new("charge", "src/app.ts:1", true, None),
+            WatcherResult::new("aes", "src/app.ts:1", false, None),
+        ];
+        let none = Waivers::default();
+        let today = clock::parse_date("2025-01-01").unwrap();
+        let found = unguarded(&patterns, &changed, &markers, &results, &none, today);
+        assert_eq!(
+            found,
+            vec![Unguarded {
+                path: "crypto/aes.rs".to_string(),
+                pattern: "crypto/*".to_string()
+            }]
+        );
+        assert!(found[0].to_string().contains("waive `protected:crypto/*`"));
+
+        results[1].suppression = Some(Suppression {
+            label: "WAIVED",
+            note: String::new(),
+        });
+        assert!(unguarded(&patterns, &changed, &markers, &results, &none, today).is_empty());
+
+        let waivers = Waivers {
+            waivers: vec![Waiver {
+                marker: "protected:billing/**".to_string(),
+                until: "2099-01-01".to_string(),
+                reason: "migration".to_string(),
+            }],
+        };
+        let found = unguarded(&patterns, &changed, &[], &[], &waivers, today);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, "crypto/aes.rs");
+    }
+
+    #[test]
+    fn added_files_only_lists_new_files() {
+        let diff = "diff --git a/api/handlers/new.rs b/api/handlers/new.rs\n\
+                    new file mode 100644\n\
+                    --- /dev/null\n\
+                    +++ b/api/handlers/new.rs\n\
+                    @@ -0,0 +1 @@\n\
+                    +fn main() {}\n\
+                    diff --git a/api/handlers/old.rs b/api/handlers/old.rs\n\
+                    --- a/api/handlers/old.rs\n\
+                    +++ b/api/handlers/old.rs\n\
+                    @@ -1 +1 @@\n\
+                    --- /dev/null\n\
+                    +++ b/not-a-header\n";
+        assert_eq!(added_files(diff), vec!["api/handlers/new.rs"]);
+    }
+
+    #[test]
+    fn unmarked_skips_files_with_markers_and_other_paths() {
+        let patterns = vec!["api/handlers/**".to_string()];
+        let added: Vec<String> = ["api/handlers/a.rs", "api/handlers/b.rs", "api/lib.rs"]
+            .map(String::from)
+            .to_vec();
+        let mut in_b = marker("b", &[]);
+        in_b.rel_path = "api/handlers/b.rs".to_string();
+        let found = unmarked(&patterns, &added, &[in_b]);
+        assert_eq!(
+            found,
+            vec![Unmarked {
+                path: "api/handlers/a.rs".to_string(),
+                pattern: "api/handlers/**".to_string()
+            }]
+        );
+    }
+}
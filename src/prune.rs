@@ -0,0 +1,366 @@
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::marker::{self, Marker};
+
+/// Why a watcher looks dead.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    /// It lists files, and none of them exist any more.
+    ScopeGone,
+    /// Its file was deleted on `base` since this branch forked from it, so
+    /// it goes away with the next rebase or merge.
+    DeletedUpstream { base: String },
+    /// Other watchers set a `suite` and this one sets none, so runs that
+    /// select suites never validate it.
+    NoSuite,
+}
+
+impl Reason {
+    pub fn describe(&self) -> String {
+        match self {
+            Reason::ScopeGone => "none of its files exist".to_string(),
+            Reason::DeletedUpstream { base } => {
+                format!("its file was deleted on {base}")
+            }
+            Reason::NoSuite => "it is in no suite, so `run --suite` never runs it".to_string(),
+        }
+    }
+}
+
+/// A dead watcher and why.
+pub struct Finding<'a> {
+    pub marker: &'a Marker,
+    pub reason: Reason,
+}
+
+/// Files deleted on a base branch: in the tree at the fork point, but no
+/// longer on the base.
+pub struct Upstream {
+    pub base: String,
+    pub at_fork: HashSet<String>,
+    pub at_base: HashSet<String>,
+}
+
+impl Upstream {
+    fn deleted(&self, path: &str) -> bool {
+        self.at_fork.contains(path) && !self.at_base.contains(path)
+    }
+}
+
+/// The dead watchers among `markers`, one finding each, in marker order.
+pub fn find<'a>(
+    markers: &'a [Marker],
+    root: &Path,
+    upstream: Option<&Upstream>,
+) -> Vec<Finding<'a>> {
+    let suites_in_use = markers.iter().any(|m| m.options.contains_key("suite"));
+    markers
+        .iter()
+        .filter_map(|m| {
+            let reason = if upstream.is_some_and(|u| u.deleted(&m.rel_path)) {
+                Reason::DeletedUpstream {
+                    base: upstream.unwrap().base.clone(),
+                }
+            } else if !m.files.is_empty()
+                && !m.watched_files(root).iter().any(|f| root.join(f).exists())
+            {
+                Reason::ScopeGone
+            } else if suites_in_use && !m.options.contains_key("suite") {
+                Reason::NoSuite
+            } else {
+                return None;
+            };
+            Some(Finding { marker: m, reason })
+        })
+        .collect()
+}
+
+/// What to do with a dead watcher.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Delete,
+    /// Replace its file list with these entries.
+    Rewrite(Vec<String>),
+}
+
+/// Ask on `input` what to do with each finding, and return the answers that
+/// change something, by finding index. File lists can only be rewritten for
+/// watchers whose files are gone. `q` or the end of input stops asking.
+pub fn ask(
+    findings: &[Finding],
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> io::Result<Vec<(usize, Action)>> {
+    let mut actions = Vec::new();
+    let mut read = |out: &mut dyn Write, question: &str| -> io::Result<Option<String>> {
+        write!(out, "{question}")?;
+        out.flush()?;
+        let mut line = String::new();
+        Ok((input.read_line(&mut line)? > 0).then(|| line.trim().to_string()))
+    };
+    'findings: for (i, f) in findings.iter().enumerate() {
+        let m = f.marker;
+        writeln!(
+            out,
+            "\n{} ({}:{}): {}",
+            m.name,
+            m.rel_path,
+            m.line,
+            f.reason.describe()
+        )?;
+        let rewritable = f.reason == Reason::ScopeGone;
+        if rewritable {
+            writeln!(out, "  files: {}", m.files.join(", "))?;
+        }
+        let question = if rewritable {
+            "[d]elete, [r]ewrite file list, [s]kip, [q]uit? "
+        } else {
+            "[d]elete, [s]kip, [q]uit? "
+        };
+        loop {
+            let Some(answer) = read(out, question)? else {
+                break 'findings;
+            };
+            match answer.as_str() {
+                "d" => actions.push((i, Action::Delete)),
+                "r" if rewritable => {
+                    let Some(list) =
+                        read(out, "New file list (comma-separated, e.g. ./src/api/): ")?
+                    else {
+                        break 'findings;
+                    };
+                    let entries: Vec<String> = list
+                        .split(',')
+                        .map(|e| e.trim().to_string())
+                        .filter(|e| !e.is_empty())
+                        .collect();
+                    if entries.is_empty() {
+                        continue;
+                    }
+                    actions.push((i, Action::Rewrite(entries)));
+                }
+                "s" | "" => {}
+                "q" => break 'findings,
+                _ => continue,
+            }
+            break;
+        }
+    }
+    Ok(actions)
+}
+
+/// `contents` with `actions` applied to the tags opening on their lines.
+pub fn apply(
+    contents: &str,
+    rel_path: &str,
+    actions: &[(usize, &Action)],
+) -> Result<String, String> {
+    let mut actions = actions.to_vec();
+    // From the bottom up, so deleting a tag leaves the lines above in place.
+    actions.sort_by_key(|&(line, _)| std::cmp::Reverse(line));
+    let mut out = contents.to_string();
+    for (line, action) in actions {
+        out = match action {
+            Action::Delete => delete_tag(&out, rel_path, line)?,
+            Action::Rewrite(entries) => rewrite_files(&out, rel_path, line, entries)?,
+        };
+    }
+    Ok(out)
+}
+
+/// `contents` without the tag opening on `line` (1-based). The tag must
+/// have its lines to itself, so that only comment lines are removed.
+pub fn delete_tag(contents: &str, rel_path: &str, line: usize) -> Result<String, String> {
+    let (tags, _) = marker::extract_raw_tags(contents.lines(), rel_path);
+    let tag = tags
+        .iter()
+        .find(|t| t.line == line)
+        .ok_or_else(|| format!("no watcher opens on {rel_path}:{line}"))?;
+    let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+    let first = lines[tag.line - 1];
+    let last = lines[tag.end_line - 1];
+    let (col, _) = marker::find_tag_in_line(first).unwrap();
+    let before = &first[..col];
+    let alone = marker::detect_comment_prefix(before).is_some()
+        && marker::comment_lead(before) == Some(before.trim())
+        && last
+            .rfind("/>")
+            .is_some_and(|close| last[close + 2..].trim().is_empty());
+    if !alone {
+        return Err(format!(
+            "the watcher on {rel_path}:{line} shares its lines with code; remove it by hand"
+        ));
+    }
+    Ok([&lines[..tag.line - 1], &lines[tag.end_line..]]
+        .concat()
+        .concat())
+}
+
+/// `contents` with the file list of the tag opening on `line` (1-based)
+/// replaced by `entries`, or added after the name if it has none.
+pub fn rewrite_files(
+    contents: &str,
+    rel_path: &str,
+    line: usize,
+    entries: &[String],
+) -> Result<String, String> {
+    let mut lines: Vec<String> = contents.split_inclusive('\n').map(str::to_string).collect();
+    let first = lines
+        .get(line - 1)
+        .ok_or_else(|| format!("no watcher opens on {rel_path}:{line}"))?;
+    let opening = marker::tag_opening(first)
+        .ok_or_else(|| format!("no watcher opens on {rel_path}:{line}"))?;
+    let name_end = first.find(opening).unwrap() + opening.len();
+    let rest = &first[name_end..];
+    let list = format!(" [{}]", entries.join(", "));
+    let after = match rest.trim_start().strip_prefix('[') {
+        Some(inner) => match inner.find(']') {
+            Some(close) => &inner[close + 1..],
+            None => return Err(format!("the file list on {rel_path}:{line} is not closed")),
+        },
+        None => rest,
+    };
+    lines[line - 1] = format!("{}{list}{after}", &first[..name_end]);
+    Ok(lines.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker(name: &str, files: &[&str], suite: Option<&str>) -> Marker {
+        let mut m = Marker {
+            name: name.to_string(),
+            rel_path: format!("src/{name}.ts"),
+            line: 1,
+            instruction: "Check it".to_string(),
+            files: files.iter().map(|f| f.to_string()).collect(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: Default::default(),
+        };
+        if let Some(suite) = suite {
+            m.options.insert("suite".to_string(), suite.to_string());
+        }
+        m
+    }
+
+    #[test]
+    fn find_reports_each_kind_of_dead_watcher() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/live.ts"), "").unwrap();
+        let markers = [
+            marker("live", &["src/live.ts"], Some("ci")),
+            marker("gone", &["src/old.ts"], Some("ci")),
+            marker("unsuited", &["src/live.ts"], None),
+            marker("removed", &[], Some("ci")),
+        ];
+        let upstream = Upstream {
+            base: "origin/main".to_string(),
+            at_fork: HashSet::from(["src/removed.ts".to_string()]),
+            at_base: HashSet::new(),
+        };
+        let findings = find(&markers, dir.path(), Some(&upstream));
+        let found: Vec<(&str, &Reason)> = findings
+            .iter()
+            .map(|f| (f.marker.name.as_str(), &f.reason))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("gone", &Reason::ScopeGone),
+                ("unsuited", &Reason::NoSuite),
+                (
+                    "removed",
+                    &Reason::DeletedUpstream {
+                        base: "origin/main".to_string()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn delete_tag_removes_only_its_lines() {
+        let src = "a();\n// <wk: one [./x.ts]\n// Check it.\n// />\nb(); // <wk: two Inline. />\n";
+        assert_eq!(
+            delete_tag(src, "f.ts", 2).unwrap(),
+            "a();\nb(); // <wk: two Inline. />\n"
+        );
+        let err = delete_tag(src, "f.ts", 5).unwrap_err();
+        assert!(err.contains("shares its lines with code"), "err was: {err}");
+        assert!(delete_tag(src, "f.ts", 1).is_err());
+    }
+
+    #[test]
+    fn ask_collects_answers_until_quit() {
+        let markers = [
+            marker("gone", &["src/old.ts"], None),
+            marker("unsuited", &[], None),
+            marker("other", &[], None),
+            marker("last", &[], None),
+        ];
+        let findings: Vec<Finding> = vec![
+            Finding {
+                marker: &markers[0],
+                reason: Reason::ScopeGone,
+            },
+            Finding {
+                marker: &markers[1],
+                reason: Reason::NoSuite,
+            },
+            Finding {
+                marker: &markers[2],
+                reason: Reason::NoSuite,
+            },
+            Finding {
+                marker: &markers[3],
+                reason: Reason::NoSuite,
+            },
+        ];
+        // `r` is not offered for a watcher in no suite, so it is asked again.
+        let mut input = io::Cursor::new("r\n./src/new.ts, ./lib/\nr\nd\ns\nq\n");
+        let mut out = Vec::new();
+        let actions = ask(&findings, &mut input, &mut out).unwrap();
+        assert_eq!(
+            actions,
+            [
+                (
+                    0,
+                    Action::Rewrite(vec!["./src/new.ts".to_string(), "./lib/".to_string()])
+                ),
+                (1, Action::Delete),
+            ]
+        );
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("gone (src/gone.ts:1): none of its files exist"));
+        assert!(out.contains("  files: src/old.ts\n"));
+    }
+
+    #[test]
+    fn apply_works_from_the_bottom_up() {
+        let src = "// <wk: one [./a.ts]\n// Check it. />\n// <wk: two [./b.ts] Check. />\n";
+        let rewrite = Action::Rewrite(vec!["./c.ts".to_string()]);
+        let out = apply(src, "f.ts", &[(1, &Action::Delete), (3, &rewrite)]).unwrap();
+        assert_eq!(out, "// <wk: two [./c.ts] Check. />\n");
+    }
+
+    #[test]
+    fn rewrite_files_replaces_or_adds_the_list() {
+        let src = "// <wk: one [./old.ts, ./gone/]\n// Check it. />\n";
+        let entries = ["./new.ts".to_string()];
+        assert_eq!(
+            rewrite_files(src, "f.ts", 1, &entries).unwrap(),
+            "// <wk: one [./new.ts]\n// Check it. />\n"
+        );
+        let src = "# <wk: two\n# Check it. />\n";
+        assert_eq!(
+            rewrite_files(src, "f.py", 1, &entries).unwrap(),
+            "# <wk: two [./new.ts]\n# Check it. />\n"
+        );
+    }
+}
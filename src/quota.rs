@@ -0,0 +1,249 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+const USAGE_DIR: &str = ".watcher_knight";
+const USAGE_FILE: &str = ".watcher_knight/usage.json";
+
+/// Held while a run merges its spending into the usage file.
+const LOCK_FILE: &str = ".watcher_knight/usage.json.lock";
+
+/// A lock older than this was left by a run that died while saving.
+const STALE_LOCK: Duration = Duration::from_secs(30);
+
+/// Backends that `[backends.<name>]` can limit: the local claude CLI and
+/// remote workers.
+pub const BACKENDS: [&str; 2] = ["claude", "remote"];
+
+/// Limits of one backend (`[backends.<name>]` in `watcher-knight.toml`).
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackendLimits {
+    /// Most calls in flight at once, below the adaptive limit.
+    pub max_inflight: Option<usize>,
+    /// Most calls started in any 60 seconds.
+    pub requests_per_minute: Option<u32>,
+    /// Tokens (prompts, files read and responses) this checkout may spend
+    /// per UTC day; watchers past it are not run.
+    pub daily_token_budget: Option<u64>,
+}
+
+/// Check the names and values of `[backends]`.
+pub fn validate(backends: &HashMap<String, BackendLimits>) -> Result<(), String> {
+    for (name, limits) in backends {
+        if !BACKENDS.contains(&name.as_str()) {
+            return Err(format!(
+                "unknown backend `{name}` under [backends] (expected one of: {})",
+                BACKENDS.join(", ")
+            ));
+        }
+        if limits.max_inflight == Some(0) || limits.requests_per_minute == Some(0) {
+            return Err(format!(
+                "[backends.{name}] limits must be at least 1 (use daily_token_budget = 0 to \
+                 disable the backend)"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// A rough token count for `text`, used for budgets and prompt sizes alike.
+/// Code runs at 3 to 4 bytes a token, so this errs on the high side.
+pub fn estimate_tokens(text: &str) -> u64 {
+    text.len().div_ceil(3) as u64
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct UsageFile {
+    date: String,
+    tokens: BTreeMap<String, u64>,
+}
+
+/// Estimated tokens spent today per backend, kept in
+/// `.watcher_knight/usage.json` so the budget holds across the runs of one
+/// checkout. Fresh checkouts (e.g. CI jobs) start from zero.
+pub struct Usage {
+    root: PathBuf,
+    today: String,
+    /// What the file said when the run started.
+    loaded: BTreeMap<String, u64>,
+    tokens: Mutex<BTreeMap<String, u64>>,
+}
+
+fn read_file(path: &Path, today: &str) -> BTreeMap<String, u64> {
+    let file: UsageFile = fs::read_to_string(path)
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default();
+    if file.date == today {
+        file.tokens
+    } else {
+        BTreeMap::new()
+    }
+}
+
+/// Take the usage lock under `root`, waiting for other runs to finish
+/// saving. A lock left by a crashed run is broken once stale.
+fn lock(root: &Path) -> Result<(), String> {
+    let path = root.join(LOCK_FILE);
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => return Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                let age = fs::metadata(&path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| SystemTime::now().duration_since(t).ok());
+                if age.is_some_and(|age| age > STALE_LOCK) {
+                    fs::remove_file(&path).ok();
+                } else {
+                    thread::sleep(Duration::from_millis(50));
+                }
+            }
+            Err(e) => return Err(format!("cannot lock {USAGE_FILE}: {e}")),
+        }
+    }
+}
+
+impl Usage {
+    /// Today's usage under `root`; a missing or unreadable file, or one from
+    /// another day, starts from zero.
+    pub fn load(root: &Path, today: &str) -> Usage {
+        let loaded = read_file(&root.join(USAGE_FILE), today);
+        Usage {
+            root: root.to_path_buf(),
+            today: today.to_string(),
+            tokens: Mutex::new(loaded.clone()),
+            loaded,
+        }
+    }
+
+    /// Count `tokens` against `backend` if that stays within `budget`.
+    /// Returns whether they were counted.
+    pub fn reserve(&self, backend: &str, budget: Option<u64>, tokens: u64) -> bool {
+        let mut spent = self.tokens.lock().unwrap();
+        let spent = spent.entry(backend.to_string()).or_insert(0);
+        if budget.is_some_and(|b| *spent + tokens > b) {
+            return false;
+        }
+        *spent += tokens;
+        true
+    }
+
+    /// Replace `reserved` tokens counted by [`Usage::reserve`] with the
+    /// `spent` ones a call turned out to cost.
+    pub fn settle(&self, backend: &str, reserved: u64, spent: u64) {
+        let mut tokens = self.tokens.lock().unwrap();
+        let total = tokens.entry(backend.to_string()).or_insert(0);
+        *total = total.saturating_sub(reserved) + spent;
+    }
+
+    /// Add what this run spent to the usage file, unless it spent nothing.
+    /// The file is re-read under a lock and replaced whole, so runs that
+    /// share the checkout and save at the same time all count.
+    pub fn save(&self) -> Result<(), String> {
+        let spent: BTreeMap<String, u64> = self
+            .tokens
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(backend, &tokens)| {
+                let before = self.loaded.get(backend).copied().unwrap_or(0);
+                (backend.clone(), tokens.saturating_sub(before))
+            })
+            .filter(|&(_, tokens)| tokens > 0)
+            .collect();
+        if spent.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(self.root.join(USAGE_DIR))
+            .map_err(|e| format!("cannot create {USAGE_DIR}: {e}"))?;
+        lock(&self.root)?;
+        let path = self.root.join(USAGE_FILE);
+        let mut file = UsageFile {
+            date: self.today.clone(),
+            tokens: read_file(&path, &self.today),
+        };
+        for (backend, tokens) in spent {
+            *file.tokens.entry(backend).or_insert(0) += tokens;
+        }
+        let tmp = path.with_extension("json.tmp");
+        let saved = fs::write(&tmp, serde_json::to_string_pretty(&file).unwrap())
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| format!("cannot write {USAGE_FILE}: {e}"));
+        fs::remove_file(self.root.join(LOCK_FILE)).ok();
+        saved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_rejects_unknown_backends_and_zero_limits() {
+        let mut backends = HashMap::new();
+        backends.insert("claude".to_string(), BackendLimits::default());
+        assert!(validate(&backends).is_ok());
+        backends.insert(
+            "remote".to_string(),
+            BackendLimits {
+                max_inflight: Some(0),
+                ..BackendLimits::default()
+            },
+        );
+        assert!(validate(&backends).unwrap_err().contains("at least 1"));
+        backends.remove("remote");
+        backends.insert("api".to_string(), BackendLimits::default());
+        assert!(
+            validate(&backends)
+                .unwrap_err()
+                .contains("unknown backend `api`")
+        );
+    }
+
+    #[test]
+    fn usage_enforces_budget_and_persists_per_day() {
+        let dir = tempfile::tempdir().unwrap();
+        let usage = Usage::load(dir.path(), "2025-03-01");
+        assert!(usage.reserve("claude", Some(100), 60));
+        assert!(!usage.reserve("claude", Some(100), 50));
+        // The call turned out to cost 90 tokens, not the 60 reserved.
+        usage.settle("claude", 60, 90);
+        assert!(usage.reserve("remote", None, 1_000));
+        usage.save().unwrap();
+
+        let again = Usage::load(dir.path(), "2025-03-01");
+        assert!(!again.reserve("claude", Some(100), 20));
+        assert!(again.reserve("claude", Some(100), 10));
+        let next_day = Usage::load(dir.path(), "2025-03-02");
+        assert!(next_day.reserve("claude", Some(100), 100));
+    }
+
+    #[test]
+    fn save_merges_runs_sharing_a_checkout() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = Usage::load(dir.path(), "2025-03-01");
+        let second = Usage::load(dir.path(), "2025-03-01");
+        assert!(first.reserve("claude", None, 40));
+        assert!(second.reserve("claude", None, 25));
+        first.save().unwrap();
+        second.save().unwrap();
+
+        let after = Usage::load(dir.path(), "2025-03-01");
+        assert!(!after.reserve("claude", Some(100), 36));
+        assert!(after.reserve("claude", Some(100), 35));
+        assert!(!dir.path().join(LOCK_FILE).exists());
+    }
+
+    #[test]
+    fn estimate_tokens_rounds_up() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::claude::WatcherResult;
+use crate::marker::Marker;
+
+pub const RATCHET_FILE: &str = "watcher-knight-ratchet.toml";
+
+/// Failure counts per severity that `run --ratchet` may not exceed.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    #[serde(default)]
+    pub failures: BTreeMap<String, usize>,
+}
+
+/// A severity whose failure count changed against the baseline.
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub severity: String,
+    pub before: usize,
+    pub after: usize,
+}
+
+/// `None` when no baseline has been recorded yet.
+pub fn load(root: &Path) -> Result<Option<Baseline>, String> {
+    match fs::read_to_string(root.join(RATCHET_FILE)) {
+        Ok(data) => toml::from_str(&data)
+            .map(Some)
+            .map_err(|e| format!("invalid {RATCHET_FILE}: {e}")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("cannot read {RATCHET_FILE}: {e}")),
+    }
+}
+
+pub fn save(root: &Path, baseline: &Baseline) -> Result<(), String> {
+    let data = toml::to_string(baseline).unwrap();
+    fs::write(root.join(RATCHET_FILE), data)
+        .map_err(|e| format!("cannot write {RATCHET_FILE}: {e}"))
+}
+
+/// Failures of the run by the `severity` option of their marker (default
+/// `error`), as in `--format compact`.
+pub fn count(results: &[WatcherResult], markers: &[Marker]) -> Baseline {
+    let mut failures = BTreeMap::new();
+    for r in results.iter().filter(|r| r.is_failure()) {
+        let severity = markers
+            .iter()
+            .find(|m| m.name == r.name && format!("{}:{}", m.rel_path, m.line) == r.location)
+            .map_or("error", Marker::severity);
+        *failures.entry(severity.to_string()).or_insert(0) += 1;
+    }
+    Baseline { failures }
+}
+
+/// Severities with more failures than `baseline` allows, and severities with
+/// fewer, in that order.
+pub fn compare(baseline: &Baseline, current: &Baseline) -> (Vec<Change>, Vec<Change>) {
+    let severities: BTreeMap<&String, ()> = baseline
+        .failures
+        .keys()
+        .chain(current.failures.keys())
+        .map(|s| (s, ()))
+        .collect();
+    let (mut increased, mut decreased) = (Vec::new(), Vec::new());
+    for severity in severities.into_keys() {
+        let before = baseline.failures.get(severity).copied().unwrap_or(0);
+        let after = current.failures.get(severity).copied().unwrap_or(0);
+        let change = Change {
+            severity: severity.clone(),
+            before,
+            after,
+        };
+        if after > before {
+            increased.push(change);
+        } else if after < before {
+            decreased.push(change);
+        }
+    }
+    (increased, decreased)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn baseline(entries: &[(&str, usize)]) -> Baseline {
+        Baseline {
+            failures: entries.iter().map(|(s, n)| (s.to_string(), *n)).collect(),
+        }
+    }
+
+    #[test]
+    fn count_groups_failures_by_severity() {
+        let mut warn = Marker {
+            name: "w".to_string(),
+            rel_path: "a.ts".to_string(),
+            line: 1,
+            instruction: "Check it".to_string(),
+            files: Vec::new(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: HashMap::new(),
+        };
+        warn.options
+            .insert("severity".to_string(), "warning".to_string());
+        let results = vec![
+            WatcherResult::new("w", "a.ts:1", false, None),
+            WatcherResult::new("e", "b.ts:1", false, None),
+            WatcherResult::new("ok", "c.ts:1", true, None),
+        ];
+        assert_eq!(
+            count(&results, &[warn]),
+            baseline(&[("error", 1), ("warning", 1)])
+        );
+    }
+
+    #[test]
+    fn compare_splits_increases_and_decreases() {
+        let before = baseline(&[("error", 3), ("warning", 2)]);
+        let after = baseline(&[("error", 4), ("info", 0)]);
+        let (increased, decreased) = compare(&before, &after);
+        assert_eq!(
+            increased,
+            vec![Change {
+                severity: "error".to_string(),
+                before: 3,
+                after: 4
+            }]
+        );
+        assert_eq!(decreased.len(), 1);
+        assert_eq!(decreased[0].severity, "warning");
+        assert_eq!(decreased[0].after, 0);
+    }
+
+    #[test]
+    fn load_and_save_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(load(dir.path()).unwrap(), None);
+        let recorded = baseline(&[("error", 2)]);
+        save(dir.path(), &recorded).unwrap();
+        assert_eq!(load(dir.path()).unwrap(), Some(recorded));
+    }
+}
@@ -0,0 +1,485 @@
+use std::env;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use crate::http::Request;
+use crate::marker::Marker;
+
+/// Environment variable holding the token shared by the CLI and its workers.
+pub const TOKEN_VAR: &str = "WK_WORKER_TOKEN";
+
+/// Endpoint workers accept validation jobs on.
+pub const JOBS_PATH: &str = "/v1/jobs";
+
+/// Version of the job request. Bump on breaking changes.
+pub const JOB_VERSION: u32 = 1;
+
+/// Largest job body a worker accepts.
+const MAX_BODY: usize = 16 << 20;
+
+/// One AI validation, as sent to a worker.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobRequest {
+    pub version: u32,
+    pub name: String,
+    pub location: String,
+    pub model: String,
+    pub tools: String,
+    /// The complete watcher prompt, including the marker's slice of the diff.
+    pub prompt: String,
+    /// Commit the client validated against; a worker checked out elsewhere
+    /// refuses the job.
+    pub commit: Option<String>,
+}
+
+/// Remote workers that run AI watchers in place of the local `claude` CLI.
+#[derive(Debug, Clone)]
+pub struct Pool {
+    workers: Vec<String>,
+    token: String,
+    commit: Option<String>,
+}
+
+impl Pool {
+    /// A pool over `workers` (base URLs), authenticating with the token from
+    /// `WK_WORKER_TOKEN`.
+    pub fn from_env(workers: Vec<String>, commit: Option<String>) -> Result<Self, String> {
+        let token = env::var(TOKEN_VAR).unwrap_or_default();
+        if token.is_empty() {
+            return Err(format!(
+                "remote workers are configured but {TOKEN_VAR} is not set"
+            ));
+        }
+        Ok(Pool {
+            workers,
+            token,
+            commit,
+        })
+    }
+
+    pub fn commit(&self) -> Option<&str> {
+        self.commit.as_deref()
+    }
+
+    /// Send `job` to a worker, starting at `start` (round-robin) and moving on
+    /// to the next worker when one is unreachable or overloaded. Returns the
+    /// worker's verdict text.
+    pub fn dispatch(&self, job: &JobRequest, start: usize) -> Result<String, String> {
+        let body = serde_json::to_string(job).unwrap();
+        let mut errors = Vec::new();
+        for k in 0..self.workers.len() {
+            let worker = &self.workers[(start + k) % self.workers.len()];
+            let url = format!("{}{JOBS_PATH}", worker.trim_end_matches('/'));
+            match post(&url, &self.token, &body) {
+                Ok((200, text)) => return Ok(text),
+                // The job itself is at fault; another worker would say the same.
+                Ok((status @ 400..=499, text)) => {
+                    return Err(format!(
+                        "worker {worker} rejected the job ({status}): {}",
+                        error_message(&text)
+                    ));
+                }
+                Ok((status, text)) => {
+                    errors.push(format!("{worker}: {status} {}", error_message(&text)))
+                }
+                Err(e) => errors.push(format!("{worker}: {e}")),
+            }
+        }
+        Err(format!(
+            "no worker could run the job ({})",
+            errors.join("; ")
+        ))
+    }
+}
+
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v.get("error").and_then(|e| e.as_str()).map(str::to_string))
+        .unwrap_or_else(|| body.trim().to_string())
+}
+
+/// POST `body` to `url`. Returns the status code and response body.
+fn post(url: &str, token: &str, body: &str) -> Result<(u16, String), String> {
+    let response = Request::post(url)
+        .bearer(token)
+        .json(body.to_string())
+        .send()?;
+    Ok((response.status, response.text()))
+}
+
+/// Keep only the sections of a unified diff for files `marker` watches. An
+/// unscoped marker keeps the whole diff.
+pub fn slice_diff(diff: &str, marker: &Marker) -> String {
+    if marker.files.is_empty() {
+        return diff.to_string();
+    }
+    filter_diff(diff, |path| marker.watches(path))
+}
+
+/// Keep only the sections of a unified diff whose path satisfies `keep`.
+pub fn filter_diff(diff: &str, keep: impl Fn(&str) -> bool) -> String {
+    let mut out = String::new();
+    let mut keeping = false;
+    for line in diff.split_inclusive('\n') {
+        if let Some(header) = line.strip_prefix("diff --git ") {
+            keeping = header
+                .trim_end()
+                .rsplit_once(" b/")
+                .is_some_and(|(_, path)| keep(path));
+        }
+        if keeping {
+            out.push_str(line);
+        }
+    }
+    out
+}
+
+// ── Worker ────────────────────────────────────────────────────────────────────
+
+/// Runs a job and returns the verdict text (`{"is_valid": ...}`).
+pub type Runner = dyn Fn(&JobRequest) -> Result<String, String> + Send + Sync;
+
+/// What the worker knows about its own checkout.
+pub struct WorkerState {
+    pub token: String,
+    /// HEAD of the worker's checkout, compared against each job's `commit`.
+    pub commit: Option<String>,
+    pub max_jobs: usize,
+    /// Tools a job may give the agent (`--allow-tools`). A rule such as
+    /// `Read(//repo/src/**)` counts as its tool.
+    pub tools: Vec<String>,
+    /// Models a job may ask for (`--allow-models`).
+    pub models: Vec<String>,
+}
+
+impl WorkerState {
+    /// Why the worker refuses to run `job`, if it does: the client, not the
+    /// worker's operator, picks the tools and model.
+    fn refusal(&self, job: &JobRequest) -> Option<String> {
+        if !self.models.contains(&job.model) {
+            return Some(format!(
+                "model `{}` is not allowed on this worker (allowed: {})",
+                job.model,
+                self.models.join(", ")
+            ));
+        }
+        let denied: Vec<&str> = job
+            .tools
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .filter(|t| {
+                let tool = t.split_once('(').map_or(*t, |(name, _)| name);
+                !self.tools.iter().any(|allowed| allowed == tool)
+            })
+            .collect();
+        (!denied.is_empty()).then(|| {
+            format!(
+                "tools {} are not allowed on this worker (allowed: {})",
+                denied.join(", "),
+                self.tools.join(", ")
+            )
+        })
+    }
+}
+
+/// Compare tokens without returning early on the first difference.
+fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Serve the job API on `listener` until the process exits, running at most
+/// `max_jobs` jobs at a time.
+pub fn serve(listener: TcpListener, state: WorkerState, run: Arc<Runner>) -> io::Result<()> {
+    let state = Arc::new(state);
+    let slots = Arc::new((Mutex::new(state.max_jobs.max(1)), Condvar::new()));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let (state, slots, run) = (state.clone(), slots.clone(), run.clone());
+        thread::spawn(move || {
+            if let Err(e) = handle(stream, &state, &slots, run.as_ref()) {
+                eprintln!("\x1b[33m[WARNING] worker connection: {e}\x1b[0m");
+            }
+        });
+    }
+    Ok(())
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<HttpRequest> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut length = 0;
+    let mut authorization = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => length = value.parse().unwrap_or(0),
+                "authorization" => authorization = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "job too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(HttpRequest {
+        method,
+        path,
+        authorization,
+        body,
+    })
+}
+
+fn respond(mut stream: &TcpStream, status: u16, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+fn handle(
+    stream: TcpStream,
+    state: &WorkerState,
+    slots: &(Mutex<usize>, Condvar),
+    run: &Runner,
+) -> io::Result<()> {
+    let request = read_request(&stream)?;
+    if request.method != "POST" || request.path != JOBS_PATH {
+        return respond(&stream, 404, &error_body("not found"));
+    }
+    let authorized = request
+        .authorization
+        .as_deref()
+        .and_then(|a| a.strip_prefix("Bearer "))
+        .is_some_and(|t| token_matches(t, &state.token));
+    if !authorized {
+        return respond(&stream, 401, &error_body("invalid token"));
+    }
+    let job: JobRequest = match serde_json::from_slice(&request.body) {
+        Ok(job) => job,
+        Err(e) => return respond(&stream, 400, &error_body(&format!("invalid job: {e}"))),
+    };
+    if job.version != JOB_VERSION {
+        let message = format!(
+            "unsupported job version {} (expected {JOB_VERSION})",
+            job.version
+        );
+        return respond(&stream, 400, &error_body(&message));
+    }
+    if let (Some(wanted), Some(have)) = (&job.commit, &state.commit)
+        && wanted != have
+    {
+        let message = format!("worker is at {have}, job needs {wanted}");
+        return respond(&stream, 409, &error_body(&message));
+    }
+    if let Some(message) = state.refusal(&job) {
+        return respond(&stream, 403, &error_body(&message));
+    }
+
+    let (count, freed) = slots;
+    {
+        let mut free = count.lock().unwrap();
+        while *free == 0 {
+            free = freed.wait(free).unwrap();
+        }
+        *free -= 1;
+    }
+    eprintln!("[job] {} ({})", job.name, job.location);
+    let verdict = run(&job);
+    *count.lock().unwrap() += 1;
+    freed.notify_one();
+
+    match verdict {
+        Ok(text) => respond(&stream, 200, &text),
+        Err(e) => respond(&stream, 500, &error_body(&e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "diff --git a/src/a.ts b/src/a.ts\n--- a/src/a.ts\n+++ b/src/a.ts\n+a\n\
+                        diff --git a/src/b.py b/src/b.py\n--- a/src/b.py\n+++ b/src/b.py\n+b\n";
+
+    fn job(commit: Option<&str>) -> JobRequest {
+        JobRequest {
+            version: JOB_VERSION,
+            name: "w".to_string(),
+            location: "src/a.ts:1".to_string(),
+            model: "sonnet".to_string(),
+            tools: "Read".to_string(),
+            prompt: "check".to_string(),
+            commit: commit.map(str::to_string),
+        }
+    }
+
+    /// Start a worker on a free port and return its base URL.
+    fn start_worker(token: &str, commit: Option<&str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = WorkerState {
+            token: token.to_string(),
+            commit: commit.map(str::to_string),
+            max_jobs: 2,
+            tools: vec!["Read".to_string(), "Grep".to_string()],
+            models: vec!["sonnet".to_string()],
+        };
+        let run: Arc<Runner> = Arc::new(|job| {
+            Ok(format!(
+                r#"{{"is_valid": false, "reason": "{}"}}"#,
+                job.name
+            ))
+        });
+        thread::spawn(move || serve(listener, state, run));
+        url
+    }
+
+    fn pool(workers: Vec<String>, token: &str) -> Pool {
+        Pool {
+            workers,
+            token: token.to_string(),
+            commit: None,
+        }
+    }
+
+    fn marker(files: &[&str]) -> Marker {
+        Marker {
+            name: "m".to_string(),
+            rel_path: "src/m.ts".to_string(),
+            line: 1,
+            instruction: "Check".to_string(),
+            files: files.iter().map(|f| f.to_string()).collect(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: Default::default(),
+        }
+    }
+
+    #[test]
+    fn slice_diff_keeps_watched_files() {
+        let sliced = slice_diff(DIFF, &marker(&["src/b.py"]));
+        assert!(sliced.starts_with("diff --git a/src/b.py"));
+        assert!(!sliced.contains("src/a.ts"));
+        assert_eq!(slice_diff(DIFF, &marker(&["src/"])), DIFF);
+        assert_eq!(slice_diff(DIFF, &marker(&[])), DIFF);
+    }
+
+    #[test]
+    fn token_matches_exact_only() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secreT", "secret"));
+        assert!(!token_matches("secret2", "secret"));
+    }
+
+    #[test]
+    fn dispatch_roundtrip() {
+        let url = start_worker("t0k", None);
+        let verdict = pool(vec![url], "t0k").dispatch(&job(None), 0).unwrap();
+        assert_eq!(verdict, r#"{"is_valid": false, "reason": "w"}"#);
+    }
+
+    #[test]
+    fn dispatch_fails_over_to_next_worker() {
+        // Nothing listens on a port we just released.
+        let dead = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dead_url = format!("http://{}", dead.local_addr().unwrap());
+        drop(dead);
+        let url = start_worker("t0k", None);
+        let verdict = pool(vec![dead_url, url], "t0k")
+            .dispatch(&job(None), 0)
+            .unwrap();
+        assert!(verdict.contains("is_valid"));
+    }
+
+    #[test]
+    fn dispatch_rejected_with_wrong_token() {
+        let url = start_worker("t0k", None);
+        let err = pool(vec![url], "nope").dispatch(&job(None), 0).unwrap_err();
+        assert!(
+            err.contains("401") && err.contains("invalid token"),
+            "err was: {err}"
+        );
+    }
+
+    #[test]
+    fn dispatch_rejected_on_commit_mismatch() {
+        let url = start_worker("t0k", Some("aaa"));
+        let err = pool(vec![url], "t0k")
+            .dispatch(&job(Some("bbb")), 0)
+            .unwrap_err();
+        assert!(
+            err.contains("409") && err.contains("job needs bbb"),
+            "err was: {err}"
+        );
+    }
+
+    #[test]
+    fn dispatch_rejected_for_tools_or_model_the_worker_disallows() {
+        let url = start_worker("t0k", None);
+        let pool = pool(vec![url], "t0k");
+        let mut confined = job(None);
+        confined.tools = "Read(//repo/src/**),Grep".to_string();
+        assert!(pool.dispatch(&confined, 0).is_ok());
+
+        let mut bash = job(None);
+        bash.tools = "Read,Bash(rm:*),Write".to_string();
+        let err = pool.dispatch(&bash, 0).unwrap_err();
+        assert!(
+            err.contains("403") && err.contains("tools Bash(rm:*), Write are not allowed"),
+            "err was: {err}"
+        );
+        let mut opus = job(None);
+        opus.model = "opus".to_string();
+        let err = pool.dispatch(&opus, 0).unwrap_err();
+        assert!(
+            err.contains("model `opus` is not allowed"),
+            "err was: {err}"
+        );
+    }
+}
@@ -0,0 +1,662 @@
+use std::env;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::acks;
+use crate::cache;
+use crate::clock;
+use crate::http::Request;
+use crate::marker::{self, Marker};
+use crate::packs::sha256_hex;
+
+/// Environment variable holding the bearer token for an HTTP cache.
+pub const TOKEN_VAR: &str = "WK_CACHE_TOKEN";
+
+/// Environment variable with the cache URL, overriding `[remote_cache] url`.
+pub const URL_VAR: &str = "WK_CACHE_URL";
+
+/// Environment variable forcing read-only mode, e.g. set by CI for untrusted
+/// pull requests.
+pub const READONLY_VAR: &str = "WK_CACHE_READONLY";
+
+/// Environment variable holding the OAuth access token for a GCS cache.
+pub const GCS_TOKEN_VAR: &str = "GOOGLE_OAUTH_ACCESS_TOKEN";
+
+/// Version of a stored verdict. Bump on breaking changes.
+const ENTRY_VERSION: u32 = 1;
+
+/// `[remote_cache]` in `watcher-knight.toml`.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RemoteCacheConfig {
+    /// `https://host/prefix` (plain GET/PUT), `s3://bucket/prefix` or
+    /// `gs://bucket/prefix`.
+    pub url: String,
+    /// S3 API endpoint (default: AWS for `region`); set it for S3-compatible stores.
+    pub endpoint: Option<String>,
+    /// S3 region used for request signing.
+    pub region: Option<String>,
+    /// Verdicts older than this many days are ignored and may be replaced.
+    pub ttl_days: Option<u64>,
+}
+
+/// A verdict as stored in the shared cache.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct RemoteEntry {
+    pub version: u32,
+    pub is_valid: bool,
+    pub reason: Option<String>,
+    /// Unix seconds; missing in entries written before TTLs.
+    #[serde(default)]
+    pub stored_at: Option<i64>,
+}
+
+impl RemoteEntry {
+    pub fn new(is_valid: bool, reason: Option<String>) -> Self {
+        RemoteEntry {
+            version: ENTRY_VERSION,
+            is_valid,
+            reason,
+            stored_at: Some(clock::now()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Backend {
+    Http {
+        base: String,
+        token: Option<String>,
+    },
+    S3 {
+        /// `<endpoint>/<bucket>[/<prefix>]`, path-style.
+        base: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+        session_token: Option<String>,
+    },
+    Gcs {
+        /// `https://storage.googleapis.com/<bucket>[/<prefix>]` (XML API).
+        base: String,
+        token: String,
+    },
+}
+
+/// What a request to the store returned.
+struct Response {
+    status: u16,
+    body: String,
+    /// Version of the object (ETag, or the generation on GCS), for
+    /// conditional writes.
+    object_version: Option<String>,
+}
+
+/// A team-shared store of verdicts, keyed by marker definition and watched
+/// file contents so a verdict is only reused for identical inputs.
+#[derive(Debug)]
+pub struct RemoteCache {
+    backend: Backend,
+    /// Never upload verdicts (for untrusted contexts such as fork PRs).
+    pub readonly: bool,
+    /// Maximum age of a usable verdict, in seconds.
+    ttl: Option<i64>,
+}
+
+impl RemoteCache {
+    /// Build the cache described by `config`, taking credentials from `var`
+    /// (`WK_CACHE_TOKEN` for HTTP, the standard `AWS_*` variables for S3,
+    /// `GOOGLE_OAUTH_ACCESS_TOKEN` for GCS).
+    pub fn new(
+        config: &RemoteCacheConfig,
+        readonly: bool,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let var = |name: &str| var(name).filter(|v| !v.is_empty());
+        let url = config.url.trim_end_matches('/');
+        let backend = if let Some(location) = url.strip_prefix("s3://") {
+            let region = config
+                .region
+                .clone()
+                .or_else(|| var("AWS_REGION"))
+                .unwrap_or_else(|| "us-east-1".to_string());
+            let endpoint = config
+                .endpoint
+                .clone()
+                .unwrap_or_else(|| format!("https://s3.{region}.amazonaws.com"));
+            let (Some(access_key), Some(secret_key)) =
+                (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY"))
+            else {
+                return Err(format!(
+                    "remote cache `{}` needs AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+                    config.url
+                ));
+            };
+            Backend::S3 {
+                base: format!("{}/{location}", endpoint.trim_end_matches('/')),
+                region,
+                access_key,
+                secret_key,
+                session_token: var("AWS_SESSION_TOKEN"),
+            }
+        } else if let Some(location) = url.strip_prefix("gs://") {
+            let Some(token) = var(GCS_TOKEN_VAR) else {
+                return Err(format!(
+                    "remote cache `{}` needs {GCS_TOKEN_VAR} (e.g. from `gcloud auth \
+                     print-access-token`)",
+                    config.url
+                ));
+            };
+            Backend::Gcs {
+                base: format!("https://storage.googleapis.com/{location}"),
+                token,
+            }
+        } else if url.starts_with("https://") || url.starts_with("http://") {
+            Backend::Http {
+                base: url.to_string(),
+                token: var(TOKEN_VAR),
+            }
+        } else {
+            return Err(format!(
+                "unsupported remote cache `{}`: expected an http(s)://, s3:// or gs:// URL",
+                config.url
+            ));
+        };
+        Ok(RemoteCache {
+            backend,
+            readonly,
+            ttl: config.ttl_days.map(|days| days as i64 * 86_400),
+        })
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        match &self.backend {
+            Backend::Http { base, .. } | Backend::S3 { base, .. } | Backend::Gcs { base, .. } => {
+                format!("{base}/{key}.json")
+            }
+        }
+    }
+
+    /// Response header carrying the object version.
+    fn version_header(&self) -> &'static str {
+        match self.backend {
+            Backend::Gcs { .. } => "x-goog-generation",
+            _ => "etag",
+        }
+    }
+
+    /// Request header making a write succeed only if the object is still at
+    /// `version`, or does not exist yet when `None`.
+    fn precondition(&self, version: Option<&str>) -> String {
+        match (&self.backend, version) {
+            (Backend::Gcs { .. }, None) => "x-goog-if-generation-match: 0".to_string(),
+            (Backend::Gcs { .. }, Some(generation)) => {
+                format!("x-goog-if-generation-match: {generation}")
+            }
+            (_, None) => "If-None-Match: *".to_string(),
+            (_, Some(etag)) => format!("If-Match: {etag}"),
+        }
+    }
+
+    /// Whether `entry` is recent enough to use under the TTL.
+    fn is_fresh(&self, entry: &RemoteEntry, now: i64) -> bool {
+        self.ttl.is_none_or(|ttl| {
+            entry
+                .stored_at
+                .is_some_and(|at| now.saturating_sub(at) <= ttl)
+        })
+    }
+
+    fn parse_entry(&self, body: &str) -> Option<RemoteEntry> {
+        serde_json::from_str::<RemoteEntry>(body)
+            .ok()
+            .filter(|e| e.version == ENTRY_VERSION && self.is_fresh(e, clock::now()))
+    }
+
+    /// `request` carrying the store's credentials.
+    fn authorize(&self, request: Request) -> Request {
+        match &self.backend {
+            Backend::Http { token: None, .. } => request,
+            Backend::Http {
+                token: Some(token), ..
+            }
+            | Backend::Gcs { token, .. } => request.bearer(token),
+            Backend::S3 {
+                region,
+                access_key,
+                secret_key,
+                session_token,
+                ..
+            } => {
+                let request = request.aws_sigv4(
+                    &format!("aws:amz:{region}:s3"),
+                    &format!("{access_key}:{secret_key}"),
+                );
+                match session_token {
+                    Some(token) => request.header(&format!("x-amz-security-token: {token}")),
+                    None => request,
+                }
+            }
+        }
+    }
+
+    /// Request the object for `key`, uploading `upload` if set, with extra
+    /// request `headers`.
+    fn request(
+        &self,
+        key: &str,
+        upload: Option<&str>,
+        headers: &[String],
+    ) -> Result<Response, String> {
+        let url = self.object_url(key);
+        let mut request = match upload {
+            Some(body) => Request::new("PUT", &url).json(body.to_string()),
+            None => Request::get(&url),
+        };
+        request = self.authorize(request);
+        for header in headers {
+            request = request.header(header);
+        }
+        let response = request.send()?;
+        Ok(Response {
+            status: response.status,
+            object_version: response.header(self.version_header()).map(str::to_string),
+            body: response.text(),
+        })
+    }
+
+    /// Fetch the verdict stored under `key`. Misses, expired verdicts and
+    /// errors all return `None`; a cache must never break a run.
+    pub fn get(&self, key: &str) -> Option<RemoteEntry> {
+        match self.request(key, None, &[]) {
+            Ok(Response {
+                status: 200, body, ..
+            }) => self.parse_entry(&body),
+            _ => None,
+        }
+    }
+
+    /// Store a verdict under `key`. Does nothing for a read-only cache.
+    ///
+    /// Writes are conditional, so parallel CI shards never overwrite each
+    /// other: the first verdict stored for a key stands until it expires, and
+    /// an expired one is only replaced if nobody replaced it in between.
+    pub fn put(&self, key: &str, entry: &RemoteEntry) -> Result<(), String> {
+        if self.readonly {
+            return Ok(());
+        }
+        let body = serde_json::to_string(entry).unwrap();
+        let created = self.request(key, Some(&body), &[self.precondition(None)])?;
+        let response = match created.status {
+            412 => {
+                let current = self.request(key, None, &[])?;
+                let expired = current.status == 200 && self.parse_entry(&current.body).is_none();
+                match current.object_version.filter(|_| expired) {
+                    Some(version) => {
+                        self.request(key, Some(&body), &[self.precondition(Some(&version))])?
+                    }
+                    None => return Ok(()),
+                }
+            }
+            _ => created,
+        };
+        match response.status {
+            // 412: another writer got there first, which is just as good.
+            200..=299 | 412 => Ok(()),
+            status => Err(format!(
+                "upload failed ({status}): {}",
+                response.body.trim()
+            )),
+        }
+    }
+}
+
+/// Cache key for a scoped marker: its definition, the markers it references
+/// and the contents of its watched files. Unscoped markers and assertions are
+/// never cached.
+pub fn key_for(marker: &Marker, related: &[&Marker], root: &Path) -> Option<String> {
+    if !cache::is_cacheable(marker) {
+        return None;
+    }
+    let mut files = marker.watched_files(root);
+    files.extend(marker.context_files().map(str::to_string));
+    let key = acks::failure_fingerprint(marker, &files, None, |f| acks::read_worktree(root, f));
+    if related.is_empty() {
+        return Some(key);
+    }
+    let fingerprints: Vec<String> = related.iter().map(|m| marker::fingerprint(m)).collect();
+    Some(sha256_hex(format!("{key}\0{}", fingerprints.join("\0")).as_bytes())[..16].to_string())
+}
+
+/// The `[remote_cache]` to use: `WK_CACHE_URL` replaces the configured URL,
+/// or sets up a cache on its own, e.g. for one CI pipeline.
+pub fn effective_config(
+    config: Option<&RemoteCacheConfig>,
+    var: impl Fn(&str) -> Option<String>,
+) -> Option<RemoteCacheConfig> {
+    let url = var(URL_VAR).filter(|u| !u.is_empty());
+    match (config, url) {
+        (Some(config), Some(url)) => Some(RemoteCacheConfig {
+            url,
+            ..config.clone()
+        }),
+        (Some(config), None) => Some(config.clone()),
+        (None, Some(url)) => Some(RemoteCacheConfig {
+            url,
+            ..Default::default()
+        }),
+        (None, None) => None,
+    }
+}
+
+/// Whether read-only mode is forced from the environment ([`READONLY_VAR`]).
+pub fn readonly_from_env() -> bool {
+    env::var(READONLY_VAR).is_ok_and(|v| v == "1" || v == "true")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::Write;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let map: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| map.get(name).cloned()
+    }
+
+    fn config(url: &str) -> RemoteCacheConfig {
+        RemoteCacheConfig {
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn new_http_backend_with_token() {
+        let cache = RemoteCache::new(
+            &config("https://cache/wk/"),
+            false,
+            vars(&[(TOKEN_VAR, "t")]),
+        )
+        .unwrap();
+        assert_eq!(cache.object_url("abc"), "https://cache/wk/abc.json");
+        assert!(
+            cache
+                .authorize(Request::get("u"))
+                .config()
+                .contains("Bearer t")
+        );
+    }
+
+    #[test]
+    fn new_s3_backend_defaults_to_aws_endpoint() {
+        let cache = RemoteCache::new(
+            &config("s3://bucket/team"),
+            false,
+            vars(&[
+                ("AWS_ACCESS_KEY_ID", "AK"),
+                ("AWS_SECRET_ACCESS_KEY", "SK"),
+                ("AWS_REGION", "eu-west-1"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            cache.object_url("abc"),
+            "https://s3.eu-west-1.amazonaws.com/bucket/team/abc.json"
+        );
+        let request = cache.authorize(Request::get("u"));
+        let auth = request.config();
+        assert!(auth.contains("aws:amz:eu-west-1:s3"), "{auth}");
+        assert!(auth.contains("user = \"AK:SK\""), "{auth}");
+    }
+
+    #[test]
+    fn new_s3_without_credentials_is_error() {
+        let err = RemoteCache::new(&config("s3://bucket"), false, vars(&[])).unwrap_err();
+        assert!(err.contains("AWS_ACCESS_KEY_ID"), "err was: {err}");
+    }
+
+    #[test]
+    fn new_rejects_unknown_scheme() {
+        assert!(RemoteCache::new(&config("ftp://x"), false, vars(&[])).is_err());
+    }
+
+    #[test]
+    fn key_for_tracks_file_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.ts"), "one").unwrap();
+        let marker = Marker {
+            name: "w".to_string(),
+            rel_path: "a.ts".to_string(),
+            line: 1,
+            instruction: "check".to_string(),
+            files: vec!["a.ts".to_string()],
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: HashMap::new(),
+        };
+        let before = key_for(&marker, &[], dir.path()).unwrap();
+        fs::write(dir.path().join("a.ts"), "two").unwrap();
+        assert_ne!(before, key_for(&marker, &[], dir.path()).unwrap());
+
+        let unscoped = Marker {
+            files: Vec::new(),
+            ..marker
+        };
+        assert_eq!(key_for(&unscoped, &[], dir.path()), None);
+    }
+
+    /// Serve GET/PUT of objects from memory on a free port, requiring `token`.
+    /// Objects carry an ETag, and PUTs honour `If-None-Match: *` and
+    /// `If-Match`.
+    fn start_server(token: &'static str) -> String {
+        use std::io::{BufRead, BufReader, Read};
+        use std::net::TcpListener;
+        use std::sync::{Arc, Mutex};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/wk", listener.local_addr().unwrap());
+        let store: Arc<Mutex<HashMap<String, (u32, String)>>> = Arc::default();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let (method, path) = (
+                    parts.next().unwrap().to_string(),
+                    parts.next().unwrap().to_string(),
+                );
+                let (mut length, mut authorized) = (0, false);
+                let (mut if_none_match, mut if_match) = (false, None);
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    let lower = line.to_ascii_lowercase();
+                    if let Some(v) = lower.strip_prefix("content-length:") {
+                        length = v.trim().parse().unwrap();
+                    }
+                    authorized |= line.trim() == format!("Authorization: Bearer {token}");
+                    if_none_match |= line.trim() == "If-None-Match: *";
+                    if let Some(v) = lower.strip_prefix("if-match:") {
+                        if_match = Some(v.trim().to_string());
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let mut store = store.lock().unwrap();
+                let current = store.get(&path).map(|(etag, _)| format!("\"{etag}\""));
+                let (status, reply) = match (authorized, method.as_str()) {
+                    (false, _) => (401, String::new()),
+                    (true, "PUT")
+                        if (if_none_match && current.is_some())
+                            || if_match
+                                .as_ref()
+                                .is_some_and(|m| Some(m) != current.as_ref()) =>
+                    {
+                        (412, String::new())
+                    }
+                    (true, "PUT") => {
+                        let etag = store.get(&path).map_or(1, |(etag, _)| etag + 1);
+                        store.insert(path.clone(), (etag, String::from_utf8(body).unwrap()));
+                        (200, String::new())
+                    }
+                    (true, _) => match store.get(&path) {
+                        Some((_, data)) => (200, data.clone()),
+                        None => (404, String::new()),
+                    },
+                };
+                let etag = store
+                    .get(&path)
+                    .map(|(etag, _)| format!("ETag: \"{etag}\"\r\n"))
+                    .unwrap_or_default();
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} X\r\n{etag}Content-Length: {}\r\nConnection: close\r\n\r\n{reply}",
+                    reply.len()
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn put_then_get_roundtrip() {
+        let url = start_server("t0k");
+        let cache = RemoteCache::new(&config(&url), false, vars(&[(TOKEN_VAR, "t0k")])).unwrap();
+        assert_eq!(cache.get("k"), None);
+        let entry = RemoteEntry::new(false, Some("said \"no\"\nbecause".to_string()));
+        cache.put("k", &entry).unwrap();
+        assert_eq!(cache.get("k"), Some(entry));
+    }
+
+    #[test]
+    fn put_keeps_the_first_verdict_until_it_expires() {
+        let url = start_server("t0k");
+        let cache = RemoteCache::new(
+            &RemoteCacheConfig {
+                ttl_days: Some(7),
+                ..config(&url)
+            },
+            false,
+            vars(&[(TOKEN_VAR, "t0k")]),
+        )
+        .unwrap();
+        let first = RemoteEntry::new(true, None);
+        cache.put("k", &first).unwrap();
+        cache
+            .put(
+                "k",
+                &RemoteEntry::new(false, Some("other shard".to_string())),
+            )
+            .unwrap();
+        assert_eq!(cache.get("k"), Some(first));
+
+        let stale = RemoteEntry {
+            stored_at: Some(0),
+            ..RemoteEntry::new(true, None)
+        };
+        cache.put("old", &stale).unwrap();
+        assert_eq!(cache.get("old"), None);
+        let fresh = RemoteEntry::new(false, Some("re-validated".to_string()));
+        cache.put("old", &fresh).unwrap();
+        assert_eq!(cache.get("old"), Some(fresh));
+    }
+
+    #[test]
+    fn ttl_rejects_old_and_undated_entries() {
+        let cache = RemoteCache::new(
+            &RemoteCacheConfig {
+                ttl_days: Some(1),
+                ..config("https://cache/wk")
+            },
+            false,
+            vars(&[]),
+        )
+        .unwrap();
+        let at = |stored_at| RemoteEntry {
+            stored_at,
+            ..RemoteEntry::new(true, None)
+        };
+        assert!(cache.is_fresh(&at(Some(1_000)), 1_000 + 86_400));
+        assert!(!cache.is_fresh(&at(Some(1_000)), 1_001 + 86_400));
+        assert!(!cache.is_fresh(&at(None), 1_000));
+
+        let no_ttl = RemoteCache::new(&config("https://cache/wk"), false, vars(&[])).unwrap();
+        assert!(no_ttl.is_fresh(&at(None), 1_000));
+    }
+
+    #[test]
+    fn effective_config_prefers_env_url() {
+        let configured = RemoteCacheConfig {
+            ttl_days: Some(3),
+            ..config("s3://bucket")
+        };
+        let from_env = effective_config(Some(&configured), vars(&[(URL_VAR, "gs://b")])).unwrap();
+        assert_eq!(from_env.url, "gs://b");
+        assert_eq!(from_env.ttl_days, Some(3));
+        assert_eq!(
+            effective_config(Some(&configured), vars(&[])),
+            Some(configured)
+        );
+        assert_eq!(effective_config(None, vars(&[])), None);
+        assert_eq!(
+            effective_config(None, vars(&[(URL_VAR, "gs://b")]))
+                .unwrap()
+                .url,
+            "gs://b"
+        );
+    }
+
+    #[test]
+    fn new_gcs_backend_uses_oauth_token() {
+        let cache = RemoteCache::new(
+            &config("gs://bucket/team"),
+            false,
+            vars(&[(GCS_TOKEN_VAR, "ya29")]),
+        )
+        .unwrap();
+        assert_eq!(
+            cache.object_url("abc"),
+            "https://storage.googleapis.com/bucket/team/abc.json"
+        );
+        assert!(
+            cache
+                .authorize(Request::get("u"))
+                .config()
+                .contains("Bearer ya29")
+        );
+        assert_eq!(cache.precondition(None), "x-goog-if-generation-match: 0");
+        assert!(
+            RemoteCache::new(&config("gs://bucket"), false, vars(&[]))
+                .unwrap_err()
+                .contains(GCS_TOKEN_VAR)
+        );
+    }
+
+    #[test]
+    fn put_with_wrong_token_is_error() {
+        let url = start_server("t0k");
+        let cache = RemoteCache::new(&config(&url), false, vars(&[(TOKEN_VAR, "bad")])).unwrap();
+        let err = cache.put("k", &RemoteEntry::new(true, None)).unwrap_err();
+        assert!(err.contains("401"), "err was: {err}");
+    }
+
+    #[test]
+    fn put_is_noop_when_readonly() {
+        let cache = RemoteCache::new(&config("http://127.0.0.1:9/"), true, vars(&[])).unwrap();
+        assert!(cache.put("k", &RemoteEntry::new(true, None)).is_ok());
+    }
+}
@@ -0,0 +1,181 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::acks::{self, Acks};
+use crate::marker::{self, Marker};
+
+/// Whether `name` may be used as a watcher name.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// `contents` with every tag named `old` renamed to `new`, or `None` if no
+/// tag is named `old`. Only the name is touched; the rest of each tag keeps
+/// its layout.
+pub fn rename_in_source(contents: &str, rel_path: &str, old: &str, new: &str) -> Option<String> {
+    let (tags, _) = marker::extract_raw_tags(contents.lines(), rel_path);
+    let mut opening: Vec<usize> = tags
+        .iter()
+        .filter(|t| marker::split_tag(&t.content).is_ok_and(|p| p.name == old))
+        .map(|t| t.line - 1)
+        .collect();
+    if opening.is_empty() {
+        return None;
+    }
+    opening.dedup();
+    let mut out = String::with_capacity(contents.len());
+    for (i, line) in contents.split_inclusive('\n').enumerate() {
+        if !opening.contains(&i) {
+            out.push_str(line);
+            continue;
+        }
+        // A line may open several tags; only those named `old` change.
+        let mut rest = line;
+        while let Some((start, end)) = name_span(rest, old) {
+            out.push_str(&rest[..start]);
+            out.push_str(new);
+            rest = &rest[end..];
+        }
+        out.push_str(rest);
+    }
+    Some(out)
+}
+
+/// Byte range of the first watcher name `name` in a line opening tags.
+fn name_span(line: &str, name: &str) -> Option<(usize, usize)> {
+    let mut from = 0;
+    while let Some((offset, prefix)) = marker::find_tag_in_line(&line[from..]) {
+        let after = from + offset + prefix.len();
+        from = after;
+        let Some(rest) = line[after..].trim_start().strip_prefix(':') else {
+            continue;
+        };
+        let rest = rest.trim_start();
+        let start = line.len() - rest.len();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(rest.len());
+        if &rest[..len] == name {
+            return Some((start, start + len));
+        }
+    }
+    None
+}
+
+/// `marker` as it is after the rename.
+pub fn renamed(marker: &Marker, new: &str) -> Marker {
+    Marker {
+        name: new.to_string(),
+        ..marker.clone()
+    }
+}
+
+/// Move the acks of the watchers in `old` to `new`, returning how many moved.
+/// An ack that still matched its failure gets the fingerprint the renamed
+/// watcher has for the same failure, so it keeps applying; stale acks are
+/// carried over as they are.
+pub fn rename_acks(root: &Path, acks: &mut Acks, old: &[&Marker], new: &str) -> usize {
+    let mut moved = 0;
+    for ack in acks.acks.iter_mut().filter(|a| a.marker == old[0].name) {
+        let tree = acks::files_at_commit(root, &ack.commit);
+        let tree_id = acks::tree_at_commit(root, &ack.commit);
+        let reader = acks::CommitReader::new(root, &ack.commit);
+        let read = |f: &str| reader.read(f);
+        for before in old {
+            let files = before.watched_files_in(&tree);
+            let fingerprint =
+                |m: &Marker| acks::failure_fingerprint(m, &files, tree_id.as_deref(), read);
+            if fingerprint(before) == ack.fingerprint {
+                ack.fingerprint = fingerprint(&renamed(before, new));
+                break;
+            }
+        }
+        ack.marker = new.to_string();
+        moved += 1;
+    }
+    moved
+}
+
+/// Replace several files so that either all of them change or none do: every
+/// new version is written next to its file first, then moved into place.
+pub fn write_all(files: &[(PathBuf, String)]) -> Result<(), String> {
+    let staged: Vec<PathBuf> = files
+        .iter()
+        .map(|(path, _)| {
+            let mut name = path.file_name().unwrap_or_default().to_os_string();
+            name.push(".wk-rename");
+            path.with_file_name(name)
+        })
+        .collect();
+    for ((path, contents), tmp) in files.iter().zip(&staged) {
+        if let Err(e) = fs::write(tmp, contents) {
+            for tmp in &staged {
+                fs::remove_file(tmp).ok();
+            }
+            return Err(format!("cannot write {}: {e}", path.display()));
+        }
+    }
+    for ((path, _), tmp) in files.iter().zip(&staged) {
+        fs::rename(tmp, path).map_err(|e| format!("cannot replace {}: {e}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rename_in_source_touches_only_the_name() {
+        let src = "// <wk:old [./a.ts]\n// Keep old-style ids. />\n// <wk: older Check. />\n\
+                   # <wk :  old  Also. />\r\n";
+        assert_eq!(
+            rename_in_source(src, "test.ts", "old", "new-name").unwrap(),
+            "// <wk:new-name [./a.ts]\n// Keep old-style ids. />\n// <wk: older Check. />\n\
+             # <wk :  new-name  Also. />\r\n"
+        );
+        assert_eq!(rename_in_source(src, "test.ts", "missing", "x"), None);
+    }
+
+    #[test]
+    fn rename_in_source_picks_the_tag_on_a_shared_line() {
+        let src = "f(); // <wk: a First. /> <wk: old Second. />\n";
+        assert_eq!(
+            rename_in_source(src, "test.ts", "old", "new").unwrap(),
+            "f(); // <wk: a First. /> <wk: new Second. />\n"
+        );
+    }
+
+    #[test]
+    fn is_valid_name_matches_parser() {
+        assert!(is_valid_name("api_v2-check"));
+        assert!(!is_valid_name("has space"));
+        assert!(!is_valid_name(""));
+    }
+
+    #[test]
+    fn write_all_replaces_every_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.txt");
+        fs::write(&a, "old").unwrap();
+        let files = vec![
+            (a.clone(), "new".to_string()),
+            (dir.path().join("b.txt"), "b".to_string()),
+        ];
+        write_all(&files).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "new");
+        assert_eq!(fs::read_to_string(dir.path().join("b.txt")).unwrap(), "b");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        let bad = vec![
+            (a.clone(), "newer".to_string()),
+            (dir.path().join("missing/c.txt"), "c".to_string()),
+        ];
+        assert!(write_all(&bad).is_err());
+        assert_eq!(fs::read_to_string(&a).unwrap(), "new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,617 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::ci;
+use crate::claude::WatcherResult;
+use crate::history::RunInfo;
+use crate::html_report;
+use crate::marker::{self, Marker, Metadata};
+use crate::packs;
+use crate::stream::Telemetry;
+
+/// Version of the results JSON schema. Bump on breaking changes.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// How `run` prints its results on stdout.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum RunFormat {
+    /// Failure sections and a summary line.
+    Text,
+    /// One `file:line: [severity] name: reason` line per finding, for problem matchers.
+    Compact,
+}
+
+/// Which failures fail `run`, by the `severity` option of their watcher.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum FailOn {
+    /// Only `error` failures (the default severity).
+    Error,
+    /// `error` and `warning` (or `warn`) failures.
+    Warn,
+    /// Every failure, whatever its severity.
+    Any,
+}
+
+impl FailOn {
+    /// Whether a failure of `severity` fails the run.
+    pub fn blocks(self, severity: &str) -> bool {
+        match self {
+            FailOn::Error => severity == "error",
+            FailOn::Warn => matches!(severity, "error" | "warning" | "warn"),
+            FailOn::Any => true,
+        }
+    }
+}
+
+/// What watchers left INCONCLUSIVE by a deadline or token budget do to a run.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OnInconclusive {
+    /// They fail the run.
+    Fail,
+    /// They are listed as a warning; the run passes if nothing else failed.
+    Warn,
+    /// They only count in the summary.
+    Pass,
+}
+
+/// Kind of report file written by `run --report KIND=FILE`.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ReportKind {
+    /// The results JSON.
+    Json,
+    /// A standalone HTML page with a dashboard and drill-down.
+    Html,
+    /// Markdown for a pull request comment: status, summary and failures.
+    Markdown,
+}
+
+/// A `--report KIND=FILE` argument.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportSpec {
+    pub kind: ReportKind,
+    pub path: PathBuf,
+}
+
+impl FromStr for ReportSpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, String> {
+        let (kind, path) = spec
+            .split_once('=')
+            .filter(|(_, path)| !path.is_empty())
+            .ok_or_else(|| format!("expected KIND=FILE (e.g. html=report.html), got `{spec}`"))?;
+        Ok(ReportSpec {
+            kind: ReportKind::from_str(kind, true).map_err(|_| {
+                format!("unknown report kind `{kind}` (expected json, html or markdown)")
+            })?,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+const REPORT_DIR: &str = ".watcher_knight";
+const REPORT_FILE: &str = ".watcher_knight/results.json";
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub version: u32,
+    /// `passed` or `failed`; `cancelled` in the partial report of a run
+    /// terminated by a signal.
+    pub status: &'static str,
+    pub summary: Summary,
+    /// Short account of the failures written by `run --summarize`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub narrative: Option<String>,
+    /// What produced the report, for auditing it later.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    pub results: Vec<ResultEntry>,
+}
+
+/// Where and how a run happened: enough to audit a report artifact and to
+/// reproduce the run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Provenance {
+    pub tool: &'static str,
+    pub version: &'static str,
+    /// SHA-256 of `watcher-knight.toml`, `None` without one.
+    pub config_sha256: Option<String>,
+    /// SHA-256 over every loaded watcher's fingerprint (`markers_sha256`).
+    pub markers_sha256: String,
+    /// What gave the verdicts: `claude`, `remote` (workers), `export`,
+    /// `import` (air-gapped runs) or `offline`.
+    pub backend: &'static str,
+    /// The models of the selected watchers: `--model` and `model` options.
+    pub models: Vec<String>,
+    /// `HEAD` when the run started.
+    pub commit: Option<String>,
+    /// The commit diff mode compared against.
+    pub diff_base: Option<String>,
+    pub host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ci: Option<ci::Job>,
+}
+
+/// SHA-256 over the fingerprints of `markers`, whatever their order: equal
+/// when the watchers' definitions are.
+pub fn markers_sha256(markers: &[Marker]) -> String {
+    let mut fingerprints: Vec<String> = markers.iter().map(marker::fingerprint).collect();
+    fingerprints.sort();
+    packs::sha256_hex(fingerprints.join("\n").as_bytes())
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    /// Failures that do not fail the run (waived, acknowledged, ...).
+    pub suppressed: usize,
+    /// Watchers not run for reasons unrelated to the deadline or budget
+    /// (offline, exported, ...).
+    pub not_run: usize,
+    /// Watchers the run's deadline or token budget left unvalidated.
+    pub inconclusive: usize,
+    pub cached: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResultEntry {
+    pub name: String,
+    pub location: String,
+    /// `passed`, `failed`, `not_run`, `inconclusive`, or the lowercased
+    /// suppression label (e.g. `waived`).
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Why the failure is suppressed, or why the watcher was not run or is
+    /// inconclusive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub cached: bool,
+    /// The marker was edited since its cached verdict.
+    pub modified: bool,
+    /// The marker's description, rationale and link.
+    #[serde(flatten)]
+    pub metadata: Metadata,
+    /// The watcher's marker options (e.g. tags or severity set by the author).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub options: BTreeMap<String, String>,
+    /// Tool calls, turns and tokens of a fresh claude validation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub telemetry: Option<Telemetry>,
+}
+
+impl Summary {
+    pub fn of(results: &[WatcherResult]) -> Self {
+        Summary {
+            total: results.len(),
+            passed: results.iter().filter(|r| r.is_valid).count(),
+            failed: results.iter().filter(|r| r.is_failure()).count(),
+            suppressed: results.iter().filter(|r| r.suppression.is_some()).count(),
+            not_run: results
+                .iter()
+                .filter(|r| r.skipped.is_some() && !r.inconclusive)
+                .count(),
+            inconclusive: results.iter().filter(|r| r.inconclusive).count(),
+            cached: results.iter().filter(|r| r.cached).count(),
+        }
+    }
+}
+
+/// The marker a result belongs to.
+pub fn marker_for<'a>(r: &WatcherResult, markers: &'a [Marker]) -> Option<&'a Marker> {
+    markers
+        .iter()
+        .find(|m| m.name == r.name && format!("{}:{}", m.rel_path, m.line) == r.location)
+}
+
+pub fn entry(r: &WatcherResult, markers: &[Marker]) -> ResultEntry {
+    let (status, note) = if let Some(why) = &r.skipped {
+        let status = if r.inconclusive {
+            "inconclusive"
+        } else {
+            "not_run"
+        };
+        (status.to_string(), Some(why.clone()))
+    } else if r.is_valid {
+        ("passed".to_string(), None)
+    } else if let Some(s) = &r.suppression {
+        (s.label.to_lowercase(), Some(s.note.clone()))
+    } else {
+        ("failed".to_string(), None)
+    };
+    let marker = marker_for(r, markers);
+    ResultEntry {
+        name: r.name.clone(),
+        location: r.location.clone(),
+        status,
+        reason: r.reason.clone(),
+        note,
+        cached: r.cached,
+        modified: r.modified,
+        metadata: marker.map(|m| m.metadata.clone()).unwrap_or_default(),
+        options: marker
+            .map(|m| m.options.clone().into_iter().collect())
+            .unwrap_or_default(),
+        telemetry: r.telemetry.clone(),
+    }
+}
+
+pub fn build_report(results: &[WatcherResult], markers: &[Marker]) -> Report {
+    let summary = Summary::of(results);
+    let mut entries: Vec<ResultEntry> = results.iter().map(|r| entry(r, markers)).collect();
+    // Results arrive in completion order; sort for stable output.
+    entries.sort_by(|a, b| (&a.location, &a.name).cmp(&(&b.location, &b.name)));
+    Report {
+        version: SCHEMA_VERSION,
+        status: if summary.failed == 0 {
+            "passed"
+        } else {
+            "failed"
+        },
+        summary,
+        narrative: None,
+        provenance: None,
+        results: entries,
+    }
+}
+
+/// What a run adds to its results in the reports it writes.
+#[derive(Clone, Copy, Default)]
+pub struct Extras<'a> {
+    /// The summary of `run --summarize`.
+    pub narrative: Option<&'a str>,
+    pub provenance: Option<&'a Provenance>,
+}
+
+pub fn to_json(results: &[WatcherResult], markers: &[Marker], extras: Extras) -> String {
+    let mut report = build_report(results, markers);
+    report.narrative = extras.narrative.map(str::to_string);
+    report.provenance = extras.provenance.cloned();
+    serde_json::to_string_pretty(&report).unwrap()
+}
+
+/// A pull request comment: status and counts, the narrative, then one line
+/// per failure.
+pub fn to_markdown(
+    results: &[WatcherResult],
+    markers: &[Marker],
+    narrative: Option<&str>,
+) -> String {
+    let report = build_report(results, markers);
+    let s = &report.summary;
+    let mut out = format!(
+        "## watcher-knight: {}\n\n{} watchers: {} passed, {} failed, {} suppressed, {} not run",
+        report.status, s.total, s.passed, s.failed, s.suppressed, s.not_run
+    );
+    if s.inconclusive > 0 {
+        out.push_str(&format!(", {} inconclusive", s.inconclusive));
+    }
+    out.push('\n');
+    if let Some(narrative) = narrative {
+        out.push_str(&format!("\n{}\n", narrative.trim()));
+    }
+    let failures: Vec<&ResultEntry> = report
+        .results
+        .iter()
+        .filter(|e| e.status == "failed")
+        .collect();
+    if !failures.is_empty() {
+        out.push_str("\n### Failures\n\n");
+        for e in failures {
+            let reason = e
+                .reason
+                .as_deref()
+                .unwrap_or("marked invalid with no reason");
+            let reason = reason.split_whitespace().collect::<Vec<_>>().join(" ");
+            out.push_str(&format!("- **{}** (`{}`): {reason}", e.name, e.location));
+            if let Some(link) = &e.metadata.link {
+                out.push_str(&format!(" ([why]({link}))"));
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// One `file:line: [severity] name: reason` line per failing or suppressed
+/// watcher. Failures use the marker's `severity` option (default `error`);
+/// suppressed failures are reported as `info` with the suppression note.
+pub fn compact_lines(results: &[WatcherResult], markers: &[Marker]) -> Vec<String> {
+    let report = build_report(results, markers);
+    report
+        .results
+        .iter()
+        .filter(|e| !matches!(e.status.as_str(), "passed" | "not_run" | "inconclusive"))
+        .map(|e| {
+            let reason = e
+                .reason
+                .as_deref()
+                .unwrap_or("marked invalid with no reason");
+            // Problem matchers work line by line.
+            let mut message = reason.split_whitespace().collect::<Vec<_>>().join(" ");
+            let severity = if e.status == "failed" {
+                e.options.get("severity").map_or("error", String::as_str)
+            } else {
+                message.push_str(&format!(
+                    " ({}: {})",
+                    e.status,
+                    e.note.as_deref().unwrap_or_default()
+                ));
+                "info"
+            };
+            if let Some(link) = &e.metadata.link {
+                message.push_str(&format!(" (see {link})"));
+            }
+            format!("{}: [{severity}] {}: {message}", e.location, e.name)
+        })
+        .collect()
+}
+
+/// Write the results JSON to `.watcher_knight/results.json` (next to the cache)
+/// and return its absolute path.
+pub fn save_report(
+    results: &[WatcherResult],
+    markers: &[Marker],
+    extras: Extras,
+) -> Result<PathBuf, String> {
+    fs::create_dir_all(REPORT_DIR).map_err(|e| format!("cannot create {REPORT_DIR}: {e}"))?;
+    fs::write(REPORT_FILE, to_json(results, markers, extras))
+        .map_err(|e| format!("cannot write {REPORT_FILE}: {e}"))?;
+    fs::canonicalize(REPORT_FILE).map_err(|e| format!("cannot resolve {REPORT_FILE}: {e}"))
+}
+
+/// Write a `--report` file. `diff` is the validated diff in diff mode.
+/// Provenance goes into the JSON report only.
+pub fn write_report(
+    spec: &ReportSpec,
+    results: &[WatcherResult],
+    markers: &[Marker],
+    run: &RunInfo,
+    started_at: i64,
+    diff: Option<&str>,
+    extras: Extras,
+) -> Result<(), String> {
+    let narrative = extras.narrative;
+    let contents = match spec.kind {
+        ReportKind::Json => to_json(results, markers, extras),
+        ReportKind::Html => html_report::render(results, markers, run, started_at, diff, narrative),
+        ReportKind::Markdown => to_markdown(results, markers, narrative),
+    };
+    fs::write(&spec.path, contents)
+        .map_err(|e| format!("cannot write {}: {e}", spec.path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::claude::Suppression;
+
+    fn results() -> Vec<WatcherResult> {
+        let mut waived = WatcherResult::new("c", "b.ts:1", false, Some("bad".to_string()));
+        waived.suppression = Some(Suppression {
+            label: "WAIVED",
+            note: "waived until 2025-03-01: JIRA-1".to_string(),
+        });
+        vec![
+            WatcherResult::new("b", "b.ts:9", false, Some("broken".to_string())),
+            WatcherResult::new("a", "a.ts:1", true, None),
+            waived,
+            WatcherResult::skipped("d", "d.ts:1", "offline"),
+        ]
+    }
+
+    #[test]
+    fn fail_on_thresholds() {
+        assert!(FailOn::Error.blocks("error"));
+        assert!(!FailOn::Error.blocks("warning"));
+        assert!(FailOn::Warn.blocks("warn") && FailOn::Warn.blocks("warning"));
+        assert!(!FailOn::Warn.blocks("info"));
+        assert!(FailOn::Any.blocks("info"));
+    }
+
+    #[test]
+    fn build_report_summary_and_status() {
+        let report = build_report(&results(), &[]);
+        assert_eq!(report.version, SCHEMA_VERSION);
+        assert_eq!(report.status, "failed");
+        assert_eq!(report.summary.total, 4);
+        assert_eq!(report.summary.passed, 1);
+        assert_eq!(report.summary.failed, 1);
+        assert_eq!(report.summary.suppressed, 1);
+        assert_eq!(report.summary.not_run, 1);
+    }
+
+    #[test]
+    fn build_report_entries_sorted_with_statuses() {
+        let report = build_report(&results(), &[]);
+        let statuses: Vec<(&str, &str)> = report
+            .results
+            .iter()
+            .map(|e| (e.name.as_str(), e.status.as_str()))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("a", "passed"),
+                ("c", "waived"),
+                ("b", "failed"),
+                ("d", "not_run")
+            ]
+        );
+        assert_eq!(report.results[3].note.as_deref(), Some("offline"));
+    }
+
+    #[test]
+    fn inconclusive_results_count_apart_from_not_run() {
+        let mut results = results();
+        results.push(WatcherResult::inconclusive(
+            "e",
+            "e.ts:1",
+            "deadline reached",
+        ));
+        let report = build_report(&results, &[]);
+        assert_eq!(report.summary.not_run, 1);
+        assert_eq!(report.summary.inconclusive, 1);
+        let e = report.results.iter().find(|e| e.name == "e").unwrap();
+        assert_eq!(e.status, "inconclusive");
+        assert_eq!(e.note.as_deref(), Some("deadline reached"));
+        assert!(to_markdown(&results, &[], None).contains("1 not run, 1 inconclusive\n"));
+        assert!(
+            compact_lines(&results, &[])
+                .iter()
+                .all(|l| !l.contains(" e:"))
+        );
+    }
+
+    #[test]
+    fn markers_sha256_follows_definitions_not_order() {
+        let marker = |name: &str, instruction: &str| Marker {
+            name: name.to_string(),
+            rel_path: "a.ts".to_string(),
+            line: 1,
+            instruction: instruction.to_string(),
+            files: Vec::new(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: Default::default(),
+        };
+        let a = marker("a", "Check it.");
+        let b = marker("b", "Check that.");
+        assert_eq!(
+            markers_sha256(&[a.clone(), b.clone()]),
+            markers_sha256(&[b.clone(), a.clone()])
+        );
+        assert_ne!(
+            markers_sha256(&[a, b]),
+            markers_sha256(&[marker("a", "Check it."), marker("b", "Check this.")])
+        );
+    }
+
+    #[test]
+    fn to_json_omits_empty_fields() {
+        let json = to_json(
+            &[WatcherResult::new("a", "a.ts:1", true, None)],
+            &[],
+            Extras::default(),
+        );
+        let val: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(val["status"], "passed");
+        assert!(val["results"][0].get("reason").is_none());
+        assert!(val["results"][0].get("note").is_none());
+        assert!(val["results"][0].get("options").is_none());
+        assert!(val.get("narrative").is_none());
+        assert!(val.get("provenance").is_none());
+    }
+
+    #[test]
+    fn markdown_lists_narrative_and_failures() {
+        let narrative = "1 failure, caused by the rename of `users.email`.";
+        let markdown = to_markdown(&results(), &[], Some(narrative));
+        assert_eq!(
+            markdown,
+            "## watcher-knight: failed\n\n\
+             4 watchers: 1 passed, 1 failed, 1 suppressed, 1 not run\n\n\
+             1 failure, caused by the rename of `users.email`.\n\n\
+             ### Failures\n\n\
+             - **b** (`b.ts:9`): broken\n"
+        );
+        let json: serde_json::Value = serde_json::from_str(&to_json(
+            &results(),
+            &[],
+            Extras {
+                narrative: Some(narrative),
+                ..Extras::default()
+            },
+        ))
+        .unwrap();
+        assert_eq!(json["narrative"], narrative);
+    }
+
+    #[test]
+    fn compact_lines_one_per_finding() {
+        let mut results = results();
+        results[0].reason = Some("line one\n  line two".to_string());
+        let lines = compact_lines(&results, &[]);
+        assert_eq!(
+            lines,
+            vec![
+                "b.ts:1: [info] c: bad (waived: waived until 2025-03-01: JIRA-1)",
+                "b.ts:9: [error] b: line one line two",
+            ]
+        );
+    }
+
+    #[test]
+    fn report_spec_parses_kind_and_path() {
+        let spec: ReportSpec = "html=out/report.html".parse().unwrap();
+        assert_eq!(spec.kind, ReportKind::Html);
+        assert_eq!(spec.path, PathBuf::from("out/report.html"));
+        assert!("report.html".parse::<ReportSpec>().is_err());
+        assert!("html=".parse::<ReportSpec>().is_err());
+        assert!(
+            "pdf=report.pdf"
+                .parse::<ReportSpec>()
+                .unwrap_err()
+                .contains("pdf")
+        );
+    }
+
+    #[test]
+    fn build_report_includes_marker_options() {
+        let marker = Marker {
+            name: "b".to_string(),
+            rel_path: "b.ts".to_string(),
+            line: 9,
+            instruction: "Check it".to_string(),
+            files: vec![],
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: [("tags".to_string(), "security".to_string())].into(),
+        };
+        let report = build_report(&results(), &[marker]);
+        let b = report.results.iter().find(|e| e.name == "b").unwrap();
+        assert_eq!(b.options["tags"], "security");
+        let a = report.results.iter().find(|e| e.name == "a").unwrap();
+        assert!(a.options.is_empty());
+    }
+
+    #[test]
+    fn report_carries_marker_metadata() {
+        let marker = Marker {
+            name: "b".to_string(),
+            rel_path: "b.ts".to_string(),
+            line: 9,
+            instruction: "Check it".to_string(),
+            files: vec![],
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Metadata {
+                rationale: Some("Clients cache responses".to_string()),
+                link: Some("https://docs.example.com/adr/3".to_string()),
+                ..Default::default()
+            },
+            options: Default::default(),
+        };
+        let markers = [marker];
+        let json: serde_json::Value =
+            serde_json::from_str(&to_json(&results(), &markers, Extras::default())).unwrap();
+        let b = &json["results"][2];
+        assert_eq!(b["rationale"], "Clients cache responses");
+        assert_eq!(b["link"], "https://docs.example.com/adr/3");
+        assert!(b.get("description").is_none());
+        assert!(json["results"][0].get("link").is_none());
+        assert_eq!(
+            compact_lines(&results(), &markers)[1],
+            "b.ts:9: [error] b: broken (see https://docs.example.com/adr/3)"
+        );
+    }
+}
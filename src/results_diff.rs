@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::report;
+
+/// How `diff-results` prints the comparison.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum DiffFormat {
+    Text,
+    Json,
+}
+
+/// The parts of a results JSON file that are compared.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct Results {
+    pub version: u32,
+    pub results: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub location: String,
+    pub status: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// A watcher whose outcome differs between the two files.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Change {
+    pub name: String,
+    /// Location in the new file, or in the old one for removed watchers.
+    pub location: String,
+    /// Status in the old file (`None` for added watchers).
+    pub before: Option<String>,
+    /// Status in the new file (`None` for removed watchers).
+    pub after: Option<String>,
+    /// The failure reason, for regressions and failing added watchers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct ResultsDiff {
+    /// Watchers that passed before and fail now.
+    pub regressions: Vec<Change>,
+    /// Watchers that failed (or were suppressed) before and pass now.
+    pub fixes: Vec<Change>,
+    pub added: Vec<Change>,
+    pub removed: Vec<Change>,
+}
+
+/// Read a results JSON file written by `run --report json=FILE` or left in
+/// `.watcher_knight/results.json`.
+pub fn load(path: &Path) -> Result<Results, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    let results: Results = serde_json::from_str(&text)
+        .map_err(|e| format!("{} is not a results file: {e}", path.display()))?;
+    if results.version != report::SCHEMA_VERSION {
+        return Err(format!(
+            "{} has results schema version {}, expected {}",
+            path.display(),
+            results.version,
+            report::SCHEMA_VERSION
+        ));
+    }
+    Ok(results)
+}
+
+/// A failure that was reported, whether or not it failed the run.
+fn is_failing(status: &str) -> bool {
+    !matches!(status, "passed" | "not_run" | "inconclusive")
+}
+
+/// Watchers are matched by name, so a marker that moved between the two
+/// runs is still the same watcher. Names used more than once in a file are
+/// qualified with their location.
+fn keyed(results: &Results) -> BTreeMap<String, &Entry> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for e in &results.results {
+        *counts.entry(e.name.as_str()).or_default() += 1;
+    }
+    results
+        .results
+        .iter()
+        .map(|e| {
+            let key = if counts[e.name.as_str()] > 1 {
+                format!("{}@{}", e.name, e.location)
+            } else {
+                e.name.clone()
+            };
+            (key, e)
+        })
+        .collect()
+}
+
+pub fn diff(old: &Results, new: &Results) -> ResultsDiff {
+    let old = keyed(old);
+    let new = keyed(new);
+    let mut out = ResultsDiff::default();
+    for (key, after) in &new {
+        let Some(before) = old.get(key) else {
+            out.added.push(Change {
+                name: after.name.clone(),
+                location: after.location.clone(),
+                before: None,
+                after: Some(after.status.clone()),
+                reason: after.reason.clone().filter(|_| is_failing(&after.status)),
+            });
+            continue;
+        };
+        let change = || Change {
+            name: after.name.clone(),
+            location: after.location.clone(),
+            before: Some(before.status.clone()),
+            after: Some(after.status.clone()),
+            reason: None,
+        };
+        if before.status == "passed" && after.status == "failed" {
+            out.regressions.push(Change {
+                reason: after.reason.clone(),
+                ..change()
+            });
+        } else if is_failing(&before.status) && after.status == "passed" {
+            out.fixes.push(change());
+        }
+    }
+    for (key, before) in &old {
+        if !new.contains_key(key) {
+            out.removed.push(Change {
+                name: before.name.clone(),
+                location: before.location.clone(),
+                before: Some(before.status.clone()),
+                after: None,
+                reason: None,
+            });
+        }
+    }
+    out
+}
+
+pub fn render_json(diff: &ResultsDiff) -> String {
+    #[derive(Serialize)]
+    struct Output<'a> {
+        version: u32,
+        #[serde(flatten)]
+        diff: &'a ResultsDiff,
+    }
+    serde_json::to_string_pretty(&Output {
+        version: report::SCHEMA_VERSION,
+        diff,
+    })
+    .unwrap()
+}
+
+pub fn render_text(diff: &ResultsDiff) -> String {
+    let mut out = String::new();
+    let sections = [
+        ("REGRESSIONS", &diff.regressions),
+        ("FIXED", &diff.fixes),
+        ("ADDED", &diff.added),
+        ("REMOVED", &diff.removed),
+    ];
+    for (title, changes) in sections {
+        if changes.is_empty() {
+            continue;
+        }
+        writeln!(out, "==== {title} ====").unwrap();
+        for c in changes {
+            let status = match (&c.before, &c.after) {
+                (Some(before), Some(after)) => format!("{before} -> {after}"),
+                (None, Some(status)) | (Some(status), None) => status.clone(),
+                (None, None) => unreachable!(),
+            };
+            writeln!(out, "{} ({}): {status}", c.name, c.location).unwrap();
+            if let Some(reason) = &c.reason {
+                writeln!(out, "  {}", reason.trim()).unwrap();
+            }
+        }
+        writeln!(out).unwrap();
+    }
+    writeln!(
+        out,
+        "{} regressed, {} fixed, {} added, {} removed",
+        diff.regressions.len(),
+        diff.fixes.len(),
+        diff.added.len(),
+        diff.removed.len()
+    )
+    .unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(entries: &[(&str, &str, &str)]) -> Results {
+        Results {
+            version: report::SCHEMA_VERSION,
+            results: entries
+                .iter()
+                .map(|(name, location, status)| Entry {
+                    name: name.to_string(),
+                    location: location.to_string(),
+                    status: status.to_string(),
+                    reason: Some(format!("{name} reason")),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn diff_classifies_changes() {
+        let old = results(&[
+            ("api", "a.rs:1", "passed"),
+            ("docs", "b.rs:1", "waived"),
+            ("gone", "c.rs:1", "passed"),
+            ("same", "d.rs:1", "failed"),
+            ("skipped", "e.rs:1", "passed"),
+        ]);
+        let new = results(&[
+            ("api", "a.rs:9", "failed"),
+            ("docs", "b.rs:1", "passed"),
+            ("same", "d.rs:1", "failed"),
+            ("skipped", "e.rs:1", "not_run"),
+            ("fresh", "f.rs:1", "failed"),
+        ]);
+        let d = diff(&old, &new);
+        assert_eq!(
+            d.regressions,
+            vec![Change {
+                name: "api".to_string(),
+                location: "a.rs:9".to_string(),
+                before: Some("passed".to_string()),
+                after: Some("failed".to_string()),
+                reason: Some("api reason".to_string()),
+            }]
+        );
+        assert_eq!(d.fixes.len(), 1);
+        assert_eq!(d.fixes[0].name, "docs");
+        assert_eq!(d.added[0].name, "fresh");
+        assert_eq!(d.added[0].reason.as_deref(), Some("fresh reason"));
+        assert_eq!(d.removed[0].name, "gone");
+        assert!(render_text(&d).ends_with("1 regressed, 1 fixed, 1 added, 1 removed\n"));
+    }
+
+    #[test]
+    fn diff_qualifies_repeated_names_with_location() {
+        let old = results(&[("dup", "a.rs:1", "passed"), ("dup", "b.rs:1", "passed")]);
+        let new = results(&[("dup", "a.rs:1", "passed"), ("dup", "b.rs:1", "failed")]);
+        let d = diff(&old, &new);
+        assert_eq!(d.regressions.len(), 1);
+        assert_eq!(d.regressions[0].location, "b.rs:1");
+        assert!(d.added.is_empty() && d.removed.is_empty());
+    }
+
+    #[test]
+    fn load_rejects_other_schema_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.json");
+        fs::write(&path, r#"{"version": 99, "results": []}"#).unwrap();
+        let err = load(&path).unwrap_err();
+        assert!(err.contains("schema version 99"), "err was: {err}");
+        fs::write(&path, "not json").unwrap();
+        assert!(load(&path).unwrap_err().contains("not a results file"));
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::claude::WatcherResult;
+use crate::cli;
+use crate::packs;
+use crate::report::Provenance;
+
+const STATE_DIR: &str = ".watcher_knight";
+const STATE_FILE: &str = ".watcher_knight/run-state.jsonl";
+
+/// Version of the state file. Bump on breaking changes.
+pub const STATE_VERSION: u32 = 1;
+
+/// The first line of the state file: everything the verdicts after it
+/// depend on. A run only resumes a state with the same header.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub commit: Option<String>,
+    /// SHA-256 of the uncommitted changes ([`changes_sha256`]), so that
+    /// edits and new files count too.
+    pub changes_sha256: String,
+    pub diff_base: Option<String>,
+    pub config_sha256: Option<String>,
+    pub markers_sha256: String,
+    pub model: String,
+}
+
+impl Header {
+    pub fn new(root: &Path, provenance: &Provenance, model: &str) -> Self {
+        Header {
+            version: STATE_VERSION,
+            commit: provenance.commit.clone(),
+            changes_sha256: changes_sha256(root),
+            diff_base: provenance.diff_base.clone(),
+            config_sha256: provenance.config_sha256.clone(),
+            markers_sha256: provenance.markers_sha256.clone(),
+            model: model.to_string(),
+        }
+    }
+
+    /// Why a state with header `other` cannot be resumed by this run, if it
+    /// cannot.
+    fn mismatch(&self, other: &Header) -> Option<&'static str> {
+        if other.version != self.version {
+            Some("it was written by another version of watcher-knight")
+        } else if self.commit.is_none() {
+            Some("this is not a git checkout")
+        } else if other.commit != self.commit {
+            Some("it ran on another commit")
+        } else if other.changes_sha256 != self.changes_sha256 {
+            Some("uncommitted changes differ since")
+        } else if other.diff_base != self.diff_base {
+            Some("it compared against another diff base")
+        } else if other.config_sha256 != self.config_sha256 {
+            Some("watcher-knight.toml changed since")
+        } else if other.markers_sha256 != self.markers_sha256 {
+            Some("the watchers changed since")
+        } else if other.model != self.model {
+            Some("it used another --model")
+        } else {
+            None
+        }
+    }
+}
+
+/// SHA-256 of `git diff HEAD`, then of each untracked file that is not
+/// ignored, by path and contents. The state directory is left out: the state
+/// file changes as the run goes.
+fn changes_sha256(root: &Path) -> String {
+    let mut changes = cli::git_output(root, &["diff", "HEAD"]).unwrap_or_default();
+    let exclude = format!(":(exclude){STATE_DIR}");
+    let untracked = cli::git_output(
+        root,
+        &[
+            "ls-files",
+            "--others",
+            "--exclude-standard",
+            "-z",
+            "--",
+            ".",
+            &exclude,
+        ],
+    )
+    .unwrap_or_default();
+    for path in untracked.split('\0').filter(|p| !p.is_empty()) {
+        let contents = fs::read(root.join(path)).unwrap_or_default();
+        changes.push_str(&format!("\0{path}\0{}", packs::sha256_hex(&contents)));
+    }
+    packs::sha256_hex(changes.as_bytes())
+}
+
+/// A verdict the run obtained, one line of the state file after the header.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    name: String,
+    location: String,
+    is_valid: bool,
+    reason: Option<String>,
+    duration_ms: Option<u64>,
+}
+
+impl Entry {
+    fn from_result(result: &WatcherResult) -> Self {
+        Entry {
+            name: result.name.clone(),
+            location: result.location.clone(),
+            is_valid: result.is_valid,
+            reason: result.reason.clone(),
+            duration_ms: result.duration_ms,
+        }
+    }
+}
+
+/// Verdicts of an interrupted run, by watcher name and location.
+pub struct Resumed(HashMap<(String, String), Entry>);
+
+impl Resumed {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The verdict the interrupted run obtained for a watcher, as a result.
+    pub fn get(&self, name: &str, location: &str) -> Option<WatcherResult> {
+        let entry = self.0.get(&(name.to_string(), location.to_string()))?;
+        let mut result = WatcherResult::new(name, location, entry.is_valid, entry.reason.clone());
+        result.duration_ms = entry.duration_ms;
+        Some(result)
+    }
+}
+
+/// Parse a state file written for `header`. A line cut short by a crash is
+/// ignored.
+fn parse(data: &str, header: &Header) -> Result<Resumed, String> {
+    let mut lines = data.lines();
+    let stored: Header = lines
+        .next()
+        .and_then(|line| serde_json::from_str(line).ok())
+        .ok_or("its state file is unreadable")?;
+    if let Some(why) = header.mismatch(&stored) {
+        return Err(why.to_string());
+    }
+    let entries = lines
+        .filter_map(|line| serde_json::from_str::<Entry>(line).ok())
+        .map(|e| ((e.name.clone(), e.location.clone()), e))
+        .collect();
+    Ok(Resumed(entries))
+}
+
+/// The verdicts of the interrupted run at `root`, if it matches `header`.
+pub fn load(root: &Path, header: &Header) -> Result<Resumed, String> {
+    let data = fs::read_to_string(root.join(STATE_FILE))
+        .map_err(|_| "no interrupted run was found".to_string())?;
+    parse(&data, header)
+}
+
+static JOURNAL: Mutex<Option<File>> = Mutex::new(None);
+
+fn write_line(file: &mut File, line: &impl Serialize) -> std::io::Result<()> {
+    writeln!(file, "{}", serde_json::to_string(line).unwrap())?;
+    file.flush()
+}
+
+/// Start this run's state file with `header` and the `resumed` verdicts it
+/// carries over. Problems are warnings: the state only helps a later run.
+pub fn start(root: &Path, header: &Header, resumed: Option<&Resumed>) {
+    let opened = fs::create_dir_all(root.join(STATE_DIR))
+        .and_then(|_| File::create(root.join(STATE_FILE)))
+        .and_then(|mut file| {
+            write_line(&mut file, header)?;
+            for entry in resumed.iter().flat_map(|r| r.0.values()) {
+                write_line(&mut file, entry)?;
+            }
+            Ok(file)
+        });
+    match opened {
+        Ok(file) => *JOURNAL.lock().unwrap() = Some(file),
+        Err(e) => eprintln!("\x1b[33m[WARNING] cannot save the run state: {e}\x1b[0m"),
+    }
+}
+
+/// Add a result to the state file as it comes in. Only verdicts are kept;
+/// watchers that were skipped or errored run again on resume.
+pub fn record(result: &WatcherResult) {
+    if result.skipped.is_some() || result.errored {
+        return;
+    }
+    if let Some(file) = JOURNAL.lock().unwrap().as_mut() {
+        write_line(file, &Entry::from_result(result)).ok();
+    }
+}
+
+/// Remove the state file once every watcher has been validated.
+pub fn finish(root: &Path) {
+    if JOURNAL.lock().unwrap().take().is_some() {
+        fs::remove_file(root.join(STATE_FILE)).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(commit: &str) -> Header {
+        Header {
+            version: STATE_VERSION,
+            commit: Some(commit.to_string()),
+            changes_sha256: "c".to_string(),
+            diff_base: None,
+            config_sha256: None,
+            markers_sha256: "m".to_string(),
+            model: "sonnet".to_string(),
+        }
+    }
+
+    fn state(header: &Header, results: &[WatcherResult]) -> String {
+        let mut data = serde_json::to_string(header).unwrap() + "\n";
+        for result in results {
+            data += &serde_json::to_string(&Entry::from_result(result)).unwrap();
+            data += "\n";
+        }
+        data
+    }
+
+    #[test]
+    fn changes_include_untracked_files_but_not_the_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let output = std::process::Command::new("git")
+                .args(["-c", "user.name=a", "-c", "user.email=a@example.com"])
+                .args(args)
+                .current_dir(dir.path())
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+        };
+        git(&["init", "-q"]);
+        fs::write(dir.path().join(".gitignore"), "build/\n").unwrap();
+        fs::write(dir.path().join("a.ts"), "one").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-qm", "one"]);
+        let clean = changes_sha256(dir.path());
+
+        fs::create_dir(dir.path().join(STATE_DIR)).unwrap();
+        fs::write(dir.path().join(STATE_FILE), "{}").unwrap();
+        fs::create_dir(dir.path().join("build")).unwrap();
+        fs::write(dir.path().join("build/out.js"), "x").unwrap();
+        assert_eq!(changes_sha256(dir.path()), clean);
+
+        fs::write(dir.path().join("b.ts"), "new").unwrap();
+        let added = changes_sha256(dir.path());
+        assert_ne!(added, clean);
+        fs::write(dir.path().join("b.ts"), "edited").unwrap();
+        assert_ne!(changes_sha256(dir.path()), added);
+    }
+
+    #[test]
+    fn parse_reads_verdicts_up_to_a_torn_line() {
+        let mut data = state(
+            &header("abc"),
+            &[
+                WatcherResult::new("a", "a.ts:1", true, None),
+                WatcherResult::new("b", "b.ts:3", false, Some("bad".to_string())),
+            ],
+        );
+        data += "{\"name\": \"c\", \"loca";
+        let resumed = parse(&data, &header("abc")).unwrap();
+        assert_eq!(resumed.len(), 2);
+        let b = resumed.get("b", "b.ts:3").unwrap();
+        assert!(!b.is_valid);
+        assert_eq!(b.reason.as_deref(), Some("bad"));
+        assert!(resumed.get("c", "c.ts:1").is_none());
+    }
+
+    #[test]
+    fn parse_rejects_state_of_another_run() {
+        let data = state(&header("abc"), &[]);
+        assert_eq!(
+            parse(&data, &header("def")).err().as_deref(),
+            Some("it ran on another commit")
+        );
+        let mut edited = header("abc");
+        edited.changes_sha256 = "d".to_string();
+        assert_eq!(
+            parse(&data, &edited).err().as_deref(),
+            Some("uncommitted changes differ since")
+        );
+        let mut opus = header("abc");
+        opus.model = "opus".to_string();
+        assert!(parse(&data, &opus).is_err());
+        assert!(parse("", &header("abc")).is_err());
+    }
+}
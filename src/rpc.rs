@@ -0,0 +1,451 @@
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::chunk;
+use crate::claude;
+use crate::cli;
+use crate::config::Config;
+use crate::context::ContextLoader;
+use crate::hooks::Commands;
+use crate::inventory;
+use crate::marker::Marker;
+use crate::noise::NoiseFilter;
+use crate::plugins;
+use crate::redact::Redactor;
+use crate::report;
+use crate::vcs;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+/// Notification sent to subscribers after every `validateMarker`.
+pub const RESULT_NOTIFICATION: &str = "result";
+
+/// How messages are delimited on the wire. Replies use the framing of the
+/// last request received.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Framing {
+    /// One JSON message per line.
+    Lines,
+    /// LSP-style `Content-Length` headers.
+    Headers,
+}
+
+/// Read the next message, or `None` at end of input.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<(String, Framing)>> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let Some((name, value)) = trimmed.split_once(':') else {
+            return Ok(Some((trimmed.to_string(), Framing::Lines)));
+        };
+        if !name.eq_ignore_ascii_case("content-length") {
+            return Ok(Some((trimmed.to_string(), Framing::Lines)));
+        }
+        let len: usize = value.trim().parse().map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "invalid Content-Length header")
+        })?;
+        // Skip any further headers up to the blank line.
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+        }
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body)?;
+        return Ok(Some((
+            String::from_utf8_lossy(&body).to_string(),
+            Framing::Headers,
+        )));
+    }
+}
+
+fn encode(message: &Value, framing: Framing) -> String {
+    let body = message.to_string();
+    match framing {
+        Framing::Lines => format!("{body}\n"),
+        Framing::Headers => format!("Content-Length: {}\r\n\r\n{body}", body.len()),
+    }
+}
+
+struct Output<W: Write> {
+    writer: W,
+    framing: Framing,
+}
+
+struct Server<W: Write> {
+    root: PathBuf,
+    config: Config,
+    model: String,
+    policies: Vec<PathBuf>,
+    redactor: Redactor,
+    /// Markers from the last scan; scanned lazily and on `refresh`.
+    markers: Mutex<Option<Vec<Marker>>>,
+    subscribed: AtomicBool,
+    out: Mutex<Output<W>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ScanParams {
+    refresh: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ValidateParams {
+    name: String,
+    /// `file:line`, to pick one of several markers with the same name.
+    location: Option<String>,
+    /// Diff against this ref (`""` auto-detects origin/main or origin/master).
+    diff: Option<String>,
+    #[serde(default)]
+    offline: bool,
+}
+
+impl<W: Write> Server<W> {
+    fn send(&self, message: Value) {
+        let mut out = self.out.lock().unwrap();
+        let text = encode(&message, out.framing);
+        out.writer.write_all(text.as_bytes()).ok();
+        out.writer.flush().ok();
+    }
+
+    fn markers(&self, refresh: bool) -> Vec<Marker> {
+        let mut cached = self.markers.lock().unwrap();
+        if refresh || cached.is_none() {
+            let (markers, errors) =
+                cli::load_markers_with_errors(&self.root, &self.config, &self.policies);
+            for e in &errors {
+                eprintln!(
+                    "\x1b[33m[WARNING] {}:{}: {}\x1b[0m",
+                    e.file, e.line, e.message
+                );
+            }
+            *cached = Some(markers);
+        }
+        cached.clone().unwrap_or_default()
+    }
+
+    fn scan_markers(&self, params: Value) -> Result<Value, (i64, String)> {
+        let params: ScanParams = parse_params(params)?;
+        let markers = self.markers(params.refresh);
+        Ok(serde_json::to_value(inventory::build_inventory(&markers, true)).unwrap())
+    }
+
+    fn validate_marker(&self, params: Value) -> Result<Value, (i64, String)> {
+        let params: ValidateParams = parse_params(params)?;
+        let markers = self.markers(false);
+        let marker = markers
+            .iter()
+            .find(|m| {
+                m.name == params.name
+                    && params
+                        .location
+                        .as_ref()
+                        .is_none_or(|l| *l == format!("{}:{}", m.rel_path, m.line))
+            })
+            .ok_or_else(|| {
+                (
+                    INVALID_PARAMS,
+                    format!("no watcher named `{}` was found", params.name),
+                )
+            })?;
+
+        let diff = params.diff.map(|r| {
+            let vcs = vcs::for_root(&self.root);
+            let diff_ref = if r.is_empty() {
+                cli::resolve_diff_ref(&*vcs)
+            } else {
+                r
+            };
+            let (diff, _) = self.redactor.redact(&cli::repo_diff(&*vcs, &diff_ref));
+            // The patterns were checked when the config was loaded.
+            match NoiseFilter::new(&self.config.ignore_hunks_matching) {
+                Ok(noise) => noise.filter(&diff).diff,
+                Err(_) => diff,
+            }
+        });
+        let suppressions = cli::Suppressions::load(&self.root, std::slice::from_ref(marker))
+            .map_err(|e| (SERVER_ERROR, e))?;
+        let suppress = |r: &claude::WatcherResult| suppressions.suppress(r);
+        let ctx = claude::RunContext {
+            root: &self.root,
+            diff: diff.as_deref(),
+            model: &self.model,
+            checkers: &plugins::discover(
+                &self.config.checkers,
+                &self.root,
+                std::slice::from_ref(marker),
+            ),
+            offline: params.offline,
+            pool: None,
+            context: &ContextLoader::new(
+                &self.root,
+                &self.config.context_allowlist,
+                &self.redactor,
+            ),
+            markers: &markers,
+            backends: &self.config.backends,
+            confine: self.config.confine_tools,
+            chunk_files: self
+                .config
+                .chunk_files
+                .unwrap_or(chunk::DEFAULT_CHUNK_FILES),
+            commands: &Commands::new(&self.config.commands),
+            airgap: None,
+            audit: false,
+            resumed: None,
+            deadline: None,
+            untrusted: false,
+        };
+        let results = claude::run_watchers(std::slice::from_ref(marker), &ctx, 1, 0, &suppress);
+        let entry = report::entry(&results[0], std::slice::from_ref(marker));
+        let value = serde_json::to_value(entry).unwrap();
+        if self.subscribed.load(Ordering::SeqCst) {
+            self.send(json!({
+                "jsonrpc": "2.0",
+                "method": RESULT_NOTIFICATION,
+                "params": value,
+            }));
+        }
+        Ok(value)
+    }
+
+    fn dispatch(&self, method: &str, params: Value) -> Result<Value, (i64, String)> {
+        match method {
+            "scanMarkers" => self.scan_markers(params),
+            "validateMarker" => self.validate_marker(params),
+            "subscribeResults" => {
+                self.subscribed.store(true, Ordering::SeqCst);
+                Ok(json!({ "subscribed": true }))
+            }
+            "unsubscribeResults" => {
+                self.subscribed.store(false, Ordering::SeqCst);
+                Ok(json!({ "subscribed": false }))
+            }
+            "shutdown" => Ok(Value::Null),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{method}`"))),
+        }
+    }
+
+    fn reply(&self, id: Value, outcome: Result<Value, (i64, String)>) {
+        let message = match outcome {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        self.send(message);
+    }
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, (i64, String)> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, format!("invalid params: {e}")))
+}
+
+/// Serve requests from `input` until `shutdown`, `exit` or end of input.
+///
+/// `validateMarker` requests run on their own threads so a slow watcher does
+/// not block scans or other validations; every other method is answered in order.
+fn serve<W: Write + Send>(server: &Server<W>, input: &mut impl BufRead) {
+    thread::scope(|scope| {
+        loop {
+            let (text, framing) = match read_message(input) {
+                Ok(Some(message)) => message,
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    break;
+                }
+            };
+            server.out.lock().unwrap().framing = framing;
+
+            let request: Value = match serde_json::from_str(&text) {
+                Ok(v) => v,
+                Err(e) => {
+                    server.reply(Value::Null, Err((PARSE_ERROR, format!("parse error: {e}"))));
+                    continue;
+                }
+            };
+            let Some(method) = request.get("method").and_then(Value::as_str) else {
+                let id = request.get("id").cloned().unwrap_or(Value::Null);
+                server.reply(id, Err((INVALID_REQUEST, "missing method".to_string())));
+                continue;
+            };
+            let method = method.to_string();
+            let params = request.get("params").cloned().unwrap_or(Value::Null);
+            // Requests without an id are notifications and get no reply.
+            let id = request.get("id").cloned();
+
+            match method.as_str() {
+                "exit" => break,
+                "validateMarker" => {
+                    scope.spawn(move || {
+                        let outcome = server.dispatch(&method, params);
+                        if let Some(id) = id {
+                            server.reply(id, outcome);
+                        }
+                    });
+                }
+                _ => {
+                    let outcome = server.dispatch(&method, params);
+                    if let Some(id) = id {
+                        server.reply(id, outcome);
+                    }
+                    if method == "shutdown" {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+pub fn run(root: &Path, config: Config, model: &str, policies: &[PathBuf], redactor: Redactor) {
+    let server = Server {
+        root: root.to_path_buf(),
+        config,
+        model: model.to_string(),
+        policies: policies.to_vec(),
+        redactor,
+        markers: Mutex::new(None),
+        subscribed: AtomicBool::new(false),
+        out: Mutex::new(Output {
+            writer: io::stdout(),
+            framing: Framing::Lines,
+        }),
+    };
+    serve(&server, &mut io::stdin().lock());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn server(root: &Path) -> Server<Vec<u8>> {
+        Server {
+            root: root.to_path_buf(),
+            config: Config::default(),
+            model: "sonnet".to_string(),
+            policies: vec![],
+            redactor: Redactor::new(&[]).unwrap(),
+            markers: Mutex::new(None),
+            subscribed: AtomicBool::new(false),
+            out: Mutex::new(Output {
+                writer: Vec::new(),
+                framing: Framing::Lines,
+            }),
+        }
+    }
+
+    fn responses(server: Server<Vec<u8>>) -> Vec<Value> {
+        let out = server.out.into_inner().unwrap().writer;
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    // ── framing ───────────────────────────────────────────────────────────
+
+    #[test]
+    fn read_message_lines_and_headers() {
+        let body = r#"{"id":2}"#;
+        let input = format!(
+            "{{\"id\":1}}\n\nContent-Length: {}\r\nContent-Type: application/json\r\n\r\n{body}",
+            body.len()
+        );
+        let mut reader = io::Cursor::new(input);
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some((r#"{"id":1}"#.to_string(), Framing::Lines))
+        );
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some((body.to_string(), Framing::Headers))
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn encode_headers() {
+        let text = encode(&json!({"id": 1}), Framing::Headers);
+        assert_eq!(text, "Content-Length: 8\r\n\r\n{\"id\":1}");
+    }
+
+    // ── methods ───────────────────────────────────────────────────────────
+
+    #[test]
+    fn serve_scan_markers_and_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("app.ts"), "// <wk: api-check Keep it. />\n").unwrap();
+        let server = server(dir.path());
+        let input = concat!(
+            r#"{"jsonrpc":"2.0","id":1,"method":"scanMarkers"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"nope"}"#,
+            "\n",
+            "not json\n",
+            r#"{"jsonrpc":"2.0","id":3,"method":"shutdown"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":4,"method":"scanMarkers"}"#,
+            "\n",
+        );
+        serve(&server, &mut io::Cursor::new(input));
+        let replies = responses(server);
+        assert_eq!(replies.len(), 4, "replies: {replies:?}");
+        assert_eq!(replies[0]["id"], 1);
+        assert_eq!(replies[0]["result"]["markers"][0]["name"], "api-check");
+        assert_eq!(replies[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(replies[2]["error"]["code"], PARSE_ERROR);
+        assert_eq!(replies[3]["id"], 3);
+    }
+
+    #[test]
+    fn serve_validate_marker_notifies_subscribers() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("app.ts"), "// <wk: api-check Keep it. />\n").unwrap();
+        let server = server(dir.path());
+        let input = concat!(
+            r#"{"jsonrpc":"2.0","id":1,"method":"subscribeResults"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"validateMarker","params":{"name":"api-check","offline":true}}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":3,"method":"validateMarker","params":{"name":"missing"}}"#,
+            "\n",
+        );
+        serve(&server, &mut io::Cursor::new(input));
+        let replies = responses(server);
+        let by_id = |id: i64| replies.iter().find(|r| r["id"] == id).unwrap();
+        assert_eq!(by_id(1)["result"]["subscribed"], true);
+        assert_eq!(by_id(2)["result"]["status"], "not_run");
+        assert_eq!(by_id(3)["error"]["code"], INVALID_PARAMS);
+        let notification = replies
+            .iter()
+            .find(|r| r["method"] == RESULT_NOTIFICATION)
+            .unwrap();
+        assert_eq!(notification["params"]["name"], "api-check");
+    }
+}
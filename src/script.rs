@@ -0,0 +1,346 @@
+use std::cell::OnceCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use glob::Pattern;
+use regex::Regex;
+use rhai::{Engine, EvalAltResult, Scope};
+
+use crate::cli;
+use crate::deps;
+use crate::marker::{self, Marker};
+use crate::platform;
+
+/// Operations a `when` condition or an `assert` may perform before it is
+/// aborted.
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// What a `when` condition can see of the diff, exposed to scripts as `diff`.
+#[derive(Debug, Clone)]
+pub struct DiffInfo {
+    /// Changed files, relative to the repo root.
+    pub files: Vec<String>,
+}
+
+impl DiffInfo {
+    fn touches(&mut self, pattern: &str) -> Result<bool, Box<EvalAltResult>> {
+        let pattern = Pattern::new(pattern)
+            .map_err(|e| format!("invalid glob `{pattern}` in diff.touches: {e}"))?;
+        Ok(self.files.iter().any(|f| pattern.matches(f)))
+    }
+
+    fn files(&mut self) -> rhai::Array {
+        self.files.iter().cloned().map(Into::into).collect()
+    }
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| eprintln!("{text}"));
+    engine
+        .register_type_with_name::<DiffInfo>("Diff")
+        .register_fn("touches", DiffInfo::touches)
+        .register_get("files", DiffInfo::files);
+    engine
+}
+
+/// Marker option values cannot contain `"`, so conditions quote strings with
+/// `'...'`. Rewrite those as rhai string literals (rhai has no use for char
+/// literals in a condition).
+fn normalize_quotes(condition: &str) -> String {
+    let mut out = String::with_capacity(condition.len());
+    let mut chars = condition.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                out.push('"');
+                while let Some(inner) = chars.next() {
+                    match inner {
+                        '\\' => {
+                            out.push('\\');
+                            if let Some(escaped) = chars.next() {
+                                out.push(escaped);
+                            }
+                        }
+                        _ if inner == c => break,
+                        '"' => out.push_str("\\\""),
+                        _ => out.push(inner),
+                    }
+                }
+                out.push('"');
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// What an `assert` expression is evaluated against: the repo, with paths
+/// anchored like the marker's file entries.
+struct Repo {
+    root: PathBuf,
+    parent: PathBuf,
+    /// The marker's own file, which `references` does not count.
+    own_file: String,
+    files: OnceCell<Vec<String>>,
+}
+
+impl Repo {
+    fn path(&self, entry: &str) -> String {
+        platform::slash_path(&marker::anchor_entry(entry, &self.parent))
+    }
+
+    fn files(&self) -> &[String] {
+        self.files.get_or_init(|| cli::repo_files(&self.root))
+    }
+
+    fn read(&self, path: &str) -> Option<String> {
+        fs::read_to_string(self.root.join(path)).ok()
+    }
+
+    fn exists(&self, entry: &str) -> bool {
+        self.root.join(self.path(entry)).exists()
+    }
+
+    fn contains(&self, entry: &str, pattern: &str) -> Result<bool, Box<EvalAltResult>> {
+        let re = Regex::new(pattern)
+            .map_err(|e| format!("invalid regex `{pattern}` in contains: {e}"))?;
+        Ok(self
+            .read(&self.path(entry))
+            .is_some_and(|text| re.is_match(&text)))
+    }
+
+    fn count(&self, glob: &str) -> Result<i64, Box<EvalAltResult>> {
+        let pattern = Pattern::new(&self.path(glob))
+            .map_err(|e| format!("invalid glob `{glob}` in count: {e}"))?;
+        Ok(self.files().iter().filter(|f| pattern.matches(f)).count() as i64)
+    }
+
+    fn references(&self, symbol: &str) -> Result<bool, Box<EvalAltResult>> {
+        if symbol.trim().is_empty() {
+            return Err("references needs a symbol".into());
+        }
+        let re = Regex::new(&format!(r"\b{}\b", regex::escape(symbol))).unwrap();
+        Ok(self
+            .files()
+            .iter()
+            .filter(|f| **f != self.own_file)
+            .any(|f| self.read(f).is_some_and(|text| re.is_match(&text))))
+    }
+
+    /// The versions of dependency `name` locked by the repo's lockfiles.
+    fn locked(&self, name: &str) -> rhai::Array {
+        let mut versions: Vec<String> = self
+            .files()
+            .iter()
+            .filter(|f| deps::is_lockfile(f))
+            .flat_map(|f| deps::locked(&self.read(f).unwrap_or_default()))
+            .filter(|(n, _)| n == name)
+            .map(|(_, version)| version)
+            .collect();
+        versions.sort();
+        versions.dedup();
+        versions.into_iter().map(Into::into).collect()
+    }
+}
+
+/// [`engine`] with the `assert` functions bound to `repo`.
+fn assert_engine(repo: Repo) -> Engine {
+    let mut engine = engine();
+    let repo = Rc::new(repo);
+    let r = repo.clone();
+    engine.register_fn("exists", move |path: &str| r.exists(path));
+    let r = repo.clone();
+    engine.register_fn("contains", move |path: &str, re: &str| r.contains(path, re));
+    let r = repo.clone();
+    engine.register_fn("count", move |glob: &str| r.count(glob));
+    let r = repo.clone();
+    engine.register_fn("locked", move |name: &str| r.locked(name));
+    engine.register_fn("references", move |symbol: &str| repo.references(symbol));
+    engine
+}
+
+/// The `assert` expression declared on a marker, if any. Such a marker is
+/// checked by evaluating it, never by an AI backend.
+pub fn assert_for(marker: &Marker) -> Option<&str> {
+    marker.options.get("assert").map(String::as_str)
+}
+
+/// Evaluate the `assert` expression `expr` of `marker` against the files
+/// under `root`. It must produce a boolean.
+pub fn eval_assert(expr: &str, marker: &Marker, root: &Path) -> Result<bool, String> {
+    let parent = Path::new(&marker.rel_path)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .to_path_buf();
+    let repo = Repo {
+        root: root.to_path_buf(),
+        parent,
+        own_file: marker.rel_path.clone(),
+        files: OnceCell::new(),
+    };
+    assert_engine(repo)
+        .eval_expression::<bool>(&normalize_quotes(expr))
+        .map_err(|e| format!("`assert` expression `{expr}` failed: {e}"))
+}
+
+/// The `when` condition declared on a marker, if any.
+pub fn when_for(marker: &Marker) -> Option<&str> {
+    marker.options.get("when").map(String::as_str)
+}
+
+/// Check that `condition` parses, without evaluating it.
+pub fn check_when(condition: &str) -> Result<(), String> {
+    engine()
+        .compile_expression(normalize_quotes(condition))
+        .map(|_| ())
+        .map_err(|e| format!("invalid `when` condition `{condition}`: {e}"))
+}
+
+/// Evaluate `condition` against the changed files. It must produce a boolean.
+pub fn eval_when(condition: &str, diff: &DiffInfo) -> Result<bool, String> {
+    let mut scope = Scope::new();
+    scope.push_constant("diff", diff.clone());
+    engine()
+        .eval_expression_with_scope::<bool>(&mut scope, &normalize_quotes(condition))
+        .map_err(|e| format!("`when` condition `{condition}` failed: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(files: &[&str]) -> DiffInfo {
+        DiffInfo {
+            files: files.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn eval_when_touches() {
+        let cond = "diff.touches(\"src/db/**\") && !diff.touches(\"migrations/**\")";
+        assert!(eval_when(cond, &diff(&["src/db/users.rs"])).unwrap());
+        assert!(!eval_when(cond, &diff(&["src/db/users.rs", "migrations/001.sql"])).unwrap());
+        assert!(!eval_when(cond, &diff(&["README.md"])).unwrap());
+    }
+
+    #[test]
+    fn eval_when_single_quoted_strings() {
+        let cond = "diff.touches('src/db/**') && !diff.touches('migrations/**')";
+        assert!(eval_when(cond, &diff(&["src/db/users.rs"])).unwrap());
+        assert!(!eval_when(cond, &diff(&["migrations/001.sql"])).unwrap());
+    }
+
+    #[test]
+    fn normalize_quotes_rewrites_single_quotes() {
+        assert_eq!(normalize_quotes("f('a')"), "f(\"a\")");
+        assert_eq!(normalize_quotes("f('say \"hi\"')"), "f(\"say \\\"hi\\\"\")");
+        assert_eq!(normalize_quotes("f(\"it's\")"), "f(\"it's\")");
+    }
+
+    #[test]
+    fn eval_when_files_array() {
+        assert!(eval_when("diff.files.len() > 1", &diff(&["a", "b"])).unwrap());
+        assert!(eval_when("\"a\" in diff.files", &diff(&["a"])).unwrap());
+    }
+
+    #[test]
+    fn eval_when_non_bool_is_error() {
+        let err = eval_when("42", &diff(&[])).unwrap_err();
+        assert!(err.contains("failed"), "err was: {err}");
+    }
+
+    #[test]
+    fn eval_when_bad_glob_is_error() {
+        assert!(eval_when("diff.touches(\"[\")", &diff(&["a"])).is_err());
+    }
+
+    #[test]
+    fn eval_when_runaway_is_aborted() {
+        let cond = "{ let n = 0; loop { n += 1; } }";
+        assert!(eval_when(cond, &diff(&[])).is_err());
+    }
+
+    fn assert_marker(rel_path: &str) -> Marker {
+        Marker {
+            name: "a".to_string(),
+            rel_path: rel_path.to_string(),
+            line: 1,
+            instruction: "Check it".to_string(),
+            files: Vec::new(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: Default::default(),
+        }
+    }
+
+    #[test]
+    fn eval_assert_checks_the_repo() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("migrations")).unwrap();
+        fs::write(
+            root.join("src/app.ts"),
+            "// <wk: a />\nexport function parseUser() {}\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("src/api.ts"),
+            "import { parseUser } from './app';\n",
+        )
+        .unwrap();
+        fs::write(root.join("migrations/001.sql"), "").unwrap();
+        fs::write(root.join("migrations/002.sql"), "").unwrap();
+        let marker = assert_marker("src/app.ts");
+        let eval = |expr: &str| eval_assert(expr, &marker, root);
+
+        assert!(eval("exists('./api.ts') && !exists('./missing.ts')").unwrap());
+        assert!(eval("exists('/migrations/001.sql')").unwrap());
+        assert!(eval("contains('./api.ts', 'parseUser\\\\s*\\\\}')").unwrap());
+        assert!(!eval("contains('./missing.ts', 'x')").unwrap());
+        assert!(eval("count('/migrations/*.sql') == 2").unwrap());
+        assert!(eval("references('parseUser')").unwrap());
+        // Only mentions in other files count, and only whole words.
+        assert!(!eval("references('parseUserId')").unwrap());
+        fs::remove_file(root.join("src/api.ts")).unwrap();
+        assert!(!eval("references('parseUser')").unwrap());
+    }
+
+    #[test]
+    fn eval_assert_reads_locked_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.lock"),
+            "[[package]]\nname = \"tokio\"\nversion = \"1.38.0\"\n\n\
+             [[package]]\nname = \"rand\"\nversion = \"0.8.5\"\n\n\
+             [[package]]\nname = \"rand\"\nversion = \"0.9.0\"\n",
+        )
+        .unwrap();
+        let marker = assert_marker("Cargo.toml");
+        let eval = |expr: &str| eval_assert(expr, &marker, dir.path());
+        assert!(eval("locked('tokio').all(|v| v.starts_with('1.'))").unwrap());
+        assert!(eval("locked('rand').len() == 2").unwrap());
+        assert!(eval("locked('openssl').is_empty()").unwrap());
+    }
+
+    #[test]
+    fn eval_assert_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = assert_marker("app.ts");
+        let eval = |expr: &str| eval_assert(expr, &marker, dir.path());
+        assert!(eval("count('[')").is_err());
+        assert!(eval("contains('a', '(')").is_err());
+        assert!(eval("references('')").is_err());
+        assert!(eval("count('*')").unwrap_err().contains("failed"));
+    }
+
+    #[test]
+    fn check_when_syntax() {
+        assert!(check_when("diff.touches(\"a/**\")").is_ok());
+        assert!(check_when("diff.touches(").is_err());
+    }
+}
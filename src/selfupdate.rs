@@ -0,0 +1,295 @@
+use std::env;
+use std::io::Write;
+
+use minisign_verify::{PublicKey, Signature};
+use semver::Version;
+use serde::Deserialize;
+
+use crate::http::Request;
+use crate::packs::sha256_hex;
+
+/// Latest release of the upstream repository. `WK_RELEASE_FEED` points
+/// self-update at a mirror instead.
+pub const RELEASE_FEED: &str =
+    "https://api.github.com/repos/DylanMoss1/watcher-knight/releases/latest";
+pub const FEED_VAR: &str = "WK_RELEASE_FEED";
+
+/// Release asset listing `<sha256>  <asset name>` for every binary.
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+/// minisign signature of the checksums file.
+const SIGNATURE_ASSET: &str = "SHA256SUMS.minisig";
+
+/// minisign public key of the release signer, embedded by the release build.
+/// Builds without it (e.g. `cargo install`) can only verify checksums, which
+/// they only settle for with `--insecure`.
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("WK_RELEASE_PUBLIC_KEY");
+
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+impl Release {
+    pub fn version(&self) -> Result<Version, String> {
+        let tag = self.tag_name.trim_start_matches('v');
+        Version::parse(tag).map_err(|e| format!("release tag `{}`: {e}", self.tag_name))
+    }
+
+    fn asset(&self, name: &str) -> Result<&Asset, String> {
+        self.assets
+            .iter()
+            .find(|a| a.name == name)
+            .ok_or_else(|| format!("release {} has no asset `{name}`", self.tag_name))
+    }
+}
+
+/// Target triple of the running binary, as used in release asset names.
+pub fn target_triple() -> Option<String> {
+    let os = match env::consts::OS {
+        "linux" if cfg!(target_env = "musl") => "unknown-linux-musl",
+        "linux" => "unknown-linux-gnu",
+        "macos" => "apple-darwin",
+        "windows" => "pc-windows-msvc",
+        _ => return None,
+    };
+    Some(format!("{}-{os}", env::consts::ARCH))
+}
+
+/// Name of the release binary for `triple`, e.g. `watcher-knight-x86_64-unknown-linux-gnu`.
+pub fn asset_name(triple: &str) -> String {
+    let suffix = if triple.contains("windows") {
+        ".exe"
+    } else {
+        ""
+    };
+    format!("watcher-knight-{triple}{suffix}")
+}
+
+/// Download a URL and return the body.
+fn download(url: &str) -> Result<Vec<u8>, String> {
+    let response = Request::get(url)
+        .header("Accept: application/json")
+        .follow_redirects()
+        .send()
+        .map_err(|e| format!("failed to download `{url}`: {e}"))?
+        .success(&format!("downloading `{url}`"))?;
+    Ok(response.body)
+}
+
+pub fn fetch_release(feed: &str) -> Result<Release, String> {
+    let body = download(feed)?;
+    serde_json::from_slice(&body).map_err(|e| format!("invalid release feed `{feed}`: {e}"))
+}
+
+/// Look up `name` in a `sha256sum`-style checksums file.
+fn expected_checksum<'a>(sums: &'a str, name: &str) -> Option<&'a str> {
+    sums.lines().find_map(|line| {
+        let (hash, file) = line.split_once(char::is_whitespace)?;
+        // `sha256sum -b` marks binary files with `*`.
+        let file = file.trim_start().trim_start_matches('*');
+        (file == name).then_some(hash)
+    })
+}
+
+fn verify_signature(public_key: &str, data: &[u8], signature: &str) -> Result<(), String> {
+    let key = PublicKey::from_base64(public_key)
+        .map_err(|e| format!("invalid release public key: {e}"))?;
+    let signature =
+        Signature::decode(signature).map_err(|e| format!("invalid release signature: {e}"))?;
+    key.verify(data, &signature, false)
+        .map_err(|e| format!("release signature does not verify: {e}"))
+}
+
+/// Download the binary `asset` of `release` and check it against the signed
+/// checksums file. Without a public key only the checksum is checked.
+pub fn fetch_verified(
+    release: &Release,
+    asset: &str,
+    public_key: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    let sums = download(&release.asset(CHECKSUMS_ASSET)?.browser_download_url)?;
+    if let Some(key) = public_key {
+        let signature = download(&release.asset(SIGNATURE_ASSET)?.browser_download_url)?;
+        verify_signature(key, &sums, &String::from_utf8_lossy(&signature))?;
+    }
+    let sums = String::from_utf8_lossy(&sums);
+    let expected = expected_checksum(&sums, asset)
+        .ok_or_else(|| format!("{CHECKSUMS_ASSET} has no entry for `{asset}`"))?;
+
+    let binary = download(&release.asset(asset)?.browser_download_url)?;
+    let actual = sha256_hex(&binary);
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(format!(
+            "checksum mismatch for `{asset}`: expected {expected}, got {actual}"
+        ));
+    }
+    Ok(binary)
+}
+
+/// Check the release feed and, unless `check_only`, replace the running
+/// binary with the newest release. `insecure` accepts a download whose
+/// signature this build cannot check.
+pub fn self_update(check_only: bool, insecure: bool) -> Result<(), String> {
+    let feed = env::var(FEED_VAR).unwrap_or_else(|_| RELEASE_FEED.to_string());
+    let release = fetch_release(&feed)?;
+    let latest = release.version()?;
+    let current = Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
+    if latest <= current {
+        println!("watcher-knight {current} is up to date");
+        return Ok(());
+    }
+    if check_only {
+        println!("watcher-knight {latest} is available (installed: {current})");
+        return Ok(());
+    }
+
+    let triple = target_triple().ok_or_else(|| {
+        format!(
+            "no release binaries for {}/{}; install with `cargo install watcher-knight`",
+            env::consts::OS,
+            env::consts::ARCH
+        )
+    })?;
+    if RELEASE_PUBLIC_KEY.is_none() {
+        // The checksums come from the same place as the binary.
+        if !insecure {
+            return Err(
+                "this build has no release signing key, so the download cannot be verified; \
+                 install with `cargo install watcher-knight`, or pass --insecure to trust the \
+                 checksum alone"
+                    .to_string(),
+            );
+        }
+        eprintln!(
+            "\x1b[33m[WARNING] This build has no release signing key; verifying the checksum only\x1b[0m"
+        );
+    }
+    let binary = fetch_verified(&release, &asset_name(&triple), RELEASE_PUBLIC_KEY)?;
+
+    // Staged next to the binary under a fresh name only this process can
+    // write, and removed when dropped.
+    let exe = env::current_exe().map_err(|e| format!("cannot find own binary: {e}"))?;
+    let dir = exe.parent().unwrap_or(&exe);
+    let staged = tempfile::Builder::new()
+        .prefix(".watcher-knight-update-")
+        .tempfile_in(dir)
+        .and_then(|mut file| file.write_all(&binary).map(|()| file))
+        .map_err(|e| format!("cannot stage the new binary in `{}`: {e}", dir.display()))?;
+    self_replace::self_replace(staged.path())
+        .map_err(|e| format!("cannot replace the running binary: {e}"))?;
+    println!("Updated watcher-knight {current} -> {latest}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    // Test vector from minisign: the message `test` signed with this key.
+    const PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==";
+
+    fn file_url(path: &Path) -> String {
+        format!("file://{}", path.display())
+    }
+
+    /// A release whose assets are files in `dir`.
+    fn local_release(dir: &Path, assets: &[(&str, &[u8])]) -> Release {
+        Release {
+            tag_name: "v9.9.9".to_string(),
+            assets: assets
+                .iter()
+                .map(|(name, data)| {
+                    let path = dir.join(name);
+                    fs::write(&path, data).unwrap();
+                    Asset {
+                        name: name.to_string(),
+                        browser_download_url: file_url(&path),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn release_version_strips_v() {
+        let release: Release =
+            serde_json::from_str(r#"{"tag_name": "v1.2.3", "assets": []}"#).unwrap();
+        assert_eq!(release.version().unwrap(), Version::new(1, 2, 3));
+    }
+
+    #[test]
+    fn asset_name_adds_exe_on_windows() {
+        assert_eq!(
+            asset_name("x86_64-pc-windows-msvc"),
+            "watcher-knight-x86_64-pc-windows-msvc.exe"
+        );
+        assert_eq!(
+            asset_name("aarch64-apple-darwin"),
+            "watcher-knight-aarch64-apple-darwin"
+        );
+    }
+
+    #[test]
+    fn expected_checksum_finds_entry() {
+        let sums = "abc  watcher-knight-a\ndef *watcher-knight-b\n";
+        assert_eq!(expected_checksum(sums, "watcher-knight-b"), Some("def"));
+        assert_eq!(expected_checksum(sums, "watcher-knight-c"), None);
+    }
+
+    #[test]
+    fn verify_signature_accepts_valid_and_rejects_tampered() {
+        assert!(verify_signature(PUBLIC_KEY, b"test", SIGNATURE).is_ok());
+        assert!(verify_signature(PUBLIC_KEY, b"tampered", SIGNATURE).is_err());
+    }
+
+    #[test]
+    fn fetch_verified_checks_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary: &[u8] = b"new binary";
+        let sums = format!("{}  watcher-knight-t\n", sha256_hex(binary));
+        let release = local_release(
+            dir.path(),
+            &[
+                ("watcher-knight-t", binary),
+                (CHECKSUMS_ASSET, sums.as_bytes()),
+            ],
+        );
+        assert_eq!(
+            fetch_verified(&release, "watcher-knight-t", None).unwrap(),
+            binary
+        );
+
+        fs::write(dir.path().join("watcher-knight-t"), "swapped").unwrap();
+        let err = fetch_verified(&release, "watcher-knight-t", None).unwrap_err();
+        assert!(err.contains("checksum mismatch"), "err was: {err}");
+    }
+
+    #[test]
+    fn fetch_verified_requires_valid_signature_with_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let release = local_release(
+            dir.path(),
+            &[
+                ("watcher-knight-t", b"bin"),
+                (CHECKSUMS_ASSET, b"not what was signed"),
+                (SIGNATURE_ASSET, SIGNATURE.as_bytes()),
+            ],
+        );
+        let err = fetch_verified(&release, "watcher-knight-t", Some(PUBLIC_KEY)).unwrap_err();
+        assert!(err.contains("signature"), "err was: {err}");
+    }
+}
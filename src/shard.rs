@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::marker::Marker;
+
+/// Expected seconds of a watcher that declares no cost, nor any of its suites.
+pub const DEFAULT_COST_SECS: u64 = 30;
+
+/// `[suites.<name>]` in `watcher-knight.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SuiteConfig {
+    /// Expected time to validate one of the suite's watchers, e.g. `"2m"`.
+    pub cost: Option<String>,
+}
+
+/// A `--shard K/N` argument: the K-th of N shards, counted from 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, String> {
+        let invalid = || format!("expected K/N with 1 <= K <= N (e.g. 2/4), got `{spec}`");
+        let (index, count) = spec.split_once('/').ok_or_else(invalid)?;
+        let index: usize = index.trim().parse().map_err(|_| invalid())?;
+        let count: usize = count.trim().parse().map_err(|_| invalid())?;
+        if index == 0 || index > count {
+            return Err(invalid());
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// Parse a cost: seconds, optionally suffixed `s`, `m` or `h` (`90`, `45s`,
+/// `2m`, `1h`).
+pub fn parse_cost(cost: &str) -> Result<u64, String> {
+    let cost = cost.trim();
+    let (number, unit) = match cost.char_indices().last() {
+        Some((i, 's')) => (&cost[..i], 1),
+        Some((i, 'm')) => (&cost[..i], 60),
+        Some((i, 'h')) => (&cost[..i], 3600),
+        _ => (cost, 1),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .map(|n| n * unit)
+        .map_err(|_| format!("invalid cost `{cost}` (expected e.g. 45s, 2m or 1h)"))
+}
+
+/// Check every suite's cost.
+pub fn validate(suites: &HashMap<String, SuiteConfig>) -> Result<(), String> {
+    for (name, suite) in suites {
+        if let Some(cost) = &suite.cost {
+            parse_cost(cost).map_err(|e| format!("[suites.{name}]: {e}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Expected seconds to validate `marker`: its `cost` option, else the
+/// highest cost of its suites, else [`DEFAULT_COST_SECS`].
+pub fn cost(marker: &Marker, suites: &HashMap<String, SuiteConfig>) -> Result<u64, String> {
+    if let Some(cost) = marker.options.get("cost") {
+        return parse_cost(cost).map_err(|e| format!("{}:{}: {e}", marker.rel_path, marker.line));
+    }
+    let suite_costs = marker
+        .options
+        .get("suite")
+        .into_iter()
+        .flat_map(|names| names.split(','))
+        .filter_map(|name| suites.get(name.trim())?.cost.as_deref())
+        .map(|cost| parse_cost(cost).unwrap_or(DEFAULT_COST_SECS));
+    Ok(suite_costs.max().unwrap_or(DEFAULT_COST_SECS))
+}
+
+/// Split `markers` into `count` shards of about equal expected cost, and
+/// return each marker's shard (from 0). The costliest watchers are placed
+/// first, each on the shard with the least cost so far. Every shard of a CI
+/// job sees the same watchers, so they all compute the same split.
+pub fn assign(
+    markers: &[Marker],
+    count: usize,
+    suites: &HashMap<String, SuiteConfig>,
+) -> Result<Vec<usize>, String> {
+    let costs = markers
+        .iter()
+        .map(|m| cost(m, suites))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut order: Vec<usize> = (0..markers.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (ma, mb) = (&markers[a], &markers[b]);
+        costs[b]
+            .cmp(&costs[a])
+            .then_with(|| ma.rel_path.cmp(&mb.rel_path))
+            .then_with(|| ma.line.cmp(&mb.line))
+            .then_with(|| ma.name.cmp(&mb.name))
+    });
+    let mut loads = vec![0; count];
+    let mut shards = vec![0; markers.len()];
+    for i in order {
+        // The first of the least loaded shards, so that ties break the same
+        // way everywhere.
+        let shard = (0..count).min_by_key(|&s| loads[s]).unwrap_or(0);
+        loads[shard] += costs[i];
+        shards[i] = shard;
+    }
+    Ok(shards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker(name: &str, options: &[(&str, &str)]) -> Marker {
+        Marker {
+            name: name.to_string(),
+            rel_path: format!("{name}.ts"),
+            line: 1,
+            instruction: String::new(),
+            files: vec![],
+            exclude: vec![],
+            context: vec![],
+            metadata: Default::default(),
+            options: options
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn shard_parses_k_of_n() {
+        assert_eq!("2/4".parse(), Ok(Shard { index: 2, count: 4 }));
+        assert_eq!(Shard { index: 1, count: 1 }.to_string(), "1/1");
+        for bad in ["0/4", "5/4", "2", "a/4", "1/0"] {
+            assert!(bad.parse::<Shard>().is_err(), "{bad} parsed");
+        }
+    }
+
+    #[test]
+    fn cost_comes_from_the_marker_then_its_suites() {
+        let suites = HashMap::from([
+            (
+                "db".to_string(),
+                SuiteConfig {
+                    cost: Some("2m".to_string()),
+                },
+            ),
+            (
+                "api".to_string(),
+                SuiteConfig {
+                    cost: Some("45s".to_string()),
+                },
+            ),
+        ]);
+        assert_eq!(cost(&marker("a", &[("cost", "1h")]), &suites), Ok(3600));
+        assert_eq!(
+            cost(&marker("b", &[("suite", "api, db")]), &suites),
+            Ok(120)
+        );
+        assert_eq!(
+            cost(&marker("c", &[("suite", "ui")]), &suites),
+            Ok(DEFAULT_COST_SECS)
+        );
+        assert!(
+            cost(&marker("d", &[("cost", "soon")]), &suites)
+                .unwrap_err()
+                .starts_with("d.ts:1: invalid cost")
+        );
+        assert_eq!(parse_cost("90"), Ok(90));
+    }
+
+    #[test]
+    fn assign_balances_expected_cost_deterministically() {
+        let markers = [
+            marker("a", &[("cost", "60")]),
+            marker("b", &[("cost", "40")]),
+            marker("c", &[("cost", "30")]),
+            marker("d", &[("cost", "20")]),
+            marker("e", &[("cost", "10")]),
+        ];
+        let shards = assign(&markers, 2, &HashMap::new()).unwrap();
+        // a (60) | b, c (70) -> d joins a (80) -> e joins b, c (80).
+        assert_eq!(shards, [0, 1, 1, 0, 1]);
+        let mut reversed = markers.clone();
+        reversed.reverse();
+        let again = assign(&reversed, 2, &HashMap::new()).unwrap();
+        assert_eq!(again, [1, 0, 1, 1, 0]);
+        assert_eq!(
+            assign(&markers, 8, &HashMap::new()).unwrap(),
+            [0, 1, 2, 3, 4]
+        );
+    }
+}
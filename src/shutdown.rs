@@ -0,0 +1,196 @@
+use std::fs;
+use std::process;
+use std::sync::Mutex;
+
+use crate::children;
+use crate::claude::WatcherResult;
+use crate::history::RunInfo;
+use crate::html_report;
+use crate::marker::Marker;
+use crate::report::{self, Provenance, ReportKind, ReportSpec};
+
+/// The status of the reports of a terminated run.
+const CANCELLED: &str = "cancelled";
+
+/// Why the watchers without a result when a run is terminated are
+/// inconclusive.
+const TERMINATED: &str = "run terminated";
+
+/// What a terminated `run` still writes to its `--report` files.
+struct Partial {
+    reports: Vec<ReportSpec>,
+    mode: &'static str,
+    model: String,
+    branch: Option<String>,
+    started_at: i64,
+    provenance: Provenance,
+    /// Watchers the run is validating, once it knows them.
+    markers: Vec<Marker>,
+    /// Results printed so far.
+    results: Vec<WatcherResult>,
+}
+
+static PARTIAL: Mutex<Option<Partial>> = Mutex::new(None);
+
+/// On SIGTERM (a CI timeout or cancel) or SIGINT, kill the children still
+/// running (they are in process groups of their own, out of reach of the
+/// signal), write the reports of a [`watch`]ed run, and exit with 128 + the
+/// signal number. Installed once, for every command.
+pub fn handle_signals() {
+    #[cfg(unix)]
+    {
+        use signal_hook::consts::{SIGINT, SIGTERM};
+        use signal_hook::iterator::Signals;
+
+        let mut signals = match Signals::new([SIGTERM, SIGINT]) {
+            Ok(signals) => signals,
+            Err(e) => {
+                eprintln!("\x1b[33m[WARNING] cannot handle termination signals: {e}\x1b[0m");
+                return;
+            }
+        };
+        std::thread::spawn(move || {
+            if let Some(signal) = signals.forever().next() {
+                flush(signal);
+            }
+        });
+    }
+}
+
+/// Have a terminated run write `reports` with the results so far and the
+/// other watchers as inconclusive.
+pub fn watch(
+    reports: &[ReportSpec],
+    mode: &'static str,
+    model: &str,
+    branch: Option<String>,
+    started_at: i64,
+    provenance: &Provenance,
+) {
+    *PARTIAL.lock().unwrap() = Some(Partial {
+        reports: reports.to_vec(),
+        mode,
+        model: model.to_string(),
+        branch,
+        started_at,
+        provenance: provenance.clone(),
+        markers: Vec::new(),
+        results: Vec::new(),
+    });
+}
+
+/// The watchers this run validates (after `--only`, `--diff` and `when`
+/// filters).
+pub fn expect(markers: &[Marker]) {
+    if let Some(partial) = PARTIAL.lock().unwrap().as_mut() {
+        partial.markers = markers.to_vec();
+    }
+}
+
+/// A result as it is printed.
+pub fn record(result: &WatcherResult) {
+    if let Some(partial) = PARTIAL.lock().unwrap().as_mut() {
+        partial.results.push(result.clone());
+    }
+}
+
+/// Results so far, plus every expected watcher without one as inconclusive:
+/// it may have been running when the run was stopped.
+fn partial_results(partial: &Partial) -> Vec<WatcherResult> {
+    let mut results = partial.results.clone();
+    for m in &partial.markers {
+        let location = format!("{}:{}", m.rel_path, m.line);
+        if !results
+            .iter()
+            .any(|r| r.name == m.name && r.location == location)
+        {
+            results.push(WatcherResult::inconclusive(&m.name, &location, TERMINATED));
+        }
+    }
+    results
+}
+
+fn flush(signal: i32) -> ! {
+    // Whatever the main thread was doing, the command ends here.
+    children::kill_all();
+    let guard = PARTIAL.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(partial) = guard.as_ref() {
+        eprintln!(
+            "\n\x1b[33m[WARNING] Terminated by signal {signal}; writing partial results\x1b[0m"
+        );
+        let results = partial_results(partial);
+        let run = RunInfo {
+            mode: partial.mode,
+            model: &partial.model,
+            commit: partial.provenance.commit.clone(),
+            branch: partial.branch.clone(),
+            passed: false,
+        };
+        for spec in &partial.reports {
+            let contents = match spec.kind {
+                ReportKind::Json => {
+                    let mut report = report::build_report(&results, &partial.markers);
+                    report.status = CANCELLED;
+                    report.provenance = Some(partial.provenance.clone());
+                    serde_json::to_string_pretty(&report).unwrap()
+                }
+                ReportKind::Html => html_report::render(
+                    &results,
+                    &partial.markers,
+                    &run,
+                    partial.started_at,
+                    None,
+                    None,
+                ),
+                ReportKind::Markdown => report::to_markdown(&results, &partial.markers, None),
+            };
+            if let Err(e) = fs::write(&spec.path, contents) {
+                eprintln!("Error: cannot write {}: {e}", spec.path.display());
+            }
+        }
+    }
+    process::exit(128 + signal);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn marker(name: &str, line: usize) -> Marker {
+        Marker {
+            name: name.to_string(),
+            rel_path: "a.ts".to_string(),
+            line,
+            instruction: "Check it".to_string(),
+            files: Vec::new(),
+            exclude: Vec::new(),
+            context: Vec::new(),
+            metadata: Default::default(),
+            options: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn unfinished_watchers_are_inconclusive() {
+        let partial = Partial {
+            reports: Vec::new(),
+            mode: "cache",
+            model: "sonnet".to_string(),
+            branch: None,
+            started_at: 0,
+            provenance: Provenance::default(),
+            markers: vec![marker("done", 1), marker("pending", 5)],
+            results: vec![WatcherResult::new("done", "a.ts:1", true, None)],
+        };
+        let results = partial_results(&partial);
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_valid);
+        assert_eq!(results[1].name, "pending");
+        assert!(results[1].inconclusive);
+        assert_eq!(results[1].skipped.as_deref(), Some(TERMINATED));
+        let report = report::build_report(&results, &partial.markers);
+        assert_eq!(report.summary.not_run, 0);
+        assert_eq!(report.summary.inconclusive, 1);
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cli;
+use crate::clock;
+use crate::packs::sha256_hex;
+use crate::vcs::Vcs;
+
+const SNAPSHOT_DIR: &str = ".watcher_knight/snapshots";
+
+/// Version of the snapshot manifest format. Bump on breaking changes.
+const MANIFEST_VERSION: u32 = 1;
+
+/// The file list of one snapshot; contents are stored once per hash under
+/// `objects/`, shared between snapshots.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Unix seconds.
+    pub created_at: i64,
+    /// Repo-relative path to content hash.
+    pub files: BTreeMap<String, String>,
+}
+
+/// A snapshot as listed by `snapshot list`.
+#[derive(Debug, PartialEq)]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub created_at: i64,
+    pub files: usize,
+}
+
+fn snapshot_dir(root: &Path) -> PathBuf {
+    root.join(SNAPSHOT_DIR)
+}
+
+fn manifest_path(root: &Path, id: &str) -> PathBuf {
+    snapshot_dir(root).join(format!("{id}.json"))
+}
+
+fn object_path(root: &Path, hash: &str) -> PathBuf {
+    snapshot_dir(root).join("objects").join(hash)
+}
+
+/// Copy the files of `root` into the snapshot store and return the new
+/// snapshot's id. The id is derived from the contents, so snapshotting an
+/// unchanged tree again returns the same id.
+pub fn create(root: &Path) -> Result<String, String> {
+    let objects = snapshot_dir(root).join("objects");
+    fs::create_dir_all(&objects)
+        .map_err(|e| format!("cannot create {}: {e}", objects.display()))?;
+    let mut files = BTreeMap::new();
+    for path in cli::repo_files(root) {
+        // Tracked files deleted from the working tree have nothing to store.
+        let Ok(contents) = fs::read(root.join(&path)) else {
+            continue;
+        };
+        let hash = sha256_hex(&contents);
+        let object = objects.join(&hash);
+        if !object.exists() {
+            fs::write(&object, &contents)
+                .map_err(|e| format!("cannot write {}: {e}", object.display()))?;
+        }
+        files.insert(path, hash);
+    }
+    let id = sha256_hex(serde_json::to_string(&files).unwrap().as_bytes())[..12].to_string();
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        created_at: clock::now(),
+        files,
+    };
+    let path = manifest_path(root, &id);
+    fs::write(&path, serde_json::to_string_pretty(&manifest).unwrap())
+        .map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+    Ok(id)
+}
+
+pub fn load(root: &Path, id: &str) -> Result<Manifest, String> {
+    let path = manifest_path(root, id);
+    let text = fs::read_to_string(&path).map_err(|_| {
+        format!(
+            "no snapshot `{id}` in {SNAPSHOT_DIR}; create one with `watcher-knight snapshot create`"
+        )
+    })?;
+    let manifest: Manifest =
+        serde_json::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+    if manifest.version != MANIFEST_VERSION {
+        return Err(format!(
+            "{} has snapshot version {}, expected {MANIFEST_VERSION}",
+            path.display(),
+            manifest.version
+        ));
+    }
+    Ok(manifest)
+}
+
+/// Snapshots in the store, newest first.
+pub fn list(root: &Path) -> Result<Vec<SnapshotInfo>, String> {
+    let Ok(entries) = fs::read_dir(snapshot_dir(root)) else {
+        return Ok(Vec::new());
+    };
+    let mut snapshots = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(id) = name.strip_suffix(".json") else {
+            continue;
+        };
+        let manifest = load(root, id)?;
+        snapshots.push(SnapshotInfo {
+            id: id.to_string(),
+            created_at: manifest.created_at,
+            files: manifest.files.len(),
+        });
+    }
+    snapshots.sort_by(|a, b| (b.created_at, &b.id).cmp(&(a.created_at, &a.id)));
+    Ok(snapshots)
+}
+
+pub fn render_list(snapshots: &[SnapshotInfo]) -> String {
+    let mut out = String::new();
+    for s in snapshots {
+        let date = clock::date_from_unix_days(s.created_at.div_euclid(86_400));
+        writeln!(out, "{}  {date}  {} files", s.id, s.files).unwrap();
+    }
+    out
+}
+
+/// Diffs the working tree against a stored snapshot, for directories
+/// without version control. The "base" revisions are snapshot ids.
+pub struct Snapshots {
+    root: PathBuf,
+}
+
+impl Snapshots {
+    pub fn new(root: &Path) -> Self {
+        Snapshots {
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// Paths whose contents differ from snapshot `id`, with their stored
+    /// hash (`None` for files added since).
+    fn changes(&self, id: &str) -> Result<Vec<(String, Option<String>)>, String> {
+        let mut old = load(&self.root, id)?.files;
+        let mut changes = Vec::new();
+        for path in cli::repo_files(&self.root) {
+            let Ok(contents) = fs::read(self.root.join(&path)) else {
+                continue;
+            };
+            let before = old.remove(&path);
+            if before.as_deref() != Some(sha256_hex(&contents).as_str()) {
+                changes.push((path, before));
+            }
+        }
+        // Whatever is left was deleted.
+        changes.extend(old.into_iter().map(|(path, hash)| (path, Some(hash))));
+        changes.sort();
+        Ok(changes)
+    }
+}
+
+impl Vcs for Snapshots {
+    fn default_base(&self) -> Result<String, String> {
+        list(&self.root)?
+            .into_iter()
+            .next()
+            .map(|s| s.id)
+            .ok_or_else(|| {
+                "no snapshots yet; create one with `watcher-knight snapshot create`".to_string()
+            })
+    }
+
+    fn fork_point(&self, parent: &str) -> String {
+        parent.to_string()
+    }
+
+    /// A git-style diff synthesized with `diff -u` from the stored copies.
+    fn diff(&self, base: &str) -> Result<String, String> {
+        let mut out = String::new();
+        for (path, before) in self.changes(base)? {
+            let current = self.root.join(&path);
+            let old = match &before {
+                Some(hash) => (object_path(&self.root, hash), format!("a/{path}")),
+                None => (PathBuf::from("/dev/null"), "/dev/null".to_string()),
+            };
+            let new = if current.exists() {
+                (current, format!("b/{path}"))
+            } else {
+                (PathBuf::from("/dev/null"), "/dev/null".to_string())
+            };
+            let output = process::Command::new("diff")
+                .args(["-u", "--label", &old.1, "--label", &new.1])
+                .args([&old.0, &new.0])
+                .output()
+                .map_err(|e| format!("failed to run `diff`: {e}"))?;
+            // diff exits 1 when the files differ and 2 on trouble.
+            if output.status.code() == Some(2) {
+                return Err(format!(
+                    "`diff` failed on {path}: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            writeln!(out, "diff --git a/{path} b/{path}").unwrap();
+            out.push_str(&String::from_utf8_lossy(&output.stdout));
+        }
+        Ok(out)
+    }
+
+    fn changed_files(&self, base: &str) -> Result<Vec<String>, String> {
+        Ok(self
+            .changes(base)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect())
+    }
+
+    /// Every file in the tree is compared, so nothing goes unseen.
+    fn untracked_files(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn file_at(&self, base: &str, path: &str) -> Option<String> {
+        let hash = load(&self.root, base).ok()?.files.remove(path)?;
+        fs::read_to_string(object_path(&self.root, &hash)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_is_content_addressed() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "a\n").unwrap();
+        let id = create(dir.path()).unwrap();
+        assert_eq!(create(dir.path()).unwrap(), id);
+        fs::write(dir.path().join("a.txt"), "b\n").unwrap();
+        let newer = create(dir.path()).unwrap();
+        assert_ne!(newer, id);
+        let ids: Vec<String> = list(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&id) && ids.contains(&newer));
+        let err = load(dir.path(), "missing").unwrap_err();
+        assert!(err.contains("snapshot create"), "err was: {err}");
+    }
+
+    #[test]
+    fn diff_synthesizes_git_style_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src/kept.ts"), "one\ntwo\n").unwrap();
+        fs::write(root.join("src/gone.ts"), "bye\n").unwrap();
+        fs::write(root.join("same.ts"), "same\n").unwrap();
+        let id = create(root).unwrap();
+
+        fs::write(root.join("src/kept.ts"), "one\nthree\n").unwrap();
+        fs::remove_file(root.join("src/gone.ts")).unwrap();
+        fs::write(root.join("src/new.ts"), "hi\n").unwrap();
+
+        let snapshots = Snapshots::new(root);
+        assert_eq!(
+            snapshots.changed_files(&id).unwrap(),
+            vec!["src/gone.ts", "src/kept.ts", "src/new.ts"]
+        );
+        let diff = snapshots.diff(&id).unwrap();
+        assert!(
+            diff.contains(
+                "diff --git a/src/kept.ts b/src/kept.ts\n--- a/src/kept.ts\n+++ b/src/kept.ts\n"
+            ),
+            "{diff}"
+        );
+        assert!(diff.contains("-two\n+three\n"), "{diff}");
+        assert!(
+            diff.contains("--- a/src/gone.ts\n+++ /dev/null\n"),
+            "{diff}"
+        );
+        assert!(diff.contains("--- /dev/null\n+++ b/src/new.ts\n"), "{diff}");
+        assert!(!diff.contains("same.ts"));
+        assert_eq!(snapshots.default_base().unwrap(), id);
+        assert_eq!(
+            snapshots.file_at(&id, "src/gone.ts").as_deref(),
+            Some("bye\n")
+        );
+        assert_eq!(snapshots.file_at(&id, "src/new.ts"), None);
+    }
+}
@@ -0,0 +1,243 @@
+use std::fs;
+use std::path::Path;
+
+use crate::cli;
+
+/// Branch names treated as the trunk: a branch stacked directly on one of
+/// them is validated against `origin/main` as usual.
+const TRUNKS: [&str; 2] = ["main", "master"];
+
+/// The base a stacked branch or merge-queue entry should be diffed against.
+#[derive(Debug, PartialEq)]
+pub struct StackBase {
+    /// Ref or commit of the parent.
+    pub parent: String,
+    /// Where the parent was found, for the log line.
+    pub source: &'static str,
+}
+
+/// Find the parent of the current branch from merge-queue or stacking-tool
+/// metadata: the GitHub merge queue event, Graphite branch metadata,
+/// git-town's parent config, or a pull request's base branch in GitHub
+/// Actions, Bitbucket Pipelines or Azure Pipelines. `var` reads environment
+/// variables.
+pub fn detect(root: &Path, var: impl Fn(&str) -> Option<String>) -> Option<StackBase> {
+    if var("GITHUB_EVENT_NAME").as_deref() == Some("merge_group")
+        && let Some(sha) = var("GITHUB_EVENT_PATH").and_then(|p| merge_group_base(Path::new(&p)))
+    {
+        return Some(StackBase {
+            parent: sha,
+            source: "GitHub merge queue",
+        });
+    }
+    let branch = var("WK_BRANCH")
+        .filter(|b| !b.is_empty())
+        .or_else(|| cli::git_output(root, &["rev-parse", "--abbrev-ref", "HEAD"]))
+        .filter(|b| b != "HEAD");
+    let stacked = |parent: String, source| {
+        (!TRUNKS.contains(&parent.as_str()))
+            .then(|| existing_ref(root, &parent))
+            .flatten()
+            .map(|parent| StackBase { parent, source })
+    };
+    if let Some(branch) = &branch {
+        let graphite = cli::git_output(
+            root,
+            &["cat-file", "-p", &format!("refs/branch-metadata/{branch}")],
+        )
+        .and_then(|meta| serde_json::from_str::<serde_json::Value>(&meta).ok())
+        .and_then(|meta| meta["parentBranchName"].as_str().map(str::to_string));
+        if let Some(base) = graphite.and_then(|parent| stacked(parent, "Graphite")) {
+            return Some(base);
+        }
+        let git_town = cli::git_output(
+            root,
+            &[
+                "config",
+                "--get",
+                &format!("git-town-branch.{branch}.parent"),
+            ],
+        );
+        if let Some(base) = git_town.and_then(|parent| stacked(parent, "git-town")) {
+            return Some(base);
+        }
+    }
+    PR_BASE_VARS
+        .iter()
+        .find_map(|name| var(name).filter(|b| !b.is_empty()))
+        .map(|b| b.trim_start_matches("refs/heads/").to_string())
+        .and_then(|parent| stacked(parent, "pull request base"))
+}
+
+/// Where CI systems put a pull request build's target branch: GitHub
+/// Actions, Bitbucket Pipelines, Azure Pipelines (as `refs/heads/<branch>`).
+const PR_BASE_VARS: [&str; 3] = [
+    "GITHUB_BASE_REF",
+    "BITBUCKET_PR_DESTINATION_BRANCH",
+    "SYSTEM_PULLREQUEST_TARGETBRANCH",
+];
+
+/// `merge_group.base_sha` from a GitHub Actions event payload.
+fn merge_group_base(event: &Path) -> Option<String> {
+    let event: serde_json::Value = serde_json::from_str(&fs::read_to_string(event).ok()?).ok()?;
+    event["merge_group"]["base_sha"]
+        .as_str()
+        .map(str::to_string)
+}
+
+/// `branch` if it exists locally, else its `origin/` remote-tracking ref
+/// (CI checkouts rarely have local branches).
+fn existing_ref(root: &Path, branch: &str) -> Option<String> {
+    [branch.to_string(), format!("origin/{branch}")]
+        .into_iter()
+        .find(|r| cli::git_output(root, &["rev-parse", "--verify", "--quiet", r]).is_some())
+}
+
+/// The commit where the current branch forked from `parent`, so the diff
+/// holds only this layer's changes even if the parent moved on since.
+/// Falls back to `parent` itself when git cannot tell.
+pub fn fork_point(root: &Path, parent: &str) -> String {
+    cli::git_output(root, &["merge-base", "HEAD", parent]).unwrap_or_else(|| parent.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::process;
+
+    fn git(root: &Path, args: &[&str]) -> String {
+        let output = process::Command::new("git")
+            .args(["-c", "user.name=wk", "-c", "user.email=wk@example.com"])
+            .args(args)
+            .current_dir(root)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    /// A repo with `main` -> `feature-a` -> `feature-b` (checked out), one
+    /// commit each.
+    fn stacked_repo() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        git(root, &["init", "--quiet", "-b", "main"]);
+        for branch in ["main", "feature-a", "feature-b"] {
+            if branch != "main" {
+                git(root, &["checkout", "--quiet", "-b", branch]);
+            }
+            fs::write(root.join(format!("{branch}.txt")), branch).unwrap();
+            git(root, &["add", "."]);
+            git(root, &["commit", "--quiet", "-m", branch]);
+        }
+        dir
+    }
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn detect_reads_git_town_parent() {
+        let dir = stacked_repo();
+        assert_eq!(detect(dir.path(), env(&[])), None);
+        git(
+            dir.path(),
+            &["config", "git-town-branch.feature-b.parent", "feature-a"],
+        );
+        assert_eq!(
+            detect(dir.path(), env(&[])),
+            Some(StackBase {
+                parent: "feature-a".to_string(),
+                source: "git-town",
+            })
+        );
+        // Stacked directly on the trunk: nothing to detect.
+        git(
+            dir.path(),
+            &["config", "git-town-branch.feature-b.parent", "main"],
+        );
+        assert_eq!(detect(dir.path(), env(&[])), None);
+    }
+
+    #[test]
+    fn detect_reads_graphite_metadata() {
+        let dir = stacked_repo();
+        let blob = process::Command::new("git")
+            .args(["hash-object", "-w", "--stdin"])
+            .current_dir(dir.path())
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .spawn()
+            .and_then(|mut child| {
+                use std::io::Write;
+                child
+                    .stdin
+                    .take()
+                    .unwrap()
+                    .write_all(br#"{"parentBranchName":"feature-a"}"#)?;
+                child.wait_with_output()
+            })
+            .unwrap();
+        let blob = String::from_utf8_lossy(&blob.stdout).trim().to_string();
+        git(
+            dir.path(),
+            &["update-ref", "refs/branch-metadata/feature-b", &blob],
+        );
+        let base = detect(dir.path(), env(&[])).unwrap();
+        assert_eq!(base.source, "Graphite");
+        assert_eq!(
+            fork_point(dir.path(), &base.parent),
+            git(dir.path(), &["rev-parse", "feature-a"])
+        );
+    }
+
+    #[test]
+    fn detect_reads_ci_metadata() {
+        let dir = stacked_repo();
+        let base = detect(dir.path(), env(&[("GITHUB_BASE_REF", "feature-a")])).unwrap();
+        assert_eq!(base.source, "pull request base");
+        assert_eq!(
+            detect(dir.path(), env(&[("GITHUB_BASE_REF", "main")])),
+            None
+        );
+        let bitbucket = [("BITBUCKET_PR_DESTINATION_BRANCH", "feature-a")];
+        assert_eq!(
+            detect(dir.path(), env(&bitbucket)).unwrap().parent,
+            "feature-a"
+        );
+        let azure = [("SYSTEM_PULLREQUEST_TARGETBRANCH", "refs/heads/feature-a")];
+        assert_eq!(detect(dir.path(), env(&azure)).unwrap().parent, "feature-a");
+
+        let event = dir.path().join("event.json");
+        fs::write(&event, r#"{"merge_group": {"base_sha": "abc123"}}"#).unwrap();
+        let vars = [
+            ("GITHUB_EVENT_NAME", "merge_group"),
+            ("GITHUB_EVENT_PATH", event.to_str().unwrap()),
+        ];
+        assert_eq!(
+            detect(dir.path(), env(&vars)),
+            Some(StackBase {
+                parent: "abc123".to_string(),
+                source: "GitHub merge queue",
+            })
+        );
+    }
+
+    #[test]
+    fn fork_point_ignores_later_parent_commits() {
+        let dir = stacked_repo();
+        let fork = git(dir.path(), &["rev-parse", "feature-a"]);
+        git(dir.path(), &["checkout", "--quiet", "feature-a"]);
+        fs::write(dir.path().join("later.txt"), "later").unwrap();
+        git(dir.path(), &["add", "."]);
+        git(dir.path(), &["commit", "--quiet", "-m", "later"]);
+        git(dir.path(), &["checkout", "--quiet", "feature-b"]);
+        assert_eq!(fork_point(dir.path(), "feature-a"), fork);
+    }
+}
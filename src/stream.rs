@@ -0,0 +1,340 @@
+use std::ops::AddAssign;
+
+use serde::{Deserialize, Serialize};
+
+/// One line of `claude -p --output-format stream-json --verbose`.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    System {
+        session_id: Option<String>,
+    },
+    Assistant {
+        message: Message,
+    },
+    Result {
+        #[serde(default)]
+        is_error: bool,
+        result: Option<String>,
+        session_id: Option<String>,
+        #[serde(default)]
+        num_turns: u32,
+        #[serde(default)]
+        usage: Usage,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct Message {
+    #[serde(default)]
+    pub content: Vec<Content>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Content {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        name: String,
+        #[serde(default)]
+        input: serde_json::Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// The path a file tool call reads: `file_path` for `Read`, the searched
+/// directory for `Grep`, `Glob` and `LS` (`.`, the working directory, when
+/// omitted). `None` for other tools.
+pub fn tool_path(name: &str, input: &serde_json::Value) -> Option<String> {
+    let field = |key: &str| input.get(key).and_then(|v| v.as_str());
+    match name {
+        "Read" => field("file_path").map(str::to_string),
+        "Grep" | "Glob" | "LS" => Some(field("path").unwrap_or(".").to_string()),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+}
+
+/// What a watcher's agent did, as reported in progress lines and the
+/// results JSON.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Telemetry {
+    pub tool_calls: u32,
+    pub turns: u32,
+    /// Prompt tokens, including cache reads and writes.
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Paths the agent read with file tools, as it passed them (see
+    /// [`tool_path`]).
+    #[serde(skip)]
+    pub paths: Vec<String>,
+}
+
+impl AddAssign<&Telemetry> for Telemetry {
+    fn add_assign(&mut self, other: &Telemetry) {
+        self.tool_calls += other.tool_calls;
+        self.turns += other.turns;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.paths.extend(other.paths.iter().cloned());
+    }
+}
+
+impl Telemetry {
+    /// Input and output tokens.
+    pub fn tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    /// Short summary for progress lines, e.g. `4 tool calls, 3 turns, 12.3k tokens`.
+    pub fn summary(&self) -> String {
+        let tokens = format_tokens(self.tokens());
+        let plural = |n: u32| if n == 1 { "" } else { "s" };
+        format!(
+            "{} tool call{}, {} turn{}, {tokens} tokens",
+            self.tool_calls,
+            plural(self.tool_calls),
+            self.turns,
+            plural(self.turns)
+        )
+    }
+}
+
+/// A token count as `950`, `12.3k` or `1.2M`.
+pub fn format_tokens(tokens: u64) -> String {
+    match tokens {
+        0..1_000 => tokens.to_string(),
+        1_000..1_000_000 => format!("{:.1}k", tokens as f64 / 1_000.0),
+        _ => format!("{:.1}M", tokens as f64 / 1_000_000.0),
+    }
+}
+
+/// Claude's answer: the final text and, if it streamed events, what it took
+/// to get there.
+#[derive(Debug, PartialEq)]
+pub struct Reply {
+    pub text: String,
+    pub telemetry: Option<Telemetry>,
+    /// The verdict, when the agent interface returned it typed; `text` is
+    /// then only its reason.
+    #[cfg(feature = "agent-sdk")]
+    pub verdict: Option<crate::agent::Verdict>,
+}
+
+impl Reply {
+    /// A reply from a backend that reports no telemetry.
+    pub fn plain(text: String) -> Self {
+        Reply {
+            text,
+            telemetry: None,
+            #[cfg(feature = "agent-sdk")]
+            verdict: None,
+        }
+    }
+}
+
+/// Collects an event stream line by line.
+#[derive(Default)]
+pub struct Transcript {
+    pub session_id: Option<String>,
+    telemetry: Telemetry,
+    last_text: String,
+    result: Option<(bool, String)>,
+    /// Lines that are not events, from a claude without stream-json.
+    plain: Vec<String>,
+    saw_events: bool,
+}
+
+impl Transcript {
+    pub fn push(&mut self, line: &str) {
+        let Ok(event) = serde_json::from_str::<Event>(line) else {
+            self.plain.push(line.to_string());
+            return;
+        };
+        self.saw_events = true;
+        match event {
+            Event::System {
+                session_id: Some(id),
+            } => self.session_id = Some(id),
+            Event::Assistant { message } => {
+                for content in message.content {
+                    match content {
+                        Content::Text { text } => self.last_text = text,
+                        Content::ToolUse { name, input } => {
+                            self.telemetry.tool_calls += 1;
+                            self.telemetry.paths.extend(tool_path(&name, &input));
+                        }
+                        Content::Other => {}
+                    }
+                }
+            }
+            Event::Result {
+                is_error,
+                result,
+                session_id,
+                num_turns,
+                usage,
+            } => {
+                if session_id.is_some() {
+                    self.session_id = session_id;
+                }
+                self.telemetry.turns = num_turns;
+                self.telemetry.input_tokens = usage.input_tokens
+                    + usage.cache_creation_input_tokens
+                    + usage.cache_read_input_tokens;
+                self.telemetry.output_tokens = usage.output_tokens;
+                let text = result
+                    .filter(|t| !t.trim().is_empty())
+                    .unwrap_or_else(|| std::mem::take(&mut self.last_text));
+                self.result = Some((is_error, text));
+            }
+            _ => {}
+        }
+    }
+
+    /// The reply, once the process has exited successfully or not.
+    /// `detail` is the last line of stderr, for errors.
+    pub fn finish(
+        self,
+        success: bool,
+        status: &str,
+        detail: Option<&str>,
+    ) -> Result<Reply, String> {
+        let failed = |what: &str| match detail {
+            Some(detail) => Err(format!("process exited with {status}{what}: {detail}")),
+            None => Err(format!("process exited with {status}{what}")),
+        };
+        match self.result {
+            Some((true, text)) => Err(format!("claude reported an error: {}", text.trim())),
+            Some((false, text)) => Ok(Reply {
+                text: text.trim().to_string(),
+                telemetry: Some(self.telemetry),
+                #[cfg(feature = "agent-sdk")]
+                verdict: None,
+            }),
+            None if !success => failed(""),
+            None if self.saw_events => failed(" without a result"),
+            None => Ok(Reply::plain(self.plain.join("\n").trim().to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STREAM: [&str; 5] = [
+        r#"{"type":"system","subtype":"init","session_id":"s1","tools":["Read"]}"#,
+        r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Looking"},{"type":"tool_use","id":"t1","name":"Read","input":{"file_path":"/repo/src/app.ts"}}]}}"#,
+        r#"{"type":"user","message":{"content":[{"type":"tool_result","tool_use_id":"t1"}]}}"#,
+        r#"{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t2","name":"Grep","input":{"pattern":"x","path":"src"}}]}}"#,
+        r#"{"type":"result","subtype":"success","is_error":false,"result":"{\"is_valid\":true}","session_id":"s1","num_turns":3,"usage":{"input_tokens":1000,"cache_read_input_tokens":11000,"output_tokens":300}}"#,
+    ];
+
+    #[test]
+    fn parses_stream_events() {
+        let lines = [
+            r#"{"type":"system","subtype":"init","session_id":"s1","tools":["Read"]}"#,
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Looking"},{"type":"tool_use","id":"t","name":"Read","input":{}}]}}"#,
+            r#"{"type":"user","message":{"content":[{"type":"tool_result"}]}}"#,
+            r#"{"type":"result","subtype":"success","is_error":false,"result":"{\"is_valid\":true}","session_id":"s1","num_turns":2}"#,
+        ];
+        let events: Vec<Event> = lines
+            .iter()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(
+            events[0],
+            Event::System {
+                session_id: Some("s1".to_string())
+            }
+        );
+        assert_eq!(
+            events[1],
+            Event::Assistant {
+                message: Message {
+                    content: vec![
+                        Content::Text {
+                            text: "Looking".to_string()
+                        },
+                        Content::ToolUse {
+                            name: "Read".to_string(),
+                            input: serde_json::json!({}),
+                        }
+                    ]
+                }
+            }
+        );
+        assert_eq!(events[2], Event::Other);
+        let Event::Result { num_turns, .. } = &events[3] else {
+            panic!("expected a result");
+        };
+        assert_eq!(*num_turns, 2);
+    }
+
+    #[test]
+    fn transcript_collects_result_and_telemetry() {
+        let mut transcript = Transcript::default();
+        for line in STREAM {
+            transcript.push(line);
+        }
+        assert_eq!(transcript.session_id.as_deref(), Some("s1"));
+        let reply = transcript.finish(true, "exit status: 0", None).unwrap();
+        assert_eq!(reply.text, "{\"is_valid\":true}");
+        let telemetry = reply.telemetry.unwrap();
+        assert_eq!(
+            telemetry,
+            Telemetry {
+                tool_calls: 2,
+                turns: 3,
+                input_tokens: 12_000,
+                output_tokens: 300,
+                paths: vec!["/repo/src/app.ts".to_string(), "src".to_string()],
+            }
+        );
+        assert_eq!(telemetry.summary(), "2 tool calls, 3 turns, 12.3k tokens");
+    }
+
+    #[test]
+    fn tool_paths_default_to_the_working_directory() {
+        let input = serde_json::json!({"pattern": "TODO"});
+        assert_eq!(tool_path("Grep", &input).as_deref(), Some("."));
+        assert_eq!(tool_path("Read", &input), None);
+        assert_eq!(tool_path("WebFetch", &input), None);
+    }
+
+    #[test]
+    fn transcript_errors_and_plain_output() {
+        let mut transcript = Transcript::default();
+        transcript.push(r#"{"type":"result","is_error":true,"result":"Rate limit reached"}"#);
+        let err = transcript.finish(true, "exit status: 0", None).unwrap_err();
+        assert!(err.contains("Rate limit"), "{err}");
+
+        let mut transcript = Transcript::default();
+        transcript.push(STREAM[0]);
+        let err = transcript
+            .finish(false, "exit status: 1", Some("boom"))
+            .unwrap_err();
+        assert_eq!(err, "process exited with exit status: 1: boom");
+
+        // A claude without stream-json prints the answer as it is.
+        let mut transcript = Transcript::default();
+        transcript.push("{\"is_valid\": false}");
+        let reply = transcript.finish(true, "exit status: 0", None).unwrap();
+        assert_eq!(reply.text, "{\"is_valid\": false}");
+        assert_eq!(reply.telemetry, None);
+    }
+}
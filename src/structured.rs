@@ -0,0 +1,399 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_yaml::Value;
+
+use crate::marker::{self, ParseError, RawTag};
+
+/// Key declaring watchers in JSON and YAML files, at any depth.
+pub const KEY: &str = "x-watcher-knight";
+
+/// Comment opening a watcher in TOML files.
+pub const TOML_TAG: &str = "#:wk";
+
+/// Config formats whose watchers are declared as data rather than in a
+/// `<wk:` comment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+/// The format of `rel_path`, by extension.
+pub fn format_of(rel_path: &str) -> Option<Format> {
+    let ext = Path::new(rel_path)
+        .extension()?
+        .to_string_lossy()
+        .to_lowercase();
+    match ext.as_str() {
+        "json" => Some(Format::Json),
+        "yaml" | "yml" => Some(Format::Yaml),
+        "toml" => Some(Format::Toml),
+        _ => None,
+    }
+}
+
+/// One `x-watcher-knight` entry. The fields mirror the parts of a tag.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Declaration {
+    name: String,
+    instruction: String,
+    #[serde(default)]
+    files: Vec<String>,
+    #[serde(default)]
+    context: Vec<String>,
+    description: Option<String>,
+    rationale: Option<String>,
+    link: Option<String>,
+    #[serde(default)]
+    options: BTreeMap<String, String>,
+}
+
+/// The watchers declared in `contents` as data, as tags: `x-watcher-knight`
+/// entries in JSON and YAML, `#:wk` comments in TOML. Files that do not
+/// mention them are not parsed, so templated configs are left alone.
+pub fn extract_tags(
+    contents: &str,
+    format: Format,
+    rel_path: &str,
+) -> (Vec<RawTag>, Vec<ParseError>) {
+    match format {
+        Format::Toml => (extract_toml_tags(contents), Vec::new()),
+        _ if !contents.contains(KEY) => (Vec::new(), Vec::new()),
+        _ => extract_keyed_tags(contents, format, rel_path),
+    }
+}
+
+fn extract_keyed_tags(
+    contents: &str,
+    format: Format,
+    rel_path: &str,
+) -> (Vec<RawTag>, Vec<ParseError>) {
+    let err = |line: usize, message: String| ParseError {
+        file: rel_path.to_string(),
+        line,
+        message,
+        unclosed: false,
+    };
+    let documents = match parse_documents(contents, format) {
+        Ok(documents) => documents,
+        Err(e) => {
+            return (
+                Vec::new(),
+                vec![err(1, format!("cannot read `{KEY}`: {e}"))],
+            );
+        }
+    };
+    let mut entries = Vec::new();
+    for document in &documents {
+        collect_entries(document, &mut entries);
+    }
+
+    let lines: Vec<&str> = contents.lines().collect();
+    let key_lines: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| l.contains(KEY))
+        .map(|(i, _)| i + 1)
+        .collect();
+    let mut tags = Vec::new();
+    let mut errors = Vec::new();
+    for (n, value) in entries.into_iter().enumerate() {
+        // Entries are collected in document order, so the nth one belongs to
+        // the nth line mentioning the key.
+        let key_line = key_lines.get(n).copied().unwrap_or(1);
+        let items = match value {
+            Value::Sequence(items) => items.clone(),
+            other => vec![other.clone()],
+        };
+        let mut from = key_line;
+        for item in items {
+            let declaration = match serde_yaml::from_value::<Declaration>(item) {
+                Ok(declaration) => declaration,
+                Err(e) => {
+                    errors.push(err(key_line, format!("invalid `{KEY}` entry: {e}")));
+                    continue;
+                }
+            };
+            let line = name_line(&lines, from, &declaration.name).unwrap_or(key_line);
+            from = line + 1;
+            match render(&declaration) {
+                Ok(content) => tags.push(RawTag {
+                    content,
+                    line,
+                    end_line: line,
+                }),
+                Err(message) => errors.push(err(line, message)),
+            }
+        }
+    }
+    (tags, errors)
+}
+
+fn parse_documents(contents: &str, format: Format) -> Result<Vec<Value>, String> {
+    match format {
+        Format::Json => serde_json::from_str(contents)
+            .map(|value| vec![value])
+            .map_err(|e| e.to_string()),
+        // Manifests often hold several documents separated by `---`.
+        _ => serde_yaml::Deserializer::from_str(contents)
+            .map(|document| Value::deserialize(document).map_err(|e| e.to_string()))
+            .collect(),
+    }
+}
+
+/// Values of every `x-watcher-knight` key below `value`, in document order.
+fn collect_entries<'a>(value: &'a Value, entries: &mut Vec<&'a Value>) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping {
+                if key.as_str() == Some(KEY) {
+                    entries.push(value);
+                } else {
+                    collect_entries(value, entries);
+                }
+            }
+        }
+        Value::Sequence(items) => {
+            for item in items {
+                collect_entries(item, entries);
+            }
+        }
+        Value::Tagged(tagged) => collect_entries(&tagged.value, entries),
+        _ => {}
+    }
+}
+
+/// 1-based line of the first `name` field set to `name` at or after `from`.
+fn name_line(lines: &[&str], from: usize, name: &str) -> Option<usize> {
+    let start = from.saturating_sub(1);
+    lines
+        .iter()
+        .enumerate()
+        .skip(start)
+        .find(|(_, l)| l.contains("name") && l.contains(name))
+        .map(|(i, _)| i + 1)
+}
+
+/// A declaration written out as tag content, to be parsed like any tag.
+fn render(d: &Declaration) -> Result<String, String> {
+    let invalid = |what: &str, value: &str, chars: &str| {
+        format!(
+            "`{KEY}` entry `{}` has {what} `{value}` containing one of {chars}",
+            d.name
+        )
+    };
+    let mut content = format!("<wk: {}", d.name);
+    if !d.files.is_empty() {
+        if let Some(f) = d.files.iter().find(|f| f.contains([',', ']'])) {
+            return Err(invalid("a file entry", f, "`,` `]`"));
+        }
+        content.push_str(&format!(" [{}]", d.files.join(", ")));
+    }
+    if !d.options.is_empty() {
+        if let Some(v) = d.options.values().find(|v| v.contains('"')) {
+            return Err(invalid("an option", v, "`\"`"));
+        }
+        let pairs: Vec<String> = d
+            .options
+            .iter()
+            .map(|(k, v)| format!("{k}=\"{v}\""))
+            .collect();
+        content.push_str(&format!("\noptions={{{}}}", pairs.join(", ")));
+    }
+    if !d.context.is_empty() {
+        if let Some(c) = d.context.iter().find(|c| c.contains([',', '}'])) {
+            return Err(invalid("a context entry", c, "`,` `}`"));
+        }
+        content.push_str(&format!("\ncontext={{{}}}", d.context.join(", ")));
+    }
+    for (key, value) in [
+        ("description", &d.description),
+        ("rationale", &d.rationale),
+        ("link", &d.link),
+    ] {
+        if let Some(value) = value {
+            if value.contains('"') {
+                return Err(invalid(key, value, "`\"`"));
+            }
+            content.push_str(&format!("\n{key}=\"{value}\""));
+        }
+    }
+    for line in d.instruction.lines() {
+        content.push('\n');
+        content.push_str(line);
+    }
+    Ok(content)
+}
+
+/// `#:wk name [files] Instruction` comments in TOML. Following `#:` lines
+/// continue the tag until a line that is not one, or a `/>`.
+fn extract_toml_tags(contents: &str) -> Vec<RawTag> {
+    let mut tags = Vec::new();
+    let mut lines = contents.lines().enumerate().peekable();
+    while let Some((i, line)) = lines.next() {
+        let Some(rest) = toml_opening(line) else {
+            continue;
+        };
+        let mut content = format!("<wk:{rest}");
+        let mut end_line = i + 1;
+        let closed = take_close(&mut content);
+        while !closed && let Some((j, next)) = lines.peek() {
+            let trimmed = next.trim_start();
+            let Some(body) = trimmed.strip_prefix("#:") else {
+                break;
+            };
+            if toml_opening(next).is_some() || marker::find_tag_in_line(next).is_some() {
+                break;
+            }
+            end_line = j + 1;
+            content.push('\n');
+            content.push_str(body.trim());
+            lines.next();
+            if take_close(&mut content) {
+                break;
+            }
+        }
+        tags.push(RawTag {
+            content,
+            line: i + 1,
+            end_line,
+        });
+    }
+    tags
+}
+
+/// What follows `#:wk` on a line opening a TOML tag.
+fn toml_opening(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix(TOML_TAG)?;
+    (rest.is_empty() || rest.starts_with(char::is_whitespace)).then_some(rest)
+}
+
+/// Drop a `/>` and anything after it from `content`, returning whether there
+/// was one.
+fn take_close(content: &mut String) -> bool {
+    match content.rfind("/>") {
+        Some(pos) => {
+            content.truncate(pos);
+            let len = content.trim_end().len();
+            content.truncate(len);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(tags: &[RawTag]) -> Vec<(&str, usize)> {
+        tags.iter()
+            .map(|t| {
+                let parts = marker::split_tag(&t.content).unwrap();
+                (parts.name, t.line)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn yaml_declarations_at_any_depth() {
+        let yaml = "\
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: api
+  x-watcher-knight:
+    name: replicas
+    files: [./service.yaml]
+    options:
+      severity: warning
+    instruction: Keep at least two replicas.
+---
+kind: Service
+x-watcher-knight:
+  - name: port
+    instruction: |
+      The port matches
+      the container port.
+  - name: selector
+    instruction: Select the api pods.
+";
+        let (tags, errors) = extract_tags(yaml, Format::Yaml, "k8s/api.yaml");
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            names(&tags),
+            vec![("replicas", 6), ("port", 14), ("selector", 18)]
+        );
+        let parts = marker::split_tag(&tags[0].content).unwrap();
+        assert_eq!(parts.files, vec!["./service.yaml"]);
+        assert_eq!(parts.options, vec![("severity", "warning")]);
+        assert_eq!(parts.instruction, vec!["Keep at least two replicas."]);
+        let parts = marker::split_tag(&tags[1].content).unwrap();
+        assert_eq!(
+            parts.instruction,
+            vec!["The port matches", "the container port."]
+        );
+    }
+
+    #[test]
+    fn json_declarations() {
+        let json = r#"{
+  "name": "app",
+  "x-watcher-knight": {
+    "name": "engines",
+    "description": "Runtime pin",
+    "instruction": "Node version matches .nvmrc."
+  }
+}"#;
+        let (tags, errors) = extract_tags(json, Format::Json, "package.json");
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(names(&tags), vec![("engines", 4)]);
+        let parts = marker::split_tag(&tags[0].content).unwrap();
+        assert_eq!(parts.metadata, vec![("description", "Runtime pin")]);
+    }
+
+    #[test]
+    fn invalid_declarations_are_errors() {
+        let yaml = "x-watcher-knight:\n  name: a\n  instructions: typo\n";
+        let (tags, errors) = extract_tags(yaml, Format::Yaml, "a.yaml");
+        assert!(tags.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("unknown field"), "{errors:?}");
+
+        let yaml = "x-watcher-knight:\n  name: a\n  description: say \"hi\"\n  instruction: b\n";
+        let (_, errors) = extract_tags(yaml, Format::Yaml, "a.yaml");
+        assert!(errors[0].message.contains("description"), "{errors:?}");
+
+        // Templates that are not YAML are only read when they use the key.
+        let template = "replicas: {{ .Values.replicas }}\n";
+        assert_eq!(
+            extract_tags(template, Format::Yaml, "t.yaml"),
+            (Vec::new(), Vec::new())
+        );
+    }
+
+    #[test]
+    fn toml_comment_tags() {
+        let toml = "\
+[package]
+#:wk version [./CHANGELOG.md] Bump the version with the changelog.
+version = \"1.0.0\"
+
+#:wk deps
+#: options={severity=\"warning\"}
+#: Keep dependencies sorted.
+#: />
+[dependencies]
+";
+        let tags = extract_tags(toml, Format::Toml, "Cargo.toml").0;
+        assert_eq!(names(&tags), vec![("version", 2), ("deps", 5)]);
+        let parts = marker::split_tag(&tags[1].content).unwrap();
+        assert_eq!(parts.options, vec![("severity", "warning")]);
+        assert_eq!(parts.instruction, vec!["Keep dependencies sorted."]);
+        assert_eq!(tags[1].end_line, 8);
+    }
+}
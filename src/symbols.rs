@@ -0,0 +1,313 @@
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::cluster;
+
+/// How to find definitions in one language.
+struct Language {
+    extensions: &'static [&'static str],
+    /// Definition lines; the first capture is the name.
+    definitions: Vec<Regex>,
+    /// Joins a member to its enclosing type: `Cart::total`, `Cart.total`.
+    separator: &'static str,
+}
+
+/// Words that open a block like a method would in C-like syntax.
+const KEYWORDS: &[&str] = &[
+    "if", "for", "while", "switch", "catch", "return", "function", "else", "do", "try", "new",
+    "typeof", "await",
+];
+
+/// Words that open a statement, never a definition, though what follows
+/// them may look like one: `return compute(a,` is a call split over lines.
+const STATEMENTS: &[&str] = &["return", "yield", "throw", "else"];
+
+static LANGUAGES: LazyLock<Vec<Language>> = LazyLock::new(|| {
+    let re = |s: &str| Regex::new(s).unwrap();
+    let member = r"^\s*(?:(?:public|private|protected|internal|static|final|abstract|override|open|async|readonly|virtual|synchronized|get|set)\s+)*";
+    vec![
+        Language {
+            extensions: &["rs"],
+            definitions: vec![
+                re(
+                    r#"^\s*(?:pub(?:\([^)]*\))?\s+)?(?:(?:async|const|unsafe|extern\s+"[^"]*")\s+)*fn\s+(\w+)"#,
+                ),
+                re(r"^\s*(?:pub(?:\([^)]*\))?\s+)?(?:struct|enum|union|trait|mod)\s+(\w+)"),
+                re(r"^\s*(?:unsafe\s+)?impl(?:<[^>]*>)?\s+(?:[\w:<>, ]+\s+for\s+)?(?:\w+::)*(\w+)"),
+            ],
+            separator: "::",
+        },
+        Language {
+            extensions: &["py"],
+            definitions: vec![
+                re(r"^\s*(?:async\s+)?def\s+(\w+)"),
+                re(r"^\s*class\s+(\w+)"),
+            ],
+            separator: ".",
+        },
+        Language {
+            extensions: &["js", "jsx", "mjs", "cjs", "ts", "tsx", "mts", "cts"],
+            definitions: vec![
+                re(r"^\s*(?:export\s+)?(?:default\s+)?(?:async\s+)?function\s*\*?\s*(\w+)"),
+                re(
+                    r"^\s*(?:export\s+)?(?:default\s+)?(?:abstract\s+)?(?:class|interface|enum)\s+(\w+)",
+                ),
+                re(
+                    r"^\s*(?:export\s+)?(?:const|let|var)\s+(\w+)\s*(?::[^=]+)?=\s*(?:async\s*)?(?:function\b|\([^)]*\)\s*(?::[^=]+)?=>|\w+\s*=>)",
+                ),
+                re(&format!(
+                    r"{member}\*?(\w+)\s*(?:<[^>]*>)?\([^)]*\)\s*(?::[^{{]+)?\{{"
+                )),
+            ],
+            separator: ".",
+        },
+        Language {
+            extensions: &["go"],
+            definitions: vec![re(r"^func\s+(?:\([^)]*\)\s*)?(\w+)"), re(r"^type\s+(\w+)")],
+            separator: ".",
+        },
+        Language {
+            extensions: &["java", "kt", "kts", "cs", "scala"],
+            definitions: vec![
+                re(&format!(
+                    r"{member}(?:data\s+|sealed\s+|partial\s+)?(?:class|interface|enum|record|object|struct|trait)\s+(\w+)"
+                )),
+                re(&format!(
+                    r"{member}(?:fun|def)\s+(?:<[^>]*>\s*)?(?:\w+\.)?(\w+)"
+                )),
+                re(&format!(r"{member}[\w<>\[\],.?]+\s+(\w+)\s*\([^;]*$")),
+            ],
+            separator: ".",
+        },
+        Language {
+            extensions: &["rb"],
+            definitions: vec![
+                re(r"^\s*def\s+(?:self\.)?(\w+[?!=]?)"),
+                re(r"^\s*(?:class|module)\s+(?:\w+::)*(\w+)"),
+            ],
+            separator: ".",
+        },
+    ]
+});
+
+fn language(path: &str) -> Option<&'static Language> {
+    let ext = Path::new(path).extension()?.to_str()?;
+    LANGUAGES.iter().find(|l| l.extensions.contains(&ext))
+}
+
+/// The name `line` defines, if it is a definition.
+fn definition<'a>(language: &Language, line: &'a str) -> Option<&'a str> {
+    let first = line.split_whitespace().next()?;
+    if STATEMENTS.contains(&first) {
+        return None;
+    }
+    language
+        .definitions
+        .iter()
+        .find_map(|re| re.captures(line))
+        .map(|c| c.get(1).unwrap().as_str())
+        .filter(|name| !KEYWORDS.contains(name))
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// The definition enclosing line `index` of `lines`, qualified by the
+/// definitions enclosing it (`Cart::total`). Nesting is read from the
+/// indentation: each enclosing line is indented less than what it encloses.
+/// A blank line is in no definition.
+fn enclosing(language: &Language, lines: &[&str], index: usize) -> Option<String> {
+    let mut names = Vec::new();
+    let line = lines[index];
+    if line.trim().is_empty() {
+        return None;
+    }
+    names.extend(definition(language, line));
+    let mut limit = indent(line);
+    for line in lines[..index].iter().rev() {
+        if limit == 0 {
+            break;
+        }
+        let trimmed = line.trim_start();
+        // Blank lines and the ends of earlier blocks enclose nothing.
+        if trimmed.is_empty()
+            || trimmed.starts_with(['}', ')', ']'])
+            || trimmed == "end"
+            || indent(line) >= limit
+        {
+            continue;
+        }
+        if let Some(name) = definition(language, line) {
+            names.push(name);
+        }
+        limit = indent(line);
+    }
+    if names.is_empty() {
+        return None;
+    }
+    names.reverse();
+    Some(names.join(language.separator))
+}
+
+/// New-file line numbers (1-based) a hunk changes. A deletion counts as a
+/// change to the line that now stands in its place.
+fn changed_lines(hunk: &str) -> Vec<usize> {
+    let mut lines = hunk.lines();
+    let Some(start) = lines
+        .next()
+        .and_then(|header| header.split_whitespace().find(|w| w.starts_with('+')))
+        .and_then(|range| range[1..].split(',').next()?.parse::<usize>().ok())
+    else {
+        return Vec::new();
+    };
+    let mut changed = Vec::new();
+    let mut line = start.max(1);
+    for text in lines {
+        match text.chars().next() {
+            Some('+') => {
+                changed.push(line);
+                line += 1;
+            }
+            Some('-') => changed.push(line),
+            Some('\\') => {}
+            _ => line += 1,
+        }
+    }
+    changed.dedup();
+    changed
+}
+
+/// `path: symbol` for each function or type whose body `diff` changes, in
+/// diff order. Files are read under `root` as they are now; deleted files
+/// and unknown languages contribute nothing.
+pub fn changed_symbols(diff: &str, root: &Path) -> Vec<String> {
+    let mut symbols: Vec<String> = Vec::new();
+    let mut path = String::new();
+    let mut contents = String::new();
+    for hunk in cluster::hunks(diff) {
+        let Some(language) = language(&hunk.path) else {
+            continue;
+        };
+        if hunk.path != path || path.is_empty() {
+            contents = fs::read_to_string(root.join(&hunk.path)).unwrap_or_default();
+        }
+        let lines: Vec<&str> = contents.lines().collect();
+        for line in changed_lines(&hunk.text) {
+            if lines.is_empty() {
+                break;
+            }
+            let index = line.min(lines.len()) - 1;
+            if let Some(name) = enclosing(language, &lines, index) {
+                let symbol = format!("{}: {name}", hunk.path);
+                if !symbols.contains(&symbol) {
+                    symbols.push(symbol);
+                }
+            }
+        }
+        path = hunk.path;
+    }
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol_at(path: &str, source: &str, line: usize) -> Option<String> {
+        let lines: Vec<&str> = source.lines().collect();
+        enclosing(language(path).unwrap(), &lines, line - 1)
+    }
+
+    #[test]
+    fn rust_methods_are_qualified_by_their_impl() {
+        let source = "\
+fn helper() {}
+
+impl<T> Display for Cart<T> {
+    pub fn total(&self) -> u32 {
+        if self.empty {
+            return 0;
+        }
+        self.sum()
+    }
+}
+";
+        assert_eq!(symbol_at("src/cart.rs", source, 8).unwrap(), "Cart::total");
+        assert_eq!(symbol_at("src/cart.rs", source, 4).unwrap(), "Cart::total");
+        assert_eq!(symbol_at("src/cart.rs", source, 1).unwrap(), "helper");
+        assert_eq!(symbol_at("src/cart.rs", source, 2), None);
+    }
+
+    #[test]
+    fn python_and_typescript_definitions_are_found() {
+        let python = "class Cart:\n    def total(self):\n        return 0\n";
+        assert_eq!(symbol_at("cart.py", python, 3).unwrap(), "Cart.total");
+        let ts = "\
+export class Cart {
+  async total(items: Item[]): Promise<number> {
+    for (const item of items) {
+      sum += item.price;
+    }
+  }
+}
+export const format = (n: number) => {
+  return `${n}`;
+};
+";
+        assert_eq!(symbol_at("cart.ts", ts, 4).unwrap(), "Cart.total");
+        assert_eq!(symbol_at("cart.ts", ts, 9).unwrap(), "format");
+    }
+
+    #[test]
+    fn calls_split_over_lines_are_not_definitions() {
+        let java = "\
+class Cart {
+    public int total(int a) {
+        return compute(a,
+            b);
+    }
+    Iterable<Item> items() {
+        throw new IllegalStateException(message,
+            cause);
+    }
+}
+";
+        assert_eq!(symbol_at("Cart.java", java, 3).unwrap(), "Cart.total");
+        assert_eq!(symbol_at("Cart.java", java, 4).unwrap(), "Cart.total");
+        assert_eq!(symbol_at("Cart.java", java, 7).unwrap(), "Cart.items");
+        let csharp = "\
+class Cart {
+    IEnumerable<int> Totals() {
+        yield return Compute(a,
+            b);
+    }
+}
+";
+        assert_eq!(symbol_at("Cart.cs", csharp, 3).unwrap(), "Cart.Totals");
+    }
+
+    #[test]
+    fn changed_lines_follow_the_new_file() {
+        let hunk = "@@ -3,4 +3,4 @@ fn main() {\n keep\n-old\n+new\n keep\n-gone\n";
+        assert_eq!(changed_lines(hunk), [4, 6]);
+    }
+
+    #[test]
+    fn changed_symbols_read_files_under_root() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("lib.py"),
+            "def a():\n    return 1\n\ndef b():\n    return 3\n",
+        )
+        .unwrap();
+        let diff = "diff --git a/lib.py b/lib.py\n--- a/lib.py\n+++ b/lib.py\n\
+                    @@ -4,2 +4,2 @@\n def b():\n-    return 2\n+    return 3\n\
+                    diff --git a/gone.py b/gone.py\n@@ -1 +0,0 @@\n-def c(): pass\n\
+                    diff --git a/notes.txt b/notes.txt\n@@ -1 +1 @@\n-a\n+b\n";
+        assert_eq!(changed_symbols(diff, dir.path()), ["lib.py: b"]);
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::quota::BackendLimits;
+
+/// Most backend calls in flight, and how many a run starts with.
+pub const MAX_LIMIT: usize = 32;
+/// Retries of a rate-limited call.
+const RETRIES: u32 = 2;
+/// Pause before the first retry, doubled for the second.
+const RETRY_PAUSE: Duration = Duration::from_secs(5);
+/// Window of `requests_per_minute`.
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// How a backend call went.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    /// Answered.
+    Done,
+    /// Refused because of a rate limit or overload.
+    RateLimited,
+    /// Failed for another reason; says nothing about load.
+    Failed,
+}
+
+/// Whether a backend error says the backend is rate limiting or overloaded.
+fn is_rate_limited(reason: &str) -> bool {
+    let reason = reason.to_lowercase();
+    [
+        "rate limit",
+        "rate_limit",
+        "too many requests",
+        "overloaded",
+        "(429)",
+        " 429 ",
+    ]
+    .iter()
+    .any(|s| reason.contains(s))
+}
+
+/// Adaptive limit on concurrent backend calls. It starts at the most the
+/// backend allows; a rate limit or overload halves it, and it grows back by
+/// one slot per round of answered calls. Latency is no signal: agent sessions
+/// vary several-fold on their own. Calls that started before a back-off do
+/// not halve it again. A backend's `max_inflight` caps the limit and its
+/// `requests_per_minute` holds calls back until the last minute has room.
+pub struct Throttle {
+    state: Mutex<State>,
+    freed: Condvar,
+    /// When the run stops (`run --deadline`); no retry waits past it.
+    deadline: Option<Instant>,
+}
+
+struct State {
+    limit: usize,
+    /// Ceiling of `limit`.
+    max: usize,
+    in_flight: usize,
+    per_minute: Option<u32>,
+    /// Start times of the calls in the last minute, oldest first (only kept
+    /// with `per_minute`).
+    recent: VecDeque<Instant>,
+    /// Bumped on every back-off.
+    epoch: u64,
+    /// Answered calls since the limit last changed.
+    answered: usize,
+}
+
+/// A slot taken by [`Throttle::acquire`], handed back to [`Throttle::release`].
+struct Permit {
+    epoch: u64,
+}
+
+impl State {
+    /// How long until another call fits in `per_minute`, if it does not now.
+    fn rate_wait(&mut self, now: Instant) -> Option<Duration> {
+        while self
+            .recent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= MINUTE)
+        {
+            self.recent.pop_front();
+        }
+        let per_minute = self.per_minute? as usize;
+        (self.recent.len() >= per_minute).then(|| MINUTE - now.duration_since(self.recent[0]))
+    }
+}
+
+impl Throttle {
+    pub fn new(limits: &BackendLimits, deadline: Option<Instant>) -> Self {
+        let max = limits.max_inflight.unwrap_or(MAX_LIMIT).clamp(1, MAX_LIMIT);
+        Throttle {
+            state: Mutex::new(State {
+                limit: max,
+                max,
+                in_flight: 0,
+                per_minute: limits.requests_per_minute,
+                recent: VecDeque::new(),
+                epoch: 0,
+                answered: 0,
+            }),
+            freed: Condvar::new(),
+            deadline,
+        }
+    }
+
+    /// Wait for a free slot.
+    fn acquire(&self) -> Permit {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let wait = state.rate_wait(now);
+            if wait.is_none() && state.in_flight < state.limit {
+                if state.per_minute.is_some() {
+                    state.recent.push_back(now);
+                }
+                break;
+            }
+            state = match wait {
+                Some(wait) => self.freed.wait_timeout(state, wait).unwrap().0,
+                None => self.freed.wait(state).unwrap(),
+            };
+        }
+        state.in_flight += 1;
+        Permit { epoch: state.epoch }
+    }
+
+    /// Free the slot of `permit` and adapt the limit to `outcome`.
+    fn release(&self, permit: Permit, outcome: Outcome) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+        match outcome {
+            Outcome::RateLimited if permit.epoch == state.epoch => {
+                state.limit = (state.limit / 2).max(1);
+                state.epoch += 1;
+                state.answered = 0;
+            }
+            Outcome::Done => {
+                state.answered += 1;
+                if state.answered >= state.limit {
+                    state.limit = (state.limit + 1).min(state.max);
+                    state.answered = 0;
+                }
+            }
+            Outcome::RateLimited | Outcome::Failed => {}
+        }
+        self.freed.notify_all();
+    }
+
+    /// Run the backend call `call` in a slot and return its result with how
+    /// long the last attempt took and how often it was retried. A
+    /// rate-limited call is retried up to [`RETRIES`] times, after a pause and
+    /// once a slot is free again, unless the pause would outlast the deadline.
+    pub fn call<T>(
+        &self,
+        call: impl Fn() -> Result<T, String>,
+    ) -> (Result<T, String>, Duration, u32) {
+        let mut retries = 0;
+        loop {
+            let permit = self.acquire();
+            let started = Instant::now();
+            let result = call();
+            let elapsed = started.elapsed();
+            let outcome = match &result {
+                Ok(_) => Outcome::Done,
+                Err(reason) if is_rate_limited(reason) => Outcome::RateLimited,
+                Err(_) => Outcome::Failed,
+            };
+            self.release(permit, outcome);
+            let pause = RETRY_PAUSE * (retries + 1);
+            if outcome != Outcome::RateLimited
+                || retries == RETRIES
+                || self.deadline.is_some_and(|d| Instant::now() + pause >= d)
+            {
+                return (result, elapsed, retries);
+            }
+            retries += 1;
+            thread::sleep(pause);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(throttle: &Throttle) -> usize {
+        throttle.state.lock().unwrap().limit
+    }
+
+    fn unlimited() -> Throttle {
+        Throttle::new(&BackendLimits::default(), None)
+    }
+
+    #[test]
+    fn starts_at_full_parallelism_and_grows_back_after_a_rate_limit() {
+        let throttle = unlimited();
+        assert_eq!(limit(&throttle), MAX_LIMIT);
+
+        let permit = throttle.acquire();
+        throttle.release(permit, Outcome::RateLimited);
+        assert_eq!(limit(&throttle), MAX_LIMIT / 2);
+
+        // It takes a full round of answered calls to grow, however slow.
+        for _ in 0..MAX_LIMIT / 2 - 1 {
+            let permit = throttle.acquire();
+            throttle.release(permit, Outcome::Done);
+        }
+        assert_eq!(limit(&throttle), MAX_LIMIT / 2);
+        let permit = throttle.acquire();
+        throttle.release(permit, Outcome::Done);
+        assert_eq!(limit(&throttle), MAX_LIMIT / 2 + 1);
+    }
+
+    #[test]
+    fn backs_off_once_per_congestion_event() {
+        let throttle = unlimited();
+        let permits: Vec<Permit> = (0..4).map(|_| throttle.acquire()).collect();
+        for permit in permits {
+            throttle.release(permit, Outcome::RateLimited);
+        }
+        assert_eq!(limit(&throttle), MAX_LIMIT / 2);
+
+        let permit = throttle.acquire();
+        throttle.release(permit, Outcome::RateLimited);
+        let permit = throttle.acquire();
+        throttle.release(permit, Outcome::RateLimited);
+        assert_eq!(limit(&throttle), MAX_LIMIT / 8);
+
+        let permit = throttle.acquire();
+        throttle.release(permit, Outcome::Failed);
+        assert_eq!(limit(&throttle), MAX_LIMIT / 8);
+    }
+
+    #[test]
+    fn limit_is_capped() {
+        let throttle = unlimited();
+        for _ in 0..2 * MAX_LIMIT {
+            let permit = throttle.acquire();
+            throttle.release(permit, Outcome::Done);
+        }
+        assert_eq!(limit(&throttle), MAX_LIMIT);
+    }
+
+    #[test]
+    fn backend_limits_cap_inflight_and_rate() {
+        let throttle = Throttle::new(
+            &BackendLimits {
+                max_inflight: Some(2),
+                requests_per_minute: Some(3),
+                daily_token_budget: None,
+            },
+            None,
+        );
+        assert_eq!(limit(&throttle), 2);
+        for _ in 0..3 {
+            let permit = throttle.acquire();
+            throttle.release(permit, Outcome::Done);
+        }
+        assert_eq!(limit(&throttle), 2);
+
+        let mut state = throttle.state.lock().unwrap();
+        let last = *state.recent.back().unwrap();
+        let first = state.recent[0];
+        assert!(state.rate_wait(first + Duration::from_secs(1)).is_some());
+        assert_eq!(
+            state.rate_wait(first + MINUTE),
+            None,
+            "the oldest call has left the window"
+        );
+        assert_eq!(state.recent.back(), Some(&last));
+    }
+
+    #[test]
+    fn no_retry_pause_runs_past_the_deadline() {
+        let deadline = Instant::now() + RETRY_PAUSE / 2;
+        let throttle = Throttle::new(&BackendLimits::default(), Some(deadline));
+        let started = Instant::now();
+        let (result, _, retries) = throttle.call(|| Err::<(), _>("Rate limit reached".to_string()));
+        assert!(result.is_err());
+        assert_eq!(retries, 0);
+        assert!(started.elapsed() < RETRY_PAUSE / 2);
+    }
+
+    #[test]
+    fn recognizes_rate_limit_errors() {
+        assert!(is_rate_limited(
+            "process exited with exit status: 1: API Error: Rate limit reached"
+        ));
+        assert!(is_rate_limited(
+            "worker https://wk-1 rejected the job (429): busy"
+        ));
+        assert!(is_rate_limited("Overloaded"));
+        assert!(!is_rate_limited("process exited with exit status: 1"));
+    }
+}
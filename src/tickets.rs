@@ -0,0 +1,534 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::history::Trend;
+use crate::http::Request;
+use crate::report::ResultEntry;
+
+/// Environment variable holding the GitHub token.
+pub const GITHUB_TOKEN_VAR: &str = "GITHUB_TOKEN";
+/// Environment variables holding Jira credentials. Without a user the token
+/// is sent as a bearer token (Data Center); with one, as basic auth (Cloud).
+pub const JIRA_TOKEN_VAR: &str = "WK_JIRA_TOKEN";
+pub const JIRA_USER_VAR: &str = "WK_JIRA_USER";
+
+/// Label put on every filed ticket; open tickets are found by it.
+const LABEL: &str = "watcher-knight";
+
+/// `[tickets]` in `watcher-knight.toml`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TicketConfig {
+    /// `github` or `jira`.
+    pub tracker: String,
+    /// GitHub repository (`owner/name`).
+    pub repo: Option<String>,
+    /// API base: the Jira site, or a GitHub Enterprise API URL.
+    pub url: Option<String>,
+    /// Jira project key.
+    pub project: Option<String>,
+    /// Only runs on this branch count and file tickets.
+    #[serde(default = "default_branch")]
+    pub branch: String,
+    /// Consecutive failing runs before a ticket is filed.
+    #[serde(default = "default_after")]
+    pub after: usize,
+    /// Extra labels for new tickets.
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+fn default_branch() -> String {
+    "main".to_string()
+}
+
+fn default_after() -> usize {
+    3
+}
+
+/// A persistent failure worth a ticket.
+#[derive(Debug)]
+pub struct Failure<'a> {
+    pub entry: &'a ResultEntry,
+    /// Consecutive failing runs on the configured branch, this one included.
+    pub streak: usize,
+    /// The marker's `owner` option, assigned to new tickets.
+    pub owner: Option<&'a str>,
+}
+
+/// What was done for a failure.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Filed(String),
+    Updated(String),
+    Unchanged(String),
+}
+
+#[derive(Debug)]
+enum Backend {
+    GitHub {
+        api: String,
+        repo: String,
+        token: String,
+    },
+    Jira {
+        base: String,
+        project: String,
+        /// Set for basic auth (Cloud); otherwise the token is a bearer token.
+        user: Option<String>,
+        token: String,
+    },
+}
+
+#[derive(Debug)]
+pub struct Tracker {
+    backend: Backend,
+    config: TicketConfig,
+}
+
+/// Failures of this run that have lasted at least `config.after` runs.
+/// `trends` must be computed over runs on `config.branch`.
+pub fn persistent_failures<'a>(
+    entries: &'a [ResultEntry],
+    trends: &[Trend],
+    config: &TicketConfig,
+) -> Vec<Failure<'a>> {
+    entries
+        .iter()
+        .filter(|e| e.status == "failed")
+        .filter_map(|entry| {
+            let streak = trends.iter().find(|t| t.name == entry.name)?.current_streak;
+            (streak >= config.after).then(|| Failure {
+                entry,
+                streak,
+                owner: entry.options.get("owner").map(String::as_str),
+            })
+        })
+        .collect()
+}
+
+impl Tracker {
+    /// Build the tracker described by `config`, taking credentials from `var`.
+    pub fn new(
+        config: &TicketConfig,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let var = |name: &str| var(name).filter(|v| !v.is_empty());
+        let missing = |what: &str| {
+            format!(
+                "[tickets] with tracker = \"{}\" needs {what}",
+                config.tracker
+            )
+        };
+        let backend = match config.tracker.as_str() {
+            "github" => Backend::GitHub {
+                api: config
+                    .url
+                    .as_deref()
+                    .unwrap_or("https://api.github.com")
+                    .trim_end_matches('/')
+                    .to_string(),
+                repo: config.repo.clone().ok_or_else(|| missing("repo"))?,
+                token: var(GITHUB_TOKEN_VAR).ok_or_else(|| missing(GITHUB_TOKEN_VAR))?,
+            },
+            "jira" => {
+                let token = var(JIRA_TOKEN_VAR).ok_or_else(|| missing(JIRA_TOKEN_VAR))?;
+                Backend::Jira {
+                    base: config
+                        .url
+                        .as_deref()
+                        .ok_or_else(|| missing("url"))?
+                        .trim_end_matches('/')
+                        .to_string(),
+                    project: config.project.clone().ok_or_else(|| missing("project"))?,
+                    user: var(JIRA_USER_VAR),
+                    token,
+                }
+            }
+            other => {
+                return Err(format!(
+                    "unknown ticket tracker `{other}` (expected github or jira)"
+                ));
+            }
+        };
+        Ok(Tracker {
+            backend,
+            config: config.clone(),
+        })
+    }
+
+    /// File a ticket for `failure`, or update the open one. An open ticket
+    /// is only updated when the failure first reaches the threshold again,
+    /// so a long streak does not comment on every run.
+    pub fn file(&self, failure: &Failure, commit: Option<&str>) -> Result<Action, String> {
+        let title = title(&failure.entry.name);
+        let body = body(failure, &self.config.branch, commit);
+        match self.find_open(&title)? {
+            Some(id) if failure.streak == self.config.after => {
+                self.comment(&id, &body)?;
+                Ok(Action::Updated(id))
+            }
+            Some(id) => Ok(Action::Unchanged(id)),
+            None => self.create(&title, &body, failure.owner).map(Action::Filed),
+        }
+    }
+
+    fn labels(&self) -> Vec<String> {
+        let mut labels = vec![LABEL.to_string()];
+        labels.extend(self.config.labels.iter().cloned());
+        labels
+    }
+
+    /// The id of the open ticket titled `title`.
+    fn find_open(&self, title: &str) -> Result<Option<String>, String> {
+        match &self.backend {
+            Backend::GitHub { api, repo, .. } => {
+                let url =
+                    format!("{api}/repos/{repo}/issues?state=open&labels={LABEL}&per_page=100");
+                let issues = self.call("GET", &url, None)?;
+                Ok(issues
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .find(|i| i["title"] == title)
+                    .and_then(|i| i["number"].as_u64())
+                    .map(|n| n.to_string()))
+            }
+            Backend::Jira { base, project, .. } => {
+                let jql = format!(
+                    "project = \"{project}\" AND labels = \"{LABEL}\" AND statusCategory != Done"
+                );
+                let found = self.call(
+                    "POST",
+                    &format!("{base}/rest/api/2/search"),
+                    Some(&json!({"jql": jql, "fields": ["summary"], "maxResults": 100})),
+                )?;
+                Ok(found["issues"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .find(|i| i["fields"]["summary"] == title)
+                    .and_then(|i| i["key"].as_str())
+                    .map(str::to_string))
+            }
+        }
+    }
+
+    fn create(&self, title: &str, body: &str, owner: Option<&str>) -> Result<String, String> {
+        match &self.backend {
+            Backend::GitHub { api, repo, .. } => {
+                let mut issue = json!({"title": title, "body": body, "labels": self.labels()});
+                if let Some(owner) = owner {
+                    issue["assignees"] = json!([owner]);
+                }
+                let created =
+                    self.call("POST", &format!("{api}/repos/{repo}/issues"), Some(&issue))?;
+                created["number"]
+                    .as_u64()
+                    .map(|n| n.to_string())
+                    .ok_or_else(|| format!("unexpected GitHub response: {created}"))
+            }
+            Backend::Jira { base, project, .. } => {
+                let mut fields = json!({
+                    "project": {"key": project},
+                    "summary": title,
+                    "description": body,
+                    "issuetype": {"name": "Bug"},
+                    "labels": self.labels(),
+                });
+                if let Some(owner) = owner {
+                    fields["assignee"] = json!({"id": owner});
+                }
+                let created = self.call(
+                    "POST",
+                    &format!("{base}/rest/api/2/issue"),
+                    Some(&json!({"fields": fields})),
+                )?;
+                created["key"]
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| format!("unexpected Jira response: {created}"))
+            }
+        }
+    }
+
+    fn comment(&self, id: &str, body: &str) -> Result<(), String> {
+        let url = match &self.backend {
+            Backend::GitHub { api, repo, .. } => format!("{api}/repos/{repo}/issues/{id}/comments"),
+            Backend::Jira { base, .. } => format!("{base}/rest/api/2/issue/{id}/comment"),
+        };
+        self.call("POST", &url, Some(&json!({"body": body})))
+            .map(|_| ())
+    }
+
+    /// `request` carrying the tracker's credentials.
+    fn authorize(&self, request: Request) -> Request {
+        match &self.backend {
+            Backend::GitHub { token, .. } => request
+                .bearer(token)
+                .header("Accept: application/vnd.github+json"),
+            Backend::Jira {
+                user: Some(user),
+                token,
+                ..
+            } => request.basic_auth(&format!("{user}:{token}")),
+            Backend::Jira { token, .. } => request.bearer(token),
+        }
+    }
+
+    /// Send a JSON request and parse the JSON answer.
+    fn call(&self, method: &str, url: &str, body: Option<&Value>) -> Result<Value, String> {
+        let mut request = self.authorize(Request::new(method, url));
+        if let Some(body) = body {
+            request = request.json(body.to_string());
+        }
+        let response = request.send()?.success(&format!("{method} {url}"))?;
+        Ok(serde_json::from_slice(&response.body).unwrap_or(Value::Null))
+    }
+}
+
+fn title(name: &str) -> String {
+    format!("watcher-knight: `{name}` is failing")
+}
+
+fn body(failure: &Failure, branch: &str, commit: Option<&str>) -> String {
+    let entry = failure.entry;
+    let mut out = format!(
+        "Watcher `{}` ({}) has failed {} consecutive runs on `{branch}`.\n\n",
+        entry.name, entry.location, failure.streak
+    );
+    let reason = entry
+        .reason
+        .as_deref()
+        .unwrap_or("marked invalid with no reason");
+    for line in reason.lines() {
+        out.push_str(&format!("> {line}\n"));
+    }
+    out.push('\n');
+    if let Some(rationale) = &entry.metadata.rationale {
+        out.push_str(&format!("Why: {rationale}\n"));
+    }
+    if let Some(link) = &entry.metadata.link {
+        out.push_str(&format!("See: {link}\n"));
+    }
+    if let Some(commit) = commit {
+        out.push_str(&format!("Commit: {commit}\n"));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    fn entry(name: &str, status: &str, owner: Option<&str>) -> ResultEntry {
+        ResultEntry {
+            name: name.to_string(),
+            location: "src/a.ts:1".to_string(),
+            status: status.to_string(),
+            reason: Some("routes are not versioned".to_string()),
+            note: None,
+            cached: false,
+            modified: false,
+            metadata: Default::default(),
+            options: owner
+                .map(|o| BTreeMap::from([("owner".to_string(), o.to_string())]))
+                .unwrap_or_default(),
+            telemetry: None,
+        }
+    }
+
+    fn trend(name: &str, current_streak: usize) -> Trend {
+        Trend {
+            name: name.to_string(),
+            location: "src/a.ts:1".to_string(),
+            runs: current_streak,
+            passed: 0,
+            pass_rate: 0.0,
+            mean_latency_ms: None,
+            current_streak,
+            longest_streak: current_streak,
+            chronic: false,
+        }
+    }
+
+    fn config(url: &str) -> TicketConfig {
+        TicketConfig {
+            tracker: "github".to_string(),
+            repo: Some("org/service".to_string()),
+            url: Some(url.to_string()),
+            project: None,
+            branch: default_branch(),
+            after: 3,
+            labels: vec!["invariants".to_string()],
+        }
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let pairs: Vec<(String, String)> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| {
+            pairs
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.clone())
+        }
+    }
+
+    #[test]
+    fn persistent_failures_need_threshold_streak() {
+        let entries = [
+            entry("a", "failed", Some("alice")),
+            entry("b", "failed", None),
+            entry("c", "waived", None),
+        ];
+        let trends = [trend("a", 3), trend("b", 2), trend("c", 7)];
+        let failures = persistent_failures(&entries, &trends, &config("http://x"));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].entry.name, "a");
+        assert_eq!(failures[0].streak, 3);
+        assert_eq!(failures[0].owner, Some("alice"));
+    }
+
+    #[test]
+    fn new_requires_tracker_settings() {
+        let mut jira = config("https://corp.atlassian.net");
+        jira.tracker = "jira".to_string();
+        let err = Tracker::new(&jira, vars(&[(JIRA_TOKEN_VAR, "t")])).unwrap_err();
+        assert!(err.contains("project"), "err was: {err}");
+        let err = Tracker::new(&config("http://x"), vars(&[])).unwrap_err();
+        assert!(err.contains(GITHUB_TOKEN_VAR), "err was: {err}");
+        let mut unknown = config("http://x");
+        unknown.tracker = "trello".to_string();
+        assert!(Tracker::new(&unknown, vars(&[])).is_err());
+    }
+
+    #[test]
+    fn body_includes_reason_and_link() {
+        let mut e = entry("a", "failed", None);
+        e.metadata.link = Some("https://docs.example.com/adr/1".to_string());
+        let failure = Failure {
+            entry: &e,
+            streak: 4,
+            owner: None,
+        };
+        assert_eq!(
+            body(&failure, "main", Some("abc123")),
+            "Watcher `a` (src/a.ts:1) has failed 4 consecutive runs on `main`.\n\n\
+             > routes are not versioned\n\n\
+             See: https://docs.example.com/adr/1\n\
+             Commit: abc123\n"
+        );
+    }
+
+    /// Path and body of each POST the fake API received.
+    type Posts = Arc<Mutex<Vec<(String, String)>>>;
+
+    /// Fake GitHub API: serves `open` issues, records POSTs.
+    fn start_github(open: &'static str) -> (String, Posts) {
+        use std::io::{BufRead, BufReader, Read};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let posts: Posts = Arc::default();
+        let recorded = Arc::clone(&posts);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let (method, path) = (
+                    parts.next().unwrap().to_string(),
+                    parts.next().unwrap().to_string(),
+                );
+                let (mut length, mut authorized) = (0, false);
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    let lower = line.to_ascii_lowercase();
+                    if let Some(v) = lower.strip_prefix("content-length:") {
+                        length = v.trim().parse().unwrap();
+                    }
+                    authorized |= line.trim() == "Authorization: Bearer ghp";
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let (status, reply) = match (authorized, method.as_str()) {
+                    (false, _) => (401, "{}".to_string()),
+                    (true, "GET") => (200, open.to_string()),
+                    (true, _) => {
+                        recorded
+                            .lock()
+                            .unwrap()
+                            .push((path, String::from_utf8(body).unwrap()));
+                        (201, "{\"number\": 42}".to_string())
+                    }
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{reply}",
+                    reply.len()
+                )
+                .unwrap();
+            }
+        });
+        (url, posts)
+    }
+
+    #[test]
+    fn file_creates_issue_assigned_to_owner() {
+        let (url, posts) = start_github("[]");
+        let tracker = Tracker::new(&config(&url), vars(&[(GITHUB_TOKEN_VAR, "ghp")])).unwrap();
+        let e = entry("a", "failed", Some("alice"));
+        let failure = Failure {
+            entry: &e,
+            streak: 3,
+            owner: Some("alice"),
+        };
+        assert_eq!(
+            tracker.file(&failure, None).unwrap(),
+            Action::Filed("42".to_string())
+        );
+        let posts = posts.lock().unwrap();
+        assert_eq!(posts[0].0, "/repos/org/service/issues");
+        let issue: Value = serde_json::from_str(&posts[0].1).unwrap();
+        assert_eq!(issue["title"], "watcher-knight: `a` is failing");
+        assert_eq!(issue["assignees"], json!(["alice"]));
+        assert_eq!(issue["labels"], json!(["watcher-knight", "invariants"]));
+    }
+
+    #[test]
+    fn file_comments_on_open_issue_once_per_streak() {
+        let (url, posts) =
+            start_github(r#"[{"number": 7, "title": "watcher-knight: `a` is failing"}]"#);
+        let tracker = Tracker::new(&config(&url), vars(&[(GITHUB_TOKEN_VAR, "ghp")])).unwrap();
+        let e = entry("a", "failed", None);
+        let at = |streak| Failure {
+            entry: &e,
+            streak,
+            owner: None,
+        };
+        assert_eq!(
+            tracker.file(&at(3), None).unwrap(),
+            Action::Updated("7".to_string())
+        );
+        assert_eq!(
+            tracker.file(&at(4), None).unwrap(),
+            Action::Unchanged("7".to_string())
+        );
+        let posts = posts.lock().unwrap();
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].0, "/repos/org/service/issues/7/comments");
+    }
+}
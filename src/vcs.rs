@@ -0,0 +1,196 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process;
+
+#[cfg(feature = "hg")]
+use crate::hg::Hg;
+use crate::jj::{self, Jj};
+use crate::stack;
+
+/// The version control operations diff mode needs. Git is the default;
+/// Jujutsu and (with the `hg` feature) Mercurial checkouts are detected by
+/// their metadata directory at the root.
+pub trait Vcs {
+    /// Diff base used by a bare `--diff`.
+    fn default_base(&self) -> Result<String, String>;
+    /// Where the working copy forked from `parent`, for `--stack-base`.
+    fn fork_point(&self, parent: &str) -> String;
+    /// The diff of the working copy against `base`, in git's unified format.
+    fn diff(&self, base: &str) -> Result<String, String>;
+    /// Repo-relative paths changed since `base`.
+    fn changed_files(&self, base: &str) -> Result<Vec<String>, String>;
+    /// Files the VCS does not track yet, which diff mode cannot see.
+    fn untracked_files(&self) -> Vec<String>;
+    /// The contents of repo-relative `path` at `base`, `None` if it did not
+    /// exist there.
+    fn file_at(&self, base: &str, path: &str) -> Option<String>;
+}
+
+/// The VCS of the checkout at `root`.
+pub fn for_root(root: &Path) -> Box<dyn Vcs> {
+    if jj::is_repo(root) {
+        return Box::new(Jj::new(root));
+    }
+    #[cfg(feature = "hg")]
+    if root.join(".hg").is_dir() {
+        return Box::new(Hg::new(root));
+    }
+    Box::new(Git::new(root))
+}
+
+/// The root of the checkout containing `start`: the git work tree, else
+/// (with the `hg` feature) the nearest directory holding `.hg`.
+pub fn discover(start: &Path) -> Option<PathBuf> {
+    if let Ok(repo) = git2::Repository::discover(start)
+        && let Some(workdir) = repo.workdir()
+    {
+        return Some(workdir.to_path_buf());
+    }
+    #[cfg(feature = "hg")]
+    if let Some(root) = start
+        .canonicalize()
+        .ok()?
+        .ancestors()
+        .find(|dir| dir.join(".hg").is_dir())
+    {
+        return Some(root.to_path_buf());
+    }
+    None
+}
+
+/// Whether a directory holds VCS metadata and must not be scanned.
+pub fn is_metadata_dir(name: &OsStr) -> bool {
+    name == ".git" || name == ".hg" || name == ".jj"
+}
+
+/// Run `program` in `root` and return its stdout, with the command line in
+/// any error.
+pub fn command(program: &str, root: &Path, args: &[&str]) -> Result<String, String> {
+    let line = format!("{program} {}", args.join(" "));
+    let output = process::Command::new(program)
+        .args(args)
+        .current_dir(root)
+        .output()
+        .map_err(|e| format!("failed to run `{line}`: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "`{line}` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub struct Git {
+    root: PathBuf,
+}
+
+impl Git {
+    pub fn new(root: &Path) -> Self {
+        Git {
+            root: root.to_path_buf(),
+        }
+    }
+}
+
+impl Vcs for Git {
+    /// The parent of a stacked branch or merge-queue entry when one is
+    /// detected, else `origin/main` or `origin/master`.
+    fn default_base(&self) -> Result<String, String> {
+        if let Some(base) = stack::detect(&self.root, |name| std::env::var(name).ok()) {
+            eprintln!(
+                "Diffing against stack parent {} (from {})",
+                base.parent, base.source
+            );
+            return Ok(self.fork_point(&base.parent));
+        }
+        ["origin/main", "origin/master"]
+            .into_iter()
+            .find(|candidate| {
+                command("git", &self.root, &["rev-parse", "--verify", candidate]).is_ok()
+            })
+            .map(str::to_string)
+            .ok_or_else(|| {
+                "could not find origin/main or origin/master. Pass a ref explicitly: --diff <ref>"
+                    .to_string()
+            })
+    }
+
+    fn fork_point(&self, parent: &str) -> String {
+        stack::fork_point(&self.root, parent)
+    }
+
+    fn diff(&self, base: &str) -> Result<String, String> {
+        command("git", &self.root, &["diff", base])
+    }
+
+    fn changed_files(&self, base: &str) -> Result<Vec<String>, String> {
+        let names = command("git", &self.root, &["diff", base, "--name-only"])?;
+        Ok(names.lines().map(str::to_string).collect())
+    }
+
+    fn untracked_files(&self) -> Vec<String> {
+        command(
+            "git",
+            &self.root,
+            &["ls-files", "--others", "--exclude-standard"],
+        )
+        .map(|out| out.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+    }
+
+    fn file_at(&self, base: &str, path: &str) -> Option<String> {
+        command("git", &self.root, &["show", &format!("{base}:{path}")]).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn git_reports_changes_against_base() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let git = |args: &[&str]| {
+            command(
+                "git",
+                root,
+                &[
+                    &["-c", "user.name=wk", "-c", "user.email=wk@example.com"],
+                    args,
+                ]
+                .concat(),
+            )
+            .unwrap()
+        };
+        git(&["init", "--quiet", "-b", "main"]);
+        fs::write(root.join("a.txt"), "a\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "--quiet", "-m", "a"]);
+        fs::write(root.join("a.txt"), "b\n").unwrap();
+        fs::write(root.join("new.txt"), "new\n").unwrap();
+
+        let vcs = for_root(root);
+        assert_eq!(vcs.changed_files("HEAD").unwrap(), vec!["a.txt"]);
+        assert!(vcs.diff("HEAD").unwrap().contains("+b"));
+        assert_eq!(vcs.untracked_files(), vec!["new.txt"]);
+        assert_eq!(vcs.file_at("HEAD", "a.txt").as_deref(), Some("a\n"));
+        assert_eq!(vcs.file_at("HEAD", "new.txt"), None);
+        let err = vcs.diff("no-such-ref").unwrap_err();
+        assert!(err.starts_with("`git diff no-such-ref` failed"), "{err}");
+        assert_eq!(
+            discover(&root.join(".")).map(|p| p.canonicalize().unwrap()),
+            Some(root.canonicalize().unwrap())
+        );
+    }
+
+    #[test]
+    fn is_metadata_dir_covers_all_backends() {
+        for name in [".git", ".hg", ".jj"] {
+            assert!(is_metadata_dir(OsStr::new(name)));
+        }
+        assert!(!is_metadata_dir(OsStr::new(".github")));
+    }
+}
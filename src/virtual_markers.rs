@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::marker::{self, Marker, Metadata};
+use crate::rename;
+
+/// A watcher declared in `watcher-knight.toml` (`[[markers]]`) instead of a
+/// comment, instantiated once per file matching `applies_to`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VirtualMarker {
+    pub name: String,
+    /// Glob of repo-relative paths, e.g. `services/*/Dockerfile`.
+    pub applies_to: String,
+    pub instruction: String,
+    /// More files to watch besides the matched one, relative to it as in a
+    /// marker written in that file.
+    #[serde(default)]
+    pub files: Vec<String>,
+    #[serde(default)]
+    pub context: Vec<String>,
+    pub description: Option<String>,
+    pub rationale: Option<String>,
+    pub link: Option<String>,
+    #[serde(default)]
+    pub options: HashMap<String, String>,
+}
+
+/// One marker per definition and matching file in `repo_files`, located at
+/// line 1 of that file, in path order.
+pub fn instantiate(
+    defs: &[VirtualMarker],
+    repo_files: &[String],
+    repo_root: &Path,
+) -> Result<Vec<Marker>, String> {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..glob::MatchOptions::new()
+    };
+    let mut markers = Vec::new();
+    for def in defs {
+        if !rename::is_valid_name(&def.name) {
+            return Err(format!(
+                "[[markers]] entry has an invalid name `{}` (names may contain alphanumeric \
+                 characters, hyphens, and underscores)",
+                def.name
+            ));
+        }
+        let instruction = def.instruction.trim();
+        if instruction.is_empty() {
+            return Err(format!(
+                "[[markers]] entry `{}` has no instruction text",
+                def.name
+            ));
+        }
+        let pattern = glob::Pattern::new(def.applies_to.trim_start_matches('/')).map_err(|e| {
+            format!(
+                "[[markers]] entry `{}` has an invalid applies_to `{}`: {e}",
+                def.name, def.applies_to
+            )
+        })?;
+        let raw: Vec<&str> = def.files.iter().map(String::as_str).collect();
+        let context: Vec<&str> = def.context.iter().map(String::as_str).collect();
+        let mut matched: Vec<&String> = repo_files
+            .iter()
+            .filter(|path| pattern.matches_with(path, options))
+            .collect();
+        matched.sort();
+        for path in matched {
+            let parent = Path::new(path).parent().unwrap_or(Path::new(""));
+            let mut scope = marker::resolve_raw_files(&raw, parent, repo_root);
+            scope.files.insert(0, path.clone());
+            markers.push(Marker {
+                name: def.name.clone(),
+                rel_path: path.clone(),
+                line: 1,
+                instruction: instruction.to_string(),
+                files: scope.files,
+                exclude: scope.exclude,
+                context: marker::resolve_context(&context, parent),
+                metadata: Metadata {
+                    description: def.description.clone(),
+                    rationale: def.rationale.clone(),
+                    link: def.link.clone(),
+                },
+                options: def.options.clone(),
+            });
+        }
+    }
+    Ok(markers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defs(toml: &str) -> Vec<VirtualMarker> {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            markers: Vec<VirtualMarker>,
+        }
+        toml::from_str::<Wrapper>(toml).unwrap().markers
+    }
+
+    #[test]
+    fn instantiates_one_marker_per_matching_file() {
+        let defs = defs(
+            "[[markers]]\n\
+             name = \"pinned-base\"\n\
+             applies_to = \"services/*/Dockerfile\"\n\
+             instruction = \"Base images are pinned by digest.\"\n\
+             files = [\"./requirements.txt\"]\n\
+             context = [\"./README.md\"]\n\
+             options = { model = \"haiku\" }\n",
+        );
+        let files: Vec<String> = [
+            "services/api/Dockerfile",
+            "services/web/Dockerfile",
+            "services/web/nested/Dockerfile",
+            "Dockerfile",
+        ]
+        .map(String::from)
+        .to_vec();
+        let markers = instantiate(&defs, &files, Path::new("/repo")).unwrap();
+        assert_eq!(markers.len(), 2);
+        assert_eq!(markers[0].name, "pinned-base");
+        assert_eq!(markers[0].rel_path, "services/api/Dockerfile");
+        assert_eq!(markers[0].line, 1);
+        assert_eq!(
+            markers[0].files,
+            vec!["services/api/Dockerfile", "services/api/requirements.txt"]
+        );
+        assert_eq!(markers[0].context, vec!["services/api/README.md"]);
+        assert_eq!(markers[0].options.get("model").unwrap(), "haiku");
+        assert_eq!(markers[1].rel_path, "services/web/Dockerfile");
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        let bad = |body: &str| {
+            let defs = defs(&format!("[[markers]]\n{body}"));
+            instantiate(&defs, &[], Path::new("/repo")).unwrap_err()
+        };
+        let err = bad("name = \"a b\"\napplies_to = \"*\"\ninstruction = \"x\"\n");
+        assert!(err.contains("invalid name"), "{err}");
+        let err = bad("name = \"a\"\napplies_to = \"*\"\ninstruction = \"  \"\n");
+        assert!(err.contains("no instruction"), "{err}");
+        let err = bad("name = \"a\"\napplies_to = \"[\"\ninstruction = \"x\"\n");
+        assert!(err.contains("invalid applies_to"), "{err}");
+    }
+}
@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::{self, Date};
+
+pub const WAIVERS_FILE: &str = "watcher-knight-waivers.toml";
+
+/// A time-boxed, justified exemption for a failing watcher.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Waiver {
+    pub marker: String,
+    /// Last day (inclusive, `YYYY-MM-DD`) on which the waiver applies.
+    pub until: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Waivers {
+    #[serde(default, rename = "waiver")]
+    pub waivers: Vec<Waiver>,
+}
+
+impl Waivers {
+    /// The waiver covering `marker` on `today`, if any. Expired waivers never match.
+    pub fn active_for(&self, marker: &str, today: Date) -> Option<&Waiver> {
+        self.waivers
+            .iter()
+            .find(|w| w.marker == marker && !w.is_expired(today))
+    }
+
+    /// Add a waiver, replacing any existing waiver for the same marker.
+    pub fn upsert(&mut self, waiver: Waiver) {
+        self.waivers.retain(|w| w.marker != waiver.marker);
+        self.waivers.push(waiver);
+        self.waivers.sort_by(|a, b| a.marker.cmp(&b.marker));
+    }
+}
+
+impl Waiver {
+    /// Whether the waiver no longer applies on `today`. One whose `until`
+    /// is not a date never applies.
+    pub fn is_expired(&self, today: Date) -> bool {
+        clock::parse_date(&self.until).map_or(true, |until| until < today)
+    }
+}
+
+/// Read the waivers file without checking its dates, for `lint`, which
+/// reports the bad ones.
+pub fn read_waivers(root: &Path) -> Result<Waivers, String> {
+    match fs::read_to_string(root.join(WAIVERS_FILE)) {
+        Ok(data) => toml::from_str(&data).map_err(|e| format!("invalid {WAIVERS_FILE}: {e}")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Waivers::default()),
+        Err(e) => Err(format!("cannot read {WAIVERS_FILE}: {e}")),
+    }
+}
+
+/// Read the waivers file, failing on a waiver whose `until` is not a date.
+pub fn load_waivers(root: &Path) -> Result<Waivers, String> {
+    let waivers = read_waivers(root)?;
+    for w in &waivers.waivers {
+        clock::parse_date(&w.until)
+            .map_err(|e| format!("invalid {WAIVERS_FILE}: waiver for `{}`: {e}", w.marker))?;
+    }
+    Ok(waivers)
+}
+
+pub fn save_waivers(root: &Path, waivers: &Waivers) -> Result<(), String> {
+    let data = toml::to_string(waivers).unwrap();
+    fs::write(root.join(WAIVERS_FILE), data)
+        .map_err(|e| format!("cannot write {WAIVERS_FILE}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waiver(marker: &str, until: &str) -> Waiver {
+        Waiver {
+            marker: marker.to_string(),
+            until: until.to_string(),
+            reason: "tracked in JIRA-123".to_string(),
+        }
+    }
+
+    fn date(date: &str) -> Date {
+        clock::parse_date(date).unwrap()
+    }
+
+    #[test]
+    fn active_for_matches_until_inclusive() {
+        let waivers = Waivers {
+            waivers: vec![waiver("a", "2025-03-01")],
+        };
+        assert!(waivers.active_for("a", date("2025-02-28")).is_some());
+        assert!(waivers.active_for("a", date("2025-03-01")).is_some());
+        assert!(waivers.active_for("a", date("2025-03-02")).is_none());
+        assert!(waivers.active_for("b", date("2025-02-28")).is_none());
+    }
+
+    #[test]
+    fn waiver_with_a_bad_date_never_applies() {
+        // As strings, "2025-3-1" would sort after every 2025-1x date.
+        assert!(waiver("a", "2025-3-1").is_expired(date("2025-02-01")));
+        assert!(waiver("a", "soon").is_expired(date("2025-02-01")));
+    }
+
+    #[test]
+    fn upsert_replaces_existing() {
+        let mut waivers = Waivers::default();
+        waivers.upsert(waiver("b", "2025-01-01"));
+        waivers.upsert(waiver("a", "2025-01-01"));
+        waivers.upsert(waiver("b", "2026-01-01"));
+        assert_eq!(waivers.waivers.len(), 2);
+        assert_eq!(waivers.waivers[0].marker, "a");
+        assert_eq!(waivers.waivers[1].until, "2026-01-01");
+    }
+
+    #[test]
+    fn waivers_toml_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut waivers = Waivers::default();
+        waivers.upsert(waiver("a", "2025-03-01"));
+        save_waivers(dir.path(), &waivers).unwrap();
+        let data = fs::read_to_string(dir.path().join(WAIVERS_FILE)).unwrap();
+        assert!(data.contains("[[waiver]]"), "data was: {data}");
+        let loaded = load_waivers(dir.path()).unwrap();
+        assert_eq!(loaded.waivers, waivers.waivers);
+    }
+
+    #[test]
+    fn load_waivers_missing_file_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load_waivers(dir.path()).unwrap().waivers.is_empty());
+    }
+
+    #[test]
+    fn load_waivers_rejects_bad_dates() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(WAIVERS_FILE),
+            "[[waiver]]\nmarker = \"a\"\nuntil = \"2025-3-1\"\nreason = \"r\"\n",
+        )
+        .unwrap();
+        let err = load_waivers(dir.path()).unwrap_err();
+        assert!(
+            err.contains("waiver for `a`: invalid date `2025-3-1`"),
+            "{err}"
+        );
+        assert_eq!(read_waivers(dir.path()).unwrap().waivers.len(), 1);
+    }
+}
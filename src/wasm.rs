@@ -0,0 +1,122 @@
+use std::path::Path;
+
+use wasmtime::{Config, Engine, Linker, Module, Store};
+use wasmtime_wasi::WasiCtxBuilder;
+use wasmtime_wasi::pipe::{MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+
+/// Instructions a module may execute before it is killed.
+const FUEL: u64 = 10_000_000_000;
+/// Largest verdict a module may print.
+const MAX_OUTPUT: usize = 1 << 20;
+
+/// Run a WASI command module as a checker: `request` on stdin, verdict on stdout.
+///
+/// The module gets no filesystem, environment, network or clock beyond what
+/// WASI provides by default, and a fixed fuel budget.
+pub fn run_module(path: &Path, request: &str) -> Result<String, String> {
+    run_module_with_fuel(path, request, FUEL)
+}
+
+fn run_module_with_fuel(path: &Path, request: &str, fuel: u64) -> Result<String, String> {
+    let what = path.display();
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config).map_err(|e| format!("wasm engine: {e}"))?;
+    let module = Module::from_file(&engine, path)
+        .map_err(|e| format!("cannot load WASM checker `{what}`: {e}"))?;
+
+    let mut linker: Linker<WasiP1Ctx> = Linker::new(&engine);
+    preview1::add_to_linker_sync(&mut linker, |ctx| ctx)
+        .map_err(|e| format!("wasm linker: {e}"))?;
+
+    let stdout = MemoryOutputPipe::new(MAX_OUTPUT);
+    let stderr = MemoryOutputPipe::new(MAX_OUTPUT);
+    let wasi = WasiCtxBuilder::new()
+        .stdin(MemoryInputPipe::new(request.to_string()))
+        .stdout(stdout.clone())
+        .stderr(stderr.clone())
+        .build_p1();
+    let mut store = Store::new(&engine, wasi);
+    store
+        .set_fuel(fuel)
+        .map_err(|e| format!("wasm fuel: {e}"))?;
+
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .map_err(|e| format!("cannot instantiate WASM checker `{what}`: {e}"))?;
+    let start = instance
+        .get_typed_func::<(), ()>(&mut store, "_start")
+        .map_err(|e| format!("WASM checker `{what}` has no `_start`: {e}"))?;
+
+    let code = match start.call(&mut store, ()) {
+        Ok(()) => 0,
+        Err(e) => match e.downcast_ref::<wasmtime_wasi::I32Exit>() {
+            Some(exit) => exit.0,
+            None => return Err(format!("WASM checker `{what}` trapped: {e}")),
+        },
+    };
+    drop(store);
+
+    if code != 0 {
+        return Err(format!(
+            "WASM checker `{what}` exited with {code}: {}",
+            String::from_utf8_lossy(&stderr.contents()).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&stdout.contents())
+        .trim()
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Drains stdin, then prints a fixed failing verdict.
+    const CHECKER_WAT: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 1024) "{\"is_valid\": false, \"reason\": \"from wasm\"}")
+  (func (export "_start")
+    ;; iovec at 0: read into 2048..3072 until EOF
+    (i32.store (i32.const 0) (i32.const 2048))
+    (i32.store (i32.const 4) (i32.const 1024))
+    (block $done
+      (loop $more
+        (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+        (br_if $done (i32.eqz (i32.load (i32.const 8))))
+        (br $more)))
+    ;; iovec at 16: the verdict
+    (i32.store (i32.const 16) (i32.const 1024))
+    (i32.store (i32.const 20) (i32.const 42))
+    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24)))))
+"#;
+
+    #[test]
+    fn run_module_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checker.wasm");
+        fs::write(&path, CHECKER_WAT).unwrap();
+        let out = run_module(&path, r#"{"version": 1}"#).unwrap();
+        assert_eq!(out, r#"{"is_valid": false, "reason": "from wasm"}"#);
+    }
+
+    #[test]
+    fn run_module_runaway_loop_runs_out_of_fuel() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spin.wasm");
+        fs::write(
+            &path,
+            r#"(module (func (export "_start") (loop $l (br $l))))"#,
+        )
+        .unwrap();
+        let err = run_module_with_fuel(&path, "{}", 1_000_000).unwrap_err();
+        assert!(err.contains("trapped"), "err was: {err}");
+    }
+}
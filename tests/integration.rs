@@ -27,6 +27,18 @@ fn cli_run_help() {
     assert!(stdout.contains("--no-cache"));
 }
 
+#[test]
+fn cli_suggest_help() {
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["suggest", "--help"])
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("--file"));
+    assert!(stdout.contains("--diff"));
+}
+
 #[test]
 fn cli_run_no_markers_empty_dir() {
     let dir = tempfile::tempdir().unwrap();
@@ -67,3 +79,2595 @@ fn cli_run_file_not_dir() {
     let stderr = String::from_utf8_lossy(&output.stderr);
     assert!(stderr.contains("not a directory"), "stderr was: {stderr}");
 }
+
+#[test]
+fn cli_run_missing_policy_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", dir.path().to_str().unwrap()])
+        .args(["--policy", "/tmp/wk_nonexistent_policy_12345.yaml"])
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("cannot read policy"),
+        "stderr was: {stderr}"
+    );
+}
+
+#[test]
+fn cli_run_exit_codes_by_failure_class() {
+    let dir = tempfile::tempdir().unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", dir.path().to_str().unwrap(), "--offline"])
+            .args(args)
+            .output()
+            .expect("failed to run binary")
+            .status
+            .code()
+    };
+    fs::write(dir.path().join("app.ts"), "// <wk: nameless />\n").unwrap();
+    assert_eq!(run(&[]), Some(2));
+    assert_eq!(run(&["--no-such-flag"]), Some(4));
+    fs::write(dir.path().join("watcher-knight.toml"), "no_such_key = 1\n").unwrap();
+    assert_eq!(run(&[]), Some(4));
+}
+
+#[test]
+fn cli_list_json_full() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: api-check [./app.ts] Keep the API stable. />\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args([
+            "list",
+            dir.path().to_str().unwrap(),
+            "--format",
+            "json",
+            "--full",
+        ])
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let val: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let entry = &val["markers"][0];
+    assert_eq!(entry["name"], "api-check");
+    assert_eq!(entry["location"]["file"], "app.ts");
+    assert_eq!(entry["instruction"], "Keep the API stable.");
+    assert_eq!(entry["files"][0], "app.ts");
+    assert!(entry["fingerprint"].is_string());
+}
+
+#[test]
+fn cli_classify_reports_exact_checkable_ratio() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: docs `docs/api.md` must exist. />\n\
+         // <wk: checked\n// options={assert=\"exists('./app.ts')\"}\n// The app exists. />\n\
+         // <wk: handlers Every handler validates its input. />\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["classify", ".", "--format", "json"])
+        .env("PATH", "")
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let val: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(val["total"], 3);
+    assert_eq!(val["deterministic"], 1);
+    assert_eq!(val["convertible"], 1);
+    assert_eq!(val["llm"], 1);
+    assert_eq!(val["watchers"][0]["assert"], "exists('/docs/api.md')");
+    assert_eq!(val["watchers"][2]["kind"], "llm");
+}
+
+#[test]
+fn cli_waive_then_lint_flags_expired_waiver() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.ts"), "// <wk: api-check Keep it. />\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["waive", "api-check", root, "--until", "2000-01-01"])
+        .args(["--reason", "tracked in JIRA-123"])
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let waivers = fs::read_to_string(dir.path().join("watcher-knight-waivers.toml")).unwrap();
+    assert!(waivers.contains("api-check"));
+    assert!(waivers.contains("tracked in JIRA-123"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["lint", root])
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("[expired-waiver]"), "stdout was: {stdout}");
+}
+
+#[test]
+fn cli_waive_rejects_bad_date() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["waive", "x", dir.path().to_str().unwrap()])
+        .args(["--until", "soon", "--reason", "r"])
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid date"), "stderr was: {stderr}");
+}
+
+#[test]
+fn cli_ack_unknown_marker() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["ack", "missing", dir.path().to_str().unwrap()])
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("no watcher named `missing`"),
+        "stderr was: {stderr}"
+    );
+}
+
+#[test]
+fn cli_run_offline_reports_not_run() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.ts"), "// <wk: api-check Keep it. />\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--offline"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stderr.contains("NOT RUN (offline)"), "stderr was: {stderr}");
+    assert!(stdout.contains("1 not run"), "stdout was: {stdout}");
+}
+
+#[test]
+fn cli_run_untrusted_fails_unscoped_watchers() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.ts"), "// <wk: api-check Keep it. />\n").unwrap();
+
+    // No claude on PATH: the watcher must fail before any agent starts.
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache"])
+        .current_dir(dir.path())
+        .env("WK_UNTRUSTED_PR", "1")
+        .env("PATH", "")
+        .output()
+        .expect("failed to run binary");
+    assert_eq!(output.status.code(), Some(3));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("unscoped watcher cannot be confined"),
+        "stdout was: {stdout}"
+    );
+}
+
+#[test]
+fn cli_run_pr_refuses_comment() {
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", "--pr", "https://github.com/o/r/pull/1", "--comment"])
+        .output()
+        .expect("failed to run binary");
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+}
+
+#[test]
+fn cli_run_profile_reports_phases_and_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.ts"), "// <wk: api-check Keep it. />\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--offline", "--profile"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("==== PROFILE ===="), "stderr was: {stderr}");
+    for phase in ["walk", "read", "parse", "diff", "filter", "validate"] {
+        assert!(
+            stderr.contains(&format!("\n{phase} ")),
+            "stderr was: {stderr}"
+        );
+    }
+    assert!(stderr.contains("Slowest files to scan (1 scanned):"));
+    assert!(stderr.contains("ms  app.ts\n"), "stderr was: {stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_offline_still_runs_checker_plugins() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: schema-check\n// Schema must validate.\n// options={checker=\"schema\"}\n// />\n",
+    )
+    .unwrap();
+    let checker = dir.path().join("check-schema.sh");
+    fs::write(
+        &checker,
+        "#!/bin/sh\ngrep -q '\"name\":\"schema-check\"' && echo '{\"is_valid\": false, \"reason\": \"bad schema\"}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(&checker, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        "[checkers]\nschema = \"check-schema.sh\"\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--offline"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("bad schema"), "stdout was: {stdout}");
+}
+
+#[test]
+fn cli_run_evaluates_assertions_without_a_backend() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("docs")).unwrap();
+    fs::write(dir.path().join("docs/api.md"), "# API\n").unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: api-docs [./docs/api.md]\n// options={assert=\"exists('./docs/api.md')\"}\n\
+         // The API is documented. />\n",
+    )
+    .unwrap();
+    let run = || {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", "."])
+            .env("PATH", "")
+            .current_dir(dir.path())
+            .output()
+            .expect("failed to run binary")
+    };
+
+    let output = run();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr was: {stderr}");
+    assert!(
+        stderr.contains("api-docs... \x1b[32mOK"),
+        "stderr was: {stderr}"
+    );
+
+    // Never answered from the cache: the assertion sees the file go.
+    fs::remove_file(dir.path().join("docs/api.md")).unwrap();
+    let output = run();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("does not hold"), "stdout was: {stdout}");
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_only_new_passes_pre_existing_failures() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: schema-check\n// options={checker=\"schema\"}\n// Schema must validate. />\n",
+    )
+    .unwrap();
+    let checker = dir.path().join("check.sh");
+    fs::write(
+        &checker,
+        "#!/bin/sh\necho '{\"is_valid\": false, \"reason\": \"bad schema\"}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(&checker, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        "[checkers]\nschema = \"check.sh\"\n",
+    )
+    .unwrap();
+
+    let run = || {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", ".", "--offline", "--only-new"])
+            .current_dir(dir.path())
+            .output()
+            .expect("failed to run binary")
+    };
+    // Nothing recorded yet, so the failure is new.
+    assert!(!run().status.success());
+    let output = run();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout was: {stdout}");
+    assert!(
+        stdout.contains("==== PRE-EXISTING ===="),
+        "stdout was: {stdout}"
+    );
+    assert!(stdout.contains("1 pre-existing"), "stdout was: {stdout}");
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_hooks_get_run_metadata() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.ts"), "// <wk: api-check Keep it. />\n").unwrap();
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        "pre_run = \"echo $WK_MODE > pre.txt\"\n\
+         post_run = \"echo $WK_STATUS $WK_TOTAL > post.txt && cp $WK_RESULTS results.json\"\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--offline"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let pre = fs::read_to_string(dir.path().join("pre.txt")).unwrap();
+    assert_eq!(pre.trim(), "cache");
+    let post = fs::read_to_string(dir.path().join("post.txt")).unwrap();
+    assert_eq!(post.trim(), "passed 1");
+    let results = fs::read_to_string(dir.path().join("results.json")).unwrap();
+    assert!(results.contains("\"not_run\""), "results were: {results}");
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_post_processor_can_veto() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.ts"), "// <wk: api-check Keep it. />\n").unwrap();
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        "post_processor = \"grep -q not_run && echo 'offline runs are not accepted' && exit 1\"\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--offline"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("offline runs are not accepted"),
+        "stdout was: {stdout}"
+    );
+    assert!(
+        stdout.contains("decided by post-processor"),
+        "stdout was: {stdout}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_format_compact() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: schema-check\n// options={checker=\"schema\", severity=\"warning\"}\n// Schema must validate. />\n",
+    )
+    .unwrap();
+    let checker = dir.path().join("check.sh");
+    fs::write(
+        &checker,
+        "#!/bin/sh\necho '{\"is_valid\": false, \"reason\": \"bad\\\\nschema\"}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(&checker, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        "[checkers]\nschema = \"check.sh\"\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--offline", "--format", "compact"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "app.ts:1: [warning] schema-check: bad schema\n");
+}
+
+#[test]
+fn cli_run_only_and_suite_filter_watchers() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: api-check Keep it. />\n\
+         // <wk: db-check\n// options={suite=\"db, nightly\"}\n// Keep the schema. />\n\
+         // <wk: ui-check Keep the UI. />\n",
+    )
+    .unwrap();
+
+    let run = |extra: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", ".", "--offline"])
+            .args(extra)
+            .current_dir(dir.path())
+            .output()
+            .expect("failed to run binary");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    assert!(run(&["--only", "api-check"]).contains("1 not run"));
+    assert!(run(&["--suite", "nightly", "--only", "ui-check"]).contains("2 not run"));
+}
+
+#[test]
+fn cli_run_shard_splits_watchers_by_expected_cost() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        "[suites.db]\ncost = \"5m\"\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: db-check\n// options={suite=\"db\"}\n// Keep the schema. />\n\
+         // <wk: api-check Keep it. />\n\
+         // <wk: ui-check\n// options={cost=\"45s\"}\n// Keep the UI. />\n",
+    )
+    .unwrap();
+
+    let run = |shard: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", ".", "--offline", "--shard", shard])
+            .current_dir(dir.path())
+            .output()
+            .expect("failed to run binary");
+        assert_eq!(output.status.code(), Some(0));
+        String::from_utf8_lossy(&output.stderr).to_string()
+    };
+    // The db suite's watcher costs more than the other two together.
+    let first = run("1/2");
+    assert!(first.contains("db-check") && first.contains("running 1 watchers"));
+    let second = run("2/2");
+    assert!(second.contains("api-check") && second.contains("ui-check"));
+    assert!(!second.contains("db-check"));
+}
+
+#[test]
+fn cli_completions_bash() {
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["completions", "bash"])
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("COMPLETE=\"bash\""), "stdout was: {stdout}");
+}
+
+#[test]
+fn cli_man_prints_roff() {
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .arg("man")
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(".TH watcher-knight"),
+        "stdout was: {stdout}"
+    );
+    assert!(
+        stdout.contains(".SH \"MARKER SYNTAX\""),
+        "stdout was: {stdout}"
+    );
+}
+
+#[test]
+fn cli_self_update_check_reads_feed() {
+    let dir = tempfile::tempdir().unwrap();
+    let feed = dir.path().join("latest.json");
+    fs::write(&feed, r#"{"tag_name": "v999.0.0", "assets": []}"#).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["self-update", "--check"])
+        .env("WK_RELEASE_FEED", format!("file://{}", feed.display()))
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("999.0.0 is available"),
+        "stdout was: {stdout}"
+    );
+}
+
+#[test]
+fn cli_self_update_refuses_without_signing_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let feed = dir.path().join("latest.json");
+    fs::write(&feed, r#"{"tag_name": "v999.0.0", "assets": []}"#).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .arg("self-update")
+        .env("WK_RELEASE_FEED", format!("file://{}", feed.display()))
+        .output()
+        .expect("failed to run binary");
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--insecure"), "stderr was: {stderr}");
+}
+
+#[cfg(windows)]
+#[test]
+fn cli_run_on_windows_uses_claude_cmd_shim_and_slash_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(
+        dir.path().join("src").join("app.ts"),
+        "// <wk: api-check [.\\app.ts]\r\n// Must hold.\r\n// />\r\n",
+    )
+    .unwrap();
+    // npm installs claude as a .cmd shim.
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude.cmd"),
+        "@echo off\r\nmore > nul\r\necho {\"is_valid\": false, \"reason\": \"seen by shim\"}\r\n",
+    )
+    .unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache", "--report", "json=report.json"])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("seen by shim"), "stdout was: {stdout}");
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.path().join("report.json")).unwrap()).unwrap();
+    assert_eq!(json["results"][0]["location"], "src/app.ts:1");
+    let provenance = &json["provenance"];
+    assert_eq!(provenance["tool"], "watcher-knight");
+    assert_eq!(provenance["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(provenance["backend"], "claude");
+    assert_eq!(provenance["models"], serde_json::json!(["sonnet"]));
+    assert_eq!(provenance["markers_sha256"].as_str().unwrap().len(), 64);
+    assert!(provenance["config_sha256"].is_null());
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_dispatches_to_remote_worker() {
+    use std::net::{TcpListener, TcpStream};
+    use std::os::unix::fs::PermissionsExt;
+    use std::thread;
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: remote-check\n// Must hold.\n// />\n",
+    )
+    .unwrap();
+    // A stand-in for the claude CLI on the worker.
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\ncat > /dev/null\necho '{\"is_valid\": false, \"reason\": \"seen by worker\"}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let addr = format!("127.0.0.1:{port}");
+    let mut worker = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["worker", ".", "--listen", &addr])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .env("WK_WORKER_TOKEN", "s3cret")
+        .spawn()
+        .expect("failed to start worker");
+    for _ in 0..100 {
+        if TcpStream::connect(&addr).is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args([
+            "run",
+            ".",
+            "--no-cache",
+            "--worker",
+            &format!("http://{addr}"),
+        ])
+        .current_dir(dir.path())
+        .env("PATH", std::env::var("PATH").unwrap())
+        .env("WK_WORKER_TOKEN", "s3cret")
+        .output()
+        .expect("failed to run binary");
+    worker.kill().ok();
+    worker.wait().ok();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("seen by worker"), "stdout was: {stdout}");
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_reports_claude_telemetry() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.ts"), "// <wk: api-check Keep it. />\n").unwrap();
+    // A stand-in for the claude CLI that streams events.
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        r#"#!/bin/sh
+cat > /dev/null
+echo '{"type":"system","subtype":"init","session_id":"s1"}'
+echo '{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t1","name":"Read","input":{}}]}}'
+echo '{"type":"result","is_error":false,"result":"{\"is_valid\": true}","num_turns":2,"usage":{"input_tokens":900,"output_tokens":100}}'
+"#,
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache", "--report", "json=report.json"])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("(1 tool call, 2 turns, 1.0k tokens)"),
+        "stderr was: {stderr}"
+    );
+    let report: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.path().join("report.json")).unwrap()).unwrap();
+    assert_eq!(
+        report["results"][0]["telemetry"],
+        serde_json::json!({"tool_calls": 1, "turns": 2, "input_tokens": 900, "output_tokens": 100})
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_writes_partial_report_on_sigterm() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::thread;
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: fast-check Keep it. />\n// <wk: slow-check Keep it too. />\n",
+    )
+    .unwrap();
+    // A stand-in for the claude CLI that answers one watcher and keeps
+    // working on the other.
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\nif grep -q 'Keep it too' ; then touch slow-started; \
+         for i in $(seq 300); do touch alive; sleep 0.1; done; fi\n\
+         echo '{\"is_valid\": true}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache", "--report", "json=report.json"])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .expect("failed to run binary");
+    for _ in 0..100 {
+        if dir.path().join("slow-started").exists() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    // Give the fast watcher time to report.
+    thread::sleep(Duration::from_millis(500));
+    let killed = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    let status = child.wait().unwrap();
+    assert_eq!(status.code(), Some(143));
+    // The agent was stopped with the run.
+    fs::remove_file(dir.path().join("alive")).unwrap();
+    thread::sleep(Duration::from_millis(500));
+    assert!(!dir.path().join("alive").exists());
+
+    let report: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.path().join("report.json")).unwrap()).unwrap();
+    assert_eq!(report["status"], "cancelled");
+    let statuses: Vec<(&str, &str)> = report["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| (r["name"].as_str().unwrap(), r["status"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![("fast-check", "passed"), ("slow-check", "inconclusive")]
+    );
+    assert_eq!(report["results"][1]["note"], "run terminated");
+}
+
+#[test]
+fn cli_query_counts_recorded_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.ts"), "// <wk: api-check Keep it. />\n").unwrap();
+    for _ in 0..2 {
+        let status = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", ".", "--offline"])
+            .current_dir(dir.path())
+            .output()
+            .expect("failed to run binary")
+            .status;
+        assert!(status.success());
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args([
+            "query",
+            ".",
+            "--status",
+            "not_run",
+            "--since",
+            "1d",
+            "--min-count",
+            "2",
+        ])
+        .args(["--format", "json"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["markers"][0]["name"], "api-check");
+    assert_eq!(json["markers"][0]["matches"], 2);
+}
+
+#[test]
+fn cli_run_writes_report_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.ts"), "// <wk: api-check Keep it. />\n").unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--offline"])
+        .args([
+            "--report",
+            "html=report.html",
+            "--report",
+            "json=report.json",
+        ])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary")
+        .status;
+    assert!(status.success());
+
+    let html = fs::read_to_string(dir.path().join("report.html")).unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("api-check"));
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.path().join("report.json")).unwrap()).unwrap();
+    assert_eq!(json["results"][0]["status"], "not_run");
+}
+
+#[test]
+fn cli_run_strict_rejects_missing_file_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: api-check [./ap.ts] Keep it. />\n",
+    )
+    .unwrap();
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", ".", "--offline"])
+            .args(extra)
+            .current_dir(dir.path())
+            .output()
+            .expect("failed to run binary")
+    };
+
+    let output = run(&[]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("[WARNING] app.ts:1: [missing-file]"),
+        "{stderr}"
+    );
+
+    let output = run(&["--strict"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Error: app.ts:1: [missing-file] watcher `api-check` lists `ap.ts`"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn cli_run_strict_rejects_unclosed_markers() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: forgot Keep it.\n// <wk: api-check Keep it. />\n",
+    )
+    .unwrap();
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", ".", "--offline"])
+            .args(extra)
+            .current_dir(dir.path())
+            .output()
+            .expect("failed to run binary")
+    };
+
+    let output = run(&[]);
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("[WARNING] app.ts:1: unclosed watcher tag"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("[1/1] api-check"), "{stderr}");
+
+    let output = run(&["--strict"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("api-check"), "{stderr}");
+    assert!(
+        stderr.contains("Error: app.ts:1: unclosed watcher tag"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn cli_map_annotates_covered_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("api")).unwrap();
+    fs::write(dir.path().join("api/handler.ts"), "export {};\n").unwrap();
+    fs::write(dir.path().join("api/util.ts"), "export {};\n").unwrap();
+    fs::write(
+        dir.path().join("README.md"),
+        "<!-- <wk: api-check [./api/handler.ts] Keep it. /> -->\n",
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["map", "api"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("api  1/2 files covered: api-check\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("├── handler.ts  api-check\n"), "{stdout}");
+    assert!(stdout.contains("└── util.ts\n"), "{stdout}");
+}
+
+#[test]
+fn cli_badge_without_history_is_unknown() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["badge", ".", "--format", "json"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["message"], "unknown");
+}
+
+#[test]
+fn cli_diff_results_fails_on_regressions() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("old.json"),
+        r#"{"version": 2, "results": [
+            {"name": "api", "location": "a.ts:1", "status": "passed"},
+            {"name": "docs", "location": "b.ts:1", "status": "failed"}
+        ]}"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("new.json"),
+        r#"{"version": 2, "results": [
+            {"name": "api", "location": "a.ts:3", "status": "failed", "reason": "Route is unversioned."},
+            {"name": "docs", "location": "b.ts:1", "status": "passed"}
+        ]}"#,
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["diff-results", "old.json", "new.json"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("api (a.ts:3): passed -> failed\n  Route is unversioned."),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("1 regressed, 1 fixed, 0 added, 0 removed"),
+        "{stdout}"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["diff-results", "new.json", "old.json", "--format", "json"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert_eq!(output.status.code(), Some(1));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["fixes"][0]["name"], "api");
+    assert_eq!(json["regressions"][0]["name"], "docs");
+    assert_eq!(json["regressions"][0]["location"], "b.ts:1");
+}
+
+#[test]
+fn cli_run_against_snapshot_diffs_without_vcs() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: api-check [./app.ts] Keep it. />\nexport {};\n",
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["snapshot", "create", "."])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    assert_eq!(id.len(), 12, "{id}");
+
+    let run = || {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", ".", "--offline", "--against-snapshot", &id])
+            .current_dir(dir.path())
+            .output()
+            .expect("failed to run binary")
+    };
+    let output = run();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("No changes since {id}")),
+        "{stderr}"
+    );
+
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: api-check [./app.ts] Keep it. />\nexport const x = 1;\n",
+    )
+    .unwrap();
+    let output = run();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("running 1 watchers"), "{stderr}");
+}
+
+#[test]
+fn cli_stats_summarizes_history() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.ts"), "// <wk: api-check Keep it. />\n").unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--offline"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary")
+        .status;
+    assert!(status.success());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["stats", ".", "--format", "json"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["runs"], 1);
+
+    // Offline runs validate nothing, so there are no trends to report.
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["stats", ".", "--trends", "--format", "json"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["markers"], serde_json::json!([]));
+}
+
+#[test]
+fn cli_stats_owners_lists_watchers_of_inactive_authors() {
+    let dir = tempfile::tempdir().unwrap();
+    let git = |args: &[&str], who: &str, date: &str| {
+        let status = Command::new("git")
+            .args(args)
+            .env("GIT_AUTHOR_NAME", who)
+            .env("GIT_AUTHOR_EMAIL", format!("{who}@example.com"))
+            .env("GIT_AUTHOR_DATE", date)
+            .env("GIT_COMMITTER_NAME", who)
+            .env("GIT_COMMITTER_EMAIL", format!("{who}@example.com"))
+            .env("GIT_COMMITTER_DATE", date)
+            .current_dir(dir.path())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    git(&["init", "-q"], "alice", "2020-01-01T00:00:00");
+    fs::write(
+        dir.path().join("old.ts"),
+        "// <wk: legacy Keep it. />
+",
+    )
+    .unwrap();
+    git(&["add", "."], "alice", "2020-01-01T00:00:00");
+    git(&["commit", "-qm", "old"], "alice", "2020-01-01T00:00:00");
+    fs::write(
+        dir.path().join("new.ts"),
+        "// <wk: fresh Keep it. />
+",
+    )
+    .unwrap();
+    git(&["add", "."], "bob", "2020-01-01T00:00:00");
+    git(&["commit", "-qm", "new"], "bob", "2099-01-01T00:00:00");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["stats", ".", "--owners", "--format", "json"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let markers = json["markers"].as_array().unwrap();
+    assert_eq!(markers.len(), 1, "markers were: {markers:?}");
+    assert_eq!(markers[0]["name"], "legacy");
+    assert_eq!(markers[0]["email"], "alice@example.com");
+    assert_eq!(markers[0]["last_commit"], "2020-01-01");
+}
+
+#[test]
+fn cli_fmt_check_then_rewrite() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.ts"), "//<wk:api-check   Keep it. />\n").unwrap();
+    fs::write(dir.path().join("ok.ts"), "// <wk: ok Fine. />\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["fmt", root, "--check"])
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "app.ts\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["fmt", root])
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("app.ts")).unwrap(),
+        "// <wk: api-check Keep it. />\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["fmt", root, "--check"])
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+}
+
+#[test]
+fn cli_custom_tag_keyword_is_scanned_linted_and_formatted() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        "tag_keywords = [\"guard\"]\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <Guard :api-check Keep it. />\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("ok.ts"), "// <wk: ok Fine. />\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+    let wk = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(args)
+            .output()
+            .expect("failed to run binary")
+    };
+
+    let output = wk(&["list", root, "--format", "json"]);
+    assert!(output.status.success(), "{output:?}");
+    let val: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(val["markers"].as_array().unwrap().len(), 2);
+
+    let output = wk(&["lint", root]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("write `<guard: api-check`"), "{stdout}");
+
+    assert!(wk(&["fmt", root]).status.success());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("app.ts")).unwrap(),
+        "// <guard: api-check Keep it. />\n"
+    );
+
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        "tag_keywords = [\"<guard>\"]\n",
+    )
+    .unwrap();
+    let output = wk(&["lint", root]);
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid tag keyword `<guard>`"), "{stderr}");
+}
+
+#[test]
+fn cli_rename_updates_marker_and_waivers() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.ts"), "// <wk: api-check Keep it. />\n").unwrap();
+    fs::write(dir.path().join("other.ts"), "// <wk: taken Keep it. />\n").unwrap();
+    let root = dir.path().to_str().unwrap();
+    let wk = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(args)
+            .output()
+            .expect("failed to run binary")
+    };
+
+    let waive = ["waive", "api-check", root, "--until", "2099-01-01"];
+    assert!(
+        wk(&[&waive[..], &["--reason", "JIRA-1"]].concat())
+            .status
+            .success()
+    );
+
+    let output = wk(&["rename", "api-check", "taken", root]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+
+    let output = wk(&["rename", "api-check", "api-contract", root]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(dir.path().join("app.ts")).unwrap(),
+        "// <wk: api-contract Keep it. />\n"
+    );
+    let waivers = fs::read_to_string(dir.path().join("watcher-knight-waivers.toml")).unwrap();
+    assert!(waivers.contains("\"api-contract\""), "{waivers}");
+    assert!(!waivers.contains("api-check"));
+}
+
+#[test]
+fn cli_list_includes_config_markers() {
+    let dir = tempfile::tempdir().unwrap();
+    for service in ["api", "web"] {
+        fs::create_dir_all(dir.path().join("services").join(service)).unwrap();
+        fs::write(
+            dir.path().join("services").join(service).join("Dockerfile"),
+            "FROM alpine\n",
+        )
+        .unwrap();
+    }
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        "[[markers]]\n\
+         name = \"pinned-base\"\n\
+         applies_to = \"services/*/Dockerfile\"\n\
+         instruction = \"Base images are pinned by digest.\"\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["list", dir.path().to_str().unwrap(), "--format", "json"])
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success(), "{output:?}");
+    let val: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let files: Vec<&str> = val["markers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["location"]["file"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        files,
+        vec!["services/api/Dockerfile", "services/web/Dockerfile"]
+    );
+}
+
+#[test]
+fn cli_list_includes_markers_declared_in_config_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("deploy.yaml"),
+        "kind: Deployment\n\
+         metadata:\n  \
+           x-watcher-knight:\n    \
+             name: replicas\n    \
+             instruction: Keep at least two replicas.\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("package.json"),
+        r#"{"x-watcher-knight": [{"name": "engines", "instruction": "Pin node."}]}"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("Cargo.toml"),
+        "[package]\n#:wk msrv Keep rust-version in CI.\nrust-version = \"1.85\"\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["list", dir.path().to_str().unwrap(), "--format", "json"])
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success(), "{output:?}");
+    let val: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let mut found: Vec<String> = val["markers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| {
+            format!(
+                "{}:{} {}",
+                m["location"]["file"].as_str().unwrap(),
+                m["location"]["line"],
+                m["name"].as_str().unwrap()
+            )
+        })
+        .collect();
+    found.sort();
+    assert_eq!(
+        found,
+        vec![
+            "Cargo.toml:2 msrv",
+            "deploy.yaml:4 replicas",
+            "package.json:1 engines"
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_ratchet_fails_only_on_more_failures() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let watcher = |name: &str| {
+        format!("// <wk: {name}\n// options={{checker=\"schema\"}}\n// Schema must validate. />\n")
+    };
+    fs::write(dir.path().join("app.ts"), watcher("schema-check")).unwrap();
+    let checker = dir.path().join("check.sh");
+    fs::write(
+        &checker,
+        "#!/bin/sh\necho '{\"is_valid\": false, \"reason\": \"bad schema\"}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(&checker, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        "[checkers]\nschema = \"check.sh\"\n",
+    )
+    .unwrap();
+
+    let run = || {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", ".", "--offline", "--ratchet"])
+            .current_dir(dir.path())
+            .output()
+            .expect("failed to run binary")
+    };
+    let output = run();
+    assert!(output.status.success(), "{output:?}");
+    let baseline = fs::read_to_string(dir.path().join("watcher-knight-ratchet.toml")).unwrap();
+    assert!(baseline.contains("error = 1"), "{baseline}");
+    assert!(run().status.success());
+
+    fs::write(dir.path().join("other.ts"), watcher("other-check")).unwrap();
+    let output = run();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("ratchet: error failures rose from 1 to 2"),
+        "{stdout}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_fail_on_ignores_lower_severities() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: style-check\n// options={checker=\"schema\", severity=\"warning\"}\n\
+         // Schema must validate. />\n",
+    )
+    .unwrap();
+    let checker = dir.path().join("check.sh");
+    fs::write(
+        &checker,
+        "#!/bin/sh\necho '{\"is_valid\": false, \"reason\": \"bad schema\"}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(&checker, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        "[checkers]\nschema = \"check.sh\"\n",
+    )
+    .unwrap();
+
+    let run = |fail_on: &str| {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", ".", "--offline", "--fail-on", fail_on])
+            .current_dir(dir.path())
+            .output()
+            .expect("failed to run binary")
+    };
+    assert!(run("error").status.success());
+    assert!(!run("warn").status.success());
+    assert!(!run("any").status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_summarize_adds_narrative_to_output_and_reports() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(
+        dir.path().join("src/app.ts"),
+        "// <wk: api-check [./app.ts]\n// Must hold.\n// />\n",
+    )
+    .unwrap();
+    // Fails every watcher and answers the summary prompt with a narrative.
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\n\
+         if grep -q 'summarizing a watcher-knight run'; then\n\
+         echo 'Fix src/app.ts first.'\n\
+         else\n\
+         echo '{\"is_valid\": false, \"reason\": \"broken\"}'\n\
+         fi\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache", "--summarize"])
+        .args([
+            "--report",
+            "json=report.json",
+            "--report",
+            "markdown=comment.md",
+        ])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("==== SUMMARY ====\x1b[0m\n\nFix src/app.ts first."),
+        "stdout was: {stdout}"
+    );
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.path().join("report.json")).unwrap()).unwrap();
+    assert_eq!(json["narrative"], "Fix src/app.ts first.");
+    let comment = fs::read_to_string(dir.path().join("comment.md")).unwrap();
+    assert!(comment.contains(
+        "\nFix src/app.ts first.\n\n### Failures\n\n- **api-check** (`src/app.ts:1`): broken\n"
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_validates_large_scopes_in_chunks() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("src/a")).unwrap();
+    fs::create_dir_all(dir.path().join("src/b")).unwrap();
+    fs::write(dir.path().join("src/a/one.ts"), "").unwrap();
+    fs::write(dir.path().join("src/b/two.ts"), "").unwrap();
+    fs::write(
+        dir.path().join("main.ts"),
+        "// <wk: handlers [./src/]\n// Handlers must log errors.\n// />\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("watcher-knight.toml"), "chunk_files = 1\n").unwrap();
+    // Fails only the chunk holding src/b/.
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\n\
+         if grep -q -- '- src/b/two.ts'; then\n\
+         echo '{\"is_valid\": false, \"reason\": \"two.ts swallows errors\"}'\n\
+         else\n\
+         echo '{\"is_valid\": true}'\n\
+         fi\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache"])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("[src/b/] two.ts swallows errors"),
+        "stdout was: {stdout}"
+    );
+    assert!(!stdout.contains("[src/a/]"), "stdout was: {stdout}");
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_command_output_strategy_inlines_the_output() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("main.ts"),
+        "// <wk: tests-pass [./main.ts]\n\
+         // options={strategy=\"command-output\", command=\"tests\"}\n\
+         // The test suite passes.\n\
+         // />\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        "[commands]\ntests = \"echo 3 passed; echo 1 failed; exit 1\"\n",
+    )
+    .unwrap();
+    // Fails when the prompt carries the failing output, and records the
+    // tools it was given.
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\n\
+         echo \"$@\" > args.txt\n\
+         if grep -q -- '1 failed'; then\n\
+         echo '{\"is_valid\": false, \"reason\": \"one test fails\"}'\n\
+         else\n\
+         echo '{\"is_valid\": true}'\n\
+         fi\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache"])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("one test fails"), "stdout was: {stdout}");
+    let args = fs::read_to_string(dir.path().join("args.txt")).unwrap();
+    assert!(!args.contains("Read"), "args were: {args}");
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_refuses_prompts_over_the_model_context() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let instruction = "Every handler logs its errors. ".repeat(25_000);
+    fs::write(
+        dir.path().join("main.ts"),
+        format!("// <wk: huge [./main.ts]\n// {instruction}\n// />\n"),
+    )
+    .unwrap();
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\ntouch called\necho '{\"is_valid\": true}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache"])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("prompt too large"), "stdout was: {stdout}");
+    assert!(!dir.path().join("called").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_privacy_scrubs_prompts() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("main.ts"),
+        "// <wk: proxy [./main.ts]\n\
+         // Calls to build01.corp.example.com go through /opt/proxy/bin/relay.\n\
+         // />\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        "[privacy]\nidentifiers = ['[a-z0-9]+\\.corp\\.example\\.com']\n",
+    )
+    .unwrap();
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\ncat > prompt.txt\necho '{\"is_valid\": true}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache"])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .output()
+        .expect("failed to run binary");
+    assert!(output.status.success());
+    let prompt = fs::read_to_string(dir.path().join("prompt.txt")).unwrap();
+    assert!(!prompt.contains("corp.example.com"), "prompt was: {prompt}");
+    assert!(!prompt.contains("/opt/proxy"), "prompt was: {prompt}");
+    assert!(prompt.contains("Calls to <id:"), "prompt was: {prompt}");
+    assert!(
+        prompt.contains(" go through <path:"),
+        "prompt was: {prompt}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_audit_full_validates_every_watcher_without_the_cache() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("main.ts"),
+        "// <wk: tree Keep it. />\n\
+         // <wk: changes\n\
+         // options={strategy=\"diff-only\"}\n\
+         // No new TODOs.\n\
+         // />\n",
+    )
+    .unwrap();
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\ncat >> prompts.txt\necho '{\"is_valid\": true}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let audit = || {
+        let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["audit", ".", "--full", "--report", "json=audit.json"])
+            .current_dir(dir.path())
+            .env("PATH", &path)
+            .output()
+            .expect("failed to run binary");
+        assert!(output.status.success());
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.path().join("audit.json")).unwrap())
+                .unwrap();
+        report["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["status"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    // The diff-only watcher is checked against the tree, and a second audit
+    // validates both again instead of reading the cache.
+    assert_eq!(audit(), ["passed", "passed"]);
+    assert_eq!(audit(), ["passed", "passed"]);
+    let prompts = fs::read_to_string(dir.path().join("prompts.txt")).unwrap();
+    assert_eq!(prompts.matches("You are validating").count(), 4);
+    assert!(prompts.contains("ALWAYS use Read/Grep/Glob"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["audit", "."])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert!(!output.status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_audit_compare_last_reports_flips_and_commit_range() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .args(args)
+            .env("GIT_AUTHOR_NAME", "a")
+            .env("GIT_AUTHOR_EMAIL", "a@example.com")
+            .env("GIT_COMMITTER_NAME", "a")
+            .env("GIT_COMMITTER_EMAIL", "a@example.com")
+            .current_dir(dir.path())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    git(&["init", "-q"]);
+    fs::write(dir.path().join(".gitignore"), "bin/\n.watcher_knight/\n").unwrap();
+    fs::write(dir.path().join("main.ts"), "// <wk: api Keep it. />\n").unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-qm", "one"]);
+    // Fails once a file named `broken` exists.
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\ncat > /dev/null\nif [ -e broken ]; then\n\
+         echo '{\"is_valid\": false, \"reason\": \"api drifted\"}'\nelse\n\
+         echo '{\"is_valid\": true}'\nfi\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let audit = || {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["audit", ".", "--full", "--compare-last"])
+            .current_dir(dir.path())
+            .env("PATH", &path)
+            .output()
+            .expect("failed to run binary")
+    };
+
+    let output = audit();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No earlier audit to compare with"));
+
+    fs::write(dir.path().join("broken"), "").unwrap();
+    git(&["add", "broken"]);
+    git(&["commit", "-qm", "two"]);
+    let output = audit();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("(1 commit)"), "stdout was: {stdout}");
+    assert!(
+        stdout.contains("Now failing:\n  api (main.ts:1): passed -> failed\n    api drifted\n"),
+        "stdout was: {stdout}"
+    );
+    assert!(stdout.contains("Bisect with: git bisect start "));
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_diff_guards_removed_and_weakened_watchers() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .args(args)
+            .env("GIT_AUTHOR_NAME", "a")
+            .env("GIT_AUTHOR_EMAIL", "a@example.com")
+            .env("GIT_COMMITTER_NAME", "a")
+            .env("GIT_COMMITTER_EMAIL", "a@example.com")
+            .current_dir(dir.path())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    git(&["init", "-q"]);
+    fs::write(dir.path().join(".gitignore"), "bin/\n.watcher_knight/\n").unwrap();
+    fs::write(
+        dir.path().join("main.ts"),
+        "// <wk: auth\n// options={owner=\"alice\"}\n// Every handler checks the session.\n// />\n\
+         // <wk: style Handlers are named after routes. />\n",
+    )
+    .unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-qm", "base"]);
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\nif grep -q 'reviewing a change to a code invariant'; then\n\
+         echo '{\"weakens\": true, \"reason\": \"only some handlers are checked\"}'\nelse\n\
+         echo '{\"is_valid\": true}'\nfi\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let run = |extra: &[&str], approvers: &str| {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", ".", "--diff", "HEAD", "--no-cache"])
+            .args(extra)
+            .current_dir(dir.path())
+            .env("PATH", &path)
+            .env("WK_APPROVED_BY", approvers)
+            .output()
+            .expect("failed to run binary")
+    };
+
+    // `auth` is weakened and `style` removed.
+    fs::write(
+        dir.path().join("main.ts"),
+        "// <wk: auth\n// options={owner=\"alice\"}\n// Most handlers check the session.\n// />\n",
+    )
+    .unwrap();
+    let output = run(&[], "");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "Error: watcher `auth` (main.ts:1): the edit weakens it: only some handlers are checked."
+        ),
+        "stderr was: {stderr}"
+    );
+    assert!(
+        stderr.contains("Error: watcher `style` (main.ts:5): it was removed."),
+        "stderr was: {stderr}"
+    );
+
+    // Alice owns `auth`, but nobody owns `style`.
+    let output = run(&[], "@alice");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("approved by its owner alice"),
+        "stderr was: {stderr}"
+    );
+    assert!(
+        !stderr.contains("Error: watcher `auth`"),
+        "stderr was: {stderr}"
+    );
+
+    let output = run(&["--allow-marker-changes"], "");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("allowed by --allow-marker-changes"),
+        "stderr was: {stderr}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_pr_validates_a_pull_request_in_a_temporary_clone() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let upstream = dir.path().join("upstream");
+    fs::create_dir(&upstream).unwrap();
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .args(args)
+            .env("GIT_AUTHOR_NAME", "a")
+            .env("GIT_AUTHOR_EMAIL", "a@example.com")
+            .env("GIT_COMMITTER_NAME", "a")
+            .env("GIT_COMMITTER_EMAIL", "a@example.com")
+            .current_dir(&upstream)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+    git(&["init", "-q", "-b", "main"]);
+    fs::write(upstream.join("main.ts"), "export {};\n").unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-qm", "base"]);
+    let base = git(&["rev-parse", "HEAD"]);
+    git(&["checkout", "-qb", "feature"]);
+    fs::write(
+        upstream.join("api.ts"),
+        "// <wk: api-logged [./api.ts]\n// options={tools=\"Read,Bash\"}\n\
+         // Handlers must log errors.\n// />\n",
+    )
+    .unwrap();
+    // The pull request's own config must not run.
+    let pwned = dir.path().join("pwned");
+    fs::write(
+        upstream.join("watcher-knight.toml"),
+        format!("pre_run = \"touch {}\"\n", pwned.display()),
+    )
+    .unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-qm", "add api"]);
+    git(&["update-ref", "refs/pull/7/head", "feature"]);
+    git(&["checkout", "-q", "main"]);
+
+    let api = dir.path().join("api");
+    fs::create_dir_all(api.join("repos/org/repo/pulls")).unwrap();
+    fs::write(
+        api.join("repos/org/repo/pulls/7"),
+        format!(
+            r#"{{"title": "Add the API", "base": {{"sha": "{base}", "ref": "main", "repo": {{"clone_url": "{}"}}}}}}"#,
+            upstream.display()
+        ),
+    )
+    .unwrap();
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    let claude_args = dir.path().join("claude-args");
+    fs::write(
+        bin.join("claude"),
+        format!(
+            "#!/bin/sh\necho \"$@\" > {}\ncat > /dev/null\n\
+             echo '{{\"type\":\"result\",\"is_error\":false,\"result\":\"{{\\\"is_valid\\\": false, \\\"reason\\\": \\\"nothing is logged\\\"}}\"}}'\n",
+            claude_args.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let tmp = dir.path().join("tmp");
+    fs::create_dir(&tmp).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args([
+            "run",
+            ".",
+            "--diff",
+            "--pr",
+            "https://github.com/org/repo/pull/7",
+        ])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .env("GITHUB_API_URL", format!("file://{}", api.display()))
+        .env("TMPDIR", &tmp)
+        .output()
+        .expect("failed to run binary");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "stderr was: {stderr}");
+    assert!(
+        stderr.contains("Checking org/repo#7 \"Add the API\""),
+        "stderr was: {stderr}"
+    );
+    assert!(
+        stdout.contains("api-logged") && stdout.contains("nothing is logged"),
+        "stdout was: {stdout}"
+    );
+    assert!(!pwned.exists());
+    let claude_args = fs::read_to_string(&claude_args).unwrap();
+    assert!(
+        claude_args.contains("--allowedTools Read(/")
+            && claude_args.trim_end().ends_with("/api.ts)"),
+        "claude was run with: {claude_args}"
+    );
+    // The clone is gone and the upstream untouched.
+    assert_eq!(fs::read_dir(&tmp).unwrap().count(), 0);
+    assert!(!upstream.join("api.ts").exists());
+
+    for args in [
+        &["run", "--pr", "https://github.com/org/repo/issues/7"][..],
+        &["run", "/etc", "--pr", "https://github.com/org/repo/pull/7"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(args)
+            .output()
+            .expect("failed to run binary");
+        assert_eq!(output.status.code(), Some(4));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_uploads_the_report_to_the_configured_url() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::os::unix::fs::PermissionsExt;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/runs", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let mut stream = listener.incoming().next().unwrap().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut head = Vec::new();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                length = v.trim().parse().unwrap();
+            }
+            head.push(line.trim().to_string());
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        write!(
+            stream,
+            "HTTP/1.1 202 X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )
+        .unwrap();
+        (head, String::from_utf8(body).unwrap())
+    });
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("watcher-knight.toml"),
+        format!("[report]\nupload_url = \"{url}\"\n"),
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("main.ts"),
+        "// <wk: logged [./main.ts]\n// Handlers must log errors.\n// />\n",
+    )
+    .unwrap();
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\ncat > /dev/null\necho '{\"is_valid\": false, \"reason\": \"nothing is logged\"}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache"])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .env("WK_REPORT_TOKEN", "s3cret")
+        .output()
+        .expect("failed to run binary");
+    assert_eq!(output.status.code(), Some(1));
+    let (head, body) = server.join().unwrap();
+    assert_eq!(head[0], "POST /runs HTTP/1.1");
+    assert!(head.contains(&"Authorization: Bearer s3cret".to_string()));
+    let upload: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(upload["mode"], "cache");
+    assert_eq!(upload["report"]["status"], "failed");
+    assert_eq!(upload["report"]["results"][0]["name"], "logged");
+    assert_eq!(
+        upload["report"]["results"][0]["reason"],
+        "nothing is logged"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_exports_prompts_and_imports_verdicts() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("main.ts"),
+        "// <wk: logged [./main.ts]\n// Handlers must log errors.\n// />\n",
+    )
+    .unwrap();
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\ntouch called\necho '{\"is_valid\": true}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let run = |flag: &str| {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", ".", "--no-cache", flag, "out"])
+            .current_dir(dir.path())
+            .env("PATH", &path)
+            .output()
+            .expect("failed to run binary")
+    };
+
+    let output = run("--export-prompts");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Exported 1 prompts to out"),
+        "stderr was: {stderr}"
+    );
+    let exported: Vec<_> = fs::read_dir(dir.path().join("out"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    assert_eq!(exported.len(), 1);
+    let request: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&exported[0]).unwrap()).unwrap();
+    assert_eq!(request["name"], "logged");
+    assert!(
+        request["prompt"]
+            .as_str()
+            .unwrap()
+            .contains("Handlers must log errors.")
+    );
+
+    let verdict = exported[0]
+        .to_str()
+        .unwrap()
+        .replace(".prompt.json", ".verdict.json");
+    fs::write(
+        verdict,
+        "{\"is_valid\": false, \"reason\": \"the handler swallows errors\"}",
+    )
+    .unwrap();
+    let output = run("--import-verdicts");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("the handler swallows errors"),
+        "stdout was: {stdout}"
+    );
+    assert!(!dir.path().join("called").exists());
+}
+
+#[test]
+fn cli_prune_lists_dead_watchers() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("live.ts"), "").unwrap();
+    fs::write(
+        dir.path().join("main.ts"),
+        "// <wk: live [./live.ts]\n// Check it.\n// />\n\
+         // <wk: gone [./old.ts, ./old/]\n// Check it.\n// />\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["prune", ".", "--dry-run"])
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run binary");
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "main.ts:4: gone: none of its files exist\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_gate_accepts_attested_commits_and_revalidates_the_rest() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir(&repo).unwrap();
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .args(args)
+            .env("GIT_AUTHOR_NAME", "a")
+            .env("GIT_AUTHOR_EMAIL", "a@example.com")
+            .env("GIT_COMMITTER_NAME", "a")
+            .env("GIT_COMMITTER_EMAIL", "a@example.com")
+            .current_dir(&repo)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+    git(&["init", "-q", "-b", "main"]);
+    fs::write(repo.join(".gitignore"), ".watcher_knight/\n").unwrap();
+    fs::write(
+        repo.join("main.ts"),
+        "// <wk: logged [./main.ts]\n// Handlers must log errors.\n// />\n",
+    )
+    .unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-qm", "add watcher"]);
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\ncat > /dev/null\necho '{\"is_valid\": true}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let wk_with_key = |key: &str, args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(args)
+            .current_dir(&repo)
+            .env("PATH", &path)
+            .env("WK_ATTESTATION_KEY", key)
+            .output()
+            .expect("failed to run binary")
+    };
+    let wk = |args: &[&str]| wk_with_key("s3cret", args);
+
+    let output = wk(&["run", ".", "--no-cache", "--attest"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("[ATTESTED]"));
+    let output = wk(&["gate", "--require-attestation", "--ref", "HEAD"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("GO"));
+    // Without the key the note is not trusted.
+    let output = wk_with_key("other", &["gate", "--require-attestation"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("signature"));
+
+    fs::write(
+        repo.join("main.ts"),
+        "// <wk: logged [./main.ts]\n// Handlers may skip logging.\n// />\n",
+    )
+    .unwrap();
+    git(&["commit", "-qam", "weaken watcher"]);
+    let output = wk(&["gate", "--require-attestation"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("NO-GO"));
+
+    let output = wk(&["gate"]);
+    assert_eq!(output.status.code(), Some(0));
+    let output = wk(&["gate", "--require-attestation"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!git(&["worktree", "list"]).contains("watcher-knight-gate"));
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_prime_uploads_verdicts_of_shareable_watchers() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::os::unix::fs::PermissionsExt;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/wk", listener.local_addr().unwrap());
+    // A store that has nothing yet and accepts every write.
+    let server = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = v.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let put = request.starts_with("PUT");
+            let status = if put { "201 Created" } else { "404 Not Found" };
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )
+            .unwrap();
+            requests.push((request.trim().to_string(), String::from_utf8(body).unwrap()));
+            if put {
+                return requests;
+            }
+        }
+        requests
+    });
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("main.ts"),
+        "// <wk: logged [./main.ts]\n// Handlers must log errors.\n// />\n\
+         // <wk: anywhere Nothing logs secrets. />\n",
+    )
+    .unwrap();
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\ncat > /dev/null\necho x >> calls\necho '{\"is_valid\": true}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["prime", "."])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .env("WK_CACHE_URL", &url)
+        .output()
+        .expect("failed to run binary");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "stderr was: {stderr}");
+    // Only the scoped watcher can be shared, so only it is validated.
+    assert_eq!(fs::read_to_string(dir.path().join("calls")).unwrap(), "x\n");
+    assert!(!stderr.contains("anywhere"));
+    let requests = server.join().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].0.starts_with("GET /wk/"));
+    assert!(requests[1].0.starts_with("PUT /wk/"));
+    assert!(requests[1].1.contains("\"is_valid\":true"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["prime", "."])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .env_remove("WK_CACHE_URL")
+        .output()
+        .expect("failed to run binary");
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("[remote_cache]"));
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_resume_only_validates_unfinished_watchers() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .args(args)
+            .env("GIT_AUTHOR_NAME", "a")
+            .env("GIT_AUTHOR_EMAIL", "a@example.com")
+            .env("GIT_COMMITTER_NAME", "a")
+            .env("GIT_COMMITTER_EMAIL", "a@example.com")
+            .current_dir(dir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+    };
+    git(&["init", "-q"]);
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: quick-check Keep it quick. />\n// <wk: slow-check Keep it slow. />\n",
+    )
+    .unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-qm", "watchers"]);
+    let bin = tempfile::tempdir().unwrap();
+    let claude = bin.path().join("claude");
+    let path = std::env::join_paths(
+        std::iter::once(bin.path().to_path_buf())
+            .chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let state = dir.path().join(".watcher_knight/run-state.jsonl");
+
+    // The slow watcher's agent takes the run down once the quick verdict is
+    // saved, like a CI job killed mid-run.
+    fs::write(
+        &claude,
+        "#!/bin/sh\nif grep -q 'Keep it slow' ; then\n\
+         while ! grep -q quick-check .watcher_knight/run-state.jsonl 2>/dev/null; do sleep 0.1; done\n\
+         kill -9 $PPID; sleep 5; fi\n\
+         echo '{\"is_valid\": true}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(&claude, fs::Permissions::from_mode(0o755)).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache"])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .output()
+        .expect("failed to run binary");
+    assert_eq!(output.status.code(), None);
+    assert!(fs::read_to_string(&state).unwrap().contains("quick-check"));
+
+    fs::write(
+        &claude,
+        "#!/bin/sh\ncat > /dev/null\necho x >> calls\necho '{\"is_valid\": true}'\n",
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache", "--resume"])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .output()
+        .expect("failed to run binary");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "stderr was: {stderr}");
+    assert!(
+        stderr.contains("1 watchers already validated"),
+        "stderr was: {stderr}"
+    );
+    assert_eq!(fs::read_to_string(dir.path().join("calls")).unwrap(), "x\n");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("2 passed"));
+    assert!(!state.exists());
+}
+
+#[test]
+fn cli_run_deadline_leaves_watchers_inconclusive_per_policy() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: first-check Keep it first. />\n// <wk: second-check Keep it second. />\n",
+    )
+    .unwrap();
+    let run = |policy: &str| {
+        Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+            .args(["run", ".", "--no-cache", "--deadline", "0s"])
+            .args(["--inconclusive", policy])
+            .current_dir(dir.path())
+            .output()
+            .expect("failed to run binary")
+    };
+
+    let output = run("warn");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "stdout was: {stdout}");
+    assert!(
+        stdout.contains("==== INCONCLUSIVE ===="),
+        "stdout was: {stdout}"
+    );
+    assert!(stdout.contains("first-check (app.ts:1): deadline reached"));
+    assert!(stdout.contains("2 inconclusive"), "stdout was: {stdout}");
+
+    let output = run("fail");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "stdout was: {stdout}");
+    assert!(
+        stdout.contains("--inconclusive fail"),
+        "stdout was: {stdout}"
+    );
+
+    let output = run("pass");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!stdout.contains("==== INCONCLUSIVE ===="));
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_deadline_kills_running_watchers() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.ts"),
+        "// <wk: slow-check Keep it slow. />\n",
+    )
+    .unwrap();
+    let bin = tempfile::tempdir().unwrap();
+    let claude = bin.path().join("claude");
+    // The agent's own children must go too, or they hold its output open.
+    fs::write(
+        &claude,
+        "#!/bin/sh\ncat > /dev/null\nsleep 30\necho '{\"is_valid\": true}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(&claude, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin.path().to_path_buf())
+            .chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let started = std::time::Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache", "--deadline", "1s"])
+        .args(["--report", "json=report.json"])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .output()
+        .expect("failed to run binary");
+    assert!(started.elapsed() < std::time::Duration::from_secs(20));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "stdout was: {stdout}");
+    assert!(
+        stdout.contains("slow-check (app.ts:1): deadline reached"),
+        "stdout was: {stdout}"
+    );
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.path().join("report.json")).unwrap()).unwrap();
+    assert_eq!(report["results"][0]["status"], "inconclusive");
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_dependencies_strategy_prompts_with_dependency_changes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .args(args)
+            .env("GIT_AUTHOR_NAME", "a")
+            .env("GIT_AUTHOR_EMAIL", "a@example.com")
+            .env("GIT_COMMITTER_NAME", "a")
+            .env("GIT_COMMITTER_EMAIL", "a@example.com")
+            .current_dir(dir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+    };
+    git(&["init", "-q"]);
+    fs::write(
+        dir.path().join("Cargo.toml"),
+        "# <wk: tokio-1x [Cargo.lock]\n# options={strategy=\"dependencies\"}\n\
+         # tokio stays on 1.x. />\n[package]\nname = \"app\"\n",
+    )
+    .unwrap();
+    let lock = |version: &str| {
+        format!(
+            "[[package]]\nname = \"serde\"\nversion = \"1.0.1\"\nchecksum = \"aa\"\n\n\
+             [[package]]\nname = \"tokio\"\nversion = \"{version}\"\nchecksum = \"{version}-sum\"\n"
+        )
+    };
+    fs::write(dir.path().join("Cargo.lock"), lock("1.38.0")).unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-qm", "deps"]);
+    fs::write(dir.path().join("Cargo.lock"), lock("2.0.0")).unwrap();
+
+    let bin = tempfile::tempdir().unwrap();
+    let claude = bin.path().join("claude");
+    fs::write(
+        &claude,
+        "#!/bin/sh\ncat > prompt.txt\necho '{\"is_valid\": false, \"reason\": \"tokio 2\"}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(&claude, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(
+        std::iter::once(bin.path().to_path_buf())
+            .chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--diff", "HEAD", "--no-cache"])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .output()
+        .expect("failed to run binary");
+    assert_eq!(output.status.code(), Some(1));
+    let prompt = fs::read_to_string(dir.path().join("prompt.txt")).unwrap();
+    assert!(
+        prompt.contains("## Dependency changes\nCargo.lock:\n- updated tokio 1.38.0 -> 2.0.0\n"),
+        "prompt was: {prompt}"
+    );
+    assert!(!prompt.contains("serde"), "prompt was: {prompt}");
+    assert!(!prompt.contains("checksum"), "prompt was: {prompt}");
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_run_reports_claude_exiting_before_reading_the_prompt() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    // A prompt bigger than a pipe buffer, so writing it hits the closed pipe.
+    let instruction = "Every handler must be logged. ".repeat(4_000);
+    fs::write(
+        dir.path().join("app.ts"),
+        format!("// <wk: logged\n// {instruction}\n// />\n"),
+    )
+    .unwrap();
+    let bin = dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        bin.join("claude"),
+        "#!/bin/sh\necho 'Invalid API key, run /login' >&2\nexit 1\n",
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("claude"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_watcher-knight"))
+        .args(["run", ".", "--no-cache"])
+        .current_dir(dir.path())
+        .env("PATH", &path)
+        .output()
+        .expect("failed to run binary");
+    assert_eq!(output.status.code(), Some(3));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("before reading the prompt for watcher logged"),
+        "stdout was: {stdout}"
+    );
+    assert!(stdout.contains("Invalid API key"), "stdout was: {stdout}");
+}